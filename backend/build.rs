@@ -0,0 +1,5 @@
+// generated by `sqlx migrate build-script`
+fn main() {
+	// trigger recompilation when a new migration is added
+	println!("cargo:rerun-if-changed=migrations");
+}
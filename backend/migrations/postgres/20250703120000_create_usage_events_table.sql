@@ -0,0 +1,17 @@
+-- Create usage_events table
+CREATE TABLE usage_events (
+    id BIGSERIAL PRIMARY KEY,
+    account_id TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    input_tokens BIGINT NOT NULL,
+    output_tokens BIGINT NOT NULL,
+    cost_usd DOUBLE PRECISION NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Create index on account_id and created_at for range summaries
+CREATE INDEX idx_usage_events_account_created_at ON usage_events(account_id, created_at);
+
+-- Optional monthly spend cap per account, in USD
+ALTER TABLE accounts ADD COLUMN monthly_spend_limit_usd DOUBLE PRECISION;
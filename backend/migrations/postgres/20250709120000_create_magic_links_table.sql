@@ -0,0 +1,9 @@
+-- Create magic_links table; each row is one unused login link
+CREATE TABLE magic_links (
+    jti TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+-- Create index on email for faster cleanup
+CREATE INDEX idx_magic_links_email ON magic_links(email);
@@ -0,0 +1,53 @@
+-- Create organizations table
+CREATE TABLE organizations (
+    org_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_by TEXT NOT NULL REFERENCES accounts(account_id),
+    created_at TEXT NOT NULL
+);
+
+-- Create org_memberships table; role is one of owner, admin, member
+CREATE TABLE org_memberships (
+    org_id TEXT NOT NULL REFERENCES organizations(org_id) ON DELETE CASCADE,
+    account_id TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (org_id, account_id)
+);
+
+CREATE INDEX idx_org_memberships_account_id ON org_memberships(account_id);
+
+-- Create org_invitations table; an email has at most one pending invite per org
+CREATE TABLE org_invitations (
+    invite_id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL REFERENCES organizations(org_id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL,
+    invited_by TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    UNIQUE (org_id, email)
+);
+
+CREATE INDEX idx_org_invitations_email ON org_invitations(email);
+
+-- Create org_prompt_templates table
+CREATE TABLE org_prompt_templates (
+    template_id TEXT PRIMARY KEY,
+    org_id TEXT NOT NULL REFERENCES organizations(org_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_by TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_org_prompt_templates_org_id ON org_prompt_templates(org_id);
+
+-- Provider keys shared with an org have org_id set; personal keys leave it NULL
+ALTER TABLE provider_keys ADD COLUMN org_id TEXT REFERENCES organizations(org_id) ON DELETE CASCADE;
+
+-- Usage made in an org context is attributed to that org for billing
+ALTER TABLE usage_events ADD COLUMN org_id TEXT;
+
+CREATE INDEX idx_usage_events_org_created_at ON usage_events(org_id, created_at);
@@ -0,0 +1,24 @@
+-- Create audit_events table; details is a JSON object that never holds
+-- prompt or completion content
+CREATE TABLE audit_events (
+    id BIGSERIAL PRIMARY KEY,
+    account_id TEXT NOT NULL REFERENCES accounts(account_id),
+    event_type TEXT NOT NULL,
+    details TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL
+);
+
+-- Create index on account_id and created_at for date-filtered reviews
+CREATE INDEX idx_audit_events_account_created_at ON audit_events(account_id, created_at);
+
+-- The audit log is append-only
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_no_update_or_delete BEFORE UPDATE OR DELETE ON audit_events
+FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
@@ -0,0 +1,27 @@
+-- Create releases table; rollout_percent limits a release to that share of
+-- installs while it is staged
+CREATE TABLE releases (
+    channel TEXT NOT NULL,
+    version TEXT NOT NULL,
+    notes TEXT NOT NULL,
+    pub_date TEXT NOT NULL,
+    rollout_percent BIGINT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (channel, version)
+);
+
+-- Create release_platforms table holding the signed bundle per platform,
+-- e.g. linux-x86_64
+CREATE TABLE release_platforms (
+    channel TEXT NOT NULL,
+    version TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    url TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    PRIMARY KEY (channel, version, platform),
+    FOREIGN KEY (channel, version) REFERENCES releases(channel, version) ON DELETE CASCADE
+);
+
+-- Create index on platform for update checks
+CREATE INDEX idx_release_platforms_platform ON release_platforms(platform);
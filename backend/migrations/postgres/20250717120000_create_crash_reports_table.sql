@@ -0,0 +1,21 @@
+-- Create crash_reports table; report_id is generated by the app so retried
+-- uploads are stored once. account_id is set when the user was logged in
+CREATE TABLE crash_reports (
+    report_id TEXT PRIMARY KEY,
+    account_id TEXT REFERENCES accounts(account_id),
+    kind TEXT NOT NULL,
+    app_version TEXT NOT NULL,
+    os TEXT NOT NULL,
+    arch TEXT NOT NULL,
+    message TEXT,
+    location TEXT,
+    thread TEXT,
+    backtrace TEXT,
+    logs TEXT NOT NULL,
+    minidump TEXT,
+    occurred_at TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Create index on app_version and occurred_at for triaging a release
+CREATE INDEX idx_crash_reports_version_occurred_at ON crash_reports(app_version, occurred_at);
@@ -0,0 +1,17 @@
+-- Create telemetry_events table; events are anonymous, with no account or
+-- installation id. event_id is generated by the app so retried uploads are
+-- stored once, and session_id only groups events of one app run
+CREATE TABLE telemetry_events (
+    event_id TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    app_version TEXT NOT NULL,
+    os TEXT NOT NULL,
+    arch TEXT NOT NULL,
+    properties TEXT NOT NULL,
+    occurred_at TEXT NOT NULL,
+    received_at TEXT NOT NULL
+);
+
+-- Create index on name and occurred_at for counting events over time
+CREATE INDEX idx_telemetry_events_name_occurred_at ON telemetry_events(name, occurred_at);
@@ -0,0 +1,29 @@
+-- Create email_deliveries table; one row per email, with the provider that
+-- sent it and the latest status its delivery webhooks reported
+CREATE TABLE email_deliveries (
+    delivery_id TEXT PRIMARY KEY,
+    template TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    provider TEXT,
+    provider_message_id TEXT,
+    status TEXT NOT NULL,
+    error TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Create index on provider and provider_message_id for matching webhook events
+CREATE INDEX idx_email_deliveries_provider_message ON email_deliveries(provider, provider_message_id);
+
+-- Create email_delivery_events table; every status event a provider reported
+CREATE TABLE email_delivery_events (
+    event_id TEXT PRIMARY KEY,
+    delivery_id TEXT NOT NULL REFERENCES email_deliveries(delivery_id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    occurred_at TEXT NOT NULL,
+    received_at TEXT NOT NULL
+);
+
+-- Create index on delivery_id for a delivery's history
+CREATE INDEX idx_email_delivery_events_delivery_id ON email_delivery_events(delivery_id);
@@ -0,0 +1,8 @@
+-- Login codes are now stored as keyed hashes. Outstanding plaintext codes
+-- are dropped; they expire quickly and a new one can be requested
+DELETE FROM login_codes;
+DROP INDEX idx_login_codes_code;
+ALTER TABLE login_codes RENAME COLUMN code TO code_hash;
+
+-- Count wrong guesses; the code is deleted once they run out
+ALTER TABLE login_codes ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- Accounts can be disabled or banned by operators of the hosted service
+ALTER TABLE accounts ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+ALTER TABLE accounts ADD COLUMN status_reason TEXT;
+ALTER TABLE accounts ADD COLUMN status_changed_at TEXT;
+
+-- Create platform_roles table; accounts granted access to the admin API,
+-- as `support` or `admin`
+CREATE TABLE platform_roles (
+    account_id TEXT PRIMARY KEY REFERENCES accounts(account_id) ON DELETE CASCADE,
+    role TEXT NOT NULL,
+    granted_by TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,14 @@
+-- Create provider_keys table
+CREATE TABLE provider_keys (
+    key_id TEXT PRIMARY KEY,
+    account_id TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    label TEXT NOT NULL,
+    ciphertext TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    key_hint TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Create index on account_id for faster lookups
+CREATE INDEX idx_provider_keys_account_id ON provider_keys(account_id);
@@ -0,0 +1,9 @@
+-- Create settings_blobs table
+CREATE TABLE settings_blobs (
+    account_id TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    namespace TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (account_id, namespace)
+);
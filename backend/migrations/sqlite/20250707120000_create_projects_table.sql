@@ -0,0 +1,13 @@
+-- Create projects table
+CREATE TABLE projects (
+    account_id TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    project_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    repo_url TEXT,
+    last_opened_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (account_id, project_id)
+);
+
+-- Create index for listing recent projects
+CREATE INDEX idx_projects_account_last_opened ON projects(account_id, last_opened_at);
@@ -0,0 +1,25 @@
+-- Create audit_events table; details is a JSON object that never holds
+-- prompt or completion content
+CREATE TABLE audit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id TEXT NOT NULL REFERENCES accounts(account_id),
+    event_type TEXT NOT NULL,
+    details TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL
+);
+
+-- Create index on account_id and created_at for date-filtered reviews
+CREATE INDEX idx_audit_events_account_created_at ON audit_events(account_id, created_at);
+
+-- The audit log is append-only
+CREATE TRIGGER audit_events_no_update BEFORE UPDATE ON audit_events
+BEGIN
+    SELECT RAISE(ABORT, 'audit_events is append-only');
+END;
+
+CREATE TRIGGER audit_events_no_delete BEFORE DELETE ON audit_events
+BEGIN
+    SELECT RAISE(ABORT, 'audit_events is append-only');
+END;
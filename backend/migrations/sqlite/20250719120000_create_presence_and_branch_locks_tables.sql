@@ -0,0 +1,26 @@
+-- Create presence table: which project and canvas each device has open
+CREATE TABLE presence (
+    account_id TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    device_id TEXT NOT NULL,
+    device_name TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    canvas_id TEXT NOT NULL DEFAULT '',
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (account_id, device_id, project_id, canvas_id)
+);
+
+-- Create index for listing live presence
+CREATE INDEX idx_presence_account_updated ON presence(account_id, updated_at);
+
+-- Create branch locks table: advisory locks on canvas branches
+CREATE TABLE branch_locks (
+    account_id TEXT NOT NULL REFERENCES accounts(account_id) ON DELETE CASCADE,
+    project_id TEXT NOT NULL,
+    branch TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    device_name TEXT NOT NULL,
+    canvas_id TEXT,
+    acquired_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    PRIMARY KEY (account_id, project_id, branch)
+);
@@ -0,0 +1,10 @@
+-- Create account_profiles table; notifications holds the notification
+-- preferences as JSON. Accounts without a row use the defaults
+CREATE TABLE account_profiles (
+    account_id TEXT PRIMARY KEY REFERENCES accounts(account_id) ON DELETE CASCADE,
+    display_name TEXT,
+    avatar_url TEXT,
+    timezone TEXT NOT NULL,
+    notifications TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
@@ -0,0 +1,649 @@
+use crate::{
+	audit::{self, AuditEvent, ClientInfo},
+	auth::{self, AuthenticatedAccount},
+	database::{
+		self, DatabaseKind, DbPool, MigrationError, PendingMigration, ACCOUNT_ACTIVE,
+	},
+	email::EmailService,
+	errors::internal_error,
+	llm::api::ApiError,
+	usage::{self, UsageGrouping, UsageScope},
+};
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use chrono::Utc;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::env;
+
+const DEFAULT_SEARCH_LIMIT: i64 = 50;
+const MAX_SEARCH_LIMIT: i64 = 200;
+const MAX_STATUS_REASON_LEN: usize = 500;
+const ACCOUNT_STATUSES: [&str; 3] = [ACCOUNT_ACTIVE, "disabled", "banned"];
+
+/// Roles of the hosted service's operators, in increasing order of
+/// privilege. Support can look accounts up, resend login codes and disable
+/// accounts; admins can also ban them and grant roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlatformRole {
+	Support,
+	Admin,
+}
+
+impl PlatformRole {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			PlatformRole::Support => "support",
+			PlatformRole::Admin => "admin",
+		}
+	}
+
+	pub fn from_str(s: &str) -> Option<Self> {
+		match s {
+			"support" => Some(PlatformRole::Support),
+			"admin" => Some(PlatformRole::Admin),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MigrateRequest {
+	/// Confirms running migrations that drop or rewrite data.
+	#[serde(default)]
+	pub allow_destructive: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+	pub pending: Vec<PendingMigration>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrateResponse {
+	pub applied: Vec<PendingMigration>,
+}
+
+/// Admin endpoints need `ADMIN_TOKEN` as a bearer token, and are disabled
+/// while it is unset.
+fn authorize_admin(req: &HttpRequest) -> Result<(), ApiError> {
+	if auth::has_secret_token(req, "ADMIN_TOKEN") {
+		return Ok(());
+	}
+	Err(ApiError {
+		error: "Admin endpoints require the admin token".to_string(),
+		code: "UNAUTHORIZED".to_string(),
+	})
+}
+
+/// Lists the migrations a `POST` would apply and which are destructive.
+///
+/// `GET /admin/migrate`
+pub async fn migration_status(
+	req: HttpRequest,
+	pool: web::Data<DbPool>,
+	kind: web::Data<DatabaseKind>,
+) -> ActixResult<HttpResponse> {
+	if let Err(e) = authorize_admin(&req) {
+		return Ok(HttpResponse::Unauthorized().json(e));
+	}
+	match database::pending_migrations(pool.get_ref(), *kind.get_ref()).await {
+		Ok(pending) => Ok(HttpResponse::Ok().json(MigrationStatus { pending })),
+		Err(e) => {
+			error!("Failed to list migrations: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Applies pending migrations, for rollouts that start the server with
+/// `MIGRATE_ON_STARTUP=false`. Destructive ones need `allow_destructive`
+/// unless they were reviewed.
+///
+/// `POST /admin/migrate`
+pub async fn migrate(
+	req: HttpRequest,
+	pool: web::Data<DbPool>,
+	kind: web::Data<DatabaseKind>,
+	body: Option<web::Json<MigrateRequest>>,
+) -> ActixResult<HttpResponse> {
+	if let Err(e) = authorize_admin(&req) {
+		return Ok(HttpResponse::Unauthorized().json(e));
+	}
+	let body = body.map(web::Json::into_inner).unwrap_or_default();
+	match database::run_migrations(
+		pool.get_ref(),
+		*kind.get_ref(),
+		body.allow_destructive,
+	)
+	.await
+	{
+		Ok(applied) => Ok(HttpResponse::Ok().json(MigrateResponse { applied })),
+		Err(e @ MigrationError::Destructive(_)) => {
+			Ok(HttpResponse::Conflict().json(ApiError {
+				error: e.to_string(),
+				code: "DESTRUCTIVE_MIGRATIONS".to_string(),
+			}))
+		}
+		Err(e) => {
+			error!("Failed to run migrations: {}", e);
+			Ok(HttpResponse::InternalServerError().json(ApiError {
+				error: e.to_string(),
+				code: "MIGRATION_FAILED".to_string(),
+			}))
+		}
+	}
+}
+
+fn forbidden(message: &str) -> HttpResponse {
+	HttpResponse::Forbidden().json(ApiError {
+		error: message.to_string(),
+		code: "FORBIDDEN".to_string(),
+	})
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+	HttpResponse::BadRequest().json(ApiError {
+		error: message.to_string(),
+		code: "INVALID_REQUEST".to_string(),
+	})
+}
+
+fn account_not_found() -> HttpResponse {
+	HttpResponse::NotFound().json(ApiError {
+		error: "Account not found".to_string(),
+		code: "ACCOUNT_NOT_FOUND".to_string(),
+	})
+}
+
+/// Emails in `ADMIN_EMAILS` (comma separated) are admins without a
+/// `platform_roles` row, so a fresh deployment has someone to grant roles.
+fn is_bootstrap_admin(email: &str) -> bool {
+	env::var("ADMIN_EMAILS").is_ok_and(|emails| {
+		emails
+			.split(',')
+			.map(str::trim)
+			.any(|admin| !admin.is_empty() && admin.eq_ignore_ascii_case(email))
+	})
+}
+
+fn effective_role(email: &str, stored: Option<&str>) -> Option<PlatformRole> {
+	if is_bootstrap_admin(email) {
+		return Some(PlatformRole::Admin);
+	}
+	stored.and_then(PlatformRole::from_str)
+}
+
+pub async fn platform_role(
+	pool: &DbPool,
+	account_id: &str,
+	email: &str,
+) -> Result<Option<PlatformRole>, sqlx::Error> {
+	let stored: Option<String> =
+		sqlx::query_scalar("SELECT role FROM platform_roles WHERE account_id = $1")
+			.bind(account_id)
+			.fetch_optional(pool)
+			.await?;
+	Ok(effective_role(email, stored.as_deref()))
+}
+
+/// Checks the account has at least the `minimum` platform role.
+pub async fn require_platform_role(
+	pool: &DbPool,
+	account: &AuthenticatedAccount,
+	minimum: PlatformRole,
+) -> Result<PlatformRole, HttpResponse> {
+	match platform_role(pool, &account.account_id, &account.email).await {
+		Ok(Some(role)) if role >= minimum => Ok(role),
+		Ok(_) => Err(forbidden(&format!(
+			"This action requires the {} platform role",
+			minimum.as_str()
+		))),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Err(internal_error())
+		}
+	}
+}
+
+#[derive(Debug, FromRow)]
+struct AdminAccountRow {
+	account_id: String,
+	email: String,
+	created_at: String,
+	status: String,
+	status_reason: Option<String>,
+	status_changed_at: Option<String>,
+	role: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminAccount {
+	pub account_id: String,
+	pub email: String,
+	pub created_at: String,
+	pub status: String,
+	pub status_reason: Option<String>,
+	pub status_changed_at: Option<String>,
+	pub role: Option<PlatformRole>,
+}
+
+impl From<AdminAccountRow> for AdminAccount {
+	fn from(row: AdminAccountRow) -> Self {
+		AdminAccount {
+			role: effective_role(&row.email, row.role.as_deref()),
+			account_id: row.account_id,
+			email: row.email,
+			created_at: row.created_at,
+			status: row.status,
+			status_reason: row.status_reason,
+			status_changed_at: row.status_changed_at,
+		}
+	}
+}
+
+const ADMIN_ACCOUNT_COLUMNS: &str = "a.account_id, a.email, a.created_at, a.status,
+	 a.status_reason, a.status_changed_at, r.role
+	 FROM accounts a
+	 LEFT JOIN platform_roles r ON r.account_id = a.account_id";
+
+async fn fetch_account(
+	pool: &DbPool,
+	account_id: &str,
+) -> Result<Option<AdminAccount>, sqlx::Error> {
+	let row = sqlx::query_as::<_, AdminAccountRow>(&format!(
+		"SELECT {} WHERE a.account_id = $1",
+		ADMIN_ACCOUNT_COLUMNS
+	))
+	.bind(account_id)
+	.fetch_optional(pool)
+	.await?;
+	Ok(row.map(AdminAccount::from))
+}
+
+/// Looks up the target of an admin action, answering 404 when it's missing.
+async fn target_account(
+	pool: &DbPool,
+	account_id: &str,
+) -> Result<AdminAccount, HttpResponse> {
+	match fetch_account(pool, account_id).await {
+		Ok(Some(account)) => Ok(account),
+		Ok(None) => Err(account_not_found()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Err(internal_error())
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountSearchQuery {
+	/// Part of an email, or an exact account id.
+	pub q: Option<String>,
+	pub status: Option<String>,
+	pub limit: Option<i64>,
+	pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountSearchResponse {
+	pub accounts: Vec<AdminAccount>,
+}
+
+/// Escapes `LIKE` wildcards so the query matches literally.
+fn like_pattern(query: &str) -> String {
+	let mut pattern = String::from("%");
+	for c in query.to_lowercase().chars() {
+		if matches!(c, '%' | '_' | '\\') {
+			pattern.push('\\');
+		}
+		pattern.push(c);
+	}
+	pattern.push('%');
+	pattern
+}
+
+/// Searches accounts by email or id, newest first.
+///
+/// `GET /admin/accounts`
+pub async fn search_accounts(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	query: web::Query<AccountSearchQuery>,
+) -> ActixResult<HttpResponse> {
+	if let Err(response) =
+		require_platform_role(pool.get_ref(), &account, PlatformRole::Support).await
+	{
+		return Ok(response);
+	}
+	if let Some(status) = &query.status {
+		if !ACCOUNT_STATUSES.contains(&status.as_str()) {
+			return Ok(bad_request(&format!("Unknown account status `{}`", status)));
+		}
+	}
+	let search = query.q.as_deref().map(str::trim).unwrap_or_default();
+	let limit = query
+		.limit
+		.unwrap_or(DEFAULT_SEARCH_LIMIT)
+		.clamp(1, MAX_SEARCH_LIMIT);
+	let offset = query.offset.unwrap_or(0).max(0);
+
+	let status_filter = if query.status.is_some() {
+		" AND a.status = $5"
+	} else {
+		""
+	};
+	let sql = format!(
+		"SELECT {}
+		 WHERE (lower(a.email) LIKE $1 ESCAPE '\\' OR a.account_id = $2){}
+		 ORDER BY a.created_at DESC
+		 LIMIT $3 OFFSET $4",
+		ADMIN_ACCOUNT_COLUMNS, status_filter
+	);
+	let mut statement = sqlx::query_as::<_, AdminAccountRow>(&sql)
+		.bind(like_pattern(search))
+		.bind(search)
+		.bind(limit)
+		.bind(offset);
+	if let Some(status) = &query.status {
+		statement = statement.bind(status);
+	}
+
+	match statement.fetch_all(pool.get_ref()).await {
+		Ok(rows) => Ok(HttpResponse::Ok().json(AccountSearchResponse {
+			accounts: rows.into_iter().map(AdminAccount::from).collect(),
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// `GET /admin/accounts/{account_id}`
+pub async fn get_account(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	account_id: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	if let Err(response) =
+		require_platform_role(pool.get_ref(), &account, PlatformRole::Support).await
+	{
+		return Ok(response);
+	}
+	match target_account(pool.get_ref(), &account_id).await {
+		Ok(target) => Ok(HttpResponse::Ok().json(target)),
+		Err(response) => Ok(response),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountUsageQuery {
+	pub from: Option<String>,
+	pub to: Option<String>,
+	#[serde(default)]
+	pub group_by: UsageGrouping,
+}
+
+/// The same summary `GET /api/usage` gives the account itself.
+///
+/// `GET /admin/accounts/{account_id}/usage`
+pub async fn get_account_usage(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	account_id: web::Path<String>,
+	query: web::Query<AccountUsageQuery>,
+) -> ActixResult<HttpResponse> {
+	if let Err(response) =
+		require_platform_role(pool.get_ref(), &account, PlatformRole::Support).await
+	{
+		return Ok(response);
+	}
+	if let Err(response) = target_account(pool.get_ref(), &account_id).await {
+		return Ok(response);
+	}
+	let now = Utc::now();
+	let from = match usage::parse_bound(query.from.as_deref(), usage::start_of_month(now))
+	{
+		Ok(from) => from,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+	let to = match usage::parse_bound(query.to.as_deref(), now) {
+		Ok(to) => to,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+
+	match usage::summarize_usage(
+		pool.get_ref(),
+		UsageScope::Account(&account_id),
+		from,
+		to,
+		query.group_by,
+	)
+	.await
+	{
+		Ok(summary) => Ok(HttpResponse::Ok().json(summary)),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetStatusRequest {
+	/// `active`, `disabled` or `banned`.
+	pub status: String,
+	pub reason: Option<String>,
+}
+
+/// Disables, bans or reactivates an account. Support can't ban, or act on
+/// banned accounts or on operators at or above their own role.
+///
+/// `PUT /admin/accounts/{account_id}/status`
+pub async fn set_account_status(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	client: ClientInfo,
+	account_id: web::Path<String>,
+	body: web::Json<SetStatusRequest>,
+) -> ActixResult<HttpResponse> {
+	let role = match require_platform_role(
+		pool.get_ref(),
+		&account,
+		PlatformRole::Support,
+	)
+	.await
+	{
+		Ok(role) => role,
+		Err(response) => return Ok(response),
+	};
+	let status = body.status.as_str();
+	if !ACCOUNT_STATUSES.contains(&status) {
+		return Ok(bad_request(&format!("Unknown account status `{}`", status)));
+	}
+	let reason = body
+		.reason
+		.as_deref()
+		.map(str::trim)
+		.filter(|reason| !reason.is_empty());
+	if reason.is_some_and(|reason| reason.chars().count() > MAX_STATUS_REASON_LEN) {
+		return Ok(bad_request(&format!(
+			"Reason exceeds {} characters",
+			MAX_STATUS_REASON_LEN
+		)));
+	}
+	if *account_id == account.account_id {
+		return Ok(forbidden("You can't change the status of your own account"));
+	}
+
+	let target = match target_account(pool.get_ref(), &account_id).await {
+		Ok(target) => target,
+		Err(response) => return Ok(response),
+	};
+	if target.role.is_some_and(|target_role| target_role >= role) {
+		return Ok(forbidden(
+			"You can't change the status of an operator with your role or above",
+		));
+	}
+	if role < PlatformRole::Admin && (status == "banned" || target.status == "banned") {
+		return Ok(forbidden("Only admins can ban or unban accounts"));
+	}
+
+	let now = Utc::now().to_rfc3339();
+	// Reactivating clears the reason the account was disabled for
+	let reason = reason.filter(|_| status != ACCOUNT_ACTIVE);
+	if let Err(e) = sqlx::query(
+		"UPDATE accounts SET status = $1, status_reason = $2, status_changed_at = $3
+		 WHERE account_id = $4",
+	)
+	.bind(status)
+	.bind(reason)
+	.bind(&now)
+	.bind(&target.account_id)
+	.execute(pool.get_ref())
+	.await
+	{
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	audit::record(
+		pool.get_ref(),
+		&target.account_id,
+		&client,
+		AuditEvent::AccountStatusChanged {
+			status,
+			reason,
+			by: &account.account_id,
+		},
+	)
+	.await;
+
+	Ok(HttpResponse::Ok().json(AdminAccount {
+		status: status.to_string(),
+		status_reason: reason.map(str::to_string),
+		status_changed_at: Some(now),
+		..target
+	}))
+}
+
+/// Emails the account a new login code and magic link, for users whose
+/// email didn't arrive.
+///
+/// `POST /admin/accounts/{account_id}/login-code`
+pub async fn resend_login_code(
+	pool: web::Data<DbPool>,
+	email_service: web::Data<EmailService>,
+	account: AuthenticatedAccount,
+	client: ClientInfo,
+	account_id: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	if let Err(response) =
+		require_platform_role(pool.get_ref(), &account, PlatformRole::Support).await
+	{
+		return Ok(response);
+	}
+	let target = match target_account(pool.get_ref(), &account_id).await {
+		Ok(target) => target,
+		Err(response) => return Ok(response),
+	};
+	if target.status != ACCOUNT_ACTIVE {
+		return Ok(HttpResponse::Conflict().json(ApiError {
+			error: format!("This account is {}", target.status),
+			code: "ACCOUNT_INACTIVE".to_string(),
+		}));
+	}
+
+	auth::send_login_code(pool.get_ref(), email_service.get_ref(), &target.email, None)
+		.await?;
+	audit::record(
+		pool.get_ref(),
+		&target.account_id,
+		&client,
+		AuditEvent::LoginCodeResent {
+			by: &account.account_id,
+		},
+	)
+	.await;
+
+	Ok(HttpResponse::Ok().json(serde_json::json!({
+		"message": "Login code sent",
+	})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRoleRequest {
+	/// `null` revokes the account's role.
+	pub role: Option<PlatformRole>,
+}
+
+/// Grants or revokes a platform role. Admins listed in `ADMIN_EMAILS` keep
+/// their role whatever is stored.
+///
+/// `PUT /admin/accounts/{account_id}/role`
+pub async fn set_platform_role(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	client: ClientInfo,
+	account_id: web::Path<String>,
+	body: web::Json<SetRoleRequest>,
+) -> ActixResult<HttpResponse> {
+	if let Err(response) =
+		require_platform_role(pool.get_ref(), &account, PlatformRole::Admin).await
+	{
+		return Ok(response);
+	}
+	if *account_id == account.account_id {
+		return Ok(forbidden("You can't change your own role"));
+	}
+	let target = match target_account(pool.get_ref(), &account_id).await {
+		Ok(target) => target,
+		Err(response) => return Ok(response),
+	};
+
+	let result =
+		match body.role {
+			Some(role) => sqlx::query(
+				"INSERT INTO platform_roles (account_id, role, granted_by, created_at)
+				 VALUES ($1, $2, $3, $4)
+				 ON CONFLICT (account_id) DO UPDATE
+				 SET role = excluded.role,
+				     granted_by = excluded.granted_by,
+				     created_at = excluded.created_at",
+			)
+			.bind(&target.account_id)
+			.bind(role.as_str())
+			.bind(&account.account_id)
+			.bind(Utc::now().to_rfc3339())
+			.execute(pool.get_ref())
+			.await,
+			None => {
+				sqlx::query("DELETE FROM platform_roles WHERE account_id = $1")
+					.bind(&target.account_id)
+					.execute(pool.get_ref())
+					.await
+			}
+		};
+	if let Err(e) = result {
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	audit::record(
+		pool.get_ref(),
+		&target.account_id,
+		&client,
+		AuditEvent::PlatformRoleChanged {
+			role: body.role.map(|role| role.as_str()),
+			by: &account.account_id,
+		},
+	)
+	.await;
+
+	Ok(HttpResponse::Ok().json(AdminAccount {
+		role: effective_role(&target.email, body.role.map(|role| role.as_str())),
+		..target
+	}))
+}
@@ -0,0 +1,229 @@
+use crate::{
+	auth::AuthenticatedAccount, database::DbPool, errors::internal_error,
+	usage::parse_bound,
+};
+use actix_web::{
+	dev::Payload, http::header, web, FromRequest, HttpRequest, HttpResponse,
+	Result as ActixResult,
+};
+use chrono::{Duration, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use std::future::{ready, Ready};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+/// A security-relevant action. Only metadata is recorded: never prompts,
+/// completions or key material.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditEvent<'a> {
+	/// `method` is `code` or `magic_link`.
+	Login {
+		method: &'a str,
+	},
+	LoginFailed {
+		method: &'a str,
+	},
+	KeyCreated {
+		key_id: &'a str,
+		provider: &'a str,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		org_id: Option<&'a str>,
+	},
+	KeyDeleted {
+		key_id: &'a str,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		org_id: Option<&'a str>,
+	},
+	Inference {
+		provider: &'a str,
+		model: &'a str,
+		input_tokens: u32,
+		output_tokens: u32,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		org_id: Option<&'a str>,
+	},
+	/// Recorded on the account an operator acted on; `by` is the operator.
+	AccountStatusChanged {
+		status: &'a str,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		reason: Option<&'a str>,
+		by: &'a str,
+	},
+	PlatformRoleChanged {
+		#[serde(skip_serializing_if = "Option::is_none")]
+		role: Option<&'a str>,
+		by: &'a str,
+	},
+	LoginCodeResent {
+		by: &'a str,
+	},
+}
+
+/// Where a request came from, as recorded alongside audit events.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+	pub ip_address: Option<String>,
+	pub user_agent: Option<String>,
+}
+
+impl FromRequest for ClientInfo {
+	type Error = actix_web::Error;
+	type Future = Ready<Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+		ready(Ok(ClientInfo {
+			ip_address: req
+				.connection_info()
+				.realip_remote_addr()
+				.map(str::to_string),
+			user_agent: req
+				.headers()
+				.get(header::USER_AGENT)
+				.and_then(|v| v.to_str().ok())
+				.map(str::to_string),
+		}))
+	}
+}
+
+/// Appends an event to the account's audit log. Failures are logged rather
+/// than failing the request being audited.
+pub async fn record(
+	pool: &DbPool,
+	account_id: &str,
+	client: &ClientInfo,
+	event: AuditEvent<'_>,
+) {
+	let mut details = match serde_json::to_value(&event) {
+		Ok(Value::Object(details)) => details,
+		_ => return,
+	};
+	let event_type = match details.remove("type") {
+		Some(Value::String(event_type)) => event_type,
+		_ => return,
+	};
+
+	if let Err(e) = sqlx::query(
+		"INSERT INTO audit_events
+		 (account_id, event_type, details, ip_address, user_agent, created_at)
+		 VALUES ($1, $2, $3, $4, $5, $6)",
+	)
+	.bind(account_id)
+	.bind(&event_type)
+	.bind(Value::Object(details).to_string())
+	.bind(client.ip_address.as_deref())
+	.bind(client.user_agent.as_deref())
+	.bind(Utc::now().to_rfc3339())
+	.execute(pool)
+	.await
+	{
+		error!("Failed to record audit event {}: {}", event_type, e);
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+	/// RFC 3339 lower bound, defaults to 30 days ago.
+	pub from: Option<String>,
+	/// RFC 3339 upper bound (exclusive), defaults to now.
+	pub to: Option<String>,
+	pub event_type: Option<String>,
+	/// Only return events with a smaller id, for paging back in time.
+	pub before: Option<i64>,
+	pub limit: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct AuditRow {
+	id: i64,
+	event_type: String,
+	details: String,
+	ip_address: Option<String>,
+	user_agent: Option<String>,
+	created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+	pub id: i64,
+	pub event_type: String,
+	pub details: Value,
+	pub ip_address: Option<String>,
+	pub user_agent: Option<String>,
+	pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogResponse {
+	pub events: Vec<AuditEntry>,
+	/// Pass as `before` to fetch the next page; absent on the last page.
+	pub next_before: Option<i64>,
+}
+
+/// The caller's audit log, newest first.
+pub async fn get_audit_log(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	query: web::Query<AuditQuery>,
+) -> ActixResult<HttpResponse> {
+	let now = Utc::now();
+	let from = match parse_bound(query.from.as_deref(), now - Duration::days(30)) {
+		Ok(from) => from,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+	let to = match parse_bound(query.to.as_deref(), now) {
+		Ok(to) => to,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+	let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+	// Optional filters are bound as NULL when unused
+	let rows = match sqlx::query_as::<_, AuditRow>(
+		"SELECT id, event_type, details, ip_address, user_agent, created_at
+		 FROM audit_events
+		 WHERE account_id = $1 AND created_at >= $2 AND created_at < $3
+		   AND ($4 IS NULL OR event_type = $4)
+		   AND ($5 IS NULL OR id < $5)
+		 ORDER BY id DESC
+		 LIMIT $6",
+	)
+	.bind(&account.account_id)
+	.bind(&from)
+	.bind(&to)
+	.bind(query.event_type.as_deref())
+	.bind(query.before)
+	.bind(limit)
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(rows) => rows,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	let next_before = (rows.len() as i64 == limit)
+		.then(|| rows.last().map(|row| row.id))
+		.flatten();
+	let events = rows
+		.into_iter()
+		.map(|row| AuditEntry {
+			id: row.id,
+			event_type: row.event_type,
+			details: serde_json::from_str(&row.details).unwrap_or(Value::Null),
+			ip_address: row.ip_address,
+			user_agent: row.user_agent,
+			created_at: row.created_at,
+		})
+		.collect();
+
+	Ok(HttpResponse::Ok().json(AuditLogResponse {
+		events,
+		next_before,
+	}))
+}
@@ -1,16 +1,30 @@
-use crate::{database::Account, email::EmailService};
+use crate::{
+	audit::{self, AuditEvent, ClientInfo},
+	database::{Account, DbPool, ACCOUNT_ACTIVE},
+	email::{EmailService, LoginEmail},
+	profile::{self, AccountProfile},
+	request_log,
+};
 use actix_web::{
+	dev::Payload,
+	error::InternalError,
+	get,
+	http::header,
 	post,
 	web::{self, Json},
-	HttpResponse,
+	FromRequest, HttpRequest, HttpResponse,
 };
-use chrono::{Duration, Utc};
-use jsonwebtoken::{encode, EncodingKey, Header};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::LocalBoxFuture;
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use log::error;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
+use sha2::Sha256;
 use std::env;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
 
 use validator::Validate;
 
@@ -35,6 +49,7 @@ pub struct ValidateLoginCodeRequest {
 pub struct AuthResponse {
 	pub token: String,
 	pub account: Account,
+	pub profile: AccountProfile,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,31 +59,226 @@ struct Claims {
 	exp: i64, // expiration time
 }
 
+/// Claims of the signed token embedded in a magic login link. `purpose` keeps
+/// these from being accepted as session tokens and vice versa.
+#[derive(Debug, Serialize, Deserialize)]
+struct MagicLinkClaims {
+	sub: String, // email the link was sent to
+	jti: String,
+	purpose: String,
+	exp: i64,
+}
+
+const MAGIC_LINK_PURPOSE: &str = "magic_link";
+
+/// Wrong guesses allowed per login code before it is deleted.
+const MAX_CODE_ATTEMPTS: i64 = 5;
+
+/// Deep link the IDE registers to receive the result of a magic link login.
+const AUTH_DEEP_LINK: &str = "ariana://auth";
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkQuery {
+	pub token: String,
+}
+
+/// The account a request was made on behalf of, taken from the
+/// `Authorization: Bearer <jwt>` header issued by `/auth/validate-login-code`.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedAccount {
+	pub account_id: String,
+	pub email: String,
+}
+
+/// Tokens of disabled or banned accounts are refused, so the account's status
+/// is looked up on every request.
+impl FromRequest for AuthenticatedAccount {
+	type Error = actix_web::Error;
+	type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+		let account = authenticate(req);
+		let pool = req.app_data::<web::Data<DbPool>>().cloned();
+		Box::pin(async move {
+			let account = account?;
+			let Some(pool) = pool else {
+				return Ok(account);
+			};
+			match Account::status(pool.get_ref(), &account.account_id).await {
+				Ok(Some(status)) if status == ACCOUNT_ACTIVE => Ok(account),
+				Ok(Some(_)) => {
+					Err(actix_web::error::ErrorForbidden("Account is disabled"))
+				}
+				Ok(None) => Err(actix_web::error::ErrorUnauthorized("Account not found")),
+				Err(e) => {
+					error!("Database error: {}", e);
+					Err(actix_web::error::ErrorInternalServerError(
+						"Internal server error",
+					))
+				}
+			}
+		})
+	}
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+	req.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Whether the request's bearer token is the secret in the environment
+/// variable `var`, compared in constant time. Always false while the variable
+/// is unset or empty, so endpoints guarded by it are disabled.
+pub fn has_secret_token(req: &HttpRequest, var: &str) -> bool {
+	let Some(secret) = env::var(var).ok().filter(|secret| !secret.is_empty()) else {
+		return false;
+	};
+	bearer_token(req)
+		.is_some_and(|provided| provided.as_bytes().ct_eq(secret.as_bytes()).into())
+}
+
+fn authenticate(req: &HttpRequest) -> Result<AuthenticatedAccount, actix_web::Error> {
+	let token = bearer_token(req).ok_or_else(|| {
+		actix_web::error::ErrorUnauthorized("Missing authorization token")
+	})?;
+
+	let claims = decode::<Claims>(
+		token,
+		&DecodingKey::from_secret(jwt_secret().as_bytes()),
+		&Validation::default(),
+	)
+	.map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or expired token"))?
+	.claims;
+	request_log::set_account(req, &claims.sub);
+
+	Ok(AuthenticatedAccount {
+		account_id: claims.sub,
+		email: claims.email,
+	})
+}
+
+fn jwt_secret() -> String {
+	env::var("JWT_SECRET").expect("JWT_SECRET must be set")
+}
+
+/// Issues the 3 month session token returned after a successful login.
+fn issue_token(account: &Account) -> Result<(String, DateTime<Utc>), actix_web::Error> {
+	let expiration = Utc::now() + Duration::days(90); // 3 months
+	let claims = Claims {
+		sub: account.account_id.clone(),
+		email: account.email.clone(),
+		exp: expiration.timestamp(),
+	};
+
+	let token = encode(
+		&Header::default(),
+		&claims,
+		&EncodingKey::from_secret(jwt_secret().as_bytes()),
+	)
+	.map_err(|e| {
+		error!("Token generation error: {}", e);
+		actix_web::error::ErrorInternalServerError("Authentication error")
+	})?;
+
+	Ok((token, expiration))
+}
+
+fn public_base_url() -> String {
+	env::var("PUBLIC_BASE_URL")
+		.map(|url| url.trim_end_matches('/').to_string())
+		.unwrap_or_else(|_| {
+			let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+			format!("http://localhost:{}", port)
+		})
+}
+
+/// Stores a one-time link for `email` (replacing older ones) and returns its URL.
+async fn create_magic_link(
+	pool: &DbPool,
+	email: &str,
+	expiry_minutes: i64,
+) -> Result<String, actix_web::Error> {
+	let jti = Uuid::new_v4().to_string();
+	let expires_at = Utc::now() + Duration::minutes(expiry_minutes);
+	let claims = MagicLinkClaims {
+		sub: email.to_string(),
+		jti: jti.clone(),
+		purpose: MAGIC_LINK_PURPOSE.to_string(),
+		exp: expires_at.timestamp(),
+	};
+
+	let token = encode(
+		&Header::default(),
+		&claims,
+		&EncodingKey::from_secret(jwt_secret().as_bytes()),
+	)
+	.map_err(|e| {
+		error!("Token generation error: {}", e);
+		actix_web::error::ErrorInternalServerError("Authentication error")
+	})?;
+
+	let store = async {
+		sqlx::query("DELETE FROM magic_links WHERE email = $1")
+			.bind(email)
+			.execute(pool)
+			.await?;
+		sqlx::query(
+			"INSERT INTO magic_links (jti, email, expires_at) VALUES ($1, $2, $3)",
+		)
+		.bind(&jti)
+		.bind(email)
+		.bind(expires_at.to_rfc3339())
+		.execute(pool)
+		.await
+	};
+	store.await.map_err(|e| {
+		error!("Database error: {}", e);
+		actix_web::error::ErrorInternalServerError("Failed to create login link")
+	})?;
+
+	Ok(format!("{}/auth/magic?token={}", public_base_url(), token))
+}
+
 fn generate_login_code() -> String {
 	let mut rng = rand::thread_rng();
 	let code: String = (0..6).map(|_| rng.gen_range(0..10).to_string()).collect();
 	code
 }
 
-#[post("/request-login-code")]
-pub async fn request_login_code(
-	pool: web::Data<SqlitePool>,
-	email_service: web::Data<EmailService>,
-	req: Json<RequestLoginCodeRequest>,
-) -> Result<HttpResponse, actix_web::Error> {
-	// Validate input
-	if let Err(e) = req.validate() {
-		return Ok(HttpResponse::BadRequest().json(format!("Invalid email: {}", e)));
-	}
+/// Keyed with the JWT secret, since six digits are quick to brute force from
+/// a plain hash of a leaked table. The email is included so equal codes
+/// hash differently.
+fn login_code_mac(email: &str, code: &str) -> Hmac<Sha256> {
+	let mut mac = Hmac::<Sha256>::new_from_slice(jwt_secret().as_bytes())
+		.expect("HMAC accepts keys of any length");
+	mac.update(email.as_bytes());
+	mac.update(b":");
+	mac.update(code.as_bytes());
+	mac
+}
 
-	// Create or get account
-	let _account = Account::create_or_get(pool.get_ref(), &req.email)
-		.await
-		.map_err(|e| {
-			error!("Database error: {}", e);
-			actix_web::error::ErrorInternalServerError("Internal server error")
-		})?;
+fn hash_login_code(email: &str, code: &str) -> String {
+	hex::encode(login_code_mac(email, code).finalize().into_bytes())
+}
 
+/// Compares in constant time.
+fn verify_login_code(email: &str, code: &str, code_hash: &str) -> bool {
+	hex::decode(code_hash)
+		.map(|expected| login_code_mac(email, code).verify_slice(&expected).is_ok())
+		.unwrap_or(false)
+}
+
+/// Replaces the account's login code and magic link with new ones and emails
+/// them.
+pub async fn send_login_code(
+	pool: &DbPool,
+	email_service: &EmailService,
+	email: &str,
+	accept_language: Option<&str>,
+) -> Result<(), actix_web::Error> {
 	// Generate and store login code
 	let login_code = generate_login_code();
 	let expiry_hours: i64 = env::var("EMAIL_VERIFICATION_EXPIRY_HOURS")
@@ -80,38 +290,111 @@ pub async fn request_login_code(
 	let expires_at_str = expires_at.to_rfc3339();
 
 	// Clean up old codes for this email
-	sqlx::query!("DELETE FROM login_codes WHERE email = ?", req.email)
-		.execute(pool.get_ref())
+	sqlx::query("DELETE FROM login_codes WHERE email = $1")
+		.bind(email)
+		.execute(pool)
 		.await
 		.map_err(|e| {
 			error!("Database error: {}", e);
 			actix_web::error::ErrorInternalServerError("Failed to clean up old codes")
 		})?;
 
-	// Insert new code
-	sqlx::query!(
-		"INSERT INTO login_codes (email, code, expires_at) VALUES (?, ?, ?)",
-		req.email,
-		login_code,
-		expires_at_str,
+	// Insert new code; only its hash is stored
+	sqlx::query(
+		"INSERT INTO login_codes (email, code_hash, expires_at) VALUES ($1, $2, $3)",
 	)
-	.execute(pool.get_ref())
+	.bind(email)
+	.bind(hash_login_code(email, &login_code))
+	.bind(&expires_at_str)
+	.execute(pool)
 	.await
 	.map_err(|e| {
 		error!("Database error: {}", e);
 		actix_web::error::ErrorInternalServerError("Failed to create login code")
 	})?;
 
+	let magic_link_expiry_minutes: i64 = env::var("MAGIC_LINK_EXPIRY_MINUTES")
+		.unwrap_or_else(|_| "15".to_string())
+		.parse()
+		.unwrap_or(15);
+	let login_link = create_magic_link(pool, email, magic_link_expiry_minutes).await?;
+
 	// Send login code email
+	let login_email = LoginEmail {
+		code: &login_code,
+		code_expiry_hours: expiry_hours,
+		magic_link: &login_link,
+		magic_link_expiry_minutes,
+	};
 	if let Err(e) = email_service
-		.send_login_code_email(&req.email, &login_code)
+		.send_login_code_email(email, &login_email, accept_language)
 		.await
 	{
 		error!("Failed to send login code email: {}", e);
-		return Ok(HttpResponse::InternalServerError()
-			.json("Failed to send login code email. Please try again later."));
+		return Err(InternalError::from_response(
+			"Failed to send login code email",
+			HttpResponse::InternalServerError()
+				.json("Failed to send login code email. Please try again later."),
+		)
+		.into());
 	}
 
+	Ok(())
+}
+
+/// The response refusing a login to a disabled or banned account, if it is.
+async fn inactive_account_response(
+	pool: &DbPool,
+	account: &Account,
+) -> Result<Option<HttpResponse>, actix_web::Error> {
+	let status = Account::status(pool, &account.account_id)
+		.await
+		.map_err(|e| {
+			error!("Database error: {}", e);
+			actix_web::error::ErrorInternalServerError("Internal server error")
+		})?;
+	Ok(match status.as_deref() {
+		Some(ACCOUNT_ACTIVE) | None => None,
+		Some(_) => Some(HttpResponse::Forbidden().json("This account is disabled")),
+	})
+}
+
+#[post("/request-login-code")]
+pub async fn request_login_code(
+	pool: web::Data<DbPool>,
+	email_service: web::Data<EmailService>,
+	http_req: HttpRequest,
+	req: Json<RequestLoginCodeRequest>,
+) -> Result<HttpResponse, actix_web::Error> {
+	// Validate input
+	if let Err(e) = req.validate() {
+		return Ok(HttpResponse::BadRequest().json(format!("Invalid email: {}", e)));
+	}
+
+	// Create or get account
+	let account = Account::create_or_get(pool.get_ref(), &req.email)
+		.await
+		.map_err(|e| {
+			error!("Database error: {}", e);
+			actix_web::error::ErrorInternalServerError("Internal server error")
+		})?;
+
+	if let Some(response) = inactive_account_response(pool.get_ref(), &account).await? {
+		return Ok(response);
+	}
+
+	let accept_language = http_req
+		.headers()
+		.get(header::ACCEPT_LANGUAGE)
+		.and_then(|v| v.to_str().ok());
+	send_login_code(
+		pool.get_ref(),
+		email_service.get_ref(),
+		&req.email,
+		accept_language,
+	)
+	.await?;
+
 	Ok(HttpResponse::Ok().json(RequestLoginCodeResponse {
 		message: "Login code sent successfully. Please check your email.".to_string(),
 	}))
@@ -119,24 +402,80 @@ pub async fn request_login_code(
 
 #[post("/validate-login-code")]
 pub async fn validate_login_code(
-	pool: web::Data<SqlitePool>,
+	pool: web::Data<DbPool>,
+	client: ClientInfo,
 	req: Json<ValidateLoginCodeRequest>,
 ) -> Result<HttpResponse, actix_web::Error> {
-	// Get and validate login code
-	let code_record = sqlx::query!(
-		"SELECT email FROM login_codes 
-         WHERE code = ? AND email = ? AND datetime(expires_at) > datetime('now')",
-		req.code,
-		req.email
+	let db_error = |e: sqlx::Error| {
+		error!("Database error: {}", e);
+		actix_web::error::ErrorInternalServerError("Internal server error")
+	};
+
+	// Expiry timestamps are stored as RFC 3339 UTC text, which compares
+	// chronologically as a plain string on both SQLite and PostgreSQL.
+	let code_record: Option<(i64, String)> = sqlx::query_as(
+		"SELECT id, code_hash FROM login_codes WHERE email = $1 AND expires_at > $2",
 	)
+	.bind(&req.email)
+	.bind(Utc::now().to_rfc3339())
 	.fetch_optional(pool.get_ref())
 	.await
-	.map_err(|e| {
-		error!("Database error: {}", e);
-		actix_web::error::ErrorInternalServerError("Internal server error")
-	})?;
+	.map_err(db_error)?;
+
+	// Counting the attempt before checking it bounds the guesses, even
+	// concurrent ones, to MAX_CODE_ATTEMPTS per code.
+	let mut attempt_allowed = false;
+	if let Some((id, _)) = &code_record {
+		attempt_allowed = sqlx::query(
+			"UPDATE login_codes SET attempts = attempts + 1
+			 WHERE id = $1 AND attempts < $2",
+		)
+		.bind(id)
+		.bind(MAX_CODE_ATTEMPTS)
+		.execute(pool.get_ref())
+		.await
+		.map_err(db_error)?
+		.rows_affected()
+			== 1;
+	}
+
+	let valid = match &code_record {
+		Some((id, code_hash))
+			if attempt_allowed && verify_login_code(&req.email, &req.code, code_hash) =>
+		{
+			// Deleting the row is what makes the code single-use
+			sqlx::query("DELETE FROM login_codes WHERE id = $1")
+				.bind(id)
+				.execute(pool.get_ref())
+				.await
+				.map_err(db_error)?
+				.rows_affected()
+				== 1
+		}
+		_ => false,
+	};
 
-	if code_record.is_none() {
+	if !valid {
+		// A code is deleted once its last attempt is used up
+		if let Some((id, _)) = &code_record {
+			sqlx::query("DELETE FROM login_codes WHERE id = $1 AND attempts >= $2")
+				.bind(id)
+				.bind(MAX_CODE_ATTEMPTS)
+				.execute(pool.get_ref())
+				.await
+				.map_err(db_error)?;
+		}
+		// Failed attempts are only auditable for emails that have an account
+		if let Ok(Some(account)) = Account::get_by_email(pool.get_ref(), &req.email).await
+		{
+			audit::record(
+				pool.get_ref(),
+				&account.account_id,
+				&client,
+				AuditEvent::LoginFailed { method: "code" },
+			)
+			.await;
+		}
 		return Ok(HttpResponse::BadRequest().json("Invalid or expired login code"));
 	}
 
@@ -152,34 +491,133 @@ pub async fn validate_login_code(
 			actix_web::error::ErrorInternalServerError("Account not found")
 		})?;
 
+	if let Some(response) = inactive_account_response(pool.get_ref(), &account).await? {
+		return Ok(response);
+	}
+
 	// Generate JWT with 3 months expiration
-	let expiration = Utc::now() + Duration::days(90); // 3 months
-	let claims = Claims {
-		sub: account.account_id.clone(),
-		email: account.email.clone(),
-		exp: expiration.timestamp(),
-	};
+	let (token, _) = issue_token(&account)?;
 
-	let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+	audit::record(
+		pool.get_ref(),
+		&account.account_id,
+		&client,
+		AuditEvent::Login { method: "code" },
+	)
+	.await;
 
-	let token = encode(
-		&Header::default(),
-		&claims,
-		&EncodingKey::from_secret(jwt_secret.as_bytes()),
+	let profile = profile::load_profile(pool.get_ref(), &account.account_id)
+		.await
+		.map_err(|e| {
+			error!("Database error: {}", e);
+			actix_web::error::ErrorInternalServerError("Failed to get profile")
+		})?;
+
+	Ok(HttpResponse::Ok().json(AuthResponse {
+		token,
+		account,
+		profile,
+	}))
+}
+
+/// Verifies and consumes a magic link token, returning the account it logs in.
+/// Errors are short reason codes passed back to the IDE in the deep link.
+async fn consume_magic_link(pool: &DbPool, token: &str) -> Result<Account, &'static str> {
+	let claims = decode::<MagicLinkClaims>(
+		token,
+		&DecodingKey::from_secret(jwt_secret().as_bytes()),
+		&Validation::default(),
 	)
+	.map_err(|e| match e.kind() {
+		jsonwebtoken::errors::ErrorKind::ExpiredSignature => "expired_link",
+		_ => "invalid_link",
+	})?
+	.claims;
+
+	if claims.purpose != MAGIC_LINK_PURPOSE {
+		return Err("invalid_link");
+	}
+
+	// Deleting the row is what makes the link single-use
+	let consumed = sqlx::query(
+		"DELETE FROM magic_links WHERE jti = $1 AND email = $2 AND expires_at > $3",
+	)
+	.bind(&claims.jti)
+	.bind(&claims.sub)
+	.bind(Utc::now().to_rfc3339())
+	.execute(pool)
+	.await
 	.map_err(|e| {
-		error!("Token generation error: {}", e);
-		actix_web::error::ErrorInternalServerError("Authentication error")
+		error!("Database error: {}", e);
+		"server_error"
 	})?;
+	if consumed.rows_affected() == 0 {
+		return Err("expired_link");
+	}
 
-	// Clean up used login code
-	sqlx::query!("DELETE FROM login_codes WHERE code = ?", req.code)
-		.execute(pool.get_ref())
+	// The emailed code is no longer needed once the link was used
+	if let Err(e) = sqlx::query("DELETE FROM login_codes WHERE email = $1")
+		.bind(&claims.sub)
+		.execute(pool)
+		.await
+	{
+		error!("Database error: {}", e);
+	}
+
+	let account = Account::get_by_email(pool, &claims.sub)
 		.await
 		.map_err(|e| {
 			error!("Database error: {}", e);
-			actix_web::error::ErrorInternalServerError("Failed to clean up login code")
-		})?;
+			"server_error"
+		})?
+		.ok_or("invalid_link")?;
+
+	match Account::status(pool, &account.account_id).await {
+		Ok(Some(status)) if status != ACCOUNT_ACTIVE => Err("account_disabled"),
+		Ok(_) => Ok(account),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Err("server_error")
+		}
+	}
+}
+
+/// Target of the link in the login email. Completes the login and redirects to
+/// `ariana://auth?token=...&email=...&account_id=...&expires_at=...`, or to
+/// `ariana://auth?error=<reason>` if the link is invalid, expired or used, or
+/// the account is disabled.
+#[get("/magic")]
+pub async fn magic_link(
+	pool: web::Data<DbPool>,
+	client: ClientInfo,
+	query: web::Query<MagicLinkQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+	let params = match consume_magic_link(pool.get_ref(), &query.token).await {
+		Ok(account) => {
+			let (token, expiration) = issue_token(&account)?;
+			audit::record(
+				pool.get_ref(),
+				&account.account_id,
+				&client,
+				AuditEvent::Login {
+					method: "magic_link",
+				},
+			)
+			.await;
+			vec![
+				("token", token),
+				("email", account.email),
+				("account_id", account.account_id),
+				("expires_at", expiration.to_rfc3339()),
+			]
+		}
+		Err(reason) => vec![("error", reason.to_string())],
+	};
+
+	let location = reqwest::Url::parse_with_params(AUTH_DEEP_LINK, &params)
+		.map_err(|_| actix_web::error::ErrorInternalServerError("Invalid redirect"))?;
 
-	Ok(HttpResponse::Ok().json(AuthResponse { token, account }))
+	Ok(HttpResponse::Found()
+		.insert_header((header::LOCATION, location.as_str()))
+		.finish())
 }
@@ -0,0 +1,573 @@
+use crate::{auth::AuthenticatedAccount, llm::api::ApiError};
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use actix_ws::AggregatedMessage;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Largest single WebSocket message accepted from a client.
+const MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Upper bound on the document history kept for a room. Updates past it are
+/// rejected so a runaway client cannot grow server memory without limit.
+const MAX_ROOM_HISTORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Output kept per shared terminal so late joiners see recent context.
+const MAX_TERMINAL_SCROLLBACK: usize = 64 * 1024;
+
+/// Rooms nobody has joined yet are dropped after this long.
+const UNUSED_ROOM_TTL: Duration = Duration::from_secs(60 * 60);
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// A person connected to a room. Outgoing messages are queued on `tx` and
+/// written to the socket by the member's connection task.
+struct Member {
+	account_id: String,
+	email: String,
+	tx: mpsc::UnboundedSender<String>,
+}
+
+#[derive(Default)]
+struct DocumentLog {
+	/// Base64 encoded automerge change chunks, in arrival order.
+	changes: Vec<String>,
+}
+
+struct SharedTerminal {
+	title: String,
+	scrollback: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorState {
+	pub document: String,
+	pub anchor: String,
+	pub head: String,
+}
+
+struct Room {
+	created_by: String,
+	created_at: Instant,
+	next_member_id: u64,
+	members: HashMap<u64, Member>,
+	documents: HashMap<String, DocumentLog>,
+	history_bytes: usize,
+	/// (owner member id, terminal id) -> terminal
+	terminals: HashMap<(u64, String), SharedTerminal>,
+	cursors: HashMap<u64, CursorState>,
+}
+
+impl Room {
+	fn new(created_by: String) -> Self {
+		Room {
+			created_by,
+			created_at: Instant::now(),
+			next_member_id: 1,
+			members: HashMap::new(),
+			documents: HashMap::new(),
+			history_bytes: 0,
+			terminals: HashMap::new(),
+			cursors: HashMap::new(),
+		}
+	}
+
+	fn send_to(&self, member_id: u64, message: &ServerMessage) {
+		if let Some(member) = self.members.get(&member_id) {
+			if let Ok(text) = serde_json::to_string(message) {
+				let _ = member.tx.send(text);
+			}
+		}
+	}
+
+	/// Sends to every member except `except`.
+	fn broadcast(&self, except: Option<u64>, message: &ServerMessage) {
+		let Ok(text) = serde_json::to_string(message) else {
+			return;
+		};
+		for (id, member) in &self.members {
+			if Some(*id) != except {
+				let _ = member.tx.send(text.clone());
+			}
+		}
+	}
+
+	fn member_info(&self, member_id: u64) -> Option<MemberInfo> {
+		self.members.get(&member_id).map(|member| MemberInfo {
+			member_id,
+			account_id: member.account_id.clone(),
+			email: member.email.clone(),
+		})
+	}
+}
+
+/// Live collaboration rooms. State is held in memory only: a room lives as
+/// long as someone is connected to it.
+#[derive(Clone, Default)]
+pub struct CollabHub {
+	rooms: Arc<Mutex<HashMap<String, Room>>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberInfo {
+	pub member_id: u64,
+	pub account_id: String,
+	pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalInfo {
+	pub owner: u64,
+	pub terminal: String,
+	pub title: String,
+	pub scrollback: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberCursor {
+	pub member_id: u64,
+	#[serde(flatten)]
+	pub cursor: CursorState,
+}
+
+/// Messages sent by clients over the room socket.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+	/// Asks for the history of a document. The first member to open a
+	/// document is told to create it.
+	Open {
+		document: String,
+	},
+	Update {
+		document: String,
+		change: String,
+	},
+	Cursor(CursorState),
+	TerminalShare {
+		terminal: String,
+		title: String,
+	},
+	TerminalUnshare {
+		terminal: String,
+	},
+	TerminalOutput {
+		terminal: String,
+		data: String,
+	},
+}
+
+/// Messages sent by the server over the room socket.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+	Welcome {
+		member_id: u64,
+		members: Vec<MemberInfo>,
+		documents: Vec<String>,
+		terminals: Vec<TerminalInfo>,
+		cursors: Vec<MemberCursor>,
+	},
+	Joined {
+		member: MemberInfo,
+	},
+	Left {
+		member_id: u64,
+	},
+	Document {
+		document: String,
+		changes: Vec<String>,
+		create: bool,
+	},
+	Update {
+		from: u64,
+		document: String,
+		change: String,
+	},
+	Cursor {
+		from: u64,
+		#[serde(flatten)]
+		cursor: CursorState,
+	},
+	TerminalShared {
+		terminal: TerminalInfo,
+	},
+	TerminalUnshared {
+		owner: u64,
+		terminal: String,
+	},
+	TerminalOutput {
+		owner: u64,
+		terminal: String,
+		data: String,
+	},
+	Error {
+		message: String,
+	},
+}
+
+impl CollabHub {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn create_room(&self, account: &AuthenticatedAccount) -> String {
+		let room_id = Uuid::new_v4().to_string();
+		let mut rooms = self.rooms.lock().unwrap();
+		rooms.retain(|_, room| {
+			!room.members.is_empty() || room.created_at.elapsed() < UNUSED_ROOM_TTL
+		});
+		rooms.insert(room_id.clone(), Room::new(account.account_id.clone()));
+		room_id
+	}
+
+	fn room_exists(&self, room_id: &str) -> bool {
+		self.rooms.lock().unwrap().contains_key(room_id)
+	}
+
+	/// Adds a member and sends them the room state. Returns `None` when the
+	/// room no longer exists.
+	fn join(
+		&self,
+		room_id: &str,
+		account: &AuthenticatedAccount,
+		tx: mpsc::UnboundedSender<String>,
+	) -> Option<u64> {
+		let mut rooms = self.rooms.lock().unwrap();
+		let room = rooms.get_mut(room_id)?;
+		let member_id = room.next_member_id;
+		room.next_member_id += 1;
+		room.members.insert(
+			member_id,
+			Member {
+				account_id: account.account_id.clone(),
+				email: account.email.clone(),
+				tx,
+			},
+		);
+
+		let mut members: Vec<MemberInfo> = room
+			.members
+			.keys()
+			.filter_map(|id| room.member_info(*id))
+			.collect();
+		members.sort_by_key(|member| member.member_id);
+		let mut documents: Vec<String> = room.documents.keys().cloned().collect();
+		documents.sort();
+		let terminals = room
+			.terminals
+			.iter()
+			.map(|((owner, terminal), shared)| TerminalInfo {
+				owner: *owner,
+				terminal: terminal.clone(),
+				title: shared.title.clone(),
+				scrollback: shared.scrollback.clone(),
+			})
+			.collect();
+		let cursors = room
+			.cursors
+			.iter()
+			.map(|(id, cursor)| MemberCursor {
+				member_id: *id,
+				cursor: cursor.clone(),
+			})
+			.collect();
+
+		room.send_to(
+			member_id,
+			&ServerMessage::Welcome {
+				member_id,
+				members,
+				documents,
+				terminals,
+				cursors,
+			},
+		);
+		if let Some(member) = room.member_info(member_id) {
+			room.broadcast(Some(member_id), &ServerMessage::Joined { member });
+		}
+		Some(member_id)
+	}
+
+	/// Removes a member along with their cursor and shared terminals. The room
+	/// is dropped once the last member leaves.
+	fn leave(&self, room_id: &str, member_id: u64) {
+		let mut rooms = self.rooms.lock().unwrap();
+		let Some(room) = rooms.get_mut(room_id) else {
+			return;
+		};
+		room.members.remove(&member_id);
+		room.cursors.remove(&member_id);
+		let unshared: Vec<String> = room
+			.terminals
+			.keys()
+			.filter(|(owner, _)| *owner == member_id)
+			.map(|(_, terminal)| terminal.clone())
+			.collect();
+		for terminal in unshared {
+			room.terminals.remove(&(member_id, terminal.clone()));
+			room.broadcast(
+				None,
+				&ServerMessage::TerminalUnshared {
+					owner: member_id,
+					terminal,
+				},
+			);
+		}
+		room.broadcast(None, &ServerMessage::Left { member_id });
+
+		if room.members.is_empty() {
+			info!(
+				"Closing collaboration room {} created by {}",
+				room_id, room.created_by
+			);
+			rooms.remove(room_id);
+		}
+	}
+
+	fn handle_message(&self, room_id: &str, member_id: u64, text: &str) {
+		let mut rooms = self.rooms.lock().unwrap();
+		let Some(room) = rooms.get_mut(room_id) else {
+			return;
+		};
+		let message = match serde_json::from_str::<ClientMessage>(text) {
+			Ok(message) => message,
+			Err(e) => {
+				room.send_to(
+					member_id,
+					&ServerMessage::Error {
+						message: format!("Invalid message: {}", e),
+					},
+				);
+				return;
+			}
+		};
+
+		match message {
+			ClientMessage::Open { document } => {
+				let create = !room.documents.contains_key(&document);
+				let changes = room
+					.documents
+					.entry(document.clone())
+					.or_default()
+					.changes
+					.clone();
+				room.send_to(
+					member_id,
+					&ServerMessage::Document {
+						document,
+						changes,
+						create,
+					},
+				);
+			}
+			ClientMessage::Update { document, change } => {
+				if room.history_bytes + change.len() > MAX_ROOM_HISTORY_BYTES {
+					room.send_to(
+						member_id,
+						&ServerMessage::Error {
+							message: "Room history is full, start a new room".to_string(),
+						},
+					);
+					return;
+				}
+				room.history_bytes += change.len();
+				room.documents
+					.entry(document.clone())
+					.or_default()
+					.changes
+					.push(change.clone());
+				room.broadcast(
+					Some(member_id),
+					&ServerMessage::Update {
+						from: member_id,
+						document,
+						change,
+					},
+				);
+			}
+			ClientMessage::Cursor(cursor) => {
+				room.cursors.insert(member_id, cursor.clone());
+				room.broadcast(
+					Some(member_id),
+					&ServerMessage::Cursor {
+						from: member_id,
+						cursor,
+					},
+				);
+			}
+			ClientMessage::TerminalShare { terminal, title } => {
+				room.terminals.insert(
+					(member_id, terminal.clone()),
+					SharedTerminal {
+						title: title.clone(),
+						scrollback: String::new(),
+					},
+				);
+				room.broadcast(
+					Some(member_id),
+					&ServerMessage::TerminalShared {
+						terminal: TerminalInfo {
+							owner: member_id,
+							terminal,
+							title,
+							scrollback: String::new(),
+						},
+					},
+				);
+			}
+			ClientMessage::TerminalUnshare { terminal } => {
+				if room
+					.terminals
+					.remove(&(member_id, terminal.clone()))
+					.is_some()
+				{
+					room.broadcast(
+						Some(member_id),
+						&ServerMessage::TerminalUnshared {
+							owner: member_id,
+							terminal,
+						},
+					);
+				}
+			}
+			ClientMessage::TerminalOutput { terminal, data } => {
+				// Only the owner can write to a shared terminal; everyone else
+				// gets a read-only stream.
+				let Some(shared) = room.terminals.get_mut(&(member_id, terminal.clone()))
+				else {
+					return;
+				};
+				shared.scrollback.push_str(&data);
+				if shared.scrollback.len() > MAX_TERMINAL_SCROLLBACK {
+					let mut cut = shared.scrollback.len() - MAX_TERMINAL_SCROLLBACK;
+					while !shared.scrollback.is_char_boundary(cut) {
+						cut += 1;
+					}
+					shared.scrollback.drain(..cut);
+				}
+				room.broadcast(
+					Some(member_id),
+					&ServerMessage::TerminalOutput {
+						owner: member_id,
+						terminal,
+						data,
+					},
+				);
+			}
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateRoomResponse {
+	pub room_id: String,
+}
+
+/// Creates a room. Its id acts as the invitation: any signed in account that
+/// knows it can join.
+pub async fn create_room(
+	hub: web::Data<CollabHub>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	let room_id = hub.create_room(&account);
+	info!(
+		"Account {} created collaboration room {}",
+		account.account_id, room_id
+	);
+	Ok(HttpResponse::Created().json(CreateRoomResponse { room_id }))
+}
+
+/// Upgrades to the room WebSocket.
+pub async fn join_room(
+	req: HttpRequest,
+	body: web::Payload,
+	hub: web::Data<CollabHub>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let room_id = path.into_inner();
+	if !hub.room_exists(&room_id) {
+		return Ok(HttpResponse::NotFound().json(ApiError {
+			error: "Room not found".to_string(),
+			code: "ROOM_NOT_FOUND".to_string(),
+		}));
+	}
+
+	let (response, session, stream) = actix_ws::handle(&req, body)?;
+	let (tx, rx) = mpsc::unbounded_channel();
+	let hub = hub.get_ref().clone();
+	let member_id = hub.join(&room_id, &account, tx);
+	actix_web::rt::spawn(async move {
+		match member_id {
+			Some(member_id) => {
+				run_member(&hub, &room_id, member_id, session, stream, rx).await;
+				hub.leave(&room_id, member_id);
+			}
+			None => {
+				let _ = session.close(None).await;
+			}
+		}
+	});
+	Ok(response)
+}
+
+/// Pumps a member's socket until either side goes away.
+async fn run_member(
+	hub: &CollabHub,
+	room_id: &str,
+	member_id: u64,
+	mut session: actix_ws::Session,
+	stream: actix_ws::MessageStream,
+	mut rx: mpsc::UnboundedReceiver<String>,
+) {
+	let mut stream = stream
+		.max_frame_size(MAX_MESSAGE_BYTES)
+		.aggregate_continuations()
+		.max_continuation_size(MAX_MESSAGE_BYTES);
+	let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+	let mut last_seen = Instant::now();
+
+	loop {
+		tokio::select! {
+			outgoing = rx.recv() => {
+				let Some(text) = outgoing else { break };
+				if session.text(text).await.is_err() {
+					break;
+				}
+			}
+			incoming = stream.recv() => {
+				last_seen = Instant::now();
+				match incoming {
+					Some(Ok(AggregatedMessage::Text(text))) => {
+						hub.handle_message(room_id, member_id, &text);
+					}
+					Some(Ok(AggregatedMessage::Ping(bytes))) => {
+						if session.pong(&bytes).await.is_err() {
+							break;
+						}
+					}
+					Some(Ok(AggregatedMessage::Pong(_)))
+					| Some(Ok(AggregatedMessage::Binary(_))) => {}
+					Some(Ok(AggregatedMessage::Close(_))) | None => break,
+					Some(Err(e)) => {
+						warn!("Collaboration socket error in room {}: {}", room_id, e);
+						break;
+					}
+				}
+			}
+			_ = heartbeat.tick() => {
+				if last_seen.elapsed() > CLIENT_TIMEOUT || session.ping(b"").await.is_err() {
+					break;
+				}
+			}
+		}
+	}
+
+	let _ = session.close(None).await;
+}
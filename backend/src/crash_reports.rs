@@ -0,0 +1,140 @@
+use crate::{
+	auth::AuthenticatedAccount, database::DbPool, errors::internal_error,
+	llm::api::ApiError,
+};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Request body limit for crash reports, which may carry a minidump.
+pub const MAX_REPORT_BYTES: usize = 16 * 1024 * 1024;
+const MAX_MINIDUMP_BYTES: usize = 8 * 1024 * 1024;
+const MAX_TEXT_LEN: usize = 256 * 1024;
+const MAX_LOG_LINES: usize = 1000;
+
+/// A crash report sent by the desktop app once the user has agreed to
+/// share them.
+#[derive(Debug, Deserialize)]
+pub struct CrashReportRequest {
+	pub report_id: String,
+	/// `panic` for Rust panics, `native` for crashes captured as a minidump.
+	pub kind: String,
+	pub occurred_at: DateTime<Utc>,
+	pub app_version: String,
+	pub os: String,
+	pub arch: String,
+	pub message: Option<String>,
+	pub location: Option<String>,
+	pub thread: Option<String>,
+	pub backtrace: Option<String>,
+	/// Log lines leading up to the crash, oldest first.
+	#[serde(default)]
+	pub logs: Vec<String>,
+	/// Base64 encoded minidump.
+	pub minidump: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrashReportResponse {
+	pub report_id: String,
+}
+
+fn invalid(error: String) -> HttpResponse {
+	HttpResponse::BadRequest().json(ApiError {
+		error,
+		code: "INVALID_CRASH_REPORT".to_string(),
+	})
+}
+
+fn validate(report: &CrashReportRequest) -> Result<(), String> {
+	if Uuid::parse_str(&report.report_id).is_err() {
+		return Err("report_id must be a UUID".to_string());
+	}
+	if !matches!(report.kind.as_str(), "panic" | "native") {
+		return Err("kind must be panic or native".to_string());
+	}
+	let required = [&report.app_version, &report.os, &report.arch];
+	let optional = [
+		&report.message,
+		&report.location,
+		&report.thread,
+		&report.backtrace,
+	];
+	if required
+		.into_iter()
+		.chain(optional.into_iter().filter_map(Option::as_ref))
+		.any(|v| v.len() > MAX_TEXT_LEN)
+	{
+		return Err(format!("Text fields are limited to {} bytes", MAX_TEXT_LEN));
+	}
+	if report.logs.len() > MAX_LOG_LINES {
+		return Err(format!("At most {} log lines are accepted", MAX_LOG_LINES));
+	}
+	if let Some(minidump) = &report.minidump {
+		let bytes = BASE64
+			.decode(minidump)
+			.map_err(|_| "minidump must be base64 encoded".to_string())?;
+		if bytes.len() > MAX_MINIDUMP_BYTES {
+			return Err(format!(
+				"Minidumps are limited to {} bytes",
+				MAX_MINIDUMP_BYTES
+			));
+		}
+	}
+	Ok(())
+}
+
+/// Stores a crash report. The token is optional; reports from logged in
+/// users are linked to their account. Re-sending a report is a no-op.
+///
+/// `POST /api/crash-report`
+pub async fn submit_crash_report(
+	pool: web::Data<DbPool>,
+	account: Option<AuthenticatedAccount>,
+	body: web::Json<CrashReportRequest>,
+) -> ActixResult<HttpResponse> {
+	if let Err(e) = validate(&body) {
+		return Ok(invalid(e));
+	}
+	let logs = match serde_json::to_string(&body.logs) {
+		Ok(logs) => logs,
+		Err(e) => return Ok(invalid(e.to_string())),
+	};
+
+	let result = sqlx::query(
+		"INSERT INTO crash_reports
+		 (report_id, account_id, kind, app_version, os, arch, message, location,
+		  thread, backtrace, logs, minidump, occurred_at, created_at)
+		 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+		 ON CONFLICT (report_id) DO NOTHING",
+	)
+	.bind(&body.report_id)
+	.bind(account.map(|a| a.account_id))
+	.bind(&body.kind)
+	.bind(&body.app_version)
+	.bind(&body.os)
+	.bind(&body.arch)
+	.bind(&body.message)
+	.bind(&body.location)
+	.bind(&body.thread)
+	.bind(&body.backtrace)
+	.bind(logs)
+	.bind(&body.minidump)
+	.bind(body.occurred_at.to_rfc3339())
+	.bind(Utc::now().to_rfc3339())
+	.execute(pool.get_ref())
+	.await;
+
+	match result {
+		Ok(_) => Ok(HttpResponse::Accepted().json(CrashReportResponse {
+			report_id: body.report_id.clone(),
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
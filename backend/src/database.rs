@@ -1,54 +1,283 @@
 use chrono::Utc;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use sqlx::{migrate::MigrateError, Pool, Sqlite, SqlitePool};
+use sqlx::{
+	any::{install_default_drivers, AnyPoolOptions},
+	migrate::{Migrate, MigrateError, Migrator},
+	AnyPool, FromRow,
+};
+use std::collections::HashSet;
+use std::env;
+use std::time::Duration;
+use thiserror::Error;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Connection pool shared by all handlers. The concrete driver (SQLite or
+/// PostgreSQL) is picked at runtime from the `DATABASE_URL` scheme, so every
+/// query must stick to SQL both accept and use `$N` placeholders.
+pub type DbPool = AnyPool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseKind {
+	Sqlite,
+	Postgres,
+}
+
+impl DatabaseKind {
+	pub fn from_url(database_url: &str) -> Option<Self> {
+		if database_url.starts_with("sqlite:") {
+			Some(DatabaseKind::Sqlite)
+		} else if database_url.starts_with("postgres:")
+			|| database_url.starts_with("postgresql:")
+		{
+			Some(DatabaseKind::Postgres)
+		} else {
+			None
+		}
+	}
+}
+
+/// Status of an account that can log in. Others are `disabled`, which
+/// support can undo, and `banned`, which only admins can.
+pub const ACCOUNT_ACTIVE: &str = "active";
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Account {
-	pub account_id: String, // Store as String for SQLite compatibility
+	pub account_id: String,
 	pub email: String,
-	pub created_at: String, // Store as String for SQLite compatibility
+	pub created_at: String, // RFC 3339, stored as text on every backend
+}
+
+static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Connection attempts at startup, so the server can start alongside its
+/// database.
+const CONNECT_ATTEMPTS: u32 = 5;
+
+/// Statements that lose data or break the previous release still running
+/// during a rollout.
+const DESTRUCTIVE_STATEMENTS: [&str; 6] = [
+	"DROP TABLE",
+	"DROP COLUMN",
+	"DELETE FROM",
+	"TRUNCATE",
+	"RENAME COLUMN",
+	"RENAME TO",
+];
+
+/// Destructive migrations whose effect was reviewed and accepted for every
+/// existing deployment when they were added. They run without confirmation;
+/// any other destructive migration still needs it.
+const REVIEWED_MIGRATIONS: [i64; 1] = [
+	// Drops outstanding plaintext login codes. They live for
+	// `EMAIL_VERIFICATION_EXPIRY_HOURS` (24 by default), so anyone mid-login
+	// requests a new code.
+	20250725120000,
+];
+
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+	env::var(name)
+		.ok()
+		.and_then(|value| value.parse().ok())
+		.unwrap_or(default)
+}
+
+/// Pool limits come from `DB_MAX_CONNECTIONS`, `DB_MIN_CONNECTIONS`,
+/// `DB_ACQUIRE_TIMEOUT_SECS`, `DB_IDLE_TIMEOUT_SECS` and
+/// `DB_MAX_LIFETIME_SECS`. SQLite connections wait up to
+/// `DB_BUSY_TIMEOUT_MS` for a lock instead of failing with `SQLITE_BUSY`.
+pub async fn create_pool(
+	database_url: &str,
+	kind: DatabaseKind,
+) -> Result<DbPool, sqlx::Error> {
+	install_default_drivers();
+	let busy_timeout_ms: u64 = env_number("DB_BUSY_TIMEOUT_MS", 5000);
+	let options = AnyPoolOptions::new()
+		.max_connections(env_number("DB_MAX_CONNECTIONS", 10))
+		.min_connections(env_number("DB_MIN_CONNECTIONS", 0))
+		.acquire_timeout(Duration::from_secs(env_number(
+			"DB_ACQUIRE_TIMEOUT_SECS",
+			30,
+		)))
+		.idle_timeout(Duration::from_secs(env_number("DB_IDLE_TIMEOUT_SECS", 600)))
+		.max_lifetime(Duration::from_secs(env_number(
+			"DB_MAX_LIFETIME_SECS",
+			1800,
+		)))
+		.after_connect(move |conn, _meta| {
+			Box::pin(async move {
+				if kind == DatabaseKind::Sqlite {
+					// WAL lets readers continue while a write is in progress
+					sqlx::query("PRAGMA journal_mode = WAL")
+						.execute(&mut *conn)
+						.await?;
+					sqlx::query(&format!("PRAGMA busy_timeout = {}", busy_timeout_ms))
+						.execute(&mut *conn)
+						.await?;
+				}
+				Ok(())
+			})
+		});
+
+	let mut attempt = 1;
+	loop {
+		match options.clone().connect(database_url).await {
+			Ok(pool) => return Ok(pool),
+			Err(e) if attempt < CONNECT_ATTEMPTS => {
+				let delay = Duration::from_secs(2u64.pow(attempt));
+				warn!(
+					"Failed to connect to the database (attempt {}), retrying in {:?}: {}",
+					attempt, delay, e
+				);
+				tokio::time::sleep(delay).await;
+				attempt += 1;
+			}
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+	#[error(transparent)]
+	Migrate(#[from] MigrateError),
+
+	#[error(
+		"Refusing to run destructive migrations without confirmation \
+		 (ALLOW_DESTRUCTIVE_MIGRATIONS or allow_destructive): {}",
+		.0.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ")
+	)]
+	Destructive(Vec<PendingMigration>),
+}
+
+/// A migration not applied to the database yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingMigration {
+	pub version: i64,
+	pub description: String,
+	/// The destructive statements it contains, if any.
+	pub destructive: Vec<&'static str>,
+	/// Whether those statements were reviewed and run without confirmation.
+	pub reviewed: bool,
+}
+
+impl PendingMigration {
+	/// Whether applying it needs `allow_destructive`.
+	pub fn needs_confirmation(&self) -> bool {
+		!self.destructive.is_empty() && !self.reviewed
+	}
 }
 
-pub async fn create_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
-	SqlitePool::connect(database_url).await
+impl std::fmt::Display for PendingMigration {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} {}", self.version, self.description)?;
+		if !self.destructive.is_empty() {
+			let reviewed = if self.reviewed { ", reviewed" } else { "" };
+			write!(f, " ({}{})", self.destructive.join(", "), reviewed)?;
+		}
+		Ok(())
+	}
+}
+
+fn migrator(kind: DatabaseKind) -> &'static Migrator {
+	match kind {
+		DatabaseKind::Sqlite => &SQLITE_MIGRATIONS,
+		DatabaseKind::Postgres => &POSTGRES_MIGRATIONS,
+	}
+}
+
+fn destructive_statements(sql: &str) -> Vec<&'static str> {
+	let sql = sql
+		.lines()
+		.map(|line| line.split("--").next().unwrap_or_default())
+		.collect::<Vec<_>>()
+		.join(" ")
+		.split_whitespace()
+		.collect::<Vec<_>>()
+		.join(" ")
+		.to_uppercase();
+	DESTRUCTIVE_STATEMENTS
+		.into_iter()
+		.filter(|statement| sql.contains(statement))
+		.collect()
+}
+
+/// Migrations not applied yet, oldest first. A fresh database has nothing
+/// to lose, so none of its migrations count as destructive.
+pub async fn pending_migrations(
+	pool: &DbPool,
+	kind: DatabaseKind,
+) -> Result<Vec<PendingMigration>, MigrateError> {
+	let mut conn = pool.acquire().await?;
+	conn.ensure_migrations_table().await?;
+	let applied: HashSet<i64> = conn
+		.list_applied_migrations()
+		.await?
+		.into_iter()
+		.map(|m| m.version)
+		.collect();
+	let fresh = applied.is_empty();
+
+	Ok(migrator(kind)
+		.iter()
+		.filter(|m| {
+			!m.migration_type.is_down_migration() && !applied.contains(&m.version)
+		})
+		.map(|m| PendingMigration {
+			version: m.version,
+			description: m.description.to_string(),
+			destructive: if fresh {
+				Vec::new()
+			} else {
+				destructive_statements(&m.sql)
+			},
+			reviewed: REVIEWED_MIGRATIONS.contains(&m.version),
+		})
+		.collect())
 }
 
-pub async fn run_migrations(pool: &SqlitePool) -> Result<(), MigrateError> {
-	sqlx::migrate!("./migrations").run(pool).await
+/// Applies pending migrations, refusing when one is destructive and not
+/// reviewed unless `allow_destructive` is set. Returns the migrations applied.
+pub async fn run_migrations(
+	pool: &DbPool,
+	kind: DatabaseKind,
+	allow_destructive: bool,
+) -> Result<Vec<PendingMigration>, MigrationError> {
+	let pending = pending_migrations(pool, kind).await?;
+	let destructive: Vec<_> = pending
+		.iter()
+		.filter(|m| m.needs_confirmation())
+		.cloned()
+		.collect();
+	if !destructive.is_empty() && !allow_destructive {
+		return Err(MigrationError::Destructive(destructive));
+	}
+
+	migrator(kind).run(pool).await?;
+	for migration in &pending {
+		info!("Applied migration {}", migration);
+	}
+	Ok(pending)
 }
 
 impl Account {
-	pub async fn create_or_get(
-		pool: &Pool<Sqlite>,
-		email: &str,
-	) -> Result<Self, sqlx::Error> {
+	pub async fn create_or_get(pool: &DbPool, email: &str) -> Result<Self, sqlx::Error> {
 		// Try to find existing account
-		let existing = sqlx::query!(
-			"SELECT account_id, email, created_at FROM accounts WHERE email = ?",
-			email
-		)
-		.fetch_optional(pool)
-		.await?;
-
-		if let Some(row) = existing {
-			return Ok(Account {
-				account_id: row.account_id.unwrap_or_default(),
-				email: row.email,
-				created_at: row.created_at,
-			});
+		if let Some(existing) = Self::get_by_email(pool, email).await? {
+			return Ok(existing);
 		}
 
 		// Create new account
 		let account_id = Uuid::new_v4().to_string();
 		let now = Utc::now().to_rfc3339();
 
-		sqlx::query!(
-			"INSERT INTO accounts (account_id, email, created_at) VALUES (?, ?, ?)",
-			account_id,
-			email,
-			now
+		sqlx::query(
+			"INSERT INTO accounts (account_id, email, created_at) VALUES ($1, $2, $3)",
 		)
+		.bind(&account_id)
+		.bind(email)
+		.bind(&now)
 		.execute(pool)
 		.await?;
 
@@ -59,21 +288,26 @@ impl Account {
 		})
 	}
 
+	/// `None` when the account doesn't exist.
+	pub async fn status(
+		pool: &DbPool,
+		account_id: &str,
+	) -> Result<Option<String>, sqlx::Error> {
+		sqlx::query_scalar("SELECT status FROM accounts WHERE account_id = $1")
+			.bind(account_id)
+			.fetch_optional(pool)
+			.await
+	}
+
 	pub async fn get_by_email(
-		pool: &Pool<Sqlite>,
+		pool: &DbPool,
 		email: &str,
 	) -> Result<Option<Self>, sqlx::Error> {
-		let row = sqlx::query!(
-			"SELECT account_id, email, created_at FROM accounts WHERE email = ?",
-			email
+		sqlx::query_as::<_, Account>(
+			"SELECT account_id, email, created_at FROM accounts WHERE email = $1",
 		)
+		.bind(email)
 		.fetch_optional(pool)
-		.await?;
-
-		Ok(row.map(|r| Account {
-			account_id: r.account_id.unwrap_or_default(),
-			email: r.email,
-			created_at: r.created_at,
-		}))
+		.await
 	}
 }
@@ -0,0 +1,197 @@
+pub mod providers;
+pub mod templates;
+pub mod webhooks;
+
+use crate::database::DbPool;
+use chrono::Utc;
+use log::{error, warn};
+use providers::{EmailError, EmailProvider};
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use templates::{EmailTemplates, RenderedEmail};
+use uuid::Uuid;
+
+/// What goes into a login email: the 6-digit code and the one-time link.
+pub struct LoginEmail<'a> {
+	pub code: &'a str,
+	pub code_expiry_hours: i64,
+	pub magic_link: &'a str,
+	pub magic_link_expiry_minutes: i64,
+}
+
+/// What goes into an organization invitation email.
+pub struct OrgInviteEmail<'a> {
+	pub org_name: &'a str,
+	pub inviter_email: &'a str,
+	pub role: &'a str,
+	pub expiry_days: i64,
+}
+
+#[derive(Clone)]
+pub struct EmailService {
+	/// In order of preference; a failed send falls back to the next one.
+	providers: Arc<Vec<Box<dyn EmailProvider>>>,
+	sender_email: String,
+	templates: Arc<EmailTemplates>,
+	pool: DbPool,
+}
+
+impl EmailService {
+	pub fn new(pool: DbPool) -> Result<Self, EmailError> {
+		let providers = providers::from_env()?;
+		if providers.is_empty() {
+			return Err("EMAIL_PROVIDERS lists no provider".into());
+		}
+		let sender_email = env::var("SENDER_EMAIL")?;
+
+		let templates_dir = env::var("EMAIL_TEMPLATES_DIR").ok().map(PathBuf::from);
+		let templates = EmailTemplates::load(templates_dir.as_deref())?;
+
+		Ok(EmailService {
+			providers: Arc::new(providers),
+			sender_email,
+			templates: Arc::new(templates),
+			pool,
+		})
+	}
+
+	/// Checks that at least one provider is reachable, trying them in order.
+	pub async fn test_connection(&self) -> Result<bool, EmailError> {
+		let mut last_error = None;
+		for provider in self.providers.iter() {
+			match provider.test_connection().await {
+				Ok(true) => return Ok(true),
+				Ok(false) => {}
+				Err(e) => last_error = Some(format!("{}: {}", provider.name(), e)),
+			}
+		}
+		match last_error {
+			Some(e) => Err(e.into()),
+			None => Ok(false),
+		}
+	}
+
+	/// Sends the login code and magic link as a multipart HTML + text email,
+	/// localized from the client's `Accept-Language` header.
+	pub async fn send_login_code_email(
+		&self,
+		to_email: &str,
+		login: &LoginEmail<'_>,
+		accept_language: Option<&str>,
+	) -> Result<(), EmailError> {
+		let locale = self.templates.negotiate_locale(accept_language);
+		let expiry_hours = login.code_expiry_hours.to_string();
+		let magic_link_expiry_minutes = login.magic_link_expiry_minutes.to_string();
+		let rendered = self.templates.render(
+			"login_code",
+			&locale,
+			&[
+				("code", login.code),
+				("expiry_hours", &expiry_hours),
+				("magic_link", login.magic_link),
+				("magic_link_expiry_minutes", &magic_link_expiry_minutes),
+			],
+		)?;
+
+		self.send("login_code", to_email, rendered).await
+	}
+
+	/// Tells someone they were invited to an organization, localized like the
+	/// login email.
+	pub async fn send_org_invite_email(
+		&self,
+		to_email: &str,
+		invite: &OrgInviteEmail<'_>,
+		accept_language: Option<&str>,
+	) -> Result<(), EmailError> {
+		let locale = self.templates.negotiate_locale(accept_language);
+		let expiry_days = invite.expiry_days.to_string();
+		let rendered = self.templates.render(
+			"org_invite",
+			&locale,
+			&[
+				("org_name", invite.org_name),
+				("inviter_email", invite.inviter_email),
+				("role", invite.role),
+				("expiry_days", &expiry_days),
+			],
+		)?;
+
+		self.send("org_invite", to_email, rendered).await
+	}
+
+	/// Sends through the first provider that accepts the email and records
+	/// the delivery, so provider webhooks can update its status later.
+	async fn send(
+		&self,
+		template: &str,
+		to_email: &str,
+		rendered: RenderedEmail,
+	) -> Result<(), EmailError> {
+		let mut errors = Vec::new();
+		for provider in self.providers.iter() {
+			match provider.send(&self.sender_email, to_email, &rendered).await {
+				Ok(message_id) => {
+					if !errors.is_empty() {
+						warn!(
+							"Sent {} email through {} after: {}",
+							template,
+							provider.name(),
+							errors.join("; ")
+						);
+					}
+					self.record_delivery(
+						template,
+						to_email,
+						Some(provider.name()),
+						message_id.as_deref(),
+						"sent",
+						None,
+					)
+					.await;
+					return Ok(());
+				}
+				Err(e) => errors.push(format!("{}: {}", provider.name(), e)),
+			}
+		}
+
+		let error = errors.join("; ");
+		self.record_delivery(template, to_email, None, None, "failed", Some(&error))
+			.await;
+		Err(error.into())
+	}
+
+	/// Failing to record doesn't fail the send, the email is already out.
+	async fn record_delivery(
+		&self,
+		template: &str,
+		recipient: &str,
+		provider: Option<&str>,
+		provider_message_id: Option<&str>,
+		status: &str,
+		error: Option<&str>,
+	) {
+		let now = Utc::now().to_rfc3339();
+		let result = sqlx::query(
+			"INSERT INTO email_deliveries
+			 (delivery_id, template, recipient, provider, provider_message_id, status,
+			  error, created_at, updated_at)
+			 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+		)
+		.bind(Uuid::new_v4().to_string())
+		.bind(template)
+		.bind(recipient)
+		.bind(provider)
+		.bind(provider_message_id)
+		.bind(status)
+		.bind(error)
+		.bind(&now)
+		.bind(&now)
+		.execute(&self.pool)
+		.await;
+		if let Err(e) = result {
+			error!("Failed to record email delivery: {}", e);
+		}
+	}
+}
@@ -0,0 +1,404 @@
+//! Ways of sending email. `EMAIL_PROVIDERS` lists them in order of
+//! preference; when one fails the next is tried.
+
+use super::templates::RenderedEmail;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lettre::{
+	message::MultiPart, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+	AsyncTransport, Message, Tokio1Executor,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::time::Duration;
+
+pub type EmailError = Box<dyn std::error::Error + Send + Sync>;
+
+/// HTTP providers get this long before the next provider is tried.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[async_trait]
+pub trait EmailProvider: Send + Sync {
+	/// Name used in `EMAIL_PROVIDERS`, the delivery records and the webhook
+	/// path.
+	fn name(&self) -> &'static str;
+
+	/// Sends the email, returning the provider's message id when it gives one
+	/// back, which is what its delivery webhooks refer to.
+	async fn send(
+		&self,
+		from: &str,
+		to: &str,
+		email: &RenderedEmail,
+	) -> Result<Option<String>, EmailError>;
+
+	/// Checks that the provider is reachable. HTTP providers have no cheap
+	/// check and report success.
+	async fn test_connection(&self) -> Result<bool, EmailError> {
+		Ok(true)
+	}
+}
+
+fn http_client() -> Result<Client, EmailError> {
+	Ok(Client::builder().timeout(HTTP_TIMEOUT).build()?)
+}
+
+/// Turns a non-2xx response into an error carrying the provider's message.
+async fn check_status(
+	provider: &str,
+	response: reqwest::Response,
+) -> Result<reqwest::Response, EmailError> {
+	let status = response.status();
+	if status.is_success() {
+		return Ok(response);
+	}
+	let body = response.text().await.unwrap_or_default();
+	Err(format!("{} returned {}: {}", provider, status, body.trim()).into())
+}
+
+pub struct SmtpProvider {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpProvider {
+	/// `SMTP_TLS=none` connects without TLS, for local mail sinks like
+	/// Mailpit; an empty `SMTP_USERNAME` skips authentication.
+	pub fn from_env() -> Result<Self, EmailError> {
+		let smtp_server = env::var("SMTP_SERVER")?;
+		let smtp_port = env::var("SMTP_PORT")?.parse::<u16>()?;
+		let smtp_username = env::var("SMTP_USERNAME").unwrap_or_default();
+
+		let builder = match env::var("SMTP_TLS").as_deref() {
+			Ok("none") => {
+				AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp_server)
+			}
+			Ok("starttls") | Ok("") | Err(_) => {
+				// i had runtime errors with normal `relay` and this worked for me.
+				// i used the email service `[resend](http://resend.com/)`
+				AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp_server)?
+			}
+			Ok(other) => return Err(format!("Unknown SMTP_TLS mode `{}`", other).into()),
+		};
+		let builder = builder.port(smtp_port);
+		let transport = if smtp_username.is_empty() {
+			builder.build()
+		} else {
+			let smtp_password = env::var("SMTP_PASSWORD")?;
+			builder
+				.credentials(Credentials::new(smtp_username, smtp_password))
+				.build()
+		};
+
+		Ok(SmtpProvider { transport })
+	}
+}
+
+#[async_trait]
+impl EmailProvider for SmtpProvider {
+	fn name(&self) -> &'static str {
+		"smtp"
+	}
+
+	async fn send(
+		&self,
+		from: &str,
+		to: &str,
+		email: &RenderedEmail,
+	) -> Result<Option<String>, EmailError> {
+		let message = Message::builder()
+			.from(from.parse()?)
+			.to(to.parse()?)
+			.subject(email.subject.clone())
+			.multipart(MultiPart::alternative_plain_html(
+				email.text.clone(),
+				email.html.clone(),
+			))?;
+
+		self.transport.send(message).await?;
+		// SMTP relays don't report delivery back, so there is no id to track.
+		Ok(None)
+	}
+
+	async fn test_connection(&self) -> Result<bool, EmailError> {
+		Ok(self.transport.test_connection().await?)
+	}
+}
+
+/// <https://resend.com/docs/api-reference/emails/send-email>
+pub struct ResendProvider {
+	client: Client,
+	api_key: String,
+}
+
+#[derive(Deserialize)]
+struct ResendResponse {
+	id: String,
+}
+
+impl ResendProvider {
+	pub fn from_env() -> Result<Self, EmailError> {
+		Ok(ResendProvider {
+			client: http_client()?,
+			api_key: env::var("RESEND_API_KEY")?,
+		})
+	}
+}
+
+#[async_trait]
+impl EmailProvider for ResendProvider {
+	fn name(&self) -> &'static str {
+		"resend"
+	}
+
+	async fn send(
+		&self,
+		from: &str,
+		to: &str,
+		email: &RenderedEmail,
+	) -> Result<Option<String>, EmailError> {
+		let response = self
+			.client
+			.post("https://api.resend.com/emails")
+			.bearer_auth(&self.api_key)
+			.json(&json!({
+				"from": from,
+				"to": [to],
+				"subject": email.subject,
+				"html": email.html,
+				"text": email.text,
+			}))
+			.send()
+			.await?;
+		let response: ResendResponse =
+			check_status(self.name(), response).await?.json().await?;
+		Ok(Some(response.id))
+	}
+}
+
+/// <https://www.twilio.com/docs/sendgrid/api-reference/mail-send/mail-send>
+pub struct SendGridProvider {
+	client: Client,
+	api_key: String,
+}
+
+impl SendGridProvider {
+	pub fn from_env() -> Result<Self, EmailError> {
+		Ok(SendGridProvider {
+			client: http_client()?,
+			api_key: env::var("SENDGRID_API_KEY")?,
+		})
+	}
+}
+
+#[async_trait]
+impl EmailProvider for SendGridProvider {
+	fn name(&self) -> &'static str {
+		"sendgrid"
+	}
+
+	async fn send(
+		&self,
+		from: &str,
+		to: &str,
+		email: &RenderedEmail,
+	) -> Result<Option<String>, EmailError> {
+		let response = self
+			.client
+			.post("https://api.sendgrid.com/v3/mail/send")
+			.bearer_auth(&self.api_key)
+			.json(&json!({
+				"personalizations": [{ "to": [{ "email": to }] }],
+				"from": { "email": from },
+				"subject": email.subject,
+				"content": [
+					{ "type": "text/plain", "value": email.text },
+					{ "type": "text/html", "value": email.html },
+				],
+			}))
+			.send()
+			.await?;
+		let response = check_status(self.name(), response).await?;
+		// The body is empty; the id comes back as a header.
+		Ok(response
+			.headers()
+			.get("x-message-id")
+			.and_then(|v| v.to_str().ok())
+			.map(str::to_string))
+	}
+}
+
+/// Amazon SES through its v2 HTTP API, signed with AWS Signature Version 4.
+/// <https://docs.aws.amazon.com/ses/latest/APIReference-V2/API_SendEmail.html>
+pub struct SesProvider {
+	client: Client,
+	region: String,
+	access_key_id: String,
+	secret_access_key: String,
+	session_token: Option<String>,
+	/// Needed for SES to publish delivery events.
+	configuration_set: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SesResponse {
+	message_id: String,
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+	let mut mac =
+		Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+	mac.update(data.as_bytes());
+	mac.finalize().into_bytes().to_vec()
+}
+
+impl SesProvider {
+	pub fn from_env() -> Result<Self, EmailError> {
+		let optional = |name| env::var(name).ok().filter(|v: &String| !v.is_empty());
+		Ok(SesProvider {
+			client: http_client()?,
+			region: env::var("AWS_SES_REGION")?,
+			access_key_id: env::var("AWS_ACCESS_KEY_ID")?,
+			secret_access_key: env::var("AWS_SECRET_ACCESS_KEY")?,
+			session_token: optional("AWS_SESSION_TOKEN"),
+			configuration_set: optional("AWS_SES_CONFIGURATION_SET"),
+		})
+	}
+
+	/// The `Authorization` header for a JSON `POST` of `body` to `path`.
+	fn authorization(
+		&self,
+		host: &str,
+		path: &str,
+		amz_date: &str,
+		body: &str,
+	) -> String {
+		let date = &amz_date[..8];
+		let mut headers = vec![
+			("content-type", "application/json".to_string()),
+			("host", host.to_string()),
+			("x-amz-date", amz_date.to_string()),
+		];
+		if let Some(token) = &self.session_token {
+			headers.push(("x-amz-security-token", token.clone()));
+		}
+		let canonical_headers: String = headers
+			.iter()
+			.map(|(name, value)| format!("{}:{}\n", name, value))
+			.collect();
+		let signed_headers = headers
+			.iter()
+			.map(|(name, _)| *name)
+			.collect::<Vec<_>>()
+			.join(";");
+		let canonical_request = format!(
+			"POST\n{}\n\n{}\n{}\n{}",
+			path,
+			canonical_headers,
+			signed_headers,
+			hex::encode(Sha256::digest(body.as_bytes()))
+		);
+
+		let scope = format!("{}/{}/ses/aws4_request", date, self.region);
+		let string_to_sign = format!(
+			"AWS4-HMAC-SHA256\n{}\n{}\n{}",
+			amz_date,
+			scope,
+			hex::encode(Sha256::digest(canonical_request.as_bytes()))
+		);
+		let key = [date, self.region.as_str(), "ses", "aws4_request"]
+			.iter()
+			.fold(
+				format!("AWS4{}", self.secret_access_key).into_bytes(),
+				|key, part| hmac_sha256(&key, part),
+			);
+		format!(
+			"AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+			self.access_key_id,
+			scope,
+			signed_headers,
+			hex::encode(hmac_sha256(&key, &string_to_sign))
+		)
+	}
+}
+
+#[async_trait]
+impl EmailProvider for SesProvider {
+	fn name(&self) -> &'static str {
+		"ses"
+	}
+
+	async fn send(
+		&self,
+		from: &str,
+		to: &str,
+		email: &RenderedEmail,
+	) -> Result<Option<String>, EmailError> {
+		let host = format!("email.{}.amazonaws.com", self.region);
+		let path = "/v2/email/outbound-emails";
+		let mut body = json!({
+			"FromEmailAddress": from,
+			"Destination": { "ToAddresses": [to] },
+			"Content": {
+				"Simple": {
+					"Subject": { "Data": email.subject, "Charset": "UTF-8" },
+					"Body": {
+						"Text": { "Data": email.text, "Charset": "UTF-8" },
+						"Html": { "Data": email.html, "Charset": "UTF-8" },
+					},
+				},
+			},
+		});
+		if let Some(configuration_set) = &self.configuration_set {
+			body["ConfigurationSetName"] = json!(configuration_set);
+		}
+		let body = body.to_string();
+		let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+		let mut request = self
+			.client
+			.post(format!("https://{}{}", host, path))
+			.header("content-type", "application/json")
+			.header("x-amz-date", &amz_date)
+			.header(
+				"authorization",
+				self.authorization(&host, path, &amz_date, &body),
+			);
+		if let Some(token) = &self.session_token {
+			request = request.header("x-amz-security-token", token);
+		}
+		let response = request.body(body).send().await?;
+		let response: SesResponse =
+			check_status(self.name(), response).await?.json().await?;
+		Ok(Some(response.message_id))
+	}
+}
+
+/// The providers named in `EMAIL_PROVIDERS` (comma separated, default
+/// `smtp`), in order of preference.
+pub fn from_env() -> Result<Vec<Box<dyn EmailProvider>>, EmailError> {
+	let names = env::var("EMAIL_PROVIDERS")
+		.ok()
+		.filter(|v| !v.trim().is_empty())
+		.unwrap_or_else(|| "smtp".to_string());
+
+	let mut providers: Vec<Box<dyn EmailProvider>> = Vec::new();
+	for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+		let provider: Box<dyn EmailProvider> = match name {
+			"smtp" => Box::new(SmtpProvider::from_env()?),
+			"resend" => Box::new(ResendProvider::from_env()?),
+			"sendgrid" => Box::new(SendGridProvider::from_env()?),
+			"ses" => Box::new(SesProvider::from_env()?),
+			other => return Err(format!("Unknown email provider `{}`", other).into()),
+		};
+		if providers.iter().any(|p| p.name() == provider.name()) {
+			return Err(format!("Email provider `{}` is listed twice", name).into());
+		}
+		providers.push(provider);
+	}
+	Ok(providers)
+}
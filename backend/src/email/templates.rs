@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+/// Locale used when the client's `Accept-Language` matches nothing we have,
+/// and for any string a translation leaves out.
+pub const DEFAULT_LOCALE: &str = "en";
+
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+	(
+		"login_code.html",
+		include_str!("../../templates/email/login_code.html"),
+	),
+	(
+		"login_code.txt",
+		include_str!("../../templates/email/login_code.txt"),
+	),
+	(
+		"org_invite.html",
+		include_str!("../../templates/email/org_invite.html"),
+	),
+	(
+		"org_invite.txt",
+		include_str!("../../templates/email/org_invite.txt"),
+	),
+];
+
+const BUILTIN_LOCALES: &[(&str, &str)] = &[
+	("en", include_str!("../../templates/email/locales/en.json")),
+	("fr", include_str!("../../templates/email/locales/fr.json")),
+	("es", include_str!("../../templates/email/locales/es.json")),
+	("de", include_str!("../../templates/email/locales/de.json")),
+];
+
+#[derive(Error, Debug)]
+pub enum TemplateError {
+	#[error("Failed to read email template {0}: {1}")]
+	Io(String, std::io::Error),
+
+	#[error("Invalid locale file {0}: {1}")]
+	InvalidLocale(String, serde_json::Error),
+
+	#[error("Unknown email template {0}")]
+	UnknownTemplate(String),
+}
+
+pub struct RenderedEmail {
+	pub subject: String,
+	pub html: String,
+	pub text: String,
+}
+
+/// Email bodies and localized strings. Templates use `{{name}}` placeholders;
+/// a template `foo` is made of `foo.html`, `foo.txt` and the locale strings
+/// prefixed with `foo.` (its subject is `foo.subject`).
+///
+/// Self-hosters can point `EMAIL_TEMPLATES_DIR` at a directory laid out like
+/// `backend/templates/email`: any file found there replaces the built-in one,
+/// and extra `locales/<lang>.json` files add languages.
+pub struct EmailTemplates {
+	templates: HashMap<String, String>,
+	locales: HashMap<String, HashMap<String, String>>,
+}
+
+impl EmailTemplates {
+	pub fn load(override_dir: Option<&Path>) -> Result<Self, TemplateError> {
+		let mut templates: HashMap<String, String> = BUILTIN_TEMPLATES
+			.iter()
+			.map(|(name, body)| (name.to_string(), body.to_string()))
+			.collect();
+
+		let mut locales = HashMap::new();
+		for (lang, json) in BUILTIN_LOCALES {
+			let strings: HashMap<String, String> = serde_json::from_str(json)
+				.map_err(|e| TemplateError::InvalidLocale(lang.to_string(), e))?;
+			locales.insert(lang.to_string(), strings);
+		}
+
+		if let Some(dir) = override_dir {
+			for (name, body) in templates.iter_mut() {
+				let path = dir.join(name);
+				if path.is_file() {
+					*body = fs::read_to_string(&path)
+						.map_err(|e| TemplateError::Io(path.display().to_string(), e))?;
+				}
+			}
+
+			let locales_dir = dir.join("locales");
+			if locales_dir.is_dir() {
+				let entries = fs::read_dir(&locales_dir).map_err(|e| {
+					TemplateError::Io(locales_dir.display().to_string(), e)
+				})?;
+				for entry in entries.flatten() {
+					let path = entry.path();
+					if path.extension().and_then(|e| e.to_str()) != Some("json") {
+						continue;
+					}
+					let Some(lang) = path.file_stem().and_then(|s| s.to_str()) else {
+						continue;
+					};
+					let content = fs::read_to_string(&path)
+						.map_err(|e| TemplateError::Io(path.display().to_string(), e))?;
+					let strings: HashMap<String, String> = serde_json::from_str(&content)
+						.map_err(|e| {
+							TemplateError::InvalidLocale(path.display().to_string(), e)
+						})?;
+					locales
+						.entry(lang.to_lowercase())
+						.or_insert_with(HashMap::new)
+						.extend(strings);
+				}
+			}
+		}
+
+		Ok(EmailTemplates { templates, locales })
+	}
+
+	/// Picks the best available locale for an `Accept-Language` header value,
+	/// honouring q-values and falling back from `pt-BR` to `pt`.
+	pub fn negotiate_locale(&self, accept_language: Option<&str>) -> String {
+		let mut ranges: Vec<(String, f32)> = accept_language
+			.unwrap_or_default()
+			.split(',')
+			.filter_map(|part| {
+				let mut pieces = part.trim().split(';');
+				let tag = pieces.next()?.trim().to_lowercase();
+				if tag.is_empty() {
+					return None;
+				}
+				let quality = pieces
+					.find_map(|p| p.trim().strip_prefix("q="))
+					.and_then(|q| q.parse().ok())
+					.unwrap_or(1.0);
+				Some((tag, quality))
+			})
+			.collect();
+		ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+		for (tag, quality) in ranges {
+			if quality <= 0.0 || tag == "*" {
+				continue;
+			}
+			if self.locales.contains_key(&tag) {
+				return tag;
+			}
+			if let Some((primary, _)) = tag.split_once('-') {
+				if self.locales.contains_key(primary) {
+					return primary.to_string();
+				}
+			}
+		}
+
+		DEFAULT_LOCALE.to_string()
+	}
+
+	/// Renders template `name` in `locale` with the given placeholder values.
+	/// Values are HTML-escaped in the HTML part.
+	pub fn render(
+		&self,
+		name: &str,
+		locale: &str,
+		vars: &[(&str, &str)],
+	) -> Result<RenderedEmail, TemplateError> {
+		let html = self.template(name, "html")?;
+		let text = self.template(name, "txt")?;
+
+		let mut values: HashMap<String, String> = vars
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.to_string()))
+			.collect();
+		values.insert("lang".to_string(), locale.to_string());
+
+		// Shared strings have no prefix; template strings are prefixed with
+		// the template name. The default locale fills in missing translations.
+		let prefix = format!("{}.", name);
+		let chain = [self.locales.get(DEFAULT_LOCALE), self.locales.get(locale)];
+		for strings in chain.into_iter().flatten() {
+			for (key, value) in strings {
+				let placeholder = match key.strip_prefix(&prefix) {
+					Some(stripped) => stripped,
+					None if !key.contains('.') => key.as_str(),
+					None => continue,
+				};
+				let rendered = substitute(value, &values, false);
+				values.insert(placeholder.to_string(), rendered);
+			}
+		}
+
+		Ok(RenderedEmail {
+			subject: values.get("subject").cloned().unwrap_or_default(),
+			html: substitute(html, &values, true),
+			text: substitute(text, &values, false),
+		})
+	}
+
+	fn template(&self, name: &str, extension: &str) -> Result<&str, TemplateError> {
+		let file = format!("{}.{}", name, extension);
+		self.templates
+			.get(&file)
+			.map(String::as_str)
+			.ok_or(TemplateError::UnknownTemplate(file))
+	}
+}
+
+/// Replaces `{{name}}` placeholders. Unknown placeholders render empty.
+fn substitute(template: &str, values: &HashMap<String, String>, escape: bool) -> String {
+	let mut out = String::with_capacity(template.len());
+	let mut rest = template;
+
+	while let Some(start) = rest.find("{{") {
+		out.push_str(&rest[..start]);
+		let after = &rest[start + 2..];
+		let Some(end) = after.find("}}") else {
+			out.push_str(&rest[start..]);
+			return out;
+		};
+
+		let key = after[..end].trim();
+		if let Some(value) = values.get(key) {
+			if escape {
+				out.push_str(&escape_html(value));
+			} else {
+				out.push_str(value);
+			}
+		}
+		rest = &after[end + 2..];
+	}
+
+	out.push_str(rest);
+	out
+}
+
+fn escape_html(value: &str) -> String {
+	let mut out = String::with_capacity(value.len());
+	for c in value.chars() {
+		match c {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			'\'' => out.push_str("&#39;"),
+			c => out.push(c),
+		}
+	}
+	out
+}
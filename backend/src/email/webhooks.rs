@@ -0,0 +1,296 @@
+//! Delivery status webhooks. Each provider posts events about the emails it
+//! sent to `POST /api/email/webhooks/{provider}?token=...`; they update the
+//! matching `email_deliveries` row and are kept in `email_delivery_events`.
+
+use crate::{database::DbPool, errors::internal_error, llm::api::ApiError};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use chrono::{DateTime, TimeZone, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use uuid::Uuid;
+
+/// Orders statuses from least to most final. An event never moves a
+/// delivery back, since providers don't guarantee the order events arrive in.
+fn status_rank(status: &str) -> usize {
+	match status {
+		"sent" => 0,
+		"delayed" => 1,
+		"delivered" => 2,
+		_ => 3,
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookQuery {
+	pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookResponse {
+	/// Events matched to an email this server sent.
+	pub recorded: usize,
+	/// Events for unknown emails, or of kinds that aren't tracked, like opens.
+	pub ignored: usize,
+}
+
+/// One status change reported by a provider.
+#[derive(Debug)]
+struct DeliveryEvent {
+	message_id: String,
+	status: &'static str,
+	/// The provider's own name for the event.
+	detail: String,
+	occurred_at: DateTime<Utc>,
+}
+
+fn status_of(provider: &str, event: &str) -> Option<&'static str> {
+	Some(match (provider, event) {
+		("resend", "email.sent") | ("sendgrid", "processed") | ("ses", "Send") => "sent",
+		("resend", "email.delivery_delayed")
+		| ("sendgrid", "deferred")
+		| ("ses", "DeliveryDelay") => "delayed",
+		("resend", "email.delivered")
+		| ("sendgrid", "delivered")
+		| ("ses", "Delivery") => "delivered",
+		("resend", "email.bounced") | ("sendgrid", "bounce") | ("ses", "Bounce") => {
+			"bounced"
+		}
+		("resend", "email.complained")
+		| ("sendgrid", "spamreport")
+		| ("ses", "Complaint") => "complained",
+		("resend", "email.failed")
+		| ("sendgrid", "dropped")
+		| ("ses", "Reject")
+		| ("ses", "Rendering Failure") => "failed",
+		_ => return None,
+	})
+}
+
+fn timestamp(value: Option<&Value>) -> DateTime<Utc> {
+	value
+		.and_then(|v| match v {
+			Value::String(s) => DateTime::parse_from_rfc3339(s)
+				.ok()
+				.map(|t| t.with_timezone(&Utc)),
+			Value::Number(n) => n
+				.as_i64()
+				.and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+			_ => None,
+		})
+		.unwrap_or_else(Utc::now)
+}
+
+/// Resend posts one event per request.
+/// <https://resend.com/docs/dashboard/webhooks/event-types>
+fn parse_resend(body: &Value) -> Vec<(String, String, DateTime<Utc>)> {
+	let event = body["type"].as_str();
+	let message_id = body["data"]["email_id"].as_str();
+	match (event, message_id) {
+		(Some(event), Some(message_id)) => vec![(
+			message_id.to_string(),
+			event.to_string(),
+			timestamp(body.get("created_at")),
+		)],
+		_ => Vec::new(),
+	}
+}
+
+/// SendGrid posts a batch of events. Their `sg_message_id` starts with the
+/// `X-Message-Id` the send returned, followed by `.filter...`.
+/// <https://www.twilio.com/docs/sendgrid/for-developers/tracking-events/event>
+fn parse_sendgrid(body: &Value) -> Vec<(String, String, DateTime<Utc>)> {
+	body.as_array()
+		.into_iter()
+		.flatten()
+		.filter_map(|event| {
+			let sg_message_id = event["sg_message_id"].as_str()?;
+			let message_id = sg_message_id
+				.split_once(".filter")
+				.map_or(sg_message_id, |(id, _)| id);
+			Some((
+				message_id.to_string(),
+				event["event"].as_str()?.to_string(),
+				timestamp(event.get("timestamp")),
+			))
+		})
+		.collect()
+}
+
+/// SES publishes events to an SNS topic, which posts them wrapped in a
+/// notification whose `Message` is the event as a JSON string.
+/// <https://docs.aws.amazon.com/ses/latest/dg/event-publishing-retrieving-sns-contents.html>
+fn parse_ses(body: &Value) -> Vec<(String, String, DateTime<Utc>)> {
+	let Some(message) = body["Message"]
+		.as_str()
+		.and_then(|m| serde_json::from_str::<Value>(m).ok())
+	else {
+		return Vec::new();
+	};
+	// Event publishing uses `eventType`, the older notifications
+	// `notificationType`.
+	let event = message["eventType"]
+		.as_str()
+		.or_else(|| message["notificationType"].as_str());
+	let message_id = message["mail"]["messageId"].as_str();
+	match (event, message_id) {
+		(Some(event), Some(message_id)) => vec![(
+			message_id.to_string(),
+			event.to_string(),
+			timestamp(body.get("Timestamp")),
+		)],
+		_ => Vec::new(),
+	}
+}
+
+/// Confirms the SNS subscription of the webhook by visiting the URL AWS
+/// sends, which must be an AWS HTTPS endpoint.
+async fn confirm_sns_subscription(body: &Value) -> Result<(), String> {
+	let url = body["SubscribeURL"]
+		.as_str()
+		.and_then(|url| reqwest::Url::parse(url).ok())
+		.filter(|url| {
+			url.scheme() == "https"
+				&& url
+					.host_str()
+					.is_some_and(|host| host.ends_with(".amazonaws.com"))
+		})
+		.ok_or("SubscribeURL must be an https amazonaws.com URL")?;
+	reqwest::get(url)
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(|e| e.to_string())?;
+	Ok(())
+}
+
+/// Applies the events in one transaction, returning how many matched a
+/// delivery.
+async fn record_events(
+	pool: &DbPool,
+	provider: &str,
+	events: &[DeliveryEvent],
+) -> Result<usize, sqlx::Error> {
+	let received_at = Utc::now().to_rfc3339();
+	let mut recorded = 0;
+	let mut tx = pool.begin().await?;
+	for event in events {
+		let delivery: Option<(String, String)> = sqlx::query_as(
+			"SELECT delivery_id, status FROM email_deliveries
+			 WHERE provider = $1 AND provider_message_id = $2",
+		)
+		.bind(provider)
+		.bind(&event.message_id)
+		.fetch_optional(&mut *tx)
+		.await?;
+		let Some((delivery_id, status)) = delivery else {
+			continue;
+		};
+
+		sqlx::query(
+			"INSERT INTO email_delivery_events
+			 (event_id, delivery_id, status, detail, occurred_at, received_at)
+			 VALUES ($1, $2, $3, $4, $5, $6)",
+		)
+		.bind(Uuid::new_v4().to_string())
+		.bind(&delivery_id)
+		.bind(event.status)
+		.bind(&event.detail)
+		.bind(event.occurred_at.to_rfc3339())
+		.bind(&received_at)
+		.execute(&mut *tx)
+		.await?;
+
+		if status_rank(event.status) >= status_rank(&status) {
+			sqlx::query(
+				"UPDATE email_deliveries SET status = $1, updated_at = $2
+				 WHERE delivery_id = $3",
+			)
+			.bind(event.status)
+			.bind(&received_at)
+			.bind(&delivery_id)
+			.execute(&mut *tx)
+			.await?;
+		}
+		recorded += 1;
+	}
+	tx.commit().await?;
+	Ok(recorded)
+}
+
+/// Receives delivery events from an email provider. Providers can't send a
+/// bearer token, so the shared `EMAIL_WEBHOOK_TOKEN` goes in the query
+/// string; webhooks are disabled while it is unset.
+///
+/// `POST /api/email/webhooks/{provider}`
+pub async fn delivery_webhook(
+	pool: web::Data<DbPool>,
+	provider: web::Path<String>,
+	query: web::Query<WebhookQuery>,
+	body: web::Bytes,
+) -> ActixResult<HttpResponse> {
+	let token = env::var("EMAIL_WEBHOOK_TOKEN")
+		.ok()
+		.filter(|t| !t.is_empty());
+	match (token, query.token.as_deref()) {
+		(Some(token), Some(provided)) if token == provided => {}
+		_ => {
+			return Ok(HttpResponse::Unauthorized().json(ApiError {
+				error: "Email webhooks require the webhook token".to_string(),
+				code: "UNAUTHORIZED".to_string(),
+			}))
+		}
+	}
+
+	let bad_request = |error: String| {
+		HttpResponse::BadRequest().json(ApiError {
+			error,
+			code: "INVALID_WEBHOOK".to_string(),
+		})
+	};
+	// SNS posts JSON as text/plain, so the body is parsed by hand.
+	let body: Value = match serde_json::from_slice(&body) {
+		Ok(body) => body,
+		Err(e) => return Ok(bad_request(format!("Invalid JSON: {}", e))),
+	};
+
+	let parsed = match provider.as_str() {
+		"resend" => parse_resend(&body),
+		"sendgrid" => parse_sendgrid(&body),
+		"ses" if body["Type"] == "SubscriptionConfirmation" => {
+			return Ok(match confirm_sns_subscription(&body).await {
+				Ok(()) => {
+					info!("Confirmed SES notification subscription");
+					HttpResponse::Ok().finish()
+				}
+				Err(e) => bad_request(e),
+			});
+		}
+		"ses" => parse_ses(&body),
+		other => return Ok(bad_request(format!("Unknown email provider `{}`", other))),
+	};
+
+	let total = parsed.len();
+	let events: Vec<DeliveryEvent> = parsed
+		.into_iter()
+		.filter_map(|(message_id, detail, occurred_at)| {
+			Some(DeliveryEvent {
+				status: status_of(&provider, &detail)?,
+				message_id,
+				detail,
+				occurred_at,
+			})
+		})
+		.collect();
+
+	match record_events(pool.get_ref(), &provider, &events).await {
+		Ok(recorded) => Ok(HttpResponse::Ok().json(WebhookResponse {
+			recorded,
+			ignored: total - recorded,
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
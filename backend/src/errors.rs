@@ -0,0 +1,13 @@
+//! Error responses shared by the API handlers.
+
+use crate::llm::api::ApiError;
+use actix_web::HttpResponse;
+
+/// A failure the caller can't do anything about. Its cause is logged where
+/// it happens rather than sent back.
+pub fn internal_error() -> HttpResponse {
+	HttpResponse::InternalServerError().json(ApiError {
+		error: "Internal server error".to_string(),
+		code: "INTERNAL_ERROR".to_string(),
+	})
+}
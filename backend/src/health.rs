@@ -0,0 +1,101 @@
+use crate::{database::DbPool, email::EmailService};
+use actix_web::{get, web, HttpResponse};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// How long a single dependency check may take before it counts as failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+	pub status: &'static str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+impl CheckResult {
+	fn from_result<E: std::fmt::Display>(result: Result<(), E>) -> Self {
+		match result {
+			Ok(()) => CheckResult {
+				status: "ok",
+				error: None,
+			},
+			Err(e) => CheckResult {
+				status: "error",
+				error: Some(e.to_string()),
+			},
+		}
+	}
+
+	fn is_ok(&self) -> bool {
+		self.error.is_none()
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+	pub status: &'static str,
+	pub checks: BTreeMap<&'static str, CheckResult>,
+}
+
+async fn check_database(pool: &DbPool) -> CheckResult {
+	let result = match timeout(CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(pool)).await
+	{
+		Ok(result) => result.map(|_| ()).map_err(|e| e.to_string()),
+		Err(_) => Err("timed out".to_string()),
+	};
+	CheckResult::from_result(result)
+}
+
+async fn check_email(email_service: &EmailService) -> CheckResult {
+	let result = match timeout(CHECK_TIMEOUT, email_service.test_connection()).await {
+		Ok(Ok(true)) => Ok(()),
+		Ok(Ok(false)) => Err("No email provider accepted the connection".to_string()),
+		Ok(Err(e)) => Err(e.to_string()),
+		Err(_) => Err("timed out".to_string()),
+	};
+	CheckResult::from_result(result)
+}
+
+fn respond(checks: BTreeMap<&'static str, CheckResult>) -> HttpResponse {
+	if checks.values().all(CheckResult::is_ok) {
+		HttpResponse::Ok().json(HealthResponse {
+			status: "ok",
+			checks,
+		})
+	} else {
+		HttpResponse::ServiceUnavailable().json(HealthResponse {
+			status: "unavailable",
+			checks,
+		})
+	}
+}
+
+/// Full dependency check: database connectivity and email provider
+/// reachability.
+#[get("/healthz")]
+pub async fn healthz(
+	pool: web::Data<DbPool>,
+	email_service: web::Data<EmailService>,
+) -> HttpResponse {
+	let (database, email) = tokio::join!(
+		check_database(pool.get_ref()),
+		check_email(email_service.get_ref())
+	);
+
+	let mut checks = BTreeMap::new();
+	checks.insert("database", database);
+	checks.insert("email", email);
+	respond(checks)
+}
+
+/// Cheap readiness probe for load balancers: the server is up, migrations
+/// have run and the database answers.
+#[get("/readyz")]
+pub async fn readyz(pool: web::Data<DbPool>) -> HttpResponse {
+	let mut checks = BTreeMap::new();
+	checks.insert("database", check_database(pool.get_ref()).await);
+	respond(checks)
+}
@@ -1,9 +1,31 @@
-use crate::llm::{clients::*, providers::LLMProvider, types::*};
+use crate::{
+	audit::{self, AuditEvent, ClientInfo},
+	auth::AuthenticatedAccount,
+	database::DbPool,
+	errors::internal_error,
+	llm::{
+		clients::*,
+		diff_summary,
+		embeddings::{self, OpenAIEmbeddingsClient},
+		openai_compat::{
+			self, ChatCompletionRequest, ChunkDelta, Completion, CompletionUsage,
+		},
+		providers::LLMProvider,
+		replay::{self, MockClient, RecordingClient},
+		types::*,
+	},
+	metrics::{ActiveStreamGuard, Metrics},
+	orgs::{require_role, OrgRole},
+	request_log, usage,
+	vault::{KeyOwner, KeyVault, VaultError},
+};
 use actix_web::{
+	http::StatusCode,
 	web::{self, Bytes},
-	HttpResponse, Result as ActixResult,
+	HttpRequest, HttpResponse, Result as ActixResult,
 };
 use futures::{stream::Stream, StreamExt};
+use log::error;
 use serde::{Deserialize, Serialize};
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -15,7 +37,14 @@ pub struct InferenceRequest {
 	pub provider: String,
 	pub model: String,
 	pub messages: Vec<ApiMessage>,
-	pub api_key: String,
+	/// Raw provider key, used when `key_id` is not set.
+	pub api_key: Option<String>,
+	/// Id of a key stored in the vault via `POST /api/keys`, or via
+	/// `POST /api/orgs/{org_id}/keys` when `org_id` is set.
+	pub key_id: Option<String>,
+	/// Organization the request is made on behalf of. Usage is billed to the
+	/// org and `key_id` refers to one of its shared keys.
+	pub org_id: Option<String>,
 	#[serde(default = "default_temperature")]
 	pub temperature: f32,
 	pub max_tokens: Option<usize>,
@@ -23,6 +52,118 @@ pub struct InferenceRequest {
 	pub stream: bool,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DiffSummaryRequest {
+	pub provider: String,
+	pub model: String,
+	/// Unified diff of the branch, as printed by `git diff`.
+	pub diff: String,
+	/// Name of the branch, given to the model as context.
+	pub branch: Option<String>,
+	pub api_key: Option<String>,
+	pub key_id: Option<String>,
+	pub org_id: Option<String>,
+	#[serde(default = "default_summary_temperature")]
+	pub temperature: f32,
+	/// Output cap for the final summary.
+	pub max_tokens: Option<usize>,
+}
+
+impl DiffSummaryRequest {
+	/// The provider and key fields, for the checks shared with inference.
+	fn as_inference_request(&self) -> InferenceRequest {
+		InferenceRequest {
+			provider: self.provider.clone(),
+			model: self.model.clone(),
+			messages: Vec::new(),
+			api_key: self.api_key.clone(),
+			key_id: self.key_id.clone(),
+			org_id: self.org_id.clone(),
+			temperature: self.temperature,
+			max_tokens: self.max_tokens,
+			stream: true,
+		}
+	}
+}
+
+/// Most provider/model pairs one fan-out request may ask.
+const MAX_FANOUT_TARGETS: usize = 6;
+
+#[derive(Debug, Deserialize)]
+pub struct FanoutTarget {
+	pub provider: String,
+	pub model: String,
+	pub api_key: Option<String>,
+	pub key_id: Option<String>,
+	/// Shown with this target's chunks; `provider/model` by default.
+	pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FanoutRequest {
+	/// Pairs to ask, at most `MAX_FANOUT_TARGETS`. Chunks refer to them by index.
+	pub targets: Vec<FanoutTarget>,
+	pub messages: Vec<ApiMessage>,
+	pub org_id: Option<String>,
+	#[serde(default = "default_temperature")]
+	pub temperature: f32,
+	pub max_tokens: Option<usize>,
+}
+
+impl FanoutRequest {
+	/// One target's provider and key fields, for the checks shared with
+	/// inference.
+	fn target_request(&self, target: &FanoutTarget) -> InferenceRequest {
+		InferenceRequest {
+			provider: target.provider.clone(),
+			model: target.model.clone(),
+			messages: Vec::new(),
+			api_key: target.api_key.clone(),
+			key_id: target.key_id.clone(),
+			org_id: self.org_id.clone(),
+			temperature: self.temperature,
+			max_tokens: self.max_tokens,
+			stream: true,
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingsRequest {
+	pub provider: String,
+	pub model: String,
+	/// Texts to embed, at most `embeddings::MAX_INPUTS`.
+	pub input: Vec<String>,
+	pub api_key: Option<String>,
+	pub key_id: Option<String>,
+	pub org_id: Option<String>,
+}
+
+impl EmbeddingsRequest {
+	/// The provider and key fields, for the checks shared with inference.
+	fn as_inference_request(&self) -> InferenceRequest {
+		InferenceRequest {
+			provider: self.provider.clone(),
+			model: self.model.clone(),
+			messages: Vec::new(),
+			api_key: self.api_key.clone(),
+			key_id: self.key_id.clone(),
+			org_id: self.org_id.clone(),
+			temperature: default_temperature(),
+			max_tokens: None,
+			stream: false,
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingsResponse {
+	/// One vector per input, in input order.
+	pub embeddings: Vec<Vec<f32>>,
+	pub model: String,
+	pub usage: Option<UsageInfo>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ApiMessage {
 	pub role: String, // "system", "user", "assistant"
@@ -50,6 +191,24 @@ pub struct StreamChunk {
 	pub done: bool,
 }
 
+/// One event of a fan-out stream. Chunks of a target carry its index; the
+/// last event of the stream has no `target` and `done` set.
+#[derive(Debug, Serialize)]
+pub struct FanoutChunk {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub target: Option<usize>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub label: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub model: Option<String>,
+	pub delta: String,
+	pub done: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub usage: Option<UsageInfo>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<ApiError>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ProvidersResponse {
 	pub providers: Vec<ProviderInfo>,
@@ -79,6 +238,10 @@ fn default_temperature() -> f32 {
 	0.7
 }
 
+fn default_summary_temperature() -> f32 {
+	0.2
+}
+
 impl From<ApiMessage> for LLMClientMessage {
 	fn from(msg: ApiMessage) -> Self {
 		match msg.role.as_str() {
@@ -90,14 +253,23 @@ impl From<ApiMessage> for LLMClientMessage {
 	}
 }
 
+/// Parses a provider name. The `mock` provider is refused unless enabled.
 fn parse_provider(provider: &str) -> Result<LLMProvider, ApiError> {
-	LLMProvider::from_str(provider).ok_or_else(|| ApiError {
-		error: "Invalid provider".to_string(),
-		code: "INVALID_PROVIDER".to_string(),
-	})
+	LLMProvider::from_str(provider)
+		.filter(|p| *p != LLMProvider::Mock || replay::enabled())
+		.ok_or_else(|| ApiError {
+			error: "Invalid provider".to_string(),
+			code: "INVALID_PROVIDER".to_string(),
+		})
 }
 
-fn parse_model(model: &str) -> Result<LLMType, ApiError> {
+/// Parses a model name. The `mock` provider takes any model, to replay what
+/// was recorded with it.
+fn parse_model(provider: &LLMProvider, model: &str) -> Result<LLMType, ApiError> {
+	if *provider == LLMProvider::Mock {
+		return Ok(LLMType::Custom(model.to_string()));
+	}
+
 	let llm_type = match model {
 		// Anthropic models
 		"claude-3-opus-20240229" => LLMType::ClaudeOpus,
@@ -136,30 +308,368 @@ fn parse_model(model: &str) -> Result<LLMType, ApiError> {
 	Ok(llm_type)
 }
 
+/// Checks that the caller belongs to the org a request is made for, if any.
+async fn check_org_context(
+	request: &InferenceRequest,
+	account: Option<&AuthenticatedAccount>,
+	pool: &DbPool,
+) -> Result<(), HttpResponse> {
+	let Some(org_id) = &request.org_id else {
+		return Ok(());
+	};
+
+	let Some(account) = account else {
+		return Err(HttpResponse::Unauthorized().json(ApiError {
+			error: "Organization requests require an authenticated account".to_string(),
+			code: "UNAUTHENTICATED".to_string(),
+		}));
+	};
+
+	require_role(pool, org_id, &account.account_id, OrgRole::Member)
+		.await
+		.map(|_| ())
+}
+
+/// Picks the provider key for a request: a vault key when `key_id` is set
+/// (which requires an authenticated account), otherwise the raw `api_key`.
+/// The `mock` provider needs none.
+async fn resolve_api_key(
+	request: &InferenceRequest,
+	provider: &LLMProvider,
+	account: Option<&AuthenticatedAccount>,
+	pool: &DbPool,
+	vault: &KeyVault,
+) -> Result<String, HttpResponse> {
+	if *provider == LLMProvider::Mock {
+		return Ok(String::new());
+	}
+
+	let Some(key_id) = &request.key_id else {
+		return request.api_key.clone().ok_or_else(|| {
+			HttpResponse::BadRequest().json(ApiError {
+				error: "Either api_key or key_id must be provided".to_string(),
+				code: "MISSING_API_KEY".to_string(),
+			})
+		});
+	};
+
+	let Some(account) = account else {
+		return Err(HttpResponse::Unauthorized().json(ApiError {
+			error: "Stored keys require an authenticated account".to_string(),
+			code: "UNAUTHENTICATED".to_string(),
+		}));
+	};
+
+	let owner = match &request.org_id {
+		Some(org_id) => KeyOwner::Org(org_id),
+		None => KeyOwner::Account(&account.account_id),
+	};
+
+	vault
+		.resolve_key(pool, owner, key_id, provider)
+		.await
+		.map_err(|e| match e {
+			VaultError::NotFound => HttpResponse::NotFound().json(ApiError {
+				error: "Key not found".to_string(),
+				code: "KEY_NOT_FOUND".to_string(),
+			}),
+			VaultError::ProviderMismatch(stored) => {
+				HttpResponse::BadRequest().json(ApiError {
+					error: format!("Key was stored for provider {}", stored),
+					code: "KEY_PROVIDER_MISMATCH".to_string(),
+				})
+			}
+			e => {
+				error!("Failed to resolve stored key: {}", e);
+				internal_error()
+			}
+		})
+}
+
+/// Picks the key of a request that names none: the one most recently stored
+/// for the provider by the caller, or by the org when it is billed to one.
+async fn resolve_latest_key(
+	request: &InferenceRequest,
+	provider: &LLMProvider,
+	account: &AuthenticatedAccount,
+	pool: &DbPool,
+	vault: &KeyVault,
+) -> Result<String, CallRejected> {
+	let owner = match &request.org_id {
+		Some(org_id) => KeyOwner::Org(org_id),
+		None => KeyOwner::Account(&account.account_id),
+	};
+
+	vault
+		.resolve_latest_key(pool, owner, provider)
+		.await
+		.map_err(|e| match e {
+			VaultError::NotFound => CallRejected::Invalid(ApiError {
+				error: format!(
+					"No {} key is stored; add one with POST /api/keys",
+					provider
+				),
+				code: "MISSING_API_KEY".to_string(),
+			}),
+			e => {
+				error!("Failed to resolve stored key: {}", e);
+				CallRejected::Refused(internal_error())
+			}
+		})
+}
+
+/// What a handler calls a provider for, which decides how the model and key
+/// are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CallKind {
+	Completion,
+	/// Only OpenAI serves embeddings, with models of its own.
+	Embeddings,
+	/// Completions for OpenAI-compatible clients, which can't name a key.
+	/// Without one, the most recently stored key for the provider is used.
+	OpenAiCompatible,
+}
+
+/// Who a call is billed to: the org when the request names one, otherwise the
+/// authenticated account. Anonymous calls are not metered.
+#[derive(Debug, Clone)]
+struct Billing {
+	account: Option<AuthenticatedAccount>,
+	org_id: Option<String>,
+}
+
+/// A provider call that passed every check.
+struct PreparedCall {
+	provider: LLMProvider,
+	model: LLMType,
+	api_key: String,
+	billing: Billing,
+}
+
+/// Why a call was refused before reaching the provider.
+enum CallRejected {
+	/// Unknown provider or model, or a missing key; reported as `400`.
+	Invalid(ApiError),
+	/// Refused by the org, key or spend limit checks, with their response.
+	Refused(HttpResponse),
+}
+
+impl CallRejected {
+	fn into_response(self) -> HttpResponse {
+		match self {
+			CallRejected::Invalid(e) => HttpResponse::BadRequest().json(e),
+			CallRejected::Refused(response) => response,
+		}
+	}
+}
+
+/// Checks a request before its provider is called: parses the provider and
+/// model, checks the caller belongs to the org it is billed to, resolves the
+/// provider key and enforces the caller's spend limit.
+async fn prepare_llm_call(
+	request: &InferenceRequest,
+	kind: CallKind,
+	account: Option<&AuthenticatedAccount>,
+	pool: &DbPool,
+	vault: &KeyVault,
+) -> Result<PreparedCall, CallRejected> {
+	let provider = parse_provider(&request.provider).map_err(CallRejected::Invalid)?;
+	let model = match kind {
+		CallKind::Embeddings if provider != LLMProvider::OpenAI => {
+			return Err(CallRejected::Invalid(ApiError {
+				error: format!("{} doesn't support embeddings", provider),
+				code: "EMBEDDINGS_UNSUPPORTED".to_string(),
+			}))
+		}
+		CallKind::Embeddings => LLMType::Custom(request.model.clone()),
+		CallKind::Completion | CallKind::OpenAiCompatible => {
+			parse_model(&provider, &request.model).map_err(CallRejected::Invalid)?
+		}
+	};
+
+	check_org_context(request, account, pool)
+		.await
+		.map_err(CallRejected::Refused)?;
+
+	let names_key = request.api_key.is_some() || request.key_id.is_some();
+	let api_key = match account {
+		Some(account)
+			if kind == CallKind::OpenAiCompatible
+				&& !names_key
+				&& provider != LLMProvider::Mock =>
+		{
+			resolve_latest_key(request, &provider, account, pool, vault).await?
+		}
+		_ => resolve_api_key(request, &provider, account, pool, vault)
+			.await
+			.map_err(CallRejected::Refused)?,
+	};
+
+	// Personal spend caps don't apply to usage billed to an org
+	if let (Some(account), None) = (account, &request.org_id) {
+		usage::enforce_spend_limit(pool, &account.account_id)
+			.await
+			.map_err(CallRejected::Refused)?;
+	}
+
+	Ok(PreparedCall {
+		provider,
+		model,
+		api_key,
+		billing: Billing {
+			account: account.cloned(),
+			org_id: request.org_id.clone(),
+		},
+	})
+}
+
+fn prompt_text(messages: &[ApiMessage]) -> String {
+	messages
+		.iter()
+		.map(|m| m.content.as_str())
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+/// Records metering and an audit event for authenticated requests; anonymous
+/// requests are not attributed to any account.
+async fn record_usage(
+	pool: &DbPool,
+	billing: &Billing,
+	client_info: &ClientInfo,
+	provider: &LLMProvider,
+	model: &str,
+	response: &LLMClientCompletionResponse,
+	prompt_text: &str,
+) {
+	let Some(account) = &billing.account else {
+		return;
+	};
+	let org_id = billing.org_id.as_deref();
+
+	let provider_name = provider.to_string();
+	let statistics = response.usage_statistics();
+	let (input_tokens, output_tokens) = match usage::record_usage(
+		pool,
+		&account.account_id,
+		org_id,
+		&provider_name,
+		model,
+		statistics,
+		prompt_text,
+		response.answer_up_until_now(),
+	)
+	.await
+	{
+		Ok(tokens) => tokens,
+		Err(e) => {
+			error!("Failed to record usage: {}", e);
+			(
+				statistics.input_tokens().unwrap_or_default(),
+				statistics.output_tokens().unwrap_or_default(),
+			)
+		}
+	};
+
+	audit::record(
+		pool,
+		&account.account_id,
+		client_info,
+		AuditEvent::Inference {
+			provider: &provider_name,
+			model,
+			input_tokens,
+			output_tokens,
+			org_id,
+		},
+	)
+	.await;
+}
+
+fn message_text(messages: &[LLMClientMessage]) -> String {
+	messages
+		.iter()
+		.map(|m| m.content())
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+fn client_error(error: &LLMClientError) -> ApiError {
+	let (error, code) = match error {
+		LLMClientError::UnauthorizedAccess => ("Invalid API key", "UNAUTHORIZED"),
+		LLMClientError::RateLimitExceeded => ("Rate limit exceeded", "RATE_LIMITED"),
+		LLMClientError::UnSupportedModel => ("Model not supported", "UNSUPPORTED_MODEL"),
+		_ => ("Internal server error", "INTERNAL_ERROR"),
+	};
+	ApiError {
+		error: error.to_string(),
+		code: code.to_string(),
+	}
+}
+
+fn client_error_status(error: &LLMClientError) -> StatusCode {
+	match error {
+		LLMClientError::UnauthorizedAccess => StatusCode::UNAUTHORIZED,
+		LLMClientError::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
+		LLMClientError::UnSupportedModel => StatusCode::BAD_REQUEST,
+		_ => StatusCode::INTERNAL_SERVER_ERROR,
+	}
+}
+
+fn client_error_response(error: LLMClientError) -> HttpResponse {
+	HttpResponse::build(client_error_status(&error)).json(client_error(&error))
+}
+
+/// Gets the provider's client, recording its responses when `LLM_RECORD` is
+/// set.
 fn get_client(provider: &LLMProvider) -> Box<dyn LLMClient> {
-	match provider {
+	let client: Box<dyn LLMClient> = match provider {
 		LLMProvider::Anthropic => Box::new(AnthropicClient::new()),
 		LLMProvider::OpenAI => Box::new(OpenAIClient::new()),
 		LLMProvider::Google => Box::new(GoogleClient::new()),
 		LLMProvider::Groq => Box::new(GroqClient::new()),
 		LLMProvider::OpenRouter => Box::new(OpenRouterClient::new()),
+		LLMProvider::Mock => return Box::new(MockClient::new()),
+	};
+
+	if replay::recording() {
+		Box::new(RecordingClient::new(provider.clone(), client))
+	} else {
+		client
 	}
 }
 
-pub async fn inference(body: web::Json<InferenceRequest>) -> ActixResult<HttpResponse> {
+pub async fn inference(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	metrics: web::Data<Metrics>,
+	account: Option<AuthenticatedAccount>,
+	client_info: ClientInfo,
+	http_request: HttpRequest,
+	body: web::Json<InferenceRequest>,
+) -> ActixResult<HttpResponse> {
 	let request = body.into_inner();
+	request_log::set_llm(&http_request, &request.provider, &request.model);
 
-	// Validate provider
-	let provider = match parse_provider(&request.provider) {
-		Ok(p) => p,
-		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	let PreparedCall {
+		provider,
+		model,
+		api_key,
+		billing,
+	} = match prepare_llm_call(
+		&request,
+		CallKind::Completion,
+		account.as_ref(),
+		pool.get_ref(),
+		vault.get_ref(),
+	)
+	.await
+	{
+		Ok(call) => call,
+		Err(rejected) => return Ok(rejected.into_response()),
 	};
 
-	// Validate and parse model
-	let model = match parse_model(&request.model) {
-		Ok(m) => m,
-		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
-	};
+	let prompt_text = prompt_text(&request.messages);
 
 	// Convert messages
 	let messages: Vec<_> = request.messages.into_iter().map(|m| m.into()).collect();
@@ -175,35 +685,45 @@ pub async fn inference(body: web::Json<InferenceRequest>) -> ActixResult<HttpRes
 	// Get the appropriate client and make the request
 	let client = get_client(&provider);
 
-	match client.completion(request.api_key, completion_request).await {
-		Ok(content) => Ok(HttpResponse::Ok().json(InferenceResponse {
-			content,
-			model: request.model,
-			usage: None, // Could be enhanced to return actual usage
-		})),
-		Err(e) => match e {
-			LLMClientError::UnauthorizedAccess => {
-				Ok(HttpResponse::Unauthorized().json(ApiError {
-					error: "Invalid API key".to_string(),
-					code: "UNAUTHORIZED".to_string(),
-				}))
-			}
-			LLMClientError::RateLimitExceeded => Ok(HttpResponse::TooManyRequests()
-				.json(ApiError {
-					error: "Rate limit exceeded".to_string(),
-					code: "RATE_LIMITED".to_string(),
-				})),
-			LLMClientError::UnSupportedModel => {
-				Ok(HttpResponse::BadRequest().json(ApiError {
-					error: "Model not supported".to_string(),
-					code: "UNSUPPORTED_MODEL".to_string(),
-				}))
-			}
-			_ => Ok(HttpResponse::InternalServerError().json(ApiError {
-				error: "Internal server error".to_string(),
-				code: "INTERNAL_ERROR".to_string(),
-			})),
-		},
+	let result = client.completion(api_key, completion_request).await;
+	metrics.record_llm_request(&provider.to_string(), llm_result_label(&result));
+
+	match result {
+		Ok(response) => {
+			record_usage(
+				pool.get_ref(),
+				&billing,
+				&client_info,
+				&provider,
+				&request.model,
+				&response,
+				&prompt_text,
+			)
+			.await;
+
+			let statistics = response.usage_statistics();
+			Ok(HttpResponse::Ok().json(InferenceResponse {
+				content: response.answer_up_until_now().to_string(),
+				model: request.model,
+				usage: Some(UsageInfo {
+					input_tokens: statistics.input_tokens(),
+					output_tokens: statistics.output_tokens(),
+					cached_input_tokens: statistics.cached_input_tokens(),
+				}),
+			}))
+		}
+		Err(e) => Ok(client_error_response(e)),
+	}
+}
+
+/// Label for the `llm_requests_total` metric.
+fn llm_result_label<T>(result: &Result<T, LLMClientError>) -> &'static str {
+	match result {
+		Ok(_) => "ok",
+		Err(LLMClientError::UnauthorizedAccess) => "unauthorized",
+		Err(LLMClientError::RateLimitExceeded) => "rate_limited",
+		Err(LLMClientError::UnSupportedModel) => "unsupported_model",
+		Err(_) => "error",
 	}
 }
 
@@ -212,17 +732,21 @@ struct SseStream {
 	receiver: UnboundedReceiverStream<LLMClientCompletionResponse>,
 	model: String,
 	done: bool,
+	// Counts this stream as active until the client disconnects or it ends
+	_active: ActiveStreamGuard,
 }
 
 impl SseStream {
 	fn new(
 		receiver: mpsc::UnboundedReceiver<LLMClientCompletionResponse>,
 		model: String,
+		active: ActiveStreamGuard,
 	) -> Self {
 		Self {
 			receiver: UnboundedReceiverStream::new(receiver),
 			model,
 			done: false,
+			_active: active,
 		}
 	}
 }
@@ -276,21 +800,36 @@ impl Stream for SseStream {
 }
 
 pub async fn inference_stream(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	metrics: web::Data<Metrics>,
+	account: Option<AuthenticatedAccount>,
+	client_info: ClientInfo,
+	http_request: HttpRequest,
 	body: web::Json<InferenceRequest>,
 ) -> ActixResult<HttpResponse> {
 	let request = body.into_inner();
+	request_log::set_llm(&http_request, &request.provider, &request.model);
 
-	// Validate provider
-	let provider = match parse_provider(&request.provider) {
-		Ok(p) => p,
-		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	let PreparedCall {
+		provider,
+		model,
+		api_key,
+		billing,
+	} = match prepare_llm_call(
+		&request,
+		CallKind::Completion,
+		account.as_ref(),
+		pool.get_ref(),
+		vault.get_ref(),
+	)
+	.await
+	{
+		Ok(call) => call,
+		Err(rejected) => return Ok(rejected.into_response()),
 	};
 
-	// Validate and parse model
-	let model = match parse_model(&request.model) {
-		Ok(m) => m,
-		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
-	};
+	let prompt_text = prompt_text(&request.messages);
 
 	// Convert messages
 	let messages: Vec<_> = request.messages.into_iter().map(|m| m.into()).collect();
@@ -308,18 +847,363 @@ pub async fn inference_stream(
 
 	// Get the appropriate client
 	let client = get_client(&provider);
-	let api_key = request.api_key.clone();
 	let model_name = request.model.clone();
 
 	// Start streaming in the background
+	let pool = pool.into_inner();
+	let task_metrics = metrics.clone();
 	tokio::spawn(async move {
-		let _ = client
+		let result = client
 			.stream_completion(api_key, completion_request, sender)
 			.await;
+		task_metrics.record_llm_request(&provider.to_string(), llm_result_label(&result));
+
+		if let Ok(response) = result {
+			record_usage(
+				&pool,
+				&billing,
+				&client_info,
+				&provider,
+				&request.model,
+				&response,
+				&prompt_text,
+			)
+			.await;
+		}
 	});
 
 	// Create SSE stream
-	let stream = SseStream::new(receiver, model_name);
+	let stream = SseStream::new(receiver, model_name, metrics.stream_started());
+
+	Ok(HttpResponse::Ok()
+		.content_type("text/event-stream")
+		.insert_header(("Cache-Control", "no-cache"))
+		.insert_header(("Connection", "keep-alive"))
+		.insert_header(("Access-Control-Allow-Origin", "*"))
+		.streaming(stream))
+}
+
+/// Summarizes a branch diff and suggests a commit message, streaming the
+/// answer like `inference_stream`. Diffs too large for one request are
+/// summarized chunk by chunk first.
+pub async fn summarize_diff(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	metrics: web::Data<Metrics>,
+	account: Option<AuthenticatedAccount>,
+	client_info: ClientInfo,
+	http_request: HttpRequest,
+	body: web::Json<DiffSummaryRequest>,
+) -> ActixResult<HttpResponse> {
+	let request = body.into_inner();
+	request_log::set_llm(&http_request, &request.provider, &request.model);
+
+	if request.diff.trim().is_empty() {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: "The diff is empty".to_string(),
+			code: "EMPTY_DIFF".to_string(),
+		}));
+	}
+
+	let chunks = diff_summary::chunk_diff(&request.diff, diff_summary::MAX_CHUNK_CHARS);
+	if chunks.len() > diff_summary::MAX_CHUNKS {
+		return Ok(HttpResponse::PayloadTooLarge().json(ApiError {
+			error: format!(
+				"The diff is too large to summarize ({} characters)",
+				request.diff.len()
+			),
+			code: "DIFF_TOO_LARGE".to_string(),
+		}));
+	}
+
+	let PreparedCall {
+		provider,
+		model,
+		api_key,
+		billing,
+	} = match prepare_llm_call(
+		&request.as_inference_request(),
+		CallKind::Completion,
+		account.as_ref(),
+		pool.get_ref(),
+		vault.get_ref(),
+	)
+	.await
+	{
+		Ok(call) => call,
+		Err(rejected) => return Ok(rejected.into_response()),
+	};
+
+	let client = get_client(&provider);
+	let branch = request.branch.as_deref();
+
+	// Partial summaries are gathered before streaming starts, so their
+	// failures are reported with a status code like plain inference
+	let messages = if chunks.len() == 1 {
+		diff_summary::summary_messages(branch, &chunks[0], false)
+	} else {
+		let mut partials = Vec::with_capacity(chunks.len());
+		for (index, chunk) in chunks.iter().enumerate() {
+			let messages = diff_summary::chunk_messages(chunk, index, chunks.len());
+			let prompt_text = message_text(&messages);
+			let completion_request = LLMClientCompletionRequest::new(
+				model.clone(),
+				messages,
+				request.temperature,
+			)
+			.set_max_tokens(diff_summary::CHUNK_SUMMARY_MAX_TOKENS);
+
+			let result = client.completion(api_key.clone(), completion_request).await;
+			metrics.record_llm_request(&provider.to_string(), llm_result_label(&result));
+
+			let response = match result {
+				Ok(response) => response,
+				Err(e) => return Ok(client_error_response(e)),
+			};
+			record_usage(
+				pool.get_ref(),
+				&billing,
+				&client_info,
+				&provider,
+				&request.model,
+				&response,
+				&prompt_text,
+			)
+			.await;
+
+			partials.push(format!(
+				"## Part {}\n\n{}",
+				index + 1,
+				response.answer_up_until_now().trim()
+			));
+		}
+		diff_summary::summary_messages(branch, &partials.join("\n\n"), true)
+	};
+
+	let prompt_text = message_text(&messages);
+	let mut completion_request =
+		LLMClientCompletionRequest::new(model, messages, request.temperature);
+
+	if let Some(max_tokens) = request.max_tokens {
+		completion_request = completion_request.set_max_tokens(max_tokens);
+	}
+
+	let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+	let model_name = request.model.clone();
+
+	let pool = pool.into_inner();
+	let task_metrics = metrics.clone();
+	tokio::spawn(async move {
+		let result = client
+			.stream_completion(api_key, completion_request, sender)
+			.await;
+		task_metrics.record_llm_request(&provider.to_string(), llm_result_label(&result));
+
+		if let Ok(response) = result {
+			record_usage(
+				&pool,
+				&billing,
+				&client_info,
+				&provider,
+				&request.model,
+				&response,
+				&prompt_text,
+			)
+			.await;
+		}
+	});
+
+	let stream = SseStream::new(receiver, model_name, metrics.stream_started());
+
+	Ok(HttpResponse::Ok()
+		.content_type("text/event-stream")
+		.insert_header(("Cache-Control", "no-cache"))
+		.insert_header(("Connection", "keep-alive"))
+		.insert_header(("Access-Control-Allow-Origin", "*"))
+		.streaming(stream))
+}
+
+/// A fan-out target once its provider, model and key are checked.
+struct PreparedTarget {
+	label: String,
+	model_name: String,
+	call: PreparedCall,
+}
+
+fn sse_event<T: Serialize>(event: &T) -> Bytes {
+	let json = serde_json::to_string(event)
+		.unwrap_or_else(|_| "{\"error\": \"serialization_error\"}".to_string());
+	Bytes::from(format!("data: {}\n\n", json))
+}
+
+/// Sends the same messages to several provider/model pairs at once and
+/// streams their answers on one connection, each chunk labeled with its
+/// target. Every target is checked before streaming starts; failures after
+/// that end the target's part of the stream with an `error`.
+pub async fn inference_fanout(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	metrics: web::Data<Metrics>,
+	account: Option<AuthenticatedAccount>,
+	client_info: ClientInfo,
+	http_request: HttpRequest,
+	body: web::Json<FanoutRequest>,
+) -> ActixResult<HttpResponse> {
+	let request = body.into_inner();
+	let providers: Vec<&str> = request
+		.targets
+		.iter()
+		.map(|t| t.provider.as_str())
+		.collect();
+	let models: Vec<&str> = request.targets.iter().map(|t| t.model.as_str()).collect();
+	request_log::set_llm(&http_request, &providers.join(","), &models.join(","));
+
+	if request.targets.is_empty() || request.targets.len() > MAX_FANOUT_TARGETS {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: format!(
+				"targets must contain between 1 and {} provider/model pairs",
+				MAX_FANOUT_TARGETS
+			),
+			code: "INVALID_TARGETS".to_string(),
+		}));
+	}
+
+	let mut prepared = Vec::with_capacity(request.targets.len());
+	for (index, target) in request.targets.iter().enumerate() {
+		let call = match prepare_llm_call(
+			&request.target_request(target),
+			CallKind::Completion,
+			account.as_ref(),
+			pool.get_ref(),
+			vault.get_ref(),
+		)
+		.await
+		{
+			Ok(call) => call,
+			Err(CallRejected::Invalid(e)) => {
+				return Ok(HttpResponse::BadRequest().json(ApiError {
+					error: format!("Target {}: {}", index, e.error),
+					code: e.code,
+				}))
+			}
+			Err(CallRejected::Refused(response)) => return Ok(response),
+		};
+		prepared.push(PreparedTarget {
+			label: target
+				.label
+				.clone()
+				.unwrap_or_else(|| format!("{}/{}", target.provider, target.model)),
+			model_name: target.model.clone(),
+			call,
+		});
+	}
+
+	let prompt_text = prompt_text(&request.messages);
+	let messages: Vec<LLMClientMessage> =
+		request.messages.into_iter().map(|m| m.into()).collect();
+
+	// Every target sends its chunks here; the stream ends once all are done
+	let (events, receiver) = mpsc::unbounded_channel::<FanoutChunk>();
+	let pool = pool.into_inner();
+	for (index, target) in prepared.into_iter().enumerate() {
+		let PreparedTarget {
+			label,
+			model_name,
+			call,
+		} = target;
+		let mut completion_request = LLMClientCompletionRequest::new(
+			call.model,
+			messages.clone(),
+			request.temperature,
+		);
+		if let Some(max_tokens) = request.max_tokens {
+			completion_request = completion_request.set_max_tokens(max_tokens);
+		}
+
+		let events = events.clone();
+		let pool = pool.clone();
+		let metrics = metrics.clone();
+		let client_info = client_info.clone();
+		let prompt_text = prompt_text.clone();
+		tokio::spawn(async move {
+			let chunk = |delta: String, done: bool| FanoutChunk {
+				target: Some(index),
+				label: Some(label.clone()),
+				model: Some(model_name.clone()),
+				delta,
+				done,
+				usage: None,
+				error: None,
+			};
+
+			let (sender, mut deltas) =
+				mpsc::unbounded_channel::<LLMClientCompletionResponse>();
+			let client = get_client(&call.provider);
+			let forward = async {
+				while let Some(response) = deltas.recv().await {
+					if let Some(delta) = response.delta() {
+						let _ = events.send(chunk(delta.to_string(), false));
+					}
+				}
+			};
+			let (result, ()) = tokio::join!(
+				client.stream_completion(call.api_key, completion_request, sender),
+				forward
+			);
+			metrics.record_llm_request(
+				&call.provider.to_string(),
+				llm_result_label(&result),
+			);
+
+			let last = match result {
+				Ok(response) => {
+					record_usage(
+						&pool,
+						&call.billing,
+						&client_info,
+						&call.provider,
+						&model_name,
+						&response,
+						&prompt_text,
+					)
+					.await;
+
+					let statistics = response.usage_statistics();
+					FanoutChunk {
+						usage: Some(UsageInfo {
+							input_tokens: statistics.input_tokens(),
+							output_tokens: statistics.output_tokens(),
+							cached_input_tokens: statistics.cached_input_tokens(),
+						}),
+						..chunk(String::new(), true)
+					}
+				}
+				Err(e) => FanoutChunk {
+					error: Some(client_error(&e)),
+					..chunk(String::new(), true)
+				},
+			};
+			let _ = events.send(last);
+		});
+	}
+	drop(events);
+
+	let active = metrics.stream_started();
+	let stream = UnboundedReceiverStream::new(receiver)
+		.map(|chunk| Ok::<_, actix_web::Error>(sse_event(&chunk)))
+		.chain(futures::stream::once(async move {
+			// Counted as active until the last event is sent
+			drop(active);
+			Ok(sse_event(&FanoutChunk {
+				target: None,
+				label: None,
+				model: None,
+				delta: String::new(),
+				done: true,
+				usage: None,
+				error: None,
+			}))
+		}));
 
 	Ok(HttpResponse::Ok()
 		.content_type("text/event-stream")
@@ -329,8 +1213,292 @@ pub async fn inference_stream(
 		.streaming(stream))
 }
 
+pub async fn embeddings(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	metrics: web::Data<Metrics>,
+	account: Option<AuthenticatedAccount>,
+	client_info: ClientInfo,
+	http_request: HttpRequest,
+	body: web::Json<EmbeddingsRequest>,
+) -> ActixResult<HttpResponse> {
+	let request = body.into_inner();
+	request_log::set_llm(&http_request, &request.provider, &request.model);
+
+	if request.input.is_empty() || request.input.len() > embeddings::MAX_INPUTS {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: format!(
+				"input must contain between 1 and {} texts",
+				embeddings::MAX_INPUTS
+			),
+			code: "INVALID_INPUT".to_string(),
+		}));
+	}
+
+	let PreparedCall {
+		provider,
+		api_key,
+		billing,
+		..
+	} = match prepare_llm_call(
+		&request.as_inference_request(),
+		CallKind::Embeddings,
+		account.as_ref(),
+		pool.get_ref(),
+		vault.get_ref(),
+	)
+	.await
+	{
+		Ok(call) => call,
+		Err(rejected) => return Ok(rejected.into_response()),
+	};
+
+	let result = OpenAIEmbeddingsClient::new()
+		.embed(&api_key, &request.model, &request.input)
+		.await;
+	metrics.record_llm_request(&provider.to_string(), llm_result_label(&result));
+
+	let (vectors, statistics) = match result {
+		Ok(result) => result,
+		Err(e) => return Ok(client_error_response(e)),
+	};
+
+	// Metered like a completion with no output
+	let response =
+		LLMClientCompletionResponse::new(String::new(), None, request.model.clone())
+			.set_usage_statistics(statistics.clone());
+	record_usage(
+		pool.get_ref(),
+		&billing,
+		&client_info,
+		&provider,
+		&request.model,
+		&response,
+		&request.input.join("\n"),
+	)
+	.await;
+
+	Ok(HttpResponse::Ok().json(EmbeddingsResponse {
+		embeddings: vectors,
+		model: request.model,
+		usage: Some(UsageInfo {
+			input_tokens: statistics.input_tokens(),
+			output_tokens: statistics.output_tokens(),
+			cached_input_tokens: None,
+		}),
+	}))
+}
+
+fn header(request: &HttpRequest, name: &str) -> Option<String> {
+	request
+		.headers()
+		.get(name)
+		.and_then(|v| v.to_str().ok())
+		.map(str::trim)
+		.filter(|v| !v.is_empty())
+		.map(str::to_string)
+}
+
+/// `POST /v1/chat/completions`, for tools built on an OpenAI SDK. See
+/// `openai_compat` for how requests map onto providers and keys.
+pub async fn chat_completions(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	metrics: web::Data<Metrics>,
+	account: AuthenticatedAccount,
+	client_info: ClientInfo,
+	http_request: HttpRequest,
+	body: web::Json<ChatCompletionRequest>,
+) -> ActixResult<HttpResponse> {
+	let request = body.into_inner();
+	let bad_request = |error: ApiError| {
+		Ok(openai_compat::error_response(
+			StatusCode::BAD_REQUEST,
+			error,
+		))
+	};
+
+	let Some((provider_name, model_name)) = openai_compat::split_model(&request.model)
+	else {
+		return bad_request(ApiError {
+			error: format!("Unknown model {}; name it as provider/model", request.model),
+			code: "INVALID_MODEL".to_string(),
+		});
+	};
+	request_log::set_llm(&http_request, &provider_name, &model_name);
+
+	let output_limit = request.output_limit();
+	let include_usage = request.include_usage();
+	let temperature = request
+		.temperature
+		.unwrap_or(openai_compat::DEFAULT_TEMPERATURE);
+	let messages = match openai_compat::to_api_messages(request.messages) {
+		Ok(messages) => messages,
+		Err(e) => return bad_request(e),
+	};
+
+	let credentials = InferenceRequest {
+		provider: provider_name,
+		model: model_name.clone(),
+		messages: Vec::new(),
+		api_key: None,
+		key_id: header(&http_request, openai_compat::KEY_ID_HEADER),
+		org_id: header(&http_request, openai_compat::ORGANIZATION_HEADER),
+		temperature,
+		max_tokens: output_limit,
+		stream: request.stream,
+	};
+	let PreparedCall {
+		provider,
+		model,
+		api_key,
+		billing,
+	} = match prepare_llm_call(
+		&credentials,
+		CallKind::OpenAiCompatible,
+		Some(&account),
+		pool.get_ref(),
+		vault.get_ref(),
+	)
+	.await
+	{
+		Ok(call) => call,
+		Err(CallRejected::Invalid(e)) => return bad_request(e),
+		Err(CallRejected::Refused(response)) => return Ok(response),
+	};
+
+	let prompt_text = prompt_text(&messages);
+	let messages: Vec<LLMClientMessage> =
+		messages.into_iter().map(|m| m.into()).collect();
+	let mut completion_request =
+		LLMClientCompletionRequest::new(model, messages, temperature);
+	if let Some(max_tokens) = output_limit {
+		completion_request = completion_request.set_max_tokens(max_tokens);
+	}
+
+	let client = get_client(&provider);
+	let completion = Completion::new(&request.model);
+	let usage_of = |response: &LLMClientCompletionResponse| {
+		let statistics = response.usage_statistics();
+		CompletionUsage::new(
+			statistics.input_tokens().unwrap_or_default(),
+			statistics.output_tokens().unwrap_or_default(),
+		)
+	};
+
+	if !request.stream {
+		let result = client.completion(api_key, completion_request).await;
+		metrics.record_llm_request(&provider.to_string(), llm_result_label(&result));
+
+		return Ok(match result {
+			Ok(response) => {
+				record_usage(
+					pool.get_ref(),
+					&billing,
+					&client_info,
+					&provider,
+					&model_name,
+					&response,
+					&prompt_text,
+				)
+				.await;
+
+				HttpResponse::Ok().json(completion.response(
+					response.answer_up_until_now().to_string(),
+					usage_of(&response),
+				))
+			}
+			Err(e) => {
+				openai_compat::error_response(client_error_status(&e), client_error(&e))
+			}
+		});
+	}
+
+	let (events, receiver) = mpsc::unbounded_channel::<Bytes>();
+	let pool = pool.into_inner();
+	let active = metrics.stream_started();
+	tokio::spawn(async move {
+		let _ = events.send(sse_event(&completion.chunk(
+			ChunkDelta {
+				role: Some("assistant"),
+				content: None,
+			},
+			None,
+		)));
+
+		let (sender, mut deltas) =
+			mpsc::unbounded_channel::<LLMClientCompletionResponse>();
+		let forward = async {
+			while let Some(response) = deltas.recv().await {
+				if let Some(delta) = response.delta() {
+					let delta = ChunkDelta {
+						role: None,
+						content: Some(delta.to_string()),
+					};
+					let _ = events.send(sse_event(&completion.chunk(delta, None)));
+				}
+			}
+		};
+		let (result, ()) = tokio::join!(
+			client.stream_completion(api_key, completion_request, sender),
+			forward
+		);
+		metrics.record_llm_request(&provider.to_string(), llm_result_label(&result));
+
+		match result {
+			Ok(response) => {
+				record_usage(
+					&pool,
+					&billing,
+					&client_info,
+					&provider,
+					&model_name,
+					&response,
+					&prompt_text,
+				)
+				.await;
+
+				let _ = events.send(sse_event(
+					&completion.chunk(ChunkDelta::default(), Some("stop")),
+				));
+				if include_usage {
+					let _ = events
+						.send(sse_event(&completion.usage_chunk(usage_of(&response))));
+				}
+			}
+			Err(e) => {
+				let error =
+					openai_compat::error_body(client_error_status(&e), client_error(&e));
+				let _ = events.send(sse_event(&error));
+			}
+		}
+		let _ = events.send(Bytes::from("data: [DONE]\n\n"));
+		// Counted as active until the provider is done
+		drop(active);
+	});
+
+	Ok(HttpResponse::Ok()
+		.content_type("text/event-stream")
+		.insert_header(("Cache-Control", "no-cache"))
+		.insert_header(("Connection", "keep-alive"))
+		.streaming(UnboundedReceiverStream::new(receiver).map(Ok::<_, actix_web::Error>)))
+}
+
+/// `GET /v1/models`: every model, named `provider/model` as chat completions
+/// expect.
+pub async fn list_models(_account: AuthenticatedAccount) -> ActixResult<HttpResponse> {
+	Ok(HttpResponse::Ok().json(openai_compat::model_list(provider_catalog())))
+}
+
 pub async fn list_providers() -> ActixResult<HttpResponse> {
-	let providers = vec![
+	Ok(HttpResponse::Ok().json(ProvidersResponse {
+		providers: provider_catalog(),
+	}))
+}
+
+/// Providers and the models each serves, with `mock` when it is enabled.
+fn provider_catalog() -> Vec<ProviderInfo> {
+	let mut providers = vec![
 		ProviderInfo {
 			name: "anthropic".to_string(),
 			display_name: "Anthropic".to_string(),
@@ -438,5 +1606,24 @@ pub async fn list_providers() -> ActixResult<HttpResponse> {
 		},
 	];
 
-	Ok(HttpResponse::Ok().json(ProvidersResponse { providers }))
+	if replay::enabled() {
+		let mut models = replay::recorded_models();
+		if models.is_empty() {
+			models.push("mock".to_string());
+		}
+		providers.push(ProviderInfo {
+			name: "mock".to_string(),
+			display_name: "Mock".to_string(),
+			models: models
+				.into_iter()
+				.map(|model| ModelInfo {
+					name: model.clone(),
+					id: model,
+					context_length: None,
+				})
+				.collect(),
+		});
+	}
+
+	providers
 }
@@ -4,9 +4,46 @@ use eventsource_stream::Eventsource;
 use futures::StreamExt;
 use reqwest::Client;
 use serde_json::{json, Value};
+use std::env;
 use tokio::sync::mpsc::UnboundedSender;
 use tracing::error;
 
+/// The provider's API root. `variable` overrides `default`, to go through a
+/// proxy or point at the mock provider of `llm::mock`.
+pub fn base_url(variable: &str, default: &str) -> String {
+	env::var(variable)
+		.ok()
+		.filter(|url| !url.is_empty())
+		.map(|url| url.trim_end_matches('/').to_string())
+		.unwrap_or_else(|| default.to_string())
+}
+
+/// Reads the `usage` object that OpenAI-compatible APIs attach to the last
+/// stream chunk when `stream_options.include_usage` is set. Groq reports it
+/// under `x_groq.usage` instead.
+fn openai_compatible_usage(parsed: &Value) -> Option<LLMClientUsageStatistics> {
+	let usage = parsed
+		.get("usage")
+		.filter(|u| !u.is_null())
+		.or_else(|| parsed.get("x_groq").and_then(|x| x.get("usage")))?;
+
+	let mut statistics = LLMClientUsageStatistics::new();
+	if let Some(prompt) = usage.get("prompt_tokens").and_then(|t| t.as_u64()) {
+		statistics = statistics.set_input_tokens(prompt as u32);
+	}
+	if let Some(completion) = usage.get("completion_tokens").and_then(|t| t.as_u64()) {
+		statistics = statistics.set_output_tokens(completion as u32);
+	}
+	if let Some(cached) = usage
+		.get("prompt_tokens_details")
+		.and_then(|d| d.get("cached_tokens"))
+		.and_then(|t| t.as_u64())
+	{
+		statistics = statistics.set_cached_input_tokens(cached as u32);
+	}
+	Some(statistics)
+}
+
 pub struct AnthropicClient {
 	client: Client,
 	base_url: String,
@@ -16,7 +53,7 @@ impl AnthropicClient {
 	pub fn new() -> Self {
 		Self {
 			client: Client::new(),
-			base_url: "https://api.anthropic.com".to_string(),
+			base_url: base_url("ANTHROPIC_BASE_URL", "https://api.anthropic.com"),
 		}
 	}
 
@@ -96,6 +133,7 @@ impl LLMClient for AnthropicClient {
 
 		let mut event_source = response.bytes_stream().eventsource();
 		let mut buffered_string = String::new();
+		let mut usage = LLMClientUsageStatistics::new();
 
 		while let Some(event) = event_source.next().await {
 			match event {
@@ -105,6 +143,31 @@ impl LLMClient for AnthropicClient {
 					}
 
 					if let Ok(parsed) = serde_json::from_str::<Value>(&event.data) {
+						// `message_start` carries the input token count, each
+						// `message_delta` the running output token count.
+						if let Some(message_usage) =
+							parsed.get("message").and_then(|m| m.get("usage"))
+						{
+							if let Some(input) =
+								message_usage.get("input_tokens").and_then(|t| t.as_u64())
+							{
+								usage = usage.set_input_tokens(input as u32);
+							}
+							if let Some(cached) = message_usage
+								.get("cache_read_input_tokens")
+								.and_then(|t| t.as_u64())
+							{
+								usage = usage.set_cached_input_tokens(cached as u32);
+							}
+						}
+						if let Some(output) = parsed
+							.get("usage")
+							.and_then(|u| u.get("output_tokens"))
+							.and_then(|t| t.as_u64())
+						{
+							usage = usage.set_output_tokens(output as u32);
+						}
+
 						if let Some(delta_obj) = parsed.get("delta") {
 							if let Some(text) =
 								delta_obj.get("text").and_then(|t| t.as_str())
@@ -126,21 +189,19 @@ impl LLMClient for AnthropicClient {
 			}
 		}
 
-		Ok(LLMClientCompletionResponse::new(
-			buffered_string,
-			None,
-			model_str,
-		))
+		Ok(
+			LLMClientCompletionResponse::new(buffered_string, None, model_str)
+				.set_usage_statistics(usage),
+		)
 	}
 
 	async fn completion(
 		&self,
 		api_key: String,
 		request: LLMClientCompletionRequest,
-	) -> Result<String, LLMClientError> {
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
 		let (sender, _) = tokio::sync::mpsc::unbounded_channel();
-		let response = self.stream_completion(api_key, request, sender).await?;
-		Ok(response.answer_up_until_now().to_string())
+		self.stream_completion(api_key, request, sender).await
 	}
 }
 
@@ -153,7 +214,7 @@ impl OpenAIClient {
 	pub fn new() -> Self {
 		Self {
 			client: Client::new(),
-			base_url: "https://api.openai.com".to_string(),
+			base_url: base_url("OPENAI_BASE_URL", "https://api.openai.com"),
 		}
 	}
 
@@ -190,6 +251,7 @@ impl OpenAIClient {
 			"messages": messages,
 			"temperature": request.temperature(),
 			"stream": true,
+			"stream_options": { "include_usage": true },
 			"max_tokens": request.max_tokens().unwrap_or(4096)
 		})
 	}
@@ -221,6 +283,7 @@ impl LLMClient for OpenAIClient {
 
 		let mut event_source = response.bytes_stream().eventsource();
 		let mut buffered_string = String::new();
+		let mut usage = LLMClientUsageStatistics::new();
 
 		while let Some(event) = event_source.next().await {
 			match event {
@@ -230,6 +293,10 @@ impl LLMClient for OpenAIClient {
 					}
 
 					if let Ok(parsed) = serde_json::from_str::<Value>(&event.data) {
+						if let Some(reported) = openai_compatible_usage(&parsed) {
+							usage = reported;
+						}
+
 						if let Some(choices) =
 							parsed.get("choices").and_then(|c| c.as_array())
 						{
@@ -259,21 +326,19 @@ impl LLMClient for OpenAIClient {
 			}
 		}
 
-		Ok(LLMClientCompletionResponse::new(
-			buffered_string,
-			None,
-			model_str,
-		))
+		Ok(
+			LLMClientCompletionResponse::new(buffered_string, None, model_str)
+				.set_usage_statistics(usage),
+		)
 	}
 
 	async fn completion(
 		&self,
 		api_key: String,
 		request: LLMClientCompletionRequest,
-	) -> Result<String, LLMClientError> {
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
 		let (sender, _) = tokio::sync::mpsc::unbounded_channel();
-		let response = self.stream_completion(api_key, request, sender).await?;
-		Ok(response.answer_up_until_now().to_string())
+		self.stream_completion(api_key, request, sender).await
 	}
 }
 
@@ -286,7 +351,10 @@ impl GoogleClient {
 	pub fn new() -> Self {
 		Self {
 			client: Client::new(),
-			base_url: "https://generativelanguage.googleapis.com".to_string(),
+			base_url: base_url(
+				"GOOGLE_BASE_URL",
+				"https://generativelanguage.googleapis.com",
+			),
 		}
 	}
 
@@ -369,11 +437,32 @@ impl LLMClient for GoogleClient {
 
 		let mut event_source = response.bytes_stream().eventsource();
 		let mut buffered_string = String::new();
+		let mut usage = LLMClientUsageStatistics::new();
 
 		while let Some(event) = event_source.next().await {
 			match event {
 				Ok(event) => {
 					if let Ok(parsed) = serde_json::from_str::<Value>(&event.data) {
+						if let Some(metadata) = parsed.get("usageMetadata") {
+							if let Some(prompt) =
+								metadata.get("promptTokenCount").and_then(|t| t.as_u64())
+							{
+								usage = usage.set_input_tokens(prompt as u32);
+							}
+							if let Some(candidates) = metadata
+								.get("candidatesTokenCount")
+								.and_then(|t| t.as_u64())
+							{
+								usage = usage.set_output_tokens(candidates as u32);
+							}
+							if let Some(cached) = metadata
+								.get("cachedContentTokenCount")
+								.and_then(|t| t.as_u64())
+							{
+								usage = usage.set_cached_input_tokens(cached as u32);
+							}
+						}
+
 						if let Some(candidates) =
 							parsed.get("candidates").and_then(|c| c.as_array())
 						{
@@ -409,21 +498,19 @@ impl LLMClient for GoogleClient {
 			}
 		}
 
-		Ok(LLMClientCompletionResponse::new(
-			buffered_string,
-			None,
-			model_str,
-		))
+		Ok(
+			LLMClientCompletionResponse::new(buffered_string, None, model_str)
+				.set_usage_statistics(usage),
+		)
 	}
 
 	async fn completion(
 		&self,
 		api_key: String,
 		request: LLMClientCompletionRequest,
-	) -> Result<String, LLMClientError> {
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
 		let (sender, _) = tokio::sync::mpsc::unbounded_channel();
-		let response = self.stream_completion(api_key, request, sender).await?;
-		Ok(response.answer_up_until_now().to_string())
+		self.stream_completion(api_key, request, sender).await
 	}
 }
 
@@ -436,7 +523,7 @@ impl GroqClient {
 	pub fn new() -> Self {
 		Self {
 			client: Client::new(),
-			base_url: "https://api.groq.com".to_string(),
+			base_url: base_url("GROQ_BASE_URL", "https://api.groq.com"),
 		}
 	}
 
@@ -470,6 +557,7 @@ impl GroqClient {
 			"messages": messages,
 			"temperature": request.temperature(),
 			"stream": true,
+			"stream_options": { "include_usage": true },
 			"max_tokens": request.max_tokens().unwrap_or(4096)
 		})
 	}
@@ -501,6 +589,7 @@ impl LLMClient for GroqClient {
 
 		let mut event_source = response.bytes_stream().eventsource();
 		let mut buffered_string = String::new();
+		let mut usage = LLMClientUsageStatistics::new();
 
 		while let Some(event) = event_source.next().await {
 			match event {
@@ -510,6 +599,10 @@ impl LLMClient for GroqClient {
 					}
 
 					if let Ok(parsed) = serde_json::from_str::<Value>(&event.data) {
+						if let Some(reported) = openai_compatible_usage(&parsed) {
+							usage = reported;
+						}
+
 						if let Some(choices) =
 							parsed.get("choices").and_then(|c| c.as_array())
 						{
@@ -539,21 +632,19 @@ impl LLMClient for GroqClient {
 			}
 		}
 
-		Ok(LLMClientCompletionResponse::new(
-			buffered_string,
-			None,
-			model_str,
-		))
+		Ok(
+			LLMClientCompletionResponse::new(buffered_string, None, model_str)
+				.set_usage_statistics(usage),
+		)
 	}
 
 	async fn completion(
 		&self,
 		api_key: String,
 		request: LLMClientCompletionRequest,
-	) -> Result<String, LLMClientError> {
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
 		let (sender, _) = tokio::sync::mpsc::unbounded_channel();
-		let response = self.stream_completion(api_key, request, sender).await?;
-		Ok(response.answer_up_until_now().to_string())
+		self.stream_completion(api_key, request, sender).await
 	}
 }
 
@@ -566,7 +657,7 @@ impl OpenRouterClient {
 	pub fn new() -> Self {
 		Self {
 			client: Client::new(),
-			base_url: "https://openrouter.ai".to_string(),
+			base_url: base_url("OPENROUTER_BASE_URL", "https://openrouter.ai"),
 		}
 	}
 
@@ -591,6 +682,7 @@ impl OpenRouterClient {
 			"messages": messages,
 			"temperature": request.temperature(),
 			"stream": true,
+			"usage": { "include": true },
 			"max_tokens": request.max_tokens().unwrap_or(4096)
 		})
 	}
@@ -628,6 +720,7 @@ impl LLMClient for OpenRouterClient {
 
 		let mut event_source = response.bytes_stream().eventsource();
 		let mut buffered_string = String::new();
+		let mut usage = LLMClientUsageStatistics::new();
 
 		while let Some(event) = event_source.next().await {
 			match event {
@@ -637,6 +730,10 @@ impl LLMClient for OpenRouterClient {
 					}
 
 					if let Ok(parsed) = serde_json::from_str::<Value>(&event.data) {
+						if let Some(reported) = openai_compatible_usage(&parsed) {
+							usage = reported;
+						}
+
 						if let Some(choices) =
 							parsed.get("choices").and_then(|c| c.as_array())
 						{
@@ -666,20 +763,18 @@ impl LLMClient for OpenRouterClient {
 			}
 		}
 
-		Ok(LLMClientCompletionResponse::new(
-			buffered_string,
-			None,
-			model_str,
-		))
+		Ok(
+			LLMClientCompletionResponse::new(buffered_string, None, model_str)
+				.set_usage_statistics(usage),
+		)
 	}
 
 	async fn completion(
 		&self,
 		api_key: String,
 		request: LLMClientCompletionRequest,
-	) -> Result<String, LLMClientError> {
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
 		let (sender, _) = tokio::sync::mpsc::unbounded_channel();
-		let response = self.stream_completion(api_key, request, sender).await?;
-		Ok(response.answer_up_until_now().to_string())
+		self.stream_completion(api_key, request, sender).await
 	}
 }
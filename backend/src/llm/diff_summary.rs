@@ -0,0 +1,119 @@
+//! Prompts and chunking for `POST /api/inference/summarize-diff`.
+//!
+//! A diff that fits in one chunk is summarized in a single streamed request.
+//! Larger diffs are split at file boundaries, each chunk is summarized on its
+//! own, and the final streamed request combines those partial summaries.
+
+use crate::llm::types::LLMClientMessage;
+
+/// Upper bound on the characters of diff sent in a single request.
+pub const MAX_CHUNK_CHARS: usize = 24_000;
+
+/// Diffs needing more chunks than this are rejected rather than fanned out
+/// into dozens of provider requests.
+pub const MAX_CHUNKS: usize = 16;
+
+/// Output cap for each partial summary.
+pub const CHUNK_SUMMARY_MAX_TOKENS: usize = 512;
+
+const CHUNK_SYSTEM_PROMPT: &str = "You are reviewing one part of a git diff. \
+Summarize what it changes in a few short bullet points: name the files touched \
+and the intent of each change. Don't restate the code.";
+
+const SUMMARY_SYSTEM_PROMPT: &str = "You summarize the changes on a git branch \
+for the developer who is about to merge it. Start with a short natural-language \
+summary of what the branch does, as a few sentences or bullet points. Then write \
+a line containing only \"Commit message:\" followed by a suggested commit message: \
+an imperative subject line under 72 characters, optionally followed by a blank \
+line and a short body.";
+
+/// Splits `diff` into chunks of at most `max_chars`, keeping each file's diff
+/// whole where it fits. Files larger than a chunk are split at line
+/// boundaries.
+pub fn chunk_diff(diff: &str, max_chars: usize) -> Vec<String> {
+	let mut chunks = Vec::new();
+	let mut current = String::new();
+
+	for file in split_files(diff) {
+		if current.len() + file.len() > max_chars && !current.is_empty() {
+			chunks.push(std::mem::take(&mut current));
+		}
+		if file.len() <= max_chars {
+			current.push_str(file);
+			continue;
+		}
+
+		for line in file.split_inclusive('\n') {
+			if current.len() + line.len() > max_chars && !current.is_empty() {
+				chunks.push(std::mem::take(&mut current));
+			}
+			// A single line longer than a chunk is cut at a char boundary
+			let mut end = line.len().min(max_chars);
+			while !line.is_char_boundary(end) {
+				end -= 1;
+			}
+			current.push_str(&line[..end]);
+		}
+	}
+
+	if !current.trim().is_empty() {
+		chunks.push(current);
+	}
+	chunks
+}
+
+/// Splits a unified diff before each `diff --git` header.
+fn split_files(diff: &str) -> Vec<&str> {
+	let mut files = Vec::new();
+	let mut start = 0;
+	let mut offset = 0;
+
+	for line in diff.split_inclusive('\n') {
+		if line.starts_with("diff --git ") && offset > start {
+			files.push(&diff[start..offset]);
+			start = offset;
+		}
+		offset += line.len();
+	}
+	if start < diff.len() {
+		files.push(&diff[start..]);
+	}
+	files
+}
+
+/// Messages asking for a partial summary of chunk `index` of `total`.
+pub fn chunk_messages(chunk: &str, index: usize, total: usize) -> Vec<LLMClientMessage> {
+	vec![
+		LLMClientMessage::system(CHUNK_SYSTEM_PROMPT.to_string()),
+		LLMClientMessage::user(format!(
+			"Part {} of {} of the diff:\n\n```diff\n{}\n```",
+			index + 1,
+			total,
+			chunk
+		)),
+	]
+}
+
+/// Messages for the final, streamed summary. `content` is either the whole
+/// diff or the partial summaries of its chunks.
+pub fn summary_messages(
+	branch: Option<&str>,
+	content: &str,
+	from_partials: bool,
+) -> Vec<LLMClientMessage> {
+	let mut prompt = String::new();
+	if let Some(branch) = branch {
+		prompt.push_str(&format!("Branch: {}\n\n", branch));
+	}
+	if from_partials {
+		prompt.push_str("Summaries of each part of the branch's diff:\n\n");
+		prompt.push_str(content);
+	} else {
+		prompt.push_str(&format!("Diff:\n\n```diff\n{}\n```", content));
+	}
+
+	vec![
+		LLMClientMessage::system(SUMMARY_SYSTEM_PROMPT.to_string()),
+		LLMClientMessage::user(prompt),
+	]
+}
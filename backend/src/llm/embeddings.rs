@@ -0,0 +1,100 @@
+//! Text embeddings for `POST /api/embeddings`. Only OpenAI's embedding models
+//! are supported for now.
+
+use crate::llm::{
+	clients::base_url,
+	types::{LLMClientError, LLMClientUsageStatistics},
+};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Inputs accepted in one request, matching OpenAI's batch limit.
+pub const MAX_INPUTS: usize = 2048;
+
+pub const OPENAI_EMBEDDING_MODELS: &[&str] = &[
+	"text-embedding-3-small",
+	"text-embedding-3-large",
+	"text-embedding-ada-002",
+];
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbeddingsResponse {
+	data: Vec<OpenAIEmbedding>,
+	usage: Option<OpenAIEmbeddingsUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbedding {
+	index: usize,
+	embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbeddingsUsage {
+	prompt_tokens: u32,
+}
+
+pub struct OpenAIEmbeddingsClient {
+	client: Client,
+	base_url: String,
+}
+
+impl OpenAIEmbeddingsClient {
+	pub fn new() -> Self {
+		Self {
+			client: Client::new(),
+			base_url: base_url("OPENAI_BASE_URL", "https://api.openai.com"),
+		}
+	}
+
+	/// Embeds each input, returning the vectors in input order.
+	pub async fn embed(
+		&self,
+		api_key: &str,
+		model: &str,
+		input: &[String],
+	) -> Result<(Vec<Vec<f32>>, LLMClientUsageStatistics), LLMClientError> {
+		if !OPENAI_EMBEDDING_MODELS.contains(&model) {
+			return Err(LLMClientError::UnSupportedModel);
+		}
+
+		let response = self
+			.client
+			.post(format!("{}/v1/embeddings", self.base_url))
+			.header("Authorization", format!("Bearer {}", api_key))
+			.json(&json!({
+				"model": model,
+				"input": input,
+				"encoding_format": "float"
+			}))
+			.send()
+			.await?;
+
+		match response.status() {
+			reqwest::StatusCode::UNAUTHORIZED => {
+				return Err(LLMClientError::UnauthorizedAccess)
+			}
+			reqwest::StatusCode::TOO_MANY_REQUESTS => {
+				return Err(LLMClientError::RateLimitExceeded)
+			}
+			status if !status.is_success() => {
+				return Err(LLMClientError::FailedToGetResponse)
+			}
+			_ => {}
+		}
+
+		let mut parsed: OpenAIEmbeddingsResponse = response.json().await?;
+		parsed.data.sort_by_key(|e| e.index);
+
+		let mut statistics = LLMClientUsageStatistics::new().set_output_tokens(0);
+		if let Some(usage) = parsed.usage {
+			statistics = statistics.set_input_tokens(usage.prompt_tokens);
+		}
+
+		Ok((
+			parsed.data.into_iter().map(|e| e.embedding).collect(),
+			statistics,
+		))
+	}
+}
@@ -0,0 +1,300 @@
+//! A stand-in for the LLM providers' APIs, answering in each provider's
+//! streaming format so requests go through the real clients. Tests point the
+//! clients at it, and so does the server with the `mock-llm` feature and
+//! `MOCK_LLM=true`, for frontend work without provider keys.
+//!
+//! Replies are deterministic: they name the model and quote the last user
+//! message. Token counts are the number of words.
+
+use serde_json::{json, Value};
+use std::env;
+use wiremock::{
+	matchers::{method, path, path_regex},
+	Mock, MockServer, Request, Respond, ResponseTemplate,
+};
+
+/// Requests made with this key are refused like a revoked key would be.
+pub const INVALID_KEY: &str = "mock-invalid-key";
+
+/// Variables read by `clients::base_url`, all set by `use_for_all_providers`.
+const BASE_URL_VARIABLES: [&str; 5] = [
+	"ANTHROPIC_BASE_URL",
+	"OPENAI_BASE_URL",
+	"GOOGLE_BASE_URL",
+	"GROQ_BASE_URL",
+	"OPENROUTER_BASE_URL",
+];
+
+#[derive(Clone, Copy)]
+enum Format {
+	Anthropic,
+	/// OpenAI's chat completions, which Groq and OpenRouter also use.
+	OpenAI,
+	Google,
+}
+
+fn words(text: &str) -> u64 {
+	text.split_whitespace().count() as u64
+}
+
+fn text_of(content: &Value) -> &str {
+	content.as_str().unwrap_or_default()
+}
+
+/// The key the client authenticated with, wherever the provider takes it.
+fn api_key(format: Format, request: &Request) -> Option<String> {
+	match format {
+		Format::Anthropic => request
+			.headers
+			.get("x-api-key")
+			.and_then(|v| v.to_str().ok())
+			.map(str::to_string),
+		Format::OpenAI => request
+			.headers
+			.get("authorization")
+			.and_then(|v| v.to_str().ok())
+			.and_then(|v| v.strip_prefix("Bearer "))
+			.map(str::to_string),
+		Format::Google => request
+			.url
+			.query_pairs()
+			.find(|(name, _)| name == "key")
+			.map(|(_, key)| key.into_owned()),
+	}
+}
+
+fn unauthorized() -> ResponseTemplate {
+	ResponseTemplate::new(401).set_body_json(json!({
+		"error": { "message": "Invalid API key", "type": "authentication_error" }
+	}))
+}
+
+fn sse(events: Vec<Value>, done: bool) -> ResponseTemplate {
+	let mut body: String = events
+		.iter()
+		.map(|event| format!("data: {}\n\n", event))
+		.collect();
+	if done {
+		body.push_str("data: [DONE]\n\n");
+	}
+	ResponseTemplate::new(200).set_body_raw(body, "text/event-stream")
+}
+
+/// Streams a completion back in the provider's format.
+struct MockCompletion(Format);
+
+impl Respond for MockCompletion {
+	fn respond(&self, request: &Request) -> ResponseTemplate {
+		let format = self.0;
+		if api_key(format, request).is_none_or(|key| key == INVALID_KEY) {
+			return unauthorized();
+		}
+		let Ok(body) = request.body_json::<Value>() else {
+			return ResponseTemplate::new(400);
+		};
+
+		let (model, prompt, last_user) = match format {
+			Format::Anthropic | Format::OpenAI => {
+				let messages = body["messages"].as_array().cloned().unwrap_or_default();
+				let prompt: Vec<&str> =
+					messages.iter().map(|m| text_of(&m["content"])).collect();
+				let system = text_of(&body["system"]);
+				let last_user = messages
+					.iter()
+					.rev()
+					.find(|m| m["role"] == "user")
+					.map(|m| text_of(&m["content"]).to_string())
+					.unwrap_or_default();
+				(
+					text_of(&body["model"]).to_string(),
+					words(system) + prompt.iter().map(|p| words(p)).sum::<u64>(),
+					last_user,
+				)
+			}
+			Format::Google => {
+				let model = request
+					.url
+					.path()
+					.rsplit('/')
+					.next()
+					.and_then(|segment| segment.split(':').next())
+					.unwrap_or_default()
+					.to_string();
+				let contents = body["contents"].as_array().cloned().unwrap_or_default();
+				let text =
+					|content: &Value| text_of(&content["parts"][0]["text"]).to_string();
+				let last_user = contents
+					.iter()
+					.rev()
+					.find(|c| c["role"] == "user")
+					.map(text)
+					.unwrap_or_default();
+				let prompt = contents.iter().map(|c| words(&text(c))).sum::<u64>()
+					+ words(text_of(&body["systemInstruction"]["parts"][0]["text"]));
+				(model, prompt, last_user)
+			}
+		};
+
+		let reply = format!("Mock reply from {}: {}", model, last_user);
+		let output = words(&reply);
+		let chunks: Vec<String> =
+			reply.split_inclusive(' ').map(str::to_string).collect();
+
+		match format {
+			Format::Anthropic => {
+				let mut events = vec![json!({
+					"type": "message_start",
+					"message": {
+						"model": model,
+						"usage": { "input_tokens": prompt, "output_tokens": 0 },
+					},
+				})];
+				events.extend(chunks.iter().map(|text| {
+					json!({
+						"type": "content_block_delta",
+						"index": 0,
+						"delta": { "type": "text_delta", "text": text },
+					})
+				}));
+				events.push(json!({
+					"type": "message_delta",
+					"delta": { "stop_reason": "end_turn" },
+					"usage": { "output_tokens": output },
+				}));
+				events.push(json!({ "type": "message_stop" }));
+				sse(events, false)
+			}
+			Format::OpenAI => {
+				let mut events: Vec<Value> = chunks
+					.iter()
+					.map(|text| {
+						json!({
+							"model": model,
+							"choices": [{ "index": 0, "delta": { "content": text } }],
+						})
+					})
+					.collect();
+				events.push(json!({
+					"model": model,
+					"choices": [],
+					"usage": { "prompt_tokens": prompt, "completion_tokens": output },
+				}));
+				sse(events, true)
+			}
+			Format::Google => {
+				let mut events: Vec<Value> = chunks
+					.iter()
+					.map(|text| {
+						json!({
+							"candidates": [{
+								"content": { "role": "model", "parts": [{ "text": text }] },
+							}],
+						})
+					})
+					.collect();
+				events.push(json!({
+					"candidates": [],
+					"usageMetadata": {
+						"promptTokenCount": prompt,
+						"candidatesTokenCount": output,
+					},
+				}));
+				sse(events, false)
+			}
+		}
+	}
+}
+
+/// Embeds each input as a small vector derived from its bytes, so equal
+/// inputs get equal vectors.
+struct MockEmbeddings;
+
+impl Respond for MockEmbeddings {
+	fn respond(&self, request: &Request) -> ResponseTemplate {
+		if api_key(Format::OpenAI, request).is_none_or(|key| key == INVALID_KEY) {
+			return unauthorized();
+		}
+		let Ok(body) = request.body_json::<Value>() else {
+			return ResponseTemplate::new(400);
+		};
+		let inputs = body["input"].as_array().cloned().unwrap_or_default();
+		let data: Vec<Value> = inputs
+			.iter()
+			.enumerate()
+			.map(|(index, input)| {
+				let text = text_of(input);
+				let sum: u64 = text.bytes().map(u64::from).sum();
+				json!({
+					"object": "embedding",
+					"index": index,
+					"embedding": [
+						text.len() as f32,
+						words(text) as f32,
+						(sum % 1000) as f32 / 1000.0,
+					],
+				})
+			})
+			.collect();
+		let tokens: u64 = inputs.iter().map(|input| words(text_of(input))).sum();
+		ResponseTemplate::new(200).set_body_json(json!({
+			"object": "list",
+			"data": data,
+			"model": body["model"],
+			"usage": { "prompt_tokens": tokens, "total_tokens": tokens },
+		}))
+	}
+}
+
+pub struct MockLlm {
+	server: MockServer,
+}
+
+impl MockLlm {
+	/// Starts the server on a random local port. It runs on its own thread,
+	/// so it keeps answering across async runtimes until dropped.
+	pub async fn start() -> Self {
+		let server = MockServer::builder().start().await;
+		let completions = [
+			("/v1/messages", Format::Anthropic),
+			("/v1/chat/completions", Format::OpenAI),
+			("/openai/v1/chat/completions", Format::OpenAI),
+			("/api/v1/chat/completions", Format::OpenAI),
+		];
+		for (endpoint, format) in completions {
+			Mock::given(method("POST"))
+				.and(path(endpoint))
+				.respond_with(MockCompletion(format))
+				.mount(&server)
+				.await;
+		}
+		Mock::given(method("POST"))
+			.and(path_regex(r"^/v1beta/models/[^/]+:streamGenerateContent$"))
+			.respond_with(MockCompletion(Format::Google))
+			.mount(&server)
+			.await;
+		Mock::given(method("POST"))
+			.and(path("/v1/embeddings"))
+			.respond_with(MockEmbeddings)
+			.mount(&server)
+			.await;
+		MockLlm { server }
+	}
+
+	pub fn uri(&self) -> String {
+		self.server.uri()
+	}
+
+	/// Points every provider client at this server by setting their
+	/// `*_BASE_URL` variables.
+	pub fn use_for_all_providers(&self) {
+		for variable in BASE_URL_VARIABLES {
+			env::set_var(variable, self.uri());
+		}
+	}
+
+	/// Requests received so far, to check what the clients sent.
+	#[cfg(test)]
+	pub async fn received_requests(&self) -> Vec<Request> {
+		self.server.received_requests().await.unwrap_or_default()
+	}
+}
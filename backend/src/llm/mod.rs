@@ -1,4 +1,10 @@
 pub mod api;
 pub mod clients;
+pub mod diff_summary;
+pub mod embeddings;
+#[cfg(any(test, feature = "mock-llm"))]
+pub mod mock;
+pub mod openai_compat;
 pub mod providers;
+pub mod replay;
 pub mod types;
@@ -0,0 +1,346 @@
+//! Wire format of `POST /v1/chat/completions` and `GET /v1/models`, which let
+//! tools built on an OpenAI SDK use the backend as their base URL.
+//!
+//! The SDK's API key is the caller's Ariana token, and the provider key comes
+//! from their vault, so requests are metered like any other inference. Models
+//! are named `provider/model` (`anthropic/claude-3-haiku-20240307`); a bare
+//! model name is routed to the provider that serves it.
+
+use crate::llm::{
+	api::{ApiError, ApiMessage, ProviderInfo},
+	providers::LLMProvider,
+};
+use actix_web::{http::StatusCode, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+/// OpenAI's default, which clients leaving it out expect.
+pub const DEFAULT_TEMPERATURE: f32 = 1.0;
+
+/// Picks a stored key by id instead of the most recent one for the provider.
+pub const KEY_ID_HEADER: &str = "X-Ariana-Key-Id";
+
+/// Sent by OpenAI SDKs when configured with an organization; names the org the
+/// request is billed to.
+pub const ORGANIZATION_HEADER: &str = "OpenAI-Organization";
+
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+	pub model: String,
+	pub messages: Vec<ChatMessage>,
+	pub temperature: Option<f32>,
+	pub max_tokens: Option<usize>,
+	/// Newer name of `max_tokens`, preferred when both are set.
+	pub max_completion_tokens: Option<usize>,
+	#[serde(default)]
+	pub stream: bool,
+	pub stream_options: Option<StreamOptions>,
+}
+
+impl ChatCompletionRequest {
+	pub fn output_limit(&self) -> Option<usize> {
+		self.max_completion_tokens.or(self.max_tokens)
+	}
+
+	/// Whether a streamed answer ends with a chunk carrying the usage.
+	pub fn include_usage(&self) -> bool {
+		self.stream_options
+			.as_ref()
+			.is_some_and(|options| options.include_usage)
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamOptions {
+	#[serde(default)]
+	pub include_usage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+	pub role: String,
+	pub content: Option<ChatContent>,
+}
+
+/// Message content, either plain text or a list of typed parts.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChatContent {
+	Text(String),
+	Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContentPart {
+	#[serde(rename = "type")]
+	pub kind: String,
+	pub text: Option<String>,
+}
+
+/// Splits a model into provider and model names. A leading provider name is
+/// taken as is, so OpenRouter models are `openrouter/openai/gpt-4o`.
+pub fn split_model(model: &str) -> Option<(String, String)> {
+	if let Some((provider, name)) = model.split_once('/') {
+		if LLMProvider::from_str(provider).is_some() {
+			return Some((provider.to_string(), name.to_string()));
+		}
+	}
+
+	let provider = if model.starts_with("claude-") {
+		LLMProvider::Anthropic
+	} else if model.starts_with("gpt-") || model.starts_with("o1") {
+		LLMProvider::OpenAI
+	} else if model.starts_with("gemini-") {
+		LLMProvider::Google
+	} else if model.starts_with("llama-") {
+		LLMProvider::Groq
+	} else {
+		return None;
+	};
+	Some((provider.to_string(), model.to_string()))
+}
+
+/// Converts messages to the internal format. Only text is supported: images,
+/// tool calls and tool results are refused rather than silently dropped.
+pub fn to_api_messages(messages: Vec<ChatMessage>) -> Result<Vec<ApiMessage>, ApiError> {
+	let unsupported = |error: String| ApiError {
+		error,
+		code: "UNSUPPORTED_CONTENT".to_string(),
+	};
+
+	messages
+		.into_iter()
+		.map(|message| {
+			let role = match message.role.as_str() {
+				"system" | "developer" => "system",
+				"user" => "user",
+				"assistant" => "assistant",
+				role => {
+					return Err(unsupported(format!(
+						"Unsupported message role: {}",
+						role
+					)))
+				}
+			};
+			let content = match message.content {
+				Some(ChatContent::Text(text)) => text,
+				Some(ChatContent::Parts(parts)) => parts
+					.into_iter()
+					.map(|part| match (part.kind.as_str(), part.text) {
+						("text", Some(text)) => Ok(text),
+						(kind, _) => Err(unsupported(format!(
+							"Unsupported content part: {}",
+							kind
+						))),
+					})
+					.collect::<Result<Vec<_>, _>>()?
+					.join("\n"),
+				None => {
+					return Err(unsupported("Messages must have content".to_string()))
+				}
+			};
+			Ok(ApiMessage {
+				role: role.to_string(),
+				content,
+			})
+		})
+		.collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletion {
+	pub id: String,
+	pub object: &'static str,
+	pub created: i64,
+	pub model: String,
+	pub choices: Vec<Choice>,
+	pub usage: CompletionUsage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Choice {
+	pub index: u32,
+	pub message: AssistantMessage,
+	pub finish_reason: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssistantMessage {
+	pub role: &'static str,
+	pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionChunk {
+	pub id: String,
+	pub object: &'static str,
+	pub created: i64,
+	pub model: String,
+	pub choices: Vec<ChunkChoice>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub usage: Option<CompletionUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkChoice {
+	pub index: u32,
+	pub delta: ChunkDelta,
+	pub finish_reason: Option<&'static str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChunkDelta {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub role: Option<&'static str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub content: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompletionUsage {
+	pub prompt_tokens: u32,
+	pub completion_tokens: u32,
+	pub total_tokens: u32,
+}
+
+impl CompletionUsage {
+	pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+		Self {
+			prompt_tokens,
+			completion_tokens,
+			total_tokens: prompt_tokens + completion_tokens,
+		}
+	}
+}
+
+/// Identifies one completion, shared by all chunks of a stream.
+pub struct Completion {
+	pub id: String,
+	pub created: i64,
+	pub model: String,
+}
+
+impl Completion {
+	pub fn new(model: &str) -> Self {
+		Self {
+			id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+			created: chrono::Utc::now().timestamp(),
+			model: model.to_string(),
+		}
+	}
+
+	pub fn response(&self, content: String, usage: CompletionUsage) -> ChatCompletion {
+		ChatCompletion {
+			id: self.id.clone(),
+			object: "chat.completion",
+			created: self.created,
+			model: self.model.clone(),
+			choices: vec![Choice {
+				index: 0,
+				message: AssistantMessage {
+					role: "assistant",
+					content,
+				},
+				finish_reason: "stop",
+			}],
+			usage,
+		}
+	}
+
+	pub fn chunk(
+		&self,
+		delta: ChunkDelta,
+		finish_reason: Option<&'static str>,
+	) -> ChatCompletionChunk {
+		ChatCompletionChunk {
+			id: self.id.clone(),
+			object: "chat.completion.chunk",
+			created: self.created,
+			model: self.model.clone(),
+			choices: vec![ChunkChoice {
+				index: 0,
+				delta,
+				finish_reason,
+			}],
+			usage: None,
+		}
+	}
+
+	/// The chunk carrying the usage when `include_usage` is set, which has no
+	/// choices.
+	pub fn usage_chunk(&self, usage: CompletionUsage) -> ChatCompletionChunk {
+		ChatCompletionChunk {
+			choices: Vec::new(),
+			usage: Some(usage),
+			..self.chunk(ChunkDelta::default(), None)
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelList {
+	pub object: &'static str,
+	pub data: Vec<Model>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Model {
+	pub id: String,
+	pub object: &'static str,
+	pub created: i64,
+	pub owned_by: String,
+}
+
+/// Every model of every provider, named `provider/model`.
+pub fn model_list(providers: Vec<ProviderInfo>) -> ModelList {
+	let data = providers
+		.into_iter()
+		.flat_map(|provider| {
+			provider.models.into_iter().map(move |model| Model {
+				id: format!("{}/{}", provider.name, model.id),
+				object: "model",
+				created: 0,
+				owned_by: provider.name.clone(),
+			})
+		})
+		.collect();
+	ModelList {
+		object: "list",
+		data,
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+	pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+	pub message: String,
+	#[serde(rename = "type")]
+	pub kind: &'static str,
+	pub code: String,
+}
+
+/// An error in the shape OpenAI SDKs parse, typed after its status.
+pub fn error_body(status: StatusCode, error: ApiError) -> ErrorResponse {
+	let kind = match status {
+		StatusCode::UNAUTHORIZED => "authentication_error",
+		StatusCode::FORBIDDEN => "permission_error",
+		StatusCode::NOT_FOUND => "not_found_error",
+		StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+		status if status.is_server_error() => "server_error",
+		_ => "invalid_request_error",
+	};
+	ErrorResponse {
+		error: ErrorBody {
+			message: error.error,
+			kind,
+			code: error.code.to_lowercase(),
+		},
+	}
+}
+
+pub fn error_response(status: StatusCode, error: ApiError) -> HttpResponse {
+	HttpResponse::build(status).json(error_body(status, error))
+}
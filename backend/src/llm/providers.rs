@@ -7,6 +7,8 @@ pub enum LLMProvider {
 	Google,
 	Groq,
 	OpenRouter,
+	/// Replays recorded responses, see `llm::replay`
+	Mock,
 }
 
 impl std::fmt::Display for LLMProvider {
@@ -17,6 +19,7 @@ impl std::fmt::Display for LLMProvider {
 			LLMProvider::Google => write!(f, "google"),
 			LLMProvider::Groq => write!(f, "groq"),
 			LLMProvider::OpenRouter => write!(f, "openrouter"),
+			LLMProvider::Mock => write!(f, "mock"),
 		}
 	}
 }
@@ -29,6 +32,7 @@ impl LLMProvider {
 			"google" => Some(LLMProvider::Google),
 			"groq" => Some(LLMProvider::Groq),
 			"openrouter" => Some(LLMProvider::OpenRouter),
+			"mock" => Some(LLMProvider::Mock),
 			_ => None,
 		}
 	}
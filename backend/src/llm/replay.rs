@@ -0,0 +1,310 @@
+//! The `mock` provider, for developing the IDE's AI features without keys or
+//! network. It replays provider streams recorded to fixture files, and
+//! answers with a canned reply when no recording matches the request.
+//!
+//! Fixtures are recorded by running the server with `LLM_RECORD=true`: every
+//! stream from a real provider is then saved under `LLM_FIXTURES_DIR`, keyed by
+//! model and messages, so asking the mock provider the same thing with the same
+//! model replays it chunk by chunk.
+
+use crate::{
+	llm::{providers::LLMProvider, types::*},
+	usage,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::BTreeSet,
+	env,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+const DEFAULT_FIXTURES_DIR: &str = "fixtures/llm";
+
+/// Replayed when no recording matches, if present in the fixtures directory.
+const DEFAULT_FIXTURE: &str = "default.json";
+
+fn flag(name: &str) -> bool {
+	env::var(name).is_ok_and(|v| matches!(v.trim(), "1" | "true" | "yes"))
+}
+
+/// Whether the `mock` provider is accepted, set with `LLM_MOCK_PROVIDER`.
+pub fn enabled() -> bool {
+	flag("LLM_MOCK_PROVIDER")
+}
+
+/// Whether real provider streams are saved as fixtures, set with `LLM_RECORD`.
+pub fn recording() -> bool {
+	flag("LLM_RECORD")
+}
+
+fn fixtures_dir() -> PathBuf {
+	env::var("LLM_FIXTURES_DIR")
+		.ok()
+		.filter(|dir| !dir.is_empty())
+		.unwrap_or_else(|| DEFAULT_FIXTURES_DIR.to_string())
+		.into()
+}
+
+/// Pause between replayed chunks, so streaming UIs can be watched at work.
+fn chunk_delay() -> Option<Duration> {
+	env::var("LLM_MOCK_CHUNK_DELAY_MS")
+		.ok()
+		.and_then(|ms| ms.parse().ok())
+		.filter(|&ms| ms > 0)
+		.map(Duration::from_millis)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FixtureMessage {
+	pub role: String,
+	pub content: String,
+}
+
+/// One recorded completion. `chunks` are the stream's deltas in order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fixture {
+	pub provider: String,
+	pub model: String,
+	#[serde(default)]
+	pub messages: Vec<FixtureMessage>,
+	pub chunks: Vec<String>,
+	#[serde(default)]
+	pub usage: LLMClientUsageStatistics,
+	pub recorded_at: Option<String>,
+}
+
+fn fixture_messages(request: &LLMClientCompletionRequest) -> Vec<FixtureMessage> {
+	request
+		.messages()
+		.iter()
+		.map(|m| FixtureMessage {
+			role: m.role().to_string(),
+			content: m.content().to_string(),
+		})
+		.collect()
+}
+
+/// File name a request is recorded under. The temperature and token limit are
+/// left out, so tweaking them doesn't invalidate recordings.
+fn fixture_name(model: &str, messages: &[FixtureMessage]) -> String {
+	let mut hasher = Sha256::new();
+	hasher.update(model.as_bytes());
+	for message in messages {
+		hasher.update([0]);
+		hasher.update(message.role.as_bytes());
+		hasher.update([0]);
+		hasher.update(message.content.as_bytes());
+	}
+	format!("{}.json", hex::encode(&hasher.finalize()[..16]))
+}
+
+async fn read_fixture(path: &Path) -> Option<Fixture> {
+	let contents = tokio::fs::read(path).await.ok()?;
+	match serde_json::from_slice(&contents) {
+		Ok(fixture) => Some(fixture),
+		Err(e) => {
+			warn!("Ignoring invalid LLM fixture {}: {}", path.display(), e);
+			None
+		}
+	}
+}
+
+/// Models with a recording in the fixtures directory, listed for the `mock`
+/// provider.
+pub fn recorded_models() -> Vec<String> {
+	let Ok(entries) = std::fs::read_dir(fixtures_dir()) else {
+		return Vec::new();
+	};
+	let models: BTreeSet<String> = entries
+		.filter_map(|entry| std::fs::read(entry.ok()?.path()).ok())
+		.filter_map(|contents| serde_json::from_slice::<Fixture>(&contents).ok())
+		.map(|fixture| fixture.model)
+		.collect();
+	models.into_iter().collect()
+}
+
+/// The reply when nothing was recorded: it names the model and quotes the
+/// last user message.
+fn canned_fixture(model: &str, request: &LLMClientCompletionRequest) -> Fixture {
+	let messages = fixture_messages(request);
+	let last_user = messages
+		.iter()
+		.rev()
+		.find(|m| m.role == "user")
+		.map(|m| m.content.as_str())
+		.unwrap_or_default();
+	let reply = format!("Mock reply from {}: {}", model, last_user);
+	let prompt: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+	let usage = LLMClientUsageStatistics::new()
+		.set_input_tokens(usage::estimate_tokens(&prompt.join("\n")))
+		.set_output_tokens(usage::estimate_tokens(&reply));
+
+	Fixture {
+		provider: LLMProvider::Mock.to_string(),
+		model: model.to_string(),
+		chunks: reply.split_inclusive(' ').map(str::to_string).collect(),
+		messages,
+		usage,
+		recorded_at: None,
+	}
+}
+
+/// Client of the `mock` provider. It needs no API key.
+pub struct MockClient {
+	fixtures_dir: PathBuf,
+}
+
+impl MockClient {
+	pub fn new() -> Self {
+		Self {
+			fixtures_dir: fixtures_dir(),
+		}
+	}
+
+	async fn find_fixture(&self, request: &LLMClientCompletionRequest) -> Fixture {
+		let model = request.model().to_string();
+		let name = fixture_name(&model, &fixture_messages(request));
+
+		if let Some(fixture) = read_fixture(&self.fixtures_dir.join(name)).await {
+			return fixture;
+		}
+		if let Some(fixture) =
+			read_fixture(&self.fixtures_dir.join(DEFAULT_FIXTURE)).await
+		{
+			return fixture;
+		}
+		canned_fixture(&model, request)
+	}
+}
+
+#[async_trait]
+impl LLMClient for MockClient {
+	async fn stream_completion(
+		&self,
+		_api_key: String,
+		request: LLMClientCompletionRequest,
+		sender: UnboundedSender<LLMClientCompletionResponse>,
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
+		let model = request.model().to_string();
+		let fixture = self.find_fixture(&request).await;
+		let delay = chunk_delay();
+
+		let mut buffered_string = String::new();
+		for chunk in fixture.chunks {
+			if let Some(delay) = delay {
+				tokio::time::sleep(delay).await;
+			}
+			buffered_string.push_str(&chunk);
+			let _ = sender.send(LLMClientCompletionResponse::new(
+				buffered_string.clone(),
+				Some(chunk),
+				model.clone(),
+			));
+		}
+
+		Ok(
+			LLMClientCompletionResponse::new(buffered_string, None, model)
+				.set_usage_statistics(fixture.usage),
+		)
+	}
+
+	async fn completion(
+		&self,
+		api_key: String,
+		request: LLMClientCompletionRequest,
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
+		let (sender, _) = mpsc::unbounded_channel();
+		self.stream_completion(api_key, request, sender).await
+	}
+}
+
+/// Wraps a real provider's client to save each successful stream as a fixture
+/// the `mock` provider can replay.
+pub struct RecordingClient {
+	provider: LLMProvider,
+	inner: Box<dyn LLMClient>,
+	fixtures_dir: PathBuf,
+}
+
+impl RecordingClient {
+	pub fn new(provider: LLMProvider, inner: Box<dyn LLMClient>) -> Self {
+		Self {
+			provider,
+			inner,
+			fixtures_dir: fixtures_dir(),
+		}
+	}
+
+	async fn save(&self, fixture: &Fixture) -> std::io::Result<PathBuf> {
+		let path = self
+			.fixtures_dir
+			.join(fixture_name(&fixture.model, &fixture.messages));
+		tokio::fs::create_dir_all(&self.fixtures_dir).await?;
+		let contents = serde_json::to_vec_pretty(fixture)?;
+		tokio::fs::write(&path, contents).await?;
+		Ok(path)
+	}
+}
+
+#[async_trait]
+impl LLMClient for RecordingClient {
+	async fn stream_completion(
+		&self,
+		api_key: String,
+		request: LLMClientCompletionRequest,
+		sender: UnboundedSender<LLMClientCompletionResponse>,
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
+		let model = request.model().to_string();
+		let messages = fixture_messages(&request);
+
+		// Deltas go through here on their way to the caller, to keep the
+		// provider's chunking
+		let (chunk_sender, mut chunk_receiver) =
+			mpsc::unbounded_channel::<LLMClientCompletionResponse>();
+		let forward = async {
+			let mut chunks = Vec::new();
+			while let Some(response) = chunk_receiver.recv().await {
+				if let Some(delta) = response.delta() {
+					chunks.push(delta.to_string());
+				}
+				let _ = sender.send(response);
+			}
+			chunks
+		};
+		let (result, chunks) = tokio::join!(
+			self.inner.stream_completion(api_key, request, chunk_sender),
+			forward
+		);
+
+		if let Ok(response) = &result {
+			let fixture = Fixture {
+				provider: self.provider.to_string(),
+				model,
+				messages,
+				chunks,
+				usage: response.usage_statistics().clone(),
+				recorded_at: Some(Utc::now().to_rfc3339()),
+			};
+			match self.save(&fixture).await {
+				Ok(path) => info!("Recorded LLM fixture {}", path.display()),
+				Err(e) => warn!("Failed to record LLM fixture: {}", e),
+			}
+		}
+		result
+	}
+
+	async fn completion(
+		&self,
+		api_key: String,
+		request: LLMClientCompletionRequest,
+	) -> Result<LLMClientCompletionResponse, LLMClientError> {
+		let (sender, _) = mpsc::unbounded_channel();
+		self.stream_completion(api_key, request, sender).await
+	}
+}
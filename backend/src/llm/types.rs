@@ -174,6 +174,21 @@ impl LLMClientUsageStatistics {
 		}
 	}
 
+	pub fn set_input_tokens(mut self, input_tokens: u32) -> Self {
+		self.input_tokens = Some(input_tokens);
+		self
+	}
+
+	pub fn set_output_tokens(mut self, output_tokens: u32) -> Self {
+		self.output_tokens = Some(output_tokens);
+		self
+	}
+
+	pub fn set_cached_input_tokens(mut self, cached_input_tokens: u32) -> Self {
+		self.cached_input_tokens = Some(cached_input_tokens);
+		self
+	}
+
 	pub fn input_tokens(&self) -> Option<u32> {
 		self.input_tokens
 	}
@@ -209,6 +224,14 @@ impl LLMClientCompletionResponse {
 		}
 	}
 
+	pub fn set_usage_statistics(
+		mut self,
+		usage_statistics: LLMClientUsageStatistics,
+	) -> Self {
+		self.usage_statistics = usage_statistics;
+		self
+	}
+
 	pub fn answer_up_until_now(&self) -> &str {
 		&self.answer_up_until_now
 	}
@@ -275,5 +298,5 @@ pub trait LLMClient: Send + Sync {
 		&self,
 		api_key: String,
 		request: LLMClientCompletionRequest,
-	) -> Result<String, LLMClientError>;
+	) -> Result<LLMClientCompletionResponse, LLMClientError>;
 }
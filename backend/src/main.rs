@@ -1,47 +1,308 @@
 use actix_cors::Cors;
 use actix_web::{
+	dev::Service,
 	get,
-	middleware::{Logger, NormalizePath},
+	middleware::NormalizePath,
 	web::{self, Data},
 	App, HttpServer, Responder,
 };
 use dotenvy::dotenv;
-use log::info;
+use log::{info, warn};
 use std::env;
+use std::time::Instant;
 
+mod admin;
+mod audit;
 mod auth;
+mod collab;
+mod crash_reports;
 mod database;
 mod email;
+mod errors;
+mod health;
 mod llm;
+mod metrics;
+mod orgs;
+mod presence;
+mod profile;
+mod projects;
+mod prompt_templates;
+mod releases;
+mod request_log;
+mod settings;
+mod telemetry;
+mod usage;
+mod vault;
+
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod tests;
 
 #[get("/ping")]
 async fn ping() -> impl Responder {
 	"pong"
 }
 
+/// Every route of the server, shared with the test harness.
+fn routes(cfg: &mut web::ServiceConfig) {
+	cfg.service(ping)
+		.service(health::healthz)
+		.service(health::readyz)
+		.service(metrics::metrics)
+		.service(
+			web::resource("/admin/migrate")
+				.route(web::get().to(admin::migration_status))
+				.route(web::post().to(admin::migrate)),
+		)
+		.service(
+			web::scope("/admin/accounts")
+				.route("", web::get().to(admin::search_accounts))
+				.route("/{account_id}", web::get().to(admin::get_account))
+				.route(
+					"/{account_id}/usage",
+					web::get().to(admin::get_account_usage),
+				)
+				.route(
+					"/{account_id}/status",
+					web::put().to(admin::set_account_status),
+				)
+				.route(
+					"/{account_id}/login-code",
+					web::post().to(admin::resend_login_code),
+				)
+				.route(
+					"/{account_id}/role",
+					web::put().to(admin::set_platform_role),
+				),
+		)
+		.service(
+			web::scope("/auth")
+				.service(auth::request_login_code)
+				.service(auth::validate_login_code)
+				.service(auth::magic_link),
+		)
+		.service(
+			web::scope("/v1")
+				.route(
+					"/chat/completions",
+					web::post().to(llm::api::chat_completions),
+				)
+				.route("/models", web::get().to(llm::api::list_models)),
+		)
+		.service(
+			web::scope("/api")
+				.route("/providers", web::get().to(llm::api::list_providers))
+				.route("/inference", web::post().to(llm::api::inference))
+				.route(
+					"/inference/stream",
+					web::post().to(llm::api::inference_stream),
+				)
+				.route(
+					"/inference/summarize-diff",
+					web::post().to(llm::api::summarize_diff),
+				)
+				.route(
+					"/inference/fanout",
+					web::post().to(llm::api::inference_fanout),
+				)
+				.route("/embeddings", web::post().to(llm::api::embeddings))
+				.route("/account/profile", web::get().to(profile::get_profile))
+				.route("/account/profile", web::put().to(profile::put_profile))
+				.route("/audit", web::get().to(audit::get_audit_log))
+				.route("/usage", web::get().to(usage::get_usage))
+				.route("/usage/limit", web::put().to(usage::set_spend_limit))
+				.route("/keys", web::get().to(vault::list_keys))
+				.route("/keys", web::post().to(vault::store_key))
+				.route("/keys/{key_id}", web::delete().to(vault::delete_key))
+				.route("/projects", web::get().to(projects::list_projects))
+				.route("/projects/sync", web::post().to(projects::sync_projects))
+				.route(
+					"/projects/{project_id}",
+					web::put().to(projects::put_project),
+				)
+				.route(
+					"/projects/{project_id}",
+					web::delete().to(projects::delete_project),
+				)
+				.route(
+					"/projects/{project_id}/locks",
+					web::get().to(presence::list_branch_locks),
+				)
+				.route(
+					"/projects/{project_id}/locks/{branch:.*}",
+					web::put().to(presence::acquire_branch_lock),
+				)
+				.route(
+					"/projects/{project_id}/locks/{branch:.*}",
+					web::delete().to(presence::release_branch_lock),
+				)
+				.route("/presence", web::get().to(presence::list_presence))
+				.route(
+					"/presence/{device_id}",
+					web::put().to(presence::put_presence),
+				)
+				.route(
+					"/presence/{device_id}",
+					web::delete().to(presence::delete_presence),
+				)
+				.route("/settings", web::get().to(settings::list_settings))
+				.route(
+					"/settings/{namespace}",
+					web::get().to(settings::get_settings),
+				)
+				.route(
+					"/settings/{namespace}",
+					web::put().to(settings::put_settings),
+				)
+				.route("/orgs", web::get().to(orgs::list_organizations))
+				.route("/orgs", web::post().to(orgs::create_organization))
+				.route("/orgs/{org_id}", web::get().to(orgs::get_organization))
+				.route(
+					"/orgs/{org_id}",
+					web::delete().to(orgs::delete_organization),
+				)
+				.route(
+					"/orgs/{org_id}/invitations",
+					web::post().to(orgs::invite_member),
+				)
+				.route(
+					"/orgs/{org_id}/invitations/{invite_id}",
+					web::delete().to(orgs::revoke_invitation),
+				)
+				.route(
+					"/orgs/{org_id}/members/{account_id}",
+					web::put().to(orgs::update_member),
+				)
+				.route(
+					"/orgs/{org_id}/members/{account_id}",
+					web::delete().to(orgs::remove_member),
+				)
+				.route("/orgs/{org_id}/keys", web::get().to(vault::list_org_keys))
+				.route("/orgs/{org_id}/keys", web::post().to(vault::store_org_key))
+				.route(
+					"/orgs/{org_id}/keys/{key_id}",
+					web::delete().to(vault::delete_org_key),
+				)
+				.route(
+					"/orgs/{org_id}/prompt-templates",
+					web::get().to(prompt_templates::list_templates),
+				)
+				.route(
+					"/orgs/{org_id}/prompt-templates",
+					web::post().to(prompt_templates::create_template),
+				)
+				.route(
+					"/orgs/{org_id}/prompt-templates/{template_id}",
+					web::put().to(prompt_templates::update_template),
+				)
+				.route(
+					"/orgs/{org_id}/prompt-templates/{template_id}",
+					web::delete().to(prompt_templates::delete_template),
+				)
+				.route(
+					"/updates/{channel}/{target}/{arch}/{current_version}",
+					web::get().to(releases::check_update),
+				)
+				.route("/releases", web::post().to(releases::publish_release))
+				.route(
+					"/releases/{channel}/{version}",
+					web::patch().to(releases::update_rollout),
+				)
+				.service(
+					web::resource("/crash-report")
+						.app_data(
+							web::JsonConfig::default()
+								.limit(crash_reports::MAX_REPORT_BYTES),
+						)
+						.route(web::post().to(crash_reports::submit_crash_report)),
+				)
+				.service(
+					web::resource("/telemetry")
+						.app_data(
+							web::JsonConfig::default().limit(telemetry::MAX_BATCH_BYTES),
+						)
+						.route(web::post().to(telemetry::submit_telemetry)),
+				)
+				.route(
+					"/email/webhooks/{provider}",
+					web::post().to(email::webhooks::delivery_webhook),
+				)
+				.route("/invitations", web::get().to(orgs::list_my_invitations))
+				.route(
+					"/invitations/{invite_id}/accept",
+					web::post().to(orgs::accept_invitation),
+				)
+				.route("/collab/rooms", web::post().to(collab::create_room))
+				.route(
+					"/collab/rooms/{room_id}/ws",
+					web::get().to(collab::join_room),
+				),
+		);
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
 	dotenv().ok();
 
 	// Initialize logging
-	fern::Dispatch::new()
-		.level(log::LevelFilter::Info)
-		.chain(std::io::stdout())
-		.apply()
-		.expect("Failed to initialize logging");
+	request_log::init_logging();
 
 	let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-	let pool = database::create_pool(&database_url)
+	let database_kind = database::DatabaseKind::from_url(&database_url)
+		.expect("DATABASE_URL must start with sqlite:, postgres: or postgresql:");
+	let pool = database::create_pool(&database_url, database_kind)
 		.await
 		.expect("Failed to create database pool");
 
-	// Run migrations
-	database::run_migrations(&pool)
+	// Run migrations, unless a rollout applies them through /admin/migrate
+	let env_flag = |name: &str, default: bool| {
+		env::var(name)
+			.map(|v| matches!(v.trim(), "1" | "true" | "yes"))
+			.unwrap_or(default)
+	};
+	if env_flag("MIGRATE_ON_STARTUP", true) {
+		database::run_migrations(
+			&pool,
+			database_kind,
+			env_flag("ALLOW_DESTRUCTIVE_MIGRATIONS", false),
+		)
 		.await
-		.expect("Failed to run migrations");
+		.unwrap_or_else(|e| panic!("Failed to run migrations: {}", e));
+	} else {
+		let pending = database::pending_migrations(&pool, database_kind)
+			.await
+			.expect("Failed to list migrations");
+		if !pending.is_empty() {
+			warn!(
+				"{} migrations are pending; apply them with POST /admin/migrate",
+				pending.len()
+			);
+		}
+	}
+
+	let email_service = email::EmailService::new(pool.clone())
+		.expect("Failed to initialize email service");
+
+	let key_vault = vault::KeyVault::new().expect("Failed to initialize key vault");
 
-	let email_service =
-		email::EmailService::new().expect("Failed to initialize email service");
+	let metrics = metrics::Metrics::new();
+
+	let collab_hub = collab::CollabHub::new();
+
+	// Kept for as long as the server runs
+	#[cfg(feature = "mock-llm")]
+	let _mock_llm = if env_flag("MOCK_LLM", false) {
+		let mock_llm = llm::mock::MockLlm::start().await;
+		mock_llm.use_for_all_providers();
+		warn!(
+			"MOCK_LLM is set: LLM requests are answered by the mock provider at {}",
+			mock_llm.uri()
+		);
+		Some(mock_llm)
+	} else {
+		None
+	};
 
 	let port = env::var("PORT")
 		.unwrap_or_else(|_| "8080".to_string())
@@ -53,30 +314,46 @@ async fn main() -> std::io::Result<()> {
 	HttpServer::new(move || {
 		App::new()
 			.app_data(Data::new(pool.clone()))
+			.app_data(Data::new(database_kind))
 			.app_data(Data::new(email_service.clone()))
+			.app_data(Data::new(key_vault.clone()))
+			.app_data(Data::new(metrics.clone()))
+			.app_data(Data::new(collab_hub.clone()))
+			.wrap_fn({
+				let metrics = metrics.clone();
+				move |req, srv| {
+					let metrics = metrics.clone();
+					let method = req.method().to_string();
+					let route = req
+						.match_pattern()
+						.unwrap_or_else(|| "unmatched".to_string());
+					let started = Instant::now();
+					let response = srv.call(req);
+					async move {
+						let response = response.await;
+						let status = match &response {
+							Ok(res) => res.status(),
+							Err(e) => e.as_response_error().status_code(),
+						};
+						metrics.record_request(
+							&method,
+							&route,
+							status.as_u16(),
+							started.elapsed(),
+						);
+						response
+					}
+				}
+			})
 			.wrap(NormalizePath::trim())
-			.wrap(Logger::default())
+			.wrap(request_log::RequestLogger)
 			.wrap(
 				Cors::default()
 					.allow_any_header()
 					.allow_any_method()
 					.allow_any_origin(),
 			)
-			.service(ping)
-			.service(
-				web::scope("/auth")
-					.service(auth::request_login_code)
-					.service(auth::validate_login_code),
-			)
-			.service(
-				web::scope("/api")
-					.route("/providers", web::get().to(llm::api::list_providers))
-					.route("/inference", web::post().to(llm::api::inference))
-					.route(
-						"/inference/stream",
-						web::post().to(llm::api::inference_stream),
-					),
-			)
+			.configure(routes)
 	})
 	.bind(("127.0.0.1", port))?
 	.run()
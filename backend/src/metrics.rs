@@ -0,0 +1,200 @@
+use crate::auth;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds (seconds) of the request latency histogram buckets. LLM calls
+/// can run for a long time, so the range goes well past typical API latencies.
+const LATENCY_BUCKETS: [f64; 12] = [
+	0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 15.0, 60.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+	buckets: [u64; LATENCY_BUCKETS.len()],
+	count: u64,
+	sum: f64,
+}
+
+impl Histogram {
+	fn observe(&mut self, seconds: f64) {
+		for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+			if seconds <= bound {
+				*bucket += 1;
+			}
+		}
+		self.count += 1;
+		self.sum += seconds;
+	}
+}
+
+#[derive(Default)]
+struct MetricsInner {
+	/// (method, route pattern, status) -> count
+	requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+	/// (method, route pattern) -> latency histogram
+	latencies: Mutex<BTreeMap<(String, String), Histogram>>,
+	/// (provider, result) -> count
+	llm_requests: Mutex<BTreeMap<(String, String), u64>>,
+	active_streams: AtomicI64,
+}
+
+/// In-process metrics registry rendered in the Prometheus text format.
+#[derive(Clone, Default)]
+pub struct Metrics {
+	inner: Arc<MetricsInner>,
+}
+
+/// Keeps the active SSE stream gauge incremented while alive.
+pub struct ActiveStreamGuard {
+	metrics: Metrics,
+}
+
+impl Drop for ActiveStreamGuard {
+	fn drop(&mut self) {
+		self.metrics
+			.inner
+			.active_streams
+			.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Records one HTTP request. `route` should be the matched route pattern
+	/// (e.g. `/api/keys/{key_id}`) rather than the raw path, to keep label
+	/// cardinality bounded.
+	pub fn record_request(
+		&self,
+		method: &str,
+		route: &str,
+		status: u16,
+		elapsed: Duration,
+	) {
+		*self
+			.inner
+			.requests
+			.lock()
+			.unwrap()
+			.entry((method.to_string(), route.to_string(), status))
+			.or_default() += 1;
+		self.inner
+			.latencies
+			.lock()
+			.unwrap()
+			.entry((method.to_string(), route.to_string()))
+			.or_default()
+			.observe(elapsed.as_secs_f64());
+	}
+
+	/// Records the outcome of a call to an LLM provider; `result` is `ok` or a
+	/// short error kind such as `rate_limited`.
+	pub fn record_llm_request(&self, provider: &str, result: &str) {
+		*self
+			.inner
+			.llm_requests
+			.lock()
+			.unwrap()
+			.entry((provider.to_string(), result.to_string()))
+			.or_default() += 1;
+	}
+
+	pub fn stream_started(&self) -> ActiveStreamGuard {
+		self.inner.active_streams.fetch_add(1, Ordering::Relaxed);
+		ActiveStreamGuard {
+			metrics: self.clone(),
+		}
+	}
+
+	pub fn render(&self) -> String {
+		let mut out = String::new();
+
+		out.push_str("# HELP http_requests_total Total HTTP requests handled.\n");
+		out.push_str("# TYPE http_requests_total counter\n");
+		for ((method, route, status), count) in self.inner.requests.lock().unwrap().iter()
+		{
+			let _ = writeln!(
+				out,
+				"http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+				method,
+				escape(route),
+				status,
+				count
+			);
+		}
+
+		out.push_str("# HELP http_request_duration_seconds Time until response headers were sent.\n");
+		out.push_str("# TYPE http_request_duration_seconds histogram\n");
+		for ((method, route), histogram) in self.inner.latencies.lock().unwrap().iter() {
+			let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+			for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+				let _ = writeln!(
+					out,
+					"http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+					labels, bound, count
+				);
+			}
+			let _ = writeln!(
+				out,
+				"http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+				labels, histogram.count
+			);
+			let _ = writeln!(
+				out,
+				"http_request_duration_seconds_sum{{{}}} {}",
+				labels, histogram.sum
+			);
+			let _ = writeln!(
+				out,
+				"http_request_duration_seconds_count{{{}}} {}",
+				labels, histogram.count
+			);
+		}
+
+		out.push_str("# HELP llm_requests_total LLM provider calls by result.\n");
+		out.push_str("# TYPE llm_requests_total counter\n");
+		for ((provider, result), count) in self.inner.llm_requests.lock().unwrap().iter()
+		{
+			let _ = writeln!(
+				out,
+				"llm_requests_total{{provider=\"{}\",result=\"{}\"}} {}",
+				provider, result, count
+			);
+		}
+
+		out.push_str("# HELP sse_streams_active Inference streams currently open.\n");
+		out.push_str("# TYPE sse_streams_active gauge\n");
+		let _ = writeln!(
+			out,
+			"sse_streams_active {}",
+			self.inner.active_streams.load(Ordering::Relaxed)
+		);
+
+		out
+	}
+}
+
+fn escape(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Prometheus scrape endpoint. When `METRICS_TOKEN` is set, scrapers must send
+/// it as a bearer token.
+#[get("/metrics")]
+pub async fn metrics(req: HttpRequest, metrics: web::Data<Metrics>) -> HttpResponse {
+	let protected = env::var("METRICS_TOKEN").is_ok_and(|token| !token.is_empty());
+	if protected && !auth::has_secret_token(&req, "METRICS_TOKEN") {
+		return HttpResponse::Unauthorized().finish();
+	}
+
+	HttpResponse::Ok()
+		.content_type("text/plain; version=0.0.4")
+		.body(metrics.render())
+}
@@ -0,0 +1,712 @@
+use crate::{
+	auth::AuthenticatedAccount,
+	database::DbPool,
+	email::{EmailService, OrgInviteEmail},
+	errors::internal_error,
+	llm::api::ApiError,
+};
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Result as ActixResult};
+use chrono::{Duration, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// How long an invitation can be accepted for.
+const INVITATION_EXPIRY_DAYS: i64 = 14;
+
+/// Roles in increasing order of privilege, so `role >= OrgRole::Admin` reads
+/// as "at least admin".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+	Member,
+	Admin,
+	Owner,
+}
+
+impl OrgRole {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			OrgRole::Member => "member",
+			OrgRole::Admin => "admin",
+			OrgRole::Owner => "owner",
+		}
+	}
+
+	pub fn from_str(s: &str) -> Option<Self> {
+		match s {
+			"member" => Some(OrgRole::Member),
+			"admin" => Some(OrgRole::Admin),
+			"owner" => Some(OrgRole::Owner),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct Organization {
+	pub org_id: String,
+	pub name: String,
+	pub role: OrgRole,
+	pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrganizationsResponse {
+	pub orgs: Vec<Organization>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Member {
+	pub account_id: String,
+	pub email: String,
+	pub role: OrgRole,
+	pub joined_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Invitation {
+	pub invite_id: String,
+	pub org_id: String,
+	pub org_name: String,
+	pub email: String,
+	pub role: OrgRole,
+	pub invited_by: String,
+	pub created_at: String,
+	pub expires_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvitationsResponse {
+	pub invitations: Vec<Invitation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrganizationDetails {
+	#[serde(flatten)]
+	pub org: Organization,
+	pub members: Vec<Member>,
+	/// Pending invitations, only listed for admins.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub invitations: Option<Vec<Invitation>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationRequest {
+	pub name: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct InviteRequest {
+	#[validate(email)]
+	pub email: String,
+	pub role: Option<OrgRole>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+	pub role: OrgRole,
+}
+
+#[derive(FromRow)]
+struct OrganizationRow {
+	org_id: String,
+	name: String,
+	role: String,
+	created_at: String,
+}
+
+impl From<OrganizationRow> for Organization {
+	fn from(row: OrganizationRow) -> Self {
+		Organization {
+			org_id: row.org_id,
+			name: row.name,
+			role: OrgRole::from_str(&row.role).unwrap_or(OrgRole::Member),
+			created_at: row.created_at,
+		}
+	}
+}
+
+#[derive(FromRow)]
+struct InvitationRow {
+	invite_id: String,
+	org_id: String,
+	org_name: String,
+	email: String,
+	role: String,
+	invited_by: String,
+	created_at: String,
+	expires_at: String,
+}
+
+impl From<InvitationRow> for Invitation {
+	fn from(row: InvitationRow) -> Self {
+		Invitation {
+			invite_id: row.invite_id,
+			org_id: row.org_id,
+			org_name: row.org_name,
+			email: row.email,
+			role: OrgRole::from_str(&row.role).unwrap_or(OrgRole::Member),
+			invited_by: row.invited_by,
+			created_at: row.created_at,
+			expires_at: row.expires_at,
+		}
+	}
+}
+
+const INVITATION_COLUMNS: &str =
+	"i.invite_id, i.org_id, o.name AS org_name, i.email, i.role,
+	 a.email AS invited_by, i.created_at, i.expires_at
+	 FROM org_invitations i
+	 JOIN organizations o ON o.org_id = i.org_id
+	 JOIN accounts a ON a.account_id = i.invited_by";
+
+fn org_not_found() -> HttpResponse {
+	HttpResponse::NotFound().json(ApiError {
+		error: "Organization not found".to_string(),
+		code: "ORG_NOT_FOUND".to_string(),
+	})
+}
+
+fn forbidden(message: &str) -> HttpResponse {
+	HttpResponse::Forbidden().json(ApiError {
+		error: message.to_string(),
+		code: "FORBIDDEN".to_string(),
+	})
+}
+
+pub async fn member_role(
+	pool: &DbPool,
+	org_id: &str,
+	account_id: &str,
+) -> Result<Option<OrgRole>, sqlx::Error> {
+	let role: Option<String> = sqlx::query_scalar(
+		"SELECT role FROM org_memberships WHERE org_id = $1 AND account_id = $2",
+	)
+	.bind(org_id)
+	.bind(account_id)
+	.fetch_optional(pool)
+	.await?;
+
+	Ok(role.as_deref().and_then(OrgRole::from_str))
+}
+
+/// Returns the caller's role in `org_id` if it is at least `minimum`.
+/// Non-members get `ORG_NOT_FOUND` so org ids can't be probed.
+pub async fn require_role(
+	pool: &DbPool,
+	org_id: &str,
+	account_id: &str,
+	minimum: OrgRole,
+) -> Result<OrgRole, HttpResponse> {
+	match member_role(pool, org_id, account_id).await {
+		Ok(Some(role)) if role >= minimum => Ok(role),
+		Ok(Some(_)) => Err(forbidden(&format!(
+			"This action requires the {} role",
+			minimum.as_str()
+		))),
+		Ok(None) => Err(org_not_found()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Err(internal_error())
+		}
+	}
+}
+
+async fn fetch_organization(
+	pool: &DbPool,
+	org_id: &str,
+	account_id: &str,
+) -> Result<Option<Organization>, sqlx::Error> {
+	let row = sqlx::query_as::<_, OrganizationRow>(
+		"SELECT o.org_id, o.name, m.role, o.created_at
+		 FROM organizations o
+		 JOIN org_memberships m ON m.org_id = o.org_id
+		 WHERE o.org_id = $1 AND m.account_id = $2",
+	)
+	.bind(org_id)
+	.bind(account_id)
+	.fetch_optional(pool)
+	.await?;
+
+	Ok(row.map(Organization::from))
+}
+
+pub async fn create_organization(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	body: web::Json<CreateOrganizationRequest>,
+) -> ActixResult<HttpResponse> {
+	let name = body.name.trim();
+	if name.is_empty() || name.len() > 128 {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: "Organization name must be 1-128 characters".to_string(),
+			code: "INVALID_ORG".to_string(),
+		}));
+	}
+
+	let org_id = Uuid::new_v4().to_string();
+	let now = Utc::now().to_rfc3339();
+
+	let create = async {
+		let mut tx = pool.begin().await?;
+		sqlx::query(
+			"INSERT INTO organizations (org_id, name, created_by, created_at)
+			 VALUES ($1, $2, $3, $4)",
+		)
+		.bind(&org_id)
+		.bind(name)
+		.bind(&account.account_id)
+		.bind(&now)
+		.execute(&mut *tx)
+		.await?;
+		sqlx::query(
+			"INSERT INTO org_memberships (org_id, account_id, role, created_at)
+			 VALUES ($1, $2, $3, $4)",
+		)
+		.bind(&org_id)
+		.bind(&account.account_id)
+		.bind(OrgRole::Owner.as_str())
+		.bind(&now)
+		.execute(&mut *tx)
+		.await?;
+		tx.commit().await
+	};
+
+	if let Err(e) = create.await {
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	Ok(HttpResponse::Created().json(Organization {
+		org_id,
+		name: name.to_string(),
+		role: OrgRole::Owner,
+		created_at: now,
+	}))
+}
+
+pub async fn list_organizations(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	match sqlx::query_as::<_, OrganizationRow>(
+		"SELECT o.org_id, o.name, m.role, o.created_at
+		 FROM organizations o
+		 JOIN org_memberships m ON m.org_id = o.org_id
+		 WHERE m.account_id = $1 ORDER BY o.name",
+	)
+	.bind(&account.account_id)
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(rows) => Ok(HttpResponse::Ok().json(OrganizationsResponse {
+			orgs: rows.into_iter().map(Organization::from).collect(),
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+pub async fn get_organization(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let org_id = path.into_inner();
+
+	let details = async {
+		let Some(org) =
+			fetch_organization(pool.get_ref(), &org_id, &account.account_id).await?
+		else {
+			return Ok(None);
+		};
+
+		let members: Vec<(String, String, String, String)> = sqlx::query_as(
+			"SELECT m.account_id, a.email, m.role, m.created_at
+			 FROM org_memberships m
+			 JOIN accounts a ON a.account_id = m.account_id
+			 WHERE m.org_id = $1 ORDER BY m.created_at",
+		)
+		.bind(&org_id)
+		.fetch_all(pool.get_ref())
+		.await?;
+
+		let invitations = if org.role >= OrgRole::Admin {
+			let rows = sqlx::query_as::<_, InvitationRow>(&format!(
+				"SELECT {} WHERE i.org_id = $1 ORDER BY i.created_at",
+				INVITATION_COLUMNS
+			))
+			.bind(&org_id)
+			.fetch_all(pool.get_ref())
+			.await?;
+			Some(rows.into_iter().map(Invitation::from).collect())
+		} else {
+			None
+		};
+
+		Ok::<_, sqlx::Error>(Some(OrganizationDetails {
+			org,
+			members: members
+				.into_iter()
+				.map(|(account_id, email, role, joined_at)| Member {
+					account_id,
+					email,
+					role: OrgRole::from_str(&role).unwrap_or(OrgRole::Member),
+					joined_at,
+				})
+				.collect(),
+			invitations,
+		}))
+	};
+
+	match details.await {
+		Ok(Some(details)) => Ok(HttpResponse::Ok().json(details)),
+		Ok(None) => Ok(org_not_found()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+pub async fn delete_organization(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let org_id = path.into_inner();
+	if let Err(response) =
+		require_role(pool.get_ref(), &org_id, &account.account_id, OrgRole::Owner).await
+	{
+		return Ok(response);
+	}
+
+	if let Err(e) = sqlx::query("DELETE FROM organizations WHERE org_id = $1")
+		.bind(&org_id)
+		.execute(pool.get_ref())
+		.await
+	{
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Invites an email address to the org and emails them. Re-inviting the same
+/// address replaces the pending invitation.
+pub async fn invite_member(
+	pool: web::Data<DbPool>,
+	email_service: web::Data<EmailService>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+	http_req: HttpRequest,
+	body: web::Json<InviteRequest>,
+) -> ActixResult<HttpResponse> {
+	let org_id = path.into_inner();
+	let inviter_role =
+		match require_role(pool.get_ref(), &org_id, &account.account_id, OrgRole::Admin)
+			.await
+		{
+			Ok(role) => role,
+			Err(response) => return Ok(response),
+		};
+
+	if let Err(e) = body.validate() {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: format!("Invalid email: {}", e),
+			code: "INVALID_EMAIL".to_string(),
+		}));
+	}
+
+	let role = body.role.unwrap_or(OrgRole::Member);
+	if role == OrgRole::Owner || role > inviter_role {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: "Invitations can grant at most your own role, and never owner"
+				.to_string(),
+			code: "INVALID_ROLE".to_string(),
+		}));
+	}
+
+	let email = body.email.trim().to_lowercase();
+	let invite_id = Uuid::new_v4().to_string();
+	let now = Utc::now();
+	let expires_at = now + Duration::days(INVITATION_EXPIRY_DAYS);
+
+	let org_name = async {
+		sqlx::query(
+			"INSERT INTO org_invitations
+			 (invite_id, org_id, email, role, invited_by, created_at, expires_at)
+			 VALUES ($1, $2, $3, $4, $5, $6, $7)
+			 ON CONFLICT (org_id, email) DO UPDATE
+			 SET invite_id = excluded.invite_id, role = excluded.role,
+			     invited_by = excluded.invited_by, created_at = excluded.created_at,
+			     expires_at = excluded.expires_at",
+		)
+		.bind(&invite_id)
+		.bind(&org_id)
+		.bind(&email)
+		.bind(role.as_str())
+		.bind(&account.account_id)
+		.bind(now.to_rfc3339())
+		.bind(expires_at.to_rfc3339())
+		.execute(pool.get_ref())
+		.await?;
+
+		sqlx::query_scalar::<_, String>(
+			"SELECT name FROM organizations WHERE org_id = $1",
+		)
+		.bind(&org_id)
+		.fetch_one(pool.get_ref())
+		.await
+	};
+
+	let org_name = match org_name.await {
+		Ok(name) => name,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	let accept_language = http_req
+		.headers()
+		.get(header::ACCEPT_LANGUAGE)
+		.and_then(|v| v.to_str().ok());
+	let invite_email = OrgInviteEmail {
+		org_name: &org_name,
+		inviter_email: &account.email,
+		role: role.as_str(),
+		expiry_days: INVITATION_EXPIRY_DAYS,
+	};
+	if let Err(e) = email_service
+		.send_org_invite_email(&email, &invite_email, accept_language)
+		.await
+	{
+		// The invitation stays valid; the invitee can still see it in the IDE
+		error!("Failed to send invitation email: {}", e);
+	}
+
+	Ok(HttpResponse::Created().json(Invitation {
+		invite_id,
+		org_id,
+		org_name,
+		email,
+		role,
+		invited_by: account.email,
+		created_at: now.to_rfc3339(),
+		expires_at: expires_at.to_rfc3339(),
+	}))
+}
+
+pub async fn revoke_invitation(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<(String, String)>,
+) -> ActixResult<HttpResponse> {
+	let (org_id, invite_id) = path.into_inner();
+	if let Err(response) =
+		require_role(pool.get_ref(), &org_id, &account.account_id, OrgRole::Admin).await
+	{
+		return Ok(response);
+	}
+
+	match sqlx::query("DELETE FROM org_invitations WHERE org_id = $1 AND invite_id = $2")
+		.bind(&org_id)
+		.bind(&invite_id)
+		.execute(pool.get_ref())
+		.await
+	{
+		Ok(result) if result.rows_affected() == 0 => Ok(invitation_not_found()),
+		Ok(_) => Ok(HttpResponse::NoContent().finish()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+fn invitation_not_found() -> HttpResponse {
+	HttpResponse::NotFound().json(ApiError {
+		error: "Invitation not found or expired".to_string(),
+		code: "INVITATION_NOT_FOUND".to_string(),
+	})
+}
+
+/// Pending, unexpired invitations addressed to the caller's email.
+pub async fn list_my_invitations(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	match sqlx::query_as::<_, InvitationRow>(&format!(
+		"SELECT {} WHERE i.email = $1 AND i.expires_at > $2 ORDER BY i.created_at",
+		INVITATION_COLUMNS
+	))
+	.bind(account.email.to_lowercase())
+	.bind(Utc::now().to_rfc3339())
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(rows) => Ok(HttpResponse::Ok().json(InvitationsResponse {
+			invitations: rows.into_iter().map(Invitation::from).collect(),
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+pub async fn accept_invitation(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let invite_id = path.into_inner();
+
+	let accept = async {
+		let mut tx = pool.begin().await?;
+		let invite: Option<(String, String)> = sqlx::query_as(
+			"SELECT org_id, role FROM org_invitations
+			 WHERE invite_id = $1 AND email = $2 AND expires_at > $3",
+		)
+		.bind(&invite_id)
+		.bind(account.email.to_lowercase())
+		.bind(Utc::now().to_rfc3339())
+		.fetch_optional(&mut *tx)
+		.await?;
+		let Some((org_id, role)) = invite else {
+			return Ok(None);
+		};
+
+		// Joining never downgrades an existing membership
+		sqlx::query(
+			"INSERT INTO org_memberships (org_id, account_id, role, created_at)
+			 VALUES ($1, $2, $3, $4)
+			 ON CONFLICT (org_id, account_id) DO NOTHING",
+		)
+		.bind(&org_id)
+		.bind(&account.account_id)
+		.bind(&role)
+		.bind(Utc::now().to_rfc3339())
+		.execute(&mut *tx)
+		.await?;
+		sqlx::query("DELETE FROM org_invitations WHERE invite_id = $1")
+			.bind(&invite_id)
+			.execute(&mut *tx)
+			.await?;
+		tx.commit().await?;
+
+		fetch_organization(pool.get_ref(), &org_id, &account.account_id).await
+	};
+
+	match accept.await {
+		Ok(Some(org)) => Ok(HttpResponse::Ok().json(org)),
+		Ok(None) => Ok(invitation_not_found()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+fn member_not_found() -> HttpResponse {
+	HttpResponse::NotFound().json(ApiError {
+		error: "Member not found".to_string(),
+		code: "MEMBER_NOT_FOUND".to_string(),
+	})
+}
+
+/// Changes a member between `admin` and `member`. Owner only.
+pub async fn update_member(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<(String, String)>,
+	body: web::Json<UpdateMemberRequest>,
+) -> ActixResult<HttpResponse> {
+	let (org_id, member_id) = path.into_inner();
+	if let Err(response) =
+		require_role(pool.get_ref(), &org_id, &account.account_id, OrgRole::Owner).await
+	{
+		return Ok(response);
+	}
+
+	if body.role == OrgRole::Owner || member_id == account.account_id {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: "Ownership can't be transferred or given up".to_string(),
+			code: "INVALID_ROLE".to_string(),
+		}));
+	}
+
+	match sqlx::query(
+		"UPDATE org_memberships SET role = $1 WHERE org_id = $2 AND account_id = $3",
+	)
+	.bind(body.role.as_str())
+	.bind(&org_id)
+	.bind(&member_id)
+	.execute(pool.get_ref())
+	.await
+	{
+		Ok(result) if result.rows_affected() == 0 => Ok(member_not_found()),
+		Ok(_) => Ok(HttpResponse::NoContent().finish()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Removes a member. Admins can remove members and other admins; anyone but
+/// the owner can remove themselves to leave the org.
+pub async fn remove_member(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<(String, String)>,
+) -> ActixResult<HttpResponse> {
+	let (org_id, member_id) = path.into_inner();
+	let leaving = member_id == account.account_id;
+	let minimum = if leaving {
+		OrgRole::Member
+	} else {
+		OrgRole::Admin
+	};
+	if let Err(response) =
+		require_role(pool.get_ref(), &org_id, &account.account_id, minimum).await
+	{
+		return Ok(response);
+	}
+
+	match member_role(pool.get_ref(), &org_id, &member_id).await {
+		Ok(Some(OrgRole::Owner)) => {
+			return Ok(HttpResponse::BadRequest().json(ApiError {
+				error: "The owner can't be removed; delete the organization instead"
+					.to_string(),
+				code: "CANNOT_REMOVE_OWNER".to_string(),
+			}))
+		}
+		Ok(Some(_)) => {}
+		Ok(None) => return Ok(member_not_found()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	}
+
+	if let Err(e) =
+		sqlx::query("DELETE FROM org_memberships WHERE org_id = $1 AND account_id = $2")
+			.bind(&org_id)
+			.bind(&member_id)
+			.execute(pool.get_ref())
+			.await
+	{
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
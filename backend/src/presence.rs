@@ -0,0 +1,434 @@
+use crate::{
+	auth::AuthenticatedAccount, database::DbPool, errors::internal_error,
+	llm::api::ApiError,
+};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use chrono::{Duration, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Presence not refreshed for this long is treated as gone; clients send a
+/// heartbeat well within it.
+const PRESENCE_TTL_SECONDS: i64 = 120;
+
+/// Most projects and canvases a device can report as open at once.
+const MAX_OPEN_ENTRIES: usize = 200;
+
+const DEFAULT_LOCK_TTL_SECONDS: i64 = 5 * 60;
+const MIN_LOCK_TTL_SECONDS: i64 = 30;
+const MAX_LOCK_TTL_SECONDS: i64 = 60 * 60;
+
+#[derive(FromRow)]
+struct PresenceRow {
+	device_id: String,
+	device_name: String,
+	project_id: String,
+	canvas_id: String,
+	updated_at: String,
+}
+
+/// A project, or one of its canvases, open on one of the account's devices.
+#[derive(Debug, Serialize)]
+pub struct PresenceEntry {
+	pub device_id: String,
+	pub device_name: String,
+	pub project_id: String,
+	pub canvas_id: Option<String>,
+	pub updated_at: String,
+}
+
+impl From<PresenceRow> for PresenceEntry {
+	fn from(row: PresenceRow) -> Self {
+		PresenceEntry {
+			device_id: row.device_id,
+			device_name: row.device_name,
+			project_id: row.project_id,
+			canvas_id: Some(row.canvas_id).filter(|id| !id.is_empty()),
+			updated_at: row.updated_at,
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceResponse {
+	pub presence: Vec<PresenceEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenEntry {
+	pub project_id: String,
+	pub canvas_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutPresenceRequest {
+	pub device_name: String,
+	/// Everything open on the device; replaces what it reported before.
+	pub open: Vec<OpenEntry>,
+}
+
+/// An advisory lock on a canvas branch, so two devices of the same account
+/// don't run agents on the same branch at once. Locks expire unless
+/// refreshed by their holder.
+#[derive(Debug, Serialize, FromRow)]
+pub struct BranchLock {
+	pub project_id: String,
+	pub branch: String,
+	pub device_id: String,
+	pub device_name: String,
+	pub canvas_id: Option<String>,
+	pub acquired_at: String,
+	pub expires_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BranchLocksResponse {
+	pub locks: Vec<BranchLock>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BranchLockConflict {
+	pub error: String,
+	pub code: String,
+	pub holder: BranchLock,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcquireLockRequest {
+	pub device_id: String,
+	pub device_name: String,
+	pub canvas_id: Option<String>,
+	/// Defaults to 5 minutes, clamped to between 30 seconds and an hour.
+	pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseLockQuery {
+	pub device_id: String,
+	/// Releases the lock even when another device holds it.
+	#[serde(default)]
+	pub force: bool,
+}
+
+fn invalid_request(message: &str) -> ApiError {
+	ApiError {
+		error: message.to_string(),
+		code: "INVALID_REQUEST".to_string(),
+	}
+}
+
+fn check_length(value: &str, field: &str, max: usize) -> Result<(), ApiError> {
+	if value.trim().is_empty() || value.len() > max {
+		return Err(invalid_request(&format!(
+			"{} must be 1-{} characters",
+			field, max
+		)));
+	}
+	Ok(())
+}
+
+async fn fetch_presence(
+	pool: &DbPool,
+	account_id: &str,
+) -> Result<Vec<PresenceEntry>, sqlx::Error> {
+	let cutoff = (Utc::now() - Duration::seconds(PRESENCE_TTL_SECONDS)).to_rfc3339();
+	let rows = sqlx::query_as::<_, PresenceRow>(
+		"SELECT device_id, device_name, project_id, canvas_id, updated_at FROM presence
+		 WHERE account_id = $1 AND updated_at > $2
+		 ORDER BY device_name, project_id, canvas_id",
+	)
+	.bind(account_id)
+	.bind(&cutoff)
+	.fetch_all(pool)
+	.await?;
+	Ok(rows.into_iter().map(PresenceEntry::from).collect())
+}
+
+/// Lists what is open on the account's devices, including the caller's.
+pub async fn list_presence(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	match fetch_presence(pool.get_ref(), &account.account_id).await {
+		Ok(presence) => Ok(HttpResponse::Ok().json(PresenceResponse { presence })),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Replaces what a device has open and refreshes its heartbeat, then returns
+/// the presence of all the account's devices.
+pub async fn put_presence(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+	body: web::Json<PutPresenceRequest>,
+) -> ActixResult<HttpResponse> {
+	let device_id = path.into_inner();
+	let request = body.into_inner();
+	let validation = check_length(&device_id, "device_id", 128)
+		.and_then(|_| check_length(&request.device_name, "device_name", 256))
+		.and_then(|_| {
+			if request.open.len() > MAX_OPEN_ENTRIES {
+				return Err(invalid_request(&format!(
+					"At most {} open entries can be reported",
+					MAX_OPEN_ENTRIES
+				)));
+			}
+			for entry in &request.open {
+				check_length(&entry.project_id, "project_id", 128)?;
+				if let Some(canvas_id) = &entry.canvas_id {
+					check_length(canvas_id, "canvas_id", 128)?;
+				}
+			}
+			Ok(())
+		});
+	if let Err(e) = validation {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+
+	let now = Utc::now();
+	let cutoff = (now - Duration::seconds(PRESENCE_TTL_SECONDS)).to_rfc3339();
+	let now = now.to_rfc3339();
+	let write = async {
+		let mut tx = pool.begin().await?;
+		sqlx::query(
+			"DELETE FROM presence
+			 WHERE account_id = $1 AND (device_id = $2 OR updated_at <= $3)",
+		)
+		.bind(&account.account_id)
+		.bind(&device_id)
+		.bind(&cutoff)
+		.execute(&mut *tx)
+		.await?;
+		for entry in &request.open {
+			sqlx::query(
+				"INSERT INTO presence
+				 (account_id, device_id, device_name, project_id, canvas_id, updated_at)
+				 VALUES ($1, $2, $3, $4, $5, $6)
+				 ON CONFLICT (account_id, device_id, project_id, canvas_id) DO NOTHING",
+			)
+			.bind(&account.account_id)
+			.bind(&device_id)
+			.bind(request.device_name.trim())
+			.bind(&entry.project_id)
+			.bind(entry.canvas_id.as_deref().unwrap_or(""))
+			.bind(&now)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await
+	};
+
+	if let Err(e) = write.await {
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	list_presence(pool, account).await
+}
+
+/// Clears a device's presence, e.g. when the app quits.
+pub async fn delete_presence(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let device_id = path.into_inner();
+	match sqlx::query("DELETE FROM presence WHERE account_id = $1 AND device_id = $2")
+		.bind(&account.account_id)
+		.bind(&device_id)
+		.execute(pool.get_ref())
+		.await
+	{
+		Ok(_) => Ok(HttpResponse::NoContent().finish()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+async fn fetch_lock(
+	pool: &DbPool,
+	account_id: &str,
+	project_id: &str,
+	branch: &str,
+) -> Result<Option<BranchLock>, sqlx::Error> {
+	sqlx::query_as::<_, BranchLock>(
+		"SELECT project_id, branch, device_id, device_name, canvas_id, acquired_at,
+		 expires_at FROM branch_locks
+		 WHERE account_id = $1 AND project_id = $2 AND branch = $3",
+	)
+	.bind(account_id)
+	.bind(project_id)
+	.bind(branch)
+	.fetch_optional(pool)
+	.await
+}
+
+/// Lists the unexpired branch locks of a project.
+pub async fn list_branch_locks(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let project_id = path.into_inner();
+	match sqlx::query_as::<_, BranchLock>(
+		"SELECT project_id, branch, device_id, device_name, canvas_id, acquired_at,
+		 expires_at FROM branch_locks
+		 WHERE account_id = $1 AND project_id = $2 AND expires_at > $3
+		 ORDER BY branch",
+	)
+	.bind(&account.account_id)
+	.bind(&project_id)
+	.bind(Utc::now().to_rfc3339())
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(locks) => Ok(HttpResponse::Ok().json(BranchLocksResponse { locks })),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Takes or refreshes the lock on a branch. Succeeds when the branch is
+/// free, its lock expired, or the calling device already holds it; answers
+/// 409 with the holder otherwise.
+pub async fn acquire_branch_lock(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<(String, String)>,
+	body: web::Json<AcquireLockRequest>,
+) -> ActixResult<HttpResponse> {
+	let (project_id, branch) = path.into_inner();
+	let request = body.into_inner();
+	let validation = check_length(&project_id, "project_id", 128)
+		.and_then(|_| check_length(&branch, "branch", 255))
+		.and_then(|_| check_length(&request.device_id, "device_id", 128))
+		.and_then(|_| check_length(&request.device_name, "device_name", 256));
+	if let Err(e) = validation {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+
+	let ttl = request
+		.ttl_seconds
+		.unwrap_or(DEFAULT_LOCK_TTL_SECONDS)
+		.clamp(MIN_LOCK_TTL_SECONDS, MAX_LOCK_TTL_SECONDS);
+	let now = Utc::now();
+	let expires_at = (now + Duration::seconds(ttl)).to_rfc3339();
+	let now = now.to_rfc3339();
+
+	// The update only applies when the lock is ours or has expired, so a
+	// live lock of another device is left untouched and nothing is affected.
+	let result = sqlx::query(
+		"INSERT INTO branch_locks
+		 (account_id, project_id, branch, device_id, device_name, canvas_id,
+		  acquired_at, expires_at)
+		 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+		 ON CONFLICT (account_id, project_id, branch) DO UPDATE
+		 SET acquired_at = CASE
+		         WHEN branch_locks.device_id = excluded.device_id
+		              AND branch_locks.expires_at > excluded.acquired_at
+		         THEN branch_locks.acquired_at
+		         ELSE excluded.acquired_at
+		     END,
+		     device_id = excluded.device_id,
+		     device_name = excluded.device_name,
+		     canvas_id = excluded.canvas_id,
+		     expires_at = excluded.expires_at
+		 WHERE branch_locks.device_id = excluded.device_id
+		    OR branch_locks.expires_at <= excluded.acquired_at",
+	)
+	.bind(&account.account_id)
+	.bind(&project_id)
+	.bind(&branch)
+	.bind(&request.device_id)
+	.bind(request.device_name.trim())
+	.bind(request.canvas_id.as_deref())
+	.bind(&now)
+	.bind(&expires_at)
+	.execute(pool.get_ref())
+	.await;
+
+	let acquired = match result {
+		Ok(result) => result.rows_affected() > 0,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	match fetch_lock(pool.get_ref(), &account.account_id, &project_id, &branch).await {
+		Ok(Some(lock)) if acquired => Ok(HttpResponse::Ok().json(lock)),
+		Ok(Some(holder)) => Ok(HttpResponse::Conflict().json(BranchLockConflict {
+			error: format!("Branch is locked by {}", holder.device_name),
+			code: "BRANCH_LOCKED".to_string(),
+			holder,
+		})),
+		Ok(None) => Ok(internal_error()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Releases a branch lock. Only its holder can release a live lock unless
+/// `force` is set.
+pub async fn release_branch_lock(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<(String, String)>,
+	query: web::Query<ReleaseLockQuery>,
+) -> ActixResult<HttpResponse> {
+	let (project_id, branch) = path.into_inner();
+
+	let lock = match fetch_lock(pool.get_ref(), &account.account_id, &project_id, &branch)
+		.await
+	{
+		Ok(Some(lock)) => lock,
+		Ok(None) => {
+			return Ok(HttpResponse::NotFound().json(ApiError {
+				error: "Branch is not locked".to_string(),
+				code: "LOCK_NOT_FOUND".to_string(),
+			}))
+		}
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	let expired = lock.expires_at <= Utc::now().to_rfc3339();
+	if lock.device_id != query.device_id && !expired && !query.force {
+		return Ok(HttpResponse::Conflict().json(BranchLockConflict {
+			error: format!("Branch is locked by {}", lock.device_name),
+			code: "BRANCH_LOCKED".to_string(),
+			holder: lock,
+		}));
+	}
+
+	match sqlx::query(
+		"DELETE FROM branch_locks
+		 WHERE account_id = $1 AND project_id = $2 AND branch = $3 AND device_id = $4",
+	)
+	.bind(&account.account_id)
+	.bind(&project_id)
+	.bind(&branch)
+	.bind(&lock.device_id)
+	.execute(pool.get_ref())
+	.await
+	{
+		Ok(_) => Ok(HttpResponse::NoContent().finish()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
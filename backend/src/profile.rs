@@ -0,0 +1,279 @@
+use crate::{
+	auth::AuthenticatedAccount, database::DbPool, errors::internal_error,
+	llm::api::ApiError,
+};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+const MAX_DISPLAY_NAME_LEN: usize = 64;
+const MAX_AVATAR_URL_LEN: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestFrequency {
+	Off,
+	Daily,
+	Weekly,
+}
+
+/// Stored as JSON, so new preferences only need a default here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationPreferences {
+	pub digest: DigestFrequency,
+	/// Local hour, in the account's timezone, digests are sent at.
+	pub digest_hour: u32,
+	/// Day weekly digests are sent on, like `Mon`.
+	pub digest_weekday: Weekday,
+	pub product_updates: bool,
+	pub security_alerts: bool,
+}
+
+impl Default for NotificationPreferences {
+	fn default() -> Self {
+		NotificationPreferences {
+			digest: DigestFrequency::Off,
+			digest_hour: 9,
+			digest_weekday: Weekday::Mon,
+			product_updates: false,
+			security_alerts: true,
+		}
+	}
+}
+
+/// What the IDE shows for an account besides its email. Accounts that never
+/// set one get the defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountProfile {
+	pub display_name: Option<String>,
+	pub avatar_url: Option<String>,
+	/// IANA name, like `Europe/Paris`.
+	pub timezone: String,
+	pub notifications: NotificationPreferences,
+	/// When the next digest email is due, none while digests are off.
+	pub next_digest_at: Option<String>,
+	pub updated_at: Option<String>,
+}
+
+impl Default for AccountProfile {
+	fn default() -> Self {
+		AccountProfile {
+			display_name: None,
+			avatar_url: None,
+			timezone: "UTC".to_string(),
+			notifications: NotificationPreferences::default(),
+			next_digest_at: None,
+			updated_at: None,
+		}
+	}
+}
+
+/// Replaces the whole profile; omitted fields go back to their defaults.
+#[derive(Debug, Deserialize)]
+pub struct PutProfileRequest {
+	pub display_name: Option<String>,
+	pub avatar_url: Option<String>,
+	pub timezone: Option<String>,
+	#[serde(default)]
+	pub notifications: NotificationPreferences,
+}
+
+#[derive(FromRow)]
+struct ProfileRow {
+	display_name: Option<String>,
+	avatar_url: Option<String>,
+	timezone: String,
+	/// `NotificationPreferences` as JSON.
+	notifications: String,
+	updated_at: String,
+}
+
+fn invalid_profile(message: &str) -> ApiError {
+	ApiError {
+		error: message.to_string(),
+		code: "INVALID_PROFILE".to_string(),
+	}
+}
+
+/// The first digest time after `now`, at the preferred local hour and, for
+/// weekly digests, weekday. A local time skipped by a DST change moves to
+/// the next hour.
+fn next_digest_at(
+	timezone: Tz,
+	notifications: &NotificationPreferences,
+	now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+	if notifications.digest == DigestFrequency::Off {
+		return None;
+	}
+	let time = NaiveTime::from_hms_opt(notifications.digest_hour, 0, 0)?;
+	let today = now.with_timezone(&timezone).date_naive();
+	(0..=7)
+		.map(|days| today + Duration::days(days))
+		.filter(|date| {
+			notifications.digest == DigestFrequency::Daily
+				|| date.weekday() == notifications.digest_weekday
+		})
+		.filter_map(|date| {
+			let local = date.and_time(time);
+			timezone.from_local_datetime(&local).earliest().or_else(|| {
+				timezone
+					.from_local_datetime(&(local + Duration::hours(1)))
+					.earliest()
+			})
+		})
+		.map(|at| at.with_timezone(&Utc))
+		.find(|at| *at > now)
+}
+
+fn validate(request: PutProfileRequest) -> Result<AccountProfile, ApiError> {
+	let display_name = request
+		.display_name
+		.map(|name| name.trim().to_string())
+		.filter(|name| !name.is_empty());
+	if let Some(name) = &display_name {
+		if name.chars().count() > MAX_DISPLAY_NAME_LEN {
+			return Err(invalid_profile(&format!(
+				"Display name exceeds {} characters",
+				MAX_DISPLAY_NAME_LEN
+			)));
+		}
+		if name.chars().any(char::is_control) {
+			return Err(invalid_profile("Display name contains control characters"));
+		}
+	}
+
+	let avatar_url = request.avatar_url.filter(|url| !url.is_empty());
+	if let Some(url) = &avatar_url {
+		let valid = url.len() <= MAX_AVATAR_URL_LEN
+			&& reqwest::Url::parse(url).is_ok_and(|url| url.scheme() == "https");
+		if !valid {
+			return Err(invalid_profile(&format!(
+				"Avatar must be an https URL of at most {} bytes",
+				MAX_AVATAR_URL_LEN
+			)));
+		}
+	}
+
+	let timezone = request.timezone.unwrap_or_else(|| "UTC".to_string());
+	if timezone.parse::<Tz>().is_err() {
+		return Err(invalid_profile(&format!("Unknown timezone `{}`", timezone)));
+	}
+	if request.notifications.digest_hour > 23 {
+		return Err(invalid_profile("digest_hour must be between 0 and 23"));
+	}
+
+	Ok(AccountProfile {
+		display_name,
+		avatar_url,
+		timezone,
+		notifications: request.notifications,
+		next_digest_at: None,
+		updated_at: None,
+	})
+}
+
+/// Fills in `next_digest_at`, which depends on the current time.
+fn with_schedule(mut profile: AccountProfile) -> AccountProfile {
+	let timezone = profile.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+	profile.next_digest_at = next_digest_at(timezone, &profile.notifications, Utc::now())
+		.map(|at| at.to_rfc3339());
+	profile
+}
+
+pub async fn load_profile(
+	pool: &DbPool,
+	account_id: &str,
+) -> Result<AccountProfile, sqlx::Error> {
+	let row = sqlx::query_as::<_, ProfileRow>(
+		"SELECT display_name, avatar_url, timezone, notifications, updated_at
+		 FROM account_profiles WHERE account_id = $1",
+	)
+	.bind(account_id)
+	.fetch_optional(pool)
+	.await?;
+
+	let profile = match row {
+		Some(row) => AccountProfile {
+			display_name: row.display_name,
+			avatar_url: row.avatar_url,
+			timezone: row.timezone,
+			notifications: serde_json::from_str(&row.notifications).unwrap_or_default(),
+			next_digest_at: None,
+			updated_at: Some(row.updated_at),
+		},
+		None => AccountProfile::default(),
+	};
+	Ok(with_schedule(profile))
+}
+
+/// `GET /api/account/profile`
+pub async fn get_profile(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	match load_profile(pool.get_ref(), &account.account_id).await {
+		Ok(profile) => Ok(HttpResponse::Ok().json(profile)),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Sets the display name, avatar, timezone and notification preferences.
+///
+/// `PUT /api/account/profile`
+pub async fn put_profile(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	body: web::Json<PutProfileRequest>,
+) -> ActixResult<HttpResponse> {
+	let mut profile = match validate(body.into_inner()) {
+		Ok(profile) => profile,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+	let notifications = match serde_json::to_string(&profile.notifications) {
+		Ok(notifications) => notifications,
+		Err(e) => {
+			error!("Failed to serialize notification preferences: {}", e);
+			return Ok(internal_error());
+		}
+	};
+	let now = Utc::now().to_rfc3339();
+
+	let result = sqlx::query(
+		"INSERT INTO account_profiles
+		 (account_id, display_name, avatar_url, timezone, notifications, updated_at)
+		 VALUES ($1, $2, $3, $4, $5, $6)
+		 ON CONFLICT (account_id) DO UPDATE
+		 SET display_name = excluded.display_name,
+		     avatar_url = excluded.avatar_url,
+		     timezone = excluded.timezone,
+		     notifications = excluded.notifications,
+		     updated_at = excluded.updated_at",
+	)
+	.bind(&account.account_id)
+	.bind(&profile.display_name)
+	.bind(&profile.avatar_url)
+	.bind(&profile.timezone)
+	.bind(&notifications)
+	.bind(&now)
+	.execute(pool.get_ref())
+	.await;
+
+	match result {
+		Ok(_) => {
+			profile.updated_at = Some(now);
+			Ok(HttpResponse::Ok().json(with_schedule(profile)))
+		}
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
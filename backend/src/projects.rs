@@ -0,0 +1,253 @@
+use crate::{
+	auth::AuthenticatedAccount, database::DbPool, errors::internal_error,
+	llm::api::ApiError,
+};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::{Any, Executor, FromRow};
+
+/// Most projects accepted in a single sync request.
+const MAX_SYNC_PROJECTS: usize = 500;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Project {
+	pub project_id: String,
+	pub name: String,
+	pub repo_url: Option<String>,
+	pub last_opened_at: String,
+	pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectsResponse {
+	pub projects: Vec<Project>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectUpdate {
+	pub name: String,
+	pub repo_url: Option<String>,
+	/// RFC 3339, defaults to now.
+	pub last_opened_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncProjectEntry {
+	pub project_id: String,
+	#[serde(flatten)]
+	pub update: ProjectUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncProjectsRequest {
+	pub projects: Vec<SyncProjectEntry>,
+}
+
+fn invalid_project(message: &str) -> ApiError {
+	ApiError {
+		error: message.to_string(),
+		code: "INVALID_PROJECT".to_string(),
+	}
+}
+
+/// Checks an update and normalizes its timestamp to RFC 3339 UTC.
+fn validate_update(project_id: &str, update: &ProjectUpdate) -> Result<String, ApiError> {
+	if project_id.is_empty() || project_id.len() > 128 {
+		return Err(invalid_project("Project id must be 1-128 characters"));
+	}
+	if update.name.trim().is_empty() || update.name.len() > 256 {
+		return Err(invalid_project("Project name must be 1-256 characters"));
+	}
+	match &update.last_opened_at {
+		None => Ok(Utc::now().to_rfc3339()),
+		Some(raw) => DateTime::parse_from_rfc3339(raw)
+			.map(|d| d.with_timezone(&Utc).to_rfc3339())
+			.map_err(|_| invalid_project("last_opened_at must be an RFC 3339 timestamp")),
+	}
+}
+
+/// Inserts or updates a project. `last_opened_at` only ever moves forward, so
+/// a device that syncs late can't push an older timestamp over a newer one.
+async fn upsert_project<'e, E>(
+	executor: E,
+	account_id: &str,
+	project_id: &str,
+	update: &ProjectUpdate,
+	last_opened_at: &str,
+) -> Result<(), sqlx::Error>
+where
+	E: Executor<'e, Database = Any>,
+{
+	sqlx::query(
+		"INSERT INTO projects
+		 (account_id, project_id, name, repo_url, last_opened_at, updated_at)
+		 VALUES ($1, $2, $3, $4, $5, $6)
+		 ON CONFLICT (account_id, project_id) DO UPDATE
+		 SET name = excluded.name,
+		     repo_url = excluded.repo_url,
+		     last_opened_at = CASE
+		         WHEN excluded.last_opened_at > projects.last_opened_at
+		         THEN excluded.last_opened_at
+		         ELSE projects.last_opened_at
+		     END,
+		     updated_at = excluded.updated_at",
+	)
+	.bind(account_id)
+	.bind(project_id)
+	.bind(update.name.trim())
+	.bind(update.repo_url.as_deref())
+	.bind(last_opened_at)
+	.bind(Utc::now().to_rfc3339())
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+async fn fetch_projects(
+	pool: &DbPool,
+	account_id: &str,
+) -> Result<Vec<Project>, sqlx::Error> {
+	sqlx::query_as::<_, Project>(
+		"SELECT project_id, name, repo_url, last_opened_at, updated_at FROM projects
+		 WHERE account_id = $1 ORDER BY last_opened_at DESC",
+	)
+	.bind(account_id)
+	.fetch_all(pool)
+	.await
+}
+
+pub async fn list_projects(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	match fetch_projects(pool.get_ref(), &account.account_id).await {
+		Ok(projects) => Ok(HttpResponse::Ok().json(ProjectsResponse { projects })),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+pub async fn put_project(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+	body: web::Json<ProjectUpdate>,
+) -> ActixResult<HttpResponse> {
+	let project_id = path.into_inner();
+	let last_opened_at = match validate_update(&project_id, &body) {
+		Ok(ts) => ts,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+
+	if let Err(e) = upsert_project(
+		pool.get_ref(),
+		&account.account_id,
+		&project_id,
+		&body,
+		&last_opened_at,
+	)
+	.await
+	{
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	match sqlx::query_as::<_, Project>(
+		"SELECT project_id, name, repo_url, last_opened_at, updated_at FROM projects
+		 WHERE account_id = $1 AND project_id = $2",
+	)
+	.bind(&account.account_id)
+	.bind(&project_id)
+	.fetch_one(pool.get_ref())
+	.await
+	{
+		Ok(project) => Ok(HttpResponse::Ok().json(project)),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+pub async fn delete_project(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let project_id = path.into_inner();
+
+	let result = match sqlx::query(
+		"DELETE FROM projects WHERE account_id = $1 AND project_id = $2",
+	)
+	.bind(&account.account_id)
+	.bind(&project_id)
+	.execute(pool.get_ref())
+	.await
+	{
+		Ok(result) => result,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	if result.rows_affected() == 0 {
+		return Ok(HttpResponse::NotFound().json(ApiError {
+			error: "Project not found".to_string(),
+			code: "PROJECT_NOT_FOUND".to_string(),
+		}));
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Upserts every project the client knows about and returns the merged list,
+/// so a device can reconcile its recent-projects view in one round trip.
+pub async fn sync_projects(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	body: web::Json<SyncProjectsRequest>,
+) -> ActixResult<HttpResponse> {
+	let entries = body.into_inner().projects;
+	if entries.len() > MAX_SYNC_PROJECTS {
+		return Ok(HttpResponse::BadRequest().json(invalid_project(&format!(
+			"At most {} projects can be synced at once",
+			MAX_SYNC_PROJECTS
+		))));
+	}
+
+	let mut validated = Vec::with_capacity(entries.len());
+	for entry in &entries {
+		match validate_update(&entry.project_id, &entry.update) {
+			Ok(ts) => validated.push((entry, ts)),
+			Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+		}
+	}
+
+	let write = async {
+		let mut tx = pool.begin().await?;
+		for (entry, last_opened_at) in &validated {
+			upsert_project(
+				&mut *tx,
+				&account.account_id,
+				&entry.project_id,
+				&entry.update,
+				last_opened_at,
+			)
+			.await?;
+		}
+		tx.commit().await
+	};
+
+	if let Err(e) = write.await {
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	list_projects(pool, account).await
+}
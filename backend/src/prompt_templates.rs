@@ -0,0 +1,259 @@
+use crate::{
+	auth::AuthenticatedAccount,
+	database::DbPool,
+	errors::internal_error,
+	llm::api::ApiError,
+	orgs::{require_role, OrgRole},
+};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use chrono::Utc;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+const MAX_NAME_LEN: usize = 128;
+const MAX_CONTENT_LEN: usize = 64 * 1024;
+
+/// A prompt template shared with every member of an org.
+#[derive(Debug, Serialize, FromRow)]
+pub struct PromptTemplate {
+	pub template_id: String,
+	pub name: String,
+	pub content: String,
+	pub created_by: String,
+	pub created_at: String,
+	pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptTemplatesResponse {
+	pub templates: Vec<PromptTemplate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromptTemplateRequest {
+	pub name: String,
+	pub content: String,
+}
+
+fn template_not_found() -> HttpResponse {
+	HttpResponse::NotFound().json(ApiError {
+		error: "Prompt template not found".to_string(),
+		code: "TEMPLATE_NOT_FOUND".to_string(),
+	})
+}
+
+fn validate(request: &PromptTemplateRequest) -> Result<(), ApiError> {
+	let name = request.name.trim();
+	if name.is_empty() || name.len() > MAX_NAME_LEN {
+		return Err(ApiError {
+			error: format!("Template name must be 1-{} characters", MAX_NAME_LEN),
+			code: "INVALID_TEMPLATE".to_string(),
+		});
+	}
+	if request.content.len() > MAX_CONTENT_LEN {
+		return Err(ApiError {
+			error: format!("Template content exceeds {} bytes", MAX_CONTENT_LEN),
+			code: "INVALID_TEMPLATE".to_string(),
+		});
+	}
+	Ok(())
+}
+
+pub async fn list_templates(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let org_id = path.into_inner();
+	if let Err(response) = require_role(
+		pool.get_ref(),
+		&org_id,
+		&account.account_id,
+		OrgRole::Member,
+	)
+	.await
+	{
+		return Ok(response);
+	}
+
+	match sqlx::query_as::<_, PromptTemplate>(
+		"SELECT template_id, name, content, created_by, created_at, updated_at
+		 FROM org_prompt_templates WHERE org_id = $1 ORDER BY name",
+	)
+	.bind(&org_id)
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(templates) => {
+			Ok(HttpResponse::Ok().json(PromptTemplatesResponse { templates }))
+		}
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+pub async fn create_template(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+	body: web::Json<PromptTemplateRequest>,
+) -> ActixResult<HttpResponse> {
+	let org_id = path.into_inner();
+	if let Err(response) = require_role(
+		pool.get_ref(),
+		&org_id,
+		&account.account_id,
+		OrgRole::Member,
+	)
+	.await
+	{
+		return Ok(response);
+	}
+
+	if let Err(e) = validate(&body) {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+
+	let template = PromptTemplate {
+		template_id: Uuid::new_v4().to_string(),
+		name: body.name.trim().to_string(),
+		content: body.content.clone(),
+		created_by: account.account_id.clone(),
+		created_at: Utc::now().to_rfc3339(),
+		updated_at: Utc::now().to_rfc3339(),
+	};
+
+	if let Err(e) = sqlx::query(
+		"INSERT INTO org_prompt_templates
+		 (template_id, org_id, name, content, created_by, created_at, updated_at)
+		 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+	)
+	.bind(&template.template_id)
+	.bind(&org_id)
+	.bind(&template.name)
+	.bind(&template.content)
+	.bind(&template.created_by)
+	.bind(&template.created_at)
+	.bind(&template.updated_at)
+	.execute(pool.get_ref())
+	.await
+	{
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	Ok(HttpResponse::Created().json(template))
+}
+
+/// Checks the template exists in the org and that the caller may change it:
+/// its author, or any admin.
+async fn authorize_edit(
+	pool: &DbPool,
+	org_id: &str,
+	template_id: &str,
+	account_id: &str,
+) -> Result<(), HttpResponse> {
+	let role = require_role(pool, org_id, account_id, OrgRole::Member).await?;
+
+	let created_by: Option<String> = sqlx::query_scalar(
+		"SELECT created_by FROM org_prompt_templates
+		 WHERE template_id = $1 AND org_id = $2",
+	)
+	.bind(template_id)
+	.bind(org_id)
+	.fetch_optional(pool)
+	.await
+	.map_err(|e| {
+		error!("Database error: {}", e);
+		internal_error()
+	})?;
+
+	match created_by {
+		None => Err(template_not_found()),
+		Some(author) if author == account_id || role >= OrgRole::Admin => Ok(()),
+		Some(_) => Err(HttpResponse::Forbidden().json(ApiError {
+			error: "Only the template's author or an admin can change it".to_string(),
+			code: "FORBIDDEN".to_string(),
+		})),
+	}
+}
+
+pub async fn update_template(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<(String, String)>,
+	body: web::Json<PromptTemplateRequest>,
+) -> ActixResult<HttpResponse> {
+	let (org_id, template_id) = path.into_inner();
+	if let Err(response) =
+		authorize_edit(pool.get_ref(), &org_id, &template_id, &account.account_id).await
+	{
+		return Ok(response);
+	}
+
+	if let Err(e) = validate(&body) {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+
+	let update = async {
+		sqlx::query(
+			"UPDATE org_prompt_templates SET name = $1, content = $2, updated_at = $3
+			 WHERE template_id = $4 AND org_id = $5",
+		)
+		.bind(body.name.trim())
+		.bind(&body.content)
+		.bind(Utc::now().to_rfc3339())
+		.bind(&template_id)
+		.bind(&org_id)
+		.execute(pool.get_ref())
+		.await?;
+
+		sqlx::query_as::<_, PromptTemplate>(
+			"SELECT template_id, name, content, created_by, created_at, updated_at
+			 FROM org_prompt_templates WHERE template_id = $1",
+		)
+		.bind(&template_id)
+		.fetch_optional(pool.get_ref())
+		.await
+	};
+
+	match update.await {
+		Ok(Some(template)) => Ok(HttpResponse::Ok().json(template)),
+		Ok(None) => Ok(template_not_found()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+pub async fn delete_template(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<(String, String)>,
+) -> ActixResult<HttpResponse> {
+	let (org_id, template_id) = path.into_inner();
+	if let Err(response) =
+		authorize_edit(pool.get_ref(), &org_id, &template_id, &account.account_id).await
+	{
+		return Ok(response);
+	}
+
+	if let Err(e) = sqlx::query(
+		"DELETE FROM org_prompt_templates WHERE template_id = $1 AND org_id = $2",
+	)
+	.bind(&template_id)
+	.bind(&org_id)
+	.execute(pool.get_ref())
+	.await
+	{
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
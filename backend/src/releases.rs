@@ -0,0 +1,333 @@
+use crate::{auth, database::DbPool, errors::internal_error, llm::api::ApiError};
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use chrono::{DateTime, Utc};
+use log::error;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+/// Release channels from most to least stable. A channel also offers the
+/// releases of every channel before it, so beta installs get stable
+/// releases that are newer than the latest beta.
+const CHANNELS: [&str; 3] = ["stable", "beta", "nightly"];
+
+/// Header the desktop app sends with a random per-install id, used to pick
+/// which installs get a staged release.
+const INSTALL_ID_HEADER: &str = "X-Install-Id";
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Release {
+	pub channel: String,
+	pub version: String,
+	pub notes: String,
+	pub pub_date: String,
+	pub rollout_percent: i64,
+	pub created_at: String,
+	pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlatformBundle {
+	pub url: String,
+	/// Contents of the `.sig` file produced by the Tauri bundler.
+	pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishReleaseRequest {
+	pub channel: String,
+	pub version: String,
+	#[serde(default)]
+	pub notes: String,
+	pub pub_date: Option<DateTime<Utc>>,
+	pub rollout_percent: Option<i64>,
+	/// Keyed by `{os}-{arch}`, as in Tauri's static update manifest.
+	pub platforms: HashMap<String, PlatformBundle>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRolloutRequest {
+	pub rollout_percent: i64,
+}
+
+/// The dynamic update response expected by the Tauri updater.
+#[derive(Debug, Serialize)]
+pub struct UpdateManifest {
+	pub version: String,
+	pub notes: String,
+	pub pub_date: String,
+	pub url: String,
+	pub signature: String,
+}
+
+#[derive(Debug, FromRow)]
+struct UpdateCandidate {
+	channel: String,
+	version: String,
+	notes: String,
+	pub_date: String,
+	rollout_percent: i64,
+	url: String,
+	signature: String,
+}
+
+fn bad_request(error: String) -> HttpResponse {
+	HttpResponse::BadRequest().json(ApiError {
+		error,
+		code: "INVALID_RELEASE".to_string(),
+	})
+}
+
+/// Publishing needs `RELEASE_TOKEN` as a bearer token, and is disabled
+/// while it is unset.
+fn authorize_publisher(req: &HttpRequest) -> Result<(), ApiError> {
+	if auth::has_secret_token(req, "RELEASE_TOKEN") {
+		return Ok(());
+	}
+	Err(ApiError {
+		error: "Publishing releases requires the release token".to_string(),
+		code: "UNAUTHORIZED".to_string(),
+	})
+}
+
+fn channel_rank(channel: &str) -> Option<usize> {
+	CHANNELS.iter().position(|c| *c == channel)
+}
+
+/// Places an install in one of 100 buckets for `version`, so each staged
+/// release reaches a different sample of installs and a client gets the same
+/// answer on every check. FNV-1a rather than the std hasher, whose output may
+/// change between Rust versions.
+fn rollout_bucket(install_id: &str, version: &str) -> i64 {
+	let mut hash: u64 = 0xcbf29ce484222325;
+	for byte in install_id.bytes().chain([b':']).chain(version.bytes()) {
+		hash ^= byte as u64;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+	(hash % 100) as i64
+}
+
+/// Update check called by the desktop app's updater.
+///
+/// `GET /api/updates/{channel}/{target}/{arch}/{current_version}`
+pub async fn check_update(
+	pool: web::Data<DbPool>,
+	req: HttpRequest,
+	path: web::Path<(String, String, String, String)>,
+) -> ActixResult<HttpResponse> {
+	let (channel, target, arch, current_version) = path.into_inner();
+	let Some(rank) = channel_rank(&channel) else {
+		return Ok(HttpResponse::NotFound().json(ApiError {
+			error: format!("Unknown release channel: {}", channel),
+			code: "UNKNOWN_CHANNEL".to_string(),
+		}));
+	};
+	let Ok(current) = Version::parse(current_version.trim_start_matches('v')) else {
+		return Ok(bad_request(format!("Invalid version: {}", current_version)));
+	};
+	let install_id = req
+		.headers()
+		.get(INSTALL_ID_HEADER)
+		.and_then(|v| v.to_str().ok())
+		.filter(|id| !id.is_empty());
+
+	let candidates = match sqlx::query_as::<_, UpdateCandidate>(
+		"SELECT r.channel, r.version, r.notes, r.pub_date, r.rollout_percent,
+		        p.url, p.signature
+		 FROM releases r
+		 JOIN release_platforms p ON p.channel = r.channel AND p.version = r.version
+		 WHERE p.platform = $1 AND r.rollout_percent > 0",
+	)
+	.bind(format!("{}-{}", target, arch))
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(candidates) => candidates,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	let newest = candidates
+		.into_iter()
+		.filter(|c| channel_rank(&c.channel).is_some_and(|r| r <= rank))
+		.filter_map(|c| Some((Version::parse(&c.version).ok()?, c)))
+		.filter(|(version, _)| *version > current)
+		.filter(|(_, c)| {
+			// Installs without an id only get fully rolled out releases
+			c.rollout_percent >= 100
+				|| install_id
+					.is_some_and(|id| rollout_bucket(id, &c.version) < c.rollout_percent)
+		})
+		.max_by(|(a, _), (b, _)| a.cmp(b));
+
+	match newest {
+		Some((_, c)) => Ok(HttpResponse::Ok().json(UpdateManifest {
+			version: c.version,
+			notes: c.notes,
+			pub_date: c.pub_date,
+			url: c.url,
+			signature: c.signature,
+		})),
+		None => Ok(HttpResponse::NoContent().finish()),
+	}
+}
+
+async fn fetch_release(
+	pool: &DbPool,
+	channel: &str,
+	version: &str,
+) -> Result<Option<Release>, sqlx::Error> {
+	sqlx::query_as::<_, Release>(
+		"SELECT channel, version, notes, pub_date, rollout_percent, created_at, updated_at
+		 FROM releases WHERE channel = $1 AND version = $2",
+	)
+	.bind(channel)
+	.bind(version)
+	.fetch_optional(pool)
+	.await
+}
+
+fn validate_rollout(rollout_percent: i64) -> Result<(), ApiError> {
+	if !(0..=100).contains(&rollout_percent) {
+		return Err(ApiError {
+			error: "rollout_percent must be between 0 and 100".to_string(),
+			code: "INVALID_RELEASE".to_string(),
+		});
+	}
+	Ok(())
+}
+
+/// Publishes a release, or replaces one with the same channel and version.
+///
+/// `POST /api/releases`
+pub async fn publish_release(
+	pool: web::Data<DbPool>,
+	req: HttpRequest,
+	body: web::Json<PublishReleaseRequest>,
+) -> ActixResult<HttpResponse> {
+	if let Err(e) = authorize_publisher(&req) {
+		return Ok(HttpResponse::Unauthorized().json(e));
+	}
+	if channel_rank(&body.channel).is_none() {
+		return Ok(bad_request(format!(
+			"channel must be one of {}",
+			CHANNELS.join(", ")
+		)));
+	}
+	if Version::parse(&body.version).is_err() {
+		return Ok(bad_request(format!(
+			"version must be a semantic version, got {}",
+			body.version
+		)));
+	}
+	let rollout_percent = body.rollout_percent.unwrap_or(100);
+	if let Err(e) = validate_rollout(rollout_percent) {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+	if body.platforms.is_empty() {
+		return Ok(bad_request("At least one platform is required".to_string()));
+	}
+
+	let now = Utc::now().to_rfc3339();
+	let pub_date = body.pub_date.unwrap_or_else(Utc::now).to_rfc3339();
+	let publish = async {
+		let mut tx = pool.begin().await?;
+		sqlx::query(
+			"INSERT INTO releases
+			 (channel, version, notes, pub_date, rollout_percent, created_at, updated_at)
+			 VALUES ($1, $2, $3, $4, $5, $6, $6)
+			 ON CONFLICT (channel, version) DO UPDATE
+			 SET notes = excluded.notes,
+			     pub_date = excluded.pub_date,
+			     rollout_percent = excluded.rollout_percent,
+			     updated_at = excluded.updated_at",
+		)
+		.bind(&body.channel)
+		.bind(&body.version)
+		.bind(&body.notes)
+		.bind(&pub_date)
+		.bind(rollout_percent)
+		.bind(&now)
+		.execute(&mut *tx)
+		.await?;
+
+		sqlx::query("DELETE FROM release_platforms WHERE channel = $1 AND version = $2")
+			.bind(&body.channel)
+			.bind(&body.version)
+			.execute(&mut *tx)
+			.await?;
+		for (platform, bundle) in &body.platforms {
+			sqlx::query(
+				"INSERT INTO release_platforms (channel, version, platform, url, signature)
+				 VALUES ($1, $2, $3, $4, $5)",
+			)
+			.bind(&body.channel)
+			.bind(&body.version)
+			.bind(platform)
+			.bind(&bundle.url)
+			.bind(&bundle.signature)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+
+		fetch_release(pool.get_ref(), &body.channel, &body.version).await
+	};
+
+	match publish.await {
+		Ok(Some(release)) => Ok(HttpResponse::Ok().json(release)),
+		Ok(None) => Ok(internal_error()),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Widens, narrows or halts (0) the staged rollout of a release.
+///
+/// `PATCH /api/releases/{channel}/{version}`
+pub async fn update_rollout(
+	pool: web::Data<DbPool>,
+	req: HttpRequest,
+	path: web::Path<(String, String)>,
+	body: web::Json<UpdateRolloutRequest>,
+) -> ActixResult<HttpResponse> {
+	if let Err(e) = authorize_publisher(&req) {
+		return Ok(HttpResponse::Unauthorized().json(e));
+	}
+	if let Err(e) = validate_rollout(body.rollout_percent) {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+	let (channel, version) = path.into_inner();
+
+	let update = async {
+		sqlx::query(
+			"UPDATE releases SET rollout_percent = $1, updated_at = $2
+			 WHERE channel = $3 AND version = $4",
+		)
+		.bind(body.rollout_percent)
+		.bind(Utc::now().to_rfc3339())
+		.bind(&channel)
+		.bind(&version)
+		.execute(pool.get_ref())
+		.await?;
+
+		fetch_release(pool.get_ref(), &channel, &version).await
+	};
+
+	match update.await {
+		Ok(Some(release)) => Ok(HttpResponse::Ok().json(release)),
+		Ok(None) => Ok(HttpResponse::NotFound().json(ApiError {
+			error: "Release not found".to_string(),
+			code: "RELEASE_NOT_FOUND".to_string(),
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
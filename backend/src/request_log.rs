@@ -0,0 +1,367 @@
+use actix_web::{
+	body::MessageBody,
+	dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+	http::header::{HeaderName, HeaderValue},
+	HttpMessage, HttpRequest,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use log::info;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Log target of the per-request lines, one JSON object each.
+const TARGET: &str = "ariana::request";
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LEN: usize = 64;
+const DEFAULT_LOG_MAX_BYTES: u64 = 50 * 1024 * 1024;
+const DEFAULT_LOG_MAX_FILES: usize = 5;
+const REDACTED: &str = "[REDACTED]";
+
+/// Query parameters whose values are never logged.
+const SECRET_PARAMS: [&str; 8] = [
+	"token",
+	"code",
+	"key",
+	"api_key",
+	"apikey",
+	"password",
+	"secret",
+	"signature",
+];
+
+/// Prefixes of provider API keys and similar credentials.
+const SECRET_PREFIXES: [&str; 7] = ["sk-", "sk_", "xai-", "gsk_", "AIza", "ghp_", "eyJ"];
+/// Shorter words with those prefixes are left alone, they're likely prose.
+const MIN_SECRET_LEN: usize = 20;
+
+/// What handlers add to a request's log line. Everything here is safe to
+/// log: ids and names, never request or message contents.
+#[derive(Debug, Default, Clone)]
+pub struct LogFields {
+	pub account_id: Option<String>,
+	pub provider: Option<String>,
+	pub model: Option<String>,
+}
+
+fn update(req: &HttpRequest, f: impl FnOnce(&mut LogFields)) {
+	let mut extensions = req.extensions_mut();
+	if extensions.get::<LogFields>().is_none() {
+		extensions.insert(LogFields::default());
+	}
+	if let Some(fields) = extensions.get_mut::<LogFields>() {
+		f(fields);
+	}
+}
+
+/// Records the authenticated account of the request.
+pub fn set_account(req: &HttpRequest, account_id: &str) {
+	update(req, |fields| {
+		fields.account_id = Some(account_id.to_string())
+	});
+}
+
+/// Records the provider and model of an LLM call.
+pub fn set_llm(req: &HttpRequest, provider: &str, model: &str) {
+	update(req, |fields| {
+		fields.provider = Some(provider.to_string());
+		fields.model = Some(model.to_string());
+	});
+}
+
+#[derive(Serialize)]
+struct RequestLine<'a> {
+	request_id: &'a str,
+	method: &'a str,
+	path: &'a str,
+	route: Option<&'a str>,
+	status: u16,
+	latency_ms: f64,
+	account_id: Option<&'a str>,
+	provider: Option<&'a str>,
+	model: Option<&'a str>,
+	user_agent: Option<&'a str>,
+}
+
+/// A client supplied id is kept when it's short and plain, so requests can
+/// be traced from the app; otherwise a new one is made.
+fn request_id(req: &ServiceRequest) -> String {
+	req.headers()
+		.get(REQUEST_ID_HEADER)
+		.and_then(|value| value.to_str().ok())
+		.filter(|id| {
+			!id.is_empty()
+				&& id.len() <= MAX_REQUEST_ID_LEN
+				&& id
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+		})
+		.map(str::to_string)
+		.unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// The path with the values of credential-like query parameters replaced.
+fn redacted_path(req: &ServiceRequest) -> String {
+	let path = req.path();
+	let query = req.query_string();
+	if query.is_empty() {
+		return path.to_string();
+	}
+	let query: Vec<String> = query
+		.split('&')
+		.map(|pair| match pair.split_once('=') {
+			Some((name, _))
+				if SECRET_PARAMS.contains(&name.to_ascii_lowercase().as_str()) =>
+			{
+				format!("{}={}", name, REDACTED)
+			}
+			_ => pair.to_string(),
+		})
+		.collect();
+	format!("{}?{}", path, query.join("&"))
+}
+
+fn is_secret(word: &str) -> bool {
+	word.len() >= MIN_SECRET_LEN
+		&& SECRET_PREFIXES
+			.iter()
+			.any(|prefix| word.starts_with(prefix))
+}
+
+/// Replaces what looks like a credential in a log message: bearer tokens,
+/// provider API keys and JWTs.
+pub fn redact_secrets(message: &str) -> Cow<'_, str> {
+	let is_word = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+	let mut redacted = String::with_capacity(message.len());
+	let mut changed = false;
+	let mut after_bearer = false;
+	let mut rest = message;
+	while !rest.is_empty() {
+		let end = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+		let (word, tail) = rest.split_at(end);
+		if !word.is_empty() {
+			if is_secret(word) || (after_bearer && word.len() >= 8) {
+				redacted.push_str(REDACTED);
+				changed = true;
+			} else {
+				redacted.push_str(word);
+			}
+			after_bearer = word.eq_ignore_ascii_case("bearer");
+		}
+		let Some(separator) = tail.chars().next() else {
+			break;
+		};
+		redacted.push(separator);
+		if separator != ' ' {
+			after_bearer = false;
+		}
+		rest = &tail[separator.len_utf8()..];
+	}
+	if changed {
+		Cow::Owned(redacted)
+	} else {
+		Cow::Borrowed(message)
+	}
+}
+
+/// Logs one JSON line per request with its id, latency, account, and the
+/// provider and model of LLM calls. Bodies are never logged and secrets in
+/// the query string are redacted.
+pub struct RequestLogger;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestLogger
+where
+	S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+		+ 'static,
+	B: MessageBody + 'static,
+{
+	type Response = ServiceResponse<B>;
+	type Error = actix_web::Error;
+	type Transform = RequestLoggerMiddleware<S>;
+	type InitError = ();
+	type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+	fn new_transform(&self, service: S) -> Self::Future {
+		ready(Ok(RequestLoggerMiddleware { service }))
+	}
+}
+
+pub struct RequestLoggerMiddleware<S> {
+	service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestLoggerMiddleware<S>
+where
+	S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+		+ 'static,
+	B: MessageBody + 'static,
+{
+	type Response = ServiceResponse<B>;
+	type Error = actix_web::Error;
+	type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+	forward_ready!(service);
+
+	fn call(&self, req: ServiceRequest) -> Self::Future {
+		let started = Instant::now();
+		let id = request_id(&req);
+		let method = req.method().to_string();
+		let path = redacted_path(&req);
+		let user_agent = req
+			.headers()
+			.get("user-agent")
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_string);
+		let response = self.service.call(req);
+
+		Box::pin(async move {
+			let mut response = response.await?;
+			let fields = response
+				.request()
+				.extensions()
+				.get::<LogFields>()
+				.cloned()
+				.unwrap_or_default();
+			let route = response.request().match_pattern();
+			let line = RequestLine {
+				request_id: &id,
+				method: &method,
+				path: &path,
+				route: route.as_deref(),
+				status: response.status().as_u16(),
+				latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+				account_id: fields.account_id.as_deref(),
+				provider: fields.provider.as_deref(),
+				model: fields.model.as_deref(),
+				user_agent: user_agent.as_deref(),
+			};
+			if let Ok(line) = serde_json::to_string(&line) {
+				info!(target: TARGET, "{}", line);
+			}
+			if let Ok(value) = HeaderValue::from_str(&id) {
+				response
+					.headers_mut()
+					.insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+			}
+			Ok(response)
+		})
+	}
+}
+
+/// A log file that is rotated once it grows past `max_bytes`:
+/// `backend.log` becomes `backend.log.1`, and so on up to `max_files`.
+struct RotatingFile {
+	path: PathBuf,
+	max_bytes: u64,
+	max_files: usize,
+	file: File,
+	size: u64,
+}
+
+impl RotatingFile {
+	fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+		if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+			fs::create_dir_all(dir)?;
+		}
+		let file = OpenOptions::new().create(true).append(true).open(&path)?;
+		let size = file.metadata()?.len();
+		Ok(Self {
+			path,
+			max_bytes,
+			max_files,
+			file,
+			size,
+		})
+	}
+
+	fn rotated(&self, index: usize) -> PathBuf {
+		let mut name = self.path.clone().into_os_string();
+		name.push(format!(".{}", index));
+		PathBuf::from(name)
+	}
+
+	fn rotate(&mut self) -> io::Result<()> {
+		self.file.flush()?;
+		for index in (1..self.max_files).rev() {
+			let from = if index == 1 {
+				self.path.clone()
+			} else {
+				self.rotated(index - 1)
+			};
+			if from.exists() {
+				fs::rename(&from, self.rotated(index))?;
+			}
+		}
+		if self.max_files <= 1 {
+			fs::remove_file(&self.path)?;
+		}
+		self.file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&self.path)?;
+		self.size = 0;
+		Ok(())
+	}
+}
+
+impl Write for RotatingFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+			self.rotate()?;
+		}
+		let written = self.file.write(buf)?;
+		self.size += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.file.flush()
+	}
+}
+
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> T {
+	env::var(name)
+		.ok()
+		.and_then(|value| value.parse().ok())
+		.unwrap_or(default)
+}
+
+/// Logs to stdout and, when `LOG_FILE` is set, to that file, rotated at
+/// `LOG_MAX_BYTES` (50 MiB) keeping `LOG_MAX_FILES` (5) files. Every
+/// message goes through `redact_secrets` first.
+pub fn init_logging() {
+	let mut dispatch = fern::Dispatch::new()
+		.level(log::LevelFilter::Info)
+		.format(|out, message, record| {
+			let message = message.to_string();
+			match record.target() {
+				TARGET => out.finish(format_args!("{}", redact_secrets(&message))),
+				target => out.finish(format_args!(
+					"{} {} {} {}",
+					chrono::Utc::now()
+						.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+					record.level(),
+					target,
+					redact_secrets(&message)
+				)),
+			}
+		})
+		.chain(std::io::stdout());
+
+	if let Some(path) = env::var("LOG_FILE").ok().filter(|path| !path.is_empty()) {
+		let file = RotatingFile::open(
+			PathBuf::from(&path),
+			env_number("LOG_MAX_BYTES", DEFAULT_LOG_MAX_BYTES),
+			env_number("LOG_MAX_FILES", DEFAULT_LOG_MAX_FILES).max(1),
+		)
+		.unwrap_or_else(|e| panic!("Failed to open LOG_FILE {}: {}", path, e));
+		dispatch = dispatch.chain(Box::new(file) as Box<dyn Write + Send>);
+	}
+
+	dispatch.apply().expect("Failed to initialize logging");
+}
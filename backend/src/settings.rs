@@ -0,0 +1,326 @@
+use crate::{
+	auth::AuthenticatedAccount, database::DbPool, errors::internal_error,
+	llm::api::ApiError,
+};
+use actix_web::{
+	http::header::{ETAG, IF_MATCH, IF_NONE_MATCH},
+	web, HttpRequest, HttpResponse, Result as ActixResult,
+};
+use chrono::Utc;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+/// Largest serialized settings blob accepted for a single namespace.
+const MAX_SETTINGS_BYTES: usize = 1024 * 1024;
+
+/// Settings are stored per account and namespace (e.g. `themes`,
+/// `keybindings`, `terminal_profiles`). Each write bumps `version`, which is
+/// exposed to clients as a strong ETag for optimistic concurrency.
+#[derive(FromRow)]
+struct SettingsRow {
+	namespace: String,
+	version: i64,
+	data: String,
+	updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingsBlob {
+	pub namespace: String,
+	pub version: i64,
+	pub etag: String,
+	pub data: Value,
+	pub updated_at: String,
+}
+
+impl From<SettingsRow> for SettingsBlob {
+	fn from(row: SettingsRow) -> Self {
+		SettingsBlob {
+			etag: etag_for(row.version),
+			data: serde_json::from_str(&row.data).unwrap_or(Value::Null),
+			namespace: row.namespace,
+			version: row.version,
+			updated_at: row.updated_at,
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingsSummary {
+	pub namespace: String,
+	pub version: i64,
+	pub etag: String,
+	pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingsListResponse {
+	pub settings: Vec<SettingsSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingsConflict {
+	pub error: String,
+	pub code: String,
+	/// The blob currently stored on the server, if any.
+	pub current: Option<SettingsBlob>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutSettingsRequest {
+	pub data: Value,
+}
+
+/// Write precondition taken from the `If-Match` / `If-None-Match` headers.
+enum Precondition {
+	/// No conditional header: last write wins.
+	None,
+	/// `If-Match: "<version>"`
+	Version(i64),
+	/// `If-Match: *`
+	Exists,
+	/// `If-None-Match: *`
+	Absent,
+}
+
+fn etag_for(version: i64) -> String {
+	format!("\"{}\"", version)
+}
+
+/// Parses an entity tag produced by [`etag_for`]. Tags we didn't issue map to
+/// a version that can never match.
+fn parse_etag(value: &str) -> i64 {
+	value
+		.trim()
+		.trim_start_matches("W/")
+		.trim_matches('"')
+		.parse()
+		.unwrap_or(-1)
+}
+
+fn header(req: &HttpRequest, name: actix_web::http::header::HeaderName) -> Option<&str> {
+	req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn precondition(req: &HttpRequest) -> Precondition {
+	if let Some(value) = header(req, IF_MATCH) {
+		if value.trim() == "*" {
+			return Precondition::Exists;
+		}
+		return Precondition::Version(parse_etag(value));
+	}
+	match header(req, IF_NONE_MATCH) {
+		Some(value) if value.trim() == "*" => Precondition::Absent,
+		_ => Precondition::None,
+	}
+}
+
+fn validate_namespace(namespace: &str) -> Result<(), ApiError> {
+	let valid = !namespace.is_empty()
+		&& namespace.len() <= 64
+		&& namespace.chars().all(|c| {
+			c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.')
+		});
+	if valid {
+		Ok(())
+	} else {
+		Err(ApiError {
+			error: "Namespace must be 1-64 characters of a-z, 0-9, '-', '_' or '.'"
+				.to_string(),
+			code: "INVALID_NAMESPACE".to_string(),
+		})
+	}
+}
+
+async fn fetch_settings(
+	pool: &DbPool,
+	account_id: &str,
+	namespace: &str,
+) -> Result<Option<SettingsRow>, sqlx::Error> {
+	sqlx::query_as::<_, SettingsRow>(
+		"SELECT namespace, version, data, updated_at FROM settings_blobs
+		 WHERE account_id = $1 AND namespace = $2",
+	)
+	.bind(account_id)
+	.bind(namespace)
+	.fetch_optional(pool)
+	.await
+}
+
+pub async fn list_settings(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	let rows: Vec<(String, i64, String)> = match sqlx::query_as(
+		"SELECT namespace, version, updated_at FROM settings_blobs
+		 WHERE account_id = $1 ORDER BY namespace",
+	)
+	.bind(&account.account_id)
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(rows) => rows,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	let settings = rows
+		.into_iter()
+		.map(|(namespace, version, updated_at)| SettingsSummary {
+			namespace,
+			version,
+			etag: etag_for(version),
+			updated_at,
+		})
+		.collect();
+
+	Ok(HttpResponse::Ok().json(SettingsListResponse { settings }))
+}
+
+pub async fn get_settings(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+	req: HttpRequest,
+) -> ActixResult<HttpResponse> {
+	let namespace = path.into_inner();
+	if let Err(e) = validate_namespace(&namespace) {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+
+	let row = match fetch_settings(pool.get_ref(), &account.account_id, &namespace).await
+	{
+		Ok(Some(row)) => row,
+		Ok(None) => {
+			return Ok(HttpResponse::NotFound().json(ApiError {
+				error: format!("No settings stored for namespace {}", namespace),
+				code: "SETTINGS_NOT_FOUND".to_string(),
+			}))
+		}
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	let etag = etag_for(row.version);
+	if header(&req, IF_NONE_MATCH).is_some_and(|v| parse_etag(v) == row.version) {
+		return Ok(HttpResponse::NotModified()
+			.insert_header((ETAG, etag))
+			.finish());
+	}
+
+	Ok(HttpResponse::Ok()
+		.insert_header((ETAG, etag))
+		.json(SettingsBlob::from(row)))
+}
+
+pub async fn put_settings(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+	req: HttpRequest,
+	body: web::Json<PutSettingsRequest>,
+) -> ActixResult<HttpResponse> {
+	let namespace = path.into_inner();
+	if let Err(e) = validate_namespace(&namespace) {
+		return Ok(HttpResponse::BadRequest().json(e));
+	}
+
+	let data = body.into_inner().data.to_string();
+	if data.len() > MAX_SETTINGS_BYTES {
+		return Ok(HttpResponse::PayloadTooLarge().json(ApiError {
+			error: format!("Settings blob exceeds {} bytes", MAX_SETTINGS_BYTES),
+			code: "SETTINGS_TOO_LARGE".to_string(),
+		}));
+	}
+
+	let now = Utc::now().to_rfc3339();
+	let written = match precondition(&req) {
+		Precondition::Version(expected) => {
+			sqlx::query(
+				"UPDATE settings_blobs SET data = $1, version = version + 1, updated_at = $2
+				 WHERE account_id = $3 AND namespace = $4 AND version = $5",
+			)
+			.bind(&data)
+			.bind(&now)
+			.bind(&account.account_id)
+			.bind(&namespace)
+			.bind(expected)
+			.execute(pool.get_ref())
+			.await
+		}
+		Precondition::Exists => {
+			sqlx::query(
+				"UPDATE settings_blobs SET data = $1, version = version + 1, updated_at = $2
+				 WHERE account_id = $3 AND namespace = $4",
+			)
+			.bind(&data)
+			.bind(&now)
+			.bind(&account.account_id)
+			.bind(&namespace)
+			.execute(pool.get_ref())
+			.await
+		}
+		Precondition::Absent => {
+			sqlx::query(
+				"INSERT INTO settings_blobs (account_id, namespace, version, data, updated_at)
+				 VALUES ($1, $2, 1, $3, $4)
+				 ON CONFLICT (account_id, namespace) DO NOTHING",
+			)
+			.bind(&account.account_id)
+			.bind(&namespace)
+			.bind(&data)
+			.bind(&now)
+			.execute(pool.get_ref())
+			.await
+		}
+		Precondition::None => {
+			sqlx::query(
+				"INSERT INTO settings_blobs (account_id, namespace, version, data, updated_at)
+				 VALUES ($1, $2, 1, $3, $4)
+				 ON CONFLICT (account_id, namespace) DO UPDATE
+				 SET data = excluded.data, version = settings_blobs.version + 1,
+				     updated_at = excluded.updated_at",
+			)
+			.bind(&account.account_id)
+			.bind(&namespace)
+			.bind(&data)
+			.bind(&now)
+			.execute(pool.get_ref())
+			.await
+		}
+	};
+
+	let written = match written {
+		Ok(result) => result.rows_affected() > 0,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	let current =
+		match fetch_settings(pool.get_ref(), &account.account_id, &namespace).await {
+			Ok(row) => row.map(SettingsBlob::from),
+			Err(e) => {
+				error!("Database error: {}", e);
+				return Ok(internal_error());
+			}
+		};
+
+	match current {
+		Some(blob) if written => Ok(HttpResponse::Ok()
+			.insert_header((ETAG, blob.etag.clone()))
+			.json(blob)),
+		current => Ok(HttpResponse::PreconditionFailed().json(SettingsConflict {
+			error: "Settings were changed on another device".to_string(),
+			code: "SETTINGS_CONFLICT".to_string(),
+			current,
+		})),
+	}
+}
@@ -0,0 +1,152 @@
+use crate::{database::DbPool, errors::internal_error, llm::api::ApiError};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+/// Request body limit for a telemetry batch.
+pub const MAX_BATCH_BYTES: usize = 1024 * 1024;
+const MAX_EVENTS: usize = 500;
+const MAX_NAME_LEN: usize = 64;
+const MAX_PROPERTIES: usize = 32;
+/// Longer strings are more likely to carry something identifying.
+const MAX_VALUE_LEN: usize = 64;
+const MAX_TEXT_LEN: usize = 64;
+
+/// One anonymous usage event. Properties are flat and scalar.
+#[derive(Debug, Deserialize)]
+pub struct TelemetryEvent {
+	pub event_id: String,
+	pub session_id: String,
+	pub name: String,
+	pub occurred_at: DateTime<Utc>,
+	#[serde(default)]
+	pub properties: Map<String, Value>,
+}
+
+/// Events the desktop app queued while the user opted in to telemetry.
+#[derive(Debug, Deserialize)]
+pub struct TelemetryBatch {
+	pub app_version: String,
+	pub os: String,
+	pub arch: String,
+	pub events: Vec<TelemetryEvent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TelemetryResponse {
+	pub accepted: usize,
+}
+
+/// Lowercase letters, digits, `_` and `.`, like `palette.opened`.
+fn valid_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name.len() <= MAX_NAME_LEN
+		&& name.chars().all(|c| {
+			c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '.')
+		})
+}
+
+fn validate_event(event: &TelemetryEvent) -> Result<(), String> {
+	if Uuid::parse_str(&event.event_id).is_err()
+		|| Uuid::parse_str(&event.session_id).is_err()
+	{
+		return Err("event_id and session_id must be UUIDs".to_string());
+	}
+	if !valid_name(&event.name) {
+		return Err(format!("Invalid event name `{}`", event.name));
+	}
+	if event.properties.len() > MAX_PROPERTIES {
+		return Err(format!(
+			"Events are limited to {} properties",
+			MAX_PROPERTIES
+		));
+	}
+	for (key, value) in &event.properties {
+		if !valid_name(key) {
+			return Err(format!("Invalid property name `{}`", key));
+		}
+		match value {
+			Value::Null | Value::Bool(_) | Value::Number(_) => {}
+			Value::String(s) if s.len() <= MAX_VALUE_LEN => {}
+			Value::String(_) => {
+				return Err(format!(
+					"Property `{}` exceeds {} bytes",
+					key, MAX_VALUE_LEN
+				))
+			}
+			_ => return Err(format!("Property `{}` must be a scalar", key)),
+		}
+	}
+	Ok(())
+}
+
+fn validate(batch: &TelemetryBatch) -> Result<(), String> {
+	if batch.events.len() > MAX_EVENTS {
+		return Err(format!("At most {} events are accepted", MAX_EVENTS));
+	}
+	if [&batch.app_version, &batch.os, &batch.arch]
+		.iter()
+		.any(|v| v.is_empty() || v.len() > MAX_TEXT_LEN)
+	{
+		return Err(format!(
+			"app_version, os and arch must be 1-{} bytes",
+			MAX_TEXT_LEN
+		));
+	}
+	batch.events.iter().try_for_each(validate_event)
+}
+
+/// Stores a batch of anonymous telemetry events. No token is read, so
+/// events can't be linked to an account. Re-sent events are ignored.
+///
+/// `POST /api/telemetry`
+pub async fn submit_telemetry(
+	pool: web::Data<DbPool>,
+	body: web::Json<TelemetryBatch>,
+) -> ActixResult<HttpResponse> {
+	if let Err(error) = validate(&body) {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error,
+			code: "INVALID_TELEMETRY".to_string(),
+		}));
+	}
+
+	let received_at = Utc::now().to_rfc3339();
+	let write = async {
+		let mut tx = pool.begin().await?;
+		for event in &body.events {
+			sqlx::query(
+				"INSERT INTO telemetry_events
+				 (event_id, session_id, name, app_version, os, arch, properties,
+				  occurred_at, received_at)
+				 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+				 ON CONFLICT (event_id) DO NOTHING",
+			)
+			.bind(&event.event_id)
+			.bind(&event.session_id)
+			.bind(&event.name)
+			.bind(&body.app_version)
+			.bind(&body.os)
+			.bind(&body.arch)
+			.bind(Value::Object(event.properties.clone()).to_string())
+			.bind(event.occurred_at.to_rfc3339())
+			.bind(&received_at)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await
+	};
+
+	match write.await {
+		Ok(()) => Ok(HttpResponse::Accepted().json(TelemetryResponse {
+			accepted: body.events.len(),
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
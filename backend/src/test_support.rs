@@ -0,0 +1,393 @@
+//! Harness for end-to-end tests. `TestApp::start` serves every route on a
+//! random port, backed by a fresh database, the mock LLM providers of
+//! `llm::mock` and an SMTP sink that keeps the emails sent.
+//!
+//! Databases are in-memory SQLite, unless `TEST_DATABASE_URL` points at a
+//! Postgres server, e.g. one started with
+//! `docker run -p 5432:5432 -e POSTGRES_PASSWORD=postgres postgres:16`.
+//! Each test then gets its own `ariana_test_*` database on it.
+//!
+//! The sink and the mock providers are shared by all tests, which should use
+//! their own email addresses.
+
+use crate::{
+	collab::CollabHub,
+	database::{self, DatabaseKind, DbPool},
+	email::EmailService,
+	llm::mock::MockLlm,
+	metrics::Metrics,
+	request_log, routes,
+	vault::KeyVault,
+};
+use actix_web::{
+	dev::ServerHandle, middleware::NormalizePath, web::Data, App, HttpServer,
+};
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::thread;
+use uuid::Uuid;
+
+/// An email received by the sink.
+#[derive(Debug, Clone)]
+pub struct SentEmail {
+	pub to: Vec<String>,
+	/// Headers and body as sent, with quoted-printable decoded.
+	pub data: String,
+}
+
+/// Accepts any email over plain SMTP and keeps it in memory. Runs on its own
+/// threads, so it outlives the runtime of the test that started it.
+pub struct SmtpSink {
+	port: u16,
+	emails: Arc<Mutex<Vec<SentEmail>>>,
+}
+
+/// Undoes quoted-printable soft line breaks and `=XX` escapes.
+fn decode_quoted_printable(data: &str) -> String {
+	let joined = data.replace("=\r\n", "");
+	let bytes = joined.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let escaped = (bytes[i] == b'=' && i + 2 < bytes.len())
+			.then(|| std::str::from_utf8(&bytes[i + 1..i + 3]).ok())
+			.flatten()
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+		match escaped {
+			Some(byte) => {
+				decoded.push(byte);
+				i += 3;
+			}
+			None => {
+				decoded.push(bytes[i]);
+				i += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn serve_smtp(
+	stream: TcpStream,
+	emails: Arc<Mutex<Vec<SentEmail>>>,
+) -> std::io::Result<()> {
+	let mut writer = stream.try_clone()?;
+	let mut reader = BufReader::new(stream);
+	let mut to = Vec::new();
+	writer.write_all(b"220 sink ESMTP\r\n")?;
+	let mut line = String::new();
+	loop {
+		line.clear();
+		if reader.read_line(&mut line)? == 0 {
+			return Ok(());
+		}
+		let command = line.trim_end().to_ascii_uppercase();
+		let reply: &[u8] = if command.starts_with("EHLO") {
+			b"250-sink\r\n250 8BITMIME\r\n"
+		} else if command.starts_with("RCPT TO:") {
+			let address =
+				line.trim_end()[8..].trim_matches(|c| c == '<' || c == '>' || c == ' ');
+			to.push(address.to_string());
+			b"250 OK\r\n"
+		} else if command == "DATA" {
+			writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")?;
+			let mut data = String::new();
+			loop {
+				line.clear();
+				if reader.read_line(&mut line)? == 0 {
+					return Ok(());
+				}
+				if line == ".\r\n" {
+					break;
+				}
+				data.push_str(line.strip_prefix('.').unwrap_or(&line));
+			}
+			emails.lock().unwrap().push(SentEmail {
+				to: std::mem::take(&mut to),
+				data: decode_quoted_printable(&data),
+			});
+			b"250 OK\r\n"
+		} else if command == "QUIT" {
+			writer.write_all(b"221 Bye\r\n")?;
+			return Ok(());
+		} else if command == "RSET" {
+			to.clear();
+			b"250 OK\r\n"
+		} else {
+			// HELO, MAIL FROM and NOOP
+			b"250 OK\r\n"
+		};
+		writer.write_all(reply)?;
+	}
+}
+
+impl SmtpSink {
+	fn start() -> Self {
+		let listener =
+			TcpListener::bind("127.0.0.1:0").expect("Failed to bind SMTP sink");
+		let port = listener
+			.local_addr()
+			.expect("SMTP sink has no address")
+			.port();
+		let emails = Arc::new(Mutex::new(Vec::new()));
+		let accepted = emails.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				let emails = accepted.clone();
+				thread::spawn(move || serve_smtp(stream, emails));
+			}
+		});
+		SmtpSink { port, emails }
+	}
+
+	/// Emails sent to `address` so far, oldest first.
+	pub fn emails_to(&self, address: &str) -> Vec<SentEmail> {
+		self.emails
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|email| email.to.iter().any(|to| to.eq_ignore_ascii_case(address)))
+			.cloned()
+			.collect()
+	}
+
+	/// The code of the latest login email sent to `address`, which is alone on
+	/// its line.
+	pub fn login_code(&self, address: &str) -> Option<String> {
+		let email = self.emails_to(address).pop()?;
+		email
+			.data
+			.lines()
+			.map(str::trim)
+			.find(|line| line.len() == 6 && line.bytes().all(|b| b.is_ascii_digit()))
+			.map(str::to_string)
+	}
+
+	/// The magic link of the latest login email sent to `address`.
+	pub fn magic_link(&self, address: &str) -> Option<String> {
+		let email = self.emails_to(address).pop()?;
+		email
+			.data
+			.lines()
+			.map(str::trim)
+			.find(|line| line.starts_with("http") && line.contains("/auth/magic?token="))
+			.map(str::to_string)
+	}
+}
+
+static SMTP_SINK: OnceLock<SmtpSink> = OnceLock::new();
+static MOCK_LLM: OnceLock<MockLlm> = OnceLock::new();
+static ENVIRONMENT: Once = Once::new();
+
+pub fn smtp_sink() -> &'static SmtpSink {
+	SMTP_SINK.get_or_init(SmtpSink::start)
+}
+
+pub fn mock_llm() -> &'static MockLlm {
+	MOCK_LLM.get_or_init(|| {
+		// Started from a runtime of its own, since the server has to keep
+		// running once the first test's runtime is gone.
+		thread::spawn(|| {
+			tokio::runtime::Builder::new_current_thread()
+				.enable_all()
+				.build()
+				.expect("Failed to build runtime")
+				.block_on(MockLlm::start())
+		})
+		.join()
+		.expect("Failed to start the mock LLM")
+	})
+}
+
+/// Sets the variables the server reads once for the whole test process,
+/// pointing email at the sink and providers at the mock.
+fn configure_environment() {
+	ENVIRONMENT.call_once(|| {
+		let sink = smtp_sink();
+		for (name, value) in [
+			("JWT_SECRET", "test-jwt-secret".to_string()),
+			(
+				"VAULT_ENCRYPTION_KEY",
+				"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string(),
+			),
+			("PUBLIC_BASE_URL", "http://localhost:8080".to_string()),
+			("SENDER_EMAIL", "ariana@example.com".to_string()),
+			("EMAIL_PROVIDERS", "smtp".to_string()),
+			("SMTP_SERVER", "127.0.0.1".to_string()),
+			("SMTP_PORT", sink.port.to_string()),
+			("SMTP_TLS", "none".to_string()),
+			("SMTP_USERNAME", String::new()),
+			("LLM_MOCK_PROVIDER", "true".to_string()),
+			(
+				"LLM_FIXTURES_DIR",
+				env::temp_dir()
+					.join(format!("ariana_fixtures_{}", Uuid::new_v4().simple()))
+					.display()
+					.to_string(),
+			),
+		] {
+			env::set_var(name, value);
+		}
+		// An in-memory database lasts as long as one of its connections
+		env::set_var("DB_MIN_CONNECTIONS", "1");
+		for name in [
+			"ADMIN_EMAILS",
+			"EMAIL_TEMPLATES_DIR",
+			"LLM_RECORD",
+			"LLM_MOCK_CHUNK_DELAY_MS",
+		] {
+			env::remove_var(name);
+		}
+		mock_llm().use_for_all_providers();
+	});
+}
+
+/// A database of its own for one test.
+async fn create_database() -> (DbPool, DatabaseKind) {
+	let name = format!("ariana_test_{}", Uuid::new_v4().simple());
+	let url = match env::var("TEST_DATABASE_URL") {
+		Ok(server) if !server.is_empty() => {
+			let admin = database::create_pool(&server, DatabaseKind::Postgres)
+				.await
+				.expect("Failed to connect to TEST_DATABASE_URL");
+			sqlx::query(&format!("CREATE DATABASE {}", name))
+				.execute(&admin)
+				.await
+				.expect("Failed to create the test database");
+			admin.close().await;
+			let mut url =
+				reqwest::Url::parse(&server).expect("Invalid TEST_DATABASE_URL");
+			url.set_path(&name);
+			url.to_string()
+		}
+		// Shared cache, so every connection of the pool sees the same database
+		_ => format!("sqlite:file:{}?mode=memory&cache=shared", name),
+	};
+	let kind = DatabaseKind::from_url(&url).expect("Unsupported TEST_DATABASE_URL");
+	let pool = database::create_pool(&url, kind)
+		.await
+		.expect("Failed to create the test database pool");
+	database::run_migrations(&pool, kind, true)
+		.await
+		.expect("Failed to run migrations");
+	(pool, kind)
+}
+
+/// The server running for one test. It stops when the test's runtime does.
+pub struct TestApp {
+	pub pool: DbPool,
+	pub base_url: String,
+	client: Client,
+	server: ServerHandle,
+}
+
+impl TestApp {
+	pub async fn start() -> Self {
+		configure_environment();
+		let (pool, kind) = create_database().await;
+		let email_service =
+			EmailService::new(pool.clone()).expect("Failed to initialize email service");
+		let key_vault = KeyVault::new().expect("Failed to initialize key vault");
+		let metrics = Metrics::new();
+		let collab_hub = CollabHub::new();
+
+		let app_pool = pool.clone();
+		let listener =
+			TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+		let port = listener
+			.local_addr()
+			.expect("Test server has no address")
+			.port();
+		let server = HttpServer::new(move || {
+			App::new()
+				.app_data(Data::new(app_pool.clone()))
+				.app_data(Data::new(kind))
+				.app_data(Data::new(email_service.clone()))
+				.app_data(Data::new(key_vault.clone()))
+				.app_data(Data::new(metrics.clone()))
+				.app_data(Data::new(collab_hub.clone()))
+				.wrap(NormalizePath::trim())
+				.wrap(request_log::RequestLogger)
+				.configure(routes)
+		})
+		.workers(1)
+		.disable_signals()
+		.listen(listener)
+		.expect("Failed to listen")
+		.run();
+		let handle = server.handle();
+		actix_web::rt::spawn(server);
+
+		TestApp {
+			pool,
+			base_url: format!("http://127.0.0.1:{}", port),
+			client: Client::new(),
+			server: handle,
+		}
+	}
+
+	pub async fn get(&self, path: &str, token: Option<&str>) -> Response {
+		let mut request = self.client.get(format!("{}{}", self.base_url, path));
+		if let Some(token) = token {
+			request = request.bearer_auth(token);
+		}
+		request.send().await.expect("Request failed")
+	}
+
+	pub async fn post(&self, path: &str, token: Option<&str>, body: &Value) -> Response {
+		let mut request = self
+			.client
+			.post(format!("{}{}", self.base_url, path))
+			.json(body);
+		if let Some(token) = token {
+			request = request.bearer_auth(token);
+		}
+		request.send().await.expect("Request failed")
+	}
+
+	/// Logs in through the emailed code like the IDE does, returning the
+	/// `/auth/validate-login-code` response.
+	pub async fn login(&self, email: &str) -> Value {
+		let response = self
+			.post("/auth/request-login-code", None, &json!({ "email": email }))
+			.await;
+		assert!(response.status().is_success(), "requesting a code failed");
+		let code = smtp_sink()
+			.login_code(email)
+			.expect("no login code was emailed");
+		let response = self
+			.post(
+				"/auth/validate-login-code",
+				None,
+				&json!({ "email": email, "code": code }),
+			)
+			.await;
+		assert!(response.status().is_success(), "validating the code failed");
+		response.json().await.expect("Invalid auth response")
+	}
+
+	/// Logs in a new account, returning its token.
+	pub async fn token(&self) -> String {
+		let email = unique_email();
+		self.login(&email).await["token"]
+			.as_str()
+			.expect("No token in the auth response")
+			.to_string()
+	}
+}
+
+impl Drop for TestApp {
+	fn drop(&mut self) {
+		// The stop command is sent right away; waiting for it is not needed.
+		drop(self.server.stop(false));
+	}
+}
+
+/// An address no other test uses, so the shared sink's emails don't mix.
+pub fn unique_email() -> String {
+	format!("test-{}@example.com", Uuid::new_v4().simple())
+}
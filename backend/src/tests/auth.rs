@@ -0,0 +1,134 @@
+use crate::test_support::{smtp_sink, unique_email, TestApp};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn login_code_is_emailed_and_exchanged_for_a_token() {
+	let app = TestApp::start().await;
+	let email = unique_email();
+
+	let auth = app.login(&email).await;
+	assert_eq!(auth["account"]["email"], email);
+	assert_eq!(auth["profile"]["timezone"], "UTC");
+
+	let email_sent = smtp_sink().emails_to(&email).pop().unwrap();
+	assert!(email_sent.data.contains("Subject: Your ariana login code"));
+
+	let token = auth["token"].as_str().unwrap();
+	let response = app.get("/api/account/profile", Some(token)).await;
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn requests_without_a_valid_token_are_unauthorized() {
+	let app = TestApp::start().await;
+
+	let response = app.get("/api/account/profile", None).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let response = app.get("/api/account/profile", Some("not-a-jwt")).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn login_code_is_single_use() {
+	let app = TestApp::start().await;
+	let email = unique_email();
+	app.login(&email).await;
+	let code = smtp_sink().login_code(&email).unwrap();
+
+	let response = app
+		.post(
+			"/auth/validate-login-code",
+			None,
+			&json!({ "email": email, "code": code }),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn login_code_is_dropped_after_too_many_wrong_guesses() {
+	let app = TestApp::start().await;
+	let email = unique_email();
+	app.post("/auth/request-login-code", None, &json!({ "email": email }))
+		.await;
+	let code = smtp_sink().login_code(&email).unwrap();
+	let wrong = if code == "000000" { "111111" } else { "000000" };
+
+	for _ in 0..5 {
+		let response = app
+			.post(
+				"/auth/validate-login-code",
+				None,
+				&json!({ "email": email, "code": wrong }),
+			)
+			.await;
+		assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	}
+
+	let response = app
+		.post(
+			"/auth/validate-login-code",
+			None,
+			&json!({ "email": email, "code": code }),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn magic_link_redirects_to_the_ide_with_a_token() {
+	let app = TestApp::start().await;
+	let email = unique_email();
+	app.post("/auth/request-login-code", None, &json!({ "email": email }))
+		.await;
+	let link = smtp_sink().magic_link(&email).unwrap();
+	let path = &link[link.find("/auth/magic").unwrap()..];
+
+	let client = reqwest::Client::builder()
+		.redirect(reqwest::redirect::Policy::none())
+		.build()
+		.unwrap();
+	let response = client
+		.get(format!("{}{}", app.base_url, path))
+		.send()
+		.await
+		.unwrap();
+	assert!(response.status().is_redirection());
+	let location = response.headers()["location"].to_str().unwrap().to_string();
+	assert!(location.starts_with("ariana://auth?token="), "{}", location);
+
+	// The link is consumed, and so is the code sent with it
+	let response = client
+		.get(format!("{}{}", app.base_url, path))
+		.send()
+		.await
+		.unwrap();
+	let location = response.headers()["location"].to_str().unwrap();
+	assert!(location.starts_with("ariana://auth?error="), "{}", location);
+}
+
+#[actix_web::test]
+async fn disabled_accounts_are_refused() {
+	let app = TestApp::start().await;
+	let email = unique_email();
+	let auth = app.login(&email).await;
+	let token = auth["token"].as_str().unwrap();
+
+	sqlx::query("UPDATE accounts SET status = 'disabled' WHERE email = $1")
+		.bind(&email)
+		.execute(&app.pool)
+		.await
+		.unwrap();
+
+	let response = app.get("/api/account/profile", Some(token)).await;
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	let response = app
+		.post("/auth/request-login-code", None, &json!({ "email": email }))
+		.await;
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body, "This account is disabled");
+}
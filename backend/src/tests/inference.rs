@@ -0,0 +1,272 @@
+use crate::llm::mock::INVALID_KEY;
+use crate::test_support::{mock_llm, TestApp};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+fn inference_request(provider: &str, model: &str, content: &str) -> Value {
+	json!({
+		"provider": provider,
+		"model": model,
+		"messages": [
+			{ "role": "system", "content": "Be brief" },
+			{ "role": "user", "content": content },
+		],
+		"api_key": "test-provider-key",
+	})
+}
+
+#[actix_web::test]
+async fn inference_goes_through_each_provider_client() {
+	let app = TestApp::start().await;
+	let models = [
+		("anthropic", "claude-3-haiku-20240307"),
+		("openai", "gpt-4o-mini"),
+		("google", "gemini-2.0-flash"),
+		("groq", "llama-3.1-8b-instant"),
+		("openrouter", "openai/gpt-4o"),
+	];
+
+	for (provider, model) in models {
+		let response = app
+			.post(
+				"/api/inference",
+				None,
+				&inference_request(provider, model, "Hello there"),
+			)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK, "{}", provider);
+		let body: Value = response.json().await.unwrap();
+		let expected = format!("Mock reply from {}: Hello there", model);
+		assert_eq!(body["content"], expected, "{}", provider);
+		assert_eq!(body["usage"]["input_tokens"], 4, "{}", provider);
+		assert_eq!(body["usage"]["output_tokens"], 6, "{}", provider);
+	}
+}
+
+#[actix_web::test]
+async fn anthropic_requests_carry_the_key_and_system_prompt() {
+	let app = TestApp::start().await;
+	let content = format!("Which request is this? {}", uuid::Uuid::new_v4());
+	app.post(
+		"/api/inference",
+		None,
+		&inference_request("anthropic", "claude-3-haiku-20240307", &content),
+	)
+	.await;
+
+	let request = mock_llm()
+		.received_requests()
+		.await
+		.into_iter()
+		.find(|r| String::from_utf8_lossy(&r.body).contains(&content))
+		.expect("the provider was not called");
+	assert_eq!(request.url.path(), "/v1/messages");
+	assert_eq!(request.headers["x-api-key"], "test-provider-key");
+	let body: Value = request.body_json().unwrap();
+	assert_eq!(body["system"], "Be brief");
+	assert_eq!(body["messages"][0]["role"], "user");
+}
+
+#[actix_web::test]
+async fn rejected_provider_keys_are_reported() {
+	let app = TestApp::start().await;
+	let mut request = inference_request("openai", "gpt-4o", "Hello");
+	request["api_key"] = json!(INVALID_KEY);
+
+	let response = app.post("/api/inference", None, &request).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body["code"], "UNAUTHORIZED");
+}
+
+#[actix_web::test]
+async fn invalid_provider_and_model_are_rejected() {
+	let app = TestApp::start().await;
+
+	let response = app
+		.post(
+			"/api/inference",
+			None,
+			&inference_request("nope", "gpt-4o", "Hello"),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body["code"], "INVALID_PROVIDER");
+
+	let response = app
+		.post(
+			"/api/inference",
+			None,
+			&inference_request("openai", "gpt-0", "Hello"),
+		)
+		.await;
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body["code"], "INVALID_MODEL");
+}
+
+#[actix_web::test]
+async fn streamed_deltas_add_up_to_the_reply() {
+	let app = TestApp::start().await;
+	let response = app
+		.post(
+			"/api/inference/stream",
+			None,
+			&inference_request("google", "gemini-1.5-flash", "Stream this please"),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let body = response.text().await.unwrap();
+
+	let chunks: Vec<Value> = body
+		.lines()
+		.filter_map(|line| line.strip_prefix("data: "))
+		.map(|data| serde_json::from_str(data).unwrap())
+		.collect();
+	let text: String = chunks
+		.iter()
+		.map(|chunk| chunk["delta"].as_str().unwrap())
+		.collect();
+	assert_eq!(text, "Mock reply from gemini-1.5-flash: Stream this please");
+	assert!(chunks.len() > 2);
+	assert_eq!(chunks.last().unwrap()["done"], true);
+}
+
+#[actix_web::test]
+async fn authenticated_inference_with_a_stored_key_is_metered() {
+	let app = TestApp::start().await;
+	let token = app.token().await;
+
+	let response = app
+		.post(
+			"/api/keys",
+			Some(&token),
+			&json!({ "provider": "openai", "api_key": "stored-provider-key" }),
+		)
+		.await;
+	assert!(response.status().is_success());
+	let key: Value = response.json().await.unwrap();
+
+	let response = app
+		.post(
+			"/api/inference",
+			Some(&token),
+			&json!({
+				"provider": "openai",
+				"model": "gpt-4o",
+				"messages": [{ "role": "user", "content": "Count my tokens" }],
+				"key_id": key["key_id"],
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let response = app.get("/api/usage?group_by=model", Some(&token)).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let usage: Value = response.json().await.unwrap();
+	assert_eq!(usage["total"]["requests"], 1);
+	assert_eq!(usage["total"]["input_tokens"], 3);
+	assert_eq!(usage["breakdown"][0]["key"], "gpt-4o");
+}
+
+#[actix_web::test]
+async fn embeddings_come_back_in_input_order() {
+	let app = TestApp::start().await;
+	let response = app
+		.post(
+			"/api/embeddings",
+			None,
+			&json!({
+				"provider": "openai",
+				"model": "text-embedding-3-small",
+				"input": ["one", "two words", "one"],
+				"api_key": "test-provider-key",
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let body: Value = response.json().await.unwrap();
+	let embeddings = body["embeddings"].as_array().unwrap();
+	assert_eq!(embeddings.len(), 3);
+	assert_eq!(embeddings[0], embeddings[2]);
+	assert_eq!(embeddings[1][1], 2.0);
+}
+
+#[actix_web::test]
+async fn fanout_streams_every_target_on_one_connection() {
+	let app = TestApp::start().await;
+	let response = app
+		.post(
+			"/api/inference/fanout",
+			None,
+			&json!({
+				"targets": [
+					{ "provider": "anthropic", "model": "claude-3-haiku-20240307", "api_key": "k" },
+					{ "provider": "groq", "model": "llama-3.1-8b-instant", "api_key": "k", "label": "fast" },
+					{ "provider": "openai", "model": "gpt-4o", "api_key": INVALID_KEY },
+				],
+				"messages": [{ "role": "user", "content": "Compare us" }],
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let body = response.text().await.unwrap();
+	let chunks: Vec<Value> = body
+		.lines()
+		.filter_map(|line| line.strip_prefix("data: "))
+		.map(|data| serde_json::from_str(data).unwrap())
+		.collect();
+
+	let answer = |target: u64| -> String {
+		chunks
+			.iter()
+			.filter(|chunk| chunk["target"] == target)
+			.filter_map(|chunk| chunk["delta"].as_str())
+			.collect()
+	};
+	assert_eq!(
+		answer(0),
+		"Mock reply from claude-3-haiku-20240307: Compare us"
+	);
+	assert_eq!(
+		answer(1),
+		"Mock reply from llama-3.1-8b-instant: Compare us"
+	);
+
+	let last_of = |target: u64| {
+		chunks
+			.iter()
+			.rfind(|chunk| chunk["target"] == target)
+			.unwrap()
+	};
+	assert_eq!(last_of(1)["label"], "fast");
+	assert_eq!(last_of(1)["done"], true);
+	assert_eq!(last_of(1)["usage"]["input_tokens"], 2);
+	assert_eq!(last_of(2)["error"]["code"], "UNAUTHORIZED");
+
+	let last = chunks.last().unwrap();
+	assert_eq!(last["done"], true);
+	assert!(last.get("target").is_none());
+}
+
+#[actix_web::test]
+async fn fanout_checks_every_target_before_streaming() {
+	let app = TestApp::start().await;
+	let response = app
+		.post(
+			"/api/inference/fanout",
+			None,
+			&json!({
+				"targets": [
+					{ "provider": "openai", "model": "gpt-4o", "api_key": "k" },
+					{ "provider": "openai", "model": "gpt-0", "api_key": "k" },
+				],
+				"messages": [{ "role": "user", "content": "Hello" }],
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body["code"], "INVALID_MODEL");
+	assert_eq!(body["error"], "Target 1: Invalid model");
+}
@@ -0,0 +1,61 @@
+use crate::database::{self, DatabaseKind};
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::Row;
+
+/// The first migration of the login code hashing series.
+const HASH_LOGIN_CODES: i64 = 20250725120000;
+
+static MIGRATIONS: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+#[actix_web::test]
+async fn upgrading_a_database_from_before_hashed_login_codes_needs_no_confirmation() {
+	let url = format!(
+		"sqlite:file:{}?mode=memory&cache=shared",
+		uuid::Uuid::new_v4().simple()
+	);
+	let pool = database::create_pool(&url, DatabaseKind::Sqlite)
+		.await
+		.unwrap();
+
+	// The database as the release before hashed login codes left it
+	{
+		let mut conn = pool.acquire().await.unwrap();
+		conn.ensure_migrations_table().await.unwrap();
+		for migration in MIGRATIONS.iter().filter(|m| m.version < HASH_LOGIN_CODES) {
+			conn.apply(migration).await.unwrap();
+		}
+	}
+	sqlx::query(
+		"INSERT INTO login_codes (email, code, expires_at, created_at)
+		 VALUES ('ada@example.com', '123456', '2025-07-24T12:00:00+00:00', '2025-07-24T11:55:00+00:00')",
+	)
+	.execute(&pool)
+	.await
+	.unwrap();
+
+	let pending = database::pending_migrations(&pool, DatabaseKind::Sqlite)
+		.await
+		.unwrap();
+	let hashing = pending
+		.iter()
+		.find(|m| m.version == HASH_LOGIN_CODES)
+		.unwrap();
+	assert!(!hashing.destructive.is_empty());
+	assert!(hashing.reviewed);
+
+	let applied = database::run_migrations(&pool, DatabaseKind::Sqlite, false)
+		.await
+		.expect("reviewed migrations should not need confirmation");
+	assert_eq!(applied.len(), pending.len());
+
+	let remaining: i64 = sqlx::query("SELECT COUNT(*) AS n FROM login_codes")
+		.fetch_one(&pool)
+		.await
+		.unwrap()
+		.get("n");
+	assert_eq!(remaining, 0);
+	assert!(database::pending_migrations(&pool, DatabaseKind::Sqlite)
+		.await
+		.unwrap()
+		.is_empty());
+}
@@ -0,0 +1,7 @@
+//! End-to-end tests against a running server, see `test_support`.
+
+mod auth;
+mod inference;
+mod migrations;
+mod openai_compat;
+mod replay;
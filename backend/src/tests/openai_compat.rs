@@ -0,0 +1,164 @@
+use crate::test_support::{mock_llm, TestApp};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+async fn store_key(app: &TestApp, token: &str, provider: &str, api_key: &str) {
+	let response = app
+		.post(
+			"/api/keys",
+			Some(token),
+			&json!({ "provider": provider, "api_key": api_key }),
+		)
+		.await;
+	assert!(response.status().is_success());
+}
+
+#[actix_web::test]
+async fn chat_completions_use_the_latest_stored_key_and_are_metered() {
+	let app = TestApp::start().await;
+	let token = app.token().await;
+	store_key(&app, &token, "openai", "older-provider-key").await;
+	store_key(&app, &token, "openai", "stored-provider-key").await;
+
+	let content = format!("Which key is this? {}", uuid::Uuid::new_v4());
+	let response = app
+		.post(
+			"/v1/chat/completions",
+			Some(&token),
+			&json!({
+				"model": "gpt-4o",
+				"messages": [
+					{ "role": "developer", "content": "Be brief" },
+					{ "role": "user", "content": [{ "type": "text", "text": content }] },
+				],
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body["object"], "chat.completion");
+	assert_eq!(body["model"], "gpt-4o");
+	assert_eq!(body["choices"][0]["message"]["role"], "assistant");
+	assert_eq!(
+		body["choices"][0]["message"]["content"],
+		format!("Mock reply from gpt-4o: {}", content)
+	);
+	assert_eq!(body["choices"][0]["finish_reason"], "stop");
+	let usage = &body["usage"];
+	assert_eq!(
+		usage["total_tokens"],
+		usage["prompt_tokens"].as_u64().unwrap()
+			+ usage["completion_tokens"].as_u64().unwrap()
+	);
+
+	let request = mock_llm()
+		.received_requests()
+		.await
+		.into_iter()
+		.find(|r| String::from_utf8_lossy(&r.body).contains(&content))
+		.expect("the provider was not called");
+	assert_eq!(
+		request.headers["authorization"],
+		"Bearer stored-provider-key"
+	);
+
+	let response = app.get("/api/usage", Some(&token)).await;
+	let usage: Value = response.json().await.unwrap();
+	assert_eq!(usage["total"]["requests"], 1);
+}
+
+#[actix_web::test]
+async fn streamed_chat_completions_end_with_usage_and_done() {
+	let app = TestApp::start().await;
+	let token = app.token().await;
+	store_key(&app, &token, "anthropic", "stored-provider-key").await;
+
+	let response = app
+		.post(
+			"/v1/chat/completions",
+			Some(&token),
+			&json!({
+				"model": "anthropic/claude-3-haiku-20240307",
+				"messages": [{ "role": "user", "content": "Stream this please" }],
+				"stream": true,
+				"stream_options": { "include_usage": true },
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let body = response.text().await.unwrap();
+	let data: Vec<&str> = body
+		.lines()
+		.filter_map(|line| line.strip_prefix("data: "))
+		.collect();
+	assert_eq!(data.last(), Some(&"[DONE]"));
+
+	let chunks: Vec<Value> = data[..data.len() - 1]
+		.iter()
+		.map(|data| serde_json::from_str(data).unwrap())
+		.collect();
+	assert!(chunks
+		.iter()
+		.all(|c| c["object"] == "chat.completion.chunk"));
+	assert!(chunks.iter().all(|c| c["id"] == chunks[0]["id"]));
+	assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+
+	let text: String = chunks
+		.iter()
+		.filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+		.collect();
+	assert_eq!(
+		text,
+		"Mock reply from claude-3-haiku-20240307: Stream this please"
+	);
+
+	let finished = &chunks[chunks.len() - 2];
+	assert_eq!(finished["choices"][0]["finish_reason"], "stop");
+	let usage = chunks.last().unwrap();
+	assert_eq!(usage["choices"], json!([]));
+	assert_eq!(usage["usage"]["prompt_tokens"], 3);
+}
+
+#[actix_web::test]
+async fn chat_completion_errors_use_the_openai_shape() {
+	let app = TestApp::start().await;
+	let request = json!({
+		"model": "gpt-4o",
+		"messages": [{ "role": "user", "content": "Hello" }],
+	});
+
+	let response = app.post("/v1/chat/completions", None, &request).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let token = app.token().await;
+	let response = app
+		.post("/v1/chat/completions", Some(&token), &request)
+		.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body["error"]["type"], "invalid_request_error");
+	assert_eq!(body["error"]["code"], "missing_api_key");
+
+	let response = app
+		.post(
+			"/v1/chat/completions",
+			Some(&token),
+			&json!({
+				"model": "mystery-model",
+				"messages": [{ "role": "user", "content": "Hello" }],
+			}),
+		)
+		.await;
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(body["error"]["code"], "invalid_model");
+
+	let response = app.get("/v1/models", Some(&token)).await;
+	let body: Value = response.json().await.unwrap();
+	let ids: Vec<&str> = body["data"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.filter_map(|model| model["id"].as_str())
+		.collect();
+	assert!(ids.contains(&"anthropic/claude-3-haiku-20240307"));
+}
@@ -0,0 +1,110 @@
+use crate::llm::{
+	clients::OpenAIClient,
+	providers::LLMProvider,
+	replay::{Fixture, RecordingClient},
+	types::*,
+};
+use crate::test_support::TestApp;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::{env, fs, path::PathBuf};
+
+fn fixture_path(containing: &str) -> Option<PathBuf> {
+	let dir = env::var("LLM_FIXTURES_DIR").unwrap();
+	fs::read_dir(dir)
+		.ok()?
+		.filter_map(|entry| entry.ok().map(|e| e.path()))
+		.find(|path| fs::read_to_string(path).is_ok_and(|c| c.contains(containing)))
+}
+
+#[actix_web::test]
+async fn recorded_streams_are_replayed_by_the_mock_provider() {
+	let app = TestApp::start().await;
+	let content = format!("Record this {}", uuid::Uuid::new_v4());
+
+	let client = RecordingClient::new(LLMProvider::OpenAI, Box::new(OpenAIClient::new()));
+	let request = LLMClientCompletionRequest::new(
+		LLMType::Gpt4OMini,
+		vec![LLMClientMessage::user(content.clone())],
+		0.7,
+	);
+	let response = client
+		.completion("test-provider-key".to_string(), request)
+		.await
+		.unwrap();
+
+	let path = fixture_path(&content).expect("no fixture was recorded");
+	let mut fixture: Fixture = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+	assert_eq!(fixture.provider, "openai");
+	assert_eq!(fixture.model, "gpt-4o-mini");
+	assert_eq!(fixture.chunks.concat(), response.answer_up_until_now());
+
+	// Replays come from the file, not from the canned reply
+	fixture.chunks = vec!["Recorded ".to_string(), "answer".to_string()];
+	fs::write(&path, serde_json::to_vec(&fixture).unwrap()).unwrap();
+
+	let response = app
+		.post(
+			"/api/inference/stream",
+			None,
+			&json!({
+				"provider": "mock",
+				"model": "gpt-4o-mini",
+				"messages": [{ "role": "user", "content": content }],
+				"temperature": 0.2,
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let body = response.text().await.unwrap();
+	let deltas: Vec<String> = body
+		.lines()
+		.filter_map(|line| line.strip_prefix("data: "))
+		.filter_map(|data| serde_json::from_str::<Value>(data).ok())
+		.filter(|chunk| chunk["done"] != true)
+		.filter_map(|chunk| chunk["delta"].as_str().map(str::to_string))
+		.collect();
+	assert_eq!(deltas, ["Recorded ", "answer"]);
+}
+
+#[actix_web::test]
+async fn mock_provider_needs_no_key_and_falls_back_to_a_canned_reply() {
+	let app = TestApp::start().await;
+
+	let response = app
+		.post(
+			"/api/inference",
+			None,
+			&json!({
+				"provider": "mock",
+				"model": "any-model",
+				"messages": [{ "role": "user", "content": "Nothing recorded" }],
+			}),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let body: Value = response.json().await.unwrap();
+	assert_eq!(
+		body["content"],
+		"Mock reply from any-model: Nothing recorded"
+	);
+	assert!(body["usage"]["output_tokens"].as_u64().unwrap() > 0);
+
+	let response = app.get("/api/providers", None).await;
+	let body: Value = response.json().await.unwrap();
+	assert!(body["providers"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.any(|p| p["name"] == "mock"));
+
+	let token = app.token().await;
+	let response = app
+		.post(
+			"/api/keys",
+			Some(&token),
+			&json!({ "provider": "mock", "api_key": "unused" }),
+		)
+		.await;
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
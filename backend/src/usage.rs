@@ -0,0 +1,420 @@
+use crate::database::DbPool;
+use crate::{
+	auth::AuthenticatedAccount,
+	errors::internal_error,
+	llm::{api::ApiError, types::LLMClientUsageStatistics},
+	orgs::{require_role, OrgRole},
+};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+/// USD price per million input and output tokens.
+fn model_pricing(model: &str) -> Option<(f64, f64)> {
+	let pricing = match model {
+		"claude-3-opus-20240229" => (15.0, 75.0),
+		"claude-3-5-sonnet-20241022" => (3.0, 15.0),
+		"claude-3-haiku-20240307" => (0.25, 1.25),
+		"gpt-4o" | "openai/gpt-4o" => (2.5, 10.0),
+		"gpt-4o-mini" => (0.15, 0.6),
+		"gpt-4-turbo" => (10.0, 30.0),
+		"o1" => (15.0, 60.0),
+		"o1-mini" => (3.0, 12.0),
+		"text-embedding-3-small" => (0.02, 0.0),
+		"text-embedding-3-large" => (0.13, 0.0),
+		"text-embedding-ada-002" => (0.1, 0.0),
+		"gemini-1.5-pro" => (1.25, 5.0),
+		"gemini-1.5-flash" => (0.075, 0.3),
+		"gemini-2.0-flash" => (0.1, 0.4),
+		"llama-3.1-8b-instant" => (0.05, 0.08),
+		"llama-3.1-70b-versatile" => (0.59, 0.79),
+		model if model.starts_with("anthropic/claude-3.5-sonnet") => (3.0, 15.0),
+		_ => return None,
+	};
+	Some(pricing)
+}
+
+pub fn estimate_cost_usd(model: &str, input_tokens: u32, output_tokens: u32) -> f64 {
+	match model_pricing(model) {
+		Some((input_price, output_price)) => {
+			(input_tokens as f64 * input_price + output_tokens as f64 * output_price)
+				/ 1_000_000.0
+		}
+		None => {
+			warn!("No pricing known for model {}, recording zero cost", model);
+			0.0
+		}
+	}
+}
+
+fn tokenizer() -> Option<&'static CoreBPE> {
+	static TOKENIZER: OnceLock<Option<CoreBPE>> = OnceLock::new();
+	TOKENIZER
+		.get_or_init(|| tiktoken_rs::cl100k_base().ok())
+		.as_ref()
+}
+
+/// Rough token count for providers that don't report usage on a stream.
+pub fn estimate_tokens(text: &str) -> u32 {
+	match tokenizer() {
+		Some(bpe) => bpe.encode_ordinary(text).len() as u32,
+		None => (text.len() / 4) as u32,
+	}
+}
+
+pub fn start_of_month(now: DateTime<Utc>) -> DateTime<Utc> {
+	Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+		.single()
+		.unwrap_or(now)
+}
+
+/// Appends a usage event for one completed inference request, attributed to
+/// `org_id` when made in an org context. `prompt` and `answer` are only used
+/// to estimate token counts the provider didn't report. Returns the recorded
+/// input and output token counts.
+#[allow(clippy::too_many_arguments)]
+pub async fn record_usage(
+	pool: &DbPool,
+	account_id: &str,
+	org_id: Option<&str>,
+	provider: &str,
+	model: &str,
+	usage: &LLMClientUsageStatistics,
+	prompt: &str,
+	answer: &str,
+) -> Result<(u32, u32), sqlx::Error> {
+	let input_tokens = usage
+		.input_tokens()
+		.unwrap_or_else(|| estimate_tokens(prompt));
+	let output_tokens = usage
+		.output_tokens()
+		.unwrap_or_else(|| estimate_tokens(answer));
+	let cost_usd = estimate_cost_usd(model, input_tokens, output_tokens);
+	let now = Utc::now().to_rfc3339();
+
+	sqlx::query(
+		"INSERT INTO usage_events
+		 (account_id, org_id, provider, model, input_tokens, output_tokens, cost_usd,
+		  created_at)
+		 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+	)
+	.bind(account_id)
+	.bind(org_id)
+	.bind(provider)
+	.bind(model)
+	.bind(input_tokens as i64)
+	.bind(output_tokens as i64)
+	.bind(cost_usd)
+	.bind(now)
+	.execute(pool)
+	.await?;
+
+	Ok((input_tokens, output_tokens))
+}
+
+/// Who a usage query covers: an account's personal usage, or everything billed
+/// to an org.
+#[derive(Debug, Clone, Copy)]
+pub enum UsageScope<'a> {
+	Account(&'a str),
+	Org(&'a str),
+}
+
+impl UsageScope<'_> {
+	/// `WHERE` condition on `usage_events` with the scope id bound as `$1`.
+	fn condition(&self) -> &'static str {
+		match self {
+			UsageScope::Account(_) => "account_id = $1 AND org_id IS NULL",
+			UsageScope::Org(_) => "org_id = $1",
+		}
+	}
+
+	fn id(&self) -> &str {
+		match self {
+			UsageScope::Account(id) | UsageScope::Org(id) => id,
+		}
+	}
+}
+
+/// Total cost recorded for the scope since the start of the current month.
+pub async fn month_to_date_cost(
+	pool: &DbPool,
+	scope: UsageScope<'_>,
+) -> Result<f64, sqlx::Error> {
+	let since = start_of_month(Utc::now()).to_rfc3339();
+	sqlx::query_scalar(&format!(
+		"SELECT CAST(COALESCE(SUM(cost_usd), 0) AS DOUBLE PRECISION) FROM usage_events
+		 WHERE {} AND created_at >= $2",
+		scope.condition()
+	))
+	.bind(scope.id())
+	.bind(since)
+	.fetch_one(pool)
+	.await
+}
+
+async fn monthly_limit(
+	pool: &DbPool,
+	account_id: &str,
+) -> Result<Option<f64>, sqlx::Error> {
+	let limit: Option<Option<f64>> = sqlx::query_scalar(
+		"SELECT monthly_spend_limit_usd FROM accounts WHERE account_id = $1",
+	)
+	.bind(account_id)
+	.fetch_optional(pool)
+	.await?;
+
+	Ok(limit.flatten())
+}
+
+/// Returns a `QUOTA_EXCEEDED` response when the account has reached its
+/// monthly spend cap. Accounts without a cap are never blocked, and usage
+/// billed to an org doesn't count towards it.
+pub async fn enforce_spend_limit(
+	pool: &DbPool,
+	account_id: &str,
+) -> Result<(), HttpResponse> {
+	let check = async {
+		let Some(limit) = monthly_limit(pool, account_id).await? else {
+			return Ok(None);
+		};
+		let spent = month_to_date_cost(pool, UsageScope::Account(account_id)).await?;
+		Ok::<_, sqlx::Error>((spent >= limit).then_some((spent, limit)))
+	};
+
+	match check.await {
+		Ok(None) => Ok(()),
+		Ok(Some((spent, limit))) => Err(HttpResponse::PaymentRequired().json(ApiError {
+			error: format!(
+				"Monthly spend limit of ${:.2} reached (${:.2} spent)",
+				limit, spent
+			),
+			code: "QUOTA_EXCEEDED".to_string(),
+		})),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Err(internal_error())
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGrouping {
+	#[default]
+	Day,
+	Provider,
+	Model,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+	/// RFC 3339 lower bound, defaults to the start of the current month.
+	pub from: Option<String>,
+	/// RFC 3339 upper bound (exclusive), defaults to now.
+	pub to: Option<String>,
+	#[serde(default)]
+	pub group_by: UsageGrouping,
+	/// Report usage billed to this org instead. Requires the admin role.
+	pub org_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct UsageTotals {
+	pub requests: i64,
+	pub input_tokens: i64,
+	pub output_tokens: i64,
+	pub cost_usd: f64,
+}
+
+impl UsageTotals {
+	fn add(&mut self, other: &UsageTotals) {
+		self.requests += other.requests;
+		self.input_tokens += other.input_tokens;
+		self.output_tokens += other.output_tokens;
+		self.cost_usd += other.cost_usd;
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageBucket {
+	pub key: String,
+	#[serde(flatten)]
+	pub totals: UsageTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageSummaryResponse {
+	pub from: String,
+	pub to: String,
+	pub total: UsageTotals,
+	pub breakdown: Vec<UsageBucket>,
+	pub monthly_limit_usd: Option<f64>,
+	pub month_to_date_cost_usd: f64,
+}
+
+#[derive(FromRow)]
+struct UsageRow {
+	day: String,
+	provider: String,
+	model: String,
+	requests: i64,
+	input_tokens: i64,
+	output_tokens: i64,
+	cost_usd: f64,
+}
+
+/// Normalizes an optional RFC 3339 query bound to UTC, or returns `default`.
+pub fn parse_bound(
+	value: Option<&str>,
+	default: DateTime<Utc>,
+) -> Result<String, ApiError> {
+	match value {
+		None => Ok(default.to_rfc3339()),
+		Some(raw) => DateTime::parse_from_rfc3339(raw)
+			.map(|d| d.with_timezone(&Utc).to_rfc3339())
+			.map_err(|_| ApiError {
+				error: format!("Invalid RFC 3339 timestamp: {}", raw),
+				code: "INVALID_DATE".to_string(),
+			}),
+	}
+}
+
+pub async fn get_usage(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	query: web::Query<UsageQuery>,
+) -> ActixResult<HttpResponse> {
+	let now = Utc::now();
+	let from = match parse_bound(query.from.as_deref(), start_of_month(now)) {
+		Ok(from) => from,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+	let to = match parse_bound(query.to.as_deref(), now) {
+		Ok(to) => to,
+		Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+	};
+
+	let scope = match &query.org_id {
+		Some(org_id) => {
+			if let Err(response) =
+				require_role(pool.get_ref(), org_id, &account.account_id, OrgRole::Admin)
+					.await
+			{
+				return Ok(response);
+			}
+			UsageScope::Org(org_id)
+		}
+		None => UsageScope::Account(&account.account_id),
+	};
+
+	match summarize_usage(pool.get_ref(), scope, from, to, query.group_by).await {
+		Ok(summary) => Ok(HttpResponse::Ok().json(summary)),
+		Err(e) => {
+			error!("Database error: {}", e);
+			Ok(internal_error())
+		}
+	}
+}
+
+/// Usage of the scope between `from` and `to`, broken down by `group_by`.
+pub async fn summarize_usage(
+	pool: &DbPool,
+	scope: UsageScope<'_>,
+	from: String,
+	to: String,
+	group_by: UsageGrouping,
+) -> Result<UsageSummaryResponse, sqlx::Error> {
+	let rows = sqlx::query_as::<_, UsageRow>(&format!(
+		"SELECT substr(created_at, 1, 10) AS day, provider, model,
+		   COUNT(*) AS requests,
+		   CAST(SUM(input_tokens) AS BIGINT) AS input_tokens,
+		   CAST(SUM(output_tokens) AS BIGINT) AS output_tokens,
+		   CAST(SUM(cost_usd) AS DOUBLE PRECISION) AS cost_usd
+		 FROM usage_events
+		 WHERE {} AND created_at >= $2 AND created_at < $3
+		 GROUP BY 1, provider, model",
+		scope.condition()
+	))
+	.bind(scope.id())
+	.bind(&from)
+	.bind(&to)
+	.fetch_all(pool)
+	.await?;
+
+	let mut total = UsageTotals::default();
+	let mut buckets: BTreeMap<String, UsageTotals> = BTreeMap::new();
+	for row in rows {
+		let totals = UsageTotals {
+			requests: row.requests,
+			input_tokens: row.input_tokens,
+			output_tokens: row.output_tokens,
+			cost_usd: row.cost_usd,
+		};
+		let key = match group_by {
+			UsageGrouping::Day => row.day,
+			UsageGrouping::Provider => row.provider,
+			UsageGrouping::Model => row.model,
+		};
+		total.add(&totals);
+		buckets.entry(key).or_default().add(&totals);
+	}
+
+	// Orgs have no spend cap of their own
+	let monthly_limit_usd = match scope {
+		UsageScope::Account(account_id) => monthly_limit(pool, account_id).await?,
+		UsageScope::Org(_) => None,
+	};
+	let month_to_date_cost_usd = month_to_date_cost(pool, scope).await?;
+
+	Ok(UsageSummaryResponse {
+		from,
+		to,
+		total,
+		breakdown: buckets
+			.into_iter()
+			.map(|(key, totals)| UsageBucket { key, totals })
+			.collect(),
+		monthly_limit_usd,
+		month_to_date_cost_usd,
+	})
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpendLimitRequest {
+	/// `null` removes the cap.
+	pub monthly_limit_usd: Option<f64>,
+}
+
+pub async fn set_spend_limit(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	body: web::Json<SpendLimitRequest>,
+) -> ActixResult<HttpResponse> {
+	let limit = body.monthly_limit_usd;
+	if matches!(limit, Some(l) if !l.is_finite() || l < 0.0) {
+		return Ok(HttpResponse::BadRequest().json(ApiError {
+			error: "Spend limit must be a non-negative amount".to_string(),
+			code: "INVALID_LIMIT".to_string(),
+		}));
+	}
+
+	if let Err(e) = sqlx::query(
+		"UPDATE accounts SET monthly_spend_limit_usd = $1 WHERE account_id = $2",
+	)
+	.bind(limit)
+	.bind(&account.account_id)
+	.execute(pool.get_ref())
+	.await
+	{
+		error!("Database error: {}", e);
+		return Ok(internal_error());
+	}
+
+	Ok(HttpResponse::Ok().json(serde_json::json!({ "monthly_limit_usd": limit })))
+}
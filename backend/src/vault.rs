@@ -0,0 +1,514 @@
+use crate::{
+	audit::{self, AuditEvent, ClientInfo},
+	auth::AuthenticatedAccount,
+	database::DbPool,
+	errors::internal_error,
+	llm::api::ApiError,
+	llm::providers::LLMProvider,
+	orgs::{require_role, OrgRole},
+};
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use aes_gcm::{
+	aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+	Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::env;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum VaultError {
+	#[error("Key not found")]
+	NotFound,
+
+	#[error("Key belongs to provider {0}")]
+	ProviderMismatch(String),
+
+	#[error("Failed to encrypt or decrypt key")]
+	Crypto,
+
+	#[error("Database error: {0}")]
+	Database(#[from] sqlx::Error),
+}
+
+/// Who a stored key belongs to: one account, or an organization whose members
+/// all use it.
+#[derive(Debug, Clone, Copy)]
+pub enum KeyOwner<'a> {
+	Account(&'a str),
+	Org(&'a str),
+}
+
+impl KeyOwner<'_> {
+	/// Associated data bound into the ciphertext. Org keys are prefixed so an
+	/// org id can never collide with an account id.
+	fn aad(&self) -> String {
+		match self {
+			KeyOwner::Account(account_id) => account_id.to_string(),
+			KeyOwner::Org(org_id) => format!("org:{}", org_id),
+		}
+	}
+}
+
+/// Encrypts provider API keys at rest with AES-256-GCM. The owning account
+/// (or org) is bound as associated data so a ciphertext can't be replayed on
+/// another owner's row.
+#[derive(Clone)]
+pub struct KeyVault {
+	cipher: Aes256Gcm,
+}
+
+impl KeyVault {
+	pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+		let encoded = env::var("VAULT_ENCRYPTION_KEY")?;
+		let key_bytes = BASE64.decode(encoded.trim())?;
+		if key_bytes.len() != 32 {
+			return Err("VAULT_ENCRYPTION_KEY must be 32 bytes encoded as base64".into());
+		}
+
+		let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+		Ok(KeyVault {
+			cipher: Aes256Gcm::new(key),
+		})
+	}
+
+	fn encrypt(
+		&self,
+		owner: KeyOwner<'_>,
+		plaintext: &str,
+	) -> Result<(String, String), VaultError> {
+		let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+		let ciphertext = self
+			.cipher
+			.encrypt(
+				&nonce,
+				Payload {
+					msg: plaintext.as_bytes(),
+					aad: owner.aad().as_bytes(),
+				},
+			)
+			.map_err(|_| VaultError::Crypto)?;
+
+		Ok((BASE64.encode(ciphertext), BASE64.encode(nonce)))
+	}
+
+	fn decrypt(
+		&self,
+		owner: KeyOwner<'_>,
+		ciphertext: &str,
+		nonce: &str,
+	) -> Result<String, VaultError> {
+		let ciphertext = BASE64.decode(ciphertext).map_err(|_| VaultError::Crypto)?;
+		let nonce = BASE64.decode(nonce).map_err(|_| VaultError::Crypto)?;
+		if nonce.len() != 12 {
+			return Err(VaultError::Crypto);
+		}
+
+		let plaintext = self
+			.cipher
+			.decrypt(
+				Nonce::from_slice(&nonce),
+				Payload {
+					msg: &ciphertext,
+					aad: owner.aad().as_bytes(),
+				},
+			)
+			.map_err(|_| VaultError::Crypto)?;
+
+		String::from_utf8(plaintext).map_err(|_| VaultError::Crypto)
+	}
+
+	/// Looks up a stored key owned by `owner` and returns the decrypted
+	/// secret, checking that it was stored for `provider`.
+	pub async fn resolve_key(
+		&self,
+		pool: &DbPool,
+		owner: KeyOwner<'_>,
+		key_id: &str,
+		provider: &LLMProvider,
+	) -> Result<String, VaultError> {
+		let query = match owner {
+			KeyOwner::Account(_) => {
+				"SELECT provider, ciphertext, nonce FROM provider_keys
+				 WHERE key_id = $1 AND account_id = $2 AND org_id IS NULL"
+			}
+			KeyOwner::Org(_) => {
+				"SELECT provider, ciphertext, nonce FROM provider_keys
+				 WHERE key_id = $1 AND org_id = $2"
+			}
+		};
+		let owner_id = match owner {
+			KeyOwner::Account(id) | KeyOwner::Org(id) => id,
+		};
+
+		let row = sqlx::query_as::<_, StoredKey>(query)
+			.bind(key_id)
+			.bind(owner_id)
+			.fetch_optional(pool)
+			.await?
+			.ok_or(VaultError::NotFound)?;
+
+		if row.provider != provider.to_string() {
+			return Err(VaultError::ProviderMismatch(row.provider));
+		}
+
+		self.decrypt(owner, &row.ciphertext, &row.nonce)
+	}
+
+	/// Returns the decrypted secret of the key `owner` most recently stored
+	/// for `provider`, for clients that can't name a key id.
+	pub async fn resolve_latest_key(
+		&self,
+		pool: &DbPool,
+		owner: KeyOwner<'_>,
+		provider: &LLMProvider,
+	) -> Result<String, VaultError> {
+		let query = match owner {
+			KeyOwner::Account(_) => {
+				"SELECT provider, ciphertext, nonce FROM provider_keys
+				 WHERE provider = $1 AND account_id = $2 AND org_id IS NULL
+				 ORDER BY created_at DESC LIMIT 1"
+			}
+			KeyOwner::Org(_) => {
+				"SELECT provider, ciphertext, nonce FROM provider_keys
+				 WHERE provider = $1 AND org_id = $2
+				 ORDER BY created_at DESC LIMIT 1"
+			}
+		};
+		let owner_id = match owner {
+			KeyOwner::Account(id) | KeyOwner::Org(id) => id,
+		};
+
+		let row = sqlx::query_as::<_, StoredKey>(query)
+			.bind(provider.to_string())
+			.bind(owner_id)
+			.fetch_optional(pool)
+			.await?
+			.ok_or(VaultError::NotFound)?;
+
+		self.decrypt(owner, &row.ciphertext, &row.nonce)
+	}
+}
+
+#[derive(FromRow)]
+struct StoredKey {
+	provider: String,
+	ciphertext: String,
+	nonce: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StoreKeyRequest {
+	pub provider: String,
+	pub label: Option<String>,
+	pub api_key: String,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ProviderKeyInfo {
+	pub key_id: String,
+	pub provider: String,
+	pub label: String,
+	pub key_hint: String,
+	pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProviderKeysResponse {
+	pub keys: Vec<ProviderKeyInfo>,
+}
+
+fn key_hint(api_key: &str) -> String {
+	let chars: Vec<char> = api_key.chars().collect();
+	if chars.len() <= 8 {
+		return "****".to_string();
+	}
+	let suffix: String = chars[chars.len() - 4..].iter().collect();
+	format!("****{}", suffix)
+}
+
+async fn insert_key(
+	pool: &DbPool,
+	vault: &KeyVault,
+	account_id: &str,
+	org_id: Option<&str>,
+	client: &ClientInfo,
+	request: StoreKeyRequest,
+) -> HttpResponse {
+	// The mock provider takes no key
+	let provider = match LLMProvider::from_str(&request.provider) {
+		Some(LLMProvider::Mock) | None => {
+			return HttpResponse::BadRequest().json(ApiError {
+				error: "Invalid provider".to_string(),
+				code: "INVALID_PROVIDER".to_string(),
+			})
+		}
+		Some(p) => p,
+	};
+
+	let api_key = request.api_key.trim();
+	if api_key.is_empty() {
+		return HttpResponse::BadRequest().json(ApiError {
+			error: "API key must not be empty".to_string(),
+			code: "INVALID_API_KEY".to_string(),
+		});
+	}
+
+	let owner = match org_id {
+		Some(org_id) => KeyOwner::Org(org_id),
+		None => KeyOwner::Account(account_id),
+	};
+	let (ciphertext, nonce) = match vault.encrypt(owner, api_key) {
+		Ok(encrypted) => encrypted,
+		Err(e) => {
+			error!("Failed to encrypt provider key: {}", e);
+			return internal_error();
+		}
+	};
+
+	let key_id = Uuid::new_v4().to_string();
+	let provider_name = provider.to_string();
+	let label = request.label.unwrap_or_else(|| provider_name.clone());
+	let hint = key_hint(api_key);
+	let now = Utc::now().to_rfc3339();
+
+	if let Err(e) = sqlx::query(
+		"INSERT INTO provider_keys
+		 (key_id, account_id, org_id, provider, label, ciphertext, nonce, key_hint,
+		  created_at)
+		 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+	)
+	.bind(&key_id)
+	.bind(account_id)
+	.bind(org_id)
+	.bind(&provider_name)
+	.bind(&label)
+	.bind(&ciphertext)
+	.bind(&nonce)
+	.bind(&hint)
+	.bind(&now)
+	.execute(pool)
+	.await
+	{
+		error!("Database error: {}", e);
+		return internal_error();
+	}
+
+	audit::record(
+		pool,
+		account_id,
+		client,
+		AuditEvent::KeyCreated {
+			key_id: &key_id,
+			provider: &provider_name,
+			org_id,
+		},
+	)
+	.await;
+
+	HttpResponse::Created().json(ProviderKeyInfo {
+		key_id,
+		provider: provider_name,
+		label,
+		key_hint: hint,
+		created_at: now,
+	})
+}
+
+fn key_not_found() -> HttpResponse {
+	HttpResponse::NotFound().json(ApiError {
+		error: "Key not found".to_string(),
+		code: "KEY_NOT_FOUND".to_string(),
+	})
+}
+
+pub async fn store_key(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	account: AuthenticatedAccount,
+	client: ClientInfo,
+	body: web::Json<StoreKeyRequest>,
+) -> ActixResult<HttpResponse> {
+	Ok(insert_key(
+		pool.get_ref(),
+		vault.get_ref(),
+		&account.account_id,
+		None,
+		&client,
+		body.into_inner(),
+	)
+	.await)
+}
+
+pub async fn list_keys(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+) -> ActixResult<HttpResponse> {
+	let keys = match sqlx::query_as::<_, ProviderKeyInfo>(
+		"SELECT key_id, provider, label, key_hint, created_at FROM provider_keys
+		 WHERE account_id = $1 AND org_id IS NULL ORDER BY created_at",
+	)
+	.bind(&account.account_id)
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(keys) => keys,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	Ok(HttpResponse::Ok().json(ProviderKeysResponse { keys }))
+}
+
+pub async fn delete_key(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	client: ClientInfo,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let key_id = path.into_inner();
+
+	let result = match sqlx::query(
+		"DELETE FROM provider_keys
+		 WHERE key_id = $1 AND account_id = $2 AND org_id IS NULL",
+	)
+	.bind(&key_id)
+	.bind(&account.account_id)
+	.execute(pool.get_ref())
+	.await
+	{
+		Ok(result) => result,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	if result.rows_affected() == 0 {
+		return Ok(key_not_found());
+	}
+
+	audit::record(
+		pool.get_ref(),
+		&account.account_id,
+		&client,
+		AuditEvent::KeyDeleted {
+			key_id: &key_id,
+			org_id: None,
+		},
+	)
+	.await;
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Stores a key shared with the whole org. Admins only.
+pub async fn store_org_key(
+	pool: web::Data<DbPool>,
+	vault: web::Data<KeyVault>,
+	account: AuthenticatedAccount,
+	client: ClientInfo,
+	path: web::Path<String>,
+	body: web::Json<StoreKeyRequest>,
+) -> ActixResult<HttpResponse> {
+	let org_id = path.into_inner();
+	if let Err(response) =
+		require_role(pool.get_ref(), &org_id, &account.account_id, OrgRole::Admin).await
+	{
+		return Ok(response);
+	}
+
+	Ok(insert_key(
+		pool.get_ref(),
+		vault.get_ref(),
+		&account.account_id,
+		Some(&org_id),
+		&client,
+		body.into_inner(),
+	)
+	.await)
+}
+
+/// Lists the org's shared keys; any member can see (and use) them.
+pub async fn list_org_keys(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	path: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+	let org_id = path.into_inner();
+	if let Err(response) = require_role(
+		pool.get_ref(),
+		&org_id,
+		&account.account_id,
+		OrgRole::Member,
+	)
+	.await
+	{
+		return Ok(response);
+	}
+
+	let keys = match sqlx::query_as::<_, ProviderKeyInfo>(
+		"SELECT key_id, provider, label, key_hint, created_at FROM provider_keys
+		 WHERE org_id = $1 ORDER BY created_at",
+	)
+	.bind(&org_id)
+	.fetch_all(pool.get_ref())
+	.await
+	{
+		Ok(keys) => keys,
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	};
+
+	Ok(HttpResponse::Ok().json(ProviderKeysResponse { keys }))
+}
+
+pub async fn delete_org_key(
+	pool: web::Data<DbPool>,
+	account: AuthenticatedAccount,
+	client: ClientInfo,
+	path: web::Path<(String, String)>,
+) -> ActixResult<HttpResponse> {
+	let (org_id, key_id) = path.into_inner();
+	if let Err(response) =
+		require_role(pool.get_ref(), &org_id, &account.account_id, OrgRole::Admin).await
+	{
+		return Ok(response);
+	}
+
+	match sqlx::query("DELETE FROM provider_keys WHERE key_id = $1 AND org_id = $2")
+		.bind(&key_id)
+		.bind(&org_id)
+		.execute(pool.get_ref())
+		.await
+	{
+		Ok(result) if result.rows_affected() == 0 => return Ok(key_not_found()),
+		Ok(_) => {}
+		Err(e) => {
+			error!("Database error: {}", e);
+			return Ok(internal_error());
+		}
+	}
+
+	audit::record(
+		pool.get_ref(),
+		&account.account_id,
+		&client,
+		AuditEvent::KeyDeleted {
+			key_id: &key_id,
+			org_id: Some(&org_id),
+		},
+	)
+	.await;
+
+	Ok(HttpResponse::NoContent().finish())
+}
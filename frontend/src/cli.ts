@@ -32,6 +32,10 @@ interface AuthResponse {
 		email: string;
 		account_id: string;
 	};
+	profile?: {
+		display_name: string | null;
+		timezone: string;
+	};
 }
 
 const __filename = fileURLToPath(import.meta.url);
@@ -256,14 +260,30 @@ async function status(): Promise<void> {
 	}
 }
 
-// Launch IDE
-async function launchIDE(): Promise<void> {
+// Launch IDE, optionally opening a folder or file. A running instance
+// receives the path and opens it instead of starting a second window.
+async function launchIDE(target?: string): Promise<void> {
 	console.log("Launching ariana IDE...");
 
+	const args: string[] = [];
+	if (target) {
+		const targetPath = path.resolve(target);
+		try {
+			await fs.access(targetPath);
+		} catch {
+			console.error(`❌ No such file or directory: ${targetPath}`);
+			return;
+		}
+		args.push(targetPath);
+	}
+
 	const isDev = await isDevelopmentMode();
 	if (isDev) {
 		console.log("Development mode: Running Tauri dev server...");
-		const tauriProcess = spawn("npm", ["run", "dev-tauri"], {
+		// npm strips one `--` for dev-tauri and one for the tauri script;
+		// `tauri dev` then passes what follows the second of its two to the app
+		const devArgs = args.length > 0 ? ["--", "--", "--", "--", ...args] : [];
+		const tauriProcess = spawn("npm", ["run", "dev-tauri", ...devArgs], {
 			cwd: path.join(__dirname, ".."),
 			stdio: "inherit",
 			shell: true,
@@ -294,7 +314,7 @@ async function launchIDE(): Promise<void> {
 
 		try {
 			await fs.access(binaryPath);
-			const ideProcess = spawn(binaryPath, [], {
+			const ideProcess = spawn(binaryPath, args, {
 				detached: true,
 				stdio: "ignore",
 			});
@@ -359,12 +379,15 @@ program
 	.action(install);
 
 // Default action: if logged in, launch IDE. If not, start login flow.
-program.action(async () => {
-	if (await isLoggedIn()) {
-		await launchIDE();
-	} else {
-		await login();
-	}
-});
+// `ariana .` opens the current folder, like `code .`.
+program
+	.argument("[path]", "Folder or file to open")
+	.action(async (target?: string) => {
+		if (await isLoggedIn()) {
+			await launchIDE(target);
+		} else {
+			await login();
+		}
+	});
 
 program.parse(process.argv);
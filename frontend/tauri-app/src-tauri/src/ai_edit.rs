@@ -0,0 +1,455 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use tauri::State;
+
+use crate::document_manager::{write_atomically, DocumentManager};
+use crate::editorconfig;
+use crate::text_encoding::LineEnding;
+
+/// Fuzzy matches scoring below this are rejected.
+const DEFAULT_MIN_SIMILARITY: f32 = 0.85;
+
+/// An edit proposed by a model for one file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "format", rename_all = "camelCase")]
+pub enum ProposedEdit {
+	/// Replace each `search` text with its `replace` text.
+	SearchReplace { blocks: Vec<SearchReplaceBlock> },
+	/// A unified diff of the file; file headers are ignored.
+	UnifiedDiff { diff: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchReplaceBlock {
+	pub search: String,
+	pub replace: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyEditOptions {
+	/// Validate and report without changing anything.
+	#[serde(default)]
+	pub dry_run: bool,
+	/// Apply the hunks that matched even if others were rejected. By default
+	/// an edit is applied entirely or not at all.
+	#[serde(default)]
+	pub allow_partial: bool,
+	/// Between 0 and 1, `0.85` by default.
+	pub min_similarity: Option<f32>,
+}
+
+/// How a hunk's original text was found in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchKind {
+	Exact,
+	/// Equal once leading and trailing whitespace is ignored on every line.
+	Whitespace,
+	Fuzzy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedHunk {
+	/// Position of the hunk in the proposed edit.
+	pub index: usize,
+	/// Zero-based line range the hunk replaced in the original text.
+	pub start_line: usize,
+	pub end_line: usize,
+	pub match_kind: MatchKind,
+	pub similarity: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedHunk {
+	pub index: usize,
+	pub reason: String,
+	/// Best candidate found, to point the user at.
+	pub closest_line: Option<usize>,
+	pub similarity: Option<f32>,
+}
+
+/// Where the edited text went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EditTarget {
+	/// The file is open, so its editor text was changed and left unsaved.
+	Editor,
+	Disk,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyEditResult {
+	pub path: String,
+	pub applied: Vec<AppliedHunk>,
+	pub rejected: Vec<RejectedHunk>,
+	/// Whether the file or editor was changed.
+	pub written: bool,
+	pub target: EditTarget,
+	/// The text with the applied hunks, even for a dry run.
+	pub text: String,
+}
+
+/// A change expressed as lines to find and lines to put in their place.
+struct Hunk {
+	old: Vec<String>,
+	new: Vec<String>,
+	/// Zero-based line the diff says the hunk starts at.
+	hint: Option<usize>,
+}
+
+fn lines_of(text: &str) -> Vec<String> {
+	text.lines().map(str::to_string).collect()
+}
+
+fn parse_unified_diff(diff: &str) -> Result<Vec<Hunk>> {
+	let mut hunks = Vec::new();
+	let mut current: Option<Hunk> = None;
+	for line in diff.lines() {
+		if line.starts_with("@@") {
+			hunks.extend(current.take());
+			// @@ -start,count +start,count @@
+			let hint = line
+				.split_whitespace()
+				.nth(1)
+				.and_then(|old| old.strip_prefix('-'))
+				.and_then(|old| old.split(',').next())
+				.and_then(|start| start.parse::<usize>().ok())
+				.map(|start| start.saturating_sub(1));
+			current = Some(Hunk {
+				old: Vec::new(),
+				new: Vec::new(),
+				hint,
+			});
+			continue;
+		}
+		let Some(hunk) = current.as_mut() else {
+			// Headers before the first hunk
+			continue;
+		};
+		if line.starts_with("--- ")
+			|| line.starts_with("+++ ")
+			|| line.starts_with("diff ")
+		{
+			hunks.extend(current.take());
+			continue;
+		}
+		match line.chars().next() {
+			Some('+') => hunk.new.push(line[1..].to_string()),
+			Some('-') => hunk.old.push(line[1..].to_string()),
+			Some(' ') => {
+				hunk.old.push(line[1..].to_string());
+				hunk.new.push(line[1..].to_string());
+			}
+			Some('\\') => {}
+			// Models often drop the space of empty context lines
+			None => {
+				hunk.old.push(String::new());
+				hunk.new.push(String::new());
+			}
+			Some(_) => return Err(anyhow!("Unexpected line in diff hunk: {}", line)),
+		}
+	}
+	hunks.extend(current);
+	if hunks.is_empty() {
+		return Err(anyhow!("The diff has no hunks"));
+	}
+	Ok(hunks)
+}
+
+fn hunks(edit: &ProposedEdit) -> Result<Vec<Hunk>> {
+	match edit {
+		ProposedEdit::SearchReplace { blocks } => Ok(blocks
+			.iter()
+			.map(|block| Hunk {
+				old: lines_of(&block.search),
+				new: lines_of(&block.replace),
+				hint: None,
+			})
+			.collect()),
+		ProposedEdit::UnifiedDiff { diff } => parse_unified_diff(diff),
+	}
+}
+
+fn line_similarity(a: &str, b: &str) -> f32 {
+	let (a, b) = (a.trim(), b.trim());
+	if a == b {
+		return 1.0;
+	}
+	TextDiff::from_chars(a, b).ratio()
+}
+
+/// Finds where `hunk.old` is in `lines`: exactly, ignoring whitespace, or
+/// failing that the most similar window of the same length.
+fn locate(
+	lines: &[String],
+	hunk: &Hunk,
+	min_similarity: f32,
+) -> std::result::Result<(usize, MatchKind, f32), RejectedHunk> {
+	let reject = |reason: String, closest_line, similarity| RejectedHunk {
+		index: 0,
+		reason,
+		closest_line,
+		similarity,
+	};
+	let len = hunk.old.len();
+	if len == 0 {
+		// Pure insertions need to know where they go
+		return match hunk.hint {
+			Some(hint) if hint <= lines.len() => Ok((hint, MatchKind::Exact, 1.0)),
+			_ if lines.is_empty() => Ok((0, MatchKind::Exact, 1.0)),
+			_ => Err(reject(
+				"The hunk has no original text to match".to_string(),
+				None,
+				None,
+			)),
+		};
+	}
+	if len > lines.len() {
+		return Err(reject(
+			"The original text is longer than the file".to_string(),
+			None,
+			None,
+		));
+	}
+
+	let candidates = 0..=lines.len() - len;
+	// Closest to the hint first, so a repeated snippet resolves to the one
+	// the diff meant
+	let by_distance = |starts: Vec<usize>| -> Option<usize> {
+		match hunk.hint {
+			Some(hint) => starts.into_iter().min_by_key(|start| start.abs_diff(hint)),
+			None if starts.len() == 1 => starts.into_iter().next(),
+			None => None,
+		}
+	};
+
+	for kind in [MatchKind::Exact, MatchKind::Whitespace] {
+		let equal = |a: &str, b: &str| match kind {
+			MatchKind::Exact => a == b,
+			_ => a.trim() == b.trim(),
+		};
+		let starts: Vec<usize> = candidates
+			.clone()
+			.filter(|&start| {
+				lines[start..start + len]
+					.iter()
+					.zip(&hunk.old)
+					.all(|(a, b)| equal(a, b))
+			})
+			.collect();
+		if starts.is_empty() {
+			continue;
+		}
+		let count = starts.len();
+		return match by_distance(starts) {
+			Some(start) => Ok((start, kind, 1.0)),
+			None => Err(reject(
+				format!("The original text appears {} times in the file", count),
+				None,
+				None,
+			)),
+		};
+	}
+
+	let mut best: Option<(usize, f32)> = None;
+	for start in candidates {
+		let similarity = lines[start..start + len]
+			.iter()
+			.zip(&hunk.old)
+			.map(|(a, b)| line_similarity(a, b))
+			.sum::<f32>()
+			/ len as f32;
+		let closer = match (best, hunk.hint) {
+			(None, _) => true,
+			(Some((_, score)), _) if similarity > score => true,
+			(Some((current, score)), Some(hint)) => {
+				similarity == score && start.abs_diff(hint) < current.abs_diff(hint)
+			}
+			_ => false,
+		};
+		if closer {
+			best = Some((start, similarity));
+		}
+	}
+	match best {
+		Some((start, similarity)) if similarity >= min_similarity => {
+			Ok((start, MatchKind::Fuzzy, similarity))
+		}
+		Some((start, similarity)) => Err(reject(
+			"The original text wasn't found in the file".to_string(),
+			Some(start),
+			Some(similarity),
+		)),
+		None => Err(reject(
+			"The original text wasn't found in the file".to_string(),
+			None,
+			None,
+		)),
+	}
+}
+
+/// Validates every hunk of `edit` against `text` and applies those that
+/// match, unless some were rejected and `allow_partial` isn't set.
+fn apply(
+	text: &str,
+	edit: &ProposedEdit,
+	options: &ApplyEditOptions,
+) -> Result<(String, Vec<AppliedHunk>, Vec<RejectedHunk>)> {
+	let min_similarity = options
+		.min_similarity
+		.unwrap_or(DEFAULT_MIN_SIMILARITY)
+		.clamp(0.0, 1.0);
+	let hunks = hunks(edit)?;
+	let lines = lines_of(text);
+
+	let mut applied: Vec<(AppliedHunk, &Hunk)> = Vec::new();
+	let mut rejected = Vec::new();
+	for (index, hunk) in hunks.iter().enumerate() {
+		match locate(&lines, hunk, min_similarity) {
+			Ok((start, match_kind, similarity)) => {
+				let end = start + hunk.old.len();
+				let overlaps = applied.iter().any(|(other, _)| {
+					start < other.end_line && other.start_line < end
+						|| (start == end && start == other.start_line)
+				});
+				if overlaps {
+					rejected.push(RejectedHunk {
+						index,
+						reason: "Overlaps an earlier hunk".to_string(),
+						closest_line: Some(start),
+						similarity: Some(similarity),
+					});
+					continue;
+				}
+				applied.push((
+					AppliedHunk {
+						index,
+						start_line: start,
+						end_line: end,
+						match_kind,
+						similarity,
+					},
+					hunk,
+				));
+			}
+			Err(rejection) => rejected.push(RejectedHunk { index, ..rejection }),
+		}
+	}
+
+	if !rejected.is_empty() && !options.allow_partial {
+		return Ok((text.to_string(), Vec::new(), rejected));
+	}
+
+	// Bottom to top, so earlier line numbers stay valid
+	let mut edited = lines;
+	applied.sort_by_key(|(hunk, _)| std::cmp::Reverse(hunk.start_line));
+	for (position, hunk) in &applied {
+		edited.splice(
+			position.start_line..position.end_line,
+			hunk.new.iter().cloned(),
+		);
+	}
+	let mut result = edited.join("\n");
+	if text.ends_with('\n') && !result.is_empty() {
+		result.push('\n');
+	}
+
+	let mut applied: Vec<AppliedHunk> =
+		applied.into_iter().map(|(hunk, _)| hunk).collect();
+	applied.sort_by_key(|hunk| hunk.index);
+	Ok((result, applied, rejected))
+}
+
+fn apply_to_disk(
+	path: &str,
+	edit: &ProposedEdit,
+	options: &ApplyEditOptions,
+) -> Result<(String, Vec<AppliedHunk>, Vec<RejectedHunk>, bool)> {
+	let original = fs::read_to_string(path)?;
+	let crlf = match editorconfig::resolve(Path::new(path)).line_ending() {
+		Some(line_ending) => line_ending == LineEnding::Crlf,
+		None => original.contains("\r\n"),
+	};
+	let text = original.replace("\r\n", "\n");
+	let (edited, applied, rejected) = apply(&text, edit, options)?;
+
+	let write = !options.dry_run && !applied.is_empty() && edited != text;
+	if write {
+		let edited = if crlf {
+			edited.replace('\n', "\r\n")
+		} else {
+			edited.clone()
+		};
+		write_atomically(Path::new(path), edited.as_bytes())?;
+	}
+	Ok((edited, applied, rejected, write))
+}
+
+/// Applies a model's proposed edit to `path`. Open documents are edited in
+/// the editor and left unsaved; other files are rewritten atomically.
+#[tauri::command]
+pub async fn apply_ai_edit(
+	path: String,
+	edit: ProposedEdit,
+	options: Option<ApplyEditOptions>,
+	documents: State<'_, Arc<DocumentManager>>,
+) -> Result<ApplyEditResult, String> {
+	let options = options.unwrap_or_default();
+
+	let mut outcome = None;
+	let mut written = false;
+	let state = documents.edit_document(&path, |text| {
+		let result = apply(text, &edit, &options);
+		let edited = match &result {
+			Ok((edited, applied, _))
+				if !options.dry_run && !applied.is_empty() && edited != text =>
+			{
+				Some(edited.clone())
+			}
+			_ => None,
+		};
+		written = edited.is_some();
+		outcome = Some(result);
+		edited
+	});
+
+	if state.is_some() {
+		let (text, applied, rejected) = outcome
+			.expect("edit_document runs the edit for open documents")
+			.map_err(|e| e.to_string())?;
+		return Ok(ApplyEditResult {
+			path,
+			written,
+			applied,
+			rejected,
+			target: EditTarget::Editor,
+			text,
+		});
+	}
+
+	let disk_path = path.clone();
+	let (text, applied, rejected, written) =
+		tauri::async_runtime::spawn_blocking(move || {
+			apply_to_disk(&disk_path, &edit, &options)
+		})
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())?;
+	Ok(ApplyEditResult {
+		path,
+		applied,
+		rejected,
+		written,
+		target: EditTarget::Disk,
+		text,
+	})
+}
@@ -0,0 +1,474 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+use crate::util::write_private;
+
+/// Store remembering whether the user turned the history on.
+const HISTORY_STORE: &str = "ai-history.json";
+/// One encrypted exchange per line, in the app data directory.
+const HISTORY_FILE: &str = "ai-history.jsonl.enc";
+/// In the local app data directory, which doesn't roam with the history on
+/// Windows, so a copy of the history file alone can't be read.
+const KEY_FILE: &str = "ai-history.key";
+/// Oldest exchanges are dropped past this.
+const MAX_ENTRIES: usize = 5000;
+const NONCE_LEN: usize = 12;
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryMessage {
+	pub role: String,
+	pub content: String,
+}
+
+/// An exchange to record, as sent by the frontend after its own requests.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewAiExchange {
+	/// What made the request, like `chat`, `diff_summary` or
+	/// `terminal_explanation`.
+	pub source: String,
+	pub provider: String,
+	pub model: String,
+	pub messages: Vec<HistoryMessage>,
+	pub response: String,
+}
+
+/// One recorded request and its answer. Only ever written to disk
+/// encrypted, and never sent anywhere.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiExchange {
+	pub id: String,
+	/// RFC 3339, UTC.
+	pub created_at: String,
+	pub source: String,
+	pub provider: String,
+	pub model: String,
+	pub messages: Vec<HistoryMessage>,
+	pub response: String,
+}
+
+/// Filters for `search_ai_history`; all of them must match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryQuery {
+	/// Case-insensitive text looked for in the messages and the response.
+	pub text: Option<String>,
+	pub source: Option<String>,
+	pub provider: Option<String>,
+	pub model: Option<String>,
+	/// RFC 3339; only exchanges from then on.
+	pub since: Option<String>,
+	pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+	Json,
+	Markdown,
+}
+
+impl HistoryQuery {
+	fn matches(&self, exchange: &AiExchange, since: Option<DateTime<Utc>>) -> bool {
+		let same = |filter: &Option<String>, value: &str| {
+			filter
+				.as_deref()
+				.is_none_or(|filter| filter.eq_ignore_ascii_case(value))
+		};
+		if !same(&self.source, &exchange.source)
+			|| !same(&self.provider, &exchange.provider)
+			|| !same(&self.model, &exchange.model)
+		{
+			return false;
+		}
+		if let Some(since) = since {
+			let created_at = DateTime::parse_from_rfc3339(&exchange.created_at);
+			if created_at.map_or(true, |created_at| created_at < since) {
+				return false;
+			}
+		}
+		match self.text.as_deref().map(str::trim) {
+			Some(text) if !text.is_empty() => {
+				let text = text.to_lowercase();
+				exchange.response.to_lowercase().contains(&text)
+					|| exchange
+						.messages
+						.iter()
+						.any(|m| m.content.to_lowercase().contains(&text))
+			}
+			_ => true,
+		}
+	}
+}
+
+#[derive(Default)]
+struct HistoryState {
+	key: Option<Key<Aes256Gcm>>,
+	/// How many lines the history file has, once known.
+	count: Option<usize>,
+}
+
+/// An opt-in history of AI requests and answers, kept on this machine only
+/// and encrypted with a key generated on first use.
+pub struct AiHistory {
+	app_handle: AppHandle,
+	/// Serializes access to the history and key files.
+	state: Mutex<HistoryState>,
+}
+
+impl AiHistory {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		Arc::new(Self {
+			app_handle,
+			state: Mutex::new(HistoryState::default()),
+		})
+	}
+
+	fn history_path(&self) -> Result<PathBuf> {
+		Ok(self.app_handle.path().app_data_dir()?.join(HISTORY_FILE))
+	}
+
+	fn key_path(&self) -> Result<PathBuf> {
+		Ok(self.app_handle.path().app_local_data_dir()?.join(KEY_FILE))
+	}
+
+	/// Off until the user turns it on.
+	pub fn enabled(&self) -> bool {
+		self.app_handle
+			.store(HISTORY_STORE)
+			.ok()
+			.and_then(|store| store.get("enabled"))
+			.and_then(|v| v.as_bool())
+			.unwrap_or(false)
+	}
+
+	/// Turning the history off stops recording but keeps what was recorded,
+	/// until `clear`.
+	pub fn set_enabled(&self, enabled: bool) -> Result<()> {
+		let store = self.app_handle.store(HISTORY_STORE)?;
+		store.set("enabled", enabled);
+		store.save()?;
+		Ok(())
+	}
+
+	/// The key, read from its file or generated when there is none yet.
+	fn key_locked(&self, state: &mut HistoryState) -> Result<Key<Aes256Gcm>> {
+		if let Some(key) = state.key {
+			return Ok(key);
+		}
+		let path = self.key_path()?;
+		let key = match fs::read(&path) {
+			Ok(bytes) if bytes.len() == 32 => *Key::<Aes256Gcm>::from_slice(&bytes),
+			Ok(_) => return Err(anyhow!("Invalid AI history key in {}", path.display())),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				let key = Aes256Gcm::generate_key(OsRng);
+				if let Some(dir) = path.parent() {
+					fs::create_dir_all(dir)?;
+				}
+				write_private(&path, &key)?;
+				key
+			}
+			Err(e) => return Err(e.into()),
+		};
+		state.key = Some(key);
+		Ok(key)
+	}
+
+	fn encrypt(key: &Key<Aes256Gcm>, exchange: &AiExchange) -> Result<String> {
+		let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+		let plaintext = serde_json::to_vec(exchange)?;
+		let ciphertext = Aes256Gcm::new(key)
+			.encrypt(&nonce, plaintext.as_slice())
+			.map_err(|_| anyhow!("Failed to encrypt the AI history"))?;
+		let mut sealed = nonce.to_vec();
+		sealed.extend_from_slice(&ciphertext);
+		Ok(STANDARD.encode(sealed))
+	}
+
+	fn decrypt(key: &Key<Aes256Gcm>, line: &str) -> Option<AiExchange> {
+		let sealed = STANDARD.decode(line.trim()).ok()?;
+		if sealed.len() <= NONCE_LEN {
+			return None;
+		}
+		let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+		let plaintext = Aes256Gcm::new(key)
+			.decrypt(Nonce::from_slice(nonce), ciphertext)
+			.ok()?;
+		serde_json::from_slice(&plaintext).ok()
+	}
+
+	/// Lines of the history file with what they decrypt to, oldest first.
+	/// Lines that don't decrypt, e.g. after the key was lost, are skipped.
+	fn read_locked(&self, state: &mut HistoryState) -> Result<Vec<(String, AiExchange)>> {
+		let content = match fs::read_to_string(self.history_path()?) {
+			Ok(content) => content,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(e) => return Err(e.into()),
+		};
+		let key = self.key_locked(state)?;
+		let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+		let entries: Vec<_> = lines
+			.iter()
+			.filter_map(|line| Some((line.to_string(), Self::decrypt(&key, line)?)))
+			.collect();
+		if entries.len() < lines.len() {
+			log::warn!(
+				"Skipped {} AI history entries that could not be decrypted",
+				lines.len() - entries.len()
+			);
+		}
+		Ok(entries)
+	}
+
+	fn write_locked(&self, lines: &[String]) -> Result<()> {
+		let mut content = String::new();
+		for line in lines {
+			content.push_str(line);
+			content.push('\n');
+		}
+		fs::write(self.history_path()?, content)?;
+		Ok(())
+	}
+
+	/// Records an exchange if the history is on, returning its id.
+	pub fn record(&self, exchange: NewAiExchange) -> Result<Option<String>> {
+		if !self.enabled() {
+			return Ok(None);
+		}
+		let exchange = AiExchange {
+			id: Uuid::new_v4().to_string(),
+			created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+			source: exchange.source,
+			provider: exchange.provider,
+			model: exchange.model,
+			messages: exchange.messages,
+			response: exchange.response,
+		};
+
+		let mut state = self.state.lock().unwrap();
+		let key = self.key_locked(&mut state)?;
+		let line = Self::encrypt(&key, &exchange)?;
+		let path = self.history_path()?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let count = match state.count {
+			Some(count) => count,
+			None => self.read_locked(&mut state)?.len(),
+		};
+		let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+		writeln!(file, "{}", line)?;
+		drop(file);
+
+		state.count = Some(count + 1);
+		if count + 1 > MAX_ENTRIES {
+			let lines: Vec<String> = self
+				.read_locked(&mut state)?
+				.into_iter()
+				.map(|(line, _)| line)
+				.collect();
+			let keep = &lines[lines.len().saturating_sub(MAX_ENTRIES)..];
+			self.write_locked(keep)?;
+			state.count = Some(keep.len());
+		}
+		Ok(Some(exchange.id))
+	}
+
+	/// Matching exchanges, newest first.
+	pub fn search(&self, query: &HistoryQuery) -> Result<Vec<AiExchange>> {
+		let since = query
+			.since
+			.as_deref()
+			.map(|since| {
+				DateTime::parse_from_rfc3339(since)
+					.map(|since| since.with_timezone(&Utc))
+					.with_context(|| format!("Invalid date {}", since))
+			})
+			.transpose()?;
+		let entries = {
+			let mut state = self.state.lock().unwrap();
+			self.read_locked(&mut state)?
+		};
+		Ok(entries
+			.into_iter()
+			.rev()
+			.map(|(_, exchange)| exchange)
+			.filter(|exchange| query.matches(exchange, since))
+			.take(query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+			.collect())
+	}
+
+	pub fn get(&self, id: &str) -> Result<Option<AiExchange>> {
+		let mut state = self.state.lock().unwrap();
+		Ok(self
+			.read_locked(&mut state)?
+			.into_iter()
+			.map(|(_, exchange)| exchange)
+			.find(|exchange| exchange.id == id))
+	}
+
+	/// Returns whether the exchange was there.
+	pub fn delete(&self, id: &str) -> Result<bool> {
+		let mut state = self.state.lock().unwrap();
+		let entries = self.read_locked(&mut state)?;
+		let kept: Vec<String> = entries
+			.iter()
+			.filter(|(_, exchange)| exchange.id != id)
+			.map(|(line, _)| line.clone())
+			.collect();
+		if kept.len() == entries.len() {
+			return Ok(false);
+		}
+		self.write_locked(&kept)?;
+		state.count = Some(kept.len());
+		Ok(true)
+	}
+
+	/// Deletes the history and its key.
+	pub fn clear(&self) -> Result<()> {
+		let mut state = self.state.lock().unwrap();
+		*state = HistoryState::default();
+		for path in [self.history_path()?, self.key_path()?] {
+			match fs::remove_file(path) {
+				Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+					return Err(e.into())
+				}
+				_ => {}
+			}
+		}
+		Ok(())
+	}
+
+	/// Writes the matching exchanges, oldest first and decrypted, to `path`.
+	/// Returns how many were written.
+	pub fn export(
+		&self,
+		query: &HistoryQuery,
+		format: ExportFormat,
+		path: &Path,
+	) -> Result<usize> {
+		let query = HistoryQuery {
+			limit: Some(query.limit.unwrap_or(usize::MAX)),
+			..query.clone()
+		};
+		let mut exchanges = self.search(&query)?;
+		exchanges.reverse();
+		let content = match format {
+			ExportFormat::Json => serde_json::to_string_pretty(&exchanges)?,
+			ExportFormat::Markdown => to_markdown(&exchanges),
+		};
+		fs::write(path, content)
+			.with_context(|| format!("Failed to write {}", path.display()))?;
+		Ok(exchanges.len())
+	}
+}
+
+fn to_markdown(exchanges: &[AiExchange]) -> String {
+	let mut markdown = String::from("# AI history\n");
+	for exchange in exchanges {
+		markdown.push_str(&format!(
+			"\n## {} · {} / {} · {}\n",
+			exchange.created_at, exchange.provider, exchange.model, exchange.source
+		));
+		for message in &exchange.messages {
+			markdown
+				.push_str(&format!("\n**{}:**\n\n{}\n", message.role, message.content));
+		}
+		markdown.push_str(&format!("\n**assistant:**\n\n{}\n", exchange.response));
+	}
+	markdown
+}
+
+/// Records an exchange made by the app itself, if the history is on.
+pub fn record(app_handle: &AppHandle, exchange: NewAiExchange) {
+	let Some(history) = app_handle.try_state::<Arc<AiHistory>>() else {
+		return;
+	};
+	if let Err(e) = history.record(exchange) {
+		log::warn!("Failed to record AI history: {}", e);
+	}
+}
+
+#[tauri::command]
+pub async fn get_ai_history_enabled(
+	history: State<'_, Arc<AiHistory>>,
+) -> Result<bool, String> {
+	Ok(history.enabled())
+}
+
+#[tauri::command]
+pub async fn set_ai_history_enabled(
+	enabled: bool,
+	history: State<'_, Arc<AiHistory>>,
+) -> Result<(), String> {
+	history.set_enabled(enabled).map_err(|e| e.to_string())
+}
+
+/// Records an exchange the frontend made. Returns its id, or `None` when the
+/// history is off.
+#[tauri::command]
+pub async fn record_ai_exchange(
+	exchange: NewAiExchange,
+	history: State<'_, Arc<AiHistory>>,
+) -> Result<Option<String>, String> {
+	history.record(exchange).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn search_ai_history(
+	query: Option<HistoryQuery>,
+	history: State<'_, Arc<AiHistory>>,
+) -> Result<Vec<AiExchange>, String> {
+	history
+		.search(&query.unwrap_or_default())
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_ai_exchange(
+	id: String,
+	history: State<'_, Arc<AiHistory>>,
+) -> Result<Option<AiExchange>, String> {
+	history.get(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_ai_exchange(
+	id: String,
+	history: State<'_, Arc<AiHistory>>,
+) -> Result<bool, String> {
+	history.delete(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_ai_history(history: State<'_, Arc<AiHistory>>) -> Result<(), String> {
+	history.clear().map_err(|e| e.to_string())
+}
+
+/// Writes the matching exchanges to `path`, unencrypted, as JSON or
+/// Markdown. Returns how many were exported.
+#[tauri::command]
+pub async fn export_ai_history(
+	path: String,
+	format: ExportFormat,
+	query: Option<HistoryQuery>,
+	history: State<'_, Arc<AiHistory>>,
+) -> Result<usize, String> {
+	history
+		.export(&query.unwrap_or_default(), format, Path::new(&path))
+		.map_err(|e| e.to_string())
+}
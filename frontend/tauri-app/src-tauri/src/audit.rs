@@ -0,0 +1,660 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::dependencies::Ecosystem;
+use crate::jobs::{JobHandle, JobKind, JobManager, JobProgress, JOB_CANCELLED};
+use crate::os::OsSession;
+use crate::util::now_millis;
+
+const USER_AGENT: &str = concat!("ariana-ide/", env!("CARGO_PKG_VERSION"));
+const OSV_BATCH_URL: &str = "https://api.osv.dev/v1/querybatch";
+const OSV_VULN_URL: &str = "https://api.osv.dev/v1/vulns";
+const NPM_ADVISORIES_URL: &str =
+	"https://registry.npmjs.org/-/npm/v1/security/advisories/bulk";
+/// OSV accepts at most 1000 queries per batch.
+const OSV_BATCH_SIZE: usize = 1000;
+/// Packages per npm bulk advisory request, to keep bodies small.
+const NPM_BATCH_SIZE: usize = 500;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A locked package from one of the project's lockfiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LockedPackage {
+	ecosystem: Ecosystem,
+	name: String,
+	version: String,
+	lockfile: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Vulnerability {
+	/// E.g. `RUSTSEC-2023-0071` or `GHSA-xxxx-xxxx-xxxx`.
+	pub id: String,
+	/// Other names of the advisory, such as its CVE.
+	pub aliases: Vec<String>,
+	pub summary: String,
+	/// `low`, `moderate`, `high` or `critical` when the database rates it.
+	pub severity: Option<String>,
+	/// CVSS vector, when given.
+	pub cvss: Option<String>,
+	/// Versions that fix it, when known.
+	pub fixed_versions: Vec<String>,
+	/// Set for RustSec notices that aren't vulnerabilities, e.g.
+	/// `unmaintained` or `unsound`.
+	pub informational: Option<String>,
+	pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VulnerablePackage {
+	pub ecosystem: Ecosystem,
+	pub name: String,
+	pub version: String,
+	/// The lockfile it was found in, relative to the project root.
+	pub lockfile: String,
+	pub vulnerabilities: Vec<Vulnerability>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditReport {
+	pub lockfiles: Vec<String>,
+	pub packages_scanned: usize,
+	pub vulnerable: Vec<VulnerablePackage>,
+	/// Databases that couldn't be queried, so the report may be incomplete.
+	pub errors: Vec<String>,
+	/// Milliseconds since the Unix epoch.
+	pub generated_at: u64,
+}
+
+/// Payload of the `audit-updated` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditUpdated {
+	pub directory: String,
+	pub report: AuditReport,
+}
+
+/// The latest report of each project, and OSV advisories already fetched.
+pub struct AuditStore {
+	http: Client,
+	reports: Mutex<HashMap<String, AuditReport>>,
+	advisories: Mutex<HashMap<String, Value>>,
+}
+
+impl AuditStore {
+	pub fn new() -> Arc<Self> {
+		let http = Client::builder()
+			.user_agent(USER_AGENT)
+			.timeout(REQUEST_TIMEOUT)
+			.build()
+			.unwrap_or_default();
+		Arc::new(Self {
+			http,
+			reports: Mutex::new(HashMap::new()),
+			advisories: Mutex::new(HashMap::new()),
+		})
+	}
+}
+
+fn locked(
+	ecosystem: Ecosystem,
+	name: &str,
+	version: &str,
+	lockfile: &'static str,
+) -> LockedPackage {
+	LockedPackage {
+		ecosystem,
+		name: name.to_string(),
+		version: version.to_string(),
+		lockfile,
+	}
+}
+
+#[derive(Deserialize)]
+struct TomlLock {
+	#[serde(default)]
+	package: Vec<TomlLockPackage>,
+}
+
+#[derive(Deserialize)]
+struct TomlLockPackage {
+	name: String,
+	version: Option<String>,
+	source: Option<toml::Value>,
+}
+
+/// Registry packages of a Cargo.lock, poetry.lock or uv.lock. Workspace
+/// members, path and git packages aren't in the advisory databases.
+fn toml_lock_packages(
+	content: &str,
+	ecosystem: Ecosystem,
+	lockfile: &'static str,
+) -> Result<Vec<LockedPackage>> {
+	let lock: TomlLock = toml::from_str(content)?;
+	Ok(lock
+		.package
+		.into_iter()
+		.filter(|package| match (&package.source, ecosystem) {
+			(Some(toml::Value::String(source)), Ecosystem::Cargo) => {
+				source.starts_with("registry+")
+			}
+			(None, Ecosystem::Cargo) => false,
+			(Some(toml::Value::Table(source)), _) => source.contains_key("registry"),
+			(None, _) => true,
+			_ => false,
+		})
+		.filter_map(|package| {
+			let version = package.version?;
+			Some(locked(ecosystem, &package.name, &version, lockfile))
+		})
+		.collect())
+}
+
+/// Packages of a package-lock.json, from `packages` (lockfile v2 and v3)
+/// or the nested `dependencies` of v1.
+fn package_lock_packages(content: &str) -> Result<Vec<LockedPackage>> {
+	let lock: Value = serde_json::from_str(content)?;
+	let mut packages = Vec::new();
+	if let Some(entries) = lock["packages"].as_object() {
+		for (path, entry) in entries {
+			// The project itself, and links to workspace packages
+			let Some((_, name)) = path.rsplit_once("node_modules/") else {
+				continue;
+			};
+			if entry["link"].as_bool() == Some(true) {
+				continue;
+			}
+			if let Some(version) = entry["version"].as_str() {
+				packages.push(locked(Ecosystem::Npm, name, version, "package-lock.json"));
+			}
+		}
+		return Ok(packages);
+	}
+	fn walk(dependencies: &Value, packages: &mut Vec<LockedPackage>) {
+		let Some(dependencies) = dependencies.as_object() else {
+			return;
+		};
+		for (name, entry) in dependencies {
+			if let Some(version) = entry["version"].as_str() {
+				packages.push(locked(Ecosystem::Npm, name, version, "package-lock.json"));
+			}
+			walk(&entry["dependencies"], packages);
+		}
+	}
+	walk(&lock["dependencies"], &mut packages);
+	Ok(packages)
+}
+
+/// Packages of a yarn.lock, whose entries start with the unindented
+/// specifiers they satisfy and hold an indented `version` line.
+fn yarn_lock_packages(content: &str) -> Vec<LockedPackage> {
+	let mut packages = Vec::new();
+	let mut name: Option<String> = None;
+	for line in content.lines() {
+		if !line.starts_with(' ') && line.ends_with(':') {
+			// `"@scope/a@^1.0.0", "@scope/a@^1.2.0":`
+			let specifier = line.split(',').next().unwrap_or_default();
+			let specifier = specifier.trim().trim_matches('"');
+			name = specifier
+				.get(1..)
+				.and_then(|rest| rest.find('@'))
+				.map(|at| specifier[..at + 1].to_string());
+		} else if let (Some(version), Some(current)) =
+			(line.trim().strip_prefix("version"), name.as_deref())
+		{
+			// `version "1.2.3"`, or `version: 1.2.3` in Yarn 2+ lockfiles
+			let version = version.trim_start_matches(':').trim().trim_matches('"');
+			packages.push(locked(Ecosystem::Npm, current, version, "yarn.lock"));
+		}
+	}
+	packages
+}
+
+/// Every locked package of the project at `root`, with the lockfiles read.
+/// Cargo.lock is looked for in the workspace root above too.
+fn read_lockfiles(root: &Path) -> (Vec<String>, Vec<LockedPackage>, Vec<String>) {
+	let mut lockfiles = Vec::new();
+	let mut packages = Vec::new();
+	let mut errors = Vec::new();
+	let mut read = |path: &Path,
+	                lockfile: &'static str,
+	                parse: &dyn Fn(&str) -> Result<Vec<LockedPackage>>| {
+		let Ok(content) = fs::read_to_string(path) else {
+			return;
+		};
+		match parse(&content) {
+			Ok(found) => {
+				lockfiles.push(lockfile.to_string());
+				packages.extend(found);
+			}
+			Err(e) => errors.push(format!("Failed to read {}: {}", lockfile, e)),
+		}
+	};
+
+	if let Some(cargo_lock) = root
+		.ancestors()
+		.map(|dir| dir.join("Cargo.lock"))
+		.find(|path| path.exists())
+	{
+		read(&cargo_lock, "Cargo.lock", &|content| {
+			toml_lock_packages(content, Ecosystem::Cargo, "Cargo.lock")
+		});
+	}
+	read(
+		&root.join("package-lock.json"),
+		"package-lock.json",
+		&package_lock_packages,
+	);
+	read(&root.join("yarn.lock"), "yarn.lock", &|content| {
+		Ok(yarn_lock_packages(content))
+	});
+	read(&root.join("poetry.lock"), "poetry.lock", &|content| {
+		toml_lock_packages(content, Ecosystem::Python, "poetry.lock")
+	});
+	read(&root.join("uv.lock"), "uv.lock", &|content| {
+		toml_lock_packages(content, Ecosystem::Python, "uv.lock")
+	});
+
+	packages.sort_by(|a, b| {
+		(a.ecosystem as u8, &a.name, &a.version).cmp(&(
+			b.ecosystem as u8,
+			&b.name,
+			&b.version,
+		))
+	});
+	packages.dedup();
+	(lockfiles, packages, errors)
+}
+
+fn osv_ecosystem(ecosystem: Ecosystem) -> &'static str {
+	match ecosystem {
+		Ecosystem::Cargo => "crates.io",
+		Ecosystem::Npm => "npm",
+		Ecosystem::Python => "PyPI",
+	}
+}
+
+/// An OSV advisory as a report entry, with the versions of `name` fixing it.
+fn from_osv(advisory: &Value, name: &str) -> Vulnerability {
+	let strings = |value: &Value| -> Vec<String> {
+		value
+			.as_array()
+			.map(|values| {
+				values
+					.iter()
+					.filter_map(|value| value.as_str().map(str::to_string))
+					.collect()
+			})
+			.unwrap_or_default()
+	};
+	let id = advisory["id"].as_str().unwrap_or_default().to_string();
+	let affected = advisory["affected"]
+		.as_array()
+		.into_iter()
+		.flatten()
+		.filter(|affected| affected["package"]["name"].as_str() == Some(name));
+	let mut fixed_versions = Vec::new();
+	let mut informational = None;
+	for affected in affected {
+		for range in affected["ranges"].as_array().into_iter().flatten() {
+			for event in range["events"].as_array().into_iter().flatten() {
+				if let Some(fixed) = event["fixed"].as_str() {
+					fixed_versions.push(fixed.to_string());
+				}
+			}
+		}
+		informational = informational.or_else(|| {
+			affected["database_specific"]["informational"]
+				.as_str()
+				.map(str::to_string)
+		});
+	}
+	let url = if id.starts_with("RUSTSEC-") {
+		Some(format!("https://rustsec.org/advisories/{}.html", id))
+	} else {
+		advisory["references"]
+			.as_array()
+			.into_iter()
+			.flatten()
+			.find(|reference| reference["type"] == "ADVISORY")
+			.and_then(|reference| reference["url"].as_str())
+			.map(str::to_string)
+			.or_else(|| Some(format!("https://osv.dev/vulnerability/{}", id)))
+	};
+	Vulnerability {
+		aliases: strings(&advisory["aliases"]),
+		summary: advisory["summary"]
+			.as_str()
+			.or_else(|| advisory["details"].as_str())
+			.unwrap_or_default()
+			.lines()
+			.next()
+			.unwrap_or_default()
+			.to_string(),
+		severity: advisory["database_specific"]["severity"]
+			.as_str()
+			.map(str::to_lowercase),
+		cvss: advisory["severity"]
+			.as_array()
+			.into_iter()
+			.flatten()
+			.find_map(|severity| severity["score"].as_str().map(str::to_string)),
+		fixed_versions,
+		informational,
+		url,
+		id,
+	}
+}
+
+/// Queries OSV for `packages`, returning the advisories of each affected
+/// one by index.
+async fn query_osv(
+	store: &AuditStore,
+	packages: &[&LockedPackage],
+	job: &JobHandle,
+) -> Result<HashMap<usize, Vec<Vulnerability>>> {
+	let mut found = HashMap::new();
+	for (batch_index, batch) in packages.chunks(OSV_BATCH_SIZE).enumerate() {
+		job.token().check()?;
+		let queries: Vec<Value> = batch
+			.iter()
+			.map(|package| {
+				json!({
+					"package": {
+						"name": package.name,
+						"ecosystem": osv_ecosystem(package.ecosystem),
+					},
+					"version": package.version,
+				})
+			})
+			.collect();
+		let response: Value = store
+			.http
+			.post(OSV_BATCH_URL)
+			.json(&json!({ "queries": queries }))
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+		let results = response["results"].as_array().cloned().unwrap_or_default();
+		for (offset, result) in results.iter().enumerate() {
+			let ids: Vec<&str> = result["vulns"]
+				.as_array()
+				.into_iter()
+				.flatten()
+				.filter_map(|vuln| vuln["id"].as_str())
+				.collect();
+			if ids.is_empty() {
+				continue;
+			}
+			let index = batch_index * OSV_BATCH_SIZE + offset;
+			let mut vulnerabilities = Vec::new();
+			for id in ids {
+				job.token().check()?;
+				let cached = store.advisories.lock().unwrap().get(id).cloned();
+				let advisory = match cached {
+					Some(advisory) => advisory,
+					None => {
+						let advisory: Value = store
+							.http
+							.get(format!("{}/{}", OSV_VULN_URL, id))
+							.send()
+							.await?
+							.error_for_status()?
+							.json()
+							.await?;
+						store
+							.advisories
+							.lock()
+							.unwrap()
+							.insert(id.to_string(), advisory.clone());
+						advisory
+					}
+				};
+				vulnerabilities.push(from_osv(&advisory, &packages[index].name));
+			}
+			found.insert(index, vulnerabilities);
+		}
+	}
+	Ok(found)
+}
+
+/// Whether `version` is in an npm range like `>=2.0.0 <2.1.4 || <1.0.5`.
+/// Ranges the semver crate can't read count as matching, to err on the
+/// side of reporting.
+fn in_npm_range(version: &str, range: &str) -> bool {
+	let Ok(version) = semver::Version::parse(version) else {
+		return true;
+	};
+	range.split("||").any(|alternative| {
+		let alternative = alternative.trim();
+		if alternative.is_empty() || alternative == "*" {
+			return true;
+		}
+		let comparators = alternative
+			.split_whitespace()
+			.collect::<Vec<_>>()
+			.join(", ");
+		semver::VersionReq::parse(&comparators)
+			.map(|requirement| requirement.matches(&version))
+			.unwrap_or(true)
+	})
+}
+
+#[derive(Deserialize)]
+struct NpmAdvisory {
+	id: Value,
+	url: Option<String>,
+	title: String,
+	severity: Option<String>,
+	vulnerable_versions: String,
+	cvss: Option<NpmCvss>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NpmCvss {
+	vector_string: Option<String>,
+}
+
+/// Asks the npm registry's bulk advisory endpoint about `packages`.
+async fn query_npm(
+	store: &AuditStore,
+	packages: &[&LockedPackage],
+	job: &JobHandle,
+) -> Result<HashMap<usize, Vec<Vulnerability>>> {
+	let mut found = HashMap::new();
+	let mut indices_by_name: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+	for (index, package) in packages.iter().enumerate() {
+		indices_by_name
+			.entry(&package.name)
+			.or_default()
+			.push(index);
+	}
+	let names: Vec<&str> = indices_by_name.keys().copied().collect();
+	for batch in names.chunks(NPM_BATCH_SIZE) {
+		job.token().check()?;
+		let body: serde_json::Map<String, Value> = batch
+			.iter()
+			.map(|name| {
+				let versions: Vec<&str> = indices_by_name[name]
+					.iter()
+					.map(|index| packages[*index].version.as_str())
+					.collect();
+				(name.to_string(), json!(versions))
+			})
+			.collect();
+		let response: HashMap<String, Vec<NpmAdvisory>> = store
+			.http
+			.post(NPM_ADVISORIES_URL)
+			.json(&body)
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+		for (name, advisories) in response {
+			for index in indices_by_name.get(name.as_str()).into_iter().flatten() {
+				let version = &packages[*index].version;
+				let vulnerabilities: Vec<Vulnerability> = advisories
+					.iter()
+					.filter(|advisory| {
+						in_npm_range(version, &advisory.vulnerable_versions)
+					})
+					.map(|advisory| Vulnerability {
+						id: advisory
+							.url
+							.as_deref()
+							.and_then(|url| url.rsplit('/').next())
+							.filter(|id| id.starts_with("GHSA-"))
+							.map(str::to_string)
+							.unwrap_or_else(|| advisory.id.to_string()),
+						aliases: Vec::new(),
+						summary: advisory.title.clone(),
+						severity: advisory.severity.clone(),
+						cvss: advisory
+							.cvss
+							.as_ref()
+							.and_then(|cvss| cvss.vector_string.clone()),
+						fixed_versions: Vec::new(),
+						informational: None,
+						url: advisory.url.clone(),
+					})
+					.collect();
+				if !vulnerabilities.is_empty() {
+					found.insert(*index, vulnerabilities);
+				}
+			}
+		}
+	}
+	Ok(found)
+}
+
+/// Reads the project's lockfiles and checks every package against the
+/// advisory databases.
+fn audit(
+	session: &OsSession,
+	store: &AuditStore,
+	job: &JobHandle,
+) -> Result<AuditReport> {
+	let (lockfiles, packages, mut errors) = read_lockfiles(&session.host_path());
+	if lockfiles.is_empty() {
+		return Err(anyhow!(
+			"No Cargo.lock, package-lock.json, yarn.lock, poetry.lock or uv.lock to audit"
+		));
+	}
+
+	let (npm, osv): (Vec<&LockedPackage>, Vec<&LockedPackage>) = packages
+		.iter()
+		.partition(|package| package.ecosystem == Ecosystem::Npm);
+	let total = [osv.len(), npm.len()]
+		.iter()
+		.filter(|len| **len > 0)
+		.count() as u64;
+	let mut vulnerable = Vec::new();
+	for (step, (database, group)) in [("OSV", &osv), ("npm", &npm)]
+		.into_iter()
+		.filter(|(_, group)| !group.is_empty())
+		.enumerate()
+	{
+		job.progress(JobProgress {
+			message: Some(format!(
+				"Checking {} packages with {}",
+				group.len(),
+				database
+			)),
+			completed: Some(step as u64),
+			total: Some(total),
+		});
+		let result = tauri::async_runtime::block_on(async {
+			match database {
+				"OSV" => query_osv(store, group, job).await,
+				_ => query_npm(store, group, job).await,
+			}
+		});
+		let found = match result {
+			Ok(found) => found,
+			Err(e) if e.to_string() == JOB_CANCELLED => return Err(e),
+			Err(e) => {
+				errors.push(format!("{} couldn't be queried: {}", database, e));
+				continue;
+			}
+		};
+		for (index, vulnerabilities) in found {
+			let package = group[index];
+			vulnerable.push(VulnerablePackage {
+				ecosystem: package.ecosystem,
+				name: package.name.clone(),
+				version: package.version.clone(),
+				lockfile: package.lockfile.to_string(),
+				vulnerabilities,
+			});
+		}
+	}
+	vulnerable.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+	Ok(AuditReport {
+		lockfiles,
+		packages_scanned: packages.len(),
+		vulnerable,
+		errors,
+		generated_at: now_millis(),
+	})
+}
+
+/// Checks the packages locked by the project's Cargo.lock,
+/// package-lock.json, yarn.lock, poetry.lock or uv.lock against RustSec and
+/// the other OSV databases and npm's advisories, as a job. Returns the job
+/// id; the report is the job's result and arrives as `audit-updated`.
+#[tauri::command]
+pub async fn audit_dependencies(
+	os_session: OsSession,
+	job_manager: State<'_, Arc<JobManager>>,
+	store: State<'_, Arc<AuditStore>>,
+	app_handle: AppHandle,
+) -> Result<String, String> {
+	let store = store.inner().clone();
+	let title = format!(
+		"Audit dependencies of {}",
+		os_session.get_working_directory()
+	);
+	Ok(job_manager.spawn(JobKind::Audit, title, move |job| {
+		let report = audit(&os_session, &store, job)?;
+		let directory = os_session.get_working_directory().to_string();
+		store
+			.reports
+			.lock()
+			.unwrap()
+			.insert(directory.clone(), report.clone());
+		let _ = app_handle.emit(
+			"audit-updated",
+			AuditUpdated {
+				directory,
+				report: report.clone(),
+			},
+		);
+		Ok(report)
+	}))
+}
+
+/// The latest audit of the session's project, to badge dependencies with.
+#[tauri::command]
+pub async fn get_audit_report(
+	os_session: OsSession,
+	store: State<'_, Arc<AuditStore>>,
+) -> Result<Option<AuditReport>, String> {
+	let reports = store.reports.lock().unwrap();
+	Ok(reports.get(os_session.get_working_directory()).cloned())
+}
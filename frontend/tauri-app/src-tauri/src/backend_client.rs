@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{Client, Method, RequestBuilder};
+use serde::Deserialize;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+/// Subset of `~/.ariana/config.json` written by `ariana login`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserConfig {
+	token: Option<String>,
+	backend_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildConfig {
+	runtime_params: RuntimeParams,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuntimeParams {
+	server_url: String,
+}
+
+/// Authenticated HTTP client for the Ariana backend, using the token the CLI
+/// stored at login.
+pub struct BackendClient {
+	http: Client,
+	base_url: String,
+	token: String,
+}
+
+impl BackendClient {
+	pub fn from_app(app_handle: &AppHandle) -> Result<Self> {
+		let config_path = user_config_path(app_handle)?;
+		let content = std::fs::read_to_string(&config_path).with_context(|| {
+			format!("Not logged in: could not read {}", config_path.display())
+		})?;
+		let config: UserConfig = serde_json::from_str(&content)?;
+
+		let token = config
+			.token
+			.ok_or_else(|| anyhow!("Not logged in: run `ariana login` first"))?;
+
+		Ok(Self {
+			http: Client::new(),
+			base_url: resolve_base_url(app_handle, config.backend_url),
+			token,
+		})
+	}
+
+	/// Starts a request to `path` (e.g. `/api/settings`) with the bearer token set.
+	pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+		self.http
+			.request(method, format!("{}{}", self.base_url, path))
+			.bearer_auth(&self.token)
+	}
+
+	/// WebSocket URL for `path`, using `wss://` when the backend is served over TLS.
+	pub fn websocket_url(&self, path: &str) -> String {
+		let base = match self.base_url.split_once("://") {
+			Some(("https", rest)) => format!("wss://{}", rest),
+			Some((_, rest)) => format!("ws://{}", rest),
+			None => format!("ws://{}", self.base_url),
+		};
+		format!("{}{}", base, path)
+	}
+
+	pub fn token(&self) -> &str {
+		&self.token
+	}
+}
+
+/// Starts a request to `path` that is authenticated when logged in and
+/// anonymous otherwise, e.g. inference with a raw provider key.
+pub fn optional_auth_request(
+	app_handle: &AppHandle,
+	method: Method,
+	path: &str,
+) -> RequestBuilder {
+	match BackendClient::from_app(app_handle) {
+		Ok(backend) => backend.request(method, path),
+		Err(_) => {
+			Client::new().request(method, format!("{}{}", backend_url(app_handle), path))
+		}
+	}
+}
+
+/// Base URL of the backend, for requests that work without logging in.
+pub fn backend_url(app_handle: &AppHandle) -> String {
+	let user_url = user_config_path(app_handle)
+		.ok()
+		.and_then(|path| std::fs::read_to_string(path).ok())
+		.and_then(|content| serde_json::from_str::<UserConfig>(&content).ok())
+		.and_then(|config| config.backend_url);
+	resolve_base_url(app_handle, user_url)
+}
+
+fn user_config_path(app_handle: &AppHandle) -> Result<PathBuf> {
+	Ok(app_handle
+		.path()
+		.home_dir()?
+		.join(".ariana")
+		.join("config.json"))
+}
+
+/// Same precedence as the CLI: bundled build config, user config, default.
+fn resolve_base_url(app_handle: &AppHandle, user_url: Option<String>) -> String {
+	let base_url = app_handle
+		.path()
+		.resource_dir()
+		.ok()
+		.and_then(|dir| read_build_config(dir.join("config.json")))
+		.map(|build| build.runtime_params.server_url)
+		.or(user_url)
+		.unwrap_or_else(|| {
+			if cfg!(debug_assertions) {
+				"http://localhost:8080".to_string()
+			} else {
+				"https://api.ariana.dev".to_string()
+			}
+		});
+	base_url.trim_end_matches('/').to_string()
+}
+
+fn read_build_config(path: PathBuf) -> Option<BuildConfig> {
+	let content = std::fs::read_to_string(path).ok()?;
+	serde_json::from_str(&content).ok()
+}
@@ -0,0 +1,302 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::jobs::{self, JobHandle, JobKind, JobManager, JobProgress, JOB_CANCELLED};
+use crate::os::OsSession;
+use crate::trust;
+
+/// The per-project manifest, relative to the project root.
+const MANIFEST_PATH: &str = ".ariana/bootstrap.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepSource {
+	/// Found by looking at the project's files.
+	Detected,
+	/// Listed in `.ariana/bootstrap.toml`.
+	Manifest,
+}
+
+/// A command that prepares a project, such as installing its dependencies.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapStep {
+	/// Stable across detections, e.g. `node` or `manifest:0`.
+	pub id: String,
+	pub name: String,
+	/// Shell command line run from the project root.
+	pub command: String,
+	pub source: StepSource,
+	/// Why the step is needed, e.g. "node_modules is missing".
+	pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapPlan {
+	/// Steps still needed, in the order they run.
+	pub steps: Vec<BootstrapStep>,
+	/// Whether the manifest asks for the steps to run when the project is
+	/// opened.
+	pub run_on_open: bool,
+	/// Set when the manifest couldn't be read.
+	pub manifest_error: Option<String>,
+}
+
+/// Payload of the `bootstrap-output` event, one per line a step prints.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapOutput {
+	pub job_id: String,
+	pub step_id: String,
+	/// `stdout` or `stderr`.
+	pub stream: &'static str,
+	pub line: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepResult {
+	pub id: String,
+	pub name: String,
+	pub exit_code: Option<i32>,
+}
+
+/// `.ariana/bootstrap.toml`:
+///
+/// ```toml
+/// run_on_open = true
+/// detect = false          # skip the built-in steps
+///
+/// [[steps]]
+/// name = "Install dependencies"
+/// command = "pnpm install --frozen-lockfile"
+/// skip_if_exists = "node_modules"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Manifest {
+	run_on_open: bool,
+	detect: Option<bool>,
+	steps: Vec<ManifestStep>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestStep {
+	name: String,
+	command: String,
+	/// Relative to the project root; the step is left out once it exists.
+	skip_if_exists: Option<String>,
+}
+
+/// The package manager whose lockfile is present, npm by default.
+fn package_manager(root: &Path) -> &'static str {
+	if root.join("pnpm-lock.yaml").exists() {
+		"pnpm"
+	} else if root.join("yarn.lock").exists() {
+		"yarn"
+	} else if root.join("bun.lockb").exists() || root.join("bun.lock").exists() {
+		"bun"
+	} else {
+		"npm"
+	}
+}
+
+/// Steps the project's files show are needed.
+fn detect_steps(session: &OsSession, root: &Path) -> Vec<BootstrapStep> {
+	let mut steps = Vec::new();
+	let step = |id: &str, name: &str, command: String, reason: &str| BootstrapStep {
+		id: id.to_string(),
+		name: name.to_string(),
+		command,
+		source: StepSource::Detected,
+		reason: reason.to_string(),
+	};
+
+	if root.join("package.json").exists() && !root.join("node_modules").exists() {
+		let manager = package_manager(root);
+		steps.push(step(
+			"node",
+			"Install Node packages",
+			format!("{} install", manager),
+			"node_modules is missing",
+		));
+	}
+
+	// A fresh checkout has never been built; fetching up front lets the
+	// first build work offline
+	if root.join("Cargo.toml").exists() && !root.join("target").exists() {
+		steps.push(step(
+			"cargo",
+			"Fetch Cargo dependencies",
+			"cargo fetch".to_string(),
+			"the project hasn't been built yet",
+		));
+	}
+
+	let has_requirements = root.join("requirements.txt").exists();
+	if (has_requirements || root.join("pyproject.toml").exists())
+		&& !root.join(".venv").exists()
+		&& !root.join("venv").exists()
+	{
+		let (python, venv_python) = match session {
+			#[cfg(target_os = "windows")]
+			OsSession::Local(_) => ("python", ".venv/Scripts/python"),
+			_ => ("python3", ".venv/bin/python"),
+		};
+		steps.push(step(
+			"venv",
+			"Create Python virtual environment",
+			format!("{} -m venv .venv", python),
+			"there is no virtual environment",
+		));
+		if has_requirements {
+			steps.push(step(
+				"pip",
+				"Install Python requirements",
+				format!("{} -m pip install -r requirements.txt", venv_python),
+				"there is no virtual environment",
+			));
+		}
+	}
+	steps
+}
+
+/// The steps still needed to prepare the project in `session`.
+pub fn plan(session: &OsSession) -> BootstrapPlan {
+	let root = session.host_path();
+	let (manifest, manifest_error) = match fs::read_to_string(root.join(MANIFEST_PATH)) {
+		Ok(content) => match toml::from_str::<Manifest>(&content) {
+			Ok(manifest) => (manifest, None),
+			Err(e) => (Manifest::default(), Some(e.to_string())),
+		},
+		Err(_) => (Manifest::default(), None),
+	};
+
+	let mut steps = if manifest.detect.unwrap_or(true) {
+		detect_steps(session, &root)
+	} else {
+		Vec::new()
+	};
+	for (index, manifest_step) in manifest.steps.into_iter().enumerate() {
+		let reason = match &manifest_step.skip_if_exists {
+			Some(path) if root.join(path).exists() => continue,
+			Some(path) => format!("{} is missing", path),
+			None => "listed in the project's bootstrap manifest".to_string(),
+		};
+		steps.push(BootstrapStep {
+			id: format!("manifest:{}", index),
+			name: manifest_step.name,
+			command: manifest_step.command,
+			source: StepSource::Manifest,
+			reason,
+		});
+	}
+	BootstrapPlan {
+		steps,
+		run_on_open: manifest.run_on_open,
+		manifest_error,
+	}
+}
+
+/// Runs one step, emitting its output line by line, and returns its exit
+/// code.
+fn run_step(
+	session: &OsSession,
+	step: &BootstrapStep,
+	job: &JobHandle,
+	app_handle: &AppHandle,
+) -> Result<Option<i32>> {
+	let mut cmd = session.build_shell_command(&step.command)?;
+	let status = jobs::stream_lines(&mut cmd, job.token(), |stream, line| {
+		job.message(line.clone());
+		let _ = app_handle.emit(
+			"bootstrap-output",
+			BootstrapOutput {
+				job_id: job.id().to_string(),
+				step_id: step.id.clone(),
+				stream,
+				line,
+			},
+		);
+	})
+	.map_err(|e| match e.to_string().as_str() {
+		JOB_CANCELLED => e,
+		_ => anyhow!("Failed to run {}: {}", step.command, e),
+	})?;
+	Ok(status.code())
+}
+
+/// Runs `steps` in order as one job, stopping at the first that fails.
+fn run_steps(
+	session: &OsSession,
+	steps: &[BootstrapStep],
+	job: &JobHandle,
+	app_handle: &AppHandle,
+) -> Result<Vec<StepResult>> {
+	let mut results = Vec::new();
+	for (index, step) in steps.iter().enumerate() {
+		job.progress(JobProgress {
+			message: Some(step.name.clone()),
+			completed: Some(index as u64),
+			total: Some(steps.len() as u64),
+		});
+		let exit_code = run_step(session, step, job, app_handle)?;
+		if exit_code != Some(0) {
+			return Err(anyhow!(
+				"{} failed: `{}` exited with code {}",
+				step.name,
+				step.command,
+				exit_code.map_or("unknown".to_string(), |code| code.to_string())
+			));
+		}
+		results.push(StepResult {
+			id: step.id.clone(),
+			name: step.name.clone(),
+			exit_code,
+		});
+	}
+	Ok(results)
+}
+
+/// What the project in `os_session` needs before it can be built or run:
+/// missing `node_modules`, an unfetched Cargo registry, a Python virtual
+/// environment, plus the steps in `.ariana/bootstrap.toml`.
+#[tauri::command]
+pub async fn detect_bootstrap(os_session: OsSession) -> Result<BootstrapPlan, String> {
+	tauri::async_runtime::spawn_blocking(move || plan(&os_session))
+		.await
+		.map_err(|e| e.to_string())
+}
+
+/// Runs the needed bootstrap steps, or only those in `step_ids`, as a job
+/// and returns its id. Output arrives as `bootstrap-output` events.
+#[tauri::command]
+pub async fn run_bootstrap(
+	os_session: OsSession,
+	step_ids: Option<Vec<String>>,
+	job_manager: State<'_, Arc<JobManager>>,
+	app_handle: AppHandle,
+) -> Result<String, String> {
+	trust::ensure_trusted(&app_handle, &os_session, os_session.get_working_directory())?;
+	let session = os_session.clone();
+	let mut steps = tauri::async_runtime::spawn_blocking(move || plan(&session).steps)
+		.await
+		.map_err(|e| e.to_string())?;
+	if let Some(step_ids) = step_ids {
+		steps.retain(|step| step_ids.contains(&step.id));
+	}
+	if steps.is_empty() {
+		return Err("Nothing to set up".to_string());
+	}
+
+	let title = format!("Set up {}", os_session.get_working_directory());
+	Ok(job_manager.spawn(JobKind::Setup, title, move |job| {
+		run_steps(&os_session, &steps, job, &app_handle)
+	}))
+}
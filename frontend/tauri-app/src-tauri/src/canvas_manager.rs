@@ -0,0 +1,416 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use uuid::Uuid;
+
+use crate::git::{current_branch, run_git};
+use crate::os::OsSession;
+use crate::util::now_millis;
+
+/// Bumped when `ProjectCanvases` changes incompatibly; files written with
+/// another version are ignored.
+const CANVAS_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CanvasLockState {
+	#[default]
+	Normal,
+	/// A background agent is merging the canvas into the project root.
+	Merging,
+	Merged,
+}
+
+/// A canvas: a copy of the project on a branch of its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Canvas {
+	/// Assigned when a canvas with an empty id is saved.
+	#[serde(default)]
+	pub id: String,
+	pub name: String,
+	/// The session working in the canvas copy.
+	pub os_session: OsSession,
+	/// Branch the copy was created on, read from the copy when empty.
+	#[serde(default)]
+	pub branch: String,
+	/// Commit the copy started from, read from the copy when unset.
+	#[serde(default)]
+	pub base_commit: Option<String>,
+	#[serde(default)]
+	pub lock_state: CanvasLockState,
+	/// Background agent holding the lock.
+	#[serde(default)]
+	pub locking_agent_id: Option<String>,
+	/// Milliseconds since the Unix epoch.
+	#[serde(default)]
+	pub locked_at: Option<u64>,
+	#[serde(default)]
+	pub created_at: u64,
+	#[serde(default)]
+	pub last_modified: u64,
+}
+
+/// Something about a canvas that no longer matches the disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CanvasIssue {
+	/// The copy's directory is gone.
+	MissingDirectory,
+	/// The directory is no longer a git repository.
+	NotARepository,
+	/// Another branch is checked out, `None` for a detached HEAD.
+	#[serde(rename_all = "camelCase")]
+	BranchMismatch {
+		expected: String,
+		actual: Option<String>,
+	},
+	/// The base commit isn't in the repository, e.g. after a history rewrite.
+	#[serde(rename_all = "camelCase")]
+	MissingBaseCommit { commit: String },
+	/// The canvas was left merging by an agent from a previous session.
+	StaleLock,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanvasCheck {
+	pub canvas_id: String,
+	pub issues: Vec<CanvasIssue>,
+}
+
+/// Everything persisted for one project.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectCanvases {
+	version: u32,
+	/// The session of the project the canvases were copied from.
+	root: OsSession,
+	canvases: Vec<Canvas>,
+}
+
+/// The issues of `canvas` against the disk. Locks older than `started_at`
+/// were taken before the app started, by agents that are gone.
+fn check_canvas(canvas: &Canvas, started_at: u64) -> Vec<CanvasIssue> {
+	let mut issues = Vec::new();
+	if canvas.lock_state == CanvasLockState::Merging
+		&& canvas.locked_at.unwrap_or(0) < started_at
+	{
+		issues.push(CanvasIssue::StaleLock);
+	}
+
+	let session = &canvas.os_session;
+	let directory = session.get_working_directory();
+	if !session.host_path().is_dir() {
+		issues.push(CanvasIssue::MissingDirectory);
+		return issues;
+	}
+	if run_git(session, directory, &["rev-parse", "--git-dir"]).is_err() {
+		issues.push(CanvasIssue::NotARepository);
+		return issues;
+	}
+	if let Ok(actual) = current_branch(session, directory) {
+		if !canvas.branch.is_empty() && actual.as_deref() != Some(canvas.branch.as_str())
+		{
+			issues.push(CanvasIssue::BranchMismatch {
+				expected: canvas.branch.clone(),
+				actual,
+			});
+		}
+	}
+	if let Some(commit) = &canvas.base_commit {
+		let object = format!("{}^{{commit}}", commit);
+		if run_git(session, directory, &["cat-file", "-e", &object]).is_err() {
+			issues.push(CanvasIssue::MissingBaseCommit {
+				commit: commit.clone(),
+			});
+		}
+	}
+	issues
+}
+
+/// Canvas definitions of each project, kept in the app data directory so
+/// they survive the frontend and can be checked against the copies on disk.
+pub struct CanvasManager {
+	app_handle: AppHandle,
+	/// When the app started, to tell locks of this session from stale ones.
+	started_at: u64,
+	/// Loaded projects, by the key of their root.
+	projects: Mutex<HashMap<String, ProjectCanvases>>,
+}
+
+impl CanvasManager {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		Arc::new(Self {
+			app_handle,
+			started_at: now_millis(),
+			projects: Mutex::new(HashMap::new()),
+		})
+	}
+
+	fn project_key(root: &OsSession) -> String {
+		let mut hasher = DefaultHasher::new();
+		match root {
+			OsSession::Local(path) => path.hash(&mut hasher),
+			OsSession::Wsl(wsl_session) => {
+				wsl_session.distribution.hash(&mut hasher);
+				wsl_session.working_directory.hash(&mut hasher);
+			}
+		}
+		format!("{:016x}", hasher.finish())
+	}
+
+	fn project_path(&self, key: &str) -> Result<PathBuf> {
+		Ok(self
+			.app_handle
+			.path()
+			.app_data_dir()?
+			.join("canvases")
+			.join(format!("{}.json", key)))
+	}
+
+	/// Runs `f` on the canvases of `root`, loading them from disk the first
+	/// time and checking them against the copies, whose issues are logged.
+	fn with_project<T>(
+		&self,
+		root: &OsSession,
+		f: impl FnOnce(&mut ProjectCanvases) -> Result<T>,
+	) -> Result<T> {
+		let key = Self::project_key(root);
+		let mut projects = self.projects.lock().unwrap();
+		if !projects.contains_key(&key) {
+			let project = fs::read(self.project_path(&key)?)
+				.ok()
+				.and_then(|bytes| serde_json::from_slice::<ProjectCanvases>(&bytes).ok())
+				.filter(|project| project.version == CANVAS_FORMAT_VERSION)
+				.unwrap_or_else(|| ProjectCanvases {
+					version: CANVAS_FORMAT_VERSION,
+					root: root.clone(),
+					canvases: Vec::new(),
+				});
+			for canvas in &project.canvases {
+				let issues = check_canvas(canvas, self.started_at);
+				if !issues.is_empty() {
+					log::warn!(
+						"Canvas {} ({}) is out of date: {:?}",
+						canvas.name,
+						canvas.id,
+						issues
+					);
+				}
+			}
+			projects.insert(key.clone(), project);
+		}
+		f(projects.get_mut(&key).unwrap())
+	}
+
+	fn persist(&self, project: &ProjectCanvases) -> Result<()> {
+		let path = self.project_path(&Self::project_key(&project.root))?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(&path, serde_json::to_vec_pretty(project)?)
+			.with_context(|| format!("Failed to write {}", path.display()))
+	}
+
+	pub fn list(&self, root: &OsSession) -> Result<Vec<Canvas>> {
+		self.with_project(root, |project| Ok(project.canvases.clone()))
+	}
+
+	pub fn get(&self, root: &OsSession, id: &str) -> Result<Option<Canvas>> {
+		self.with_project(root, |project| {
+			Ok(project
+				.canvases
+				.iter()
+				.find(|canvas| canvas.id == id)
+				.cloned())
+		})
+	}
+
+	/// Adds `canvas` or replaces the one with its id. A new canvas gets an
+	/// id, and its branch and base commit are read from its copy if unset.
+	pub fn save(&self, root: &OsSession, mut canvas: Canvas) -> Result<Canvas> {
+		let session = &canvas.os_session;
+		let directory = session.get_working_directory();
+		if canvas.branch.is_empty() {
+			canvas.branch = current_branch(session, directory)?.unwrap_or_default();
+		}
+		if canvas.base_commit.is_none() {
+			canvas.base_commit = run_git(session, directory, &["rev-parse", "HEAD"])
+				.ok()
+				.map(|commit| commit.trim().to_string());
+		}
+		let now = now_millis();
+		canvas.last_modified = now;
+		if canvas.lock_state == CanvasLockState::Normal {
+			canvas.locking_agent_id = None;
+			canvas.locked_at = None;
+		} else if canvas.locked_at.is_none() {
+			canvas.locked_at = Some(now);
+		}
+
+		self.with_project(root, |project| {
+			match project
+				.canvases
+				.iter_mut()
+				.find(|existing| !canvas.id.is_empty() && existing.id == canvas.id)
+			{
+				Some(existing) => {
+					canvas.created_at = existing.created_at;
+					*existing = canvas.clone();
+				}
+				None => {
+					if canvas.id.is_empty() {
+						canvas.id = Uuid::new_v4().to_string();
+					}
+					canvas.created_at = now;
+					project.canvases.push(canvas.clone());
+				}
+			}
+			self.persist(project)?;
+			Ok(canvas)
+		})
+	}
+
+	/// Forgets the canvas with `id`. Its copy is left on disk.
+	pub fn delete(&self, root: &OsSession, id: &str) -> Result<()> {
+		self.with_project(root, |project| {
+			let count = project.canvases.len();
+			project.canvases.retain(|canvas| canvas.id != id);
+			if project.canvases.len() == count {
+				return Err(anyhow!("Canvas not found: {}", id));
+			}
+			self.persist(project)
+		})
+	}
+
+	pub fn check(&self, root: &OsSession) -> Result<Vec<CanvasCheck>> {
+		let canvases = self.list(root)?;
+		Ok(canvases
+			.iter()
+			.map(|canvas| CanvasCheck {
+				canvas_id: canvas.id.clone(),
+				issues: check_canvas(canvas, self.started_at),
+			})
+			.collect())
+	}
+
+	/// Releases a stale lock and records the branch now checked out, so a
+	/// canvas matches its copy again. Missing copies can only be deleted.
+	pub fn repair(&self, root: &OsSession, id: &str) -> Result<Canvas> {
+		let mut canvas = self
+			.get(root, id)?
+			.ok_or_else(|| anyhow!("Canvas not found: {}", id))?;
+		for issue in check_canvas(&canvas, self.started_at) {
+			match issue {
+				CanvasIssue::StaleLock => {
+					canvas.lock_state = CanvasLockState::Normal;
+				}
+				CanvasIssue::BranchMismatch { actual, .. } => {
+					canvas.branch = actual.unwrap_or_default();
+				}
+				CanvasIssue::MissingBaseCommit { .. } => {
+					canvas.base_commit = None;
+				}
+				CanvasIssue::MissingDirectory | CanvasIssue::NotARepository => {
+					return Err(anyhow!(
+						"Canvas {} has no repository at {}",
+						canvas.name,
+						canvas.os_session.get_working_directory()
+					));
+				}
+			}
+		}
+		self.save(root, canvas)
+	}
+}
+
+/// Canvases of the project at `project_root`.
+#[tauri::command]
+pub async fn list_canvases(
+	project_root: OsSession,
+	manager: State<'_, Arc<CanvasManager>>,
+) -> Result<Vec<Canvas>, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.list(&project_root))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_canvas(
+	project_root: OsSession,
+	canvas_id: String,
+	manager: State<'_, Arc<CanvasManager>>,
+) -> Result<Option<Canvas>, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.get(&project_root, &canvas_id))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+/// Creates or updates a canvas, returning it as saved.
+#[tauri::command]
+pub async fn save_canvas(
+	project_root: OsSession,
+	canvas: Canvas,
+	manager: State<'_, Arc<CanvasManager>>,
+) -> Result<Canvas, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.save(&project_root, canvas))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_canvas(
+	project_root: OsSession,
+	canvas_id: String,
+	manager: State<'_, Arc<CanvasManager>>,
+) -> Result<(), String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		manager.delete(&project_root, &canvas_id)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Checks every canvas of the project against its copy on disk.
+#[tauri::command]
+pub async fn check_canvases(
+	project_root: OsSession,
+	manager: State<'_, Arc<CanvasManager>>,
+) -> Result<Vec<CanvasCheck>, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.check(&project_root))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn repair_canvas(
+	project_root: OsSession,
+	canvas_id: String,
+	manager: State<'_, Arc<CanvasManager>>,
+) -> Result<Canvas, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		manager.repair(&project_root, &canvas_id)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
@@ -0,0 +1,175 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use clipboard_rs::common::RustImage;
+use clipboard_rs::{Clipboard, ClipboardContent, ClipboardContext, ContentFormat};
+use serde::Serialize;
+use tauri::{State, Url};
+
+use crate::os::OsSession;
+
+/// An image pasted from the clipboard into a file.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PastedImage {
+	pub path: String,
+	pub width: u32,
+	pub height: u32,
+}
+
+/// Owns the OS clipboard connection. On X11 the copied content is served
+/// from this process, so the connection lives as long as the app rather
+/// than one command; it is opened on first use.
+#[derive(Default)]
+pub struct ClipboardManager {
+	context: Mutex<Option<ClipboardContext>>,
+}
+
+impl ClipboardManager {
+	fn with<T>(
+		&self,
+		f: impl FnOnce(&ClipboardContext) -> clipboard_rs::Result<T>,
+	) -> Result<T> {
+		let mut context = self.context.lock().unwrap();
+		if context.is_none() {
+			*context = Some(
+				ClipboardContext::new()
+					.map_err(|e| anyhow!("Failed to open the clipboard: {}", e))?,
+			);
+		}
+		f(context.as_ref().unwrap()).map_err(|e| anyhow!("Clipboard error: {}", e))
+	}
+
+	/// Puts `paths`, as seen from this machine, on the clipboard as files
+	/// that file managers paste as copies.
+	pub fn copy_files(&self, paths: Vec<String>) -> Result<()> {
+		if paths.is_empty() {
+			return Err(anyhow!("No files to copy"));
+		}
+		// Linux file managers read a URI list, which needs escaping
+		#[cfg(target_os = "linux")]
+		let paths = paths
+			.iter()
+			.map(|path| {
+				Url::from_file_path(path)
+					.map(String::from)
+					.map_err(|_| anyhow!("Invalid path: {}", path))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		self.with(|context| context.set_files(paths))
+	}
+
+	/// Files copied in a file manager, as paths on this machine.
+	pub fn files(&self) -> Result<Vec<String>> {
+		let files = self.with(|context| context.get_files())?;
+		Ok(files
+			.into_iter()
+			.map(|file| {
+				match Url::parse(&file).ok().filter(|url| url.scheme() == "file") {
+					Some(url) => url
+						.to_file_path()
+						.map(|path| path.to_string_lossy().to_string())
+						.unwrap_or(file),
+					None => file,
+				}
+			})
+			.collect())
+	}
+
+	/// Copies `html` along with a plain `text` version for apps that can't
+	/// paste formatted text.
+	pub fn copy_html(&self, html: String, text: String) -> Result<()> {
+		self.with(|context| {
+			context.set(vec![
+				ClipboardContent::Text(text),
+				ClipboardContent::Html(html),
+			])
+		})
+	}
+
+	/// Writes the clipboard image to `path` as a PNG. `None` when the
+	/// clipboard holds no image.
+	pub fn save_image(&self, path: &std::path::Path) -> Result<Option<(u32, u32)>> {
+		if !self.with(|context| Ok(context.has(ContentFormat::Image)))? {
+			return Ok(None);
+		}
+		let image = self.with(|context| context.get_image())?;
+		let (width, height) = image.get_size();
+		let png = image
+			.to_png()
+			.map_err(|e| anyhow!("Failed to encode image: {}", e))?;
+		if let Some(dir) = path.parent() {
+			std::fs::create_dir_all(dir)?;
+		}
+		std::fs::write(path, png.get_bytes())?;
+		Ok(Some((width, height)))
+	}
+}
+
+/// Copies files so they can be pasted into Explorer, Finder or another
+/// file manager. Paths inside WSL are copied in their Windows form.
+#[tauri::command]
+pub async fn copy_files_to_clipboard(
+	paths: Vec<String>,
+	os_session: OsSession,
+	manager: State<'_, Arc<ClipboardManager>>,
+) -> Result<(), String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		let paths = paths
+			.iter()
+			.map(|path| os_session.host_path_for(path).to_string_lossy().to_string())
+			.collect();
+		manager.copy_files(paths)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Paths of the files on the clipboard, empty when it holds none.
+#[tauri::command]
+pub async fn get_clipboard_files(
+	manager: State<'_, Arc<ClipboardManager>>,
+) -> Result<Vec<String>, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.files())
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn copy_html_to_clipboard(
+	html: String,
+	text: String,
+	manager: State<'_, Arc<ClipboardManager>>,
+) -> Result<(), String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.copy_html(html, text))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+/// Saves the clipboard image as a PNG at `path`, a path in `os_session`.
+/// Returns `None` when the clipboard holds no image.
+#[tauri::command]
+pub async fn paste_clipboard_image(
+	path: String,
+	os_session: OsSession,
+	manager: State<'_, Arc<ClipboardManager>>,
+) -> Result<Option<PastedImage>, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		let size = manager.save_image(&os_session.host_path_for(&path))?;
+		Ok::<_, anyhow::Error>(size.map(|(width, height)| PastedImage {
+			path,
+			width,
+			height,
+		}))
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
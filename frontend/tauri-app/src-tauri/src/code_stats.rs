@@ -0,0 +1,400 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use walkdir::WalkDir;
+
+use crate::index_manager::{file_type_for, is_ignored_dir, modified_millis};
+use crate::os::OsSession;
+
+/// Bumped whenever the persisted format or the way lines are counted
+/// changes, so stale caches are recounted instead of loaded.
+const STATS_FORMAT_VERSION: u32 = 1;
+/// Files larger than this are almost always generated or minified.
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// How a language writes comments, for telling comment lines from code.
+/// Languages are matched by the name `file_type_for` gives a file.
+struct Language {
+	name: &'static str,
+	line_comments: &'static [&'static str],
+	block_comments: &'static [(&'static str, &'static str)],
+}
+
+const C_LINE: &[&str] = &["//"];
+const C_BLOCK: &[(&str, &str)] = &[("/*", "*/")];
+const HASH: &[&str] = &["#"];
+const XML_BLOCK: &[(&str, &str)] = &[("<!--", "-->")];
+
+const fn language(
+	name: &'static str,
+	line_comments: &'static [&'static str],
+	block_comments: &'static [(&'static str, &'static str)],
+) -> Language {
+	Language {
+		name,
+		line_comments,
+		block_comments,
+	}
+}
+
+const LANGUAGES: &[Language] = &[
+	language("Rust", C_LINE, C_BLOCK),
+	language("TypeScript", C_LINE, C_BLOCK),
+	language("TSX", C_LINE, C_BLOCK),
+	language("JavaScript", C_LINE, C_BLOCK),
+	language("Python", HASH, &[]),
+	language("Go", C_LINE, C_BLOCK),
+	language("Java", C_LINE, C_BLOCK),
+	language("Kotlin", C_LINE, C_BLOCK),
+	language("Swift", C_LINE, C_BLOCK),
+	language("C", C_LINE, C_BLOCK),
+	language("C++", C_LINE, C_BLOCK),
+	language("C#", C_LINE, C_BLOCK),
+	language("Scala", C_LINE, C_BLOCK),
+	language("Dart", C_LINE, C_BLOCK),
+	language("Zig", C_LINE, &[]),
+	language("Solidity", C_LINE, C_BLOCK),
+	language("Protobuf", C_LINE, C_BLOCK),
+	language("PHP", &["//", "#"], C_BLOCK),
+	language("Ruby", HASH, &[("=begin", "=end")]),
+	language("Elixir", HASH, &[]),
+	language("Haskell", &["--"], &[("{-", "-}")]),
+	language("Lua", &["--"], &[("--[[", "]]")]),
+	language("SQL", &["--"], C_BLOCK),
+	language("Shell", HASH, &[]),
+	language("PowerShell", HASH, &[("<#", "#>")]),
+	language("Nix", HASH, C_BLOCK),
+	language("HTML", &[], XML_BLOCK),
+	language("Vue", C_LINE, &[("<!--", "-->"), ("/*", "*/")]),
+	language("Svelte", C_LINE, &[("<!--", "-->"), ("/*", "*/")]),
+	language("CSS", &[], C_BLOCK),
+	language("SCSS", C_LINE, C_BLOCK),
+	language("XML", &[], XML_BLOCK),
+	language("JSON", &[], &[]),
+	language("YAML", HASH, &[]),
+	language("TOML", HASH, &[]),
+	language("GraphQL", HASH, &[]),
+	language("Markdown", &[], XML_BLOCK),
+	language("Dockerfile", HASH, &[]),
+	language("Makefile", HASH, &[]),
+	language("CMake", HASH, &[]),
+];
+
+fn language_for(path: &Path) -> Option<&'static Language> {
+	let name = file_type_for(path)?.name;
+	LANGUAGES.iter().find(|language| language.name == name)
+}
+
+/// Line counts of one file, or of many added up.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineCounts {
+	pub code: u64,
+	pub comments: u64,
+	pub blanks: u64,
+	pub bytes: u64,
+}
+
+impl LineCounts {
+	fn add(&mut self, other: &LineCounts) {
+		self.code += other.code;
+		self.comments += other.comments;
+		self.blanks += other.blanks;
+		self.bytes += other.bytes;
+	}
+}
+
+/// Counts the code, comment and blank lines of `text`. A line with both
+/// code and a comment counts as code; comment markers inside strings
+/// aren't told apart.
+fn count_lines(language: &Language, text: &str) -> LineCounts {
+	let mut counts = LineCounts {
+		bytes: text.len() as u64,
+		..LineCounts::default()
+	};
+	// The end marker of the block comment the line starts in
+	let mut open_block: Option<&str> = None;
+	for line in text.lines() {
+		let mut rest = line.trim();
+		if rest.is_empty() && open_block.is_none() {
+			counts.blanks += 1;
+			continue;
+		}
+		let mut code = false;
+		let mut comment = open_block.is_some();
+		while !rest.is_empty() {
+			if let Some(end) = open_block {
+				match rest.find(end) {
+					Some(index) => {
+						rest = rest[index + end.len()..].trim_start();
+						open_block = None;
+					}
+					None => rest = "",
+				}
+				continue;
+			}
+			if language
+				.line_comments
+				.iter()
+				.any(|marker| rest.starts_with(marker))
+				&& !language
+					.block_comments
+					.iter()
+					.any(|(start, _)| rest.starts_with(start))
+			{
+				comment = true;
+				break;
+			}
+			if let Some((start, end)) = language
+				.block_comments
+				.iter()
+				.find(|(start, _)| rest.starts_with(start))
+			{
+				comment = true;
+				open_block = Some(end);
+				rest = &rest[start.len()..];
+				continue;
+			}
+			code = true;
+			// Skip to the next comment marker, if the line has one
+			let next = language
+				.line_comments
+				.iter()
+				.chain(language.block_comments.iter().map(|(start, _)| start))
+				.filter_map(|marker| rest.find(marker))
+				.min();
+			match next {
+				Some(0) | None => break,
+				Some(index) => rest = &rest[index..],
+			}
+		}
+		if code {
+			counts.code += 1;
+		} else if comment {
+			counts.comments += 1;
+		} else {
+			counts.blanks += 1;
+		}
+	}
+	counts
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileEntry {
+	size: u64,
+	/// Modification time in milliseconds since the epoch.
+	modified: u64,
+	language: String,
+	counts: LineCounts,
+}
+
+/// What is persisted for a workspace, keyed by path relative to the root.
+#[derive(Serialize, Deserialize)]
+struct PersistedStats {
+	version: u32,
+	root: PathBuf,
+	files: HashMap<PathBuf, FileEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguageStats {
+	pub language: String,
+	pub files: usize,
+	#[serde(flatten)]
+	pub counts: LineCounts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceStats {
+	pub root: String,
+	/// Most code first.
+	pub languages: Vec<LanguageStats>,
+	pub files: usize,
+	#[serde(flatten)]
+	pub total: LineCounts,
+	/// Files in no language it knows, or too large to count.
+	pub other_files: usize,
+	/// Files counted again because they changed since the last time.
+	pub recounted: usize,
+}
+
+/// Counted files of a workspace, by path relative to its root.
+type WorkspaceFiles = Arc<Mutex<HashMap<PathBuf, FileEntry>>>;
+
+/// Line counts of each file of the workspaces asked about, persisted in the
+/// app cache so only files that changed since are read again.
+pub struct CodeStatsCache {
+	app_handle: AppHandle,
+	workspaces: Mutex<HashMap<PathBuf, WorkspaceFiles>>,
+}
+
+impl CodeStatsCache {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		Arc::new(Self {
+			app_handle,
+			workspaces: Mutex::new(HashMap::new()),
+		})
+	}
+
+	fn cache_path(&self, root: &Path) -> Result<PathBuf> {
+		let mut hasher = DefaultHasher::new();
+		root.hash(&mut hasher);
+		Ok(self
+			.app_handle
+			.path()
+			.app_cache_dir()?
+			.join("code-stats")
+			.join(format!("{:016x}.json", hasher.finish())))
+	}
+
+	fn load(&self, root: &Path) -> HashMap<PathBuf, FileEntry> {
+		self.cache_path(root)
+			.ok()
+			.and_then(|path| fs::read(path).ok())
+			.and_then(|bytes| serde_json::from_slice::<PersistedStats>(&bytes).ok())
+			.filter(|stats| stats.version == STATS_FORMAT_VERSION && stats.root == root)
+			.map(|stats| stats.files)
+			.unwrap_or_default()
+	}
+
+	fn persist(&self, root: &Path, files: &HashMap<PathBuf, FileEntry>) -> Result<()> {
+		let path = self.cache_path(root)?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let stats = PersistedStats {
+			version: STATS_FORMAT_VERSION,
+			root: root.to_path_buf(),
+			files: files.clone(),
+		};
+		fs::write(&path, serde_json::to_vec(&stats)?)
+			.with_context(|| format!("Failed to write {}", path.display()))
+	}
+
+	/// Walks `root`, counting the lines of files that are new or changed
+	/// since the cached counts, and adds everything up per language.
+	pub fn stats(&self, root: &Path) -> Result<WorkspaceStats> {
+		let root = fs::canonicalize(root)
+			.with_context(|| format!("Failed to open workspace {}", root.display()))?;
+		if !root.is_dir() {
+			return Err(anyhow!("{} is not a directory", root.display()));
+		}
+		let workspace = self
+			.workspaces
+			.lock()
+			.unwrap()
+			.entry(root.clone())
+			.or_insert_with(|| Arc::new(Mutex::new(self.load(&root))))
+			.clone();
+		// One count at a time per workspace; a second caller reuses its work
+		let mut files = workspace.lock().unwrap();
+
+		let mut seen = HashMap::new();
+		let mut other_files = 0;
+		let mut recounted = 0;
+		for entry in WalkDir::new(&root)
+			.into_iter()
+			.filter_entry(|e| !is_ignored_dir(e))
+			.filter_map(|e| e.ok())
+		{
+			if !entry.file_type().is_file() {
+				continue;
+			}
+			let (Some(language), Ok(metadata)) =
+				(language_for(entry.path()), entry.metadata())
+			else {
+				other_files += 1;
+				continue;
+			};
+			if metadata.len() > MAX_FILE_BYTES {
+				other_files += 1;
+				continue;
+			}
+			let Ok(relative) = entry.path().strip_prefix(&root) else {
+				continue;
+			};
+			let relative = relative.to_path_buf();
+			let modified = modified_millis(&metadata);
+			let cached = files.remove(&relative).filter(|cached| {
+				cached.size == metadata.len()
+					&& cached.modified == modified
+					&& cached.language == language.name
+			});
+			let entry = match cached {
+				Some(cached) => cached,
+				None => {
+					let Ok(bytes) = fs::read(entry.path()) else {
+						other_files += 1;
+						continue;
+					};
+					recounted += 1;
+					FileEntry {
+						size: metadata.len(),
+						modified,
+						language: language.name.to_string(),
+						counts: count_lines(language, &String::from_utf8_lossy(&bytes)),
+					}
+				}
+			};
+			seen.insert(relative, entry);
+		}
+		// Whatever wasn't seen was deleted
+		let removed = !files.is_empty();
+		*files = seen;
+		if recounted > 0 || removed {
+			if let Err(e) = self.persist(&root, &files) {
+				log::warn!("Failed to persist code statistics: {}", e);
+			}
+		}
+
+		let mut languages: BTreeMap<&str, LanguageStats> = BTreeMap::new();
+		let mut total = LineCounts::default();
+		for entry in files.values() {
+			let stats = languages.entry(entry.language.as_str()).or_insert_with(|| {
+				LanguageStats {
+					language: entry.language.clone(),
+					files: 0,
+					counts: LineCounts::default(),
+				}
+			});
+			stats.files += 1;
+			stats.counts.add(&entry.counts);
+			total.add(&entry.counts);
+		}
+		let mut languages: Vec<LanguageStats> = languages.into_values().collect();
+		languages.sort_by_key(|stats| std::cmp::Reverse(stats.counts.code));
+
+		Ok(WorkspaceStats {
+			root: root.to_string_lossy().to_string(),
+			languages,
+			files: files.len(),
+			total,
+			other_files,
+			recounted,
+		})
+	}
+}
+
+/// Lines of code, comments and blanks per language in the session's
+/// project, with file counts. Only files changed since the last call are
+/// read again.
+#[tauri::command]
+pub async fn get_workspace_stats(
+	os_session: OsSession,
+	cache: State<'_, Arc<CodeStatsCache>>,
+) -> Result<WorkspaceStats, String> {
+	let cache = cache.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || cache.stats(&os_session.host_path()))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
@@ -0,0 +1,958 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+use anyhow::{anyhow, Context, Result};
+use automerge::{
+	transaction::Transactable, AutoCommit, Cursor, CursorPosition, ObjId, ObjType,
+	ReadDoc, TextEncoding, Value, ROOT,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, EventId, Listener, State};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{
+	client::IntoClientRequest, http::header::AUTHORIZATION, Message,
+};
+
+use crate::backend_client::BackendClient;
+use crate::document_manager::{DocumentListener, DocumentManager, DocumentState};
+use crate::os::OsSession;
+
+/// Someone connected to the room, as reported by the backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase", deserialize = "snake_case"))]
+pub struct CollabMember {
+	pub member_id: u64,
+	pub account_id: String,
+	pub email: String,
+}
+
+/// A terminal another member shares. It is a read-only stream: nothing typed
+/// here reaches the owner's shell.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase", deserialize = "snake_case"))]
+pub struct RemoteTerminal {
+	pub owner: u64,
+	pub terminal: String,
+	pub title: String,
+	/// Recent output, so late joiners see some context.
+	pub scrollback: String,
+}
+
+/// Payload of the `collab-status` event.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollabStatus {
+	pub room_id: Option<String>,
+	pub connected: bool,
+	pub member_id: Option<u64>,
+	pub members: Vec<CollabMember>,
+	/// Absolute paths of the documents kept in sync with the room.
+	pub documents: Vec<String>,
+	/// Local terminal ids streamed to the room.
+	pub shared_terminals: Vec<String>,
+	pub remote_terminals: Vec<RemoteTerminal>,
+	pub error: Option<String>,
+}
+
+/// Payload of the `collab-cursor` event, with UTF-16 offsets into the
+/// document text.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteCursor {
+	pub member_id: u64,
+	pub path: String,
+	pub anchor: usize,
+	pub head: usize,
+}
+
+/// Payload of the `collab-terminal-output` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteTerminalOutput {
+	pub owner: u64,
+	pub terminal: String,
+	pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WireCursor {
+	document: String,
+	anchor: String,
+	head: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WireMemberCursor {
+	member_id: u64,
+	#[serde(flatten)]
+	cursor: WireCursor,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage<'a> {
+	Open { document: &'a str },
+	Update { document: &'a str, change: String },
+	Cursor(WireCursor),
+	TerminalShare { terminal: &'a str, title: &'a str },
+	TerminalUnshare { terminal: &'a str },
+	TerminalOutput { terminal: &'a str, data: &'a str },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+	Welcome {
+		member_id: u64,
+		members: Vec<CollabMember>,
+		documents: Vec<String>,
+		terminals: Vec<RemoteTerminal>,
+		cursors: Vec<WireMemberCursor>,
+	},
+	Joined {
+		member: CollabMember,
+	},
+	Left {
+		member_id: u64,
+	},
+	Document {
+		document: String,
+		changes: Vec<String>,
+		create: bool,
+	},
+	Update {
+		document: String,
+		change: String,
+	},
+	Cursor {
+		from: u64,
+		#[serde(flatten)]
+		cursor: WireCursor,
+	},
+	TerminalShared {
+		terminal: RemoteTerminal,
+	},
+	TerminalUnshared {
+		owner: u64,
+		terminal: String,
+	},
+	TerminalOutput {
+		owner: u64,
+		terminal: String,
+		data: String,
+	},
+	Error {
+		message: String,
+	},
+}
+
+/// Local replica of a shared document. The editor text lives in the
+/// `DocumentManager`; `doc` holds the same text as an automerge text object
+/// so concurrent edits from several members merge.
+struct SharedDocument {
+	path: String,
+	doc: AutoCommit,
+	/// `None` until the member who created the document in the room has sent
+	/// its first change.
+	text: Option<ObjId>,
+	/// Editor text as of the last sync, to tell local edits from the echo of
+	/// remote ones.
+	last_text: String,
+	/// Remote cursors by member id.
+	cursors: HashMap<u64, (Cursor, Cursor)>,
+}
+
+impl SharedDocument {
+	fn new(path: String) -> Self {
+		SharedDocument {
+			path,
+			doc: AutoCommit::new_with_encoding(TextEncoding::Utf16CodeUnit),
+			text: None,
+			last_text: String::new(),
+			cursors: HashMap::new(),
+		}
+	}
+
+	/// Applies changes received from the room and returns the new editor
+	/// text, plus the changes to send back when this member created the
+	/// document.
+	fn apply_remote(
+		&mut self,
+		editor_text: &str,
+		changes: &[Vec<u8>],
+		create: bool,
+	) -> Result<(Option<String>, Vec<u8>)> {
+		for change in changes {
+			self.doc.load_incremental(change)?;
+		}
+		// Loaded changes are already known to the room; only our own
+		// changes are sent from here on.
+		let _ = self.doc.save_incremental();
+
+		let mut outgoing = Vec::new();
+		if self.text.is_none() {
+			self.text = find_text(&self.doc);
+			if self.text.is_none() && create {
+				let text = self.doc.put_object(ROOT, "text", ObjType::Text)?;
+				self.doc.update_text(&text, editor_text)?;
+				self.text = Some(text);
+				outgoing = self.doc.save_incremental();
+			}
+		}
+
+		match &self.text {
+			Some(text) => {
+				let text = self.doc.text(text)?;
+				self.last_text = text.clone();
+				Ok((Some(text), outgoing))
+			}
+			None => Ok((None, outgoing)),
+		}
+	}
+
+	/// Records an editor edit, returning the change to send, if any.
+	fn apply_local(&mut self, editor_text: &str) -> Result<Vec<u8>> {
+		let Some(text) = &self.text else {
+			return Ok(Vec::new());
+		};
+		if editor_text == self.last_text {
+			return Ok(Vec::new());
+		}
+		self.doc.update_text(text, editor_text)?;
+		self.last_text = editor_text.to_string();
+		Ok(self.doc.save_incremental())
+	}
+
+	fn cursor_at(&self, offset: usize) -> Result<Cursor> {
+		let text = self
+			.text
+			.as_ref()
+			.ok_or_else(|| anyhow!("Document is not synced yet"))?;
+		let position = if offset >= self.doc.length(text) {
+			CursorPosition::End
+		} else {
+			CursorPosition::Index(offset)
+		};
+		Ok(self.doc.get_cursor(text, position, None)?)
+	}
+
+	fn resolve_cursors(&self) -> Vec<RemoteCursor> {
+		let Some(text) = &self.text else {
+			return Vec::new();
+		};
+		self.cursors
+			.iter()
+			.filter_map(|(member_id, (anchor, head))| {
+				Some(RemoteCursor {
+					member_id: *member_id,
+					path: self.path.clone(),
+					anchor: self.doc.get_cursor_position(text, anchor, None).ok()?,
+					head: self.doc.get_cursor_position(text, head, None).ok()?,
+				})
+			})
+			.collect()
+	}
+}
+
+fn find_text(doc: &AutoCommit) -> Option<ObjId> {
+	match doc.get(ROOT, "text") {
+		Ok(Some((Value::Object(ObjType::Text), id))) => Some(id),
+		_ => None,
+	}
+}
+
+struct CollabSession {
+	/// Distinguishes this connection from earlier ones still shutting down.
+	connection: u64,
+	room_id: String,
+	root: PathBuf,
+	connected: bool,
+	member_id: Option<u64>,
+	members: Vec<CollabMember>,
+	outgoing: mpsc::UnboundedSender<String>,
+	/// Keyed by path relative to `root`, with `/` separators.
+	documents: HashMap<String, SharedDocument>,
+	/// Local terminal id -> listeners for its output and disconnect events.
+	shared_terminals: HashMap<String, (EventId, EventId)>,
+	remote_terminals: Vec<RemoteTerminal>,
+	error: Option<String>,
+}
+
+impl CollabSession {
+	fn send(&self, message: &ClientMessage) {
+		if let Ok(text) = serde_json::to_string(message) {
+			let _ = self.outgoing.send(text);
+		}
+	}
+
+	fn send_update(&self, document: &str, change: &[u8]) {
+		if !change.is_empty() {
+			self.send(&ClientMessage::Update {
+				document,
+				change: BASE64.encode(change),
+			});
+		}
+	}
+
+	/// Room id of the document at `path`, if it lies inside the session root.
+	fn document_id(&self, path: &str) -> Option<String> {
+		let relative = Path::new(path).strip_prefix(&self.root).ok()?;
+		let mut parts = Vec::new();
+		for component in relative.components() {
+			match component {
+				Component::Normal(part) => parts.push(part.to_str()?),
+				_ => return None,
+			}
+		}
+		(!parts.is_empty()).then(|| parts.join("/"))
+	}
+
+	fn document_by_path(&self, path: &str) -> Option<(&String, &SharedDocument)> {
+		self.documents
+			.iter()
+			.find(|(_, shared)| shared.path == path)
+	}
+
+	fn status(&self) -> CollabStatus {
+		let mut documents: Vec<String> = self
+			.documents
+			.values()
+			.map(|shared| shared.path.clone())
+			.collect();
+		documents.sort();
+		let mut shared_terminals: Vec<String> =
+			self.shared_terminals.keys().cloned().collect();
+		shared_terminals.sort();
+		CollabStatus {
+			room_id: Some(self.room_id.clone()),
+			connected: self.connected,
+			member_id: self.member_id,
+			members: self.members.clone(),
+			documents,
+			shared_terminals,
+			remote_terminals: self.remote_terminals.clone(),
+			error: self.error.clone(),
+		}
+	}
+}
+
+/// Pairs on a workspace through a backend collaboration room: open documents
+/// are synced as automerge text, cursors are relayed, and terminals can be
+/// streamed read-only to the other members.
+pub struct CollabManager {
+	app_handle: AppHandle,
+	documents: Arc<DocumentManager>,
+	session: Mutex<Option<CollabSession>>,
+	next_connection: Mutex<u64>,
+}
+
+impl CollabManager {
+	pub fn new(app_handle: AppHandle, documents: Arc<DocumentManager>) -> Arc<Self> {
+		let manager = Arc::new(Self {
+			app_handle,
+			documents: documents.clone(),
+			session: Mutex::new(None),
+			next_connection: Mutex::new(0),
+		});
+		documents.add_listener(Box::new(CollabDocumentListener {
+			manager: Arc::downgrade(&manager),
+		}));
+		manager
+	}
+
+	pub fn status(&self) -> CollabStatus {
+		self.session
+			.lock()
+			.unwrap()
+			.as_ref()
+			.map(CollabSession::status)
+			.unwrap_or_default()
+	}
+
+	fn emit_status(&self) {
+		let _ = self.app_handle.emit("collab-status", self.status());
+	}
+
+	/// Runs `f` on the session if it is still the one for `connection`.
+	fn with_session<T>(
+		&self,
+		connection: u64,
+		f: impl FnOnce(&mut CollabSession) -> T,
+	) -> Option<T> {
+		let mut session = self.session.lock().unwrap();
+		session
+			.as_mut()
+			.filter(|session| session.connection == connection)
+			.map(f)
+	}
+
+	pub fn join(self: &Arc<Self>, room_id: &str, root: PathBuf) -> Result<()> {
+		self.leave();
+
+		let backend = BackendClient::from_app(&self.app_handle)?;
+		let mut request = backend
+			.websocket_url(&format!("/api/collab/rooms/{}/ws", room_id))
+			.into_client_request()?;
+		request.headers_mut().insert(
+			AUTHORIZATION,
+			format!("Bearer {}", backend.token()).parse()?,
+		);
+
+		let connection = {
+			let mut next = self.next_connection.lock().unwrap();
+			*next += 1;
+			*next
+		};
+		let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+		*self.session.lock().unwrap() = Some(CollabSession {
+			connection,
+			room_id: room_id.to_string(),
+			root,
+			connected: false,
+			member_id: None,
+			members: Vec::new(),
+			outgoing,
+			documents: HashMap::new(),
+			shared_terminals: HashMap::new(),
+			remote_terminals: Vec::new(),
+			error: None,
+		});
+		self.emit_status();
+
+		let manager = Arc::downgrade(self);
+		tauri::async_runtime::spawn(async move {
+			let result = run_connection(&manager, connection, request, outgoing_rx).await;
+			if let Some(manager) = manager.upgrade() {
+				let changed = manager.with_session(connection, |session| {
+					session.connected = false;
+					if let Err(e) = &result {
+						session.error = Some(e.to_string());
+					}
+				});
+				if changed.is_some() {
+					manager.emit_status();
+				}
+			}
+		});
+		Ok(())
+	}
+
+	/// Leaves the room. Local documents and terminals are left as they are.
+	pub fn leave(&self) {
+		let Some(session) = self.session.lock().unwrap().take() else {
+			return;
+		};
+		for (data, disconnect) in session.shared_terminals.values() {
+			self.app_handle.unlisten(*data);
+			self.app_handle.unlisten(*disconnect);
+		}
+		// Dropping the session closes the outgoing channel, which ends the
+		// connection task.
+		drop(session);
+		self.emit_status();
+	}
+
+	/// Starts syncing the document at `path` with the room, opening it in the
+	/// document manager if needed.
+	pub fn share_document(&self, path: &str) -> Result<()> {
+		let connection = self.current_connection()?;
+		self.share_document_on(connection, path)
+	}
+
+	fn current_connection(&self) -> Result<u64> {
+		self.session
+			.lock()
+			.unwrap()
+			.as_ref()
+			.map(|session| session.connection)
+			.ok_or_else(|| anyhow!("Not in a collaboration room"))
+	}
+
+	fn share_document_on(&self, connection: u64, path: &str) -> Result<()> {
+		self.documents.open_document(path)?;
+		self.with_session(connection, |session| {
+			let document = session
+				.document_id(path)
+				.ok_or_else(|| anyhow!("{} is outside the shared workspace", path))?;
+			if !session.documents.contains_key(&document) {
+				session
+					.documents
+					.insert(document.clone(), SharedDocument::new(path.to_string()));
+				session.send(&ClientMessage::Open {
+					document: &document,
+				});
+			}
+			Ok(())
+		})
+		.unwrap_or_else(|| Err(anyhow!("Not in a collaboration room")))?;
+		self.emit_status();
+		Ok(())
+	}
+
+	/// Opens a document the room knows about, if it exists in this workspace.
+	fn open_room_document(&self, connection: u64, document: &str) {
+		let Some(path) =
+			self.with_session(connection, |session| session.root.join(document))
+		else {
+			return;
+		};
+		if !path.is_file() {
+			return;
+		}
+		if let Err(e) = self.share_document_on(connection, &path.to_string_lossy()) {
+			log::warn!("Could not open shared document {}: {}", document, e);
+		}
+	}
+
+	pub fn update_cursor(&self, path: &str, anchor: usize, head: usize) -> Result<()> {
+		let mut session = self.session.lock().unwrap();
+		let session = session
+			.as_mut()
+			.ok_or_else(|| anyhow!("Not in a collaboration room"))?;
+		let (document, shared) = session
+			.document_by_path(path)
+			.ok_or_else(|| anyhow!("Document is not shared: {}", path))?;
+		let cursor = WireCursor {
+			document: document.clone(),
+			anchor: shared.cursor_at(anchor)?.to_string(),
+			head: shared.cursor_at(head)?.to_string(),
+		};
+		session.send(&ClientMessage::Cursor(cursor));
+		Ok(())
+	}
+
+	/// Streams a terminal's output to the room. Other members only watch it.
+	pub fn share_terminal(self: &Arc<Self>, terminal: &str, title: &str) -> Result<()> {
+		let mut guard = self.session.lock().unwrap();
+		let session = guard
+			.as_mut()
+			.ok_or_else(|| anyhow!("Not in a collaboration room"))?;
+		if session.shared_terminals.contains_key(terminal) {
+			return Ok(());
+		}
+
+		let outgoing = session.outgoing.clone();
+		let terminal_id = terminal.to_string();
+		let data =
+			self.app_handle
+				.listen(format!("terminal-data-{}", terminal), move |event| {
+					let Ok(data) = serde_json::from_str::<String>(event.payload()) else {
+						return;
+					};
+					let message = ClientMessage::TerminalOutput {
+						terminal: &terminal_id,
+						data: &data,
+					};
+					if let Ok(text) = serde_json::to_string(&message) {
+						let _ = outgoing.send(text);
+					}
+				});
+		let manager = Arc::downgrade(self);
+		let terminal_id = terminal.to_string();
+		let disconnect = self.app_handle.listen(
+			format!("terminal-disconnect-{}", terminal),
+			move |_| {
+				// Unlistening from inside a handler isn't allowed, so do it
+				// from a task.
+				if let Some(manager) = manager.upgrade() {
+					let terminal_id = terminal_id.clone();
+					tauri::async_runtime::spawn(async move {
+						let _ = manager.unshare_terminal(&terminal_id);
+					});
+				}
+			},
+		);
+
+		session
+			.shared_terminals
+			.insert(terminal.to_string(), (data, disconnect));
+		session.send(&ClientMessage::TerminalShare { terminal, title });
+		drop(guard);
+		self.emit_status();
+		Ok(())
+	}
+
+	pub fn unshare_terminal(&self, terminal: &str) -> Result<()> {
+		let mut guard = self.session.lock().unwrap();
+		let session = guard
+			.as_mut()
+			.ok_or_else(|| anyhow!("Not in a collaboration room"))?;
+		let Some((data, disconnect)) = session.shared_terminals.remove(terminal) else {
+			return Ok(());
+		};
+		self.app_handle.unlisten(data);
+		self.app_handle.unlisten(disconnect);
+		session.send(&ClientMessage::TerminalUnshare { terminal });
+		drop(guard);
+		self.emit_status();
+		Ok(())
+	}
+
+	fn handle_message(&self, connection: u64, message: ServerMessage) {
+		match message {
+			ServerMessage::Welcome {
+				member_id,
+				members,
+				documents,
+				terminals,
+				cursors,
+			} => {
+				self.with_session(connection, |session| {
+					session.connected = true;
+					session.error = None;
+					session.member_id = Some(member_id);
+					session.members = members;
+					session.remote_terminals = terminals;
+				});
+				for document in &documents {
+					self.open_room_document(connection, document);
+				}
+				for cursor in cursors {
+					self.set_remote_cursor(connection, cursor.member_id, cursor.cursor);
+				}
+				self.emit_status();
+			}
+			ServerMessage::Joined { member } => {
+				self.with_session(connection, |session| session.members.push(member));
+				self.emit_status();
+			}
+			ServerMessage::Left { member_id } => {
+				self.with_session(connection, |session| {
+					session
+						.members
+						.retain(|member| member.member_id != member_id);
+					for shared in session.documents.values_mut() {
+						shared.cursors.remove(&member_id);
+					}
+				});
+				self.emit_status();
+			}
+			ServerMessage::Document {
+				document,
+				changes,
+				create,
+			} => self.apply_remote(connection, &document, &changes, create),
+			ServerMessage::Update { document, change } => {
+				let known = self
+					.with_session(connection, |session| {
+						session.documents.contains_key(&document)
+					})
+					.unwrap_or(false);
+				if known {
+					self.apply_remote(connection, &document, &[change], false);
+				} else {
+					// The reply to `open` carries the whole history,
+					// including this change.
+					self.open_room_document(connection, &document);
+				}
+			}
+			ServerMessage::Cursor { from, cursor } => {
+				self.set_remote_cursor(connection, from, cursor);
+			}
+			ServerMessage::TerminalShared { terminal } => {
+				self.with_session(connection, |session| {
+					session.remote_terminals.push(terminal)
+				});
+				self.emit_status();
+			}
+			ServerMessage::TerminalUnshared { owner, terminal } => {
+				self.with_session(connection, |session| {
+					session
+						.remote_terminals
+						.retain(|t| t.owner != owner || t.terminal != terminal)
+				});
+				self.emit_status();
+			}
+			ServerMessage::TerminalOutput {
+				owner,
+				terminal,
+				data,
+			} => {
+				let _ = self.app_handle.emit(
+					"collab-terminal-output",
+					RemoteTerminalOutput {
+						owner,
+						terminal,
+						data,
+					},
+				);
+			}
+			ServerMessage::Error { message } => {
+				log::warn!("Collaboration room error: {}", message);
+				self.with_session(connection, |session| session.error = Some(message));
+				self.emit_status();
+			}
+		}
+	}
+
+	/// Merges remote changes into a shared document and the editor text.
+	fn apply_remote(
+		&self,
+		connection: u64,
+		document: &str,
+		changes: &[String],
+		create: bool,
+	) {
+		let changes: Vec<Vec<u8>> = changes
+			.iter()
+			.filter_map(|change| BASE64.decode(change).ok())
+			.collect();
+		let Some(path) = self
+			.with_session(connection, |session| {
+				session
+					.documents
+					.get(document)
+					.map(|shared| shared.path.clone())
+			})
+			.flatten()
+		else {
+			return;
+		};
+
+		// The document manager lock is held while the closure runs, so no
+		// local edit slips in between merging and replacing the editor text.
+		// The resulting `did_change` matches `last_text` and isn't echoed.
+		let mut cursors = Vec::new();
+		self.documents.edit_document(&path, |editor_text| {
+			self.with_session(connection, |session| {
+				let shared = session.documents.get_mut(document)?;
+				match shared.apply_remote(editor_text, &changes, create) {
+					Ok((text, outgoing)) => {
+						cursors = shared.resolve_cursors();
+						session.send_update(document, &outgoing);
+						text
+					}
+					Err(e) => {
+						log::warn!("Could not apply changes to {}: {}", document, e);
+						None
+					}
+				}
+			})
+			.flatten()
+		});
+		for cursor in cursors {
+			let _ = self.app_handle.emit("collab-cursor", cursor);
+		}
+	}
+
+	fn set_remote_cursor(&self, connection: u64, member_id: u64, cursor: WireCursor) {
+		let resolved = self
+			.with_session(connection, |session| {
+				let shared = session.documents.get_mut(&cursor.document)?;
+				let anchor = Cursor::try_from(cursor.anchor.as_str()).ok()?;
+				let head = Cursor::try_from(cursor.head.as_str()).ok()?;
+				shared.cursors.insert(member_id, (anchor, head));
+				Some(shared.resolve_cursors())
+			})
+			.flatten()
+			.unwrap_or_default();
+		for cursor in resolved.into_iter().filter(|c| c.member_id == member_id) {
+			let _ = self.app_handle.emit("collab-cursor", cursor);
+		}
+	}
+
+	fn local_change(&self, path: &str, text: &str) {
+		let mut session = self.session.lock().unwrap();
+		let Some(session) = session.as_mut() else {
+			return;
+		};
+		let Some(document) = session.document_by_path(path).map(|(id, _)| id.clone())
+		else {
+			return;
+		};
+		let Some(shared) = session.documents.get_mut(&document) else {
+			return;
+		};
+		match shared.apply_local(text) {
+			Ok(change) => session.send_update(&document, &change),
+			Err(e) => log::warn!("Could not record edit to {}: {}", path, e),
+		}
+	}
+
+	/// A closed document stops syncing; it is reopened from the room's history
+	/// on the next remote change.
+	fn document_closed(&self, path: &str) {
+		let removed = {
+			let mut session = self.session.lock().unwrap();
+			let Some(session) = session.as_mut() else {
+				return;
+			};
+			let before = session.documents.len();
+			session.documents.retain(|_, shared| shared.path != path);
+			session.documents.len() != before
+		};
+		if removed {
+			self.emit_status();
+		}
+	}
+}
+
+struct CollabDocumentListener {
+	manager: Weak<CollabManager>,
+}
+
+impl DocumentListener for CollabDocumentListener {
+	fn did_open(&self, _state: &DocumentState, _text: &str) {}
+
+	fn did_change(&self, state: &DocumentState, text: &str) {
+		if let Some(manager) = self.manager.upgrade() {
+			manager.local_change(&state.path, text);
+		}
+	}
+
+	fn did_save(&self, _state: &DocumentState) {}
+
+	fn did_close(&self, state: &DocumentState) {
+		if let Some(manager) = self.manager.upgrade() {
+			manager.document_closed(&state.path);
+		}
+	}
+}
+
+/// Pumps the room socket until either side closes it.
+async fn run_connection(
+	manager: &Weak<CollabManager>,
+	connection: u64,
+	request: tokio_tungstenite::tungstenite::handshake::client::Request,
+	mut outgoing: mpsc::UnboundedReceiver<String>,
+) -> Result<()> {
+	let (socket, _) = tokio_tungstenite::connect_async(request)
+		.await
+		.context("Could not connect to the collaboration room")?;
+	let (mut write, mut read) = socket.split();
+
+	loop {
+		tokio::select! {
+			message = outgoing.recv() => {
+				let Some(text) = message else {
+					let _ = write.close().await;
+					return Ok(());
+				};
+				write.send(Message::Text(text.into())).await?;
+			}
+			message = read.next() => {
+				let text = match message {
+					Some(Ok(Message::Text(text))) => text,
+					Some(Ok(Message::Close(_))) | None => return Ok(()),
+					Some(Ok(_)) => continue,
+					Some(Err(e)) => return Err(e.into()),
+				};
+				let Some(manager) = manager.upgrade() else {
+					return Ok(());
+				};
+				match serde_json::from_str::<ServerMessage>(&text) {
+					Ok(message) => {
+						// Applying changes takes the document manager lock,
+						// so keep it off the async runtime threads.
+						tauri::async_runtime::spawn_blocking(move || {
+							manager.handle_message(connection, message)
+						})
+						.await?;
+					}
+					Err(e) => log::warn!("Unexpected collaboration message: {}", e),
+				}
+			}
+		}
+	}
+}
+
+#[tauri::command]
+pub async fn create_collab_room(
+	os_session: OsSession,
+	app_handle: AppHandle,
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<String, String> {
+	#[derive(Deserialize)]
+	struct CreateRoomResponse {
+		room_id: String,
+	}
+
+	let backend = BackendClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	let response = backend
+		.request(Method::POST, "/api/collab/rooms")
+		.send()
+		.await
+		.and_then(|r| r.error_for_status())
+		.map_err(|e| e.to_string())?;
+	let room: CreateRoomResponse = response.json().await.map_err(|e| e.to_string())?;
+
+	manager
+		.join(&room.room_id, os_session.host_path())
+		.map_err(|e| e.to_string())?;
+	Ok(room.room_id)
+}
+
+#[tauri::command]
+pub async fn join_collab_room(
+	room_id: String,
+	os_session: OsSession,
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<(), String> {
+	manager
+		.join(&room_id, os_session.host_path())
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn leave_collab_room(
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<(), String> {
+	manager.leave();
+	Ok(())
+}
+
+#[tauri::command]
+pub async fn get_collab_status(
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<CollabStatus, String> {
+	Ok(manager.status())
+}
+
+#[tauri::command]
+pub async fn share_collab_document(
+	path: String,
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<(), String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.share_document(&path))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_collab_cursor(
+	path: String,
+	anchor: usize,
+	head: usize,
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<(), String> {
+	manager
+		.update_cursor(&path, anchor, head)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn share_collab_terminal(
+	terminal_id: String,
+	title: String,
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<(), String> {
+	manager
+		.share_terminal(&terminal_id, &title)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unshare_collab_terminal(
+	terminal_id: String,
+	manager: State<'_, Arc<CollabManager>>,
+) -> Result<(), String> {
+	manager
+		.unshare_terminal(&terminal_id)
+		.map_err(|e| e.to_string())
+}
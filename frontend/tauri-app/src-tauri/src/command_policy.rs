@@ -0,0 +1,555 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::{
+	DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+use tauri_plugin_store::StoreExt;
+
+use crate::util::write_private;
+
+/// Store holding the default command limits.
+const COMMAND_POLICY_STORE: &str = "command_policy.json";
+
+/// The allow-list lives in this directory of the app's local data, which no
+/// webview fs scope reaches, and only Rust reads and writes it. It is not kept
+/// in a store: the webview can write those through the store plugin.
+const POLICY_DIR: &str = "command-policy";
+const ALLOW_LIST_FILE: &str = "allowed-programs";
+/// First line of the allow-list. A file without it, such as JSON written
+/// through the store plugin, is ignored.
+const ALLOW_LIST_HEADER: &str = "# Programs the app may run without asking, one per line";
+
+/// Programs the frontend runs itself, allowed until the user changes the list.
+/// `git` is not one of them: its `-c` options and config can run any command,
+/// so it is confirmed on first use like other programs.
+const DEFAULT_ALLOWED: [&str; 1] = ["test"];
+
+const AUDIT_LOG_FILE: &str = "command_audit.log";
+/// The audit log is rotated to `command_audit.log.1` once it reaches this size.
+const MAX_AUDIT_LOG_SIZE: u64 = 5 * 1024 * 1024;
+
+const MAX_ARGS: usize = 256;
+/// Combined length of the program and its arguments, in bytes.
+const MAX_COMMAND_LENGTH: usize = 64 * 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Bytes kept of each of stdout and stderr; the rest is dropped.
+const DEFAULT_MAX_OUTPUT: usize = 10 * 1024 * 1024;
+const MAX_OUTPUT: usize = 100 * 1024 * 1024;
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+const RUN_ONCE: &str = "Run once";
+const ALWAYS_ALLOW: &str = "Always allow";
+const CANCEL: &str = "Cancel";
+
+/// Serializes writes to the audit log.
+static AUDIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Why a command was or wasn't run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+	/// The program is on the allow-list.
+	Allowed,
+	/// The user confirmed it in a dialog.
+	Approved,
+	/// The user declined it.
+	Denied,
+	/// It broke the argument limits.
+	Rejected,
+}
+
+/// One command the webview asked to run, as kept in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+	/// RFC 3339, UTC.
+	pub timestamp: String,
+	pub program: String,
+	pub args: Vec<String>,
+	pub directory: Option<String>,
+	/// Set for commands run inside WSL.
+	pub distribution: Option<String>,
+	pub decision: Decision,
+	pub exit_code: Option<i32>,
+	pub duration_ms: Option<u64>,
+	pub error: Option<String>,
+}
+
+/// How long a command may run and how much of its output is kept.
+/// Unset fields fall back to the limits saved with `set_command_limits`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOptions {
+	/// Milliseconds; capped at ten minutes.
+	#[serde(default)]
+	pub timeout_ms: Option<u64>,
+	/// Bytes kept of each of stdout and stderr, capped at 100 MiB. Output
+	/// past it is replaced by a truncation marker.
+	#[serde(default)]
+	pub max_output_bytes: Option<usize>,
+	/// Emit the output as `command-output-{streamId}` events while the
+	/// command runs instead of collecting it. The command then returns an
+	/// empty string.
+	#[serde(default)]
+	pub stream_id: Option<String>,
+}
+
+/// A piece of a streamed command's output.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOutput {
+	/// `stdout` or `stderr`.
+	pub stream: &'static str,
+	pub data: String,
+}
+
+/// A command from the webview, checked against the policy before it runs.
+pub struct CommandRequest {
+	pub program: String,
+	pub args: Vec<String>,
+	pub directory: Option<String>,
+	pub distribution: Option<String>,
+	pub options: CommandOptions,
+}
+
+fn allow_list_path(app_handle: &AppHandle) -> Result<PathBuf> {
+	Ok(app_handle
+		.path()
+		.app_local_data_dir()?
+		.join(POLICY_DIR)
+		.join(ALLOW_LIST_FILE))
+}
+
+pub fn allowed_programs(app_handle: &AppHandle) -> Result<Vec<String>> {
+	let path = allow_list_path(app_handle)?;
+	let defaults = || DEFAULT_ALLOWED.map(str::to_string).to_vec();
+	let content = match fs::read_to_string(&path) {
+		Ok(content) => content,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(defaults()),
+		Err(e) => return Err(e.into()),
+	};
+	let mut lines = content.lines();
+	if lines.next() != Some(ALLOW_LIST_HEADER) {
+		log::warn!("Ignoring malformed command allow-list {}", path.display());
+		return Ok(defaults());
+	}
+	Ok(lines
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(str::to_string)
+		.collect())
+}
+
+/// Replaces the allow-list. It is written owner-only and never left
+/// half-written.
+fn set_allowed_programs(app_handle: &AppHandle, programs: &[String]) -> Result<()> {
+	let path = allow_list_path(app_handle)?;
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir)?;
+	}
+	let mut content = format!("{}\n", ALLOW_LIST_HEADER);
+	for program in programs.iter().filter(|p| !p.contains(['\n', '\r'])) {
+		content.push_str(program);
+		content.push('\n');
+	}
+	write_private(&path, content.as_bytes())
+}
+
+/// The limits used for options a command leaves unset.
+pub fn default_limits(app_handle: &AppHandle) -> Result<CommandOptions> {
+	let store = app_handle.store(COMMAND_POLICY_STORE)?;
+	Ok(store
+		.get("limits")
+		.and_then(|v| serde_json::from_value(v).ok())
+		.unwrap_or_default())
+}
+
+fn check_limits(request: &CommandRequest) -> Result<(), String> {
+	if request.program.trim().is_empty() {
+		return Err("COMMAND_REJECTED: no program given".to_string());
+	}
+	if request.args.len() > MAX_ARGS {
+		return Err(format!(
+			"COMMAND_REJECTED: {} arguments, at most {} are allowed",
+			request.args.len(),
+			MAX_ARGS
+		));
+	}
+	let length =
+		request.program.len() + request.args.iter().map(String::len).sum::<usize>();
+	if length > MAX_COMMAND_LENGTH {
+		return Err(format!(
+			"COMMAND_REJECTED: the command is {} bytes, at most {} are allowed",
+			length, MAX_COMMAND_LENGTH
+		));
+	}
+	Ok(())
+}
+
+/// Asks the user in a native dialog, which the webview can't answer for
+/// them, whether to run `request`.
+fn confirm(app_handle: &AppHandle, request: &CommandRequest) -> Result<Decision> {
+	let mut command = request.program.clone();
+	for arg in &request.args {
+		command.push(' ');
+		command.push_str(arg);
+	}
+	let location = match (&request.directory, &request.distribution) {
+		(Some(directory), Some(distribution)) => {
+			format!(" in {} ({})", directory, distribution)
+		}
+		(Some(directory), None) => format!(" in {}", directory),
+		(None, _) => String::new(),
+	};
+	let answer = app_handle
+		.dialog()
+		.message(format!(
+			"The app wants to run a program that isn't on the allowed list{}:\n\n{}",
+			location, command
+		))
+		.title("Run command?")
+		.kind(MessageDialogKind::Warning)
+		.buttons(MessageDialogButtons::YesNoCancelCustom(
+			RUN_ONCE.to_string(),
+			ALWAYS_ALLOW.to_string(),
+			CANCEL.to_string(),
+		))
+		.blocking_show_with_result();
+	match answer {
+		MessageDialogResult::Custom(label) if label == RUN_ONCE => Ok(Decision::Approved),
+		MessageDialogResult::Custom(label) if label == ALWAYS_ALLOW => {
+			let mut allowed = allowed_programs(app_handle)?;
+			if !allowed.contains(&request.program) {
+				allowed.push(request.program.clone());
+				set_allowed_programs(app_handle, &allowed)?;
+			}
+			Ok(Decision::Approved)
+		}
+		_ => Ok(Decision::Denied),
+	}
+}
+
+/// Output collected from one of a command's pipes.
+#[derive(Default)]
+struct Captured {
+	bytes: Vec<u8>,
+	/// Bytes read past the limit and dropped.
+	dropped: usize,
+}
+
+impl Captured {
+	fn into_string(self) -> String {
+		let mut text = String::from_utf8_lossy(&self.bytes).to_string();
+		if self.dropped > 0 {
+			text.push_str(&format!(
+				"\n[output truncated: {} more bytes]",
+				self.dropped
+			));
+		}
+		text
+	}
+}
+
+/// Reads `reader` to the end, keeping up to `limit` bytes, or emitting
+/// everything to `event` when streaming.
+fn pump(
+	mut reader: impl Read,
+	limit: usize,
+	event: Option<(AppHandle, String, &'static str)>,
+) -> Captured {
+	let mut captured = Captured::default();
+	let mut chunk = [0u8; READ_CHUNK_SIZE];
+	// Bytes of a character split across reads, held for the next event
+	let mut pending = Vec::new();
+	loop {
+		let read = match reader.read(&mut chunk) {
+			Ok(0) | Err(_) => break,
+			Ok(read) => read,
+		};
+		let chunk = &chunk[..read];
+		if let Some((app_handle, name, stream)) = &event {
+			pending.extend_from_slice(chunk);
+			let complete = match std::str::from_utf8(&pending) {
+				Ok(_) => pending.len(),
+				Err(e) if e.error_len().is_none() => e.valid_up_to(),
+				Err(_) => pending.len(),
+			};
+			let data = String::from_utf8_lossy(&pending[..complete]).to_string();
+			pending.drain(..complete);
+			let _ = app_handle.emit(name, CommandOutput { stream, data });
+			continue;
+		}
+		let kept = chunk.len().min(limit - captured.bytes.len());
+		captured.bytes.extend_from_slice(&chunk[..kept]);
+		captured.dropped += chunk.len() - kept;
+	}
+	if let Some((app_handle, name, stream)) = &event {
+		if !pending.is_empty() {
+			let data = String::from_utf8_lossy(&pending).to_string();
+			let _ = app_handle.emit(name, CommandOutput { stream, data });
+		}
+	}
+	captured
+}
+
+/// Runs `cmd`, killing it once `timeout` has passed. Returns its exit code
+/// with stdout and stderr, which are empty when streamed to `stream_id`.
+fn output_with_timeout(
+	app_handle: &AppHandle,
+	mut cmd: Command,
+	timeout: Duration,
+	max_output: usize,
+	stream_id: Option<&str>,
+) -> Result<(ExitStatus, String, String), String> {
+	let mut child = cmd
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| format!("Failed to execute command: {}", e))?;
+
+	// Read both pipes as the command runs so it can't block on a full one
+	let event = |stream: &'static str| {
+		stream_id.map(|id| (app_handle.clone(), format!("command-output-{}", id), stream))
+	};
+	let stdout = child.stdout.take().unwrap();
+	let stderr = child.stderr.take().unwrap();
+	let stdout_event = event("stdout");
+	let stderr_event = event("stderr");
+	let stdout_reader = thread::spawn(move || pump(stdout, max_output, stdout_event));
+	let stderr_reader = thread::spawn(move || pump(stderr, max_output, stderr_event));
+
+	let deadline = Instant::now() + timeout;
+	let status = loop {
+		match child.try_wait() {
+			Ok(Some(status)) => break status,
+			Ok(None) if Instant::now() >= deadline => {
+				let _ = child.kill();
+				let _ = child.wait();
+				return Err(format!(
+					"COMMAND_TIMED_OUT: stopped after {} seconds",
+					timeout.as_secs()
+				));
+			}
+			Ok(None) => thread::sleep(Duration::from_millis(20)),
+			Err(e) => return Err(format!("Failed to wait for command: {}", e)),
+		}
+	};
+	Ok((
+		status,
+		stdout_reader.join().unwrap_or_default().into_string(),
+		stderr_reader.join().unwrap_or_default().into_string(),
+	))
+}
+
+fn audit(app_handle: &AppHandle, entry: &AuditEntry) -> Result<()> {
+	let dir = app_handle.path().app_log_dir()?;
+	fs::create_dir_all(&dir)?;
+	let path = dir.join(AUDIT_LOG_FILE);
+	let _lock = AUDIT_LOCK.lock().unwrap();
+	if fs::metadata(&path).is_ok_and(|meta| meta.len() > MAX_AUDIT_LOG_SIZE) {
+		fs::rename(&path, dir.join(format!("{}.1", AUDIT_LOG_FILE)))?;
+	}
+	let mut line = serde_json::to_string(entry)?;
+	line.push('\n');
+	OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(&path)?
+		.write_all(line.as_bytes())?;
+	Ok(())
+}
+
+/// Runs `cmd` for `request` if the policy lets it: programs on the
+/// allow-list run directly, others only once the user confirms them.
+/// Every request is written to the audit log. Returns stdout on success
+/// and stderr otherwise.
+pub async fn run(
+	app_handle: &AppHandle,
+	request: CommandRequest,
+	cmd: Command,
+) -> Result<String, String> {
+	let app_handle = app_handle.clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		let mut entry = AuditEntry {
+			timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+			program: request.program.clone(),
+			args: request.args.clone(),
+			directory: request.directory.clone(),
+			distribution: request.distribution.clone(),
+			decision: Decision::Allowed,
+			exit_code: None,
+			duration_ms: None,
+			error: None,
+		};
+		let result = authorize_and_run(&app_handle, &request, cmd, &mut entry);
+		if let Err(e) = &result {
+			entry.error = Some(e.clone());
+		}
+		if let Err(e) = audit(&app_handle, &entry) {
+			log::error!("Failed to write command audit log: {}", e);
+		}
+		result
+	})
+	.await
+	.map_err(|e| e.to_string())?
+}
+
+fn authorize_and_run(
+	app_handle: &AppHandle,
+	request: &CommandRequest,
+	cmd: Command,
+	entry: &mut AuditEntry,
+) -> Result<String, String> {
+	if let Err(e) = check_limits(request) {
+		entry.decision = Decision::Rejected;
+		return Err(e);
+	}
+	let allowed = allowed_programs(app_handle).map_err(|e| e.to_string())?;
+	if !allowed.contains(&request.program) {
+		entry.decision = confirm(app_handle, request).map_err(|e| e.to_string())?;
+		if entry.decision == Decision::Denied {
+			return Err(format!("COMMAND_DENIED: {}", request.program));
+		}
+	}
+
+	let defaults = default_limits(app_handle).map_err(|e| e.to_string())?;
+	let options = &request.options;
+	let timeout = options
+		.timeout_ms
+		.or(defaults.timeout_ms)
+		.map(Duration::from_millis)
+		.unwrap_or(DEFAULT_TIMEOUT)
+		.min(MAX_TIMEOUT);
+	let max_output = options
+		.max_output_bytes
+		.or(defaults.max_output_bytes)
+		.unwrap_or(DEFAULT_MAX_OUTPUT)
+		.min(MAX_OUTPUT);
+	let started = Instant::now();
+	let output = output_with_timeout(
+		app_handle,
+		cmd,
+		timeout,
+		max_output,
+		options.stream_id.as_deref(),
+	);
+	entry.duration_ms = Some(started.elapsed().as_millis() as u64);
+	let (status, stdout, stderr) = output?;
+	entry.exit_code = status.code();
+
+	if status.success() {
+		Ok(stdout)
+	} else if options.stream_id.is_some() {
+		// stderr was already streamed
+		Err(format!(
+			"Command exited with code {}",
+			status.code().unwrap_or(-1)
+		))
+	} else {
+		Err(stderr)
+	}
+}
+
+#[tauri::command]
+pub async fn get_allowed_commands(app_handle: AppHandle) -> Result<Vec<String>, String> {
+	allowed_programs(&app_handle).map_err(|e| e.to_string())
+}
+
+/// Adds `program` to or removes it from the allow-list. Adding asks the
+/// user first, so the webview can't allow programs by itself.
+#[tauri::command]
+pub async fn set_command_allowed(
+	program: String,
+	allowed: bool,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		let mut programs = allowed_programs(&app_handle).map_err(|e| e.to_string())?;
+		programs.retain(|p| p != &program);
+		if allowed {
+			let confirmed = app_handle
+				.dialog()
+				.message(format!(
+					"Allow the app to run {} without asking each time?",
+					program
+				))
+				.title("Allow command?")
+				.kind(MessageDialogKind::Warning)
+				.buttons(MessageDialogButtons::OkCancelCustom(
+					ALWAYS_ALLOW.to_string(),
+					CANCEL.to_string(),
+				))
+				.blocking_show_with_result();
+			if confirmed != MessageDialogResult::Custom(ALWAYS_ALLOW.to_string()) {
+				return Err(format!("COMMAND_DENIED: {}", program));
+			}
+			programs.push(program);
+		}
+		set_allowed_programs(&app_handle, &programs).map_err(|e| e.to_string())
+	})
+	.await
+	.map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_command_limits(app_handle: AppHandle) -> Result<CommandOptions, String> {
+	default_limits(&app_handle).map_err(|e| e.to_string())
+}
+
+/// Saves the timeout and output limit used when a command sets none.
+#[tauri::command]
+pub async fn set_command_limits(
+	timeout_ms: Option<u64>,
+	max_output_bytes: Option<usize>,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	let limits = CommandOptions {
+		timeout_ms: timeout_ms.map(|ms| ms.min(MAX_TIMEOUT.as_millis() as u64)),
+		max_output_bytes: max_output_bytes.map(|bytes| bytes.min(MAX_OUTPUT)),
+		stream_id: None,
+	};
+	let store = app_handle
+		.store(COMMAND_POLICY_STORE)
+		.map_err(|e| e.to_string())?;
+	store.set(
+		"limits",
+		serde_json::to_value(limits).map_err(|e| e.to_string())?,
+	);
+	store.save().map_err(|e| e.to_string())
+}
+
+/// The most recent audit log entries, oldest first.
+#[tauri::command]
+pub async fn get_command_audit_log(
+	limit: Option<usize>,
+	app_handle: AppHandle,
+) -> Result<Vec<AuditEntry>, String> {
+	let path = app_handle
+		.path()
+		.app_log_dir()
+		.map_err(|e| e.to_string())?
+		.join(AUDIT_LOG_FILE);
+	let content = match fs::read_to_string(&path) {
+		Ok(content) => content,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e.to_string()),
+	};
+	let entries: Vec<AuditEntry> = content
+		.lines()
+		.filter_map(|line| serde_json::from_str(line).ok())
+		.collect();
+	let skip = entries.len().saturating_sub(limit.unwrap_or(500));
+	Ok(entries.into_iter().skip(skip).collect())
+}
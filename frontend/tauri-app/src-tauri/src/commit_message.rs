@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::diff_summary::{self, SummaryModel};
+use crate::git::{current_branch, run_git};
+use crate::os::OsSession;
+
+/// How long a proposal waits for the user before the commit is cancelled.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Payload of the `commit-message-proposed` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitMessageProposal {
+	/// Answer with `confirm_commit_message`.
+	pub proposal_id: String,
+	pub directory: String,
+	pub message: String,
+	/// What the model made of the staged changes.
+	pub summary: String,
+}
+
+/// Generated commit messages waiting for the user to accept, edit or reject
+/// them, by proposal id.
+#[derive(Default)]
+pub struct CommitMessageProposals {
+	pending: Mutex<HashMap<String, oneshot::Sender<Option<String>>>>,
+}
+
+/// Stages every change, asks the model for a commit message and waits for
+/// the user to confirm it. Returns the confirmed, possibly edited, message,
+/// or `None` if the user rejected it.
+pub async fn propose(
+	app_handle: &AppHandle,
+	os_session: &OsSession,
+	directory: &str,
+	model: &SummaryModel,
+) -> Result<Option<String>> {
+	let (session, dir) = (os_session.clone(), directory.to_string());
+	let (branch, diff) = tauri::async_runtime::spawn_blocking(move || -> Result<_> {
+		run_git(&session, &dir, &["add", "."])?;
+		let diff = run_git(
+			&session,
+			&dir,
+			&["diff", "--cached", "--no-color", "--no-ext-diff"],
+		)?;
+		Ok((current_branch(&session, &dir)?, diff))
+	})
+	.await??;
+	if diff.trim().is_empty() {
+		return Err(anyhow!("NO_CHANGES_TO_COMMIT"));
+	}
+
+	let branch = branch.unwrap_or_else(|| "HEAD".to_string());
+	let answer = diff_summary::summarize(app_handle, &branch, &diff, model, None).await?;
+	let (summary, message) = diff_summary::split_answer(&answer);
+	// Without a suggestion, the summary's first line is the best subject
+	let message = message
+		.or_else(|| summary.lines().next().map(str::to_string))
+		.ok_or_else(|| anyhow!("The model didn't propose a commit message"))?;
+
+	let proposal_id = Uuid::new_v4().to_string();
+	let (sender, receiver) = oneshot::channel();
+	let proposals = app_handle.state::<Arc<CommitMessageProposals>>();
+	proposals
+		.pending
+		.lock()
+		.unwrap()
+		.insert(proposal_id.clone(), sender);
+	let _ = app_handle.emit(
+		"commit-message-proposed",
+		CommitMessageProposal {
+			proposal_id: proposal_id.clone(),
+			directory: directory.to_string(),
+			message,
+			summary,
+		},
+	);
+
+	let answer = tokio::time::timeout(CONFIRMATION_TIMEOUT, receiver).await;
+	proposals.pending.lock().unwrap().remove(&proposal_id);
+	Ok(match answer {
+		Ok(Ok(message)) => message.filter(|m| !m.trim().is_empty()),
+		_ => None,
+	})
+}
+
+/// Answers a `commit-message-proposed` event: `message` is the message to
+/// commit with, or `None` to cancel the commit.
+#[tauri::command]
+pub async fn confirm_commit_message(
+	proposal_id: String,
+	message: Option<String>,
+	proposals: State<'_, Arc<CommitMessageProposals>>,
+) -> Result<(), String> {
+	let sender = proposals
+		.pending
+		.lock()
+		.unwrap()
+		.remove(&proposal_id)
+		.ok_or_else(|| format!("No commit message proposal {}", proposal_id))?;
+	let _ = sender.send(message);
+	Ok(())
+}
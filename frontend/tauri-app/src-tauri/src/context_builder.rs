@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::git::run_git;
+use crate::os::OsSession;
+use crate::semantic_index::{SemanticHit, SemanticIndexManager};
+
+/// Shares of the budget open files and the git diff may use. Whatever a
+/// source leaves unused goes to the next one, and semantic hits get the rest.
+const OPEN_FILES_SHARE: f64 = 0.5;
+const GIT_DIFF_SHARE: f64 = 0.25;
+/// Blocks that would be truncated below this are left out instead.
+const MIN_BLOCK_TOKENS: usize = 64;
+const DEFAULT_SEMANTIC_RESULTS: usize = 20;
+
+/// A file open in the editor. `content` holds unsaved changes, if any.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenFile {
+	pub path: String,
+	pub content: Option<String>,
+	/// Zero-based line the cursor is on; truncation keeps the lines around it.
+	pub cursor_line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextRequest {
+	pub query: String,
+	pub token_budget: usize,
+	/// Most relevant first, usually the active editor.
+	#[serde(default)]
+	pub open_files: Vec<OpenFile>,
+	/// Workspace to search semantically and to show paths relative to.
+	pub root: Option<String>,
+	/// Repository whose uncommitted changes are included.
+	pub git_session: Option<OsSession>,
+	/// How many semantic hits to consider, 0 to skip semantic search.
+	pub semantic_results: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContextSource {
+	OpenFile,
+	GitDiff,
+	SemanticSearch,
+}
+
+/// A piece of the assembled context and where it came from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextBlock {
+	pub source: ContextSource,
+	pub path: String,
+	/// Zero-based, inclusive. Unset for diffs.
+	pub start_line: Option<usize>,
+	/// Zero-based, exclusive.
+	pub end_line: Option<usize>,
+	/// Similarity with the query, for semantic hits.
+	pub score: Option<f32>,
+	pub content: String,
+	pub tokens: usize,
+	pub truncated: bool,
+}
+
+/// Something that was considered but left out.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OmittedContext {
+	pub source: ContextSource,
+	pub path: Option<String>,
+	pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextBundle {
+	pub blocks: Vec<ContextBlock>,
+	pub omitted: Vec<OmittedContext>,
+	pub total_tokens: usize,
+	pub token_budget: usize,
+	/// The blocks formatted for a chat prompt, in order.
+	pub prompt: String,
+}
+
+/// Rough token count: providers' tokenizers average about four characters
+/// per token on code.
+pub fn estimate_tokens(text: &str) -> usize {
+	text.len().div_ceil(4)
+}
+
+/// The widest range of lines around `center` that fits in `max_tokens`.
+fn window(lines: &[&str], center: usize, max_tokens: usize) -> (usize, usize) {
+	let center = center.min(lines.len().saturating_sub(1));
+	let (mut start, mut end) = (center, center);
+	let mut tokens = 0;
+	loop {
+		let mut grew = false;
+		for forward in [true, false] {
+			let line = if forward {
+				lines.get(end)
+			} else if start > 0 {
+				lines.get(start - 1)
+			} else {
+				None
+			};
+			let Some(line) = line else {
+				continue;
+			};
+			let cost = estimate_tokens(line) + 1;
+			if tokens + cost > max_tokens {
+				continue;
+			}
+			tokens += cost;
+			if forward {
+				end += 1;
+			} else {
+				start -= 1;
+			}
+			grew = true;
+		}
+		if !grew {
+			return (start, end);
+		}
+	}
+}
+
+/// Lines `[start, end)` of a file, narrowed around `focus` when truncated.
+struct Span {
+	start: usize,
+	end: usize,
+	focus: usize,
+}
+
+/// Adds blocks to the bundle while keeping track of the budget.
+struct Assembler {
+	budget: usize,
+	used: usize,
+	blocks: Vec<ContextBlock>,
+	omitted: Vec<OmittedContext>,
+	/// The workspace root as given and canonicalized, since open files and
+	/// semantic hits may use either form.
+	roots: Vec<String>,
+}
+
+impl Assembler {
+	fn remaining(&self) -> usize {
+		self.budget.saturating_sub(self.used)
+	}
+
+	fn display_path(&self, path: &str) -> String {
+		self.roots
+			.iter()
+			.find_map(|root| Path::new(path).strip_prefix(root).ok())
+			.map(|relative| relative.to_string_lossy().to_string())
+			.unwrap_or_else(|| path.to_string())
+	}
+
+	fn omit(&mut self, source: ContextSource, path: Option<&str>, reason: &str) {
+		self.omitted.push(OmittedContext {
+			source,
+			path: path.map(|p| self.display_path(p)),
+			reason: reason.to_string(),
+		});
+	}
+
+	/// Adds `span` of a file, narrowed to `limit` tokens. Returns the range
+	/// that was included.
+	fn push_lines(
+		&mut self,
+		source: ContextSource,
+		path: &str,
+		lines: &[&str],
+		span: Span,
+		limit: usize,
+		score: Option<f32>,
+	) -> Option<(usize, usize)> {
+		let Span { start, end, focus } = span;
+		let range = &lines[start..end];
+		let (window_start, window_end) =
+			window(range, focus.saturating_sub(start), limit);
+		let tokens = range[window_start..window_end]
+			.iter()
+			.map(|l| estimate_tokens(l) + 1)
+			.sum::<usize>();
+		if window_end <= window_start || tokens < MIN_BLOCK_TOKENS.min(limit) {
+			self.omit(source, Some(path), "Doesn't fit in the token budget");
+			return None;
+		}
+
+		let (included_start, included_end) = (start + window_start, start + window_end);
+		self.used += tokens;
+		self.blocks.push(ContextBlock {
+			source,
+			path: self.display_path(path),
+			start_line: Some(included_start),
+			end_line: Some(included_end),
+			score,
+			content: range[window_start..window_end].join("\n"),
+			tokens,
+			truncated: included_start > start || included_end < end,
+		});
+		Some((included_start, included_end))
+	}
+
+	fn push_diff(&mut self, path: &str, diff: &str, limit: usize) {
+		let lines: Vec<&str> = diff.lines().collect();
+		let (_, end) = window(&lines, 0, limit);
+		let tokens = lines[..end]
+			.iter()
+			.map(|l| estimate_tokens(l) + 1)
+			.sum::<usize>();
+		if tokens < MIN_BLOCK_TOKENS.min(limit) {
+			self.omit(
+				ContextSource::GitDiff,
+				Some(path),
+				"Doesn't fit in the token budget",
+			);
+			return;
+		}
+		self.used += tokens;
+		self.blocks.push(ContextBlock {
+			source: ContextSource::GitDiff,
+			path: path.to_string(),
+			start_line: None,
+			end_line: None,
+			score: None,
+			content: lines[..end].join("\n"),
+			tokens,
+			truncated: end < lines.len(),
+		});
+	}
+
+	fn prompt(&self) -> String {
+		self.blocks
+			.iter()
+			.map(|block| {
+				let label = match (block.source, block.start_line, block.end_line) {
+					(ContextSource::GitDiff, _, _) => {
+						format!("Uncommitted changes to {}", block.path)
+					}
+					(_, Some(start), Some(end)) => {
+						format!("{} (lines {}-{})", block.path, start + 1, end)
+					}
+					_ => block.path.clone(),
+				};
+				let language = match block.source {
+					ContextSource::GitDiff => "diff",
+					_ => Path::new(&block.path)
+						.extension()
+						.and_then(|e| e.to_str())
+						.unwrap_or(""),
+				};
+				format!("### {}\n```{}\n{}\n```", label, language, block.content)
+			})
+			.collect::<Vec<_>>()
+			.join("\n\n")
+	}
+}
+
+/// Splits a unified diff into `(path, diff)` per file.
+fn split_diff(diff: &str) -> Vec<(String, String)> {
+	let mut files: Vec<(String, String)> = Vec::new();
+	for line in diff.lines() {
+		if let Some(header) = line.strip_prefix("diff --git ") {
+			let path = header
+				.rsplit_once(" b/")
+				.map(|(_, path)| path)
+				.unwrap_or(header);
+			files.push((path.to_string(), String::new()));
+		}
+		if let Some((_, content)) = files.last_mut() {
+			content.push_str(line);
+			content.push('\n');
+		}
+	}
+	files
+}
+
+/// Collapses overlapping hits in the same file into one range, keeping the
+/// best score. The result is ordered by score.
+fn merge_hits(hits: Vec<SemanticHit>) -> Vec<SemanticHit> {
+	let mut by_file: HashMap<String, Vec<SemanticHit>> = HashMap::new();
+	for hit in hits {
+		by_file.entry(hit.path.clone()).or_default().push(hit);
+	}
+
+	let mut merged = Vec::new();
+	for (_, mut hits) in by_file {
+		hits.sort_by_key(|h| h.start_line);
+		let mut current: Option<SemanticHit> = None;
+		for hit in hits {
+			match &mut current {
+				Some(range) if hit.start_line <= range.end_line => {
+					range.end_line = range.end_line.max(hit.end_line);
+					range.score = range.score.max(hit.score);
+				}
+				_ => merged.extend(current.replace(hit)),
+			}
+		}
+		merged.extend(current);
+	}
+	merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+	merged
+}
+
+/// Assembles chat context for `request.query`: open files first, then
+/// uncommitted changes, then semantic search hits, without exceeding the
+/// token budget.
+pub async fn build(
+	request: ContextRequest,
+	semantic_index: &SemanticIndexManager,
+) -> ContextBundle {
+	let mut assembler = Assembler {
+		budget: request.token_budget,
+		used: 0,
+		blocks: Vec::new(),
+		omitted: Vec::new(),
+		roots: request
+			.root
+			.iter()
+			.flat_map(|root| {
+				let canonical = fs::canonicalize(root).ok();
+				[
+					Some(root.clone()),
+					canonical.map(|p| p.to_string_lossy().to_string()),
+				]
+			})
+			.flatten()
+			.collect(),
+	};
+	// Ranges already included per canonical path, to skip duplicate
+	// semantic hits
+	let mut included: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+
+	let open_files_limit = (request.token_budget as f64 * OPEN_FILES_SHARE) as usize;
+	for file in &request.open_files {
+		let content = match &file.content {
+			Some(content) => content.clone(),
+			None => match fs::read_to_string(&file.path) {
+				Ok(content) => content,
+				Err(e) => {
+					assembler.omit(
+						ContextSource::OpenFile,
+						Some(&file.path),
+						&e.to_string(),
+					);
+					continue;
+				}
+			},
+		};
+		let lines: Vec<&str> = content.lines().collect();
+		let limit = open_files_limit.saturating_sub(assembler.used);
+		if lines.is_empty() || limit == 0 {
+			continue;
+		}
+		if let Some(range) = assembler.push_lines(
+			ContextSource::OpenFile,
+			&file.path,
+			&lines,
+			Span {
+				start: 0,
+				end: lines.len(),
+				focus: file.cursor_line.unwrap_or(0),
+			},
+			limit,
+			None,
+		) {
+			let path = fs::canonicalize(&file.path)
+				.map(|p| p.to_string_lossy().to_string())
+				.unwrap_or_else(|_| file.path.clone());
+			included.entry(path).or_default().push(range);
+		}
+	}
+
+	if let Some(session) = &request.git_session {
+		let directory = session.get_working_directory().to_string();
+		let diff_session = session.clone();
+		let diff = tauri::async_runtime::spawn_blocking(move || {
+			run_git(
+				&diff_session,
+				&directory,
+				&["diff", "--no-color", "--no-ext-diff", "HEAD"],
+			)
+		})
+		.await;
+		match diff {
+			Ok(Ok(diff)) => {
+				let diff_limit = (request.token_budget as f64
+					* (OPEN_FILES_SHARE + GIT_DIFF_SHARE)) as usize;
+				for (path, file_diff) in split_diff(&diff) {
+					let limit = diff_limit.saturating_sub(assembler.used);
+					if limit == 0 {
+						assembler.omit(
+							ContextSource::GitDiff,
+							Some(&path),
+							"Doesn't fit in the token budget",
+						);
+						continue;
+					}
+					assembler.push_diff(&path, &file_diff, limit);
+				}
+			}
+			Ok(Err(e)) => assembler.omit(ContextSource::GitDiff, None, &e.to_string()),
+			Err(e) => assembler.omit(ContextSource::GitDiff, None, &e.to_string()),
+		}
+	}
+
+	let semantic_results = request.semantic_results.unwrap_or(DEFAULT_SEMANTIC_RESULTS);
+	if semantic_results > 0 && assembler.remaining() > 0 {
+		match semantic_index
+			.search(
+				&request.query,
+				Some(semantic_results),
+				request.root.as_deref(),
+			)
+			.await
+		{
+			Ok(hits) => {
+				for hit in merge_hits(hits) {
+					let range = (hit.start_line as usize, hit.end_line as usize);
+					let duplicate = included.get(&hit.path).is_some_and(|ranges| {
+						ranges
+							.iter()
+							.any(|(start, end)| range.0 >= *start && range.1 <= *end)
+					});
+					if duplicate {
+						continue;
+					}
+					let limit = assembler.remaining();
+					if limit == 0 {
+						assembler.omit(
+							ContextSource::SemanticSearch,
+							Some(&hit.path),
+							"Doesn't fit in the token budget",
+						);
+						continue;
+					}
+					let Ok(content) = fs::read_to_string(&hit.path) else {
+						continue;
+					};
+					let lines: Vec<&str> = content.lines().collect();
+					let end = range.1.min(lines.len());
+					if range.0 >= end {
+						continue;
+					}
+					if let Some(range) = assembler.push_lines(
+						ContextSource::SemanticSearch,
+						&hit.path,
+						&lines,
+						Span {
+							start: range.0,
+							end,
+							focus: range.0,
+						},
+						limit,
+						Some(hit.score),
+					) {
+						included.entry(hit.path.clone()).or_default().push(range);
+					}
+				}
+			}
+			Err(e) => assembler.omit(ContextSource::SemanticSearch, None, &e.to_string()),
+		}
+	}
+
+	ContextBundle {
+		prompt: assembler.prompt(),
+		total_tokens: assembler.used,
+		token_budget: assembler.budget,
+		blocks: assembler.blocks,
+		omitted: assembler.omitted,
+	}
+}
+
+/// Gathers context for an AI chat message under a token budget, with the
+/// provenance of every block so the chat can show what was included.
+#[tauri::command]
+pub async fn build_chat_context(
+	request: ContextRequest,
+	semantic_index: State<'_, Arc<SemanticIndexManager>>,
+) -> Result<ContextBundle, String> {
+	Ok(build(request, &semantic_index).await)
+}
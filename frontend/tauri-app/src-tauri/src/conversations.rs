@@ -0,0 +1,545 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::{SecondsFormat, Utc};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use uuid::Uuid;
+
+use crate::ai_history::{self, HistoryMessage, NewAiExchange};
+use crate::backend_client;
+use crate::context_builder::estimate_tokens;
+use crate::diff_summary::SummaryModel;
+
+/// Used when the backend doesn't list the model's context length.
+const DEFAULT_CONTEXT_LIMIT: u32 = 16_000;
+/// Older turns are summarized once the next prompt would take more than this
+/// share of the context window.
+const COMPACT_THRESHOLD: f64 = 0.75;
+/// Tokens kept free for the answer when `max_tokens` isn't set.
+const DEFAULT_ANSWER_TOKENS: u32 = 1024;
+/// Most recent messages never summarized, so the model sees the latest
+/// exchanges word for word.
+const KEEP_RECENT_MESSAGES: usize = 4;
+const SUMMARY_MAX_TOKENS: u32 = 1024;
+
+const SUMMARY_PROMPT: &str = "You are compressing the earlier part of a conversation between a developer and an AI assistant so it can continue within a limited context window. \
+Write a concise summary that keeps every fact, decision, file name, code identifier and open question the assistant will need later. \
+If a previous summary is given, merge it with the new messages into one summary. Answer with the summary only.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApiMessage {
+	role: String,
+	content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct InferenceRequest<'a> {
+	provider: &'a str,
+	model: &'a str,
+	messages: &'a [ApiMessage],
+	api_key: Option<&'a str>,
+	key_id: Option<&'a str>,
+	org_id: Option<&'a str>,
+	max_tokens: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Usage {
+	input_tokens: Option<u32>,
+	output_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InferenceResponse {
+	content: String,
+	usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+	error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProvidersResponse {
+	providers: Vec<ProviderInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderInfo {
+	name: String,
+	models: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelInfo {
+	id: String,
+	context_length: Option<u32>,
+}
+
+/// Tokens a prompt message costs beyond its text, for its role and framing.
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+
+/// Rough size of a prompt message, for deciding when to summarize before
+/// the provider reports real counts.
+fn message_tokens(message: &ApiMessage) -> u32 {
+	estimate_tokens(&message.content) as u32 + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// How a conversation is created.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewConversation {
+	pub model: SummaryModel,
+	/// Cheaper model that summarizes older turns; the conversation's model
+	/// when unset.
+	pub summarizer: Option<SummaryModel>,
+	pub system_prompt: Option<String>,
+	/// Overrides the model's context length as listed by the backend.
+	pub context_limit: Option<u32>,
+	/// Largest answer asked for, kept free in the context window.
+	pub max_tokens: Option<u32>,
+}
+
+/// What the UI shows about a conversation, emitted as
+/// `conversation-state-{id}` whenever it changes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationState {
+	pub id: String,
+	pub provider: String,
+	pub model: String,
+	pub context_limit: u32,
+	/// Estimated size of the next prompt: system prompt, summary and the
+	/// messages kept verbatim.
+	pub context_tokens: u32,
+	/// Totals over every request, as reported by the provider when it does.
+	pub input_tokens: u64,
+	pub output_tokens: u64,
+	/// Messages in the conversation, summarized or not.
+	pub message_count: usize,
+	/// How many of the oldest messages are only sent as the summary.
+	pub summarized_messages: usize,
+	pub summary: Option<String>,
+	/// How many times older turns were summarized.
+	pub compactions: u32,
+	pub last_compacted_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationReply {
+	pub answer: String,
+	pub state: ConversationState,
+}
+
+struct Conversation {
+	id: String,
+	model: SummaryModel,
+	summarizer: SummaryModel,
+	system_prompt: Option<String>,
+	context_limit: u32,
+	max_tokens: Option<u32>,
+	messages: Vec<ApiMessage>,
+	/// Messages before this index are covered by `summary`.
+	summarized: usize,
+	summary: Option<String>,
+	input_tokens: u64,
+	output_tokens: u64,
+	compactions: u32,
+	last_compacted_at: Option<String>,
+}
+
+impl Conversation {
+	/// What is sent to the model: the system prompt, the summary of older
+	/// turns and the messages after it.
+	fn prompt(&self) -> Vec<ApiMessage> {
+		let mut prompt = Vec::new();
+		if let Some(system) = &self.system_prompt {
+			prompt.push(ApiMessage {
+				role: "system".to_string(),
+				content: system.clone(),
+			});
+		}
+		if let Some(summary) = &self.summary {
+			prompt.push(ApiMessage {
+				role: "system".to_string(),
+				content: format!("Summary of the conversation so far:\n{}", summary),
+			});
+		}
+		prompt.extend(self.messages[self.summarized..].iter().cloned());
+		prompt
+	}
+
+	fn context_tokens(&self) -> u32 {
+		self.prompt().iter().map(message_tokens).sum()
+	}
+
+	/// Whether the next prompt leaves too little room for the answer.
+	fn needs_compaction(&self) -> bool {
+		let answer = self.max_tokens.unwrap_or(DEFAULT_ANSWER_TOKENS);
+		let budget = (self.context_limit as f64 * COMPACT_THRESHOLD) as u32;
+		self.context_tokens() + answer > budget
+			&& self.messages.len() > self.summarized + KEEP_RECENT_MESSAGES
+	}
+
+	fn state(&self) -> ConversationState {
+		ConversationState {
+			id: self.id.clone(),
+			provider: self.model.provider.clone(),
+			model: self.model.model.clone(),
+			context_limit: self.context_limit,
+			context_tokens: self.context_tokens(),
+			input_tokens: self.input_tokens,
+			output_tokens: self.output_tokens,
+			message_count: self.messages.len(),
+			summarized_messages: self.summarized,
+			summary: self.summary.clone(),
+			compactions: self.compactions,
+			last_compacted_at: self.last_compacted_at.clone(),
+		}
+	}
+
+	fn add_usage(&mut self, usage: Option<Usage>, prompt: &[ApiMessage], answer: &str) {
+		let usage = usage.unwrap_or_default();
+		self.input_tokens += usage
+			.input_tokens
+			.unwrap_or_else(|| prompt.iter().map(message_tokens).sum())
+			as u64;
+		self.output_tokens += usage
+			.output_tokens
+			.unwrap_or_else(|| estimate_tokens(answer) as u32)
+			as u64;
+	}
+}
+
+async fn complete(
+	app_handle: &AppHandle,
+	model: &SummaryModel,
+	messages: &[ApiMessage],
+	max_tokens: Option<u32>,
+) -> Result<InferenceResponse> {
+	let body = InferenceRequest {
+		provider: &model.provider,
+		model: &model.model,
+		messages,
+		api_key: model.api_key.as_deref(),
+		key_id: model.key_id.as_deref(),
+		org_id: model.org_id.as_deref(),
+		max_tokens,
+	};
+	let response =
+		backend_client::optional_auth_request(app_handle, Method::POST, "/api/inference")
+			.json(&body)
+			.send()
+			.await?;
+
+	let status = response.status();
+	if !status.is_success() {
+		return Err(match response.json::<ErrorResponse>().await {
+			Ok(body) => anyhow!("Inference failed ({}): {}", status, body.error),
+			Err(_) => anyhow!("Inference failed ({})", status),
+		});
+	}
+	Ok(response.json().await?)
+}
+
+/// Conversations of this session, whose older turns are summarized with a
+/// cheaper model when they approach the context window.
+pub struct ConversationManager {
+	app_handle: AppHandle,
+	conversations: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Conversation>>>>,
+	/// Context lengths from `/api/providers`, by provider and model.
+	context_limits: Mutex<Option<HashMap<(String, String), u32>>>,
+}
+
+impl ConversationManager {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		Arc::new(Self {
+			app_handle,
+			conversations: Mutex::new(HashMap::new()),
+			context_limits: Mutex::new(None),
+		})
+	}
+
+	fn get(&self, id: &str) -> Result<Arc<tokio::sync::Mutex<Conversation>>> {
+		self.conversations
+			.lock()
+			.unwrap()
+			.get(id)
+			.cloned()
+			.ok_or_else(|| anyhow!("Conversation {} not found", id))
+	}
+
+	/// The model's context length as listed by the backend, fetched once.
+	async fn context_limit(&self, model: &SummaryModel) -> u32 {
+		let cached = self.context_limits.lock().unwrap().clone();
+		let limits = match cached {
+			Some(limits) => limits,
+			None => match self.fetch_context_limits().await {
+				Ok(limits) => {
+					*self.context_limits.lock().unwrap() = Some(limits.clone());
+					limits
+				}
+				Err(e) => {
+					log::warn!("Failed to fetch model context lengths: {}", e);
+					HashMap::new()
+				}
+			},
+		};
+		limits
+			.get(&(model.provider.clone(), model.model.clone()))
+			.copied()
+			.unwrap_or(DEFAULT_CONTEXT_LIMIT)
+	}
+
+	async fn fetch_context_limits(&self) -> Result<HashMap<(String, String), u32>> {
+		let response = backend_client::optional_auth_request(
+			&self.app_handle,
+			Method::GET,
+			"/api/providers",
+		)
+		.send()
+		.await?
+		.error_for_status()?
+		.json::<ProvidersResponse>()
+		.await?;
+		Ok(response
+			.providers
+			.into_iter()
+			.flat_map(|provider| {
+				let name = provider.name;
+				provider.models.into_iter().filter_map(move |model| {
+					Some(((name.clone(), model.id), model.context_length?))
+				})
+			})
+			.collect())
+	}
+
+	fn emit_state(&self, state: &ConversationState) {
+		let _ = self
+			.app_handle
+			.emit(&format!("conversation-state-{}", state.id), state);
+	}
+
+	pub async fn create(&self, request: NewConversation) -> ConversationState {
+		let context_limit = match request.context_limit {
+			Some(limit) => limit,
+			None => self.context_limit(&request.model).await,
+		};
+		let conversation = Conversation {
+			id: Uuid::new_v4().to_string(),
+			summarizer: request.summarizer.unwrap_or_else(|| request.model.clone()),
+			model: request.model,
+			system_prompt: request.system_prompt.filter(|s| !s.trim().is_empty()),
+			context_limit,
+			max_tokens: request.max_tokens,
+			messages: Vec::new(),
+			summarized: 0,
+			summary: None,
+			input_tokens: 0,
+			output_tokens: 0,
+			compactions: 0,
+			last_compacted_at: None,
+		};
+		let state = conversation.state();
+		self.conversations.lock().unwrap().insert(
+			conversation.id.clone(),
+			Arc::new(tokio::sync::Mutex::new(conversation)),
+		);
+		state
+	}
+
+	/// Summarizes the messages before the most recent ones into the
+	/// conversation's summary, merged with the previous summary.
+	async fn compact_locked(&self, conversation: &mut Conversation) -> Result<()> {
+		let end = conversation
+			.messages
+			.len()
+			.saturating_sub(KEEP_RECENT_MESSAGES);
+		if end <= conversation.summarized {
+			return Ok(());
+		}
+
+		let mut transcript = String::new();
+		if let Some(summary) = &conversation.summary {
+			transcript.push_str(&format!("Previous summary:\n{}\n\n", summary));
+		}
+		transcript.push_str("New messages:\n");
+		for message in &conversation.messages[conversation.summarized..end] {
+			transcript.push_str(&format!("\n{}: {}\n", message.role, message.content));
+		}
+		let prompt = [
+			ApiMessage {
+				role: "system".to_string(),
+				content: SUMMARY_PROMPT.to_string(),
+			},
+			ApiMessage {
+				role: "user".to_string(),
+				content: transcript,
+			},
+		];
+
+		let summarizer = conversation.summarizer.clone();
+		let response = complete(
+			&self.app_handle,
+			&summarizer,
+			&prompt,
+			Some(SUMMARY_MAX_TOKENS),
+		)
+		.await?;
+		let summary = response.content.trim().to_string();
+		if summary.is_empty() {
+			return Err(anyhow!("The summarizer returned an empty summary"));
+		}
+		conversation.add_usage(response.usage, &prompt, &summary);
+		conversation.summary = Some(summary);
+		conversation.summarized = end;
+		conversation.compactions += 1;
+		conversation.last_compacted_at =
+			Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+		self.emit_state(&conversation.state());
+		Ok(())
+	}
+
+	/// Adds the user's message and returns the model's answer, summarizing
+	/// older turns first when the prompt would be too large.
+	pub async fn send(&self, id: &str, content: String) -> Result<ConversationReply> {
+		let conversation = self.get(id)?;
+		let mut conversation = conversation.lock().await;
+
+		conversation.messages.push(ApiMessage {
+			role: "user".to_string(),
+			content,
+		});
+		if conversation.needs_compaction() {
+			// The full prompt may still fit, so a failed summary isn't fatal
+			if let Err(e) = self.compact_locked(&mut conversation).await {
+				log::warn!("Failed to summarize conversation {}: {}", id, e);
+			}
+		}
+
+		let prompt = conversation.prompt();
+		let model = conversation.model.clone();
+		let response =
+			match complete(&self.app_handle, &model, &prompt, conversation.max_tokens)
+				.await
+			{
+				Ok(response) => response,
+				Err(e) => {
+					// Leave the conversation as it was, so the message can be resent
+					conversation.messages.pop();
+					return Err(e);
+				}
+			};
+
+		let answer = response.content;
+		conversation.add_usage(response.usage, &prompt, &answer);
+		conversation.messages.push(ApiMessage {
+			role: "assistant".to_string(),
+			content: answer.clone(),
+		});
+		ai_history::record(
+			&self.app_handle,
+			NewAiExchange {
+				source: "chat".to_string(),
+				provider: model.provider,
+				model: model.model,
+				messages: prompt
+					.into_iter()
+					.map(|m| HistoryMessage {
+						role: m.role,
+						content: m.content,
+					})
+					.collect(),
+				response: answer.clone(),
+			},
+		);
+
+		let state = conversation.state();
+		self.emit_state(&state);
+		Ok(ConversationReply { answer, state })
+	}
+
+	/// Summarizes older turns now, whatever the prompt size.
+	pub async fn compact(&self, id: &str) -> Result<ConversationState> {
+		let conversation = self.get(id)?;
+		let mut conversation = conversation.lock().await;
+		self.compact_locked(&mut conversation).await?;
+		Ok(conversation.state())
+	}
+
+	pub async fn state(&self, id: &str) -> Result<ConversationState> {
+		Ok(self.get(id)?.lock().await.state())
+	}
+
+	pub async fn list(&self) -> Vec<ConversationState> {
+		let conversations: Vec<_> = self
+			.conversations
+			.lock()
+			.unwrap()
+			.values()
+			.cloned()
+			.collect();
+		let mut states = Vec::with_capacity(conversations.len());
+		for conversation in conversations {
+			states.push(conversation.lock().await.state());
+		}
+		states
+	}
+
+	pub fn delete(&self, id: &str) -> bool {
+		self.conversations.lock().unwrap().remove(id).is_some()
+	}
+}
+
+#[tauri::command]
+pub async fn create_conversation(
+	conversation: NewConversation,
+	manager: State<'_, Arc<ConversationManager>>,
+) -> Result<ConversationState, String> {
+	Ok(manager.create(conversation).await)
+}
+
+#[tauri::command]
+pub async fn send_conversation_message(
+	id: String,
+	content: String,
+	manager: State<'_, Arc<ConversationManager>>,
+) -> Result<ConversationReply, String> {
+	manager.send(&id, content).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn compact_conversation(
+	id: String,
+	manager: State<'_, Arc<ConversationManager>>,
+) -> Result<ConversationState, String> {
+	manager.compact(&id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_conversation_state(
+	id: String,
+	manager: State<'_, Arc<ConversationManager>>,
+) -> Result<ConversationState, String> {
+	manager.state(&id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_conversations(
+	manager: State<'_, Arc<ConversationManager>>,
+) -> Result<Vec<ConversationState>, String> {
+	Ok(manager.list().await)
+}
+
+#[tauri::command]
+pub async fn delete_conversation(
+	id: String,
+	manager: State<'_, Arc<ConversationManager>>,
+) -> Result<bool, String> {
+	Ok(manager.delete(&id))
+}
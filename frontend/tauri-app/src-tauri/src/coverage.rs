@@ -0,0 +1,495 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::canvas_manager::CanvasManager;
+use crate::jobs::{self, JobHandle, JobKind, JobManager};
+use crate::os::OsSession;
+use crate::trust;
+use crate::util::now_millis;
+
+/// Where `run_coverage` has the tools write their report, relative to the
+/// project root.
+const REPORT_DIR: &str = ".ariana/coverage";
+const REPORT_FILE: &str = "lcov.info";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoverageTool {
+	CargoLlvmCov,
+	C8,
+	CoveragePy,
+}
+
+impl CoverageTool {
+	/// The tool for the project at `root`, from its manifest files.
+	fn detect(root: &Path) -> Option<Self> {
+		if root.join("Cargo.toml").exists() {
+			Some(Self::CargoLlvmCov)
+		} else if root.join("package.json").exists() {
+			Some(Self::C8)
+		} else if [
+			"pyproject.toml",
+			"setup.py",
+			"requirements.txt",
+			"pytest.ini",
+		]
+		.iter()
+		.any(|file| root.join(file).exists())
+		{
+			Some(Self::CoveragePy)
+		} else {
+			None
+		}
+	}
+
+	/// Shell command running the project's tests and writing an LCOV report
+	/// to `REPORT_DIR`/`REPORT_FILE`.
+	fn command(self, session: &OsSession) -> String {
+		let report = format!("{}/{}", REPORT_DIR, REPORT_FILE);
+		match self {
+			Self::CargoLlvmCov => {
+				format!("cargo llvm-cov --lcov --output-path {}", report)
+			}
+			Self::C8 => format!(
+				"npx --yes c8 --reporter=lcovonly --report-dir {} npm test",
+				REPORT_DIR
+			),
+			Self::CoveragePy => {
+				let python = match session {
+					#[cfg(target_os = "windows")]
+					OsSession::Local(_) => "python",
+					_ => "python3",
+				};
+				// The report is written even when tests fail
+				format!(
+					"{python} -m coverage run -m pytest; {python} -m coverage lcov -o {}",
+					report
+				)
+			}
+		}
+	}
+
+	fn label(self) -> &'static str {
+		match self {
+			Self::CargoLlvmCov => "cargo-llvm-cov",
+			Self::C8 => "c8",
+			Self::CoveragePy => "coverage.py",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineHits {
+	/// One-based, as in the reports.
+	pub line: u32,
+	pub hits: u64,
+}
+
+/// Hit counts of the executable lines of one file, for the editor gutter.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileCoverage {
+	/// Relative to the project root with `/` separators, or absolute for
+	/// files outside it.
+	pub path: String,
+	pub lines: Vec<LineHits>,
+	pub lines_found: u64,
+	pub lines_hit: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageSummary {
+	/// The tool or report file the results came from.
+	pub source: String,
+	pub files: usize,
+	pub lines_found: u64,
+	pub lines_hit: u64,
+	/// `lines_hit` over `lines_found`, 0 to 100.
+	pub percent: f64,
+	/// Milliseconds since the Unix epoch.
+	pub generated_at: u64,
+}
+
+/// Coverage of one canvas's branch, `None` until it has been measured.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanvasCoverage {
+	pub canvas_id: String,
+	pub name: String,
+	pub branch: String,
+	pub summary: Option<CoverageSummary>,
+}
+
+/// Payload of the `coverage-updated` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageUpdated {
+	pub directory: String,
+	pub summary: CoverageSummary,
+}
+
+struct CoverageReport {
+	summary: CoverageSummary,
+	files: HashMap<String, FileCoverage>,
+}
+
+/// The latest coverage of each project or canvas copy, by working
+/// directory.
+#[derive(Default)]
+pub struct CoverageStore {
+	reports: Mutex<HashMap<String, Arc<CoverageReport>>>,
+}
+
+impl CoverageStore {
+	pub fn new() -> Arc<Self> {
+		Arc::new(Self::default())
+	}
+
+	fn get(&self, session: &OsSession) -> Option<Arc<CoverageReport>> {
+		let reports = self.reports.lock().unwrap();
+		reports.get(session.get_working_directory()).cloned()
+	}
+
+	fn insert(&self, session: &OsSession, report: CoverageReport) {
+		self.reports.lock().unwrap().insert(
+			session.get_working_directory().to_string(),
+			Arc::new(report),
+		);
+	}
+}
+
+/// Hits of each line of each file, as the report names them.
+type Hits = BTreeMap<String, BTreeMap<u32, u64>>;
+
+/// Reads an LCOV tracefile. Only line records are used; hits of a file
+/// listed more than once, e.g. per test, are added up.
+fn parse_lcov(content: &str) -> Result<Hits> {
+	let mut hits = Hits::new();
+	let mut file: Option<String> = None;
+	for (index, line) in content.lines().enumerate() {
+		let line = line.trim();
+		if let Some(path) = line.strip_prefix("SF:") {
+			file = Some(path.to_string());
+		} else if let Some(record) = line.strip_prefix("DA:") {
+			let file = file
+				.as_ref()
+				.ok_or_else(|| anyhow!("Line {}: DA before SF", index + 1))?;
+			let mut fields = record.split(',');
+			let (Some(number), Some(count)) = (fields.next(), fields.next()) else {
+				return Err(anyhow!("Line {}: malformed DA record", index + 1));
+			};
+			let number: u32 = number
+				.parse()
+				.with_context(|| format!("Line {}: bad line number", index + 1))?;
+			// Some tools write fractional or negative counts on overflow
+			let count = count.parse::<f64>().unwrap_or(0.0).max(0.0) as u64;
+			*hits
+				.entry(file.clone())
+				.or_default()
+				.entry(number)
+				.or_default() += count;
+		} else if line == "end_of_record" {
+			file = None;
+		}
+	}
+	Ok(hits)
+}
+
+/// Reads a Cobertura XML report, as written by coverage.py, gcovr or
+/// Istanbul. Relative file names are joined to the first `<source>`.
+fn parse_cobertura(content: &str) -> Result<Hits> {
+	let document = roxmltree::Document::parse(content)?;
+	let root = document.root_element();
+	if root.tag_name().name() != "coverage" {
+		return Err(anyhow!("Not a Cobertura report"));
+	}
+	let source = root
+		.descendants()
+		.find(|node| node.has_tag_name("source"))
+		.and_then(|node| node.text())
+		.map(|text| text.trim().to_string());
+
+	let mut hits = Hits::new();
+	for class in root.descendants().filter(|node| node.has_tag_name("class")) {
+		let Some(filename) = class.attribute("filename") else {
+			continue;
+		};
+		let file = match &source {
+			Some(source) if !is_absolute(filename) => {
+				format!("{}/{}", source.trim_end_matches(['/', '\\']), filename)
+			}
+			_ => filename.to_string(),
+		};
+		let file_hits = hits.entry(file).or_default();
+		// Methods repeat their lines; only the class's own list is read
+		let lines = class
+			.children()
+			.filter(|node| node.has_tag_name("lines"))
+			.flat_map(|lines| lines.children())
+			.filter(|node| node.has_tag_name("line"));
+		for line in lines {
+			let number = line.attribute("number").and_then(|n| n.parse().ok());
+			let count = line.attribute("hits").and_then(|n| n.parse::<u64>().ok());
+			if let (Some(number), Some(count)) = (number, count) {
+				*file_hits.entry(number).or_insert(0) += count;
+			}
+		}
+	}
+	Ok(hits)
+}
+
+fn is_absolute(path: &str) -> bool {
+	path.starts_with('/') || path.starts_with('\\') || Path::new(path).is_absolute()
+}
+
+/// `path` relative to the project at `roots`, which may name the same
+/// directory in several ways, e.g. as seen from WSL and from Windows.
+fn relative_path(path: &str, roots: &[String]) -> String {
+	let path = path.replace('\\', "/");
+	for root in roots {
+		let root = root.replace('\\', "/");
+		let root = root.trim_end_matches('/');
+		if let Some(rest) = path.strip_prefix(root) {
+			if let Some(rest) = rest.strip_prefix('/') {
+				return rest.to_string();
+			}
+		}
+	}
+	path.trim_start_matches("./").to_string()
+}
+
+fn roots(session: &OsSession) -> Vec<String> {
+	vec![
+		session.get_working_directory().to_string(),
+		session.host_path().to_string_lossy().to_string(),
+	]
+}
+
+/// Turns a parsed report into per-file coverage keyed by project-relative
+/// path.
+fn build_report(session: &OsSession, hits: Hits, source: String) -> CoverageReport {
+	let roots = roots(session);
+	let mut files: HashMap<String, FileCoverage> = HashMap::new();
+	for (file, line_hits) in hits {
+		let path = relative_path(&file, &roots);
+		let coverage = files.entry(path.clone()).or_insert_with(|| FileCoverage {
+			path,
+			lines: Vec::new(),
+			lines_found: 0,
+			lines_hit: 0,
+		});
+		coverage.lines.extend(
+			line_hits
+				.into_iter()
+				.map(|(line, hits)| LineHits { line, hits }),
+		);
+	}
+	for coverage in files.values_mut() {
+		// Two report paths may name the same file
+		coverage.lines.sort_by_key(|line| line.line);
+		coverage.lines.dedup_by(|line, kept| {
+			let same = line.line == kept.line;
+			if same {
+				kept.hits += line.hits;
+			}
+			same
+		});
+		coverage.lines_found = coverage.lines.len() as u64;
+		coverage.lines_hit =
+			coverage.lines.iter().filter(|line| line.hits > 0).count() as u64;
+	}
+
+	let lines_found = files.values().map(|file| file.lines_found).sum::<u64>();
+	let lines_hit = files.values().map(|file| file.lines_hit).sum::<u64>();
+	CoverageReport {
+		summary: CoverageSummary {
+			source,
+			files: files.len(),
+			lines_found,
+			lines_hit,
+			percent: if lines_found == 0 {
+				0.0
+			} else {
+				lines_hit as f64 * 100.0 / lines_found as f64
+			},
+			generated_at: now_millis(),
+		},
+		files,
+	}
+}
+
+/// Reads an LCOV or Cobertura report, telling them apart by content.
+fn read_report(
+	session: &OsSession,
+	host_path: &Path,
+	source: String,
+) -> Result<CoverageReport> {
+	let content = fs::read_to_string(host_path)
+		.with_context(|| format!("Failed to read {}", host_path.display()))?;
+	let hits = if content.trim_start().starts_with('<') {
+		parse_cobertura(&content)?
+	} else {
+		parse_lcov(&content)?
+	};
+	if hits.is_empty() {
+		return Err(anyhow!("{} has no line coverage", host_path.display()));
+	}
+	Ok(build_report(session, hits, source))
+}
+
+fn store_report(
+	app_handle: &AppHandle,
+	store: &CoverageStore,
+	session: &OsSession,
+	report: CoverageReport,
+) -> CoverageSummary {
+	let summary = report.summary.clone();
+	store.insert(session, report);
+	let _ = app_handle.emit(
+		"coverage-updated",
+		CoverageUpdated {
+			directory: session.get_working_directory().to_string(),
+			summary: summary.clone(),
+		},
+	);
+	summary
+}
+
+/// Runs `tool` in the project and reads the LCOV report it writes.
+fn measure(
+	session: &OsSession,
+	tool: CoverageTool,
+	job: &JobHandle,
+) -> Result<CoverageReport> {
+	let report_dir = session.host_path().join(REPORT_DIR);
+	let report_path: PathBuf = report_dir.join(REPORT_FILE);
+	fs::create_dir_all(&report_dir)?;
+	// A stale report would hide a run that wrote nothing
+	let _ = fs::remove_file(&report_path);
+
+	let command = tool.command(session);
+	job.message(command.clone());
+	let mut cmd = session.build_shell_command(&command)?;
+	let mut last_error = None;
+	let status = jobs::stream_lines(&mut cmd, job.token(), |stream, line| {
+		if stream == "stderr" && !line.trim().is_empty() {
+			last_error = Some(line.clone());
+		}
+		job.message(line);
+	})?;
+	// Failing tests still leave a report worth showing
+	if !report_path.exists() {
+		return Err(anyhow!(
+			"{} wrote no report (exit code {}){}",
+			tool.label(),
+			status
+				.code()
+				.map_or("unknown".to_string(), |code| code.to_string()),
+			last_error.map_or(String::new(), |line| format!(": {}", line))
+		));
+	}
+	read_report(session, &report_path, tool.label().to_string())
+}
+
+/// Runs the project's tests under cargo-llvm-cov, c8 or coverage.py, the
+/// one matching the project when `tool` is missing, as a job and returns
+/// its id. The results arrive as a `coverage-updated` event.
+#[tauri::command]
+pub async fn run_coverage(
+	os_session: OsSession,
+	tool: Option<CoverageTool>,
+	job_manager: State<'_, Arc<JobManager>>,
+	store: State<'_, Arc<CoverageStore>>,
+	app_handle: AppHandle,
+) -> Result<String, String> {
+	trust::ensure_trusted(&app_handle, &os_session, os_session.get_working_directory())?;
+	let tool = match tool {
+		Some(tool) => tool,
+		None => CoverageTool::detect(&os_session.host_path())
+			.ok_or("No Rust, Node or Python project to measure coverage of")?,
+	};
+	let store = store.inner().clone();
+	let title = format!(
+		"Coverage of {} ({})",
+		os_session.get_working_directory(),
+		tool.label()
+	);
+	Ok(job_manager.spawn(JobKind::Coverage, title, move |job| {
+		let report = measure(&os_session, tool, job)?;
+		Ok(store_report(&app_handle, &store, &os_session, report))
+	}))
+}
+
+/// Reads an LCOV or Cobertura report produced outside the app. `path` is
+/// inside the session, relative to its working directory or absolute.
+#[tauri::command]
+pub async fn import_coverage(
+	os_session: OsSession,
+	path: String,
+	store: State<'_, Arc<CoverageStore>>,
+	app_handle: AppHandle,
+) -> Result<CoverageSummary, String> {
+	let store = store.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		let host_path = os_session.host_path_for(&os_session.resolve_path(&path));
+		let report = read_report(&os_session, &host_path, path)?;
+		Ok::<_, anyhow::Error>(store_report(&app_handle, &store, &os_session, report))
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Line hits of `path`, relative to the project or absolute, from the
+/// latest coverage of the session. `None` when the file wasn't measured.
+#[tauri::command]
+pub async fn get_file_coverage(
+	os_session: OsSession,
+	path: String,
+	store: State<'_, Arc<CoverageStore>>,
+) -> Result<Option<FileCoverage>, String> {
+	let Some(report) = store.get(&os_session) else {
+		return Ok(None);
+	};
+	let path = relative_path(&path, &roots(&os_session));
+	Ok(report.files.get(&path).cloned())
+}
+
+/// Totals of the latest coverage of the session.
+#[tauri::command]
+pub async fn get_coverage_summary(
+	os_session: OsSession,
+	store: State<'_, Arc<CoverageStore>>,
+) -> Result<Option<CoverageSummary>, String> {
+	Ok(store.get(&os_session).map(|report| report.summary.clone()))
+}
+
+/// Totals of the latest coverage of each canvas of the project at
+/// `project_root`, to compare branches.
+#[tauri::command]
+pub async fn get_canvas_coverage(
+	project_root: OsSession,
+	manager: State<'_, Arc<CanvasManager>>,
+	store: State<'_, Arc<CoverageStore>>,
+) -> Result<Vec<CanvasCoverage>, String> {
+	let canvases = manager.list(&project_root).map_err(|e| e.to_string())?;
+	Ok(canvases
+		.into_iter()
+		.map(|canvas| CanvasCoverage {
+			summary: store
+				.get(&canvas.os_session)
+				.map(|report| report.summary.clone()),
+			canvas_id: canvas.id,
+			name: canvas.name,
+			branch: canvas.branch,
+		})
+		.collect())
+}
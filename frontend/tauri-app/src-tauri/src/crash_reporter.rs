@@ -0,0 +1,438 @@
+use std::backtrace::Backtrace;
+use std::fs::{self, File};
+use std::io::Write;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{SecondsFormat, Utc};
+use crash_handler::{make_crash_event, CrashContext, CrashEventResult, CrashHandler};
+use minidumper::{Client, LoopAction, MinidumpBinary, ServerHandler, SocketName};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+use crate::backend_client::{self, BackendClient};
+use crate::logging;
+
+/// Starts the process as a crash monitor rather than the app:
+/// `--crash-monitor <socket> <crashes dir> <app version>`.
+const MONITOR_ARG: &str = "--crash-monitor";
+/// Next to the log files.
+const CRASHES_DIR: &str = "crashes";
+/// Store remembering whether the user agreed to send crash reports.
+const CRASH_REPORTS_STORE: &str = "crash-reports.json";
+/// Older reports are deleted.
+const MAX_REPORTS: usize = 20;
+/// Log lines included in a report.
+const LOG_LINES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrashKind {
+	/// A Rust panic, caught by the panic hook.
+	Panic,
+	/// A signal or exception, written as a minidump by the monitor process.
+	Native,
+}
+
+/// A crash, stored as `<id>.json` in the crashes directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+	pub id: String,
+	pub kind: CrashKind,
+	/// RFC 3339, UTC.
+	pub occurred_at: String,
+	pub app_version: String,
+	pub os: String,
+	pub arch: String,
+	pub message: Option<String>,
+	pub location: Option<String>,
+	pub thread: Option<String>,
+	pub backtrace: Option<String>,
+	pub logs: Vec<String>,
+	/// File name of the minidump next to the report, for native crashes.
+	pub minidump: Option<String>,
+	#[serde(default)]
+	pub uploaded: bool,
+}
+
+impl CrashReport {
+	fn new(kind: CrashKind, app_version: &str) -> Self {
+		Self {
+			id: Uuid::new_v4().to_string(),
+			kind,
+			occurred_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+			app_version: app_version.to_string(),
+			os: std::env::consts::OS.to_string(),
+			arch: std::env::consts::ARCH.to_string(),
+			message: None,
+			location: None,
+			thread: None,
+			backtrace: None,
+			logs: Vec::new(),
+			minidump: None,
+			uploaded: false,
+		}
+	}
+
+	fn save(&self, dir: &Path) -> Result<()> {
+		fs::write(
+			dir.join(format!("{}.json", self.id)),
+			serde_json::to_vec_pretty(self)?,
+		)?;
+		prune_reports(dir)
+	}
+}
+
+/// The report as the backend's `/api/crash-report` expects it.
+#[derive(Serialize)]
+struct CrashReportUpload<'a> {
+	report_id: &'a str,
+	kind: CrashKind,
+	occurred_at: &'a str,
+	app_version: &'a str,
+	os: &'a str,
+	arch: &'a str,
+	message: &'a Option<String>,
+	location: &'a Option<String>,
+	thread: &'a Option<String>,
+	backtrace: &'a Option<String>,
+	logs: &'a [String],
+	minidump: Option<String>,
+}
+
+fn crashes_dir(app_handle: &AppHandle) -> Result<PathBuf> {
+	Ok(app_handle.path().app_log_dir()?.join(CRASHES_DIR))
+}
+
+/// Reports in `dir`, newest first.
+fn read_reports(dir: &Path) -> Result<Vec<CrashReport>> {
+	let mut reports: Vec<CrashReport> = fs::read_dir(dir)?
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+		.filter_map(|entry| fs::read(entry.path()).ok())
+		.filter_map(|content| serde_json::from_slice(&content).ok())
+		.collect();
+	reports.sort_by(|a, b| b.occurred_at.cmp(&a.occurred_at));
+	Ok(reports)
+}
+
+fn prune_reports(dir: &Path) -> Result<()> {
+	for report in read_reports(dir)?.into_iter().skip(MAX_REPORTS) {
+		delete_report(dir, &report)?;
+	}
+	Ok(())
+}
+
+fn delete_report(dir: &Path, report: &CrashReport) -> Result<()> {
+	if let Some(minidump) = &report.minidump {
+		let _ = fs::remove_file(dir.join(minidump));
+	}
+	fs::remove_file(dir.join(format!("{}.json", report.id)))?;
+	Ok(())
+}
+
+/// Where panic reports go; set once the app knows its log directory.
+struct PanicContext {
+	dir: PathBuf,
+	app_version: String,
+}
+
+static PANIC_CONTEXT: OnceLock<PanicContext> = OnceLock::new();
+/// Kept for the life of the process; dropping it detaches the handler.
+static CRASH_HANDLER: OnceLock<CrashHandler> = OnceLock::new();
+
+fn write_panic_report(info: &PanicHookInfo) {
+	let Some(context) = PANIC_CONTEXT.get() else {
+		return;
+	};
+	let payload = info.payload();
+	let message = payload
+		.downcast_ref::<&str>()
+		.map(|s| s.to_string())
+		.or_else(|| payload.downcast_ref::<String>().cloned());
+
+	let report = CrashReport {
+		message,
+		location: info.location().map(|l| l.to_string()),
+		thread: thread::current().name().map(str::to_string),
+		backtrace: Some(Backtrace::force_capture().to_string()),
+		logs: logging::try_recent_lines(LOG_LINES),
+		..CrashReport::new(CrashKind::Panic, &context.app_version)
+	};
+	// Nothing sensible to do if this fails while panicking
+	let _ = report.save(&context.dir);
+}
+
+/// Runs in the monitor process, writing a minidump and report when the app
+/// crashes.
+struct MonitorHandler {
+	dir: PathBuf,
+	app_version: String,
+	report: Mutex<Option<CrashReport>>,
+}
+
+impl ServerHandler for MonitorHandler {
+	fn create_minidump_file(&self) -> Result<(File, PathBuf), std::io::Error> {
+		let report = CrashReport::new(CrashKind::Native, &self.app_version);
+		let path = self.dir.join(format!("{}.dmp", report.id));
+		let file = File::create(&path)?;
+		*self.report.lock().unwrap() = Some(report);
+		Ok((file, path))
+	}
+
+	fn on_minidump_created(
+		&self,
+		result: Result<MinidumpBinary, minidumper::Error>,
+	) -> LoopAction {
+		let report = self.report.lock().unwrap().take();
+		match (result, report) {
+			(Ok(mut minidump), Some(mut report)) => {
+				let _ = minidump.file.flush();
+				report.minidump = minidump
+					.path
+					.file_name()
+					.map(|name| name.to_string_lossy().to_string());
+				// The app's ring buffer died with it; use the log file instead
+				report.logs = self
+					.dir
+					.parent()
+					.and_then(|dir| fs::read_to_string(dir.join(logging::LOG_FILE)).ok())
+					.map(|content| {
+						let lines: Vec<_> = content.lines().map(str::to_string).collect();
+						lines[lines.len().saturating_sub(LOG_LINES)..].to_vec()
+					})
+					.unwrap_or_default();
+				if let Err(e) = report.save(&self.dir) {
+					eprintln!("Failed to save crash report: {}", e);
+				}
+			}
+			(Err(e), _) => eprintln!("Failed to write minidump: {}", e),
+			(Ok(_), None) => {}
+		}
+		LoopAction::Exit
+	}
+
+	fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {}
+
+	fn on_client_disconnected(&self, num_clients: usize) -> LoopAction {
+		if num_clients == 0 {
+			LoopAction::Exit
+		} else {
+			LoopAction::Continue
+		}
+	}
+}
+
+/// Runs the crash monitor if the process was started as one, returning
+/// whether it was. The app must not start in that case.
+pub fn run_monitor_if_requested() -> bool {
+	let args: Vec<String> = std::env::args().collect();
+	let [_, flag, socket, dir, app_version] = args.as_slice() else {
+		return false;
+	};
+	if flag != MONITOR_ARG {
+		return false;
+	}
+
+	let handler = MonitorHandler {
+		dir: PathBuf::from(dir),
+		app_version: app_version.clone(),
+		report: Mutex::new(None),
+	};
+	let result = minidumper::Server::with_name(SocketName::from(Path::new(socket)))
+		.and_then(|mut server| server.run(Box::new(handler), &Default::default(), None));
+	if let Err(e) = result {
+		eprintln!("Crash monitor failed: {}", e);
+	}
+	true
+}
+
+/// Starts a copy of this executable as the crash monitor and attaches a
+/// handler that asks it for a minidump when the app crashes. Dumps must be
+/// written from outside the crashed process.
+fn start_monitor(dir: &Path, app_version: &str) -> Result<()> {
+	let socket =
+		std::env::temp_dir().join(format!("ariana-crash-{}.sock", std::process::id()));
+	let _ = fs::remove_file(&socket);
+	let monitor = Command::new(std::env::current_exe()?)
+		.arg(MONITOR_ARG)
+		.arg(&socket)
+		.arg(dir)
+		.arg(app_version)
+		.spawn()
+		.context("Failed to start crash monitor")?;
+
+	let mut client = None;
+	for _ in 0..50 {
+		match Client::with_name(SocketName::from(socket.as_path())) {
+			Ok(connected) => {
+				client = Some(connected);
+				break;
+			}
+			Err(_) => thread::sleep(Duration::from_millis(50)),
+		}
+	}
+	let client =
+		client.ok_or_else(|| anyhow!("Crash monitor did not start listening"))?;
+
+	// Safety: the handler runs in a crashed process; it only sends the crash
+	// context over the socket, which was opened beforehand.
+	let handler = CrashHandler::attach(unsafe {
+		make_crash_event(move |context: &CrashContext| {
+			CrashEventResult::Handled(client.request_dump(context).is_ok())
+		})
+	})?;
+	// Yama may otherwise stop the monitor from reading our memory
+	#[cfg(target_os = "linux")]
+	handler.set_ptracer(Some(monitor.id()));
+	#[cfg(not(target_os = "linux"))]
+	let _ = monitor;
+	let _ = CRASH_HANDLER.set(handler);
+	Ok(())
+}
+
+/// Installs the panic hook and the native crash monitor, and sends reports
+/// left by earlier runs if the user agreed to.
+pub fn init(app_handle: &AppHandle) -> Result<()> {
+	let dir = crashes_dir(app_handle)?;
+	fs::create_dir_all(&dir)?;
+	let app_version = app_handle.package_info().version.to_string();
+
+	let _ = PANIC_CONTEXT.set(PanicContext {
+		dir: dir.clone(),
+		app_version: app_version.clone(),
+	});
+	let default_hook = std::panic::take_hook();
+	std::panic::set_hook(Box::new(move |info| {
+		write_panic_report(info);
+		default_hook(info);
+	}));
+
+	if let Err(e) = start_monitor(&dir, &app_version) {
+		log::warn!("Native crashes won't be reported: {}", e);
+	}
+
+	if consent(app_handle)? == Some(true) {
+		let app_handle = app_handle.clone();
+		tauri::async_runtime::spawn(async move {
+			if let Err(e) = upload_pending(&app_handle).await {
+				log::warn!("Failed to upload crash reports: {}", e);
+			}
+		});
+	}
+	Ok(())
+}
+
+/// `None` until the user has been asked.
+pub fn consent(app_handle: &AppHandle) -> Result<Option<bool>> {
+	let store = app_handle.store(CRASH_REPORTS_STORE)?;
+	Ok(store.get("uploadConsent").and_then(|v| v.as_bool()))
+}
+
+pub fn set_consent(app_handle: &AppHandle, consent: bool) -> Result<()> {
+	let store = app_handle.store(CRASH_REPORTS_STORE)?;
+	store.set("uploadConsent", consent);
+	store.save()?;
+	Ok(())
+}
+
+/// Sends reports that haven't been uploaded yet, linked to the user's
+/// account when logged in. Returns how many were sent.
+pub async fn upload_pending(app_handle: &AppHandle) -> Result<usize> {
+	if consent(app_handle)? != Some(true) {
+		return Err(anyhow!("The user has not agreed to send crash reports"));
+	}
+	let dir = crashes_dir(app_handle)?;
+	let backend = BackendClient::from_app(app_handle).ok();
+	let url = format!(
+		"{}/api/crash-report",
+		backend_client::backend_url(app_handle)
+	);
+
+	let mut sent = 0;
+	for mut report in read_reports(&dir)?.into_iter().filter(|r| !r.uploaded) {
+		let minidump = match &report.minidump {
+			Some(name) => Some(BASE64.encode(fs::read(dir.join(name))?)),
+			None => None,
+		};
+		let upload = CrashReportUpload {
+			report_id: &report.id,
+			kind: report.kind,
+			occurred_at: &report.occurred_at,
+			app_version: &report.app_version,
+			os: &report.os,
+			arch: &report.arch,
+			message: &report.message,
+			location: &report.location,
+			thread: &report.thread,
+			backtrace: &report.backtrace,
+			logs: &report.logs,
+			minidump,
+		};
+		let request = match &backend {
+			Some(backend) => backend.request(Method::POST, "/api/crash-report"),
+			None => reqwest::Client::new().post(&url),
+		};
+		request.json(&upload).send().await?.error_for_status()?;
+
+		report.uploaded = true;
+		report.save(&dir)?;
+		sent += 1;
+	}
+	Ok(sent)
+}
+
+/// Crash reports kept on this machine, newest first.
+#[tauri::command]
+pub async fn list_crash_reports(
+	app_handle: AppHandle,
+) -> Result<Vec<CrashReport>, String> {
+	crashes_dir(&app_handle)
+		.and_then(|dir| read_reports(&dir))
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_crash_report(
+	id: String,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	let dir = crashes_dir(&app_handle).map_err(|e| e.to_string())?;
+	let report = read_reports(&dir)
+		.map_err(|e| e.to_string())?
+		.into_iter()
+		.find(|report| report.id == id)
+		.ok_or_else(|| format!("Crash report not found: {}", id))?;
+	delete_report(&dir, &report).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_crash_report_consent(
+	app_handle: AppHandle,
+) -> Result<Option<bool>, String> {
+	consent(&app_handle).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_crash_report_consent(
+	consent: bool,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	set_consent(&app_handle, consent).map_err(|e| e.to_string())
+}
+
+/// Sends pending reports now; requires consent.
+#[tauri::command]
+pub async fn upload_crash_reports(app_handle: AppHandle) -> Result<usize, String> {
+	upload_pending(&app_handle).await.map_err(|e| e.to_string())
+}
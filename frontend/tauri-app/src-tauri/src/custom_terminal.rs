@@ -20,7 +20,10 @@ use unicode_width::UnicodeWidthStr;
 use uuid::Uuid;
 use vt100::{Cell, Color as VtColor, Parser};
 
+use crate::env_files::{self, EnvTarget};
 use crate::os::OsSession;
+use crate::terminal_errors;
+use crate::toolchains;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Color {
@@ -54,6 +57,19 @@ pub struct LineItem {
 	pub is_italic: bool,
 	pub background_color: Option<Color>,
 	pub foreground_color: Option<Color>,
+	/// Set on every item of a `file:line` reference.
+	pub link: Option<FileLink>,
+}
+
+/// A `path:line:column` reference printed in the terminal, for the frontend
+/// to open in the editor. `path` is as printed, so relative paths are
+/// relative to the shell's working directory.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileLink {
+	pub path: String,
+	/// One-based.
+	pub line: u32,
+	pub column: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -160,6 +176,20 @@ impl TerminalState {
 		self.build_screen_events(full)
 	}
 
+	/// Text of the last `count` lines, oldest first, without trailing blanks.
+	pub fn last_lines(&self, count: usize) -> Vec<String> {
+		let mut lines: Vec<String> = self
+			.rows_state
+			.iter()
+			.map(|(text, _)| text.trim_end().to_string())
+			.collect();
+		while lines.last().is_some_and(|line| line.is_empty()) {
+			lines.pop();
+		}
+		let start = lines.len().saturating_sub(count);
+		lines.split_off(start)
+	}
+
 	fn build_screen_events(&mut self, full: bool) -> Vec<TerminalEvent> {
 		let screen = self.parser.screen();
 
@@ -182,6 +212,7 @@ impl TerminalState {
 			for item in &row_vec {
 				cumulated_text.push_str(&item.lexeme);
 			}
+			tag_file_links(&cumulated_text, &mut row_vec);
 			current_screen.push((cumulated_text, row_vec));
 		}
 
@@ -366,6 +397,29 @@ fn cell_to_item(opt: Option<Cell>, col: u16) -> LineItem {
 		is_underline: underline,
 		foreground_color: fg,
 		background_color: bg,
+		link: None,
+	}
+}
+
+/// Marks the items of `row` that make up a `file:line` reference in `text`,
+/// the concatenation of their lexemes.
+fn tag_file_links(text: &str, row: &mut [LineItem]) {
+	let locations = terminal_errors::find_locations(text);
+	if locations.is_empty() {
+		return;
+	}
+	let mut offset = 0;
+	for item in row.iter_mut() {
+		let start = offset;
+		offset += item.lexeme.len();
+		item.link = locations
+			.iter()
+			.find(|(range, _)| range.contains(&start))
+			.map(|(_, location)| FileLink {
+				path: location.path.clone(),
+				line: location.line,
+				column: location.column,
+			});
 	}
 }
 
@@ -400,7 +454,9 @@ impl CustomTerminalConnection {
 		})?;
 
 		// spawn the requested command
-		let cmd = os_session.build_command(false)?;
+		let mut cmd = os_session.build_command(false)?;
+		env_files::apply(&app_handle, &os_session, EnvTarget::Agent, &mut cmd);
+		toolchains::apply(&app_handle, &os_session, &mut cmd);
 		let _ = pty_pair.slave.spawn_command(cmd)?;
 
 		let state = Arc::new(Mutex::new(TerminalState::new(24, 64)));
@@ -431,7 +487,7 @@ impl CustomTerminalConnection {
 					.emit(&format!("custom-terminal-event-{id_clone}"), &events)
 					.is_err()
 				{
-					println!(
+					log::warn!(
 						"Terminal connection {id_clone} disconnected (emit failure)"
 					);
 					break;
@@ -439,7 +495,7 @@ impl CustomTerminalConnection {
 			}
 
 			// Channel closed – reader thread stopped. Notify the frontend once.
-			println!("Terminal connection {id_clone} disconnected");
+			log::debug!("Terminal connection {id_clone} disconnected");
 			let _ = app.emit(&format!("custom-terminal-disconnect-{id_clone}"), ());
 		});
 
@@ -455,13 +511,13 @@ impl CustomTerminalConnection {
 						};
 						if !events.is_empty() {
 							if let Err(e) = event_tx.send(events) {
-								eprintln!("Failed to send events to channel: {}", e);
+								log::error!("Failed to send events to channel: {}", e);
 								break;
 							}
 						}
 					}
 					Err(e) => {
-						eprintln!("PTY read error: {e}");
+						log::error!("PTY read error: {e}");
 						break;
 					}
 				}
@@ -546,7 +602,7 @@ impl CustomTerminalManager {
 		self.connections.lock().unwrap().insert(id.clone(), conn);
 		self.writers.lock().unwrap().insert(id.clone(), writer);
 
-		println!("Connected terminal: {}", id);
+		log::info!("Connected terminal: {}", id);
 		let events = self
 			.connections
 			.lock()
@@ -614,6 +670,14 @@ impl CustomTerminalManager {
 		}
 	}
 
+	pub fn last_lines(&self, id: &str, count: usize) -> Result<Vec<String>> {
+		if let Some(conn) = self.connections.lock().unwrap().get(id) {
+			Ok(conn.terminal_state.lock().unwrap().last_lines(count))
+		} else {
+			Err(anyhow!("Terminal connection not found"))
+		}
+	}
+
 	pub fn kill_terminal(&self, id: &str) -> Result<()> {
 		self.connections.lock().unwrap().remove(id);
 		self.writers.lock().unwrap().remove(id);
@@ -1,4 +1,4 @@
-use crate::{custom_terminal::CustomTerminalManager, os::OsSession};
+use crate::{custom_terminal::CustomTerminalManager, os::OsSession, trust};
 use std::sync::Arc;
 use tauri::{AppHandle, State};
 
@@ -8,6 +8,8 @@ pub async fn custom_connect_terminal(
 	app_handle: AppHandle,
 	manager: State<'_, Arc<CustomTerminalManager>>,
 ) -> Result<String, String> {
+	// Agents run in custom terminals
+	trust::ensure_trusted(&app_handle, &os_session, os_session.get_working_directory())?;
 	let terminal_manager = manager;
 	terminal_manager
 		.connect_terminal(os_session, app_handle)
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Emitter, Manager, State, Url};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+const SCHEME: &str = "ariana";
+
+/// An action requested through an `ariana://` URL, sent to the frontend as a
+/// `deep-link` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum DeepLink {
+	/// `ariana://open-project?path=<dir>`
+	#[serde(rename_all = "camelCase")]
+	OpenProject { path: String },
+	/// `ariana://open-file?path=<file>&line=<n>&column=<n>`, one-based.
+	#[serde(rename_all = "camelCase")]
+	OpenFile {
+		path: String,
+		line: Option<u32>,
+		column: Option<u32>,
+	},
+	/// `ariana://auth?token=...&email=...&account_id=...&expires_at=...`
+	/// from a magic login link. The token is saved to `~/.ariana/config.json`
+	/// and not forwarded.
+	#[serde(rename_all = "camelCase")]
+	Auth {
+		email: String,
+		account_id: Option<String>,
+		expires_at: String,
+	},
+	/// `ariana://auth?error=<reason>` when a login link was rejected.
+	#[serde(rename_all = "camelCase")]
+	AuthError { error: String },
+	/// `ariana://join-canvas?id=<canvas>`
+	#[serde(rename_all = "camelCase")]
+	JoinCanvas { canvas_id: String },
+}
+
+/// The private part of an auth link, written to the user config.
+struct Credentials {
+	token: String,
+	email: String,
+	account_id: Option<String>,
+	expires_at: String,
+}
+
+fn parse(url: &Url) -> Result<(DeepLink, Option<Credentials>)> {
+	if url.scheme() != SCHEME {
+		return Err(anyhow!("Not an {}:// URL: {}", SCHEME, url));
+	}
+	let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+	let param = |name: &str| {
+		params
+			.get(name)
+			.filter(|v| !v.is_empty())
+			.cloned()
+			.ok_or_else(|| anyhow!("Missing `{}` in {}", name, url))
+	};
+	let number = |name: &str| params.get(name).and_then(|v| v.parse().ok());
+
+	let link = match url.host_str().unwrap_or_default() {
+		"open-project" => DeepLink::OpenProject {
+			path: param("path")?,
+		},
+		"open-file" => DeepLink::OpenFile {
+			path: param("path")?,
+			line: number("line"),
+			column: number("column"),
+		},
+		"auth" => {
+			if let Ok(error) = param("error") {
+				return Ok((DeepLink::AuthError { error }, None));
+			}
+			let credentials = Credentials {
+				token: param("token")?,
+				email: param("email")?,
+				account_id: param("account_id").ok(),
+				expires_at: param("expires_at")?,
+			};
+			let link = DeepLink::Auth {
+				email: credentials.email.clone(),
+				account_id: credentials.account_id.clone(),
+				expires_at: credentials.expires_at.clone(),
+			};
+			return Ok((link, Some(credentials)));
+		}
+		"join-canvas" => DeepLink::JoinCanvas {
+			canvas_id: param("id")?,
+		},
+		action => return Err(anyhow!("Unknown {}:// action: {}", SCHEME, action)),
+	};
+	Ok((link, None))
+}
+
+/// Stores credentials the way `ariana login` does, keeping other settings
+/// such as `backendUrl`.
+fn save_credentials(app_handle: &AppHandle, credentials: &Credentials) -> Result<()> {
+	let dir = app_handle.path().home_dir()?.join(".ariana");
+	let path = dir.join("config.json");
+	let mut config = fs::read_to_string(&path)
+		.ok()
+		.and_then(|content| serde_json::from_str::<Map<String, Value>>(&content).ok())
+		.unwrap_or_default();
+
+	config.insert("token".into(), credentials.token.clone().into());
+	config.insert("email".into(), credentials.email.clone().into());
+	config.insert("expiresAt".into(), credentials.expires_at.clone().into());
+	match &credentials.account_id {
+		Some(id) => config.insert("accountId".into(), id.clone().into()),
+		None => config.remove("accountId"),
+	};
+
+	fs::create_dir_all(&dir)?;
+	fs::write(&path, serde_json::to_string_pretty(&config)?)
+		.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Holds deep links that arrive before the frontend is listening, such as
+/// the URL the app was launched with.
+pub struct DeepLinkState {
+	/// `None` once the frontend has taken the pending links; later links
+	/// are emitted straight away.
+	pending: Mutex<Option<Vec<DeepLink>>>,
+}
+
+impl Default for DeepLinkState {
+	fn default() -> Self {
+		Self {
+			pending: Mutex::new(Some(Vec::new())),
+		}
+	}
+}
+
+pub fn focus_main_window(app_handle: &AppHandle) {
+	if let Some(window) = app_handle.get_webview_window("main") {
+		let _ = window.unminimize();
+		let _ = window.show();
+		let _ = window.set_focus();
+	}
+}
+
+/// Parses and dispatches `ariana://` URLs. Invalid URLs are logged and
+/// skipped.
+pub fn handle_urls(app_handle: &AppHandle, urls: Vec<Url>) {
+	for url in urls {
+		let link = match parse(&url) {
+			Ok((link, credentials)) => {
+				if let Some(credentials) = credentials {
+					if let Err(e) = save_credentials(app_handle, &credentials) {
+						log::error!("Failed to save login from deep link: {}", e);
+						continue;
+					}
+				}
+				link
+			}
+			Err(e) => {
+				log::warn!("Ignoring deep link: {}", e);
+				continue;
+			}
+		};
+
+		dispatch(app_handle, link);
+	}
+	focus_main_window(app_handle);
+}
+
+/// Opens the folders and files named on the command line, as `ariana .`
+/// does. Relative paths are resolved against `cwd`, the directory the
+/// command was run from. Flags and `ariana://` URLs are left to others.
+pub fn handle_args(app_handle: &AppHandle, args: &[String], cwd: &Path) {
+	for arg in args {
+		if arg.starts_with('-') || arg.starts_with(&format!("{}:", SCHEME)) {
+			continue;
+		}
+		let path = match cwd.join(arg).canonicalize() {
+			Ok(path) => path,
+			Err(e) => {
+				log::warn!("Ignoring argument {}: {}", arg, e);
+				continue;
+			}
+		};
+		let link = if path.is_dir() {
+			DeepLink::OpenProject {
+				path: path.to_string_lossy().to_string(),
+			}
+		} else {
+			DeepLink::OpenFile {
+				path: path.to_string_lossy().to_string(),
+				line: None,
+				column: None,
+			}
+		};
+		dispatch(app_handle, link);
+	}
+}
+
+/// Sends `link` to the frontend, or queues it until the frontend is ready.
+pub fn dispatch(app_handle: &AppHandle, link: DeepLink) {
+	let state = app_handle.state::<DeepLinkState>();
+	let mut pending = state.pending.lock().unwrap();
+	match pending.as_mut() {
+		Some(pending) => pending.push(link),
+		None => {
+			let _ = app_handle.emit("deep-link", link);
+		}
+	}
+}
+
+/// Hooks URL opening into `handle_urls`, including the URL the app was
+/// launched with, and opens paths passed on the command line. URLs and
+/// paths given while the app is running arrive through the single instance
+/// plugin.
+pub fn setup(app_handle: &AppHandle) -> Result<()> {
+	app_handle.manage(DeepLinkState::default());
+
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	handle_args(app_handle, &args, &std::env::current_dir()?);
+
+	// Installers register the scheme; this covers development builds
+	#[cfg(any(windows, target_os = "linux"))]
+	if let Err(e) = app_handle.deep_link().register_all() {
+		log::warn!("Failed to register {}:// scheme: {}", SCHEME, e);
+	}
+
+	let handle = app_handle.clone();
+	app_handle
+		.deep_link()
+		.on_open_url(move |event| handle_urls(&handle, event.urls()));
+	if let Some(urls) = app_handle.deep_link().get_current()? {
+		handle_urls(app_handle, urls);
+	}
+	Ok(())
+}
+
+/// Returns the deep links received before the frontend was ready; later
+/// ones arrive as `deep-link` events.
+#[tauri::command]
+pub async fn take_pending_deep_links(
+	state: State<'_, DeepLinkState>,
+) -> Result<Vec<DeepLink>, String> {
+	Ok(state.pending.lock().unwrap().take().unwrap_or_default())
+}
@@ -0,0 +1,1574 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::State;
+use tokio::sync::Semaphore;
+use toml_edit::{Array, DocumentMut, Item, Table, TableLike};
+
+use crate::os::OsSession;
+
+const USER_AGENT: &str = concat!("ariana-ide/", env!("CARGO_PKG_VERSION"));
+/// How long a registry's answer is reused.
+const LATEST_TTL: Duration = Duration::from_secs(60 * 60);
+/// Registry requests in flight at once.
+const MAX_CONCURRENT_LOOKUPS: usize = 8;
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CARGO_TABLES: [(&str, DependencyKind); 3] = [
+	("dependencies", DependencyKind::Normal),
+	("dev-dependencies", DependencyKind::Dev),
+	("build-dependencies", DependencyKind::Build),
+];
+const NPM_SECTIONS: [(&str, DependencyKind); 4] = [
+	("dependencies", DependencyKind::Normal),
+	("devDependencies", DependencyKind::Dev),
+	("peerDependencies", DependencyKind::Peer),
+	("optionalDependencies", DependencyKind::Optional),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ecosystem {
+	Cargo,
+	Npm,
+	Python,
+}
+
+impl Ecosystem {
+	const ALL: [Ecosystem; 3] = [Ecosystem::Cargo, Ecosystem::Npm, Ecosystem::Python];
+
+	fn manifest(self) -> &'static str {
+		match self {
+			Self::Cargo => "Cargo.toml",
+			Self::Npm => "package.json",
+			Self::Python => "pyproject.toml",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+	#[default]
+	Normal,
+	Dev,
+	Build,
+	Peer,
+	Optional,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependency {
+	pub name: String,
+	pub kind: DependencyKind,
+	/// Where it's declared, e.g. `dev-dependencies`,
+	/// `target.'cfg(windows)'.dependencies` or
+	/// `project.optional-dependencies.test`.
+	pub section: String,
+	/// The version requirement as written, e.g. `^1.2` or `>=2.0`.
+	pub requirement: Option<String>,
+	/// Set for path, git and workspace dependencies, which the registry
+	/// doesn't serve.
+	pub source: Option<String>,
+	/// From the lockfile, `node_modules` or the virtual environment.
+	pub installed: Option<String>,
+	/// Newest stable release in the registry, when it was asked.
+	pub latest: Option<String>,
+	/// Whether `latest` is newer than what's installed, or than the
+	/// requirement when nothing is.
+	pub outdated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestDependencies {
+	pub ecosystem: Ecosystem,
+	/// The manifest inside the session.
+	pub path: String,
+	pub dependencies: Vec<Dependency>,
+	/// Set when the manifest couldn't be read; `dependencies` is empty.
+	pub error: Option<String>,
+}
+
+/// A manifest change, written unless it was a dry run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEdit {
+	pub path: String,
+	pub original: String,
+	pub updated: String,
+	pub written: bool,
+}
+
+/// When a package's latest version was looked up, and what it was.
+type LatestEntry = (Instant, Option<String>);
+
+/// Latest versions from crates.io, npm and PyPI, kept for `LATEST_TTL`.
+pub struct RegistryCache {
+	http: Client,
+	latest: Mutex<HashMap<(Ecosystem, String), LatestEntry>>,
+}
+
+impl RegistryCache {
+	pub fn new() -> Arc<Self> {
+		let http = Client::builder()
+			.user_agent(USER_AGENT)
+			.timeout(LOOKUP_TIMEOUT)
+			.build()
+			.unwrap_or_default();
+		Arc::new(Self {
+			http,
+			latest: Mutex::new(HashMap::new()),
+		})
+	}
+
+	async fn fetch(&self, ecosystem: Ecosystem, name: &str) -> Result<Option<String>> {
+		let url = match ecosystem {
+			Ecosystem::Cargo => format!("https://crates.io/api/v1/crates/{}", name),
+			// Scoped packages keep their `@` but escape the slash
+			Ecosystem::Npm => format!(
+				"https://registry.npmjs.org/{}/latest",
+				name.replace('/', "%2F")
+			),
+			Ecosystem::Python => format!("https://pypi.org/pypi/{}/json", name),
+		};
+		let response = self.http.get(&url).send().await?;
+		if response.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+		let body: Value = response.error_for_status()?.json().await?;
+		let version = match ecosystem {
+			Ecosystem::Cargo => body["crate"]["max_stable_version"]
+				.as_str()
+				.or_else(|| body["crate"]["max_version"].as_str()),
+			Ecosystem::Npm => body["version"].as_str(),
+			Ecosystem::Python => body["info"]["version"].as_str(),
+		};
+		Ok(version.map(str::to_string))
+	}
+
+	/// The newest release of `name`, `None` when the registry doesn't know
+	/// it or can't be reached.
+	pub async fn latest(&self, ecosystem: Ecosystem, name: &str) -> Option<String> {
+		let key = (ecosystem, name.to_string());
+		if let Some((fetched_at, version)) = self.latest.lock().unwrap().get(&key) {
+			if fetched_at.elapsed() < LATEST_TTL {
+				return version.clone();
+			}
+		}
+		match self.fetch(ecosystem, name).await {
+			Ok(version) => {
+				self.latest
+					.lock()
+					.unwrap()
+					.insert(key, (Instant::now(), version.clone()));
+				version
+			}
+			Err(e) => {
+				log::warn!("Failed to look up the latest {}: {}", name, e);
+				None
+			}
+		}
+	}
+}
+
+/// Numeric components of a version, e.g. `[1, 2, 3]` for `v1.2.3-beta` or
+/// `1.2.3.post1`.
+fn version_numbers(version: &str) -> Vec<u64> {
+	version
+		.trim_start_matches(['v', '='])
+		.split(['.', '-', '+'])
+		.map_while(|part| {
+			let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+			digits.parse().ok()
+		})
+		.collect()
+}
+
+/// The lowest version a requirement allows, e.g. `1.2` for `^1.2` or
+/// `>=1.2,<2`.
+fn requirement_version(requirement: &str) -> Option<&str> {
+	let version = requirement
+		.trim()
+		.trim_start_matches(['^', '~', '>', '<', '=', '!', ' '])
+		.split([',', ' ', '|'])
+		.next()?;
+	(!version.is_empty() && version.starts_with(|c: char| c.is_ascii_digit()))
+		.then_some(version)
+}
+
+fn is_newer(latest: &str, current: &str) -> bool {
+	if let (Ok(latest), Ok(current)) = (
+		semver::Version::parse(latest),
+		semver::Version::parse(current),
+	) {
+		return latest > current;
+	}
+	version_numbers(latest) > version_numbers(current)
+}
+
+/// The `table.key` path of a section, quoting keys that need it.
+fn section_name(parts: &[&str]) -> String {
+	parts
+		.iter()
+		.map(|part| {
+			if part
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+			{
+				part.to_string()
+			} else {
+				format!("'{}'", part)
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(".")
+}
+
+fn parse_toml(content: &str) -> Result<DocumentMut> {
+	content
+		.parse::<DocumentMut>()
+		.map_err(|e| anyhow!("Invalid TOML: {}", e))
+}
+
+/// Every dependency table of a Cargo manifest, with its section and kind:
+/// the top-level ones, those under `target.<cfg>` and
+/// `workspace.dependencies`.
+fn cargo_tables(document: &DocumentMut) -> Vec<(String, DependencyKind, &dyn TableLike)> {
+	let mut tables = Vec::new();
+	for (name, kind) in CARGO_TABLES {
+		if let Some(table) = document.get(name).and_then(Item::as_table_like) {
+			tables.push((name.to_string(), kind, table));
+		}
+	}
+	if let Some(targets) = document.get("target").and_then(Item::as_table_like) {
+		for (target, item) in targets.iter() {
+			for (name, kind) in CARGO_TABLES {
+				if let Some(table) = item.get(name).and_then(Item::as_table_like) {
+					tables.push((section_name(&["target", target, name]), kind, table));
+				}
+			}
+		}
+	}
+	if let Some(table) = document
+		.get("workspace")
+		.and_then(|workspace| workspace.get("dependencies"))
+		.and_then(Item::as_table_like)
+	{
+		tables.push((
+			"workspace.dependencies".to_string(),
+			DependencyKind::Normal,
+			table,
+		));
+	}
+	tables
+}
+
+/// The versions of each package in the nearest `Cargo.lock`, which for a
+/// workspace member is in the workspace root.
+fn cargo_lock_versions(root: &Path) -> HashMap<String, Vec<String>> {
+	#[derive(Deserialize)]
+	struct Lock {
+		#[serde(default)]
+		package: Vec<LockPackage>,
+	}
+	#[derive(Deserialize)]
+	struct LockPackage {
+		name: String,
+		version: String,
+	}
+
+	let mut versions: HashMap<String, Vec<String>> = HashMap::new();
+	let lock = root
+		.ancestors()
+		.find_map(|dir| fs::read_to_string(dir.join("Cargo.lock")).ok())
+		.and_then(|content| toml::from_str::<Lock>(&content).ok());
+	for package in lock.map(|lock| lock.package).unwrap_or_default() {
+		versions
+			.entry(package.name)
+			.or_default()
+			.push(package.version);
+	}
+	versions
+}
+
+fn cargo_dependencies(root: &Path, content: &str) -> Result<Vec<Dependency>> {
+	let document = parse_toml(content)?;
+	let locked = cargo_lock_versions(root);
+	let mut dependencies = Vec::new();
+	for (section, kind, table) in cargo_tables(&document) {
+		for (key, item) in table.iter() {
+			let (requirement, source, package) = match item.as_str() {
+				Some(version) => (Some(version.to_string()), None, None),
+				None => {
+					let field = |name: &str| item.get(name).and_then(Item::as_str);
+					let source = if item.get("workspace").is_some() {
+						Some("workspace".to_string())
+					} else if let Some(path) = field("path") {
+						Some(format!("path: {}", path))
+					} else {
+						field("git").map(|git| format!("git: {}", git))
+					};
+					(
+						field("version").map(str::to_string),
+						source,
+						field("package").map(str::to_string),
+					)
+				}
+			};
+			let package = package.unwrap_or_else(|| key.to_string());
+			// Of several locked versions, the one the requirement allows
+			let installed = locked.get(&package).and_then(|versions| {
+				let requirement = requirement
+					.as_deref()
+					.and_then(|requirement| semver::VersionReq::parse(requirement).ok());
+				versions
+					.iter()
+					.filter(|version| {
+						let (Some(requirement), Ok(version)) =
+							(&requirement, semver::Version::parse(version))
+						else {
+							return true;
+						};
+						requirement.matches(&version)
+					})
+					.max_by(|a, b| version_numbers(a).cmp(&version_numbers(b)))
+					.cloned()
+			});
+			dependencies.push(Dependency {
+				name: package,
+				kind,
+				section: section.clone(),
+				requirement,
+				source,
+				installed,
+				latest: None,
+				outdated: false,
+			});
+		}
+	}
+	Ok(dependencies)
+}
+
+fn npm_installed(root: &Path, name: &str) -> Option<String> {
+	let manifest = root.join("node_modules").join(name).join("package.json");
+	let package: Value =
+		serde_json::from_str(&fs::read_to_string(manifest).ok()?).ok()?;
+	package["version"].as_str().map(str::to_string)
+}
+
+fn npm_dependencies(root: &Path, content: &str) -> Result<Vec<Dependency>> {
+	let package: Map<String, Value> =
+		serde_json::from_str(content).context("Invalid package.json")?;
+	let mut dependencies = Vec::new();
+	for (section, kind) in NPM_SECTIONS {
+		let Some(entries) = package.get(section).and_then(Value::as_object) else {
+			continue;
+		};
+		for (name, requirement) in entries {
+			let requirement = requirement.as_str().unwrap_or_default().to_string();
+			// `workspace:*`, `file:../x`, `github:owner/repo`, git URLs
+			let source = (requirement.contains(':') || requirement.contains('/'))
+				.then(|| requirement.clone());
+			dependencies.push(Dependency {
+				name: name.clone(),
+				kind,
+				section: section.to_string(),
+				installed: npm_installed(root, name),
+				requirement: Some(requirement),
+				source,
+				latest: None,
+				outdated: false,
+			});
+		}
+	}
+	Ok(dependencies)
+}
+
+/// PEP 503 name normalization, under which `Foo.Bar` and `foo-bar` match.
+pub fn normalize_python_name(name: &str) -> String {
+	let mut normalized = String::new();
+	for c in name.chars() {
+		if matches!(c, '-' | '_' | '.') {
+			if !normalized.ends_with('-') {
+				normalized.push('-');
+			}
+		} else {
+			normalized.push(c.to_ascii_lowercase());
+		}
+	}
+	normalized
+}
+
+/// The `.dist-info` directories of the project's virtual environment, with
+/// the package name and version each is for.
+pub fn python_dist_infos(root: &Path) -> Vec<(PathBuf, String, String)> {
+	let mut dist_infos = Vec::new();
+	for venv in [".venv", "venv"] {
+		let venv = root.join(venv);
+		let mut site_packages = vec![venv.join("Lib").join("site-packages")];
+		if let Ok(entries) = fs::read_dir(venv.join("lib")) {
+			site_packages.extend(
+				entries
+					.filter_map(|entry| entry.ok())
+					.map(|entry| entry.path().join("site-packages")),
+			);
+		}
+		for dir in site_packages {
+			let Ok(entries) = fs::read_dir(dir) else {
+				continue;
+			};
+			for entry in entries.filter_map(|entry| entry.ok()) {
+				let name = entry.file_name().to_string_lossy().to_string();
+				let Some(stem) = name.strip_suffix(".dist-info") else {
+					continue;
+				};
+				if let Some((package, version)) = stem.split_once('-') {
+					dist_infos.push((
+						entry.path(),
+						package.to_string(),
+						version.to_string(),
+					));
+				}
+			}
+		}
+	}
+	dist_infos
+}
+
+/// Versions of the packages in the project's virtual environment, by
+/// normalized name, read from their `.dist-info` directories.
+fn python_installed(root: &Path) -> HashMap<String, String> {
+	python_dist_infos(root)
+		.into_iter()
+		.map(|(_, package, version)| (normalize_python_name(&package), version))
+		.collect()
+}
+
+/// Splits a PEP 508 requirement like `requests[socks]>=2.0; python_version
+/// >= "3.8"` into its name and the rest.
+fn split_pep508(requirement: &str) -> (&str, &str) {
+	let requirement = requirement.trim();
+	let end = requirement
+		.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+		.unwrap_or(requirement.len());
+	(&requirement[..end], &requirement[end..])
+}
+
+/// The version specifier of the rest of a PEP 508 requirement, without
+/// extras or markers.
+fn pep508_specifier(rest: &str) -> &str {
+	let rest = rest.split(';').next().unwrap_or_default();
+	let rest = match rest.find(']') {
+		Some(end) if rest.trim_start().starts_with('[') => &rest[end + 1..],
+		_ => rest,
+	};
+	rest.trim()
+		.trim_start_matches('(')
+		.trim_end_matches(')')
+		.trim()
+}
+
+fn python_dependencies(root: &Path, content: &str) -> Result<Vec<Dependency>> {
+	let document = parse_toml(content)?;
+	let installed = python_installed(root);
+	let mut dependencies = Vec::new();
+	let mut push =
+		|name: &str, requirement: Option<String>, source, kind, section: String| {
+			dependencies.push(Dependency {
+				name: name.to_string(),
+				kind,
+				section,
+				requirement: requirement.filter(|requirement| !requirement.is_empty()),
+				source,
+				installed: installed.get(&normalize_python_name(name)).cloned(),
+				latest: None,
+				outdated: false,
+			});
+		};
+
+	let mut arrays: Vec<(String, DependencyKind, &Array)> = Vec::new();
+	let project = document.get("project");
+	if let Some(array) = project
+		.and_then(|project| project.get("dependencies"))
+		.and_then(Item::as_array)
+	{
+		arrays.push((
+			"project.dependencies".to_string(),
+			DependencyKind::Normal,
+			array,
+		));
+	}
+	if let Some(groups) = project
+		.and_then(|project| project.get("optional-dependencies"))
+		.and_then(Item::as_table_like)
+	{
+		for (group, item) in groups.iter() {
+			if let Some(array) = item.as_array() {
+				arrays.push((
+					section_name(&["project", "optional-dependencies", group]),
+					DependencyKind::Optional,
+					array,
+				));
+			}
+		}
+	}
+	if let Some(groups) = document
+		.get("dependency-groups")
+		.and_then(Item::as_table_like)
+	{
+		for (group, item) in groups.iter() {
+			if let Some(array) = item.as_array() {
+				arrays.push((
+					section_name(&["dependency-groups", group]),
+					DependencyKind::Dev,
+					array,
+				));
+			}
+		}
+	}
+	for (section, kind, array) in arrays {
+		// Entries may also be `{include-group = "..."}` tables
+		for requirement in array.iter().filter_map(|value| value.as_str()) {
+			let (name, rest) = split_pep508(requirement);
+			let source = rest.contains(" @ ").then(|| rest.trim().to_string());
+			push(
+				name,
+				Some(pep508_specifier(rest).to_string()),
+				source,
+				kind,
+				section.clone(),
+			);
+		}
+	}
+
+	for (path, kind) in poetry_paths(&document) {
+		let Some(table) = table_at(&document, &path) else {
+			continue;
+		};
+		let section = section_name(&path.iter().map(String::as_str).collect::<Vec<_>>());
+		for (name, item) in table.iter() {
+			// The Python version the project supports
+			if name == "python" {
+				continue;
+			}
+			let (requirement, source) = match item.as_str() {
+				Some(version) => (Some(version.to_string()), None),
+				None => {
+					let field = |key: &str| item.get(key).and_then(Item::as_str);
+					let source = field("path")
+						.map(|path| format!("path: {}", path))
+						.or_else(|| field("git").map(|git| format!("git: {}", git)));
+					(field("version").map(str::to_string), source)
+				}
+			};
+			push(name, requirement, source, kind, section.clone());
+		}
+	}
+	Ok(dependencies)
+}
+
+/// Key paths of Poetry's dependency tables: `tool.poetry.dependencies`,
+/// the legacy `dev-dependencies` and `tool.poetry.group.<name>.dependencies`.
+fn poetry_paths(document: &DocumentMut) -> Vec<(Vec<String>, DependencyKind)> {
+	let mut paths = Vec::new();
+	let Some(poetry) = document.get("tool").and_then(|tool| tool.get("poetry")) else {
+		return paths;
+	};
+	for (name, kind) in [
+		("dependencies", DependencyKind::Normal),
+		("dev-dependencies", DependencyKind::Dev),
+	] {
+		if poetry.get(name).is_some_and(Item::is_table_like) {
+			paths.push((vec!["tool".into(), "poetry".into(), name.into()], kind));
+		}
+	}
+	if let Some(groups) = poetry.get("group").and_then(Item::as_table_like) {
+		for (group, item) in groups.iter() {
+			if item.get("dependencies").is_some_and(Item::is_table_like) {
+				let path = ["tool", "poetry", "group", group, "dependencies"];
+				paths.push((path.map(str::to_string).to_vec(), DependencyKind::Dev));
+			}
+		}
+	}
+	paths
+}
+
+fn table_at<'a>(document: &'a DocumentMut, path: &[String]) -> Option<&'a dyn TableLike> {
+	let mut table: &dyn TableLike = document.as_table();
+	for key in path {
+		table = table.get(key)?.as_table_like()?;
+	}
+	Some(table)
+}
+
+fn table_at_mut<'a>(
+	document: &'a mut DocumentMut,
+	path: &[String],
+) -> Option<&'a mut dyn TableLike> {
+	let mut table: &mut dyn TableLike = document.as_table_mut();
+	for key in path {
+		table = table.get_mut(key)?.as_table_like_mut()?;
+	}
+	Some(table)
+}
+
+/// The key `name` is declared under in a Poetry table, which may be
+/// spelled differently.
+fn poetry_key(table: &dyn TableLike, name: &str) -> Option<String> {
+	table
+		.iter()
+		.find(|(key, _)| normalize_python_name(key) == normalize_python_name(name))
+		.map(|(key, _)| key.to_string())
+}
+
+fn manifest_path(session: &OsSession, ecosystem: Ecosystem) -> String {
+	let directory = session.get_working_directory();
+	let separator = if directory.contains('\\') { '\\' } else { '/' };
+	format!(
+		"{}{}{}",
+		directory.trim_end_matches(['/', '\\']),
+		separator,
+		ecosystem.manifest()
+	)
+}
+
+/// The dependencies of each manifest in the session's working directory.
+fn read_manifests(session: &OsSession) -> Vec<ManifestDependencies> {
+	let root = session.host_path();
+	Ecosystem::ALL
+		.into_iter()
+		.filter_map(|ecosystem| {
+			let content = fs::read_to_string(root.join(ecosystem.manifest())).ok()?;
+			let dependencies = match ecosystem {
+				Ecosystem::Cargo => cargo_dependencies(&root, &content),
+				Ecosystem::Npm => npm_dependencies(&root, &content),
+				Ecosystem::Python => python_dependencies(&root, &content),
+			};
+			let (dependencies, error) = match dependencies {
+				Ok(dependencies) => (dependencies, None),
+				Err(e) => (Vec::new(), Some(e.to_string())),
+			};
+			Some(ManifestDependencies {
+				ecosystem,
+				path: manifest_path(session, ecosystem),
+				dependencies,
+				error,
+			})
+		})
+		.collect()
+}
+
+/// Fills in the latest versions of registry dependencies.
+async fn check_latest(
+	cache: &Arc<RegistryCache>,
+	manifests: &mut [ManifestDependencies],
+) {
+	let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_LOOKUPS));
+	let mut lookups = tokio::task::JoinSet::new();
+	for (manifest_index, manifest) in manifests.iter().enumerate() {
+		for (index, dependency) in manifest.dependencies.iter().enumerate() {
+			if dependency.source.is_some() {
+				continue;
+			}
+			let (cache, semaphore) = (cache.clone(), semaphore.clone());
+			let (ecosystem, name) = (manifest.ecosystem, dependency.name.clone());
+			lookups.spawn(async move {
+				let _permit = semaphore.acquire().await;
+				(manifest_index, index, cache.latest(ecosystem, &name).await)
+			});
+		}
+	}
+	while let Some(result) = lookups.join_next().await {
+		let Ok((manifest_index, index, Some(latest))) = result else {
+			continue;
+		};
+		let dependency = &mut manifests[manifest_index].dependencies[index];
+		let current = dependency.installed.as_deref().or_else(|| {
+			dependency
+				.requirement
+				.as_deref()
+				.and_then(requirement_version)
+		});
+		dependency.outdated = current.is_some_and(|current| is_newer(&latest, current));
+		dependency.latest = Some(latest);
+	}
+}
+
+/// Sets the version of a Cargo or Poetry dependency, keeping the rest of an
+/// inline or full table as it is.
+fn set_version(item: &mut Item, version: &str) -> Result<()> {
+	if item.is_str() {
+		let decor = item.as_value().map(|value| value.decor().clone());
+		*item = toml_edit::value(version);
+		if let (Some(decor), Some(value)) = (decor, item.as_value_mut()) {
+			*value.decor_mut() = decor;
+		}
+		return Ok(());
+	}
+	let table = item
+		.as_table_like_mut()
+		.ok_or_else(|| anyhow!("Unexpected dependency entry"))?;
+	let elsewhere = ["path", "git", "workspace"]
+		.iter()
+		.any(|key| table.contains_key(key));
+	if elsewhere && !table.contains_key("version") {
+		return Err(anyhow!(
+			"Path, git and workspace dependencies have no version to change"
+		));
+	}
+	match table.get_mut("version") {
+		Some(existing) => {
+			let decor = existing.as_value().map(|value| value.decor().clone());
+			*existing = toml_edit::value(version);
+			if let (Some(decor), Some(value)) = (decor, existing.as_value_mut()) {
+				*value.decor_mut() = decor;
+			}
+		}
+		None => {
+			table.insert("version", toml_edit::value(version));
+		}
+	}
+	Ok(())
+}
+
+/// Mutable dependency tables of a Cargo manifest, as `cargo_tables` finds
+/// them, matching `kind` when given.
+fn cargo_tables_mut(
+	document: &mut DocumentMut,
+	kind: Option<DependencyKind>,
+) -> Vec<&mut dyn TableLike> {
+	let wanted = |table_kind: DependencyKind| kind.is_none_or(|kind| kind == table_kind);
+	let mut tables: Vec<&mut dyn TableLike> = Vec::new();
+	let root = document.as_table_mut();
+	let mut targets = None;
+	let mut workspace = None;
+	for (key, item) in root.iter_mut() {
+		let key = key.get();
+		if let Some((_, table_kind)) = CARGO_TABLES.iter().find(|(name, _)| *name == key)
+		{
+			if wanted(*table_kind) {
+				tables.extend(item.as_table_like_mut());
+			}
+		} else if key == "target" {
+			targets = item.as_table_like_mut();
+		} else if key == "workspace" {
+			workspace = item.as_table_like_mut();
+		}
+	}
+	if let Some(targets) = targets {
+		for (_, target) in targets.iter_mut() {
+			let Some(target) = target.as_table_like_mut() else {
+				continue;
+			};
+			for (key, item) in target.iter_mut() {
+				let key = key.get();
+				if let Some((_, table_kind)) =
+					CARGO_TABLES.iter().find(|(name, _)| *name == key)
+				{
+					if wanted(*table_kind) {
+						tables.extend(item.as_table_like_mut());
+					}
+				}
+			}
+		}
+	}
+	if let Some(workspace) = workspace {
+		if wanted(DependencyKind::Normal) {
+			tables.extend(
+				workspace
+					.get_mut("dependencies")
+					.and_then(Item::as_table_like_mut),
+			);
+		}
+	}
+	tables
+}
+
+/// The key a Cargo dependency is declared under, which differs from the
+/// package name when it's renamed.
+fn cargo_key(table: &dyn TableLike, name: &str) -> Option<String> {
+	table.iter().find_map(|(key, item)| {
+		let package = item.get("package").and_then(Item::as_str).unwrap_or(key);
+		(package == name).then(|| key.to_string())
+	})
+}
+
+fn edit_cargo(content: &str, edit: &Edit) -> Result<String> {
+	let mut document = parse_toml(content)?;
+	match edit {
+		Edit::Add {
+			name,
+			version,
+			kind,
+		} => {
+			let table_name = match kind {
+				DependencyKind::Normal => "dependencies",
+				DependencyKind::Dev => "dev-dependencies",
+				DependencyKind::Build => "build-dependencies",
+				_ => return Err(anyhow!("Cargo has no {:?} dependencies", kind)),
+			};
+			let table = document
+				.entry(table_name)
+				.or_insert_with(|| Item::Table(Table::new()))
+				.as_table_like_mut()
+				.ok_or_else(|| anyhow!("{} isn't a table", table_name))?;
+			match cargo_key(table, name).and_then(|key| table.get_mut(&key)) {
+				Some(item) => set_version(item, version)?,
+				None => {
+					table.insert(name, toml_edit::value(version.as_str()));
+				}
+			}
+		}
+		Edit::Remove { name, kind } => {
+			let mut removed = false;
+			for table in cargo_tables_mut(&mut document, *kind) {
+				if let Some(key) = cargo_key(table, name) {
+					removed |= table.remove(&key).is_some();
+				}
+			}
+			if !removed {
+				return Err(anyhow!("{} isn't a dependency", name));
+			}
+		}
+		Edit::Upgrade { name, version } => {
+			let mut upgraded = false;
+			for table in cargo_tables_mut(&mut document, None) {
+				let Some(key) = cargo_key(table, name) else {
+					continue;
+				};
+				if let Some(item) = table.get_mut(&key) {
+					set_version(item, version)?;
+					upgraded = true;
+				}
+			}
+			if !upgraded {
+				return Err(anyhow!("{} isn't a dependency", name));
+			}
+		}
+	}
+	Ok(document.to_string())
+}
+
+/// The indentation of the first indented line, two spaces by default as
+/// npm writes.
+fn json_indent(content: &str) -> String {
+	content
+		.lines()
+		.skip(1)
+		.find_map(|line| {
+			let indent: String = line
+				.chars()
+				.take_while(|c| *c == ' ' || *c == '\t')
+				.collect();
+			(!indent.is_empty()).then_some(indent)
+		})
+		.unwrap_or_else(|| "  ".to_string())
+}
+
+fn json_line_ending(content: &str) -> &'static str {
+	if content.contains("\r\n") {
+		"\r\n"
+	} else {
+		"\n"
+	}
+}
+
+/// A member of a JSON object, as byte offsets into the document.
+struct JsonMember {
+	key: String,
+	start: usize,
+	value_start: usize,
+	end: usize,
+}
+
+/// A JSON object, from its `{` to its `}`.
+struct JsonObject {
+	open: usize,
+	close: usize,
+	members: Vec<JsonMember>,
+}
+
+impl JsonObject {
+	fn position(&self, key: &str) -> Option<usize> {
+		self.members.iter().position(|member| member.key == key)
+	}
+
+	fn is_sorted(&self) -> bool {
+		self.members
+			.iter()
+			.zip(self.members.iter().skip(1))
+			.all(|(a, b)| a.key <= b.key)
+	}
+}
+
+fn skip_whitespace(bytes: &[u8], mut at: usize) -> usize {
+	while bytes.get(at).is_some_and(u8::is_ascii_whitespace) {
+		at += 1;
+	}
+	at
+}
+
+/// The end of the string starting at `at`.
+fn json_string_end(bytes: &[u8], mut at: usize) -> Result<usize> {
+	at += 1;
+	while let Some(byte) = bytes.get(at) {
+		match byte {
+			b'\\' => at += 2,
+			b'"' => return Ok(at + 1),
+			_ => at += 1,
+		}
+	}
+	Err(anyhow!("Unterminated string in package.json"))
+}
+
+/// The end of the value starting at `at`.
+fn json_value_end(bytes: &[u8], at: usize) -> Result<usize> {
+	match bytes.get(at) {
+		Some(b'"') => json_string_end(bytes, at),
+		Some(b'{' | b'[') => {
+			let mut depth = 0;
+			let mut at = at;
+			while let Some(byte) = bytes.get(at) {
+				match byte {
+					b'"' => {
+						at = json_string_end(bytes, at)?;
+						continue;
+					}
+					b'{' | b'[' => depth += 1,
+					b'}' | b']' => {
+						depth -= 1;
+						if depth == 0 {
+							return Ok(at + 1);
+						}
+					}
+					_ => {}
+				}
+				at += 1;
+			}
+			Err(anyhow!("Unterminated value in package.json"))
+		}
+		Some(_) => Ok(bytes[at..]
+			.iter()
+			.position(|b| matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace())
+			.map_or(bytes.len(), |length| at + length)),
+		None => Err(anyhow!("Unexpected end of package.json")),
+	}
+}
+
+/// Reads the object whose `{` is at `open` out of a document already known
+/// to be valid JSON.
+fn json_object(content: &str, open: usize) -> Result<JsonObject> {
+	let bytes = content.as_bytes();
+	let mut members = Vec::new();
+	let mut at = skip_whitespace(bytes, open + 1);
+	if bytes.get(at) == Some(&b'}') {
+		return Ok(JsonObject {
+			open,
+			close: at,
+			members,
+		});
+	}
+	loop {
+		let key_end = json_string_end(bytes, at)?;
+		let key = serde_json::from_str(&content[at..key_end])?;
+		let colon = skip_whitespace(bytes, key_end);
+		let value_start = skip_whitespace(bytes, colon + 1);
+		let end = json_value_end(bytes, value_start)?;
+		members.push(JsonMember {
+			key,
+			start: at,
+			value_start,
+			end,
+		});
+		at = skip_whitespace(bytes, end);
+		match bytes.get(at) {
+			Some(b',') => at = skip_whitespace(bytes, at + 1),
+			Some(b'}') => {
+				return Ok(JsonObject {
+					open,
+					close: at,
+					members,
+				})
+			}
+			_ => return Err(anyhow!("Invalid package.json")),
+		}
+	}
+}
+
+fn npm_root(content: &str) -> Result<JsonObject> {
+	json_object(content, skip_whitespace(content.as_bytes(), 0))
+}
+
+/// The section named `name`, if it is an object.
+fn npm_section(content: &str, name: &str) -> Result<Option<JsonObject>> {
+	let root = npm_root(content)?;
+	match root.members.iter().find(|member| member.key == name) {
+		Some(member) if content.as_bytes()[member.value_start] == b'{' => {
+			json_object(content, member.value_start).map(Some)
+		}
+		_ => Ok(None),
+	}
+}
+
+/// Adds `member` to an object nested `depth` levels deep, laid out like the
+/// members already there. It goes before the first greater key when `sorted`,
+/// last otherwise.
+fn insert_json_member(
+	content: &mut String,
+	object: &JsonObject,
+	key: &str,
+	member: &str,
+	depth: usize,
+	indent: &str,
+	sorted: bool,
+) {
+	let Some(first) = object.members.first() else {
+		let line_ending = json_line_ending(content);
+		let text = format!(
+			"{{{line_ending}{}{member}{line_ending}{}}}",
+			indent.repeat(depth + 1),
+			indent.repeat(depth)
+		);
+		content.replace_range(object.open..=object.close, &text);
+		return;
+	};
+
+	let separator = content[object.open + 1..first.start].to_string();
+	let next = sorted
+		.then(|| object.members.iter().find(|m| m.key.as_str() > key))
+		.flatten();
+	match next {
+		Some(next) => content.insert_str(next.start, &format!("{member},{separator}")),
+		None => {
+			let last = object.members.last().unwrap_or(first);
+			content.insert_str(last.end, &format!(",{separator}{member}"));
+		}
+	}
+}
+
+/// Removes the member at `index` along with the comma separating it.
+fn remove_json_member(content: &mut String, object: &JsonObject, index: usize) {
+	let members = &object.members;
+	let range = match index {
+		_ if members.len() == 1 => object.open + 1..object.close,
+		0 => members[0].start..members[1].start,
+		index => members[index - 1].end..members[index].end,
+	};
+	content.replace_range(range, "");
+}
+
+/// Keeps the range operator of `requirement`, e.g. `^` or `~`, in front of
+/// `version`.
+fn with_operator(requirement: &str, version: &str) -> String {
+	let operator: String = requirement
+		.chars()
+		.take_while(|c| matches!(c, '^' | '~' | '>' | '=' | '<'))
+		.collect();
+	format!("{}{}", operator, version)
+}
+
+/// Edits package.json in place, so everything but the edited entry keeps
+/// its order and formatting.
+fn edit_npm(content: &str, edit: &Edit) -> Result<String> {
+	let package: Map<String, Value> =
+		serde_json::from_str(content).context("Invalid package.json")?;
+	let mut updated = content.to_string();
+	match edit {
+		Edit::Add {
+			name,
+			version,
+			kind,
+		} => {
+			let section_name = NPM_SECTIONS
+				.iter()
+				.find(|(_, section_kind)| section_kind == kind)
+				.map(|(name, _)| *name)
+				.ok_or_else(|| anyhow!("npm has no {:?} dependencies", kind))?;
+			if package.get(section_name).is_some_and(|s| !s.is_object()) {
+				return Err(anyhow!("{} isn't an object", section_name));
+			}
+			let requirement = match package
+				.get(section_name)
+				.and_then(|section| section.get(name))
+				.and_then(Value::as_str)
+			{
+				Some(existing) => with_operator(existing, version),
+				None => format!("^{}", version),
+			};
+			let requirement = serde_json::to_string(&requirement)?;
+			let member = format!("{}: {}", serde_json::to_string(name)?, requirement);
+			let indent = json_indent(content);
+
+			match npm_section(&updated, section_name)? {
+				Some(section) => match section.position(name) {
+					Some(index) => {
+						let existing = &section.members[index];
+						updated.replace_range(
+							existing.value_start..existing.end,
+							&requirement,
+						);
+					}
+					// npm keeps the sections sorted; a hand-ordered one stays as is
+					None => {
+						let sorted = section.is_sorted();
+						insert_json_member(
+							&mut updated,
+							&section,
+							name,
+							&member,
+							1,
+							&indent,
+							sorted,
+						)
+					}
+				},
+				None => {
+					let line_ending = json_line_ending(content);
+					let section = format!(
+						"{}: {{{line_ending}{indent}{indent}{member}{line_ending}{indent}}}",
+						serde_json::to_string(section_name)?
+					);
+					let root = npm_root(&updated)?;
+					insert_json_member(
+						&mut updated,
+						&root,
+						section_name,
+						&section,
+						0,
+						&indent,
+						false,
+					);
+				}
+			}
+		}
+		Edit::Remove { name, kind } => {
+			let mut removed = false;
+			for (section_name, section_kind) in NPM_SECTIONS {
+				if kind.is_some_and(|kind| kind != section_kind) {
+					continue;
+				}
+				if let Some(section) = npm_section(&updated, section_name)? {
+					if let Some(index) = section.position(name) {
+						remove_json_member(&mut updated, &section, index);
+						removed = true;
+					}
+				}
+			}
+			if !removed {
+				return Err(anyhow!("{} isn't a dependency", name));
+			}
+		}
+		Edit::Upgrade { name, version } => {
+			let mut upgraded = false;
+			for (section_name, _) in NPM_SECTIONS {
+				let Some(current) = package
+					.get(section_name)
+					.and_then(|section| section.get(name))
+				else {
+					continue;
+				};
+				let current = current.as_str().unwrap_or_default();
+				if current.contains(':') || current.contains('/') {
+					return Err(anyhow!("{} isn't installed from the registry", name));
+				}
+				let Some(section) = npm_section(&updated, section_name)? else {
+					continue;
+				};
+				if let Some(index) = section.position(name) {
+					let member = &section.members[index];
+					updated.replace_range(
+						member.value_start..member.end,
+						&serde_json::to_string(&with_operator(current, version))?,
+					);
+					upgraded = true;
+				}
+			}
+			if !upgraded {
+				return Err(anyhow!("{} isn't a dependency", name));
+			}
+		}
+	}
+	Ok(updated)
+}
+
+/// Adds `value` to a TOML array, laid out like its last element.
+fn push_like_last(array: &mut Array, value: &str) {
+	let prefix = array
+		.iter()
+		.last()
+		.and_then(|last| last.decor().prefix())
+		.and_then(|prefix| prefix.as_str())
+		.map(str::to_string);
+	array.push(value);
+	if let (Some(prefix), Some(pushed)) = (prefix, array.iter_mut().last()) {
+		pushed.decor_mut().set_prefix(prefix);
+	}
+}
+
+/// Mutable PEP 508 arrays of a pyproject.toml.
+fn python_arrays_mut(document: &mut DocumentMut) -> Vec<(DependencyKind, &mut Array)> {
+	let mut arrays = Vec::new();
+	let root = document.as_table_mut();
+	for (key, item) in root.iter_mut() {
+		match key.get() {
+			"project" => {
+				let Some(project) = item.as_table_like_mut() else {
+					continue;
+				};
+				for (key, item) in project.iter_mut() {
+					match key.get() {
+						"dependencies" => {
+							arrays.extend(
+								item.as_array_mut()
+									.map(|array| (DependencyKind::Normal, array)),
+							);
+						}
+						"optional-dependencies" => {
+							if let Some(groups) = item.as_table_like_mut() {
+								for (_, group) in groups.iter_mut() {
+									arrays.extend(
+										group.as_array_mut().map(|array| {
+											(DependencyKind::Optional, array)
+										}),
+									);
+								}
+							}
+						}
+						_ => {}
+					}
+				}
+			}
+			"dependency-groups" => {
+				if let Some(groups) = item.as_table_like_mut() {
+					for (_, group) in groups.iter_mut() {
+						arrays.extend(
+							group
+								.as_array_mut()
+								.map(|array| (DependencyKind::Dev, array)),
+						);
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	arrays
+}
+
+fn edit_python(content: &str, edit: &Edit) -> Result<String> {
+	let mut document = parse_toml(content)?;
+	let matches = |requirement: &str, name: &str| {
+		normalize_python_name(split_pep508(requirement).0) == normalize_python_name(name)
+	};
+	let uses_poetry = !poetry_paths(&document).is_empty()
+		&& document
+			.get("project")
+			.and_then(|project| project.get("dependencies"))
+			.is_none();
+	match edit {
+		Edit::Add {
+			name,
+			version,
+			kind,
+		} if uses_poetry => {
+			let path: &[&str] = match kind {
+				DependencyKind::Normal => &["tool", "poetry", "dependencies"],
+				_ => &["tool", "poetry", "group", "dev", "dependencies"],
+			};
+			let mut table: &mut dyn TableLike = document.as_table_mut();
+			for key in path {
+				let item = table.entry(key).or_insert_with(|| {
+					let mut table = Table::new();
+					table.set_implicit(true);
+					Item::Table(table)
+				});
+				table = item
+					.as_table_like_mut()
+					.ok_or_else(|| anyhow!("{} isn't a table", key))?;
+			}
+			match poetry_key(table, name).and_then(|key| table.get_mut(&key)) {
+				Some(item) => set_version(item, &format!("^{}", version))?,
+				None => {
+					table.insert(name, toml_edit::value(format!("^{}", version)));
+				}
+			}
+		}
+		Edit::Add {
+			name,
+			version,
+			kind,
+		} => {
+			let project = document
+				.entry("project")
+				.or_insert_with(|| Item::Table(Table::new()))
+				.as_table_like_mut()
+				.ok_or_else(|| anyhow!("project isn't a table"))?;
+			let array = match kind {
+				DependencyKind::Normal => project.entry("dependencies"),
+				group => {
+					let group = if *group == DependencyKind::Optional {
+						"optional"
+					} else {
+						"dev"
+					};
+					let groups = project
+						.entry("optional-dependencies")
+						.or_insert_with(|| Item::Table(Table::new()))
+						.as_table_like_mut()
+						.ok_or_else(|| anyhow!("optional-dependencies isn't a table"))?;
+					groups.entry(group)
+				}
+			}
+			.or_insert_with(|| toml_edit::value(Array::new()))
+			.as_array_mut()
+			.ok_or_else(|| anyhow!("Dependencies aren't an array"))?;
+			let requirement = format!("{}>={}", name, version);
+			let existing = array
+				.iter()
+				.position(|value| value.as_str().is_some_and(|r| matches(r, name)));
+			match existing {
+				Some(index) => {
+					let decor = array.get(index).map(|value| value.decor().clone());
+					array.replace(index, requirement.as_str());
+					if let (Some(decor), Some(value)) = (decor, array.get_mut(index)) {
+						*value.decor_mut() = decor;
+					}
+				}
+				None => push_like_last(array, &requirement),
+			}
+		}
+		Edit::Remove { name, kind } => {
+			let mut removed = false;
+			for (array_kind, array) in python_arrays_mut(&mut document) {
+				if kind.is_some_and(|kind| kind != array_kind) {
+					continue;
+				}
+				let before = array.len();
+				array.retain(|value| !value.as_str().is_some_and(|r| matches(r, name)));
+				removed |= array.len() < before;
+			}
+			for (path, table_kind) in poetry_paths(&document) {
+				if kind.is_some_and(|kind| kind != table_kind) {
+					continue;
+				}
+				let Some(table) = table_at_mut(&mut document, &path) else {
+					continue;
+				};
+				if let Some(key) = poetry_key(table, name) {
+					removed |= table.remove(&key).is_some();
+				}
+			}
+			if !removed {
+				return Err(anyhow!("{} isn't a dependency", name));
+			}
+		}
+		Edit::Upgrade { name, version } => {
+			let mut upgraded = false;
+			for (_, array) in python_arrays_mut(&mut document) {
+				for value in array.iter_mut() {
+					let Some(requirement) = value.as_str().filter(|r| matches(r, name))
+					else {
+						continue;
+					};
+					let (package, rest) = split_pep508(requirement);
+					if rest.contains(" @ ") {
+						return Err(anyhow!("{} isn't installed from PyPI", name));
+					}
+					// Extras and environment markers are kept
+					let extras = rest
+						.trim_start()
+						.strip_prefix('[')
+						.and_then(|rest| rest.split_once(']'))
+						.map(|(extras, _)| format!("[{}]", extras))
+						.unwrap_or_default();
+					let markers = rest
+						.split_once(';')
+						.map(|(_, markers)| format!("; {}", markers.trim()))
+						.unwrap_or_default();
+					let decor = value.decor().clone();
+					*value =
+						format!("{}{}>={}{}", package, extras, version, markers).into();
+					*value.decor_mut() = decor;
+					upgraded = true;
+				}
+			}
+			for (path, _) in poetry_paths(&document) {
+				let Some(table) = table_at_mut(&mut document, &path) else {
+					continue;
+				};
+				if let Some(item) =
+					poetry_key(table, name).and_then(|key| table.get_mut(&key))
+				{
+					let current = item
+						.as_str()
+						.or_else(|| item.get("version").and_then(Item::as_str))
+						.unwrap_or("^");
+					set_version(item, &with_operator(current, version))?;
+					upgraded = true;
+				}
+			}
+			if !upgraded {
+				return Err(anyhow!("{} isn't a dependency", name));
+			}
+		}
+	}
+	Ok(document.to_string())
+}
+
+enum Edit {
+	Add {
+		name: String,
+		version: String,
+		kind: DependencyKind,
+	},
+	Remove {
+		name: String,
+		kind: Option<DependencyKind>,
+	},
+	Upgrade {
+		name: String,
+		version: String,
+	},
+}
+
+impl Edit {
+	fn name(&self) -> &str {
+		match self {
+			Edit::Add { name, .. }
+			| Edit::Remove { name, .. }
+			| Edit::Upgrade { name, .. } => name,
+		}
+	}
+}
+
+/// `version`, or the latest release when it's missing.
+async fn version_or_latest(
+	cache: &RegistryCache,
+	ecosystem: Ecosystem,
+	name: &str,
+	version: Option<String>,
+) -> Result<String, String> {
+	match version {
+		Some(version) => Ok(version),
+		None => cache
+			.latest(ecosystem, name)
+			.await
+			.ok_or_else(|| format!("Couldn't find the latest version of {}", name)),
+	}
+}
+
+/// Applies `edit` to the session's manifest for `ecosystem`, writing it
+/// unless `dry_run`.
+async fn apply_edit(
+	os_session: OsSession,
+	ecosystem: Ecosystem,
+	edit: Edit,
+	dry_run: bool,
+) -> Result<ManifestEdit, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		let host_path: PathBuf = os_session.host_path().join(ecosystem.manifest());
+		let original = fs::read_to_string(&host_path)
+			.with_context(|| format!("Failed to read {}", ecosystem.manifest()))?;
+		let updated = match ecosystem {
+			Ecosystem::Cargo => edit_cargo(&original, &edit),
+			Ecosystem::Npm => edit_npm(&original, &edit),
+			Ecosystem::Python => edit_python(&original, &edit),
+		}
+		.with_context(|| {
+			format!("Failed to edit {} in {}", edit.name(), ecosystem.manifest())
+		})?;
+		let written = !dry_run && updated != original;
+		if written {
+			fs::write(&host_path, &updated)?;
+		}
+		Ok::<_, anyhow::Error>(ManifestEdit {
+			path: manifest_path(&os_session, ecosystem),
+			original,
+			updated,
+			written,
+		})
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// The dependencies declared in the Cargo.toml, package.json and
+/// pyproject.toml of the session's working directory, with their installed
+/// versions. With `check_latest`, the registries are asked for the newest
+/// releases too.
+#[tauri::command]
+pub async fn list_dependencies(
+	os_session: OsSession,
+	check_latest: Option<bool>,
+	cache: State<'_, Arc<RegistryCache>>,
+) -> Result<Vec<ManifestDependencies>, String> {
+	let mut manifests =
+		tauri::async_runtime::spawn_blocking(move || read_manifests(&os_session))
+			.await
+			.map_err(|e| e.to_string())?;
+	if check_latest.unwrap_or(false) {
+		self::check_latest(cache.inner(), &mut manifests).await;
+	}
+	Ok(manifests)
+}
+
+/// Adds `name` to the manifest, at `version` or the latest release, or
+/// changes its version when it's already there.
+#[tauri::command]
+pub async fn add_dependency(
+	os_session: OsSession,
+	ecosystem: Ecosystem,
+	name: String,
+	version: Option<String>,
+	kind: Option<DependencyKind>,
+	dry_run: Option<bool>,
+	cache: State<'_, Arc<RegistryCache>>,
+) -> Result<ManifestEdit, String> {
+	let version = version_or_latest(&cache, ecosystem, &name, version).await?;
+	let edit = Edit::Add {
+		name,
+		version,
+		kind: kind.unwrap_or_default(),
+	};
+	apply_edit(os_session, ecosystem, edit, dry_run.unwrap_or(false)).await
+}
+
+/// Removes `name` from the manifest's dependency sections, or only those
+/// of `kind`.
+#[tauri::command]
+pub async fn remove_dependency(
+	os_session: OsSession,
+	ecosystem: Ecosystem,
+	name: String,
+	kind: Option<DependencyKind>,
+	dry_run: Option<bool>,
+) -> Result<ManifestEdit, String> {
+	let edit = Edit::Remove { name, kind };
+	apply_edit(os_session, ecosystem, edit, dry_run.unwrap_or(false)).await
+}
+
+/// Moves `name` to `version`, or the latest release, wherever it's
+/// declared, keeping range operators, features, extras and markers.
+#[tauri::command]
+pub async fn upgrade_dependency(
+	os_session: OsSession,
+	ecosystem: Ecosystem,
+	name: String,
+	version: Option<String>,
+	dry_run: Option<bool>,
+	cache: State<'_, Arc<RegistryCache>>,
+) -> Result<ManifestEdit, String> {
+	let version = version_or_latest(&cache, ecosystem, &name, version).await?;
+	let edit = Edit::Upgrade { name, version };
+	apply_edit(os_session, ecosystem, edit, dry_run.unwrap_or(false)).await
+}
@@ -0,0 +1,691 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize, Serializer};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::custom_terminal::CustomTerminalManager;
+use crate::os::OsSession;
+
+const DEFAULT_LINE_COUNT: usize = 2000;
+/// Lines after a rustc header that may hold its `-->` location.
+const MAX_RUSTC_HEADER_DISTANCE: usize = 3;
+
+/// The build tools whose output is understood. Each keeps its own set of
+/// problems, replaced whenever it runs again.
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildTool {
+	Cargo,
+	Tsc,
+	Eslint,
+}
+
+impl BuildTool {
+	/// The tools `command` likely runs, so their old problems can be
+	/// cleared when it prints none.
+	fn run_by(command: &str) -> Vec<Self> {
+		let mut tools = Vec::new();
+		if command.contains("cargo ") || command.contains("rustc ") {
+			tools.push(Self::Cargo);
+		}
+		if command.contains("tsc") || command.contains("vue-tsc") {
+			tools.push(Self::Tsc);
+		}
+		if command.contains("eslint") || command.contains("lint") {
+			tools.push(Self::Eslint);
+		}
+		tools
+	}
+}
+
+/// Zero-based, as in LSP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Position {
+	pub line: u32,
+	pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Range {
+	pub start: Position,
+	pub end: Position,
+}
+
+impl Range {
+	/// From one-based lines and columns as the tools print them, the end
+	/// column exclusive. A missing end makes an empty range at the start.
+	fn from_one_based(line: u32, column: u32, end: Option<(u32, u32)>) -> Self {
+		let position = |line: u32, column: u32| Position {
+			line: line.saturating_sub(1),
+			character: column.saturating_sub(1),
+		};
+		let start = position(line, column);
+		Self {
+			start,
+			end: end.map_or(start, |(line, column)| position(line, column)),
+		}
+	}
+}
+
+/// `DiagnosticSeverity` of LSP, serialized as its number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiagnosticSeverity {
+	Error = 1,
+	Warning = 2,
+	Information = 3,
+	Hint = 4,
+}
+
+impl Serialize for DiagnosticSeverity {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_u8(*self as u8)
+	}
+}
+
+impl DiagnosticSeverity {
+	fn from_level(level: &str) -> Self {
+		match level {
+			"warning" => Self::Warning,
+			"note" | "info" | "failure-note" => Self::Information,
+			"help" => Self::Hint,
+			_ => Self::Error,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+	pub path: String,
+	pub range: Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticRelatedInformation {
+	pub location: Location,
+	pub message: String,
+}
+
+/// A problem in a file, shaped like an LSP `Diagnostic`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+	pub range: Range,
+	pub severity: DiagnosticSeverity,
+	/// E.g. `E0308`, `TS2322` or `no-unused-vars`.
+	pub code: Option<String>,
+	/// `rustc`, `tsc` or `eslint`.
+	pub source: String,
+	pub message: String,
+	pub related_information: Vec<DiagnosticRelatedInformation>,
+}
+
+/// The problems of one file, like LSP's `PublishDiagnosticsParams`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiagnostics {
+	/// Inside the session; relative paths printed by the tools are resolved
+	/// against its working directory.
+	pub path: String,
+	pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Payload of the `problems-updated` event: every problem now known for
+/// the directory.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProblemsUpdated {
+	pub directory: String,
+	pub files: Vec<FileDiagnostics>,
+}
+
+/// A diagnostic with the file it's in, as printed.
+type Found = (BuildTool, String, Diagnostic);
+
+#[derive(Deserialize)]
+struct CargoMessage {
+	reason: String,
+	message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+	message: String,
+	code: Option<RustcCode>,
+	level: String,
+	#[serde(default)]
+	spans: Vec<RustcSpan>,
+	#[serde(default)]
+	children: Vec<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcCode {
+	code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+	file_name: String,
+	line_start: u32,
+	line_end: u32,
+	column_start: u32,
+	column_end: u32,
+	is_primary: bool,
+	label: Option<String>,
+}
+
+impl RustcSpan {
+	/// rustc counts columns in characters, which only differs from LSP's
+	/// UTF-16 units past the Basic Multilingual Plane.
+	fn location(&self) -> Location {
+		Location {
+			path: self.file_name.clone(),
+			range: Range::from_one_based(
+				self.line_start,
+				self.column_start,
+				Some((self.line_end, self.column_end)),
+			),
+		}
+	}
+}
+
+/// A line of `cargo --message-format=json` or `rustc --error-format=json`.
+fn parse_rustc_json(line: &str) -> Option<Found> {
+	let message = match serde_json::from_str::<CargoMessage>(line) {
+		Ok(cargo) if cargo.reason == "compiler-message" => cargo.message?,
+		Ok(_) => return None,
+		Err(_) => serde_json::from_str::<RustcMessage>(line).ok()?,
+	};
+	// Summaries such as "aborting due to 2 previous errors" have no span
+	let primary = message.spans.iter().find(|span| span.is_primary)?;
+
+	let mut text = message.message.clone();
+	let mut related: Vec<DiagnosticRelatedInformation> = message
+		.spans
+		.iter()
+		.filter(|span| !span.is_primary)
+		.filter_map(|span| {
+			Some(DiagnosticRelatedInformation {
+				location: span.location(),
+				message: span.label.clone()?,
+			})
+		})
+		.collect();
+	if let Some(label) = primary.label.as_deref().filter(|label| !label.is_empty()) {
+		text.push_str(&format!("\n{}", label));
+	}
+	for child in &message.children {
+		match child.spans.first() {
+			Some(span) => related.push(DiagnosticRelatedInformation {
+				location: span.location(),
+				message: format!("{}: {}", child.level, child.message),
+			}),
+			None => text.push_str(&format!("\n{}: {}", child.level, child.message)),
+		}
+	}
+	Some((
+		BuildTool::Cargo,
+		primary.file_name.clone(),
+		Diagnostic {
+			range: primary.location().range,
+			severity: DiagnosticSeverity::from_level(&message.level),
+			code: message.code.map(|code| code.code),
+			source: "rustc".to_string(),
+			message: text,
+			related_information: related,
+		},
+	))
+}
+
+/// `error[E0308]: mismatched types` or `warning: unused variable: x`, as
+/// level, code and message.
+fn parse_rustc_header(line: &str) -> Option<(&str, Option<&str>, &str)> {
+	let (head, message) = line.split_once(": ")?;
+	let (level, code) = match head.split_once('[') {
+		Some((level, code)) => (level, Some(code.strip_suffix(']')?)),
+		None => (head, None),
+	};
+	if !matches!(level, "error" | "warning") {
+		return None;
+	}
+	Some((level, code, message))
+}
+
+/// `  --> src/main.rs:3:5` under a rustc header.
+fn parse_rustc_arrow(line: &str) -> Option<(String, u32, u32)> {
+	let location = line.trim_start().strip_prefix("--> ")?;
+	let mut parts = location.rsplitn(3, ':');
+	let column = parts.next()?.trim().parse().ok()?;
+	let line = parts.next()?.parse().ok()?;
+	Some((parts.next()?.to_string(), line, column))
+}
+
+/// `src/a.ts(12,5): error TS2322: ...`, or `src/a.ts:12:5 - error TS2322:
+/// ...` with `--pretty`.
+fn parse_tsc(line: &str) -> Option<Found> {
+	let (path, line_number, column, rest) =
+		if let Some((location, rest)) = line.split_once("): ") {
+			let (path, position) = location.rsplit_once('(')?;
+			let (line_number, column) = position.split_once(',')?;
+			(path, line_number, column, rest)
+		} else {
+			let (location, rest) = line.split_once(" - ")?;
+			let mut parts = location.rsplitn(3, ':');
+			let column = parts.next()?;
+			let line_number = parts.next()?;
+			(parts.next()?, line_number, column, rest)
+		};
+	let (level, rest) = rest.split_once(' ')?;
+	if !matches!(level, "error" | "warning" | "message") {
+		return None;
+	}
+	let (code, message) = rest.split_once(": ")?;
+	if !code.starts_with("TS") {
+		return None;
+	}
+	Some((
+		BuildTool::Tsc,
+		path.trim().to_string(),
+		Diagnostic {
+			range: Range::from_one_based(
+				line_number.trim().parse().ok()?,
+				column.trim().parse().ok()?,
+				None,
+			),
+			severity: DiagnosticSeverity::from_level(level),
+			code: Some(code.to_string()),
+			source: "tsc".to_string(),
+			message: message.to_string(),
+			related_information: Vec::new(),
+		},
+	))
+}
+
+/// `  12:5  error  'x' is defined but never used  no-unused-vars` under the
+/// file's name, in ESLint's default format.
+fn parse_eslint_stylish(line: &str, file: &str) -> Option<Found> {
+	if !line.starts_with(char::is_whitespace) {
+		return None;
+	}
+	let line = line.trim();
+	let (position, rest) = line.split_once(char::is_whitespace)?;
+	let (line_number, column) = position.split_once(':')?;
+	let (level, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+	if !matches!(level, "error" | "warning") {
+		return None;
+	}
+	// The rule is separated from the message by at least two spaces
+	let rest = rest.trim();
+	let (message, rule) = match rest.rsplit_once("  ") {
+		Some((message, rule)) if !rule.contains(' ') => (message.trim(), Some(rule)),
+		_ => (rest, None),
+	};
+	Some((
+		BuildTool::Eslint,
+		file.to_string(),
+		Diagnostic {
+			range: Range::from_one_based(
+				line_number.parse().ok()?,
+				column.parse().ok()?,
+				None,
+			),
+			severity: DiagnosticSeverity::from_level(level),
+			code: rule.map(str::to_string),
+			source: "eslint".to_string(),
+			message: message.to_string(),
+			related_information: Vec::new(),
+		},
+	))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintFile {
+	file_path: String,
+	messages: Vec<EslintMessage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintMessage {
+	rule_id: Option<String>,
+	severity: u8,
+	message: String,
+	line: Option<u32>,
+	column: Option<u32>,
+	end_line: Option<u32>,
+	end_column: Option<u32>,
+}
+
+/// The output of `eslint --format json`.
+fn parse_eslint_json(output: &str) -> Option<Vec<Found>> {
+	let files: Vec<EslintFile> = serde_json::from_str(output.trim()).ok()?;
+	Some(
+		files
+			.into_iter()
+			.flat_map(|file| {
+				let path = file.file_path;
+				file.messages.into_iter().map(move |message| {
+					let end = message.end_line.zip(message.end_column);
+					(
+						BuildTool::Eslint,
+						path.clone(),
+						Diagnostic {
+							range: Range::from_one_based(
+								message.line.unwrap_or(1),
+								message.column.unwrap_or(1),
+								end,
+							),
+							severity: if message.severity >= 2 {
+								DiagnosticSeverity::Error
+							} else {
+								DiagnosticSeverity::Warning
+							},
+							code: message.rule_id,
+							source: "eslint".to_string(),
+							message: message.message,
+							related_information: Vec::new(),
+						},
+					)
+				})
+			})
+			.collect(),
+	)
+}
+
+/// Removes the color and cursor escape sequences terminals print.
+fn strip_ansi(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		if c != '\u{1b}' {
+			result.push(c);
+			continue;
+		}
+		match chars.next() {
+			// CSI: parameters up to a final byte in @..~
+			Some('[') => {
+				for c in chars.by_ref() {
+					if ('@'..='~').contains(&c) {
+						break;
+					}
+				}
+			}
+			// OSC: up to BEL or ESC \
+			Some(']') => {
+				while let Some(c) = chars.next() {
+					if c == '\u{7}' {
+						break;
+					}
+					if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+						chars.next();
+						break;
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	result
+}
+
+/// Every diagnostic in the output of cargo (JSON or human), rustc, tsc and
+/// ESLint (stylish or JSON), which may be mixed, as from `npm run build`.
+pub fn parse_output(output: &str) -> Vec<Found> {
+	let output = strip_ansi(output);
+	if output.trim_start().starts_with('[') {
+		if let Some(found) = parse_eslint_json(&output) {
+			return found;
+		}
+	}
+
+	let mut found: Vec<Found> = Vec::new();
+	let mut rustc_header: Option<(usize, DiagnosticSeverity, Option<String>, String)> =
+		None;
+	let mut last_unindented = String::new();
+	for (index, line) in output.lines().enumerate() {
+		let line = line.trim_end_matches('\r');
+		if line.starts_with('{') {
+			found.extend(parse_rustc_json(line));
+			continue;
+		}
+		if let Some((level, code, message)) = parse_rustc_header(line) {
+			rustc_header = Some((
+				index,
+				DiagnosticSeverity::from_level(level),
+				code.map(str::to_string),
+				message.to_string(),
+			));
+			continue;
+		}
+		if let Some((path, line_number, column)) = parse_rustc_arrow(line) {
+			if let Some((start, severity, code, message)) = rustc_header.take() {
+				if index - start <= MAX_RUSTC_HEADER_DISTANCE {
+					found.push((
+						BuildTool::Cargo,
+						path,
+						Diagnostic {
+							range: Range::from_one_based(line_number, column, None),
+							severity,
+							code,
+							source: "rustc".to_string(),
+							message,
+							related_information: Vec::new(),
+						},
+					));
+				}
+			}
+			continue;
+		}
+		if let Some(diagnostic) = parse_tsc(line) {
+			found.push(diagnostic);
+			continue;
+		}
+		if let Some(diagnostic) = parse_eslint_stylish(line, &last_unindented) {
+			found.push(diagnostic);
+			continue;
+		}
+		// tsc indents the rest of a multi-line message
+		if line.starts_with("  ") && !line.trim().is_empty() {
+			if let Some((BuildTool::Tsc, _, diagnostic)) = found.last_mut() {
+				diagnostic.message.push('\n');
+				diagnostic.message.push_str(line.trim());
+				continue;
+			}
+		}
+		if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+			last_unindented = line.trim().to_string();
+		}
+	}
+	found
+}
+
+/// Groups diagnostics by file, dropping duplicates such as those cargo
+/// prints once per target.
+fn group(session: &OsSession, found: Vec<(String, Diagnostic)>) -> Vec<FileDiagnostics> {
+	let mut files: BTreeMap<String, Vec<Diagnostic>> = BTreeMap::new();
+	for (path, mut diagnostic) in found {
+		for related in &mut diagnostic.related_information {
+			related.location.path = session.resolve_path(&related.location.path);
+		}
+		let diagnostics = files.entry(session.resolve_path(&path)).or_default();
+		if !diagnostics.contains(&diagnostic) {
+			diagnostics.push(diagnostic);
+		}
+	}
+	files
+		.into_iter()
+		.map(|(path, mut diagnostics)| {
+			diagnostics.sort_by_key(|diagnostic| (diagnostic.range, diagnostic.severity));
+			FileDiagnostics { path, diagnostics }
+		})
+		.collect()
+}
+
+/// The diagnostics of each build tool, with the file each is in.
+type ToolProblems = BTreeMap<BuildTool, Vec<(String, Diagnostic)>>;
+
+/// The Problems feed: diagnostics of the latest run of each build tool, by
+/// working directory.
+#[derive(Default)]
+pub struct ProblemsStore {
+	problems: Mutex<HashMap<String, ToolProblems>>,
+}
+
+impl ProblemsStore {
+	pub fn new() -> Arc<Self> {
+		Arc::new(Self::default())
+	}
+
+	/// Every problem of the session, grouped by file.
+	pub fn get(&self, session: &OsSession) -> Vec<FileDiagnostics> {
+		let problems = self.problems.lock().unwrap();
+		let found = problems
+			.get(session.get_working_directory())
+			.map(|tools| tools.values().flatten().cloned().collect())
+			.unwrap_or_default();
+		group(session, found)
+	}
+
+	/// Replaces the problems of the tools in `found`, and clears those of
+	/// `ran` that found none.
+	fn replace(&self, session: &OsSession, ran: &[BuildTool], found: Vec<Found>) {
+		let mut problems = self.problems.lock().unwrap();
+		let tools = problems
+			.entry(session.get_working_directory().to_string())
+			.or_default();
+		for tool in ran {
+			tools.remove(tool);
+		}
+		let mut replaced = Vec::new();
+		for (tool, path, diagnostic) in found {
+			if !replaced.contains(&tool) {
+				tools.remove(&tool);
+				replaced.push(tool);
+			}
+			tools.entry(tool).or_default().push((path, diagnostic));
+		}
+	}
+
+	fn clear(&self, session: &OsSession, tool: Option<BuildTool>) {
+		let mut problems = self.problems.lock().unwrap();
+		let directory = session.get_working_directory();
+		match tool {
+			Some(tool) => {
+				if let Some(tools) = problems.get_mut(directory) {
+					tools.remove(&tool);
+				}
+			}
+			None => {
+				problems.remove(directory);
+			}
+		}
+	}
+}
+
+fn emit_updated(app_handle: &AppHandle, store: &ProblemsStore, session: &OsSession) {
+	let _ = app_handle.emit(
+		"problems-updated",
+		ProblemsUpdated {
+			directory: session.get_working_directory().to_string(),
+			files: store.get(session),
+		},
+	);
+}
+
+/// Parses `output` and merges it into the Problems feed, clearing the
+/// previous problems of the tools in `ran`. Returns what was found.
+pub fn record(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	ran: &[BuildTool],
+	output: &str,
+) -> Vec<FileDiagnostics> {
+	let found = parse_output(output);
+	if found.is_empty() && ran.is_empty() {
+		return Vec::new();
+	}
+	let parsed = group(
+		session,
+		found
+			.iter()
+			.map(|(_, path, diagnostic)| (path.clone(), diagnostic.clone()))
+			.collect(),
+	);
+	let store = app_handle.state::<Arc<ProblemsStore>>();
+	store.replace(session, ran, found);
+	emit_updated(app_handle, &store, session);
+	parsed
+}
+
+/// Records the problems printed by a finished task running `command`.
+pub fn record_task_output(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	command: &str,
+	output: &str,
+) {
+	record(app_handle, session, &BuildTool::run_by(command), output);
+}
+
+/// Parses build output into LSP-shaped diagnostics and merges them into the
+/// Problems feed. With `tool` set, that tool's old problems are cleared
+/// even when the output has none.
+#[tauri::command]
+pub async fn parse_build_output(
+	os_session: OsSession,
+	output: String,
+	tool: Option<BuildTool>,
+	app_handle: AppHandle,
+) -> Result<Vec<FileDiagnostics>, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		record(&app_handle, &os_session, &Vec::from_iter(tool), &output)
+	})
+	.await
+	.map_err(|e| e.to_string())
+}
+
+/// Reads the last `line_count` lines of a terminal and merges the build
+/// problems printed in them into the Problems feed.
+#[tauri::command]
+pub async fn collect_terminal_problems(
+	os_session: OsSession,
+	id: String,
+	line_count: Option<usize>,
+	manager: State<'_, Arc<CustomTerminalManager>>,
+	app_handle: AppHandle,
+) -> Result<Vec<FileDiagnostics>, String> {
+	let lines = manager
+		.last_lines(&id, line_count.unwrap_or(DEFAULT_LINE_COUNT))
+		.map_err(|e| e.to_string())?;
+	Ok(record(&app_handle, &os_session, &[], &lines.join("\n")))
+}
+
+/// Every problem known for the session, from all build tools.
+#[tauri::command]
+pub async fn get_problems(
+	os_session: OsSession,
+	store: State<'_, Arc<ProblemsStore>>,
+) -> Result<Vec<FileDiagnostics>, String> {
+	Ok(store.get(&os_session))
+}
+
+/// Forgets the problems of `tool`, or of every tool.
+#[tauri::command]
+pub async fn clear_problems(
+	os_session: OsSession,
+	tool: Option<BuildTool>,
+	store: State<'_, Arc<ProblemsStore>>,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	store.clear(&os_session, tool);
+	emit_updated(&app_handle, &store, &os_session);
+	Ok(())
+}
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::ai_history::{self, HistoryMessage, NewAiExchange};
+use crate::backend_client;
+use crate::canvas_manager::CanvasManager;
+use crate::git::run_git;
+use crate::os::OsSession;
+
+/// Line the backend puts between the summary and the suggested commit message.
+const COMMIT_MESSAGE_MARKER: &str = "Commit message:";
+
+/// Provider and key to summarize with, as for `/api/inference`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryModel {
+	pub provider: String,
+	pub model: String,
+	pub api_key: Option<String>,
+	/// Vault key id, which requires being logged in.
+	pub key_id: Option<String>,
+	pub org_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSummary {
+	pub branch: String,
+	pub summary: String,
+	/// `None` when the model didn't suggest one.
+	pub commit_message: Option<String>,
+}
+
+/// Emitted as `diff-summary-{streamId}` for each piece of the answer.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffSummaryDelta {
+	pub delta: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SummarizeDiffRequest<'a> {
+	provider: &'a str,
+	model: &'a str,
+	diff: &'a str,
+	branch: &'a str,
+	api_key: Option<&'a str>,
+	key_id: Option<&'a str>,
+	org_id: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+	#[serde(default)]
+	delta: String,
+	#[serde(default)]
+	done: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+	error: String,
+}
+
+/// Branch and diff of a canvas against the commit it started from. Includes
+/// uncommitted changes to tracked files.
+fn canvas_diff(
+	manager: &CanvasManager,
+	project_root: &OsSession,
+	canvas_id: &str,
+) -> Result<(String, String)> {
+	let canvas = manager
+		.get(project_root, canvas_id)?
+		.ok_or_else(|| anyhow!("Canvas {} not found", canvas_id))?;
+	let base = canvas
+		.base_commit
+		.ok_or_else(|| anyhow!("Canvas {} has no base commit", canvas_id))?;
+	let session = &canvas.os_session;
+	let diff = run_git(
+		session,
+		session.get_working_directory(),
+		&["diff", "--no-color", "--no-ext-diff", &base],
+	)?;
+	Ok((canvas.branch, diff))
+}
+
+/// Splits the model's answer into the summary and the suggested commit
+/// message.
+pub fn split_answer(answer: &str) -> (String, Option<String>) {
+	match answer.split_once(COMMIT_MESSAGE_MARKER) {
+		Some((summary, message)) => {
+			let message = message.trim().trim_matches('`').trim();
+			(
+				summary.trim().to_string(),
+				(!message.is_empty()).then(|| message.to_string()),
+			)
+		}
+		None => (answer.trim().to_string(), None),
+	}
+}
+
+/// Sends `diff` to the backend for a summary and returns the model's answer,
+/// emitting it as `diff-summary-{streamId}` events as it arrives.
+pub async fn summarize(
+	app_handle: &AppHandle,
+	branch: &str,
+	diff: &str,
+	model: &SummaryModel,
+	stream_id: Option<&str>,
+) -> Result<String> {
+	let body = SummarizeDiffRequest {
+		provider: &model.provider,
+		model: &model.model,
+		diff,
+		branch,
+		api_key: model.api_key.as_deref(),
+		key_id: model.key_id.as_deref(),
+		org_id: model.org_id.as_deref(),
+	};
+	// Raw provider keys work without logging in
+	let mut response = backend_client::optional_auth_request(
+		app_handle,
+		Method::POST,
+		"/api/inference/summarize-diff",
+	)
+	.json(&body)
+	.send()
+	.await?;
+
+	let status = response.status();
+	if !status.is_success() {
+		return Err(match response.json::<ErrorResponse>().await {
+			Ok(body) => anyhow!("Diff summary failed ({}): {}", status, body.error),
+			Err(_) => anyhow!("Diff summary failed ({})", status),
+		});
+	}
+
+	let event = stream_id.map(|id| format!("diff-summary-{}", id));
+	let mut answer = String::new();
+	let mut buffer = Vec::new();
+	while let Some(bytes) = response.chunk().await? {
+		buffer.extend_from_slice(&bytes);
+		// Server-sent events end with a blank line
+		while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+			let event_bytes: Vec<u8> = buffer.drain(..end + 2).collect();
+			let text = String::from_utf8_lossy(&event_bytes);
+			let Some(data) = text.trim().strip_prefix("data:") else {
+				continue;
+			};
+			let Ok(chunk) = serde_json::from_str::<StreamChunk>(data.trim()) else {
+				continue;
+			};
+			if !chunk.delta.is_empty() {
+				answer.push_str(&chunk.delta);
+				if let Some(event) = &event {
+					let _ =
+						app_handle.emit(event, DiffSummaryDelta { delta: chunk.delta });
+				}
+			}
+			if chunk.done {
+				return Ok(answer);
+			}
+		}
+	}
+	Ok(answer)
+}
+
+/// Summarizes a canvas's changes with the given model and suggests a commit
+/// message. With `stream_id` set, the answer is also emitted as it arrives.
+#[tauri::command]
+pub async fn summarize_canvas_diff(
+	app_handle: AppHandle,
+	project_root: OsSession,
+	canvas_id: String,
+	model: SummaryModel,
+	stream_id: Option<String>,
+	manager: State<'_, Arc<CanvasManager>>,
+) -> Result<DiffSummary, String> {
+	let manager = manager.inner().clone();
+	let (branch, diff) = tauri::async_runtime::spawn_blocking(move || {
+		canvas_diff(&manager, &project_root, &canvas_id)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())?;
+
+	if diff.trim().is_empty() {
+		return Err("The canvas has no changes to summarize".to_string());
+	}
+
+	let answer = summarize(&app_handle, &branch, &diff, &model, stream_id.as_deref())
+		.await
+		.map_err(|e| e.to_string())?;
+	ai_history::record(
+		&app_handle,
+		NewAiExchange {
+			source: "diff_summary".to_string(),
+			provider: model.provider,
+			model: model.model,
+			messages: vec![HistoryMessage {
+				role: "user".to_string(),
+				content: diff,
+			}],
+			response: answer.clone(),
+		},
+	);
+	let (summary, commit_message) = split_answer(&answer);
+	Ok(DiffSummary {
+		branch,
+		summary,
+		commit_message,
+	})
+}
@@ -0,0 +1,88 @@
+use crate::document_manager::{DocumentManager, DocumentSnapshot, DocumentState};
+use crate::text_encoding::{self, EncodingInfo, LineEnding, TextEncoding};
+use std::sync::Arc;
+use tauri::State;
+
+#[tauri::command]
+pub async fn open_document(
+	path: String,
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<DocumentSnapshot, String> {
+	manager.open_document(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_document(
+	path: String,
+	text: String,
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<DocumentState, String> {
+	manager
+		.update_document(&path, text)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn save_document(
+	path: String,
+	force: Option<bool>,
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<DocumentState, String> {
+	manager
+		.save_document(&path, force.unwrap_or(false))
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn revert_document(
+	path: String,
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<DocumentSnapshot, String> {
+	manager.revert_document(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_document_format(
+	path: String,
+	encoding: Option<TextEncoding>,
+	bom: Option<bool>,
+	line_ending: Option<LineEnding>,
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<DocumentState, String> {
+	manager
+		.set_document_format(&path, encoding, bom, line_ending)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reopen_document_with_encoding(
+	path: String,
+	encoding: TextEncoding,
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<DocumentSnapshot, String> {
+	manager
+		.reopen_document(&path, encoding)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn close_document(
+	path: String,
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<(), String> {
+	manager.close_document(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_documents(
+	manager: State<'_, Arc<DocumentManager>>,
+) -> Result<Vec<DocumentState>, String> {
+	Ok(manager.list_documents())
+}
+
+/// Reports the encoding, BOM and line endings of any file, open or not.
+#[tauri::command]
+pub async fn detect_file_encoding(path: String) -> Result<EncodingInfo, String> {
+	let bytes = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+	Ok(text_encoding::inspect(&bytes))
+}
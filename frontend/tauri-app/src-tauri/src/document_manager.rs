@@ -0,0 +1,614 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Emitter, Url};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::editorconfig;
+use crate::file_watcher::{FileChange, FileChangeKind, FileWatcher};
+use crate::text_encoding::{self, FileFormat, LineEnding, TextEncoding};
+
+/// Metadata about an open document, without its text.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentState {
+	pub path: String,
+	pub uri: String,
+	pub language_id: String,
+	pub version: i32,
+	/// The format the document will be saved in.
+	#[serde(flatten)]
+	pub format: FileFormat,
+	/// The editor text or format differs from what was last loaded or saved.
+	pub dirty: bool,
+	/// The file changed on disk while the document had unsaved edits.
+	pub conflict: bool,
+	/// The file no longer exists on disk.
+	pub deleted: bool,
+}
+
+/// An open document including its text, always with `\n` line endings.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSnapshot {
+	#[serde(flatten)]
+	pub state: DocumentState,
+	pub text: String,
+}
+
+/// Payload of the `document-external-change` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalChange {
+	#[serde(flatten)]
+	pub state: DocumentState,
+	/// The editor text was replaced with the new disk contents; only happens
+	/// for documents without unsaved edits.
+	pub reloaded: bool,
+}
+
+/// Receives the lifecycle of open documents, mirroring the LSP text document
+/// notifications (full text sync).
+pub trait DocumentListener: Send + Sync {
+	fn did_open(&self, state: &DocumentState, text: &str);
+	fn did_change(&self, state: &DocumentState, text: &str);
+	fn did_save(&self, state: &DocumentState);
+	fn did_close(&self, state: &DocumentState);
+}
+
+/// Forwards document lifecycle as `lsp-notification` events carrying the
+/// LSP method and params, so a language client hosted by the frontend stays
+/// in sync without the editor sending each notification itself.
+pub struct LspNotificationForwarder {
+	app_handle: AppHandle,
+}
+
+impl LspNotificationForwarder {
+	pub fn new(app_handle: AppHandle) -> Self {
+		Self { app_handle }
+	}
+
+	fn emit(&self, method: &str, params: serde_json::Value) {
+		let _ = self.app_handle.emit(
+			"lsp-notification",
+			json!({ "method": method, "params": params }),
+		);
+	}
+}
+
+impl DocumentListener for LspNotificationForwarder {
+	fn did_open(&self, state: &DocumentState, text: &str) {
+		self.emit(
+			"textDocument/didOpen",
+			json!({
+				"textDocument": {
+					"uri": state.uri,
+					"languageId": state.language_id,
+					"version": state.version,
+					"text": text,
+				}
+			}),
+		);
+	}
+
+	fn did_change(&self, state: &DocumentState, text: &str) {
+		self.emit(
+			"textDocument/didChange",
+			json!({
+				"textDocument": { "uri": state.uri, "version": state.version },
+				"contentChanges": [{ "text": text }],
+			}),
+		);
+	}
+
+	fn did_save(&self, state: &DocumentState) {
+		self.emit(
+			"textDocument/didSave",
+			json!({ "textDocument": { "uri": state.uri } }),
+		);
+	}
+
+	fn did_close(&self, state: &DocumentState) {
+		self.emit(
+			"textDocument/didClose",
+			json!({ "textDocument": { "uri": state.uri } }),
+		);
+	}
+}
+
+struct Document {
+	path: PathBuf,
+	uri: String,
+	language_id: String,
+	version: i32,
+	format: FileFormat,
+	/// Format as last loaded or saved.
+	saved_format: FileFormat,
+	/// The format was picked by the user, so `.editorconfig` doesn't
+	/// change it on save.
+	format_chosen: bool,
+	/// Editor contents, `\n` line endings.
+	text: String,
+	/// Contents as last loaded or saved, `\n` line endings.
+	saved_text: String,
+	/// Hash of the raw bytes last seen on disk, `None` if the file is gone.
+	disk_hash: Option<u64>,
+	conflict: bool,
+}
+
+impl Document {
+	fn state(&self) -> DocumentState {
+		DocumentState {
+			path: self.path.to_string_lossy().to_string(),
+			uri: self.uri.clone(),
+			language_id: self.language_id.clone(),
+			version: self.version,
+			format: self.format,
+			dirty: self.text != self.saved_text
+				|| self.format != self.saved_format
+				|| self.disk_hash.is_none(),
+			conflict: self.conflict,
+			deleted: self.disk_hash.is_none(),
+		}
+	}
+
+	fn snapshot(&self) -> DocumentSnapshot {
+		DocumentSnapshot {
+			state: self.state(),
+			text: self.text.clone(),
+		}
+	}
+
+	/// Replaces editor and saved text with freshly read disk contents.
+	fn load(&mut self, disk: DiskContents) {
+		self.format = disk.format;
+		self.saved_format = disk.format;
+		self.text = disk.text.clone();
+		self.saved_text = disk.text;
+		self.disk_hash = Some(disk.hash);
+		self.conflict = false;
+		self.version += 1;
+	}
+}
+
+struct DiskContents {
+	text: String,
+	format: FileFormat,
+	hash: u64,
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	bytes.hash(&mut hasher);
+	hasher.finish()
+}
+
+/// Reads and decodes a file, detecting its encoding unless one is given.
+fn read_disk(path: &Path, encoding: Option<TextEncoding>) -> Result<DiskContents> {
+	let bytes =
+		fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+	let hash = hash_bytes(&bytes);
+	let (text, format) = text_encoding::decode(&bytes, encoding)
+		.with_context(|| format!("Failed to decode {}", path.display()))?;
+
+	Ok(DiskContents { text, format, hash })
+}
+
+/// Reads a file that is already open, keeping the encoding it was opened
+/// with unless the new contents are no longer valid in it.
+fn reread_disk(path: &Path, encoding: TextEncoding) -> Result<DiskContents> {
+	read_disk(path, Some(encoding)).or_else(|_| read_disk(path, None))
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it over the
+/// original, so a crash mid-save never leaves a truncated file. The original
+/// permissions are kept, and symlinks are written through to their target.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+	let target = match fs::symlink_metadata(path) {
+		Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+		_ => path.to_path_buf(),
+	};
+	let dir = target
+		.parent()
+		.ok_or_else(|| anyhow!("{} has no parent directory", target.display()))?;
+	let file_name = target
+		.file_name()
+		.ok_or_else(|| anyhow!("{} is not a file path", target.display()))?
+		.to_string_lossy();
+	let temp_path = dir.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+
+	let result = (|| -> Result<()> {
+		let mut file = File::create(&temp_path)?;
+		file.write_all(bytes)?;
+		file.sync_all()?;
+		if let Ok(meta) = fs::metadata(&target) {
+			fs::set_permissions(&temp_path, meta.permissions())?;
+		}
+		fs::rename(&temp_path, &target)?;
+		Ok(())
+	})();
+
+	if result.is_err() {
+		let _ = fs::remove_file(&temp_path);
+	}
+	result.with_context(|| format!("Failed to save {}", target.display()))
+}
+
+fn language_id(path: &Path) -> String {
+	let extension = path
+		.extension()
+		.and_then(|e| e.to_str())
+		.unwrap_or_default()
+		.to_lowercase();
+	let id = match extension.as_str() {
+		"rs" => "rust",
+		"ts" | "mts" | "cts" => "typescript",
+		"tsx" => "typescriptreact",
+		"js" | "mjs" | "cjs" => "javascript",
+		"jsx" => "javascriptreact",
+		"py" => "python",
+		"go" => "go",
+		"java" => "java",
+		"c" | "h" => "c",
+		"cpp" | "cc" | "cxx" | "hpp" | "hh" => "cpp",
+		"cs" => "csharp",
+		"rb" => "ruby",
+		"php" => "php",
+		"swift" => "swift",
+		"kt" | "kts" => "kotlin",
+		"json" => "json",
+		"toml" => "toml",
+		"yaml" | "yml" => "yaml",
+		"md" | "markdown" => "markdown",
+		"html" | "htm" => "html",
+		"css" => "css",
+		"scss" => "scss",
+		"sh" | "bash" | "zsh" => "shellscript",
+		"sql" => "sql",
+		_ => "plaintext",
+	};
+	id.to_string()
+}
+
+/// Owns the files open in the editor: their text, version and dirty state.
+/// Saves are atomic, and the file watcher reloads clean documents changed on
+/// disk or flags a conflict for documents with unsaved edits.
+pub struct DocumentManager {
+	app_handle: AppHandle,
+	watcher: Arc<FileWatcher>,
+	documents: Mutex<HashMap<PathBuf, Document>>,
+	listeners: Mutex<Vec<Box<dyn DocumentListener>>>,
+}
+
+impl DocumentManager {
+	pub fn new(app_handle: AppHandle, watcher: Arc<FileWatcher>) -> Arc<Self> {
+		let manager = Arc::new(Self {
+			app_handle: app_handle.clone(),
+			watcher,
+			documents: Mutex::new(HashMap::new()),
+			listeners: Mutex::new(Vec::new()),
+		});
+		manager.add_listener(Box::new(LspNotificationForwarder::new(app_handle)));
+		manager.start_watching();
+		manager
+	}
+
+	pub fn add_listener(&self, listener: Box<dyn DocumentListener>) {
+		self.listeners.lock().unwrap().push(listener);
+	}
+
+	fn notify(&self, f: impl Fn(&dyn DocumentListener)) {
+		for listener in self.listeners.lock().unwrap().iter() {
+			f(listener.as_ref());
+		}
+	}
+
+	fn start_watching(self: &Arc<Self>) {
+		let mut changes = self.watcher.subscribe();
+		let manager = Arc::downgrade(self);
+		tauri::async_runtime::spawn(async move {
+			loop {
+				let change = match changes.recv().await {
+					Ok(change) => change,
+					Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => break,
+				};
+				let Some(manager) = manager.upgrade() else {
+					break;
+				};
+				manager.handle_external_change(change);
+			}
+		});
+	}
+
+	fn handle_external_change(&self, change: FileChange) {
+		let mut documents = self.documents.lock().unwrap();
+		let Some(document) = documents.get_mut(&change.path) else {
+			return;
+		};
+
+		let disk = match change.kind {
+			FileChangeKind::Removed if !change.path.exists() => None,
+			_ => match reread_disk(&change.path, document.saved_format.encoding) {
+				Ok(disk) => Some(disk),
+				// Unreadable mid-write or gone; a later event will settle it
+				Err(_) => return,
+			},
+		};
+		// Our own saves, and events that didn't change the contents
+		if disk.as_ref().map(|d| d.hash) == document.disk_hash {
+			return;
+		}
+
+		let mut reloaded = false;
+		match disk {
+			None => document.disk_hash = None,
+			Some(disk) => {
+				if document.text == document.saved_text {
+					document.load(disk);
+					reloaded = true;
+				} else {
+					document.disk_hash = Some(disk.hash);
+					document.conflict = true;
+				}
+			}
+		}
+
+		let state = document.state();
+		if reloaded {
+			let text = document.text.clone();
+			self.notify(|l| l.did_change(&state, &text));
+		}
+		let _ = self.app_handle.emit(
+			"document-external-change",
+			ExternalChange { state, reloaded },
+		);
+	}
+
+	pub fn open_document(&self, path: &str) -> Result<DocumentSnapshot> {
+		let path = PathBuf::from(path);
+		if !path.is_absolute() {
+			return Err(anyhow!(
+				"Document path must be absolute: {}",
+				path.display()
+			));
+		}
+
+		let mut documents = self.documents.lock().unwrap();
+		if let Some(document) = documents.get(&path) {
+			return Ok(document.snapshot());
+		}
+
+		let disk = read_disk(&path, None)?;
+		let uri = Url::from_file_path(&path)
+			.map_err(|_| anyhow!("Invalid document path: {}", path.display()))?
+			.to_string();
+		if let Some(dir) = path.parent() {
+			// Watch the directory: editors that save by renaming replace the
+			// file's inode, which would end a watch on the file itself.
+			self.watcher.watch(dir, false)?;
+		}
+
+		let document = Document {
+			language_id: language_id(&path),
+			path: path.clone(),
+			uri,
+			version: 1,
+			format: disk.format,
+			saved_format: disk.format,
+			text: disk.text.clone(),
+			saved_text: disk.text,
+			disk_hash: Some(disk.hash),
+			conflict: false,
+			format_chosen: false,
+		};
+		let snapshot = document.snapshot();
+		documents.insert(path, document);
+
+		self.notify(|l| l.did_open(&snapshot.state, &snapshot.text));
+		Ok(snapshot)
+	}
+
+	/// Replaces the editor text of an open document, bumping its version.
+	pub fn update_document(&self, path: &str, text: String) -> Result<DocumentState> {
+		let mut documents = self.documents.lock().unwrap();
+		let document = documents
+			.get_mut(Path::new(path))
+			.ok_or_else(|| anyhow!("Document is not open: {}", path))?;
+
+		let text = text.replace("\r\n", "\n");
+		if text == document.text {
+			return Ok(document.state());
+		}
+		document.text = text;
+		document.version += 1;
+
+		let state = document.state();
+		self.notify(|l| l.did_change(&state, &document.text));
+		Ok(state)
+	}
+
+	/// Runs `edit` on the editor text of an open document and applies the
+	/// text it returns, if any, as one update. The document stays locked in
+	/// between so no other update interleaves. Returns `None` if the document
+	/// isn't open.
+	pub fn edit_document(
+		&self,
+		path: &str,
+		edit: impl FnOnce(&str) -> Option<String>,
+	) -> Option<DocumentState> {
+		let mut documents = self.documents.lock().unwrap();
+		let document = documents.get_mut(Path::new(path))?;
+
+		match edit(&document.text) {
+			Some(text) if text != document.text => {
+				document.text = text;
+				document.version += 1;
+				let state = document.state();
+				self.notify(|l| l.did_change(&state, &document.text));
+				Some(state)
+			}
+			_ => Some(document.state()),
+		}
+	}
+
+	/// Writes the document to disk in its encoding and line endings, after
+	/// applying the file's `.editorconfig` properties. Refuses to overwrite
+	/// changes made on disk since it was loaded unless `force`.
+	pub fn save_document(&self, path: &str, force: bool) -> Result<DocumentState> {
+		let mut documents = self.documents.lock().unwrap();
+		let document = documents
+			.get_mut(Path::new(path))
+			.ok_or_else(|| anyhow!("Document is not open: {}", path))?;
+
+		if !force {
+			let current = fs::read(&document.path)
+				.ok()
+				.map(|bytes| hash_bytes(&bytes));
+			if document.conflict || (current.is_some() && current != document.disk_hash) {
+				document.conflict = true;
+				return Err(anyhow!(
+					"{} changed on disk; revert or save with force to overwrite",
+					path
+				));
+			}
+		}
+
+		let config = editorconfig::resolve(&document.path);
+		let mut format = document.format;
+		if !document.format_chosen {
+			if let Some(line_ending) = config.line_ending() {
+				format.line_ending = line_ending;
+			}
+			if let Some((encoding, bom)) = config.encoding() {
+				format.encoding = encoding;
+				format.bom = bom;
+			}
+		}
+		let text = config.apply_on_save(&document.text);
+		let bytes = text_encoding::encode(&text, &format)?;
+		write_atomically(&document.path, &bytes)?;
+		if text != document.text {
+			document.text = text;
+			document.version += 1;
+			let state = document.state();
+			self.notify(|l| l.did_change(&state, &document.text));
+		}
+		document.format = format;
+		document.saved_format = format;
+		document.disk_hash = Some(hash_bytes(&bytes));
+		document.saved_text = document.text.clone();
+		document.conflict = false;
+
+		let state = document.state();
+		self.notify(|l| l.did_save(&state));
+		Ok(state)
+	}
+
+	/// Discards unsaved edits and reloads the document from disk.
+	pub fn revert_document(&self, path: &str) -> Result<DocumentSnapshot> {
+		let mut documents = self.documents.lock().unwrap();
+		let document = documents
+			.get_mut(Path::new(path))
+			.ok_or_else(|| anyhow!("Document is not open: {}", path))?;
+
+		let disk = reread_disk(&document.path, document.saved_format.encoding)?;
+		document.load(disk);
+
+		let snapshot = document.snapshot();
+		self.notify(|l| l.did_change(&snapshot.state, &snapshot.text));
+		Ok(snapshot)
+	}
+
+	/// Changes the encoding, BOM or line ending the document is saved with.
+	/// The text is unchanged; the conversion happens on the next save.
+	pub fn set_document_format(
+		&self,
+		path: &str,
+		encoding: Option<TextEncoding>,
+		bom: Option<bool>,
+		line_ending: Option<LineEnding>,
+	) -> Result<DocumentState> {
+		let mut documents = self.documents.lock().unwrap();
+		let document = documents
+			.get_mut(Path::new(path))
+			.ok_or_else(|| anyhow!("Document is not open: {}", path))?;
+
+		let format = FileFormat {
+			encoding: encoding.unwrap_or(document.format.encoding),
+			bom: bom.unwrap_or(document.format.bom),
+			line_ending: line_ending.unwrap_or(document.format.line_ending),
+		};
+		if format.bom && format.encoding == TextEncoding::Latin1 {
+			return Err(anyhow!("Latin-1 files cannot have a byte order mark"));
+		}
+		// Catch unrepresentable characters now rather than at save time
+		text_encoding::encode(&document.text, &format)?;
+		document.format = format;
+		document.format_chosen = true;
+		Ok(document.state())
+	}
+
+	/// Decodes the file on disk again with `encoding`, for files whose
+	/// encoding was guessed wrong. Unsaved edits are discarded.
+	pub fn reopen_document(
+		&self,
+		path: &str,
+		encoding: TextEncoding,
+	) -> Result<DocumentSnapshot> {
+		let mut documents = self.documents.lock().unwrap();
+		let document = documents
+			.get_mut(Path::new(path))
+			.ok_or_else(|| anyhow!("Document is not open: {}", path))?;
+
+		document.load(read_disk(&document.path, Some(encoding))?);
+		document.format_chosen = true;
+
+		let snapshot = document.snapshot();
+		self.notify(|l| l.did_change(&snapshot.state, &snapshot.text));
+		Ok(snapshot)
+	}
+
+	/// Forgets an open document, discarding unsaved edits.
+	pub fn close_document(&self, path: &str) -> Result<()> {
+		let Some(document) = self.documents.lock().unwrap().remove(Path::new(path))
+		else {
+			return Ok(());
+		};
+
+		if let Some(dir) = document.path.parent() {
+			self.watcher.unwatch(dir)?;
+		}
+		self.notify(|l| l.did_close(&document.state()));
+		Ok(())
+	}
+
+	/// Documents with edits that haven't been saved, including their text.
+	pub fn unsaved_documents(&self) -> Vec<DocumentSnapshot> {
+		self.documents
+			.lock()
+			.unwrap()
+			.values()
+			.filter(|document| document.text != document.saved_text)
+			.map(Document::snapshot)
+			.collect()
+	}
+
+	pub fn list_documents(&self) -> Vec<DocumentState> {
+		self.documents
+			.lock()
+			.unwrap()
+			.values()
+			.map(Document::state)
+			.collect()
+	}
+}
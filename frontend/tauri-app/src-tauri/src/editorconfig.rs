@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::text_encoding::{LineEnding, TextEncoding};
+
+const FILE_NAME: &str = ".editorconfig";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+	Tab,
+	Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndOfLine {
+	Lf,
+	Crlf,
+	/// Classic Mac OS line endings, which the editor can't save.
+	Cr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Charset {
+	#[serde(rename = "latin1")]
+	Latin1,
+	#[serde(rename = "utf-8")]
+	Utf8,
+	#[serde(rename = "utf-8-bom")]
+	Utf8Bom,
+	#[serde(rename = "utf-16be")]
+	Utf16Be,
+	#[serde(rename = "utf-16le")]
+	Utf16Le,
+}
+
+/// The properties `.editorconfig` files give a file; `None` where none of
+/// them says.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditorConfig {
+	pub indent_style: Option<IndentStyle>,
+	/// Columns per indentation level.
+	pub indent_size: Option<u32>,
+	/// Columns a tab character is displayed as.
+	pub tab_width: Option<u32>,
+	pub end_of_line: Option<EndOfLine>,
+	pub charset: Option<Charset>,
+	pub trim_trailing_whitespace: Option<bool>,
+	pub insert_final_newline: Option<bool>,
+	pub max_line_length: Option<u32>,
+	/// The `.editorconfig` files read, nearest last.
+	pub sources: Vec<String>,
+}
+
+impl EditorConfig {
+	/// The line ending files are saved with, unless it's one the editor
+	/// can't write.
+	pub fn line_ending(&self) -> Option<LineEnding> {
+		match self.end_of_line? {
+			EndOfLine::Lf => Some(LineEnding::Lf),
+			EndOfLine::Crlf => Some(LineEnding::Crlf),
+			EndOfLine::Cr => None,
+		}
+	}
+
+	/// The encoding files are saved in, and whether they start with a BOM.
+	pub fn encoding(&self) -> Option<(TextEncoding, bool)> {
+		Some(match self.charset? {
+			Charset::Latin1 => (TextEncoding::Latin1, false),
+			Charset::Utf8 => (TextEncoding::Utf8, false),
+			Charset::Utf8Bom => (TextEncoding::Utf8, true),
+			Charset::Utf16Be => (TextEncoding::Utf16Be, true),
+			Charset::Utf16Le => (TextEncoding::Utf16Le, true),
+		})
+	}
+
+	/// Applies `trim_trailing_whitespace` and `insert_final_newline` to
+	/// `\n`-terminated text about to be written.
+	pub fn apply_on_save(&self, text: &str) -> String {
+		let mut text = if self.trim_trailing_whitespace == Some(true) {
+			text.split('\n')
+				.map(|line| line.trim_end_matches([' ', '\t']))
+				.collect::<Vec<_>>()
+				.join("\n")
+		} else {
+			text.to_string()
+		};
+		match self.insert_final_newline {
+			Some(true) if !text.is_empty() && !text.ends_with('\n') => text.push('\n'),
+			Some(false) => {
+				let trimmed = text.trim_end_matches('\n').len();
+				text.truncate(trimmed);
+			}
+			_ => {}
+		}
+		text
+	}
+}
+
+/// One `[glob]` section of an `.editorconfig`, with its lowercased keys.
+struct Section {
+	glob: String,
+	properties: Vec<(String, String)>,
+}
+
+struct ConfigFile {
+	root: bool,
+	sections: Vec<Section>,
+}
+
+/// Reads an `.editorconfig`: `key = value` pairs under `[glob]` headers,
+/// `#` and `;` starting comment lines, and `root = true` before the first
+/// section.
+fn parse(content: &str) -> ConfigFile {
+	let mut file = ConfigFile {
+		root: false,
+		sections: Vec::new(),
+	};
+	for line in content.lines() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with(['#', ';']) {
+			continue;
+		}
+		if let Some(glob) = line
+			.strip_prefix('[')
+			.and_then(|line| line.strip_suffix(']'))
+		{
+			file.sections.push(Section {
+				glob: glob.to_string(),
+				properties: Vec::new(),
+			});
+			continue;
+		}
+		let Some((key, value)) = line.split_once('=') else {
+			continue;
+		};
+		let key = key.trim().to_lowercase();
+		let value = value.trim().to_string();
+		match file.sections.last_mut() {
+			Some(section) => section.properties.push((key, value)),
+			None if key == "root" => file.root = value.eq_ignore_ascii_case("true"),
+			None => {}
+		}
+	}
+	file
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Char(char),
+	/// `?`
+	Any,
+	/// `*`, anything but `/`.
+	Star,
+	/// `**`, anything.
+	DoubleStar,
+	/// `[a-z]` or `[!abc]`.
+	Class(Vec<(char, char)>, bool),
+	/// `{a,b}`.
+	Alternatives(Vec<Vec<Token>>),
+	/// `{1..10}`.
+	Numbers(i64, i64),
+}
+
+/// Splits `{a,{b,c}}` contents on their top-level commas.
+fn split_alternatives(inner: &[char]) -> Vec<&[char]> {
+	let mut parts = Vec::new();
+	let (mut depth, mut start, mut escaped) = (0, 0, false);
+	for (index, c) in inner.iter().enumerate() {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' => escaped = true,
+			'{' => depth += 1,
+			'}' => depth -= 1,
+			',' if depth == 0 => {
+				parts.push(&inner[start..index]);
+				start = index + 1;
+			}
+			_ => {}
+		}
+	}
+	parts.push(&inner[start..]);
+	parts
+}
+
+/// The index of the `}` closing the `{` at `open`.
+fn closing_brace(glob: &[char], open: usize) -> Option<usize> {
+	let (mut depth, mut escaped) = (0, false);
+	for (index, c) in glob.iter().enumerate().skip(open) {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' => escaped = true,
+			'{' => depth += 1,
+			'}' => {
+				depth -= 1;
+				if depth == 0 {
+					return Some(index);
+				}
+			}
+			_ => {}
+		}
+	}
+	None
+}
+
+fn compile(glob: &[char]) -> Vec<Token> {
+	let mut tokens = Vec::new();
+	let mut index = 0;
+	while index < glob.len() {
+		let c = glob[index];
+		index += 1;
+		match c {
+			'\\' if index < glob.len() => {
+				tokens.push(Token::Char(glob[index]));
+				index += 1;
+			}
+			'?' => tokens.push(Token::Any),
+			'*' if glob.get(index) == Some(&'*') => {
+				tokens.push(Token::DoubleStar);
+				index += 1;
+			}
+			'*' => tokens.push(Token::Star),
+			'[' => {
+				let Some(end) = glob[index..].iter().position(|c| *c == ']') else {
+					tokens.push(Token::Char('['));
+					continue;
+				};
+				let mut class = &glob[index..index + end];
+				index += end + 1;
+				let negated = class.first() == Some(&'!');
+				if negated {
+					class = &class[1..];
+				}
+				let mut ranges = Vec::new();
+				let mut position = 0;
+				while position < class.len() {
+					if class.get(position + 1) == Some(&'-') && position + 2 < class.len()
+					{
+						ranges.push((class[position], class[position + 2]));
+						position += 3;
+					} else {
+						ranges.push((class[position], class[position]));
+						position += 1;
+					}
+				}
+				tokens.push(Token::Class(ranges, negated));
+			}
+			'{' => {
+				let Some(end) = closing_brace(glob, index - 1) else {
+					tokens.push(Token::Char('{'));
+					continue;
+				};
+				let inner = &glob[index..end];
+				index = end + 1;
+				let text: String = inner.iter().collect();
+				let numbers = text.split_once("..").and_then(|(low, high)| {
+					Some((low.parse::<i64>().ok()?, high.parse::<i64>().ok()?))
+				});
+				let alternatives = split_alternatives(inner);
+				if let Some((low, high)) = numbers {
+					tokens.push(Token::Numbers(low.min(high), low.max(high)));
+				} else if alternatives.len() > 1 {
+					tokens.push(Token::Alternatives(
+						alternatives.into_iter().map(compile).collect(),
+					));
+				} else {
+					// A brace without commas is literal, e.g. `{single}`
+					tokens.push(Token::Char('{'));
+					tokens.extend(compile(inner));
+					tokens.push(Token::Char('}'));
+				}
+			}
+			c => tokens.push(Token::Char(c)),
+		}
+	}
+	tokens
+}
+
+fn matches(tokens: &[Token], text: &[char]) -> bool {
+	let Some((token, rest)) = tokens.split_first() else {
+		return text.is_empty();
+	};
+	match token {
+		Token::Char(c) => text.first() == Some(c) && matches(rest, &text[1..]),
+		Token::Any => {
+			text.first().is_some_and(|c| *c != '/') && matches(rest, &text[1..])
+		}
+		Token::Star => {
+			let limit = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+			(0..=limit).any(|skip| matches(rest, &text[skip..]))
+		}
+		Token::DoubleStar => {
+			// `a/**/b` matches `a/b` too
+			(rest.first() == Some(&Token::Char('/')) && matches(&rest[1..], text))
+				|| (0..=text.len()).any(|skip| matches(rest, &text[skip..]))
+		}
+		Token::Class(ranges, negated) => match text.first() {
+			Some(c) if *c != '/' => {
+				ranges.iter().any(|(low, high)| (low..=high).contains(&c)) != *negated
+					&& matches(rest, &text[1..])
+			}
+			_ => false,
+		},
+		Token::Alternatives(alternatives) => alternatives.iter().any(|alternative| {
+			let mut joined = alternative.clone();
+			joined.extend_from_slice(rest);
+			matches(&joined, text)
+		}),
+		Token::Numbers(low, high) => {
+			let sign = usize::from(text.first() == Some(&'-'));
+			let digits = text[sign..]
+				.iter()
+				.take_while(|c| c.is_ascii_digit())
+				.count();
+			(1..=digits).any(|length| {
+				let number: String = text[..sign + length].iter().collect();
+				number
+					.parse::<i64>()
+					.is_ok_and(|number| (*low..=*high).contains(&number))
+					&& matches(rest, &text[sign + length..])
+			})
+		}
+	}
+}
+
+/// Whether a section's glob covers `relative`, a `/`-separated path from the
+/// directory of its `.editorconfig`. Globs without a `/` match at any depth.
+fn glob_matches(glob: &str, relative: &str) -> bool {
+	let text: Vec<char> = relative.chars().collect();
+	if glob.contains('/') {
+		let glob: Vec<char> = glob.trim_start_matches('/').chars().collect();
+		return matches(&compile(&glob), &text);
+	}
+	let tokens = compile(&glob.chars().collect::<Vec<_>>());
+	std::iter::once(0)
+		.chain(
+			text.iter()
+				.enumerate()
+				.filter(|(_, c)| **c == '/')
+				.map(|(index, _)| index + 1),
+		)
+		.any(|start| matches(&tokens, &text[start..]))
+}
+
+fn parse_size(value: &str) -> Option<u32> {
+	value.parse().ok().filter(|size| *size > 0)
+}
+
+/// The EditorConfig properties of the file at `path`, from the
+/// `.editorconfig` files in its directory and above, up to one marked
+/// `root = true`.
+pub fn resolve(path: &Path) -> EditorConfig {
+	let mut files = Vec::new();
+	for dir in path.ancestors().skip(1) {
+		let config_path = dir.join(FILE_NAME);
+		let Ok(content) = fs::read_to_string(&config_path) else {
+			continue;
+		};
+		let file = parse(&content);
+		let root = file.root;
+		files.push((dir, config_path, file));
+		if root {
+			break;
+		}
+	}
+
+	// Nearer files and later sections win
+	let mut properties: HashMap<String, String> = HashMap::new();
+	let mut sources = Vec::new();
+	for (dir, config_path, file) in files.into_iter().rev() {
+		let Ok(relative) = path.strip_prefix(dir) else {
+			continue;
+		};
+		let relative = relative.to_string_lossy().replace('\\', "/");
+		sources.push(config_path.to_string_lossy().to_string());
+		for section in file.sections {
+			if !glob_matches(&section.glob, &relative) {
+				continue;
+			}
+			for (key, value) in section.properties {
+				if value.eq_ignore_ascii_case("unset") {
+					properties.remove(&key);
+				} else {
+					properties.insert(key, value.to_lowercase());
+				}
+			}
+		}
+	}
+
+	let get = |key: &str| properties.get(key).map(String::as_str);
+	let boolean = |key: &str| match get(key)? {
+		"true" => Some(true),
+		"false" => Some(false),
+		_ => None,
+	};
+	let indent_style = match get("indent_style") {
+		Some("tab") => Some(IndentStyle::Tab),
+		Some("space") => Some(IndentStyle::Space),
+		_ => None,
+	};
+	let mut tab_width = get("tab_width").and_then(parse_size);
+	let indent_size = match get("indent_size") {
+		Some("tab") => tab_width,
+		Some(size) => parse_size(size),
+		// Tab indentation is a tab wide unless it says otherwise
+		None if indent_style == Some(IndentStyle::Tab) => tab_width,
+		None => None,
+	};
+	if tab_width.is_none() {
+		tab_width = indent_size;
+	}
+
+	EditorConfig {
+		indent_style,
+		indent_size,
+		tab_width,
+		end_of_line: match get("end_of_line") {
+			Some("lf") => Some(EndOfLine::Lf),
+			Some("crlf") => Some(EndOfLine::Crlf),
+			Some("cr") => Some(EndOfLine::Cr),
+			_ => None,
+		},
+		charset: match get("charset") {
+			Some("latin1") => Some(Charset::Latin1),
+			Some("utf-8") => Some(Charset::Utf8),
+			Some("utf-8-bom") => Some(Charset::Utf8Bom),
+			Some("utf-16be") => Some(Charset::Utf16Be),
+			Some("utf-16le") => Some(Charset::Utf16Le),
+			_ => None,
+		},
+		trim_trailing_whitespace: boolean("trim_trailing_whitespace"),
+		insert_final_newline: boolean("insert_final_newline"),
+		// `off` parses as nothing
+		max_line_length: get("max_line_length").and_then(parse_size),
+		sources,
+	}
+}
+
+/// The EditorConfig properties of a file, which needn't exist yet.
+#[tauri::command]
+pub async fn resolve_editorconfig(path: String) -> Result<EditorConfig, String> {
+	tauri::async_runtime::spawn_blocking(move || resolve(Path::new(&path)))
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+
+	/// A fresh directory holding `files`, given as relative path and content.
+	fn tree(files: &[(&str, &str)]) -> PathBuf {
+		let root =
+			std::env::temp_dir().join(format!("editorconfig-{}", uuid::Uuid::new_v4()));
+		for (path, content) in files {
+			let path = root.join(path);
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(path, content).unwrap();
+		}
+		root
+	}
+
+	#[test]
+	fn matches_brace_alternatives() {
+		assert!(glob_matches("*.{js,ts}", "app.ts"));
+		assert!(glob_matches("*.{js,ts}", "src/app.js"));
+		assert!(!glob_matches("*.{js,ts}", "app.rs"));
+		assert!(glob_matches("{a,{b,c}}.txt", "c.txt"));
+		// Without a comma the braces are literal
+		assert!(glob_matches("{single}.txt", "{single}.txt"));
+		assert!(!glob_matches("{single}.txt", "single.txt"));
+	}
+
+	#[test]
+	fn matches_double_star_across_directories() {
+		assert!(glob_matches("src/**/*.rs", "src/main.rs"));
+		assert!(glob_matches("src/**/*.rs", "src/a/b/main.rs"));
+		assert!(!glob_matches("src/**/*.rs", "lib/main.rs"));
+		assert!(!glob_matches("src/*.rs", "src/a/main.rs"));
+		assert!(glob_matches("/src/*.rs", "src/main.rs"));
+	}
+
+	#[test]
+	fn matches_number_ranges() {
+		assert!(glob_matches("file{1..3}.txt", "file1.txt"));
+		assert!(glob_matches("file{1..3}.txt", "file3.txt"));
+		assert!(!glob_matches("file{1..3}.txt", "file4.txt"));
+		assert!(glob_matches("file{3..1}.txt", "file2.txt"));
+		assert!(glob_matches("v{-2..10}", "v-1"));
+		assert!(glob_matches("v{-2..10}", "v10"));
+		assert!(!glob_matches("v{-2..10}", "v11"));
+	}
+
+	#[test]
+	fn matches_character_classes() {
+		assert!(glob_matches("[a-c].md", "b.md"));
+		assert!(!glob_matches("[a-c].md", "d.md"));
+		assert!(glob_matches("[!a-c].md", "d.md"));
+		assert!(!glob_matches("?.md", "/.md"));
+	}
+
+	#[test]
+	fn reads_root_only_before_the_first_section() {
+		assert!(parse("root = true\n[*]\nindent_size = 2\n").root);
+		assert!(!parse("[*]\nroot = true\n").root);
+	}
+
+	#[test]
+	fn stops_at_the_root_editorconfig() {
+		let root = tree(&[
+			(
+				".editorconfig",
+				"[*]\nindent_size = 8\nend_of_line = crlf\n",
+			),
+			(
+				"project/.editorconfig",
+				"root = true\n[*]\nindent_style = space\n",
+			),
+		]);
+		let config = resolve(&root.join("project/main.rs"));
+		assert_eq!(config.indent_style, Some(IndentStyle::Space));
+		assert_eq!(config.indent_size, None);
+		assert_eq!(config.end_of_line, None);
+		assert_eq!(config.sources.len(), 1);
+		fs::remove_dir_all(root).unwrap();
+	}
+
+	#[test]
+	fn lets_nearer_files_and_later_sections_override() {
+		let root = tree(&[
+			(
+				".editorconfig",
+				"root = true\n[*]\nindent_style = tab\nindent_size = 8\n\
+				 charset = utf-8\ninsert_final_newline = true\n",
+			),
+			(
+				"project/.editorconfig",
+				"[*]\nindent_style = space\nindent_size = 4\n\
+				 [*.md]\nindent_size = 2\ninsert_final_newline = unset\n",
+			),
+		]);
+		let config = resolve(&root.join("project/README.md"));
+		assert_eq!(config.indent_style, Some(IndentStyle::Space));
+		assert_eq!(config.indent_size, Some(2));
+		assert_eq!(config.tab_width, Some(2));
+		assert_eq!(config.charset, Some(Charset::Utf8));
+		assert_eq!(config.insert_final_newline, None);
+		// Outer files are listed first
+		assert_eq!(
+			config.sources,
+			vec![
+				root.join(".editorconfig").to_string_lossy().to_string(),
+				root.join("project/.editorconfig")
+					.to_string_lossy()
+					.to_string(),
+			]
+		);
+
+		let config = resolve(&root.join("project/main.rs"));
+		assert_eq!(config.indent_size, Some(4));
+		assert_eq!(config.insert_final_newline, Some(true));
+		fs::remove_dir_all(root).unwrap();
+	}
+}
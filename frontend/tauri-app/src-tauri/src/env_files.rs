@@ -0,0 +1,624 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::document_manager::write_atomically;
+use crate::os::OsSession;
+
+/// Store holding which variables are injected for each session.
+const ENV_STORE: &str = "env.json";
+
+const EXAMPLE_FILE: &str = ".env.example";
+
+/// A problem found while parsing an env file. Lines are 1-based.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvIssue {
+	pub line: usize,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvVariable {
+	pub key: String,
+	pub value: String,
+	/// Where the variable is defined; the last definition wins.
+	pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvFileContents {
+	pub name: String,
+	pub variables: Vec<EnvVariable>,
+	pub issues: Vec<EnvIssue>,
+}
+
+/// Sets `key` to `value`, or removes it when `value` is `None`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvEdit {
+	pub key: String,
+	pub value: Option<String>,
+}
+
+/// How an env file differs from `.env.example`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvDiff {
+	/// In the example but not in the file.
+	pub missing: Vec<String>,
+	/// In the file but not in the example.
+	pub extra: Vec<String>,
+	/// In both, but empty in the file.
+	pub empty: Vec<String>,
+}
+
+/// What a process is started for, so variables can be injected into some
+/// kinds of process but not others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvTarget {
+	Terminal,
+	Task,
+	/// Custom terminals, which agents run in.
+	Agent,
+}
+
+/// Variables from one of a session's env files to set in the processes it
+/// starts. Values are read when each process starts, so edits apply to
+/// new processes straight away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvInjection {
+	pub file: String,
+	/// Keys to inject; every variable in the file when empty.
+	#[serde(default)]
+	pub keys: Vec<String>,
+	pub terminals: bool,
+	pub tasks: bool,
+	pub agents: bool,
+}
+
+impl EnvInjection {
+	fn applies_to(&self, target: EnvTarget) -> bool {
+		match target {
+			EnvTarget::Terminal => self.terminals,
+			EnvTarget::Task => self.tasks,
+			EnvTarget::Agent => self.agents,
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+	key: String,
+	value: String,
+	/// Written as `export KEY=...`.
+	export: bool,
+	/// Trailing ` # comment`, kept when the value is changed.
+	comment: Option<String>,
+	line: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+	/// Blank lines, comments and lines that didn't parse, kept verbatim.
+	Other(String),
+	/// The source is `None` once the entry has been edited.
+	Entry(Entry, Option<String>),
+}
+
+/// A parsed `.env` file that can be edited without disturbing comments,
+/// ordering or formatting of untouched lines.
+#[derive(Debug, Clone, Default)]
+pub struct EnvFile {
+	lines: Vec<Line>,
+	issues: Vec<EnvIssue>,
+	trailing_newline: bool,
+}
+
+fn valid_key(key: &str) -> bool {
+	let mut chars = key.chars();
+	chars
+		.next()
+		.is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a double-quoted value starting after its opening quote into the
+/// unescaped value and the rest of the line, or `None` if it isn't closed.
+fn parse_double_quoted(rest: &str) -> Option<(String, &str)> {
+	let mut value = String::new();
+	let mut chars = rest.char_indices();
+	while let Some((i, c)) = chars.next() {
+		match c {
+			'"' => return Some((value, &rest[i + 1..])),
+			'\\' => match chars.next() {
+				Some((_, 'n')) => value.push('\n'),
+				Some((_, 'r')) => value.push('\r'),
+				Some((_, 't')) => value.push('\t'),
+				Some((_, other)) => {
+					if !matches!(other, '"' | '\\' | '$') {
+						value.push('\\');
+					}
+					value.push(other);
+				}
+				None => value.push('\\'),
+			},
+			c => value.push(c),
+		}
+	}
+	None
+}
+
+fn trailing_comment(rest: &str) -> Option<String> {
+	let rest = rest.trim();
+	rest.starts_with('#').then(|| rest.to_string())
+}
+
+fn render_value(value: &str) -> String {
+	let plain = value.chars().all(|c| {
+		c.is_ascii_alphanumeric()
+			|| matches!(c, '_' | '-' | '.' | '/' | ':' | '@' | ',' | '+' | '%')
+	});
+	if plain {
+		return value.to_string();
+	}
+	let mut quoted = String::from("\"");
+	for c in value.chars() {
+		match c {
+			'"' => quoted.push_str("\\\""),
+			'\\' => quoted.push_str("\\\\"),
+			'$' => quoted.push_str("\\$"),
+			'\n' => quoted.push_str("\\n"),
+			'\r' => quoted.push_str("\\r"),
+			c => quoted.push(c),
+		}
+	}
+	quoted.push('"');
+	quoted
+}
+
+fn render_entry(entry: &Entry) -> String {
+	let mut line = format!(
+		"{}{}={}",
+		if entry.export { "export " } else { "" },
+		entry.key,
+		render_value(&entry.value)
+	);
+	if let Some(comment) = &entry.comment {
+		line.push(' ');
+		line.push_str(comment);
+	}
+	line
+}
+
+impl EnvFile {
+	pub fn parse(content: &str) -> Self {
+		let mut file = Self {
+			trailing_newline: content.is_empty() || content.ends_with('\n'),
+			..Self::default()
+		};
+		let source: Vec<&str> = content.lines().collect();
+		let mut index = 0;
+		while index < source.len() {
+			let raw = source[index];
+			let line = index + 1;
+			index += 1;
+
+			let trimmed = raw.trim_start();
+			if trimmed.is_empty() || trimmed.starts_with('#') {
+				file.lines.push(Line::Other(raw.to_string()));
+				continue;
+			}
+			let (export, assignment) = match trimmed.strip_prefix("export ") {
+				Some(rest) => (true, rest.trim_start()),
+				None => (false, trimmed),
+			};
+			let Some((key, rest)) = assignment.split_once('=') else {
+				file.issue(line, "Expected KEY=value");
+				file.lines.push(Line::Other(raw.to_string()));
+				continue;
+			};
+			let key = key.trim();
+			if !valid_key(key) {
+				file.issue(line, format!("Invalid variable name: {}", key));
+				file.lines.push(Line::Other(raw.to_string()));
+				continue;
+			}
+
+			let rest = rest.trim_start();
+			let mut raw = raw.to_string();
+			let value = if let Some(quoted) = rest.strip_prefix('"') {
+				// Double-quoted values may span lines
+				let mut text = quoted.to_string();
+				let mut end = index;
+				loop {
+					if let Some((value, after)) = parse_double_quoted(&text) {
+						let comment = trailing_comment(after);
+						for continuation in &source[index..end] {
+							raw.push('\n');
+							raw.push_str(continuation);
+						}
+						index = end;
+						break Some((value, comment));
+					}
+					if end == source.len() {
+						break None;
+					}
+					text.push('\n');
+					text.push_str(source[end]);
+					end += 1;
+				}
+			} else if let Some(quoted) = rest.strip_prefix('\'') {
+				quoted
+					.split_once('\'')
+					.map(|(value, after)| (value.to_string(), trailing_comment(after)))
+			} else if rest.starts_with('#') {
+				Some((String::new(), trailing_comment(rest)))
+			} else {
+				let (value, comment) = match rest.find(" #") {
+					Some(i) => (&rest[..i], Some(rest[i + 1..].trim().to_string())),
+					None => (rest, None),
+				};
+				Some((value.trim_end().to_string(), comment))
+			};
+
+			match value {
+				Some((value, comment)) => file.lines.push(Line::Entry(
+					Entry {
+						key: key.to_string(),
+						value,
+						export,
+						comment,
+						line,
+					},
+					Some(raw),
+				)),
+				None => {
+					file.issue(line, format!("Unterminated quote in {}", key));
+					file.lines.push(Line::Other(raw));
+				}
+			}
+		}
+
+		let mut seen = HashSet::new();
+		let duplicates: Vec<_> = file
+			.entries()
+			.filter(|entry| !seen.insert(entry.key.clone()))
+			.map(|entry| (entry.line, entry.key.clone()))
+			.collect();
+		for (line, key) in duplicates {
+			file.issue(line, format!("{} is defined more than once", key));
+		}
+		file
+	}
+
+	fn issue(&mut self, line: usize, message: impl Into<String>) {
+		self.issues.push(EnvIssue {
+			line,
+			message: message.into(),
+		});
+	}
+
+	fn entries(&self) -> impl Iterator<Item = &Entry> {
+		self.lines.iter().filter_map(|line| match line {
+			Line::Entry(entry, _) => Some(entry),
+			Line::Other(_) => None,
+		})
+	}
+
+	pub fn issues(&self) -> &[EnvIssue] {
+		&self.issues
+	}
+
+	/// The effective variables, in the order they were first defined.
+	pub fn variables(&self) -> Vec<EnvVariable> {
+		let mut variables: Vec<EnvVariable> = Vec::new();
+		for entry in self.entries() {
+			let variable = EnvVariable {
+				key: entry.key.clone(),
+				value: entry.value.clone(),
+				line: entry.line,
+			};
+			match variables.iter_mut().find(|v| v.key == entry.key) {
+				Some(existing) => *existing = variable,
+				None => variables.push(variable),
+			}
+		}
+		variables
+	}
+
+	/// Sets `key`, replacing its last definition in place or appending it.
+	pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+		if !valid_key(key) {
+			return Err(anyhow!("Invalid variable name: {}", key));
+		}
+		let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+			Line::Entry(entry, source) if entry.key == key => Some((entry, source)),
+			_ => None,
+		});
+		match existing {
+			Some((entry, source)) => {
+				if entry.value != value {
+					entry.value = value.to_string();
+					*source = None;
+				}
+			}
+			None => self.lines.push(Line::Entry(
+				Entry {
+					key: key.to_string(),
+					value: value.to_string(),
+					export: false,
+					comment: None,
+					line: 0,
+				},
+				None,
+			)),
+		}
+		Ok(())
+	}
+
+	/// Removes every definition of `key`.
+	pub fn remove(&mut self, key: &str) {
+		self.lines
+			.retain(|line| !matches!(line, Line::Entry(entry, _) if entry.key == key));
+	}
+
+	pub fn render(&self) -> String {
+		let lines: Vec<String> = self
+			.lines
+			.iter()
+			.map(|line| match line {
+				Line::Other(raw) | Line::Entry(_, Some(raw)) => raw.clone(),
+				Line::Entry(entry, None) => render_entry(entry),
+			})
+			.collect();
+		let mut content = lines.join("\n");
+		if self.trailing_newline && !content.is_empty() {
+			content.push('\n');
+		}
+		content
+	}
+}
+
+/// Path of the env file `name` at the root of the session.
+fn env_path(session: &OsSession, name: &str) -> Result<PathBuf> {
+	if !name.starts_with(".env") || name.contains(['/', '\\']) {
+		return Err(anyhow!("Not an env file: {}", name));
+	}
+	Ok(session.host_path().join(name))
+}
+
+fn read_env_file(session: &OsSession, name: &str) -> Result<EnvFile> {
+	let path = env_path(session, name)?;
+	let content = fs::read_to_string(&path)
+		.map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+	Ok(EnvFile::parse(&content))
+}
+
+/// Names of the `.env*` files at the root of the session, sorted.
+pub fn list_files(session: &OsSession) -> Result<Vec<String>> {
+	let mut names: Vec<String> = fs::read_dir(session.host_path())?
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+		.filter_map(|entry| entry.file_name().into_string().ok())
+		.filter(|name| name.starts_with(".env"))
+		.collect();
+	names.sort();
+	Ok(names)
+}
+
+pub fn read(session: &OsSession, name: &str) -> Result<EnvFileContents> {
+	let file = read_env_file(session, name)?;
+	Ok(EnvFileContents {
+		name: name.to_string(),
+		variables: file.variables(),
+		issues: file.issues().to_vec(),
+	})
+}
+
+/// Applies `edits` to the env file `name`, creating it if needed.
+pub fn edit(
+	session: &OsSession,
+	name: &str,
+	edits: &[EnvEdit],
+) -> Result<EnvFileContents> {
+	let path = env_path(session, name)?;
+	let mut file = match fs::read_to_string(&path) {
+		Ok(content) => EnvFile::parse(&content),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => EnvFile::parse(""),
+		Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+	};
+	for edit in edits {
+		match &edit.value {
+			Some(value) => file.set(&edit.key, value)?,
+			None => file.remove(&edit.key),
+		}
+	}
+	write_atomically(&path, file.render().as_bytes())?;
+	read(session, name)
+}
+
+pub fn diff(session: &OsSession, name: &str) -> Result<EnvDiff> {
+	let example = read_env_file(session, EXAMPLE_FILE)?.variables();
+	let file = read_env_file(session, name)?.variables();
+
+	let values: HashMap<&str, &str> = file
+		.iter()
+		.map(|v| (v.key.as_str(), v.value.as_str()))
+		.collect();
+	let example_keys: HashSet<&str> = example.iter().map(|v| v.key.as_str()).collect();
+	Ok(EnvDiff {
+		missing: example
+			.iter()
+			.filter(|v| !values.contains_key(v.key.as_str()))
+			.map(|v| v.key.clone())
+			.collect(),
+		extra: file
+			.iter()
+			.filter(|v| !example_keys.contains(v.key.as_str()))
+			.map(|v| v.key.clone())
+			.collect(),
+		empty: example
+			.iter()
+			.filter(|v| {
+				values
+					.get(v.key.as_str())
+					.is_some_and(|value| value.is_empty())
+			})
+			.map(|v| v.key.clone())
+			.collect(),
+	})
+}
+
+fn session_key(session: &OsSession) -> String {
+	match session {
+		OsSession::Local(dir) => dir.clone(),
+		OsSession::Wsl(wsl) => {
+			format!("wsl:{}:{}", wsl.distribution, wsl.working_directory)
+		}
+	}
+}
+
+pub fn injections(
+	app_handle: &AppHandle,
+	session: &OsSession,
+) -> Result<Vec<EnvInjection>> {
+	let store = app_handle.store(ENV_STORE)?;
+	Ok(store
+		.get(session_key(session))
+		.and_then(|v| serde_json::from_value(v).ok())
+		.unwrap_or_default())
+}
+
+pub fn set_injections(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	injections: &[EnvInjection],
+) -> Result<()> {
+	for injection in injections {
+		env_path(session, &injection.file)?;
+	}
+	let store = app_handle.store(ENV_STORE)?;
+	if injections.is_empty() {
+		store.delete(session_key(session));
+	} else {
+		store.set(session_key(session), serde_json::to_value(injections)?);
+	}
+	store.save()?;
+	Ok(())
+}
+
+/// The variables to inject into a `target` process started in `session`.
+/// Later injections win over earlier ones.
+pub fn variables_for(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	target: EnvTarget,
+) -> Result<Vec<(String, String)>> {
+	let mut variables: Vec<(String, String)> = Vec::new();
+	for injection in injections(app_handle, session)?
+		.into_iter()
+		.filter(|injection| injection.applies_to(target))
+	{
+		let file = read_env_file(session, &injection.file)?;
+		for variable in file.variables() {
+			if !injection.keys.is_empty() && !injection.keys.contains(&variable.key) {
+				continue;
+			}
+			variables.retain(|(key, _)| *key != variable.key);
+			variables.push((variable.key, variable.value));
+		}
+	}
+	Ok(variables)
+}
+
+/// Sets the session's injected variables on `cmd`. Failures are logged
+/// rather than stopping the process from starting.
+pub fn apply(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	target: EnvTarget,
+	cmd: &mut CommandBuilder,
+) {
+	let variables = match variables_for(app_handle, session, target) {
+		Ok(variables) => variables,
+		Err(e) => {
+			log::warn!("Failed to load env variables to inject: {}", e);
+			return;
+		}
+	};
+	if variables.is_empty() {
+		return;
+	}
+
+	// Only variables listed in WSLENV cross into WSL
+	if let OsSession::Wsl(_) = session {
+		let mut shared: Vec<String> = cmd
+			.get_env("WSLENV")
+			.map(|v| v.to_string_lossy().into_owned())
+			.filter(|v| !v.is_empty())
+			.into_iter()
+			.collect();
+		shared.extend(variables.iter().map(|(key, _)| key.clone()));
+		cmd.env("WSLENV", shared.join(":"));
+	}
+	for (key, value) in variables {
+		cmd.env(key, value);
+	}
+}
+
+#[tauri::command]
+pub async fn list_env_files(os_session: OsSession) -> Result<Vec<String>, String> {
+	list_files(&os_session).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn read_env_file_variables(
+	os_session: OsSession,
+	name: String,
+) -> Result<EnvFileContents, String> {
+	read(&os_session, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn edit_env_file(
+	os_session: OsSession,
+	name: String,
+	edits: Vec<EnvEdit>,
+) -> Result<EnvFileContents, String> {
+	edit(&os_session, &name, &edits).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn diff_env_file(
+	os_session: OsSession,
+	name: String,
+) -> Result<EnvDiff, String> {
+	diff(&os_session, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_env_injections(
+	os_session: OsSession,
+	app_handle: AppHandle,
+) -> Result<Vec<EnvInjection>, String> {
+	injections(&app_handle, &os_session).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_env_injections(
+	os_session: OsSession,
+	injections: Vec<EnvInjection>,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	set_injections(&app_handle, &os_session, &injections).map_err(|e| e.to_string())
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::os::OsSession;
+#[cfg(target_os = "windows")]
+use crate::wsl::WslCommand;
+
+/// Toolchains looked up in a session, each with the programs tried in
+/// order; the first one on the PATH wins.
+const TOOLCHAINS: &[(&str, &[&str])] = &[
+	("node", &["node"]),
+	("npm", &["npm"]),
+	("python", &["python3", "python"]),
+	("rustc", &["rustc"]),
+	("cargo", &["cargo"]),
+	("go", &["go"]),
+	("git", &["git"]),
+];
+
+/// Variables worth showing or telling an agent about. Only these are read,
+/// so tokens and keys in the environment never leave the session.
+const ENV_VARS: &[&str] = &[
+	"PATH",
+	"HOME",
+	"USER",
+	"LANG",
+	"SHELL",
+	"VIRTUAL_ENV",
+	"CONDA_DEFAULT_ENV",
+	"NVM_DIR",
+	"CARGO_HOME",
+	"RUSTUP_TOOLCHAIN",
+	"GOPATH",
+	"JAVA_HOME",
+];
+
+const MARKER: &str = "@@ariana";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Toolchain {
+	pub name: String,
+	/// The program found, e.g. "python3" where "python" isn't installed.
+	pub program: Option<String>,
+	pub path: Option<String>,
+	/// The version number alone, e.g. "1.80.0".
+	pub version: Option<String>,
+	/// The first line the program printed for `--version`.
+	pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentReport {
+	/// Every toolchain in `TOOLCHAINS`, with nothing set when it's missing.
+	pub toolchains: Vec<Toolchain>,
+	pub shell: Option<String>,
+	pub system: Option<String>,
+	pub env: BTreeMap<String, String>,
+}
+
+/// A POSIX script printing everything the report needs, each value after a
+/// marker line so stray output from profiles can't be mistaken for it.
+fn posix_script() -> String {
+	let mut script = String::new();
+	for (name, programs) in TOOLCHAINS {
+		script.push_str(&format!(
+			"for p in {}; do if command -v \"$p\" >/dev/null 2>&1; then \
+			 echo '{MARKER} tool {name}'; echo \"$p\"; command -v \"$p\"; \
+			 \"$p\" --version 2>&1 | head -n 1; break; fi; done\n",
+			programs.join(" ")
+		));
+	}
+	script.push_str(&format!("echo '{MARKER} shell'; echo \"$SHELL\"\n"));
+	script.push_str(&format!("echo '{MARKER} system'; uname -sr\n"));
+	for name in ENV_VARS {
+		script.push_str(&format!(
+			"if [ -n \"${{{name}+x}}\" ]; then echo '{MARKER} env {name}'; printenv {name}; fi\n"
+		));
+	}
+	script
+}
+
+/// The PowerShell counterpart of `posix_script`, for Windows without Git
+/// Bash.
+#[cfg(target_os = "windows")]
+fn powershell_script() -> String {
+	let mut script = String::from("$ErrorActionPreference = 'SilentlyContinue'\n");
+	for (name, programs) in TOOLCHAINS {
+		let programs = programs
+			.iter()
+			.map(|program| format!("'{program}'"))
+			.collect::<Vec<_>>()
+			.join(",");
+		script.push_str(&format!(
+			"foreach ($p in @({programs})) {{ $c = Get-Command $p -CommandType Application | Select-Object -First 1; \
+			 if ($c) {{ '{MARKER} tool {name}'; $p; $c.Source; \
+			 (& $c.Source --version 2>&1 | Select-Object -First 1) -as [string]; break }} }}\n"
+		));
+	}
+	script.push_str(&format!("'{MARKER} shell'; 'powershell'\n"));
+	script.push_str(&format!(
+		"'{MARKER} system'; [Environment]::OSVersion.VersionString\n"
+	));
+	for name in ENV_VARS {
+		script.push_str(&format!(
+			"$v = [Environment]::GetEnvironmentVariable('{name}'); if ($v -ne $null) {{ '{MARKER} env {name}'; $v }}\n"
+		));
+	}
+	script
+}
+
+/// Builds the one command that gathers the report in `session`.
+fn build_inspect_command(session: &OsSession) -> Result<Command> {
+	let directory = session.get_working_directory();
+	match session {
+		OsSession::Wsl(wsl_session) => {
+			#[cfg(target_os = "windows")]
+			{
+				let mut cmd =
+					WslCommand::script(&wsl_session.distribution, &posix_script());
+				cmd.current_dir(directory).login();
+				Ok(cmd.build())
+			}
+			#[cfg(not(target_os = "windows"))]
+			{
+				let _ = (wsl_session, directory);
+				Err(anyhow!("WSL is only available on Windows"))
+			}
+		}
+		OsSession::Local(_) => {
+			#[cfg(any(target_os = "macos", target_os = "linux"))]
+			{
+				// A login shell, so the PATH matches the user's terminals
+				let shell =
+					std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+				let mut cmd = Command::new(shell);
+				cmd.args(["-l", "-c", &posix_script()]);
+				cmd.current_dir(directory);
+				Ok(cmd)
+			}
+			#[cfg(target_os = "windows")]
+			{
+				let git_bash = "C:\\Program Files\\Git\\bin\\bash.exe";
+				let mut cmd = if std::path::Path::new(git_bash).exists() {
+					let mut cmd = Command::new(git_bash);
+					cmd.args(["--login", "-c", &posix_script()]);
+					cmd
+				} else {
+					let mut cmd = Command::new("powershell.exe");
+					cmd.args(["-NoProfile", "-Command", &powershell_script()]);
+					cmd
+				};
+				cmd.current_dir(directory);
+				Ok(cmd)
+			}
+		}
+	}
+}
+
+/// The version number in a `--version` line: "v20.11.0", "go1.22.1" and
+/// "git version 2.43.0" all give the digits and dots.
+fn parse_version(details: &str) -> Option<String> {
+	details.split_whitespace().find_map(|word| {
+		let word = word.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+		let version: String = word
+			.chars()
+			.take_while(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-')
+			.collect();
+		let version = version.trim_end_matches(['.', '-']);
+		(version.starts_with(|c: char| c.is_ascii_digit()) && version.contains('.'))
+			.then(|| version.to_string())
+	})
+}
+
+fn parse_report(output: &str) -> EnvironmentReport {
+	let mut found: BTreeMap<String, Vec<String>> = BTreeMap::new();
+	let mut shell = None;
+	let mut system = None;
+	let mut env = BTreeMap::new();
+
+	// Splits the output into the sections the script marked
+	let mut sections: Vec<(&str, Vec<&str>)> = Vec::new();
+	for line in output.lines() {
+		let line = line.trim_end_matches('\r');
+		match line.strip_prefix(MARKER) {
+			Some(header) => sections.push((header.trim(), Vec::new())),
+			None => {
+				if let Some((_, lines)) = sections.last_mut() {
+					lines.push(line);
+				}
+			}
+		}
+	}
+
+	for (header, lines) in sections {
+		let first = lines.first().map(|line| line.trim().to_string());
+		let mut words = header.splitn(2, ' ');
+		match (words.next(), words.next()) {
+			(Some("tool"), Some(name)) => {
+				found.insert(
+					name.to_string(),
+					lines.iter().map(|line| line.trim().to_string()).collect(),
+				);
+			}
+			(Some("shell"), None) => shell = first.filter(|shell| !shell.is_empty()),
+			(Some("system"), None) => system = first.filter(|system| !system.is_empty()),
+			(Some("env"), Some(name)) => {
+				env.insert(name.to_string(), lines.join("\n"));
+			}
+			_ => {}
+		}
+	}
+
+	let toolchains = TOOLCHAINS
+		.iter()
+		.map(|(name, _)| {
+			let lines = found.remove(*name).unwrap_or_default();
+			let field =
+				|index: usize| lines.get(index).filter(|line| !line.is_empty()).cloned();
+			let details = field(2);
+			Toolchain {
+				name: name.to_string(),
+				program: field(0),
+				path: field(1),
+				version: details.as_deref().and_then(parse_version),
+				details,
+			}
+		})
+		.collect();
+
+	EnvironmentReport {
+		toolchains,
+		shell,
+		system,
+		env,
+	}
+}
+
+pub fn inspect(session: &OsSession) -> Result<EnvironmentReport> {
+	let output = build_inspect_command(session)?
+		.output()
+		.map_err(|e| anyhow!("Failed to inspect the environment: {}", e))?;
+	// A failing profile or tool shouldn't hide what was found, so the exit
+	// status only matters when nothing was printed
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	if !output.status.success() && !stdout.contains(MARKER) {
+		return Err(anyhow!(
+			"Failed to inspect the environment: {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(parse_report(&stdout))
+}
+
+/// Reports the toolchains, shell and notable environment variables of
+/// `os_session`, gathered with a single shell invocation.
+#[tauri::command]
+pub async fn inspect_os_session(
+	os_session: OsSession,
+) -> Result<EnvironmentReport, String> {
+	tauri::async_runtime::spawn_blocking(move || inspect(&os_session))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
@@ -0,0 +1,369 @@
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::os::OsSession;
+#[cfg(target_os = "macos")]
+use crate::util::shell_quote;
+#[cfg(target_os = "windows")]
+use crate::wsl::{self, WslCommand};
+
+/// Store holding the external editor setting.
+const EXTERNAL_APPS_STORE: &str = "external_apps.json";
+
+/// The editor `open_in_external_editor` starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalEditor {
+	/// Program to run, e.g. `code` or `vim`.
+	pub command: String,
+	/// `{path}` and `{line}` are replaced; the path is added last when no
+	/// argument mentions it.
+	#[serde(default)]
+	pub args: Vec<String>,
+	/// Run in a terminal window, for editors such as vim.
+	#[serde(default)]
+	pub terminal: bool,
+}
+
+impl Default for ExternalEditor {
+	fn default() -> Self {
+		Self {
+			command: "code".to_string(),
+			args: vec!["--goto".to_string(), "{path}:{line}".to_string()],
+			terminal: false,
+		}
+	}
+}
+
+impl ExternalEditor {
+	fn args_for(&self, path: &str, line: Option<u32>) -> Vec<String> {
+		let line = line.unwrap_or(1).to_string();
+		let mut args: Vec<String> = self
+			.args
+			.iter()
+			.map(|arg| arg.replace("{path}", path).replace("{line}", &line))
+			.collect();
+		if !self.args.iter().any(|arg| arg.contains("{path}")) {
+			args.push(path.to_string());
+		}
+		args
+	}
+}
+
+pub fn editor(app_handle: &AppHandle) -> Result<ExternalEditor> {
+	let store = app_handle.store(EXTERNAL_APPS_STORE)?;
+	Ok(store
+		.get("editor")
+		.and_then(|v| serde_json::from_value(v).ok())
+		.unwrap_or_default())
+}
+
+pub fn set_editor(app_handle: &AppHandle, editor: &ExternalEditor) -> Result<()> {
+	let store = app_handle.store(EXTERNAL_APPS_STORE)?;
+	store.set("editor", serde_json::to_value(editor)?);
+	store.save()?;
+	Ok(())
+}
+
+/// Starts `cmd` without waiting for it, reaping it when it exits.
+fn spawn_detached(mut cmd: Command) -> Result<()> {
+	let mut child = cmd
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.spawn()?;
+	std::thread::spawn(move || child.wait());
+	Ok(())
+}
+
+/// Like `spawn_detached`, also trying `program.cmd`: tools such as `code`
+/// are batch files on Windows, which aren't found without the extension.
+#[cfg(target_os = "windows")]
+fn spawn_program(program: &str, args: &[String]) -> Result<()> {
+	let mut cmd = Command::new(program);
+	cmd.args(args);
+	match spawn_detached(cmd) {
+		Err(e) if !program.contains('.') => {
+			let mut cmd = Command::new(format!("{}.cmd", program));
+			cmd.args(args);
+			spawn_detached(cmd).map_err(|_| e)
+		}
+		result => result,
+	}
+}
+
+#[cfg(not(target_os = "windows"))]
+fn spawn_program(program: &str, args: &[String]) -> Result<()> {
+	let mut cmd = Command::new(program);
+	cmd.args(args);
+	spawn_detached(cmd)
+}
+
+/// Opens a terminal window in `directory`, running `program` with `args`
+/// instead of a shell if given.
+fn open_terminal(
+	session: &OsSession,
+	directory: &str,
+	program: Option<(&str, &[String])>,
+) -> Result<()> {
+	#[cfg(target_os = "windows")]
+	{
+		use std::os::windows::process::CommandExt;
+		const CREATE_NEW_CONSOLE: u32 = 0x0000_0010;
+
+		let (local_directory, argv): (Option<&str>, Vec<String>) = match session {
+			OsSession::Local(_) => (
+				Some(directory),
+				program
+					.map(|(program, args)| {
+						std::iter::once(program.to_string())
+							.chain(args.iter().cloned())
+							.collect()
+					})
+					.unwrap_or_default(),
+			),
+			OsSession::Wsl(wsl_session) => {
+				let distribution = &wsl_session.distribution;
+				let mut wsl_args = vec![
+					"wsl".to_string(),
+					"-d".to_string(),
+					distribution.clone(),
+					"--cd".to_string(),
+					wsl::to_wsl_path(distribution, directory),
+				];
+				if let Some((program, args)) = program {
+					wsl_args.extend(["--exec".to_string(), program.to_string()]);
+					wsl_args.extend(args.iter().cloned());
+				}
+				(None, wsl_args)
+			}
+		};
+
+		// Windows Terminal when it's installed
+		let mut wt = Command::new("wt");
+		if let Some(directory) = local_directory {
+			wt.args(["-d", directory]);
+		}
+		if !argv.is_empty() {
+			wt.arg("--").args(&argv);
+		}
+		if spawn_detached(wt).is_ok() {
+			return Ok(());
+		}
+
+		// Otherwise a console window of its own
+		let (program, args) = match argv.split_first() {
+			Some((program, args)) => (program.as_str(), args),
+			None => ("cmd", &[][..]),
+		};
+		let mut cmd = Command::new(program);
+		cmd.args(args).creation_flags(CREATE_NEW_CONSOLE);
+		if let Some(directory) = local_directory {
+			cmd.current_dir(directory);
+		}
+		spawn_detached(cmd).map_err(|e| anyhow!("Failed to open a terminal: {}", e))
+	}
+
+	#[cfg(target_os = "macos")]
+	{
+		if let OsSession::Wsl(_) = session {
+			return Err(anyhow!("WSL is only available on Windows"));
+		}
+		let Some((program, args)) = program else {
+			let mut cmd = Command::new("open");
+			cmd.args(["-a", "Terminal", directory]);
+			return spawn_detached(cmd).context("Failed to open Terminal");
+		};
+		let script = std::iter::once(program)
+			.chain(args.iter().map(String::as_str))
+			.map(shell_quote)
+			.collect::<Vec<_>>()
+			.join(" ");
+		let script = format!("cd {} && {}", shell_quote(directory), script);
+		let apple_script = format!(
+			"tell application \"Terminal\"\nactivate\ndo script \"{}\"\nend tell",
+			script.replace('\\', "\\\\").replace('"', "\\\"")
+		);
+		let mut cmd = Command::new("osascript");
+		cmd.args(["-e", &apple_script]);
+		spawn_detached(cmd).context("Failed to open Terminal")
+	}
+
+	#[cfg(target_os = "linux")]
+	{
+		if let OsSession::Wsl(_) = session {
+			return Err(anyhow!("WSL is only available on Windows"));
+		}
+		// Each terminal with the option that makes it run a program
+		let terminals = [
+			("x-terminal-emulator", Some("-e")),
+			("gnome-terminal", Some("--")),
+			("konsole", Some("-e")),
+			("xfce4-terminal", Some("-x")),
+			("alacritty", Some("-e")),
+			("kitty", None),
+			("xterm", Some("-e")),
+		];
+		for (terminal, run_flag) in terminals {
+			let mut cmd = Command::new(terminal);
+			cmd.current_dir(directory);
+			if let Some((program, args)) = program {
+				cmd.args(run_flag).arg(program).args(args);
+			}
+			if spawn_detached(cmd).is_ok() {
+				return Ok(());
+			}
+		}
+		Err(anyhow!("No terminal emulator found"))
+	}
+}
+
+/// Opens `path` in the configured editor at `line`. In WSL sessions the
+/// editor runs inside the distribution, where `code` opens a remote window.
+pub fn open_in_editor(
+	editor: &ExternalEditor,
+	session: &OsSession,
+	path: &str,
+	line: Option<u32>,
+) -> Result<()> {
+	let args = editor.args_for(path, line);
+	if editor.terminal {
+		let directory = std::path::Path::new(path)
+			.parent()
+			.map(|parent| parent.to_string_lossy().to_string())
+			.filter(|parent| !parent.is_empty())
+			.unwrap_or_else(|| session.get_working_directory().to_string());
+		return open_terminal(session, &directory, Some((&editor.command, &args)));
+	}
+	match session {
+		OsSession::Local(_) => spawn_program(&editor.command, &args)
+			.with_context(|| format!("Failed to start {}", editor.command)),
+		OsSession::Wsl(wsl_session) => {
+			#[cfg(target_os = "windows")]
+			{
+				let mut cmd = WslCommand::new(&wsl_session.distribution, &editor.command);
+				cmd.login().args(&args);
+				spawn_detached(cmd.build())
+					.with_context(|| format!("Failed to start {}", editor.command))
+			}
+			#[cfg(not(target_os = "windows"))]
+			{
+				let _ = wsl_session;
+				Err(anyhow!("WSL is only available on Windows"))
+			}
+		}
+	}
+}
+
+/// Opens `path` with the application the OS associates with it.
+pub fn open_with_default(session: &OsSession, path: &str) -> Result<()> {
+	#[cfg(target_os = "windows")]
+	{
+		// Files inside WSL are opened through their Windows form
+		let path = match session {
+			OsSession::Wsl(wsl_session) => {
+				wsl::to_windows_path(&wsl_session.distribution, path)
+			}
+			OsSession::Local(_) => path.replace('/', "\\"),
+		};
+		let mut cmd = Command::new("rundll32");
+		cmd.arg("url.dll,FileProtocolHandler").arg(&path);
+		spawn_detached(cmd).context("Failed to open the default application")
+	}
+
+	#[cfg(target_os = "macos")]
+	{
+		if let OsSession::Wsl(_) = session {
+			return Err(anyhow!("WSL is only available on Windows"));
+		}
+		let output = Command::new("open").arg(path).output()?;
+		if !output.status.success() {
+			return Err(anyhow!(
+				"open failed: {}",
+				String::from_utf8_lossy(&output.stderr).trim()
+			));
+		}
+		Ok(())
+	}
+
+	#[cfg(target_os = "linux")]
+	{
+		if let OsSession::Wsl(_) = session {
+			return Err(anyhow!("WSL is only available on Windows"));
+		}
+		for opener in [&["xdg-open"][..], &["gio", "open"]] {
+			let result = Command::new(opener[0])
+				.args(&opener[1..])
+				.arg(path)
+				.output();
+			if result.is_ok_and(|output| output.status.success()) {
+				return Ok(());
+			}
+		}
+		Err(anyhow!(
+			"Failed to open {} with the default application",
+			path
+		))
+	}
+}
+
+#[tauri::command]
+pub async fn get_external_editor(
+	app_handle: AppHandle,
+) -> Result<ExternalEditor, String> {
+	editor(&app_handle).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_external_editor(
+	editor: ExternalEditor,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	set_editor(&app_handle, &editor).map_err(|e| e.to_string())
+}
+
+/// Opens `path` in the configured external editor, at `line` if given.
+#[tauri::command]
+pub async fn open_in_external_editor(
+	path: String,
+	line: Option<u32>,
+	os_session: OsSession,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	let editor = editor(&app_handle).map_err(|e| e.to_string())?;
+	tauri::async_runtime::spawn_blocking(move || {
+		open_in_editor(&editor, &os_session, &path, line)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Opens a system terminal window in `directory`.
+#[tauri::command]
+pub async fn open_terminal_at(
+	directory: String,
+	os_session: OsSession,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		open_terminal(&os_session, &directory, None)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn open_with_default_app(
+	path: String,
+	os_session: OsSession,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || open_with_default(&os_session, &path))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
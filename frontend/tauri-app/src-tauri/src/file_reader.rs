@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+use serde::Serialize;
+use tauri::State;
+
+/// Largest chunk a single read may return, to keep IPC payloads small.
+const MAX_CHUNK_BYTES: u64 = 4 * 1024 * 1024;
+/// Largest number of lines a single line read may return.
+const MAX_LINES: usize = 10_000;
+/// How much of the file is sampled to decide whether it is binary.
+const BINARY_SAMPLE_BYTES: usize = 8 * 1024;
+/// The line index records the offset of every `LINE_INDEX_STRIDE`th line;
+/// lines in between are found by scanning forward from the nearest entry.
+const LINE_INDEX_STRIDE: u64 = 128;
+const HEX_ROW_BYTES: usize = 16;
+
+/// One row of a hex dump: 16 bytes at `offset`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HexRow {
+	pub offset: u64,
+	pub hex: String,
+	pub ascii: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "mode")]
+pub enum ChunkContent {
+	/// Lossily decoded UTF-8 text.
+	Text { text: String },
+	/// Hex dump for binary files.
+	Hex { rows: Vec<HexRow> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChunk {
+	pub offset: u64,
+	/// Bytes covered by this chunk. Text chunks may be slightly shorter than
+	/// requested so they never end inside a UTF-8 character; continue reading
+	/// at `offset + length`.
+	pub length: u64,
+	pub total_size: u64,
+	pub binary: bool,
+	#[serde(flatten)]
+	pub content: ChunkContent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineIndexInfo {
+	pub line_count: u64,
+	pub total_size: u64,
+	pub binary: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileLines {
+	pub start_line: u64,
+	/// Byte offset of `start_line`.
+	pub start_offset: u64,
+	/// Lines without their terminators.
+	pub lines: Vec<String>,
+	pub line_count: u64,
+}
+
+/// The file's bytes, memory mapped. Empty files can't be mapped on every
+/// platform, so they are represented without a map.
+struct MappedFile {
+	map: Option<Mmap>,
+}
+
+impl MappedFile {
+	fn open(path: &Path) -> Result<Self> {
+		let file = File::open(path)?;
+		if file.metadata()?.len() == 0 {
+			return Ok(Self { map: None });
+		}
+		// Safety: the map is only read, and lives no longer than one command.
+		// If another process truncates the file meanwhile we may read zeros,
+		// which is acceptable for a viewer.
+		let map = unsafe { Mmap::map(&file)? };
+		Ok(Self { map: Some(map) })
+	}
+
+	fn bytes(&self) -> &[u8] {
+		self.map.as_deref().unwrap_or_default()
+	}
+}
+
+/// Heuristic used by most editors: a NUL byte, or a high share of control
+/// characters, in the first few KB means the file is binary.
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+	let sample = &bytes[..bytes.len().min(BINARY_SAMPLE_BYTES)];
+	if sample.contains(&0) {
+		return true;
+	}
+	let control = sample
+		.iter()
+		.filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0c | 0x1b))
+		.count();
+	control * 10 > sample.len()
+}
+
+fn hex_rows(bytes: &[u8], base_offset: u64) -> Vec<HexRow> {
+	bytes
+		.chunks(HEX_ROW_BYTES)
+		.enumerate()
+		.map(|(i, row)| HexRow {
+			offset: base_offset + (i * HEX_ROW_BYTES) as u64,
+			hex: row
+				.iter()
+				.map(|b| format!("{:02x}", b))
+				.collect::<Vec<_>>()
+				.join(" "),
+			ascii: row
+				.iter()
+				.map(|&b| {
+					if b.is_ascii_graphic() || b == b' ' {
+						b as char
+					} else {
+						'.'
+					}
+				})
+				.collect(),
+		})
+		.collect()
+}
+
+/// Length of `bytes` without a trailing incomplete UTF-8 character, so a
+/// chunk boundary never splits one. Other invalid bytes are left for lossy
+/// decoding.
+fn utf8_boundary(bytes: &[u8]) -> usize {
+	for back in 1..=bytes.len().min(4) {
+		let start = bytes.len() - back;
+		let width = match bytes[start] {
+			0x80..=0xbf => continue,
+			0xc0..=0xdf => 2,
+			0xe0..=0xef => 3,
+			0xf0..=0xf7 => 4,
+			_ => 1,
+		};
+		return if back < width { start } else { bytes.len() };
+	}
+	bytes.len()
+}
+
+fn read_chunk(path: &Path, offset: u64, length: u64) -> Result<FileChunk> {
+	let file = MappedFile::open(path)?;
+	let bytes = file.bytes();
+	let total_size = bytes.len() as u64;
+	if offset > total_size {
+		return Err(anyhow!(
+			"Offset {} is past the end of the file ({} bytes)",
+			offset,
+			total_size
+		));
+	}
+
+	let end = (offset + length.min(MAX_CHUNK_BYTES)).min(total_size);
+	let chunk = &bytes[offset as usize..end as usize];
+	let binary = is_binary(bytes);
+
+	let (length, content) = if binary {
+		(
+			chunk.len() as u64,
+			ChunkContent::Hex {
+				rows: hex_rows(chunk, offset),
+			},
+		)
+	} else {
+		let chunk = if end < total_size {
+			&chunk[..utf8_boundary(chunk)]
+		} else {
+			chunk
+		};
+		(
+			chunk.len() as u64,
+			ChunkContent::Text {
+				text: String::from_utf8_lossy(chunk).to_string(),
+			},
+		)
+	};
+
+	Ok(FileChunk {
+		offset,
+		length,
+		total_size,
+		binary,
+		content,
+	})
+}
+
+/// Sparse index of line start offsets, valid while the file's size and
+/// modification time are unchanged.
+struct LineIndex {
+	size: u64,
+	modified: Option<SystemTime>,
+	binary: bool,
+	line_count: u64,
+	/// `checkpoints[i]` is the byte offset of line `i * LINE_INDEX_STRIDE`.
+	checkpoints: Vec<u64>,
+}
+
+impl LineIndex {
+	fn build(path: &Path) -> Result<Self> {
+		let metadata = std::fs::metadata(path)?;
+		let file = MappedFile::open(path)?;
+		let bytes = file.bytes();
+
+		let mut checkpoints = vec![0];
+		let mut line_count = 1;
+		for (i, &b) in bytes.iter().enumerate() {
+			if b == b'\n' {
+				if line_count % LINE_INDEX_STRIDE == 0 {
+					checkpoints.push(i as u64 + 1);
+				}
+				line_count += 1;
+			}
+		}
+		// A trailing newline ends the last line rather than starting a new one
+		if bytes.last() == Some(&b'\n') {
+			line_count -= 1;
+		}
+
+		Ok(Self {
+			size: bytes.len() as u64,
+			modified: metadata.modified().ok(),
+			binary: is_binary(bytes),
+			line_count,
+			checkpoints,
+		})
+	}
+
+	fn is_current(&self, path: &Path) -> bool {
+		std::fs::metadata(path)
+			.map(|m| m.len() == self.size && m.modified().ok() == self.modified)
+			.unwrap_or(false)
+	}
+
+	fn info(&self) -> LineIndexInfo {
+		LineIndexInfo {
+			line_count: self.line_count,
+			total_size: self.size,
+			binary: self.binary,
+		}
+	}
+
+	fn read_lines(
+		&self,
+		path: &Path,
+		start_line: u64,
+		count: usize,
+	) -> Result<FileLines> {
+		let file = MappedFile::open(path)?;
+		let bytes = file.bytes();
+		let start_line = start_line.min(self.line_count);
+
+		// Jump to the nearest checkpoint, then scan forward to the line
+		let checkpoint = (start_line / LINE_INDEX_STRIDE) as usize;
+		let mut position =
+			self.checkpoints.get(checkpoint).copied().unwrap_or(0) as usize;
+		for _ in 0..start_line % LINE_INDEX_STRIDE {
+			match bytes[position..].iter().position(|&b| b == b'\n') {
+				Some(newline) => position += newline + 1,
+				None => position = bytes.len(),
+			}
+		}
+		let start_offset = position as u64;
+
+		let mut lines = Vec::new();
+		while lines.len() < count.min(MAX_LINES) && position < bytes.len() {
+			let end = bytes[position..]
+				.iter()
+				.position(|&b| b == b'\n')
+				.map(|newline| position + newline)
+				.unwrap_or(bytes.len());
+			let line = &bytes[position..end];
+			let line = line.strip_suffix(b"\r").unwrap_or(line);
+			lines.push(String::from_utf8_lossy(line).to_string());
+			position = end + 1;
+		}
+
+		Ok(FileLines {
+			start_line,
+			start_offset,
+			lines,
+			line_count: self.line_count,
+		})
+	}
+}
+
+/// Serves large files to the webview a piece at a time instead of loading
+/// them whole. Line indexes are cached per path until the file changes.
+pub struct FileReaderManager {
+	indexes: Mutex<HashMap<PathBuf, Arc<LineIndex>>>,
+}
+
+impl FileReaderManager {
+	pub fn new() -> Self {
+		Self {
+			indexes: Mutex::new(HashMap::new()),
+		}
+	}
+
+	fn line_index(&self, path: &Path) -> Result<Arc<LineIndex>> {
+		if let Some(index) = self.indexes.lock().unwrap().get(path) {
+			if index.is_current(path) {
+				return Ok(index.clone());
+			}
+		}
+
+		let index = Arc::new(LineIndex::build(path)?);
+		self.indexes
+			.lock()
+			.unwrap()
+			.insert(path.to_path_buf(), index.clone());
+		Ok(index)
+	}
+
+	pub fn build_line_index(&self, path: &Path) -> Result<LineIndexInfo> {
+		Ok(self.line_index(path)?.info())
+	}
+
+	pub fn read_lines(
+		&self,
+		path: &Path,
+		start_line: u64,
+		count: usize,
+	) -> Result<FileLines> {
+		self.line_index(path)?.read_lines(path, start_line, count)
+	}
+
+	pub fn forget(&self, path: &Path) {
+		self.indexes.lock().unwrap().remove(path);
+	}
+}
+
+/// Reads up to `length` bytes (at most 4 MiB) starting at `offset`. Binary
+/// files come back as a hex dump.
+#[tauri::command]
+pub async fn read_file_chunk(
+	path: String,
+	offset: u64,
+	length: u64,
+) -> Result<FileChunk, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		read_chunk(Path::new(&path), offset, length)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Indexes line offsets so `read_file_lines` can jump anywhere in the file.
+#[tauri::command]
+pub async fn build_line_index(
+	path: String,
+	manager: State<'_, Arc<FileReaderManager>>,
+) -> Result<LineIndexInfo, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		manager.build_line_index(Path::new(&path))
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Reads `count` lines (at most 10,000) starting at the zero-based
+/// `start_line`, building the line index first if needed.
+#[tauri::command]
+pub async fn read_file_lines(
+	path: String,
+	start_line: u64,
+	count: usize,
+	manager: State<'_, Arc<FileReaderManager>>,
+) -> Result<FileLines, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		manager.read_lines(Path::new(&path), start_line, count)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Drops the cached line index of a file the viewer closed.
+#[tauri::command]
+pub async fn close_line_index(
+	path: String,
+	manager: State<'_, Arc<FileReaderManager>>,
+) -> Result<(), String> {
+	manager.forget(Path::new(&path));
+	Ok(())
+}
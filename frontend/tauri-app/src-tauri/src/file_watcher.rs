@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::broadcast;
+
+/// A change to a watched path, as reported by the OS.
+#[derive(Debug, Clone)]
+pub struct FileChange {
+	pub path: PathBuf,
+	pub kind: FileChangeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChangeKind {
+	Created,
+	Modified,
+	Removed,
+}
+
+/// Shared filesystem watcher. Paths are reference counted so several
+/// subscribers (open documents, indexers, ...) can watch the same file, and
+/// every change is broadcast to all subscribers, who filter what they need.
+pub struct FileWatcher {
+	watcher: Mutex<RecommendedWatcher>,
+	watched: Mutex<HashMap<PathBuf, usize>>,
+	sender: broadcast::Sender<FileChange>,
+}
+
+impl FileWatcher {
+	pub fn new() -> Result<Arc<Self>> {
+		let (sender, _) = broadcast::channel(1024);
+		let event_sender = sender.clone();
+
+		let watcher =
+			notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+				let Ok(event) = res else {
+					return;
+				};
+				let kind = match event.kind {
+					EventKind::Create(_) => FileChangeKind::Created,
+					EventKind::Modify(_) => FileChangeKind::Modified,
+					EventKind::Remove(_) => FileChangeKind::Removed,
+					_ => return,
+				};
+				for path in event.paths {
+					// No receivers is fine, nobody is interested right now
+					let _ = event_sender.send(FileChange { path, kind });
+				}
+			})?;
+
+		Ok(Arc::new(Self {
+			watcher: Mutex::new(watcher),
+			watched: Mutex::new(HashMap::new()),
+			sender,
+		}))
+	}
+
+	pub fn subscribe(&self) -> broadcast::Receiver<FileChange> {
+		self.sender.subscribe()
+	}
+
+	pub fn watch(&self, path: &Path, recursive: bool) -> Result<()> {
+		let mut watched = self.watched.lock().unwrap();
+		let count = watched.entry(path.to_path_buf()).or_insert(0);
+		if *count == 0 {
+			let mode = if recursive {
+				RecursiveMode::Recursive
+			} else {
+				RecursiveMode::NonRecursive
+			};
+			self.watcher.lock().unwrap().watch(path, mode)?;
+		}
+		*count += 1;
+		Ok(())
+	}
+
+	pub fn unwatch(&self, path: &Path) -> Result<()> {
+		let mut watched = self.watched.lock().unwrap();
+		let Some(count) = watched.get_mut(path) else {
+			return Ok(());
+		};
+		*count -= 1;
+		if *count == 0 {
+			watched.remove(path);
+			// The path may already be gone, which also ends the watch
+			let _ = self.watcher.lock().unwrap().unwatch(path);
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,705 @@
+use anyhow::{anyhow, Result};
+use reqwest::{Client, Method, RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::git::run_git;
+use crate::os::OsSession;
+use crate::util::error_from;
+
+/// Store holding an access token for each forge host.
+const FORGE_STORE: &str = "forge.json";
+
+const USER_AGENT: &str = concat!("ariana-ide/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+	GitHub,
+	GitLab,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ForgeCredentials {
+	kind: ForgeKind,
+	token: String,
+	username: String,
+}
+
+/// A host with a stored token. The token itself never leaves the backend.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgeAccount {
+	pub host: String,
+	pub kind: ForgeKind,
+	pub username: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PullRequestState {
+	Open,
+	Closed,
+	Merged,
+}
+
+/// A GitHub pull request or GitLab merge request.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequest {
+	/// The number shown in the UI, `iid` on GitLab.
+	pub number: u64,
+	pub title: String,
+	pub state: PullRequestState,
+	pub draft: bool,
+	pub url: String,
+	pub author: String,
+	pub source_branch: String,
+	pub target_branch: String,
+	pub created_at: String,
+	pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewPullRequest {
+	pub title: String,
+	pub body: Option<String>,
+	/// The branch to merge into, the repository's default branch if unset.
+	pub base: Option<String>,
+	#[serde(default)]
+	pub draft: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewComment {
+	pub id: u64,
+	pub author: String,
+	pub body: String,
+	/// Set for comments on a line of the diff.
+	pub path: Option<String>,
+	pub line: Option<u64>,
+	pub url: Option<String>,
+	pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issue {
+	pub number: u64,
+	pub title: String,
+	pub url: String,
+	pub author: String,
+	pub labels: Vec<String>,
+	pub created_at: String,
+	pub updated_at: String,
+}
+
+/// The repository a remote points at, e.g. `github.com` and `owner/repo`.
+#[derive(Debug, Clone)]
+struct ForgeRepo {
+	host: String,
+	path: String,
+}
+
+impl ForgeRepo {
+	/// Parses `git@host:owner/repo.git`, `ssh://git@host:22/owner/repo` and
+	/// `https://host/owner/repo.git`.
+	fn from_remote_url(url: &str) -> Option<Self> {
+		let url = url.trim();
+		let (host, path) = if let Some((_, rest)) = url.split_once("://") {
+			let (authority, path) = rest.split_once('/')?;
+			let host = authority.rsplit('@').next()?;
+			// SSH ports aren't the web port
+			let host = if url.starts_with("ssh://") {
+				host.split(':').next()?
+			} else {
+				host
+			};
+			(host, path)
+		} else {
+			let (authority, path) = url.split_once(':')?;
+			(authority.rsplit('@').next()?, path)
+		};
+		let path = path.trim_matches('/').trim_end_matches(".git");
+		if host.is_empty() || !path.contains('/') {
+			return None;
+		}
+		Some(Self {
+			host: host.to_lowercase(),
+			path: path.to_string(),
+		})
+	}
+}
+
+fn api_base(host: &str, kind: ForgeKind) -> String {
+	match kind {
+		ForgeKind::GitHub if host == "github.com" => "https://api.github.com".to_string(),
+		ForgeKind::GitHub => format!("https://{}/api/v3", host),
+		ForgeKind::GitLab => format!("https://{}/api/v4", host),
+	}
+}
+
+/// Authenticated client for one forge host.
+struct ForgeClient {
+	http: Client,
+	kind: ForgeKind,
+	base_url: String,
+	token: String,
+	username: String,
+}
+
+impl ForgeClient {
+	fn new(host: &str, kind: ForgeKind, token: String, username: String) -> Self {
+		Self {
+			http: Client::new(),
+			kind,
+			base_url: api_base(host, kind),
+			token,
+			username,
+		}
+	}
+
+	fn for_host(app_handle: &AppHandle, host: &str) -> Result<Self> {
+		let credentials = credentials(app_handle, host)?.ok_or_else(|| {
+			anyhow!(
+				"No access token for {}; add one in the forge settings",
+				host
+			)
+		})?;
+		Ok(Self::new(
+			host,
+			credentials.kind,
+			credentials.token,
+			credentials.username,
+		))
+	}
+
+	/// Starts a request to `segments` under the API root. Segments are
+	/// escaped, so a GitLab project path can be passed as one.
+	fn request(&self, method: Method, segments: &[&str]) -> Result<RequestBuilder> {
+		let mut url = Url::parse(&self.base_url)?;
+		url.path_segments_mut()
+			.map_err(|_| anyhow!("Invalid forge URL {}", self.base_url))?
+			.extend(segments);
+		let request = self
+			.http
+			.request(method, url)
+			.header(reqwest::header::USER_AGENT, USER_AGENT);
+		Ok(match self.kind {
+			ForgeKind::GitHub => request
+				.bearer_auth(&self.token)
+				.header(reqwest::header::ACCEPT, "application/vnd.github+json")
+				.header("X-GitHub-Api-Version", "2022-11-28"),
+			ForgeKind::GitLab => request.header("PRIVATE-TOKEN", &self.token),
+		})
+	}
+
+	async fn send(&self, request: RequestBuilder) -> Result<Value> {
+		let response = request.send().await?;
+		if !response.status().is_success() {
+			return Err(error_from(response, "Forge request").await);
+		}
+		Ok(response.json().await?)
+	}
+}
+
+fn credentials(app_handle: &AppHandle, host: &str) -> Result<Option<ForgeCredentials>> {
+	let store = app_handle.store(FORGE_STORE)?;
+	Ok(store
+		.get(host)
+		.and_then(|value| serde_json::from_value(value).ok()))
+}
+
+fn str_field(value: &Value, pointer: &str) -> String {
+	value
+		.pointer(pointer)
+		.and_then(Value::as_str)
+		.unwrap_or_default()
+		.to_string()
+}
+
+fn u64_field(value: &Value, pointer: &str) -> Option<u64> {
+	value.pointer(pointer).and_then(Value::as_u64)
+}
+
+fn pull_request_from(kind: ForgeKind, value: &Value) -> PullRequest {
+	match kind {
+		ForgeKind::GitHub => PullRequest {
+			number: u64_field(value, "/number").unwrap_or(0),
+			title: str_field(value, "/title"),
+			state: if value.get("merged_at").is_some_and(|v| !v.is_null()) {
+				PullRequestState::Merged
+			} else if str_field(value, "/state") == "open" {
+				PullRequestState::Open
+			} else {
+				PullRequestState::Closed
+			},
+			draft: value.get("draft").and_then(Value::as_bool).unwrap_or(false),
+			url: str_field(value, "/html_url"),
+			author: str_field(value, "/user/login"),
+			source_branch: str_field(value, "/head/ref"),
+			target_branch: str_field(value, "/base/ref"),
+			created_at: str_field(value, "/created_at"),
+			updated_at: str_field(value, "/updated_at"),
+		},
+		ForgeKind::GitLab => PullRequest {
+			number: u64_field(value, "/iid").unwrap_or(0),
+			title: str_field(value, "/title"),
+			state: match str_field(value, "/state").as_str() {
+				"opened" => PullRequestState::Open,
+				"merged" => PullRequestState::Merged,
+				_ => PullRequestState::Closed,
+			},
+			draft: value.get("draft").and_then(Value::as_bool).unwrap_or(false),
+			url: str_field(value, "/web_url"),
+			author: str_field(value, "/author/username"),
+			source_branch: str_field(value, "/source_branch"),
+			target_branch: str_field(value, "/target_branch"),
+			created_at: str_field(value, "/created_at"),
+			updated_at: str_field(value, "/updated_at"),
+		},
+	}
+}
+
+fn array(value: Value) -> Vec<Value> {
+	match value {
+		Value::Array(items) => items,
+		_ => Vec::new(),
+	}
+}
+
+/// The forge repository behind `remote` and the branch checked out in
+/// `directory`, if any.
+async fn repo_context(
+	directory: String,
+	os_session: OsSession,
+	remote: Option<String>,
+) -> Result<(ForgeRepo, Option<String>)> {
+	tauri::async_runtime::spawn_blocking(move || {
+		let remote = remote.unwrap_or_else(|| "origin".to_string());
+		let url = run_git(&os_session, &directory, &["remote", "get-url", &remote])?;
+		let repo = ForgeRepo::from_remote_url(&url).ok_or_else(|| {
+			anyhow!(
+				"Remote {} is not a forge repository: {}",
+				remote,
+				url.trim()
+			)
+		})?;
+		let branch = run_git(
+			&os_session,
+			&directory,
+			&["rev-parse", "--abbrev-ref", "HEAD"],
+		)?
+		.trim()
+		.to_string();
+		Ok((repo, (branch != "HEAD").then_some(branch)))
+	})
+	.await?
+}
+
+fn checked_out(branch: Option<String>) -> Result<String> {
+	branch.ok_or_else(|| anyhow!("No branch is checked out"))
+}
+
+async fn default_branch(client: &ForgeClient, repo: &ForgeRepo) -> Result<String> {
+	let project = match client.kind {
+		ForgeKind::GitHub => {
+			let (owner, name) = repo.path.split_once('/').unwrap_or_default();
+			client
+				.send(client.request(Method::GET, &["repos", owner, name])?)
+				.await?
+		}
+		ForgeKind::GitLab => {
+			client
+				.send(client.request(Method::GET, &["projects", &repo.path])?)
+				.await?
+		}
+	};
+	project
+		.get("default_branch")
+		.and_then(Value::as_str)
+		.map(str::to_string)
+		.ok_or_else(|| anyhow!("{} has no default branch", repo.path))
+}
+
+async fn list_pull_requests(
+	client: &ForgeClient,
+	repo: &ForgeRepo,
+	branch: &str,
+) -> Result<Vec<PullRequest>> {
+	let items = match client.kind {
+		ForgeKind::GitHub => {
+			let (owner, name) = repo.path.split_once('/').unwrap_or_default();
+			let head = format!("{}:{}", owner, branch);
+			client
+				.send(
+					client
+						.request(Method::GET, &["repos", owner, name, "pulls"])?
+						.query(&[
+							("head", head.as_str()),
+							("state", "all"),
+							("per_page", "100"),
+						]),
+				)
+				.await?
+		}
+		ForgeKind::GitLab => {
+			client
+				.send(
+					client
+						.request(
+							Method::GET,
+							&["projects", &repo.path, "merge_requests"],
+						)?
+						.query(&[
+							("source_branch", branch),
+							("state", "all"),
+							("per_page", "100"),
+						]),
+				)
+				.await?
+		}
+	};
+	Ok(array(items)
+		.iter()
+		.map(|item| pull_request_from(client.kind, item))
+		.collect())
+}
+
+async fn create_pull_request(
+	client: &ForgeClient,
+	repo: &ForgeRepo,
+	branch: &str,
+	base: &str,
+	request: &NewPullRequest,
+) -> Result<PullRequest> {
+	let (title, body) = (&request.title, request.body.as_deref().unwrap_or_default());
+	let created = match client.kind {
+		ForgeKind::GitHub => {
+			let (owner, name) = repo.path.split_once('/').unwrap_or_default();
+			client
+				.send(
+					client
+						.request(Method::POST, &["repos", owner, name, "pulls"])?
+						.json(&json!({
+							"title": title,
+							"body": body,
+							"head": branch,
+							"base": base,
+							"draft": request.draft,
+						})),
+				)
+				.await?
+		}
+		ForgeKind::GitLab => {
+			// GitLab marks drafts by title
+			let title = if request.draft && !title.starts_with("Draft:") {
+				format!("Draft: {}", title)
+			} else {
+				title.to_string()
+			};
+			client
+				.send(
+					client
+						.request(
+							Method::POST,
+							&["projects", &repo.path, "merge_requests"],
+						)?
+						.json(&json!({
+							"title": title,
+							"description": body,
+							"source_branch": branch,
+							"target_branch": base,
+						})),
+				)
+				.await?
+		}
+	};
+	Ok(pull_request_from(client.kind, &created))
+}
+
+async fn review_comments(
+	client: &ForgeClient,
+	repo: &ForgeRepo,
+	number: u64,
+) -> Result<Vec<ReviewComment>> {
+	let number = number.to_string();
+	Ok(match client.kind {
+		ForgeKind::GitHub => {
+			let (owner, name) = repo.path.split_once('/').unwrap_or_default();
+			let items = client
+				.send(
+					client
+						.request(
+							Method::GET,
+							&["repos", owner, name, "pulls", &number, "comments"],
+						)?
+						.query(&[("per_page", "100")]),
+				)
+				.await?;
+			array(items)
+				.iter()
+				.map(|item| ReviewComment {
+					id: u64_field(item, "/id").unwrap_or(0),
+					author: str_field(item, "/user/login"),
+					body: str_field(item, "/body"),
+					path: item.get("path").and_then(Value::as_str).map(str::to_string),
+					line: u64_field(item, "/line")
+						.or_else(|| u64_field(item, "/original_line")),
+					url: item
+						.get("html_url")
+						.and_then(Value::as_str)
+						.map(str::to_string),
+					created_at: str_field(item, "/created_at"),
+				})
+				.collect()
+		}
+		ForgeKind::GitLab => {
+			let items = client
+				.send(
+					client
+						.request(
+							Method::GET,
+							&["projects", &repo.path, "merge_requests", &number, "notes"],
+						)?
+						.query(&[("sort", "asc"), ("per_page", "100")]),
+				)
+				.await?;
+			array(items)
+				.iter()
+				// System notes record events such as pushes, not comments
+				.filter(|item| {
+					!item.get("system").and_then(Value::as_bool).unwrap_or(false)
+				})
+				.map(|item| ReviewComment {
+					id: u64_field(item, "/id").unwrap_or(0),
+					author: str_field(item, "/author/username"),
+					body: str_field(item, "/body"),
+					path: item
+						.pointer("/position/new_path")
+						.and_then(Value::as_str)
+						.map(str::to_string),
+					line: u64_field(item, "/position/new_line")
+						.or_else(|| u64_field(item, "/position/old_line")),
+					url: None,
+					created_at: str_field(item, "/created_at"),
+				})
+				.collect()
+		}
+	})
+}
+
+async fn assigned_issues(client: &ForgeClient, repo: &ForgeRepo) -> Result<Vec<Issue>> {
+	Ok(match client.kind {
+		ForgeKind::GitHub => {
+			let (owner, name) = repo.path.split_once('/').unwrap_or_default();
+			let items = client
+				.send(
+					client
+						.request(Method::GET, &["repos", owner, name, "issues"])?
+						.query(&[
+							("assignee", client.username.as_str()),
+							("state", "open"),
+							("per_page", "100"),
+						]),
+				)
+				.await?;
+			array(items)
+				.iter()
+				// GitHub lists pull requests as issues too
+				.filter(|item| item.get("pull_request").is_none())
+				.map(|item| Issue {
+					number: u64_field(item, "/number").unwrap_or(0),
+					title: str_field(item, "/title"),
+					url: str_field(item, "/html_url"),
+					author: str_field(item, "/user/login"),
+					labels: array(item.get("labels").cloned().unwrap_or_default())
+						.iter()
+						.map(|label| str_field(label, "/name"))
+						.collect(),
+					created_at: str_field(item, "/created_at"),
+					updated_at: str_field(item, "/updated_at"),
+				})
+				.collect()
+		}
+		ForgeKind::GitLab => {
+			let items = client
+				.send(
+					client
+						.request(Method::GET, &["projects", &repo.path, "issues"])?
+						.query(&[
+							("assignee_username", client.username.as_str()),
+							("state", "opened"),
+							("per_page", "100"),
+						]),
+				)
+				.await?;
+			array(items)
+				.iter()
+				.map(|item| Issue {
+					number: u64_field(item, "/iid").unwrap_or(0),
+					title: str_field(item, "/title"),
+					url: str_field(item, "/web_url"),
+					author: str_field(item, "/author/username"),
+					labels: array(item.get("labels").cloned().unwrap_or_default())
+						.iter()
+						.filter_map(|label| label.as_str().map(str::to_string))
+						.collect(),
+					created_at: str_field(item, "/created_at"),
+					updated_at: str_field(item, "/updated_at"),
+				})
+				.collect()
+		}
+	})
+}
+
+/// Hosts with a stored access token.
+#[tauri::command]
+pub async fn list_forge_accounts(
+	app_handle: AppHandle,
+) -> Result<Vec<ForgeAccount>, String> {
+	let store = app_handle.store(FORGE_STORE).map_err(|e| e.to_string())?;
+	let mut accounts: Vec<ForgeAccount> = store
+		.entries()
+		.into_iter()
+		.filter_map(|(host, value)| {
+			let credentials: ForgeCredentials = serde_json::from_value(value).ok()?;
+			Some(ForgeAccount {
+				host,
+				kind: credentials.kind,
+				username: credentials.username,
+			})
+		})
+		.collect();
+	accounts.sort_by(|a, b| a.host.cmp(&b.host));
+	Ok(accounts)
+}
+
+/// Checks `token` against `host` (e.g. `github.com` or a self-hosted GitLab)
+/// and stores it.
+#[tauri::command]
+pub async fn set_forge_token(
+	app_handle: AppHandle,
+	host: String,
+	kind: ForgeKind,
+	token: String,
+) -> Result<ForgeAccount, String> {
+	let host = host.trim().trim_end_matches('/').to_lowercase();
+	let client = ForgeClient::new(&host, kind, token.clone(), String::new());
+	let user = client
+		.send(
+			client
+				.request(Method::GET, &["user"])
+				.map_err(|e| e.to_string())?,
+		)
+		.await
+		.map_err(|e| e.to_string())?;
+	let username = match kind {
+		ForgeKind::GitHub => str_field(&user, "/login"),
+		ForgeKind::GitLab => str_field(&user, "/username"),
+	};
+
+	let credentials = ForgeCredentials {
+		kind,
+		token,
+		username: username.clone(),
+	};
+	let store = app_handle.store(FORGE_STORE).map_err(|e| e.to_string())?;
+	store.set(
+		host.clone(),
+		serde_json::to_value(credentials).map_err(|e| e.to_string())?,
+	);
+	store.save().map_err(|e| e.to_string())?;
+	Ok(ForgeAccount {
+		host,
+		kind,
+		username,
+	})
+}
+
+#[tauri::command]
+pub async fn remove_forge_token(
+	app_handle: AppHandle,
+	host: String,
+) -> Result<(), String> {
+	let store = app_handle.store(FORGE_STORE).map_err(|e| e.to_string())?;
+	store.delete(host.trim().to_lowercase());
+	store.save().map_err(|e| e.to_string())
+}
+
+/// Pull requests from the branch checked out in `directory`, newest first.
+#[tauri::command]
+pub async fn forge_list_pull_requests(
+	app_handle: AppHandle,
+	directory: String,
+	os_session: OsSession,
+	remote: Option<String>,
+) -> Result<Vec<PullRequest>, String> {
+	let result = async {
+		let (repo, branch) = repo_context(directory, os_session, remote).await?;
+		let client = ForgeClient::for_host(&app_handle, &repo.host)?;
+		list_pull_requests(&client, &repo, &checked_out(branch)?).await
+	};
+	result.await.map_err(|e| e.to_string())
+}
+
+/// Opens a pull request from the checked out branch, which must already be
+/// pushed, into `base` or the repository's default branch.
+#[tauri::command]
+pub async fn forge_create_pull_request(
+	app_handle: AppHandle,
+	directory: String,
+	os_session: OsSession,
+	request: NewPullRequest,
+	remote: Option<String>,
+) -> Result<PullRequest, String> {
+	let result = async {
+		let (repo, branch) = repo_context(directory, os_session, remote).await?;
+		let branch = checked_out(branch)?;
+		let client = ForgeClient::for_host(&app_handle, &repo.host)?;
+		let base = match &request.base {
+			Some(base) => base.clone(),
+			None => default_branch(&client, &repo).await?,
+		};
+		create_pull_request(&client, &repo, &branch, &base, &request).await
+	};
+	result.await.map_err(|e| e.to_string())
+}
+
+/// Review comments on a pull request, oldest first.
+#[tauri::command]
+pub async fn forge_pull_request_comments(
+	app_handle: AppHandle,
+	directory: String,
+	os_session: OsSession,
+	number: u64,
+	remote: Option<String>,
+) -> Result<Vec<ReviewComment>, String> {
+	let result = async {
+		let (repo, _) = repo_context(directory, os_session, remote).await?;
+		let client = ForgeClient::for_host(&app_handle, &repo.host)?;
+		review_comments(&client, &repo, number).await
+	};
+	result.await.map_err(|e| e.to_string())
+}
+
+/// Open issues in the repository assigned to the token's user.
+#[tauri::command]
+pub async fn forge_list_assigned_issues(
+	app_handle: AppHandle,
+	directory: String,
+	os_session: OsSession,
+	remote: Option<String>,
+) -> Result<Vec<Issue>, String> {
+	let result = async {
+		let (repo, _) = repo_context(directory, os_session, remote).await?;
+		let client = ForgeClient::for_host(&app_handle, &repo.host)?;
+		assigned_issues(&client, &repo).await
+	};
+	result.await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,416 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::document_manager::write_atomically;
+use crate::editorconfig::{self, IndentStyle};
+use crate::os::OsSession;
+use crate::trust;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Formatter {
+	Rustfmt,
+	Prettier,
+	Black,
+	Gofmt,
+}
+
+impl Formatter {
+	/// The formatter for a file, judged by its extension.
+	pub fn for_path(path: &str) -> Option<Self> {
+		let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+		match extension.as_str() {
+			"rs" => Some(Self::Rustfmt),
+			"py" | "pyi" => Some(Self::Black),
+			"go" => Some(Self::Gofmt),
+			"js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" | "json"
+			| "css" | "scss" | "less" | "html" | "vue" | "md" | "yaml" | "yml"
+			| "graphql" => Some(Self::Prettier),
+			_ => None,
+		}
+	}
+
+	fn name(&self) -> &'static str {
+		match self {
+			Self::Rustfmt => "rustfmt",
+			Self::Prettier => "prettier",
+			Self::Black => "black",
+			Self::Gofmt => "gofmt",
+		}
+	}
+}
+
+/// A problem the formatter reported, usually a syntax error. Lines and
+/// columns are 1-based.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatError {
+	pub line: Option<u32>,
+	pub column: Option<u32>,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum FormatResult {
+	#[serde(rename_all = "camelCase")]
+	Formatted {
+		formatter: Formatter,
+		text: String,
+		changed: bool,
+	},
+	#[serde(rename_all = "camelCase")]
+	Failed {
+		formatter: Formatter,
+		errors: Vec<FormatError>,
+	},
+	/// No formatter handles this kind of file.
+	Unsupported,
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+	package: Option<CargoPackage>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+	edition: Option<toml::Value>,
+}
+
+fn parent_dir(path: &str) -> Result<String> {
+	Path::new(path)
+		.parent()
+		.map(|dir| dir.to_string_lossy().into_owned())
+		.filter(|dir| !dir.is_empty())
+		.ok_or_else(|| anyhow!("{} has no parent directory", path))
+}
+
+/// Directories from `dir` up to the root, as session paths.
+fn ancestors(dir: &str) -> impl Iterator<Item = String> + '_ {
+	Path::new(dir)
+		.ancestors()
+		.map(|dir| dir.to_string_lossy().into_owned())
+}
+
+/// The edition in the nearest Cargo.toml, which rustfmt can't find itself
+/// when reading from stdin.
+fn rust_edition(session: &OsSession, dir: &str) -> String {
+	ancestors(dir)
+		.find_map(|dir| {
+			let content =
+				fs::read_to_string(session.host_path_for(&dir).join("Cargo.toml"))
+					.ok()?;
+			let manifest: CargoManifest = toml::from_str(&content).ok()?;
+			match manifest.package?.edition? {
+				toml::Value::String(edition) => Some(edition),
+				_ => None,
+			}
+		})
+		.unwrap_or_else(|| "2021".to_string())
+}
+
+/// Settings for rustfmt from the file's `.editorconfig`, unless the project
+/// configures rustfmt itself.
+fn rustfmt_config(session: &OsSession, dir: &str, path: &str) -> Option<String> {
+	let configured = ancestors(dir).any(|dir| {
+		let dir = session.host_path_for(&dir);
+		dir.join("rustfmt.toml").is_file() || dir.join(".rustfmt.toml").is_file()
+	});
+	if configured {
+		return None;
+	}
+	let config = editorconfig::resolve(&session.host_path_for(path));
+	let mut options = Vec::new();
+	if let Some(style) = config.indent_style {
+		options.push(format!("hard_tabs={}", style == IndentStyle::Tab));
+	}
+	if let Some(size) = config.indent_size {
+		options.push(format!("tab_spaces={}", size));
+	}
+	if let Some(width) = config.max_line_length {
+		options.push(format!("max_width={}", width));
+	}
+	(!options.is_empty()).then(|| options.join(","))
+}
+
+/// The project's own prettier if it has one, so its version and plugins
+/// are used, otherwise whichever is installed.
+fn prettier_program(session: &OsSession, dir: &str) -> String {
+	let bin = if cfg!(target_os = "windows") && matches!(session, OsSession::Local(_)) {
+		"node_modules/.bin/prettier.cmd"
+	} else {
+		"node_modules/.bin/prettier"
+	};
+	ancestors(dir)
+		.map(|dir| format!("{}/{}", dir.trim_end_matches(['/', '\\']), bin))
+		.find(|program| session.host_path_for(program).is_file())
+		.unwrap_or_else(|| "prettier".to_string())
+}
+
+/// Pulls `line:column` out of the formatter's messages where it gives them.
+fn parse_errors(formatter: Formatter, stderr: &str) -> Vec<FormatError> {
+	fn position(text: &str) -> Option<(u32, u32)> {
+		let (line, column) = text.trim().split_once(':')?;
+		let column = column.trim_start_matches(|c: char| !c.is_ascii_digit());
+		let column: String = column.chars().take_while(char::is_ascii_digit).collect();
+		Some((line.trim().parse().ok()?, column.parse().ok()?))
+	}
+
+	let mut errors: Vec<FormatError> = Vec::new();
+	for line in stderr.lines().map(str::trim).filter(|l| !l.is_empty()) {
+		let error = match formatter {
+			// error: expected one of ... \n --> <stdin>:3:5
+			Formatter::Rustfmt => {
+				if let Some(message) = line.strip_prefix("error") {
+					let message = message.trim_start_matches(|c| c != ':');
+					Some(FormatError {
+						line: None,
+						column: None,
+						message: message.trim_start_matches(':').trim().to_string(),
+					})
+				} else if let Some(location) = line.strip_prefix("--> ") {
+					if let (Some(last), Some((l, c))) = (
+						errors.last_mut(),
+						location.split_once(':').and_then(|(_, p)| position(p)),
+					) {
+						last.line.get_or_insert(l);
+						last.column.get_or_insert(c);
+					}
+					None
+				} else {
+					None
+				}
+			}
+			// error: cannot format -: Cannot parse: 3:5: message
+			Formatter::Black => line.strip_prefix("error: ").map(|message| {
+				let parsed =
+					message.split_once("Cannot parse: ").and_then(|(_, rest)| {
+						let mut parts = rest.splitn(3, ':');
+						let l = parts.next()?.trim().parse().ok()?;
+						let c = parts.next()?.trim().parse().ok()?;
+						Some((l, c, parts.next().unwrap_or_default().trim()))
+					});
+				match parsed {
+					Some((l, c, text)) => FormatError {
+						line: Some(l),
+						column: Some(c),
+						message: text.to_string(),
+					},
+					None => FormatError {
+						line: None,
+						column: None,
+						message: message.to_string(),
+					},
+				}
+			}),
+			// <standard input>:3:5: message
+			Formatter::Gofmt => line.strip_prefix("<standard input>:").map(|rest| {
+				let mut parts = rest.splitn(3, ':');
+				let l = parts.next().and_then(|p| p.parse().ok());
+				let c = parts.next().and_then(|p| p.parse().ok());
+				FormatError {
+					line: l,
+					column: c,
+					message: parts.next().unwrap_or(rest).trim().to_string(),
+				}
+			}),
+			// [error] file.ts: SyntaxError: message (3:5)
+			Formatter::Prettier => line.strip_prefix("[error] ").and_then(|message| {
+				let message = message.split_once(": ").map_or(message, |(_, m)| m);
+				// Code frames follow the first line of each error
+				if message
+					.starts_with(|c: char| c.is_ascii_digit() || c == '>' || c == '|')
+				{
+					return None;
+				}
+				let (l, c) = message
+					.rfind('(')
+					.and_then(|i| position(message[i + 1..].trim_end_matches(')')))
+					.unzip();
+				Some(FormatError {
+					line: l,
+					column: c,
+					message: message.to_string(),
+				})
+			}),
+		};
+		errors.extend(error);
+	}
+
+	if errors.is_empty() {
+		errors.push(FormatError {
+			line: None,
+			column: None,
+			message: stderr.trim().to_string(),
+		});
+	}
+	errors
+}
+
+/// Formats `text` as the contents of `path`, a file in the session. The
+/// formatter runs inside the session so it picks up the project's config.
+pub fn format_text(
+	session: &OsSession,
+	path: &str,
+	text: String,
+) -> Result<FormatResult> {
+	let Some(formatter) = Formatter::for_path(path) else {
+		return Ok(FormatResult::Unsupported);
+	};
+	let dir = parent_dir(path)?;
+
+	let mut cmd = match formatter {
+		Formatter::Rustfmt => {
+			let mut cmd = session.build_process_command("rustfmt", &dir)?;
+			cmd.args([
+				"--emit",
+				"stdout",
+				"--edition",
+				&rust_edition(session, &dir),
+			]);
+			if let Some(config) = rustfmt_config(session, &dir, path) {
+				cmd.args(["--config", &config]);
+			}
+			cmd
+		}
+		Formatter::Prettier => {
+			let mut cmd =
+				session.build_process_command(&prettier_program(session, &dir), &dir)?;
+			cmd.args(["--stdin-filepath", path]);
+			cmd
+		}
+		Formatter::Black => {
+			let mut cmd = session.build_process_command("black", &dir)?;
+			cmd.args(["--quiet", "--stdin-filename", path, "-"]);
+			cmd
+		}
+		Formatter::Gofmt => session.build_process_command("gofmt", &dir)?,
+	};
+
+	let mut child = cmd
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| anyhow!("Failed to run {}: {}", formatter.name(), e))?;
+	// Written from another thread so a full stdout pipe can't deadlock us
+	let mut stdin = child
+		.stdin
+		.take()
+		.ok_or_else(|| anyhow!("Failed to open {} stdin", formatter.name()))?;
+	let input = text.clone();
+	let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+	let output = child.wait_with_output()?;
+	let _ = writer.join();
+
+	if !output.status.success() {
+		return Ok(FormatResult::Failed {
+			formatter,
+			errors: parse_errors(formatter, &String::from_utf8_lossy(&output.stderr)),
+		});
+	}
+	let formatted = String::from_utf8(output.stdout)
+		.map_err(|_| anyhow!("{} produced invalid UTF-8", formatter.name()))?;
+	Ok(FormatResult::Formatted {
+		formatter,
+		changed: formatted != text,
+		text: formatted,
+	})
+}
+
+/// Formats `path` in place, returning the result.
+pub fn format_in_place(session: &OsSession, path: &str) -> Result<FormatResult> {
+	let host_path = session.host_path_for(path);
+	let text = fs::read_to_string(&host_path)
+		.map_err(|e| anyhow!("Failed to read {}: {}", host_path.display(), e))?;
+	let result = format_text(session, path, text)?;
+	if let FormatResult::Formatted {
+		text,
+		changed: true,
+		..
+	} = &result
+	{
+		write_atomically(&host_path, text.as_bytes())?;
+	}
+	Ok(result)
+}
+
+/// Formats every changed or new file in the repository at `directory`, for
+/// running before a commit. Errors name the file and position.
+pub fn format_changed_files(session: &OsSession, directory: &str) -> Result<Vec<String>> {
+	let mut files = Vec::new();
+	for args in [
+		&["diff", "--name-only", "--diff-filter=ACMR", "HEAD"][..],
+		&["ls-files", "--others", "--exclude-standard"][..],
+	] {
+		let output = session
+			.build_process_command("git", directory)?
+			.args(args)
+			.output()?;
+		// A repository without commits has no HEAD to diff against
+		if output.status.success() {
+			files.extend(
+				String::from_utf8_lossy(&output.stdout)
+					.lines()
+					.map(str::to_string),
+			);
+		}
+	}
+
+	let mut formatted = Vec::new();
+	let mut failures = Vec::new();
+	for file in files {
+		if Formatter::for_path(&file).is_none() {
+			continue;
+		}
+		let path = format!("{}/{}", directory.trim_end_matches(['/', '\\']), file);
+		match format_in_place(session, &path)? {
+			FormatResult::Formatted { changed: true, .. } => formatted.push(file),
+			FormatResult::Failed { errors, .. } => {
+				for error in errors {
+					failures.push(match (error.line, error.column) {
+						(Some(line), Some(column)) => {
+							format!("{}:{}:{}: {}", file, line, column, error.message)
+						}
+						_ => format!("{}: {}", file, error.message),
+					});
+				}
+			}
+			_ => {}
+		}
+	}
+	if !failures.is_empty() {
+		return Err(anyhow!("Formatting failed:\n{}", failures.join("\n")));
+	}
+	Ok(formatted)
+}
+
+/// Formats a file, or `contents` as that file's text when given, e.g. an
+/// unsaved editor buffer. Nothing is written.
+#[tauri::command]
+pub async fn format_file(
+	path: String,
+	os_session: OsSession,
+	contents: Option<String>,
+	app_handle: tauri::AppHandle,
+) -> Result<FormatResult, String> {
+	// Project formatters such as node_modules/.bin/prettier are its code
+	trust::ensure_trusted(&app_handle, &os_session, &path)?;
+	tauri::async_runtime::spawn_blocking(move || {
+		let text = match contents {
+			Some(text) => text,
+			None => fs::read_to_string(os_session.host_path_for(&path))?,
+		};
+		format_text(&os_session, &path, text)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
@@ -0,0 +1,873 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use walkdir::WalkDir;
+
+use crate::jobs::{JobHandle, JobKind, JobManager};
+use crate::os::OsSession;
+use crate::previews;
+
+/// The longest side of thumbnails in binary diffs.
+const THUMBNAIL_SIZE: u32 = 512;
+
+/// Runs git in `directory` inside the session, returning its stdout.
+pub fn run_git(session: &OsSession, directory: &str, args: &[&str]) -> Result<String> {
+	Ok(String::from_utf8_lossy(&run_git_bytes(session, directory, args)?).into_owned())
+}
+
+/// Like `run_git`, for output that may not be text, such as blobs.
+fn run_git_bytes(session: &OsSession, directory: &str, args: &[&str]) -> Result<Vec<u8>> {
+	let output = session
+		.build_process_command("git", directory)?
+		.args(args)
+		.output()
+		.map_err(|e| anyhow!("Failed to execute git {}: {}", args[0], e))?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"git {} failed: {}",
+			args[0],
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(output.stdout)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiffStat {
+	pub path: String,
+	/// Set when the file was renamed or copied.
+	pub old_path: Option<String>,
+	/// Line counts are zero for binary files.
+	pub additions: u64,
+	pub deletions: u64,
+	pub binary: bool,
+	/// Sizes and hashes, for binary files.
+	pub binary_change: Option<BinaryChange>,
+}
+
+/// One side of a binary change.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryVersion {
+	pub size: u64,
+	/// The git blob id, so equal contents give equal hashes on both sides.
+	pub hash: String,
+	/// A PNG `data:` URL, for images that decode.
+	pub thumbnail: Option<String>,
+}
+
+/// A change to a file git can't diff line by line. A side is missing when
+/// the file was added or deleted.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryChange {
+	pub old: Option<BinaryVersion>,
+	pub new: Option<BinaryVersion>,
+	pub size_delta: i64,
+	/// Set for formats previews exist for, e.g. `image/png`.
+	pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffStats {
+	pub files: Vec<FileDiffStat>,
+	pub additions: u64,
+	pub deletions: u64,
+}
+
+/// Parses `git diff --numstat -z`, where renamed files give their
+/// counts followed by the old and new paths as separate fields.
+fn parse_numstat(output: &str) -> Vec<FileDiffStat> {
+	let mut files = Vec::new();
+	let mut fields = output.split('\0');
+	while let Some(record) = fields.next() {
+		let mut parts = record.splitn(3, '\t');
+		let (Some(additions), Some(deletions), Some(path)) =
+			(parts.next(), parts.next(), parts.next())
+		else {
+			continue;
+		};
+		let (path, old_path) = if path.is_empty() {
+			let old_path = fields.next().unwrap_or_default().to_string();
+			(
+				fields.next().unwrap_or_default().to_string(),
+				Some(old_path),
+			)
+		} else {
+			(path.to_string(), None)
+		};
+		let binary = additions == "-";
+		files.push(FileDiffStat {
+			path,
+			old_path,
+			additions: additions.parse().unwrap_or(0),
+			deletions: deletions.parse().unwrap_or(0),
+			binary,
+			binary_change: None,
+		});
+	}
+	files
+}
+
+/// The revisions a diff compares, resolved from the arguments
+/// `diff_stats` takes. No `new` revision means the working tree.
+struct DiffSides {
+	old: String,
+	new: Option<String>,
+	/// The repository root, which diff paths are relative to.
+	root: String,
+}
+
+fn diff_sides(
+	session: &OsSession,
+	directory: &str,
+	from: &str,
+	to: Option<&str>,
+) -> Result<DiffSides> {
+	let root = run_git(session, directory, &["rev-parse", "--show-toplevel"])?
+		.trim()
+		.to_string();
+	let or_head = |rev: &str| {
+		if rev.is_empty() {
+			"HEAD".to_string()
+		} else {
+			rev.to_string()
+		}
+	};
+	// `a...b` compares b with where it branched off a
+	if let Some((base, head)) = from.split_once("...") {
+		let (base, head) = (or_head(base), or_head(head));
+		let old = run_git(session, directory, &["merge-base", &base, &head])?
+			.trim()
+			.to_string();
+		return Ok(DiffSides {
+			old,
+			new: Some(head),
+			root,
+		});
+	}
+	if let Some((old, new)) = from.split_once("..") {
+		return Ok(DiffSides {
+			old: or_head(old),
+			new: Some(or_head(new)),
+			root,
+		});
+	}
+	Ok(DiffSides {
+		old: from.to_string(),
+		new: to.map(str::to_string),
+		root,
+	})
+}
+
+/// The MIME type of formats a webview can preview.
+fn preview_mime_type(path: &str) -> Option<&'static str> {
+	let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+	Some(match extension.as_str() {
+		"png" => "image/png",
+		"jpg" | "jpeg" => "image/jpeg",
+		"gif" => "image/gif",
+		"webp" => "image/webp",
+		"avif" => "image/avif",
+		"bmp" => "image/bmp",
+		"ico" => "image/x-icon",
+		"svg" => "image/svg+xml",
+		_ => return None,
+	})
+}
+
+/// A thumbnail of `bytes`, or `None` if they're too large or don't decode.
+fn thumbnail_of(path: &str, bytes: &[u8]) -> Option<String> {
+	previews::render_thumbnail(path, bytes, THUMBNAIL_SIZE)
+		.ok()
+		.map(|thumbnail| thumbnail.data_url)
+}
+
+/// `path` as committed in `rev`, or `None` if it doesn't exist there.
+fn blob_version(
+	session: &OsSession,
+	sides: &DiffSides,
+	rev: &str,
+	path: &str,
+	thumbnail: bool,
+) -> Result<Option<BinaryVersion>> {
+	let spec = format!("{}:{}", rev, path);
+	let Ok(hash) = run_git(
+		session,
+		&sides.root,
+		&["rev-parse", "--verify", "--quiet", &spec],
+	) else {
+		return Ok(None);
+	};
+	let hash = hash.trim().to_string();
+	let size: u64 = run_git(session, &sides.root, &["cat-file", "-s", &hash])?
+		.trim()
+		.parse()?;
+	let thumbnail = if thumbnail && size <= previews::MAX_SOURCE_BYTES {
+		let bytes = run_git_bytes(session, &sides.root, &["cat-file", "blob", &hash])?;
+		thumbnail_of(path, &bytes)
+	} else {
+		None
+	};
+	Ok(Some(BinaryVersion {
+		size,
+		hash,
+		thumbnail,
+	}))
+}
+
+/// `path` in the working tree, or `None` if it was deleted.
+fn worktree_version(
+	session: &OsSession,
+	sides: &DiffSides,
+	path: &str,
+	thumbnail: bool,
+) -> Result<Option<BinaryVersion>> {
+	let host_path = session.host_path_for(&format!("{}/{}", sides.root, path));
+	let Ok(metadata) = fs::metadata(&host_path) else {
+		return Ok(None);
+	};
+	// Hashed the way git would store it, so unchanged files match
+	let hash = run_git(session, &sides.root, &["hash-object", "--", path])?
+		.trim()
+		.to_string();
+	let thumbnail = if thumbnail && metadata.len() <= previews::MAX_SOURCE_BYTES {
+		thumbnail_of(path, &fs::read(&host_path)?)
+	} else {
+		None
+	};
+	Ok(Some(BinaryVersion {
+		size: metadata.len(),
+		hash,
+		thumbnail,
+	}))
+}
+
+/// Describes how a binary file changed between the sides of a diff, with
+/// thumbnails of both versions if asked for and the file is an image.
+fn binary_change(
+	session: &OsSession,
+	sides: &DiffSides,
+	old_path: &str,
+	new_path: &str,
+	thumbnails: bool,
+) -> Result<BinaryChange> {
+	let mime_type = preview_mime_type(new_path);
+	let thumbnail = thumbnails && previews::is_previewable(new_path);
+	let old = blob_version(session, sides, &sides.old, old_path, thumbnail)?;
+	let new = match &sides.new {
+		Some(rev) => blob_version(session, sides, rev, new_path, thumbnail)?,
+		None => worktree_version(session, sides, new_path, thumbnail)?,
+	};
+	let size_of =
+		|version: &Option<BinaryVersion>| version.as_ref().map_or(0, |v| v.size as i64);
+	Ok(BinaryChange {
+		size_delta: size_of(&new) - size_of(&old),
+		old,
+		new,
+		mime_type: mime_type.map(str::to_string),
+	})
+}
+
+/// Line counts per file between `from` and `to`, or between `from` and
+/// the working tree if `to` is omitted. `from` may be `main...HEAD` to
+/// count only what changed on a branch.
+pub fn diff_stats(
+	session: &OsSession,
+	directory: &str,
+	from: &str,
+	to: Option<&str>,
+) -> Result<DiffStats> {
+	if from.starts_with('-') || to.is_some_and(|to| to.starts_with('-')) {
+		return Err(anyhow!("Invalid revision"));
+	}
+	let mut args = vec!["diff", "--numstat", "-z", "-M", from];
+	args.extend(to);
+	args.push("--");
+	let mut files = parse_numstat(&run_git(session, directory, &args)?);
+	let sides = diff_sides(session, directory, from, to)?;
+	for file in files.iter_mut().filter(|file| file.binary) {
+		let old_path = file.old_path.as_deref().unwrap_or(&file.path);
+		file.binary_change =
+			binary_change(session, &sides, old_path, &file.path, false).ok();
+	}
+	Ok(DiffStats {
+		additions: files.iter().map(|f| f.additions).sum(),
+		deletions: files.iter().map(|f| f.deletions).sum(),
+		files,
+	})
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubmoduleState {
+	/// Checked out at the recorded commit.
+	Current,
+	/// Not initialized or not checked out.
+	Uninitialized,
+	/// Checked out at a different commit than the one recorded.
+	Modified,
+	/// Has merge conflicts.
+	Conflicted,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmoduleStatus {
+	pub path: String,
+	pub commit: String,
+	pub state: SubmoduleState,
+	/// e.g. a tag or `heads/main`, when git could describe the commit.
+	pub describe: Option<String>,
+}
+
+/// Parses `git submodule status`, e.g. `+1a2b3c sub/lib (v1.0-2-g1a2b3c)`.
+fn parse_submodule_status(output: &str) -> Vec<SubmoduleStatus> {
+	output
+		.lines()
+		.filter(|line| line.len() > 1)
+		.filter_map(|line| {
+			let state = match line.chars().next()? {
+				'-' => SubmoduleState::Uninitialized,
+				'+' => SubmoduleState::Modified,
+				'U' => SubmoduleState::Conflicted,
+				_ => SubmoduleState::Current,
+			};
+			let (commit, rest) = line[1..].split_once(' ')?;
+			let (path, describe) = match rest.rsplit_once(" (") {
+				Some((path, describe)) if describe.ends_with(')') => {
+					(path, Some(describe.trim_end_matches(')').to_string()))
+				}
+				_ => (rest, None),
+			};
+			Some(SubmoduleStatus {
+				path: path.to_string(),
+				commit: commit.to_string(),
+				state,
+				describe,
+			})
+		})
+		.collect()
+}
+
+/// Every submodule in the repository at `directory`, recursively.
+pub fn submodule_status(
+	session: &OsSession,
+	directory: &str,
+) -> Result<Vec<SubmoduleStatus>> {
+	Ok(parse_submodule_status(&run_git(
+		session,
+		directory,
+		&["submodule", "status", "--recursive"],
+	)?))
+}
+
+/// Runs a long git command as part of `job`, reporting each line it
+/// prints, progress included, and waits for it to finish. Cancelling the
+/// job kills git.
+pub fn run_job(
+	session: &OsSession,
+	directory: &str,
+	args: &[&str],
+	job: &JobHandle,
+) -> Result<()> {
+	let mut child = session
+		.build_process_command("git", directory)?
+		.args(args)
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| anyhow!("Failed to execute git {}: {}", args[0], e))?;
+	let mut stderr = child
+		.stderr
+		.take()
+		.ok_or_else(|| anyhow!("Failed to read git {} output", args[0]))?;
+	let child = Arc::new(Mutex::new(child));
+	let killed = child.clone();
+	job.token().on_cancel(move || {
+		let _ = killed.lock().unwrap().kill();
+	});
+
+	// Progress lines end in \r as they are redrawn
+	let mut output = Vec::new();
+	let mut line = Vec::new();
+	let mut buf = [0u8; 4096];
+	while let Ok(read) = stderr.read(&mut buf) {
+		if read == 0 {
+			break;
+		}
+		for &byte in &buf[..read] {
+			if byte != b'\r' && byte != b'\n' {
+				line.push(byte);
+				continue;
+			}
+			if line.is_empty() {
+				continue;
+			}
+			let text = String::from_utf8_lossy(&line).into_owned();
+			line.clear();
+			job.message(text.clone());
+			if byte == b'\n' {
+				output.push(text);
+			}
+		}
+	}
+
+	let status = child.lock().unwrap().wait()?;
+	job.token().check()?;
+	if !status.success() {
+		return Err(anyhow!("git {} failed: {}", args[0], output.join("\n")));
+	}
+	Ok(())
+}
+
+/// Starts `run_job` in the background as a job of its own, returning the
+/// job id.
+pub fn start_job(
+	session: &OsSession,
+	directory: &str,
+	args: &'static [&'static str],
+	app_handle: AppHandle,
+) -> String {
+	let session = session.clone();
+	let directory = directory.to_string();
+	let title = format!("git {} in {}", args.join(" "), directory);
+	app_handle
+		.state::<Arc<JobManager>>()
+		.spawn(JobKind::Git, title, move |job| {
+			run_job(&session, &directory, args, job)
+		})
+}
+
+/// Start of every LFS pointer file.
+const LFS_POINTER_HEADER: &[u8] = b"version https://git-lfs.github.com/spec/v1";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LfsStatus {
+	/// Some tracked files are stored in LFS.
+	pub uses_lfs: bool,
+	/// `git lfs` is available in the session.
+	pub installed: bool,
+	/// LFS files checked out as pointers because their content was never
+	/// downloaded.
+	pub missing: Vec<String>,
+}
+
+fn is_lfs_pointer(path: &Path) -> bool {
+	let mut header = [0u8; LFS_POINTER_HEADER.len()];
+	fs::File::open(path)
+		.and_then(|mut file| file.read_exact(&mut header))
+		.is_ok_and(|_| header == LFS_POINTER_HEADER)
+}
+
+/// Whether the repository at `directory` uses LFS and which of its LFS
+/// files are missing. Works without git-lfs installed, which is when
+/// files are most likely to be missing.
+pub fn lfs_status(session: &OsSession, directory: &str) -> Result<LfsStatus> {
+	let installed = run_git(session, directory, &["lfs", "version"]).is_ok();
+	let files = run_git(
+		session,
+		directory,
+		&["ls-files", "-z", ":(attr:filter=lfs)"],
+	)?;
+	let root = directory.trim_end_matches(['/', '\\']);
+	let tracked: Vec<&str> = files.split('\0').filter(|f| !f.is_empty()).collect();
+	let missing = tracked
+		.iter()
+		.filter(|file| {
+			is_lfs_pointer(&session.host_path_for(&format!("{}/{}", root, file)))
+		})
+		.map(|file| file.to_string())
+		.collect();
+	Ok(LfsStatus {
+		uses_lfs: !tracked.is_empty(),
+		installed,
+		missing,
+	})
+}
+
+/// Points submodule `.git` files under `destination`, a copy of the
+/// repository at `source`, at the copy's own git directory. Older git
+/// versions wrote absolute paths there, which would leave the copy's
+/// submodules sharing the original's history.
+pub fn relink_submodule_gitdirs(
+	session: &OsSession,
+	source: &str,
+	destination: &str,
+) -> Result<()> {
+	let source = source.trim_end_matches(['/', '\\']);
+	let destination = destination.trim_end_matches(['/', '\\']);
+	let entries = WalkDir::new(session.host_path_for(destination))
+		.into_iter()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().is_file() && entry.file_name() == ".git");
+	for entry in entries {
+		let content = fs::read_to_string(entry.path())?;
+		let Some(gitdir) = content.trim().strip_prefix("gitdir: ") else {
+			continue;
+		};
+		let Some(rest) = gitdir.strip_prefix(source) else {
+			continue;
+		};
+		if Path::new(gitdir).is_relative() || !rest.starts_with(['/', '\\']) {
+			continue;
+		}
+		fs::write(entry.path(), format!("gitdir: {}{}\n", destination, rest))?;
+	}
+	Ok(())
+}
+
+fn ref_exists(session: &OsSession, directory: &str, reference: &str) -> bool {
+	run_git(
+		session,
+		directory,
+		&["show-ref", "--verify", "--quiet", reference],
+	)
+	.is_ok()
+}
+
+/// The branch checked out in `directory`, `None` when HEAD is detached.
+pub fn current_branch(session: &OsSession, directory: &str) -> Result<Option<String>> {
+	let branch = run_git(session, directory, &["branch", "--show-current"])?;
+	let branch = branch.trim();
+	Ok((!branch.is_empty()).then(|| branch.to_string()))
+}
+
+/// Checks out `name`, a local branch or a remote one such as `origin/foo`.
+/// With `create_tracking`, a remote branch is checked out as a local branch
+/// tracking it, reusing the local branch if one exists by that name;
+/// otherwise HEAD is detached at it. Returns the branch checked out.
+pub fn checkout_branch(
+	session: &OsSession,
+	directory: &str,
+	name: &str,
+	create_tracking: bool,
+) -> Result<Option<String>> {
+	if name.is_empty() || name.starts_with('-') {
+		return Err(anyhow!("Invalid branch name: {}", name));
+	}
+
+	let local = format!("refs/heads/{}", name);
+	let remote = format!("refs/remotes/{}", name);
+	if ref_exists(session, directory, &local) {
+		run_git(session, directory, &["checkout", name, "--"])?;
+	} else if ref_exists(session, directory, &remote) {
+		let remotes = run_git(session, directory, &["remote"])?;
+		let local_name = remotes
+			.lines()
+			.find_map(|remote| name.strip_prefix(remote)?.strip_prefix('/'))
+			.ok_or_else(|| anyhow!("{} is not on a configured remote", name))?;
+		if !create_tracking {
+			run_git(session, directory, &["checkout", "--detach", name, "--"])?;
+		} else if ref_exists(session, directory, &format!("refs/heads/{}", local_name)) {
+			run_git(session, directory, &["checkout", local_name, "--"])?;
+		} else {
+			run_git(
+				session,
+				directory,
+				&["checkout", "-b", local_name, "--track", name, "--"],
+			)?;
+		}
+	} else if create_tracking {
+		// Lets git find `name` on exactly one remote and track it
+		run_git(session, directory, &["checkout", "--guess", name, "--"])?;
+	} else {
+		return Err(anyhow!("No branch named {}", name));
+	}
+	current_branch(session, directory)
+}
+
+/// Sets the branch `branch`, or the checked out one, pulls from and pushes
+/// to, e.g. `origin/foo`. Without `upstream` the upstream is removed.
+pub fn set_upstream(
+	session: &OsSession,
+	directory: &str,
+	branch: Option<&str>,
+	upstream: Option<&str>,
+) -> Result<()> {
+	if [branch, upstream]
+		.iter()
+		.flatten()
+		.any(|name| name.starts_with('-'))
+	{
+		return Err(anyhow!("Invalid branch name"));
+	}
+	let set_upstream = upstream.map(|upstream| format!("--set-upstream-to={}", upstream));
+	let mut args = vec!["branch"];
+	args.push(set_upstream.as_deref().unwrap_or("--unset-upstream"));
+	args.extend(branch);
+	run_git(session, directory, &args)?;
+	Ok(())
+}
+
+/// A multi-step operation left in progress, usually waiting on conflicts
+/// to be resolved.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RepoOperation {
+	#[serde(rename_all = "camelCase")]
+	Merge {
+		merge_head: String,
+	},
+	#[serde(rename_all = "camelCase")]
+	Rebase {
+		interactive: bool,
+		/// The branch being rebased, `None` if it was detached.
+		branch: Option<String>,
+		onto: Option<String>,
+		/// 1-based step and step count, when known.
+		step: Option<u32>,
+		total: Option<u32>,
+	},
+	/// `git am`.
+	ApplyMailbox,
+	#[serde(rename_all = "camelCase")]
+	CherryPick {
+		head: String,
+	},
+	#[serde(rename_all = "camelCase")]
+	Revert {
+		head: String,
+	},
+	Bisect,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepoState {
+	/// The checked out branch, `None` when HEAD is detached.
+	pub branch: Option<String>,
+	pub detached: bool,
+	/// The commit HEAD points at, `None` before the first commit.
+	pub head: Option<String>,
+	/// Operations in progress, usually at most one, though a bisect can
+	/// run alongside the others.
+	pub operations: Vec<RepoOperation>,
+	/// Canvas operations should wait until the repository is back on a
+	/// branch with nothing in progress.
+	pub busy: bool,
+}
+
+/// Reports HEAD and any merge, rebase, cherry-pick, revert, `am` or bisect
+/// in progress, from the state files git leaves in its directory.
+pub fn repo_state(session: &OsSession, directory: &str) -> Result<RepoState> {
+	let git_dir = run_git(session, directory, &["rev-parse", "--absolute-git-dir"])?;
+	let git_dir = git_dir.trim();
+	let path = |name: &str| session.host_path_for(&format!("{}/{}", git_dir, name));
+	let read = |name: &str| {
+		fs::read_to_string(path(name))
+			.ok()
+			.map(|content| content.trim().to_string())
+			.filter(|content| !content.is_empty())
+	};
+	let number = |name: &str| read(name).and_then(|n| n.parse().ok());
+
+	let branch = current_branch(session, directory)?;
+	let head = run_git(session, directory, &["rev-parse", "--verify", "-q", "HEAD"])
+		.ok()
+		.map(|head| head.trim().to_string());
+
+	let mut operations = Vec::new();
+	if path("rebase-merge").is_dir() {
+		operations.push(RepoOperation::Rebase {
+			interactive: path("rebase-merge/interactive").exists(),
+			branch: read("rebase-merge/head-name")
+				.map(|name| name.trim_start_matches("refs/heads/").to_string()),
+			onto: read("rebase-merge/onto"),
+			step: number("rebase-merge/msgnum"),
+			total: number("rebase-merge/end"),
+		});
+	} else if path("rebase-apply/applying").exists() {
+		operations.push(RepoOperation::ApplyMailbox);
+	} else if path("rebase-apply").is_dir() {
+		operations.push(RepoOperation::Rebase {
+			interactive: false,
+			branch: read("rebase-apply/head-name")
+				.map(|name| name.trim_start_matches("refs/heads/").to_string()),
+			onto: read("rebase-apply/onto"),
+			step: number("rebase-apply/next"),
+			total: number("rebase-apply/last"),
+		});
+	}
+	if let Some(merge_head) = read("MERGE_HEAD") {
+		// One line per merged commit for octopus merges
+		let merge_head = merge_head.lines().next().unwrap_or_default().to_string();
+		operations.push(RepoOperation::Merge { merge_head });
+	}
+	if let Some(head) = read("CHERRY_PICK_HEAD") {
+		operations.push(RepoOperation::CherryPick { head });
+	}
+	if let Some(head) = read("REVERT_HEAD") {
+		operations.push(RepoOperation::Revert { head });
+	}
+	if path("BISECT_LOG").exists() {
+		operations.push(RepoOperation::Bisect);
+	}
+
+	let detached = branch.is_none() && head.is_some();
+	Ok(RepoState {
+		busy: detached || !operations.is_empty(),
+		detached,
+		branch,
+		head,
+		operations,
+	})
+}
+
+#[tauri::command]
+pub async fn git_diff_stats(
+	directory: String,
+	from: String,
+	to: Option<String>,
+	os_session: OsSession,
+) -> Result<DiffStats, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		diff_stats(&os_session, &directory, &from, to.as_deref())
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Sizes, hashes and, for images, thumbnails of both versions of a binary
+/// file in the diff `git_diff_stats` would show for `from` and `to`.
+#[tauri::command]
+pub async fn git_binary_diff(
+	directory: String,
+	path: String,
+	old_path: Option<String>,
+	from: String,
+	to: Option<String>,
+	os_session: OsSession,
+) -> Result<BinaryChange, String> {
+	if from.starts_with('-') || to.as_deref().is_some_and(|to| to.starts_with('-')) {
+		return Err("Invalid revision".to_string());
+	}
+	tauri::async_runtime::spawn_blocking(move || {
+		let sides = diff_sides(&os_session, &directory, &from, to.as_deref())?;
+		let old_path = old_path.as_deref().unwrap_or(&path);
+		binary_change(&os_session, &sides, old_path, &path, true)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn git_submodule_status(
+	directory: String,
+	os_session: OsSession,
+) -> Result<Vec<SubmoduleStatus>, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		submodule_status(&os_session, &directory)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Initializes and updates submodules recursively in the background,
+/// returning the job id.
+#[tauri::command]
+pub async fn git_submodule_update(
+	directory: String,
+	os_session: OsSession,
+	app_handle: AppHandle,
+) -> Result<String, String> {
+	Ok(start_job(
+		&os_session,
+		&directory,
+		&["submodule", "update", "--init", "--recursive", "--progress"],
+		app_handle,
+	))
+}
+
+#[tauri::command]
+pub async fn git_lfs_status(
+	directory: String,
+	os_session: OsSession,
+) -> Result<LfsStatus, String> {
+	tauri::async_runtime::spawn_blocking(move || lfs_status(&os_session, &directory))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+/// Downloads the repository's LFS files in the background, returning the
+/// job id.
+#[tauri::command]
+pub async fn git_lfs_pull(
+	directory: String,
+	os_session: OsSession,
+	app_handle: AppHandle,
+) -> Result<String, String> {
+	Ok(start_job(
+		&os_session,
+		&directory,
+		&["lfs", "pull"],
+		app_handle,
+	))
+}
+
+#[tauri::command]
+pub async fn git_checkout_branch(
+	directory: String,
+	name: String,
+	create_tracking: Option<bool>,
+	os_session: OsSession,
+) -> Result<Option<String>, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		checkout_branch(
+			&os_session,
+			&directory,
+			&name,
+			create_tracking.unwrap_or(true),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn git_set_upstream(
+	directory: String,
+	branch: Option<String>,
+	upstream: Option<String>,
+	os_session: OsSession,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		set_upstream(
+			&os_session,
+			&directory,
+			branch.as_deref(),
+			upstream.as_deref(),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Reports detached HEAD and in-progress operations, so the UI can hold off
+/// on canvas operations until the user finishes or aborts them.
+#[tauri::command]
+pub async fn git_repo_state(
+	directory: String,
+	os_session: OsSession,
+) -> Result<RepoState, String> {
+	tauri::async_runtime::spawn_blocking(move || repo_state(&os_session, &directory))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
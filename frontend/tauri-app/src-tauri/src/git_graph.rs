@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::git::run_git;
+use crate::os::OsSession;
+
+const DEFAULT_MAX_COUNT: usize = 1000;
+
+/// Separates fields and records in the `git log` output.
+const FIELD_SEP: char = '\x1f';
+const RECORD_SEP: char = '\x1e';
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitGraphOptions {
+	/// 1000 by default.
+	pub max_count: Option<usize>,
+	/// Commits to skip, for loading further pages.
+	#[serde(default)]
+	pub skip: usize,
+	/// Revisions to start from, e.g. branch names. Every ref when empty.
+	#[serde(default)]
+	pub revisions: Vec<String>,
+	/// Only commits touching this path.
+	pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RefKind {
+	Head,
+	Branch,
+	RemoteBranch,
+	Tag,
+	Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphRef {
+	/// Short name, e.g. `main`, `origin/main` or `v1.0`.
+	pub name: String,
+	pub kind: RefKind,
+	/// HEAD points at this branch.
+	pub current: bool,
+}
+
+/// A line from a commit to one of its parents. It leaves the commit's lane,
+/// runs down `lane` and joins the parent's lane at the parent's row.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEdge {
+	pub parent: String,
+	pub lane: usize,
+	/// `None` if the parent is beyond the loaded commits, in which case the
+	/// line runs off the bottom of the graph.
+	pub parent_row: Option<usize>,
+	pub parent_lane: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphCommit {
+	pub hash: String,
+	pub parents: Vec<String>,
+	pub author_name: String,
+	pub author_email: String,
+	/// Seconds since the Unix epoch.
+	pub timestamp: i64,
+	pub subject: String,
+	pub refs: Vec<GraphRef>,
+	pub lane: usize,
+	pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitGraph {
+	pub commits: Vec<GraphCommit>,
+	/// The widest row, in lanes.
+	pub lane_count: usize,
+	/// More commits follow; load them with `skip`.
+	pub has_more: bool,
+}
+
+/// Parses `%D` with `--decorate=full`, e.g.
+/// `HEAD -> refs/heads/main, refs/remotes/origin/main, tag: refs/tags/v1`.
+fn parse_refs(decorations: &str) -> Vec<GraphRef> {
+	let mut refs = Vec::new();
+	for decoration in decorations.split(", ").filter(|d| !d.is_empty()) {
+		let (name, current) = match decoration.strip_prefix("HEAD -> ") {
+			Some(branch) => {
+				refs.push(GraphRef {
+					name: "HEAD".to_string(),
+					kind: RefKind::Head,
+					current: false,
+				});
+				(branch, true)
+			}
+			None => (decoration, false),
+		};
+		let name = name.strip_prefix("tag: ").unwrap_or(name);
+		let (name, kind) = if let Some(branch) = name.strip_prefix("refs/heads/") {
+			(branch, RefKind::Branch)
+		} else if let Some(branch) = name.strip_prefix("refs/remotes/") {
+			(branch, RefKind::RemoteBranch)
+		} else if let Some(tag) = name.strip_prefix("refs/tags/") {
+			(tag, RefKind::Tag)
+		} else if name == "HEAD" {
+			(name, RefKind::Head)
+		} else {
+			(name, RefKind::Other)
+		};
+		refs.push(GraphRef {
+			name: name.to_string(),
+			kind,
+			current,
+		});
+	}
+	refs
+}
+
+fn parse_log(output: &str) -> Vec<GraphCommit> {
+	output
+		.split(RECORD_SEP)
+		.filter_map(|record| {
+			let mut fields = record.trim_start_matches('\n').split(FIELD_SEP);
+			let hash = fields.next().filter(|h| !h.is_empty())?.to_string();
+			let parents = fields
+				.next()?
+				.split_whitespace()
+				.map(str::to_string)
+				.collect();
+			Some(GraphCommit {
+				hash,
+				parents,
+				author_name: fields.next()?.to_string(),
+				author_email: fields.next()?.to_string(),
+				timestamp: fields.next()?.parse().unwrap_or(0),
+				refs: parse_refs(fields.next()?),
+				subject: fields.next()?.to_string(),
+				lane: 0,
+				edges: Vec::new(),
+			})
+		})
+		.collect()
+}
+
+fn free_lane(lanes: &mut Vec<Option<String>>) -> usize {
+	match lanes.iter().position(Option::is_none) {
+		Some(lane) => lane,
+		None => {
+			lanes.push(None);
+			lanes.len() - 1
+		}
+	}
+}
+
+/// Assigns each commit, in topological order, a lane and routes the edges
+/// to its parents. Each lane holds the commit expected next in it; a commit
+/// takes the first lane expecting it and hands that lane to its first
+/// parent, while other parents get a lane of their own unless one already
+/// expects them.
+fn assign_lanes(commits: &mut [GraphCommit]) -> usize {
+	let mut lanes: Vec<Option<String>> = Vec::new();
+	let mut lane_count = 0;
+	for commit in commits.iter_mut() {
+		let lane = match lanes
+			.iter()
+			.position(|expected| expected.as_deref() == Some(commit.hash.as_str()))
+		{
+			Some(lane) => lane,
+			None => free_lane(&mut lanes),
+		};
+		// Other lanes that were heading here end at this commit
+		for expected in lanes.iter_mut() {
+			if expected.as_deref() == Some(commit.hash.as_str()) {
+				*expected = None;
+			}
+		}
+
+		for (i, parent) in commit.parents.iter().enumerate() {
+			let edge_lane = if i == 0 {
+				lane
+			} else if let Some(existing) = lanes
+				.iter()
+				.position(|expected| expected.as_deref() == Some(parent.as_str()))
+			{
+				existing
+			} else {
+				free_lane(&mut lanes)
+			};
+			lanes[edge_lane] = Some(parent.clone());
+			commit.edges.push(GraphEdge {
+				parent: parent.clone(),
+				lane: edge_lane,
+				parent_row: None,
+				parent_lane: None,
+			});
+		}
+		commit.lane = lane;
+		lane_count = lane_count.max(lanes.len());
+
+		while lanes.last().is_some_and(Option::is_none) {
+			lanes.pop();
+		}
+	}
+
+	let rows: HashMap<String, (usize, usize)> = commits
+		.iter()
+		.enumerate()
+		.map(|(row, commit)| (commit.hash.clone(), (row, commit.lane)))
+		.collect();
+	for edge in commits
+		.iter_mut()
+		.flat_map(|commit| commit.edges.iter_mut())
+	{
+		if let Some(&(row, lane)) = rows.get(&edge.parent) {
+			edge.parent_row = Some(row);
+			edge.parent_lane = Some(lane);
+		}
+	}
+	lane_count
+}
+
+pub fn commit_graph(
+	session: &OsSession,
+	directory: &str,
+	options: &CommitGraphOptions,
+) -> Result<CommitGraph> {
+	if options.revisions.iter().any(|rev| rev.starts_with('-')) {
+		return Err(anyhow!("Invalid revision"));
+	}
+	let max_count = options.max_count.unwrap_or(DEFAULT_MAX_COUNT);
+	let format = format!(
+		"--format=%H{0}%P{0}%an{0}%ae{0}%at{0}%D{0}%s{1}",
+		FIELD_SEP, RECORD_SEP
+	);
+	let max = format!("--max-count={}", max_count + 1);
+	let skip = format!("--skip={}", options.skip);
+	let mut args = vec![
+		"log",
+		"--topo-order",
+		"--decorate=full",
+		&format,
+		&max,
+		&skip,
+	];
+	if options.revisions.is_empty() {
+		args.push("--all");
+	}
+	args.extend(options.revisions.iter().map(String::as_str));
+	args.push("--");
+	args.extend(options.path.as_deref());
+
+	let mut commits = parse_log(&run_git(session, directory, &args)?);
+	let has_more = commits.len() > max_count;
+	commits.truncate(max_count);
+	let lane_count = assign_lanes(&mut commits);
+	Ok(CommitGraph {
+		commits,
+		lane_count,
+		has_more,
+	})
+}
+
+#[tauri::command]
+pub async fn git_commit_graph(
+	directory: String,
+	options: Option<CommitGraphOptions>,
+	os_session: OsSession,
+) -> Result<CommitGraph, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		commit_graph(&os_session, &directory, &options.unwrap_or_default())
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
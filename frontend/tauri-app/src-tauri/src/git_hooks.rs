@@ -0,0 +1,170 @@
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::os::OsSession;
+
+/// Hooks `git commit` runs, in order.
+const COMMIT_HOOKS: [&str; 4] = [
+	"pre-commit",
+	"prepare-commit-msg",
+	"commit-msg",
+	"post-commit",
+];
+
+/// Hooks that can stop a commit. `--no-verify` skips all but
+/// `prepare-commit-msg`.
+const BLOCKING_HOOKS: [&str; 3] = ["pre-commit", "prepare-commit-msg", "commit-msg"];
+
+/// Payload of the `git-hook-output` event, sent for each line printed
+/// while committing, including git's own summary.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookOutput {
+	pub directory: String,
+	/// `stdout` or `stderr`.
+	pub stream: &'static str,
+	pub line: String,
+}
+
+pub struct CommitOutput {
+	pub success: bool,
+	pub stdout: String,
+	pub stderr: String,
+	/// Both streams, interleaved as they were printed.
+	pub combined: String,
+}
+
+fn is_executable(path: &Path) -> bool {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		path.metadata()
+			.is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+	}
+	#[cfg(not(unix))]
+	{
+		// Git for Windows runs hooks regardless of permissions
+		path.is_file()
+	}
+}
+
+/// Commit hooks installed in the repository at `directory`, honouring
+/// `core.hooksPath`.
+pub fn commit_hooks(session: &OsSession, directory: &str) -> Vec<String> {
+	let output = session
+		.build_process_command("git", directory)
+		.and_then(|mut cmd| {
+			Ok(cmd.args(["rev-parse", "--git-path", "hooks"]).output()?)
+		});
+	let hooks_dir = match output {
+		Ok(output) if output.status.success() => {
+			String::from_utf8_lossy(&output.stdout).trim().to_string()
+		}
+		_ => return Vec::new(),
+	};
+	let hooks_dir = if Path::new(&hooks_dir).is_absolute() || hooks_dir.starts_with('/') {
+		hooks_dir
+	} else {
+		format!("{}/{}", directory.trim_end_matches(['/', '\\']), hooks_dir)
+	};
+
+	COMMIT_HOOKS
+		.iter()
+		.filter(|hook| {
+			let path = session.host_path_for(&format!("{}/{}", hooks_dir, hook));
+			match session {
+				OsSession::Local(_) => is_executable(&path),
+				// Permissions can't be read through \\wsl$
+				OsSession::Wsl(_) => path.is_file(),
+			}
+		})
+		.map(|hook| hook.to_string())
+		.collect()
+}
+
+/// Runs a `git commit` command, emitting `git-hook-output` events for its
+/// output as it arrives when `stream` is set.
+pub fn run_commit(
+	mut cmd: Command,
+	app_handle: &AppHandle,
+	directory: &str,
+	stream: bool,
+) -> Result<CommitOutput, String> {
+	let mut child = cmd
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| format!("Failed to execute git commit command: {}", e))?;
+
+	let combined = Arc::new(Mutex::new(Vec::new()));
+	let pump = |reader: Box<dyn Read + Send>, name: &'static str| {
+		let combined = combined.clone();
+		let app_handle = app_handle.clone();
+		let directory = directory.to_string();
+		thread::spawn(move || {
+			let mut captured = Vec::new();
+			for line in BufReader::new(reader).lines().map_while(|line| line.ok()) {
+				if stream {
+					let _ = app_handle.emit(
+						"git-hook-output",
+						HookOutput {
+							directory: directory.clone(),
+							stream: name,
+							line: line.clone(),
+						},
+					);
+				}
+				combined.lock().unwrap().push(line.clone());
+				captured.push(line);
+			}
+			captured.join("\n")
+		})
+	};
+	let stdout = child.stdout.take().map(|out| pump(Box::new(out), "stdout"));
+	let stderr = child.stderr.take().map(|err| pump(Box::new(err), "stderr"));
+
+	let status = child
+		.wait()
+		.map_err(|e| format!("Failed to execute git commit command: {}", e))?;
+	let stdout = stdout.and_then(|t| t.join().ok()).unwrap_or_default();
+	let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+	let combined = combined.lock().unwrap().join("\n");
+	Ok(CommitOutput {
+		success: status.success(),
+		stdout,
+		stderr,
+		combined,
+	})
+}
+
+/// Whether a failed commit was stopped by a hook rather than by git, in
+/// which case git itself prints nothing of note.
+pub fn failed_in_hook(output: &CommitOutput, hooks: &[String], no_verify: bool) -> bool {
+	let hook_ran = hooks.iter().any(|hook| {
+		BLOCKING_HOOKS.contains(&hook.as_str())
+			&& (!no_verify || hook == "prepare-commit-msg")
+	});
+	hook_ran
+		&& !output
+			.combined
+			.lines()
+			.any(|line| line.starts_with("fatal:"))
+}
+
+/// Commit hooks installed in a repository, so the UI can say they will run.
+#[tauri::command]
+pub async fn git_list_hooks(
+	directory: String,
+	os_session: OsSession,
+) -> Result<Vec<String>, String> {
+	tauri::async_runtime::spawn_blocking(move || commit_hooks(&os_session, &directory))
+		.await
+		.map_err(|e| e.to_string())
+}
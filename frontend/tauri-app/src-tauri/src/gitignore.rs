@@ -0,0 +1,262 @@
+use std::fs;
+use std::io::Write;
+use std::process::Stdio;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::document_manager::write_atomically;
+use crate::os::OsSession;
+
+/// Built-in `.gitignore` templates, by language.
+const TEMPLATES: &[(&str, &[&str])] = &[
+	("rust", &["/target/", "**/*.rs.bk", "*.pdb"]),
+	(
+		"node",
+		&[
+			"node_modules/",
+			"dist/",
+			"build/",
+			".env",
+			".env.*",
+			"!.env.example",
+			"npm-debug.log*",
+			"yarn-debug.log*",
+			"yarn-error.log*",
+			"pnpm-debug.log*",
+			".npm/",
+			".eslintcache",
+			"coverage/",
+		],
+	),
+	(
+		"python",
+		&[
+			"__pycache__/",
+			"*.py[cod]",
+			"*.egg-info/",
+			".eggs/",
+			"build/",
+			"dist/",
+			".venv/",
+			"venv/",
+			".env",
+			".pytest_cache/",
+			".mypy_cache/",
+			".ruff_cache/",
+			".coverage",
+			"htmlcov/",
+		],
+	),
+	(
+		"go",
+		&["*.exe", "*.test", "*.out", "/vendor/", "go.work.sum"],
+	),
+	(
+		"java",
+		&[
+			"*.class", "*.jar", "*.war", "target/", "build/", ".gradle/", "out/",
+		],
+	),
+	(
+		"c",
+		&[
+			"*.o", "*.obj", "*.a", "*.lib", "*.so", "*.dylib", "*.dll", "*.exe", "build/",
+		],
+	),
+	(
+		"editors",
+		&[
+			".DS_Store",
+			"Thumbs.db",
+			".idea/",
+			".vscode/*",
+			"!.vscode/extensions.json",
+			"*.swp",
+		],
+	),
+];
+
+/// The rule deciding whether a path is ignored, as `git check-ignore -v`
+/// reports it. A negated pattern (`!...`) means the path is not ignored.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IgnoreRule {
+	/// The file the pattern is in, relative to the repository, or a path
+	/// such as `.git/info/exclude` or the global excludes file.
+	pub source: String,
+	pub line: u32,
+	pub pattern: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IgnoreStatus {
+	pub path: String,
+	pub ignored: bool,
+	/// The last rule matching the path, if any.
+	pub rule: Option<IgnoreRule>,
+}
+
+/// Which of `paths`, relative to `directory`, are ignored and why. Tracked
+/// files are reported as matching rules too, since adding a pattern
+/// doesn't untrack them.
+pub fn check_ignore(
+	session: &OsSession,
+	directory: &str,
+	paths: &[String],
+) -> Result<Vec<IgnoreStatus>> {
+	let mut child = session
+		.build_process_command("git", directory)?
+		.args([
+			"check-ignore",
+			"--verbose",
+			"--non-matching",
+			"--no-index",
+			"-z",
+			"--stdin",
+		])
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| anyhow!("Failed to execute git check-ignore: {}", e))?;
+	let mut stdin = child
+		.stdin
+		.take()
+		.ok_or_else(|| anyhow!("Failed to open git check-ignore stdin"))?;
+	let input: Vec<u8> = paths
+		.iter()
+		.flat_map(|path| path.bytes().chain([0]))
+		.collect();
+	let writer = thread::spawn(move || stdin.write_all(&input));
+	let output = child.wait_with_output()?;
+	let _ = writer.join();
+
+	// Exits with 1 when nothing is ignored
+	if !matches!(output.status.code(), Some(0 | 1)) {
+		return Err(anyhow!(
+			"git check-ignore failed: {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+
+	// Each path gives four fields: source, line, pattern and path, the
+	// first three empty if no rule matched
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let fields: Vec<&str> = stdout.split('\0').collect();
+	Ok(fields
+		.chunks_exact(4)
+		.map(|fields| {
+			let [source, line, pattern, path] =
+				[fields[0], fields[1], fields[2], fields[3]];
+			let rule = (!source.is_empty()).then(|| IgnoreRule {
+				source: source.to_string(),
+				line: line.parse().unwrap_or(0),
+				pattern: pattern.to_string(),
+			});
+			IgnoreStatus {
+				path: path.to_string(),
+				ignored: rule
+					.as_ref()
+					.is_some_and(|rule| !rule.pattern.starts_with('!')),
+				rule,
+			}
+		})
+		.collect())
+}
+
+/// Appends `patterns` to the `.gitignore` in `directory`, creating it if
+/// needed. Patterns already in the file are skipped; the ones added are
+/// returned.
+pub fn append_patterns(
+	session: &OsSession,
+	directory: &str,
+	patterns: &[String],
+	comment: Option<&str>,
+) -> Result<Vec<String>> {
+	let path = session.host_path_for(&format!(
+		"{}/.gitignore",
+		directory.trim_end_matches(['/', '\\'])
+	));
+	let mut content = match fs::read_to_string(&path) {
+		Ok(content) => content,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+		Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+	};
+
+	let mut added: Vec<String> = Vec::new();
+	for pattern in patterns.iter().map(|p| p.trim()) {
+		if pattern.is_empty() || pattern.contains('\n') {
+			continue;
+		}
+		let present = content.lines().any(|line| line.trim() == pattern);
+		if !present && !added.iter().any(|p| p == pattern) {
+			added.push(pattern.to_string());
+		}
+	}
+	if added.is_empty() {
+		return Ok(added);
+	}
+
+	if !content.is_empty() && !content.ends_with('\n') {
+		content.push('\n');
+	}
+	if let Some(comment) = comment {
+		if !content.is_empty() {
+			content.push('\n');
+		}
+		content.push_str(&format!("# {}\n", comment));
+	}
+	for pattern in &added {
+		content.push_str(pattern);
+		content.push('\n');
+	}
+	write_atomically(&path, content.as_bytes())?;
+	Ok(added)
+}
+
+pub fn template(language: &str) -> Option<Vec<String>> {
+	TEMPLATES
+		.iter()
+		.find(|(name, _)| name.eq_ignore_ascii_case(language))
+		.map(|(_, patterns)| patterns.iter().map(|p| p.to_string()).collect())
+}
+
+#[tauri::command]
+pub async fn git_check_ignore(
+	directory: String,
+	paths: Vec<String>,
+	os_session: OsSession,
+) -> Result<Vec<IgnoreStatus>, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		check_ignore(&os_session, &directory, &paths)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn append_to_gitignore(
+	directory: String,
+	patterns: Vec<String>,
+	comment: Option<String>,
+	os_session: OsSession,
+) -> Result<Vec<String>, String> {
+	append_patterns(&os_session, &directory, &patterns, comment.as_deref())
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_gitignore_templates() -> Result<Vec<String>, String> {
+	Ok(TEMPLATES.iter().map(|(name, _)| name.to_string()).collect())
+}
+
+/// The patterns of a built-in template, to preview or pass to
+/// `append_to_gitignore`.
+#[tauri::command]
+pub async fn get_gitignore_template(language: String) -> Result<Vec<String>, String> {
+	template(&language).ok_or_else(|| format!("No .gitignore template for {}", language))
+}
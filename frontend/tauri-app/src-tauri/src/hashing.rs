@@ -0,0 +1,204 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::os::OsSession;
+
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+	#[default]
+	Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathKind {
+	File,
+	Directory,
+	Missing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathHash {
+	pub path: String,
+	pub kind: PathKind,
+	/// Hex digest. A directory's covers the relative path and contents of
+	/// every file under it except `.git`, so two copies of a tree hash the
+	/// same.
+	pub hash: Option<String>,
+	/// Bytes hashed.
+	pub size: u64,
+	/// Files hashed, 1 for a file.
+	pub files: usize,
+	pub error: Option<String>,
+}
+
+fn to_hex(digest: &[u8]) -> String {
+	digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+	let mut file = File::open(path)?;
+	let mut hasher = Sha256::new();
+	let mut buffer = vec![0; READ_BUFFER_SIZE];
+	loop {
+		let read = file.read(&mut buffer)?;
+		if read == 0 {
+			break;
+		}
+		hasher.update(&buffer[..read]);
+	}
+	Ok(to_hex(&hasher.finalize()))
+}
+
+/// What goes into a tree hash for one entry: a file's contents, or where a
+/// symlink points, since links aren't followed.
+fn hash_entry(path: &Path) -> Result<(String, u64)> {
+	let metadata = fs::symlink_metadata(path)?;
+	if metadata.file_type().is_symlink() {
+		let target = fs::read_link(path)?;
+		let digest = Sha256::digest(format!("link:{}", target.to_string_lossy()));
+		return Ok((to_hex(&digest), 0));
+	}
+	Ok((sha256_file(path)?, metadata.len()))
+}
+
+/// Hashes `paths` on as many threads as there are cores.
+fn hash_all(paths: &[PathBuf]) -> Vec<Result<(String, u64), String>> {
+	let results = Mutex::new(
+		(0..paths.len())
+			.map(|_| Err(String::new()))
+			.collect::<Vec<_>>(),
+	);
+	let next = AtomicUsize::new(0);
+	let workers = thread::available_parallelism()
+		.map(|n| n.get())
+		.unwrap_or(4)
+		.min(paths.len().max(1));
+	thread::scope(|scope| {
+		for _ in 0..workers {
+			scope.spawn(|| loop {
+				let index = next.fetch_add(1, Ordering::SeqCst);
+				let Some(path) = paths.get(index) else {
+					break;
+				};
+				let result = hash_entry(path).map_err(|e| e.to_string());
+				results.lock().unwrap()[index] = result;
+			});
+		}
+	});
+	results.into_inner().unwrap()
+}
+
+/// Hashes files and directories in `session`, reading them from this
+/// machine in parallel.
+pub fn hash_session_paths(
+	session: &OsSession,
+	paths: &[String],
+	algorithm: HashAlgorithm,
+) -> Vec<PathHash> {
+	// The only algorithm so far
+	let HashAlgorithm::Sha256 = algorithm;
+
+	// Every file to hash, with the input it belongs to and its path
+	// relative to that input
+	let mut entries: Vec<(usize, String, PathBuf)> = Vec::new();
+	let mut hashes: Vec<PathHash> = Vec::with_capacity(paths.len());
+	for (index, path) in paths.iter().enumerate() {
+		let host_path = session.host_path_for(path);
+		let kind = match fs::metadata(&host_path) {
+			Ok(metadata) if metadata.is_dir() => PathKind::Directory,
+			Ok(_) => PathKind::File,
+			Err(_) => PathKind::Missing,
+		};
+		match kind {
+			PathKind::File => entries.push((index, String::new(), host_path)),
+			PathKind::Directory => {
+				for entry in WalkDir::new(&host_path)
+					.into_iter()
+					.filter_entry(|entry| entry.file_name() != ".git")
+					.filter_map(|entry| entry.ok())
+					.filter(|entry| !entry.file_type().is_dir())
+				{
+					let relative = entry
+						.path()
+						.strip_prefix(&host_path)
+						.map(|relative| relative.to_string_lossy().replace('\\', "/"))
+						.unwrap_or_default();
+					entries.push((index, relative, entry.into_path()));
+				}
+			}
+			PathKind::Missing => {}
+		}
+		hashes.push(PathHash {
+			path: path.clone(),
+			kind,
+			hash: None,
+			size: 0,
+			files: 0,
+			error: (kind == PathKind::Missing)
+				.then(|| "No such file or directory".to_string()),
+		});
+	}
+
+	// Sorted so a directory's entries are always combined in the same order
+	entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+	let files: Vec<PathBuf> = entries.iter().map(|(_, _, path)| path.clone()).collect();
+	let results = hash_all(&files);
+
+	let mut trees: Vec<Sha256> = paths.iter().map(|_| Sha256::new()).collect();
+	for ((index, relative, _), result) in entries.iter().zip(results) {
+		let hash = &mut hashes[*index];
+		match result {
+			Ok((digest, size)) => {
+				hash.size += size;
+				hash.files += 1;
+				match hash.kind {
+					PathKind::File => hash.hash = Some(digest),
+					_ => trees[*index].update(format!("{}\0{}\n", relative, digest)),
+				}
+			}
+			// One unreadable file makes the whole tree's hash meaningless
+			Err(e) => {
+				if hash.error.is_none() {
+					hash.error = Some(if relative.is_empty() {
+						e
+					} else {
+						format!("{}: {}", relative, e)
+					});
+				}
+			}
+		}
+	}
+	for (hash, tree) in hashes.iter_mut().zip(trees) {
+		if hash.kind == PathKind::Directory && hash.error.is_none() {
+			hash.hash = Some(to_hex(&tree.finalize()));
+		}
+	}
+	hashes
+}
+
+#[tauri::command]
+pub async fn hash_paths(
+	os_session: OsSession,
+	paths: Vec<String>,
+	algorithm: Option<HashAlgorithm>,
+) -> Result<Vec<PathHash>, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		hash_session_paths(&os_session, &paths, algorithm.unwrap_or_default())
+	})
+	.await
+	.map_err(|e| e.to_string())
+}
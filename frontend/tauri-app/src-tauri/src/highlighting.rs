@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, FontStyle, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::file_reader::is_binary;
+use crate::os::OsSession;
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+/// How much of a file is highlighted when no limit is asked for.
+const DEFAULT_MAX_BYTES: u64 = 64 * 1024;
+/// Previews never highlight more than this, whatever is asked for.
+const MAX_BYTES_LIMIT: u64 = 1024 * 1024;
+
+pub fn syntaxes() -> &'static SyntaxSet {
+	static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+	SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+pub fn themes() -> &'static ThemeSet {
+	static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+	THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// The syntax for a Markdown fence language or file extension.
+pub fn syntax_for_token(token: &str) -> Option<&'static SyntaxReference> {
+	// The bundled syntaxes have no TypeScript; JavaScript is close
+	let token = match token {
+		"ts" | "tsx" | "mts" | "cts" | "typescript" => "js",
+		"sh" | "shell" | "zsh" | "console" => "bash",
+		token => token,
+	};
+	syntaxes().find_syntax_by_token(token)
+}
+
+/// A run of text in one style.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Span {
+	pub text: String,
+	/// `#rrggbb`, or `#rrggbbaa` when not opaque.
+	pub color: String,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub bold: bool,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub italic: bool,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub underline: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightedFile {
+	/// The syntax used, e.g. "Rust" or "Plain Text".
+	pub language: String,
+	pub theme: String,
+	pub background: Option<String>,
+	pub foreground: Option<String>,
+	/// Spans of each line, without line terminators.
+	pub lines: Vec<Vec<Span>>,
+	/// Whether the file goes on past the highlighted lines.
+	pub truncated: bool,
+	pub total_size: u64,
+}
+
+fn hex(color: Color) -> String {
+	if color.a == 0xff {
+		format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+	} else {
+		format!(
+			"#{:02x}{:02x}{:02x}{:02x}",
+			color.r, color.g, color.b, color.a
+		)
+	}
+}
+
+fn syntax_for_file(path: &str, text: &str) -> &'static SyntaxReference {
+	let syntaxes = syntaxes();
+	let extension = Path::new(path)
+		.extension()
+		.and_then(|e| e.to_str())
+		.map(str::to_lowercase);
+	extension
+		.as_deref()
+		.and_then(|extension| syntaxes.find_syntax_by_extension(extension))
+		.or_else(|| {
+			let name = Path::new(path).file_name()?.to_str()?;
+			syntaxes.find_syntax_by_extension(name)
+		})
+		.or_else(|| syntaxes.find_syntax_by_first_line(text.lines().next()?))
+		.or_else(|| syntax_for_token(extension.as_deref()?))
+		.unwrap_or_else(|| syntaxes.find_syntax_plain_text())
+}
+
+/// Highlights `text`, the start of the file at `path`, with `theme`.
+pub fn highlight(path: &str, text: &str, theme_name: &str) -> Result<HighlightedFile> {
+	let theme = themes()
+		.themes
+		.get(theme_name)
+		.ok_or_else(|| anyhow!("No highlighting theme {}", theme_name))?;
+	let syntax = syntax_for_file(path, text);
+	let mut highlighter = HighlightLines::new(syntax, theme);
+	let mut lines = Vec::new();
+	for line in LinesWithEndings::from(text) {
+		let mut spans: Vec<Span> = Vec::new();
+		for (style, piece) in highlighter.highlight_line(line, syntaxes())? {
+			let piece = piece.trim_end_matches(['\n', '\r']);
+			if piece.is_empty() {
+				continue;
+			}
+			let color = hex(style.foreground);
+			let bold = style.font_style.contains(FontStyle::BOLD);
+			let italic = style.font_style.contains(FontStyle::ITALIC);
+			let underline = style.font_style.contains(FontStyle::UNDERLINE);
+			// Adjacent pieces often share a style; merging them keeps the
+			// payload small
+			match spans.last_mut() {
+				Some(last)
+					if last.color == color
+						&& (last.bold, last.italic, last.underline)
+							== (bold, italic, underline) =>
+				{
+					last.text.push_str(piece)
+				}
+				_ => spans.push(Span {
+					text: piece.to_string(),
+					color,
+					bold,
+					italic,
+					underline,
+				}),
+			}
+		}
+		lines.push(spans);
+	}
+	Ok(HighlightedFile {
+		language: syntax.name.clone(),
+		theme: theme_name.to_string(),
+		background: theme.settings.background.map(hex),
+		foreground: theme.settings.foreground.map(hex),
+		lines,
+		truncated: false,
+		total_size: text.len() as u64,
+	})
+}
+
+/// Highlights the first `max_bytes` of the file at `path` in `session`,
+/// ending at the last whole line.
+pub fn highlight_session_file(
+	session: &OsSession,
+	path: &str,
+	theme: &str,
+	max_bytes: u64,
+) -> Result<HighlightedFile> {
+	let host_path = session.host_path_for(path);
+	let mut file = File::open(&host_path)?;
+	let total_size = file.metadata()?.len();
+	let mut bytes = Vec::new();
+	file.by_ref()
+		.take(max_bytes.clamp(1, MAX_BYTES_LIMIT))
+		.read_to_end(&mut bytes)?;
+	if is_binary(&bytes) {
+		return Err(anyhow!("{} is a binary file", path));
+	}
+	let truncated = (bytes.len() as u64) < total_size;
+	if truncated {
+		if let Some(end) = bytes.iter().rposition(|&b| b == b'\n') {
+			bytes.truncate(end + 1);
+		}
+	}
+	let text = String::from_utf8_lossy(&bytes);
+	let mut highlighted = highlight(path, &text, theme)?;
+	highlighted.truncated = truncated;
+	highlighted.total_size = total_size;
+	Ok(highlighted)
+}
+
+/// Styled spans for the start of a file, for read-only previews like diff
+/// context and search results that don't need an editor.
+#[tauri::command]
+pub async fn highlight_file(
+	os_session: OsSession,
+	path: String,
+	theme: Option<String>,
+	max_bytes: Option<u64>,
+) -> Result<HighlightedFile, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		highlight_session_file(
+			&os_session,
+			&path,
+			theme.as_deref().unwrap_or(DEFAULT_THEME),
+			max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Names of the themes `highlight_file` and `markdown_highlight_css` accept.
+#[tauri::command]
+pub async fn list_highlight_themes() -> Result<Vec<String>, String> {
+	Ok(themes().themes.keys().cloned().collect())
+}
@@ -0,0 +1,679 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tree_sitter_tags::{TagsConfiguration, TagsContext};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::file_watcher::FileWatcher;
+use crate::watched_workspaces::{
+	covers, has_files_under, WatchedWorkspace, WatchedWorkspaces,
+};
+
+/// Bumped whenever the persisted format or the extracted symbols change, so
+/// stale indexes on disk are rebuilt instead of loaded.
+const INDEX_FORMAT_VERSION: u32 = 1;
+/// Files larger than this are almost always generated or minified.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+/// How long the watcher must be quiet before queued changes are indexed.
+const UPDATE_DEBOUNCE: Duration = Duration::from_millis(500);
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+const MAX_SEARCH_LIMIT: usize = 1000;
+/// Directories never worth indexing: dependencies, build output and VCS data.
+pub const IGNORED_DIRS: &[&str] = &[
+	"node_modules",
+	"target",
+	"dist",
+	"build",
+	"out",
+	"vendor",
+	"venv",
+	"__pycache__",
+];
+
+/// A symbol definition found in a source file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Symbol {
+	pub name: String,
+	/// `function`, `method`, `class`, `interface`, `module`, ... as named by
+	/// the grammar's tags query.
+	pub kind: String,
+	/// Zero-based line of the definition.
+	pub line: u32,
+	/// Zero-based UTF-16 column of the name, as LSP positions use.
+	pub column: u32,
+}
+
+/// A search result: a symbol and the file it is defined in.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolMatch {
+	#[serde(flatten)]
+	pub symbol: Symbol,
+	pub path: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexState {
+	Indexing,
+	Ready,
+}
+
+/// Payload of the `symbol-index-status` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStatus {
+	pub root: String,
+	pub state: IndexState,
+	pub file_count: usize,
+	pub symbol_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileEntry {
+	size: u64,
+	/// Modification time in milliseconds since the epoch.
+	modified: u64,
+	symbols: Vec<Symbol>,
+}
+
+/// What is persisted for a workspace, keyed by path relative to the root.
+#[derive(Serialize, Deserialize)]
+struct PersistedIndex {
+	version: u32,
+	root: PathBuf,
+	files: HashMap<PathBuf, FileEntry>,
+}
+
+/// A kind of source file, told apart by its extension or, for files without
+/// a telling one, by its name. The symbol index and the code statistics both
+/// go by this table, so they agree on what language a file is in.
+pub struct FileType {
+	pub name: &'static str,
+	extensions: &'static [&'static str],
+	/// File names without a telling extension, e.g. `Dockerfile`.
+	file_names: &'static [&'static str],
+}
+
+const fn file_type(name: &'static str, extensions: &'static [&'static str]) -> FileType {
+	FileType {
+		name,
+		extensions,
+		file_names: &[],
+	}
+}
+
+const FILE_TYPES: &[FileType] = &[
+	file_type("Rust", &["rs"]),
+	file_type("TypeScript", &["ts", "mts", "cts"]),
+	file_type("TSX", &["tsx"]),
+	file_type("JavaScript", &["js", "jsx", "mjs", "cjs"]),
+	file_type("Python", &["py", "pyi"]),
+	file_type("Go", &["go"]),
+	file_type("Java", &["java"]),
+	file_type("Kotlin", &["kt", "kts"]),
+	file_type("Swift", &["swift"]),
+	file_type("C", &["c", "h"]),
+	file_type("C++", &["cpp", "cc", "cxx", "hpp", "hh", "hxx"]),
+	file_type("C#", &["cs"]),
+	file_type("Scala", &["scala", "sc"]),
+	file_type("Dart", &["dart"]),
+	file_type("Zig", &["zig"]),
+	file_type("Solidity", &["sol"]),
+	file_type("Protobuf", &["proto"]),
+	file_type("PHP", &["php"]),
+	file_type("Ruby", &["rb"]),
+	file_type("Elixir", &["ex", "exs"]),
+	file_type("Haskell", &["hs"]),
+	file_type("Lua", &["lua"]),
+	file_type("SQL", &["sql"]),
+	file_type("Shell", &["sh", "bash", "zsh", "fish"]),
+	file_type("PowerShell", &["ps1", "psm1"]),
+	file_type("Nix", &["nix"]),
+	file_type("HTML", &["html", "htm"]),
+	file_type("Vue", &["vue"]),
+	file_type("Svelte", &["svelte"]),
+	file_type("CSS", &["css"]),
+	file_type("SCSS", &["scss", "sass", "less"]),
+	file_type("XML", &["xml", "xsd", "xsl"]),
+	file_type("JSON", &["json", "jsonc"]),
+	file_type("YAML", &["yml", "yaml"]),
+	file_type("TOML", &["toml"]),
+	file_type("GraphQL", &["graphql", "gql"]),
+	file_type("Markdown", &["md", "mdx"]),
+	FileType {
+		name: "Dockerfile",
+		extensions: &["dockerfile"],
+		file_names: &["Dockerfile", "Containerfile"],
+	},
+	FileType {
+		name: "Makefile",
+		extensions: &["mk"],
+		file_names: &["Makefile", "GNUmakefile", "makefile"],
+	},
+	FileType {
+		name: "CMake",
+		extensions: &["cmake"],
+		file_names: &["CMakeLists.txt"],
+	},
+];
+
+/// The file type of `path`, by its name first, then by its extension.
+pub fn file_type_for(path: &Path) -> Option<&'static FileType> {
+	let file_name = path.file_name()?.to_str()?;
+	if let Some(file_type) = FILE_TYPES
+		.iter()
+		.find(|file_type| file_type.file_names.contains(&file_name))
+	{
+		return Some(file_type);
+	}
+	let extension = path.extension()?.to_str()?.to_lowercase();
+	FILE_TYPES
+		.iter()
+		.find(|file_type| file_type.extensions.contains(&extension.as_str()))
+}
+
+/// A tree-sitter grammar with its tags query.
+struct Language {
+	/// Name of the `FileType` it parses.
+	name: &'static str,
+	config: TagsConfiguration,
+}
+
+fn languages() -> &'static [Language] {
+	static LANGUAGES: OnceLock<Vec<Language>> = OnceLock::new();
+	LANGUAGES.get_or_init(|| {
+		// The TypeScript tags query only covers what it adds to JavaScript
+		let typescript_tags = format!(
+			"{}\n{}",
+			tree_sitter_javascript::TAGS_QUERY,
+			tree_sitter_typescript::TAGS_QUERY
+		);
+		let grammars: Vec<(&'static str, tree_sitter::Language, &str)> = vec![
+			(
+				"Rust",
+				tree_sitter_rust::LANGUAGE.into(),
+				tree_sitter_rust::TAGS_QUERY,
+			),
+			(
+				"JavaScript",
+				tree_sitter_javascript::LANGUAGE.into(),
+				tree_sitter_javascript::TAGS_QUERY,
+			),
+			(
+				"TypeScript",
+				tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+				&typescript_tags,
+			),
+			(
+				"TSX",
+				tree_sitter_typescript::LANGUAGE_TSX.into(),
+				&typescript_tags,
+			),
+			(
+				"Python",
+				tree_sitter_python::LANGUAGE.into(),
+				tree_sitter_python::TAGS_QUERY,
+			),
+			(
+				"Go",
+				tree_sitter_go::LANGUAGE.into(),
+				tree_sitter_go::TAGS_QUERY,
+			),
+		];
+
+		grammars
+			.into_iter()
+			.filter_map(|(name, language, tags_query)| {
+				let config = TagsConfiguration::new(language, tags_query, "")
+					.inspect_err(|e| {
+						log::warn!("Failed to load tags query for {}: {}", name, e)
+					})
+					.ok()?;
+				Some(Language { name, config })
+			})
+			.collect()
+	})
+}
+
+fn language_for(path: &Path) -> Option<&'static Language> {
+	let name = file_type_for(path)?.name;
+	languages().iter().find(|l| l.name == name)
+}
+
+pub fn is_ignored_dir(entry: &DirEntry) -> bool {
+	entry.depth() > 0
+		&& entry.file_type().is_dir()
+		&& entry
+			.file_name()
+			.to_str()
+			.map(|name| name.starts_with('.') || IGNORED_DIRS.contains(&name))
+			.unwrap_or(false)
+}
+
+pub fn modified_millis(metadata: &fs::Metadata) -> u64 {
+	metadata
+		.modified()
+		.ok()
+		.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+		.map(|d| d.as_millis() as u64)
+		.unwrap_or(0)
+}
+
+/// Extracts the symbol definitions of one file.
+fn extract_symbols(
+	context: &mut TagsContext,
+	language: &Language,
+	source: &[u8],
+) -> Result<Vec<Symbol>> {
+	let (tags, _) = context
+		.generate_tags(&language.config, source, None)
+		.map_err(|e| anyhow!("Failed to parse: {}", e))?;
+
+	let mut symbols = Vec::new();
+	for tag in tags {
+		let Ok(tag) = tag else {
+			continue;
+		};
+		if !tag.is_definition {
+			continue;
+		}
+		symbols.push(Symbol {
+			name: String::from_utf8_lossy(&source[tag.name_range.clone()]).to_string(),
+			kind: language
+				.config
+				.syntax_type_name(tag.syntax_type_id)
+				.to_string(),
+			line: tag.span.start.row as u32,
+			column: tag.utf16_column_range.start as u32,
+		});
+	}
+	Ok(symbols)
+}
+
+/// Ranks how well `name` matches the lowercased `query`: exact, prefix,
+/// substring, then subsequence. Lower is better.
+fn match_score(name: &str, query: &str) -> Option<u8> {
+	let name = name.to_lowercase();
+	if name == query {
+		Some(0)
+	} else if name.starts_with(query) {
+		Some(1)
+	} else if name.contains(query) {
+		Some(2)
+	} else {
+		let mut chars = name.chars();
+		query.chars().all(|q| chars.any(|c| c == q)).then_some(3)
+	}
+}
+
+struct Workspace {
+	root: PathBuf,
+	files: Mutex<HashMap<PathBuf, FileEntry>>,
+	state: Mutex<IndexState>,
+	/// Set when the workspace is closed, to stop an index build early.
+	closed: AtomicBool,
+}
+
+impl WatchedWorkspace for Workspace {
+	fn close(&self) {
+		self.closed.store(true, Ordering::Relaxed);
+	}
+}
+
+impl Workspace {
+	fn status(&self) -> IndexStatus {
+		let files = self.files.lock().unwrap();
+		IndexStatus {
+			root: self.root.to_string_lossy().to_string(),
+			state: *self.state.lock().unwrap(),
+			file_count: files.len(),
+			symbol_count: files.values().map(|f| f.symbols.len()).sum(),
+		}
+	}
+
+	/// Re-indexes `path` if it changed since it was last indexed, or drops it
+	/// if it no longer exists or isn't a supported source file. Returns
+	/// whether the index changed.
+	fn update_file(&self, context: &mut TagsContext, path: &Path) -> bool {
+		let Ok(relative) = path.strip_prefix(&self.root) else {
+			return false;
+		};
+		let language = language_for(path);
+		let metadata = fs::metadata(path).ok().filter(|m| m.is_file());
+
+		let (Some(language), Some(metadata)) = (language, metadata) else {
+			return self.files.lock().unwrap().remove(relative).is_some();
+		};
+		if metadata.len() > MAX_FILE_BYTES {
+			return self.files.lock().unwrap().remove(relative).is_some();
+		}
+
+		let size = metadata.len();
+		let modified = modified_millis(&metadata);
+		if let Some(entry) = self.files.lock().unwrap().get(relative) {
+			if entry.size == size && entry.modified == modified {
+				return false;
+			}
+		}
+
+		let symbols = match fs::read(path)
+			.map_err(anyhow::Error::from)
+			.and_then(|source| extract_symbols(context, language, &source))
+		{
+			Ok(symbols) => symbols,
+			Err(_) => return self.files.lock().unwrap().remove(relative).is_some(),
+		};
+		self.files.lock().unwrap().insert(
+			relative.to_path_buf(),
+			FileEntry {
+				size,
+				modified,
+				symbols,
+			},
+		);
+		true
+	}
+
+	/// Walks `dir` and updates every source file under it, dropping entries
+	/// for files that disappeared. Returns whether the index changed.
+	fn scan(&self, context: &mut TagsContext, dir: &Path) -> bool {
+		let mut changed = false;
+		let mut seen = HashSet::new();
+		for entry in WalkDir::new(dir)
+			.into_iter()
+			.filter_entry(|e| !is_ignored_dir(e))
+			.filter_map(|e| e.ok())
+		{
+			if self.closed.load(Ordering::Relaxed) {
+				return changed;
+			}
+			if !entry.file_type().is_file() || language_for(entry.path()).is_none() {
+				continue;
+			}
+			changed |= self.update_file(context, entry.path());
+			seen.insert(entry.into_path());
+		}
+
+		let Ok(relative_dir) = dir.strip_prefix(&self.root) else {
+			return changed;
+		};
+		let mut files = self.files.lock().unwrap();
+		let before = files.len();
+		files.retain(|relative, _| {
+			!relative.starts_with(relative_dir)
+				|| seen.contains(&self.root.join(relative))
+		});
+		changed || files.len() != before
+	}
+}
+
+/// Background symbol index of open workspaces, built with tree-sitter so
+/// symbol search works without a language server. Indexes are persisted
+/// in the app cache and kept current from file watcher events.
+pub struct IndexManager {
+	app_handle: AppHandle,
+	workspaces: WatchedWorkspaces<Workspace>,
+}
+
+impl IndexManager {
+	pub fn new(app_handle: AppHandle, watcher: Arc<FileWatcher>) -> Arc<Self> {
+		let manager = Arc::new(Self {
+			app_handle,
+			workspaces: WatchedWorkspaces::new(watcher),
+		});
+		manager.workspaces.watch_changes(
+			&manager,
+			UPDATE_DEBOUNCE,
+			|manager, paths, rescan| async move {
+				let _ = tauri::async_runtime::spawn_blocking(move || {
+					manager.apply_changes(paths, rescan)
+				})
+				.await;
+			},
+		);
+		manager
+	}
+
+	fn index_path(&self, root: &Path) -> Result<PathBuf> {
+		let mut hasher = DefaultHasher::new();
+		root.hash(&mut hasher);
+		Ok(self
+			.app_handle
+			.path()
+			.app_cache_dir()?
+			.join("symbol-index")
+			.join(format!("{:016x}.json", hasher.finish())))
+	}
+
+	fn load(&self, root: &Path) -> HashMap<PathBuf, FileEntry> {
+		self.index_path(root)
+			.ok()
+			.and_then(|path| fs::read(path).ok())
+			.and_then(|bytes| serde_json::from_slice::<PersistedIndex>(&bytes).ok())
+			.filter(|index| index.version == INDEX_FORMAT_VERSION && index.root == root)
+			.map(|index| index.files)
+			.unwrap_or_default()
+	}
+
+	fn persist(&self, workspace: &Workspace) -> Result<()> {
+		let path = self.index_path(&workspace.root)?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let index = PersistedIndex {
+			version: INDEX_FORMAT_VERSION,
+			root: workspace.root.clone(),
+			files: workspace.files.lock().unwrap().clone(),
+		};
+		fs::write(&path, serde_json::to_vec(&index)?)
+			.with_context(|| format!("Failed to write {}", path.display()))
+	}
+
+	fn emit_status(&self, workspace: &Workspace) {
+		let _ = self
+			.app_handle
+			.emit("symbol-index-status", workspace.status());
+	}
+
+	/// Starts indexing `root` in the background, picking up from the index
+	/// persisted by a previous session. Indexing an already indexed
+	/// workspace just reports its status.
+	pub fn index_workspace(self: &Arc<Self>, root: &str) -> Result<IndexStatus> {
+		let (workspace, created) = self.workspaces.open(
+			root,
+			|_| true,
+			|root| Workspace {
+				files: Mutex::new(self.load(&root)),
+				root,
+				state: Mutex::new(IndexState::Indexing),
+				closed: AtomicBool::new(false),
+			},
+		)?;
+		let status = workspace.status();
+		if !created {
+			return Ok(status);
+		}
+
+		let manager = self.clone();
+		tauri::async_runtime::spawn_blocking(move || {
+			let mut context = TagsContext::new();
+			workspace.scan(&mut context, &workspace.root);
+			if workspace.closed.load(Ordering::Relaxed) {
+				return;
+			}
+			*workspace.state.lock().unwrap() = IndexState::Ready;
+			if let Err(e) = manager.persist(&workspace) {
+				log::warn!("Failed to persist symbol index: {}", e);
+			}
+			manager.emit_status(&workspace);
+		});
+		Ok(status)
+	}
+
+	/// Stops indexing and watching `root`. The persisted index is kept for
+	/// the next time the workspace is opened.
+	pub fn close_workspace(&self, root: &str) -> Result<()> {
+		self.workspaces.close(root)
+	}
+
+	pub fn status(&self, root: &str) -> Option<IndexStatus> {
+		self.workspaces
+			.get(root)
+			.map(|workspace| workspace.status())
+	}
+
+	/// Finds symbols whose name matches `query`, best matches first. Searches
+	/// every open workspace unless `root` is given.
+	pub fn search_symbols(
+		&self,
+		query: &str,
+		root: Option<&str>,
+		limit: Option<usize>,
+	) -> Vec<SymbolMatch> {
+		self.search_symbols_in(query, root.as_ref().map(std::slice::from_ref), limit)
+	}
+
+	/// Like `search_symbols`, limited to the open workspaces among `roots`,
+	/// e.g. the roots of a multi-root workspace.
+	pub fn search_symbols_in(
+		&self,
+		query: &str,
+		roots: Option<&[&str]>,
+		limit: Option<usize>,
+	) -> Vec<SymbolMatch> {
+		let query = query.trim().to_lowercase();
+		if query.is_empty() {
+			return Vec::new();
+		}
+		let roots: Option<Vec<PathBuf>> = roots.map(|roots| {
+			roots
+				.iter()
+				.filter_map(|root| fs::canonicalize(root).ok())
+				.collect()
+		});
+		let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+
+		let workspaces: Vec<_> = self
+			.workspaces
+			.all()
+			.into_iter()
+			.filter(|w| roots.as_ref().is_none_or(|roots| roots.contains(&w.root)))
+			.collect();
+
+		let mut matches = Vec::new();
+		for workspace in &workspaces {
+			let files = workspace.files.lock().unwrap();
+			for (relative, entry) in files.iter() {
+				for symbol in &entry.symbols {
+					if let Some(score) = match_score(&symbol.name, &query) {
+						let path = workspace.root.join(relative);
+						matches.push((score, symbol.clone(), path));
+					}
+				}
+			}
+		}
+
+		matches.sort_by(|(a_score, a, a_path), (b_score, b, b_path)| {
+			a_score
+				.cmp(b_score)
+				.then(a.name.len().cmp(&b.name.len()))
+				.then(a.name.cmp(&b.name))
+				.then(a_path.cmp(b_path))
+		});
+		matches
+			.into_iter()
+			.take(limit)
+			.map(|(_, symbol, path)| SymbolMatch {
+				symbol,
+				path: path.to_string_lossy().to_string(),
+			})
+			.collect()
+	}
+
+	fn apply_changes(&self, paths: HashSet<PathBuf>, rescan: bool) {
+		let workspaces = self.workspaces.all();
+		let mut context = TagsContext::new();
+
+		for workspace in workspaces {
+			// Let the initial build pick these up
+			if *workspace.state.lock().unwrap() != IndexState::Ready {
+				continue;
+			}
+
+			let mut changed = false;
+			if rescan {
+				changed = workspace.scan(&mut context, &workspace.root);
+			} else {
+				for path in paths.iter().filter(|p| covers(&workspace.root, p)) {
+					// A directory that appeared, moved or vanished changes
+					// every file below it
+					let is_dir = path.is_dir()
+						|| has_files_under(
+							&workspace.root,
+							&*workspace.files.lock().unwrap(),
+							path,
+						);
+					changed |= if is_dir {
+						workspace.scan(&mut context, path)
+					} else {
+						workspace.update_file(&mut context, path)
+					};
+				}
+			}
+
+			if changed {
+				if let Err(e) = self.persist(&workspace) {
+					log::warn!("Failed to persist symbol index: {}", e);
+				}
+				self.emit_status(&workspace);
+			}
+		}
+	}
+}
+
+/// Starts indexing a workspace in the background; progress is reported with
+/// `symbol-index-status` events.
+#[tauri::command]
+pub async fn index_workspace(
+	root: String,
+	manager: State<'_, Arc<IndexManager>>,
+) -> Result<IndexStatus, String> {
+	manager.index_workspace(&root).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn close_workspace_index(
+	root: String,
+	manager: State<'_, Arc<IndexManager>>,
+) -> Result<(), String> {
+	manager.close_workspace(&root).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_index_status(
+	root: String,
+	manager: State<'_, Arc<IndexManager>>,
+) -> Result<Option<IndexStatus>, String> {
+	Ok(manager.status(&root))
+}
+
+#[tauri::command]
+pub async fn search_symbols(
+	query: String,
+	root: Option<String>,
+	limit: Option<usize>,
+	manager: State<'_, Arc<IndexManager>>,
+) -> Result<Vec<SymbolMatch>, String> {
+	Ok(manager.search_symbols(&query, root.as_deref(), limit))
+}
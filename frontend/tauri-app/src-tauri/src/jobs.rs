@@ -0,0 +1,484 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+use crate::util::now_millis;
+
+/// Store holding the most recently finished jobs.
+const JOBS_STORE: &str = "jobs.json";
+/// Finished jobs kept for the progress UI, newest first.
+const MAX_RECENT_JOBS: usize = 50;
+/// How often processes run by `output` and `stream_lines` are checked for
+/// cancellation.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Error of a job stopped with `cancel_job`.
+pub const JOB_CANCELLED: &str = "JOB_CANCELLED";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+	/// Copying files or directories.
+	Copy,
+	/// A long git operation, such as a submodule update or LFS pull.
+	Git,
+	/// Searching the disk for repositories.
+	Search,
+	/// A project task or agent running in a terminal.
+	Task,
+	/// Installing a project's dependencies or preparing its environment.
+	Setup,
+	/// Running a project's tests to measure code coverage.
+	Coverage,
+	/// Checking a project's dependencies for known vulnerabilities.
+	Audit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+	Running,
+	Succeeded,
+	Failed,
+	Cancelled,
+}
+
+/// How far a job has come. Any field may be unknown.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+	/// What the job is doing, e.g. the last line git printed.
+	pub message: Option<String>,
+	pub completed: Option<u64>,
+	pub total: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+	pub id: String,
+	pub kind: JobKind,
+	pub title: String,
+	pub state: JobState,
+	pub progress: JobProgress,
+	/// Set when the job failed or was cancelled.
+	pub error: Option<String>,
+	/// What the job produced, depending on its kind.
+	pub result: Option<serde_json::Value>,
+	/// Milliseconds since the Unix epoch.
+	pub started_at: u64,
+	pub finished_at: Option<u64>,
+}
+
+/// Payload of the `job-progress` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgressEvent {
+	pub job_id: String,
+	pub kind: JobKind,
+	pub progress: JobProgress,
+}
+
+type CancelHook = Box<dyn FnOnce() + Send>;
+
+/// Set once a job is cancelled. Work loops check it between steps; work
+/// that can't check it, such as a terminal, registers a hook to stop it.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+	cancelled: Arc<AtomicBool>,
+	hooks: Arc<Mutex<Vec<CancelHook>>>,
+}
+
+impl CancelToken {
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::SeqCst)
+	}
+
+	/// Fails with `JOB_CANCELLED` once the job is cancelled.
+	pub fn check(&self) -> Result<()> {
+		if self.is_cancelled() {
+			return Err(anyhow!(JOB_CANCELLED));
+		}
+		Ok(())
+	}
+
+	/// Runs `hook` when the job is cancelled, right away if it already is.
+	pub fn on_cancel(&self, hook: impl FnOnce() + Send + 'static) {
+		let mut hooks = self.hooks.lock().unwrap();
+		if self.is_cancelled() {
+			drop(hooks);
+			hook();
+		} else {
+			hooks.push(Box::new(hook));
+		}
+	}
+
+	pub fn cancel(&self) {
+		if self.cancelled.swap(true, Ordering::SeqCst) {
+			return;
+		}
+		let hooks = std::mem::take(&mut *self.hooks.lock().unwrap());
+		for hook in hooks {
+			hook();
+		}
+	}
+}
+
+/// Runs `cmd` to completion like `Command::output`, killing it if `token`
+/// is cancelled first.
+pub fn output(cmd: &mut Command, token: &CancelToken) -> Result<Output> {
+	let mut child = cmd
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()?;
+
+	// Read both pipes as the command runs so it can't block on a full one
+	let read = |mut pipe: Box<dyn Read + Send>| {
+		thread::spawn(move || {
+			let mut buffer = Vec::new();
+			let _ = pipe.read_to_end(&mut buffer);
+			buffer
+		})
+	};
+	let stdout = child.stdout.take().map(|pipe| read(Box::new(pipe)));
+	let stderr = child.stderr.take().map(|pipe| read(Box::new(pipe)));
+
+	let status = loop {
+		if let Some(status) = child.try_wait()? {
+			break status;
+		}
+		if token.is_cancelled() {
+			let _ = child.kill();
+			let _ = child.wait();
+			return Err(anyhow!(JOB_CANCELLED));
+		}
+		thread::sleep(CANCEL_POLL_INTERVAL);
+	};
+	let join = |reader: Option<thread::JoinHandle<Vec<u8>>>| {
+		reader
+			.and_then(|reader| reader.join().ok())
+			.unwrap_or_default()
+	};
+	Ok(Output {
+		status,
+		stdout: join(stdout),
+		stderr: join(stderr),
+	})
+}
+
+/// Runs `cmd` to completion, passing each line it prints to `on_line` with
+/// `stdout` or `stderr` as they arrive, and kills it if `token` is
+/// cancelled first.
+pub fn stream_lines(
+	cmd: &mut Command,
+	token: &CancelToken,
+	mut on_line: impl FnMut(&'static str, String),
+) -> Result<ExitStatus> {
+	let mut child = cmd
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()?;
+
+	let (sender, receiver) = mpsc::channel::<(&'static str, String)>();
+	let read = |pipe: Box<dyn Read + Send>, stream: &'static str| {
+		let sender = sender.clone();
+		thread::spawn(move || {
+			for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+				let _ = sender.send((stream, line));
+			}
+		})
+	};
+	let readers = [
+		child
+			.stdout
+			.take()
+			.map(|pipe| read(Box::new(pipe), "stdout")),
+		child
+			.stderr
+			.take()
+			.map(|pipe| read(Box::new(pipe), "stderr")),
+	];
+	drop(sender);
+
+	let status = loop {
+		match receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+			Ok((stream, line)) => on_line(stream, line),
+			// Both pipes closed, so the command has exited or is about to
+			Err(mpsc::RecvTimeoutError::Disconnected) => break child.wait()?,
+			Err(mpsc::RecvTimeoutError::Timeout) => {}
+		}
+		if token.is_cancelled() {
+			let _ = child.kill();
+			let _ = child.wait();
+			return Err(anyhow!(JOB_CANCELLED));
+		}
+	};
+	for reader in readers.into_iter().flatten() {
+		let _ = reader.join();
+	}
+	Ok(status)
+}
+
+struct RunningJob {
+	job: Mutex<Job>,
+	token: CancelToken,
+}
+
+/// A running job, handed to the code doing its work.
+#[derive(Clone)]
+pub struct JobHandle {
+	manager: Arc<JobManager>,
+	running: Arc<RunningJob>,
+	id: String,
+}
+
+impl JobHandle {
+	pub fn id(&self) -> &str {
+		&self.id
+	}
+
+	pub fn token(&self) -> &CancelToken {
+		&self.running.token
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.running.token.is_cancelled()
+	}
+
+	/// Replaces the job's progress and emits a `job-progress` event.
+	pub fn progress(&self, progress: JobProgress) {
+		let kind = {
+			let mut job = self.running.job.lock().unwrap();
+			job.progress = progress.clone();
+			job.kind
+		};
+		let _ = self.manager.app_handle.emit(
+			"job-progress",
+			JobProgressEvent {
+				job_id: self.id.clone(),
+				kind,
+				progress,
+			},
+		);
+	}
+
+	/// Reports what the job is doing, keeping its counts.
+	pub fn message(&self, message: impl Into<String>) {
+		let mut progress = self.running.job.lock().unwrap().progress.clone();
+		progress.message = Some(message.into());
+		self.progress(progress);
+	}
+
+	/// Ends the job with `result`, emitting a `job-finished` event. A job
+	/// that fails after being cancelled is reported as cancelled.
+	pub fn finish(&self, result: Result<Option<serde_json::Value>, String>) {
+		let job = {
+			let mut job = self.running.job.lock().unwrap();
+			match result {
+				Ok(value) => {
+					job.state = JobState::Succeeded;
+					job.result = value;
+				}
+				Err(_) if self.is_cancelled() => {
+					job.state = JobState::Cancelled;
+					job.error = Some(JOB_CANCELLED.to_string());
+				}
+				Err(e) => {
+					job.state = JobState::Failed;
+					job.error = Some(e);
+				}
+			}
+			job.finished_at = Some(now_millis());
+			job.clone()
+		};
+		self.manager.finished(job);
+	}
+}
+
+/// Tracks long-running backend work, such as copies, git operations,
+/// searches and tasks, so one UI can show and cancel all of it. Jobs
+/// report through `job-started`, `job-progress` and `job-finished` events;
+/// recently finished ones are kept across restarts.
+pub struct JobManager {
+	app_handle: AppHandle,
+	running: Mutex<HashMap<String, Arc<RunningJob>>>,
+	recent: Mutex<VecDeque<Job>>,
+}
+
+impl JobManager {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		let recent = app_handle
+			.store(JOBS_STORE)
+			.ok()
+			.and_then(|store| store.get("recent"))
+			.and_then(|value| serde_json::from_value(value).ok())
+			.unwrap_or_default();
+		Arc::new(Self {
+			app_handle,
+			running: Mutex::new(HashMap::new()),
+			recent: Mutex::new(recent),
+		})
+	}
+
+	/// Registers a new running job. Whoever does the work must end it with
+	/// `JobHandle::finish`.
+	pub fn start(self: &Arc<Self>, kind: JobKind, title: impl Into<String>) -> JobHandle {
+		let id = Uuid::new_v4().to_string();
+		let job = Job {
+			id: id.clone(),
+			kind,
+			title: title.into(),
+			state: JobState::Running,
+			progress: JobProgress::default(),
+			error: None,
+			result: None,
+			started_at: now_millis(),
+			finished_at: None,
+		};
+		let _ = self.app_handle.emit("job-started", &job);
+		let running = Arc::new(RunningJob {
+			job: Mutex::new(job),
+			token: CancelToken::default(),
+		});
+		self.running
+			.lock()
+			.unwrap()
+			.insert(id.clone(), running.clone());
+		JobHandle {
+			manager: self.clone(),
+			running,
+			id,
+		}
+	}
+
+	/// Runs `work` as a job on a background thread, returning the job id.
+	pub fn spawn<T: Serialize>(
+		self: &Arc<Self>,
+		kind: JobKind,
+		title: impl Into<String>,
+		work: impl FnOnce(&JobHandle) -> Result<T> + Send + 'static,
+	) -> String {
+		let job = self.start(kind, title);
+		let id = job.id.clone();
+		thread::spawn(move || {
+			let result = work(&job);
+			job.finish(Self::job_result(&result));
+		});
+		id
+	}
+
+	/// Runs `work` as a job and waits for it, for commands that return the
+	/// outcome themselves while still showing in the progress UI.
+	pub async fn run<T: Serialize + Send + 'static>(
+		self: &Arc<Self>,
+		kind: JobKind,
+		title: impl Into<String>,
+		work: impl FnOnce(&JobHandle) -> Result<T> + Send + 'static,
+	) -> Result<T, String> {
+		let job = self.start(kind, title);
+		tauri::async_runtime::spawn_blocking(move || {
+			let result = work(&job);
+			job.finish(Self::job_result(&result));
+			result.map_err(|e| e.to_string())
+		})
+		.await
+		.map_err(|e| e.to_string())?
+	}
+
+	fn job_result<T: Serialize>(
+		result: &Result<T>,
+	) -> Result<Option<serde_json::Value>, String> {
+		match result {
+			Ok(value) => Ok(serde_json::to_value(value)
+				.ok()
+				.filter(|value| !value.is_null())),
+			Err(e) => Err(e.to_string()),
+		}
+	}
+
+	fn finished(&self, job: Job) {
+		self.running.lock().unwrap().remove(&job.id);
+		let _ = self.app_handle.emit("job-finished", &job);
+		let mut recent = self.recent.lock().unwrap();
+		recent.push_front(job);
+		recent.truncate(MAX_RECENT_JOBS);
+		if let Err(e) = self.persist(&recent) {
+			log::warn!("Failed to persist recent jobs: {}", e);
+		}
+	}
+
+	fn persist(&self, recent: &VecDeque<Job>) -> Result<()> {
+		let store = self.app_handle.store(JOBS_STORE)?;
+		store.set("recent", serde_json::to_value(recent)?);
+		store.save()?;
+		Ok(())
+	}
+
+	/// Running jobs, oldest first, followed by finished ones, newest first.
+	pub fn list(&self) -> Vec<Job> {
+		let mut jobs: Vec<Job> = self
+			.running
+			.lock()
+			.unwrap()
+			.values()
+			.map(|running| running.job.lock().unwrap().clone())
+			.collect();
+		jobs.sort_by_key(|job| job.started_at);
+		jobs.extend(self.recent.lock().unwrap().iter().cloned());
+		jobs
+	}
+
+	pub fn cancel(&self, id: &str) -> Result<()> {
+		let running = self
+			.running
+			.lock()
+			.unwrap()
+			.get(id)
+			.cloned()
+			.ok_or_else(|| anyhow!("No running job {}", id))?;
+		running.token.cancel();
+		Ok(())
+	}
+
+	/// Forgets finished jobs.
+	pub fn clear_finished(&self) -> Result<()> {
+		let mut recent = self.recent.lock().unwrap();
+		recent.clear();
+		self.persist(&recent)
+	}
+}
+
+#[tauri::command]
+pub async fn list_jobs(manager: State<'_, Arc<JobManager>>) -> Result<Vec<Job>, String> {
+	Ok(manager.list())
+}
+
+/// Asks a running job to stop. It ends with a `job-finished` event in the
+/// cancelled state once it has.
+#[tauri::command]
+pub async fn cancel_job(
+	job_id: String,
+	manager: State<'_, Arc<JobManager>>,
+) -> Result<(), String> {
+	manager.cancel(&job_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_finished_jobs(
+	manager: State<'_, Arc<JobManager>>,
+) -> Result<(), String> {
+	manager.clear_finished().map_err(|e| e.to_string())
+}
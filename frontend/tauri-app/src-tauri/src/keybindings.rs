@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::palette::PaletteManager;
+use crate::shortcuts::GlobalShortcuts;
+
+/// Keys other than letters, digits and function keys, by every name they
+/// are written with, and their canonical name.
+const NAMED_KEYS: &[(&[&str], &str)] = &[
+	(&["enter", "return"], "enter"),
+	(&["escape", "esc"], "escape"),
+	(&["tab"], "tab"),
+	(&["space"], "space"),
+	(&["backspace"], "backspace"),
+	(&["delete", "del"], "delete"),
+	(&["insert", "ins"], "insert"),
+	(&["up", "arrowup"], "up"),
+	(&["down", "arrowdown"], "down"),
+	(&["left", "arrowleft"], "left"),
+	(&["right", "arrowright"], "right"),
+	(&["home"], "home"),
+	(&["end"], "end"),
+	(&["pageup"], "pageup"),
+	(&["pagedown"], "pagedown"),
+	(&["plus"], "plus"),
+];
+const PUNCTUATION: &str = "`-=[]\\;',./";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Platform {
+	Macos,
+	Windows,
+	Linux,
+}
+
+impl Platform {
+	fn current() -> Self {
+		match std::env::consts::OS {
+			"macos" => Platform::Macos,
+			"windows" => Platform::Windows,
+			_ => Platform::Linux,
+		}
+	}
+}
+
+/// A keybinding as the user wrote it, e.g. in `keybindings.json`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeybindingDefinition {
+	/// One or more keystrokes separated by spaces, e.g. `ctrl+k ctrl+s`.
+	pub key: String,
+	pub action: String,
+	/// Context the binding applies in, e.g. `editorFocus`. Bindings without
+	/// one apply everywhere.
+	pub when: Option<String>,
+}
+
+/// A keystroke with platform modifiers resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Keystroke {
+	ctrl: bool,
+	alt: bool,
+	shift: bool,
+	meta: bool,
+	key: String,
+}
+
+impl Keystroke {
+	/// As the platform shows it, e.g. `⌘⇧P` or `Ctrl+Shift+P`.
+	fn label(&self, platform: Platform) -> String {
+		let key = if self.key.chars().count() == 1 {
+			self.key.to_uppercase()
+		} else {
+			let mut chars = self.key.chars();
+			chars
+				.next()
+				.map(|first| first.to_uppercase().chain(chars).collect())
+				.unwrap_or_default()
+		};
+		if platform == Platform::Macos {
+			let mut label = String::new();
+			for (held, symbol) in [
+				(self.ctrl, "⌃"),
+				(self.alt, "⌥"),
+				(self.shift, "⇧"),
+				(self.meta, "⌘"),
+			] {
+				if held {
+					label.push_str(symbol);
+				}
+			}
+			return label + &key;
+		}
+		let meta = if platform == Platform::Windows {
+			"Win"
+		} else {
+			"Super"
+		};
+		let mut parts: Vec<&str> = [
+			(self.ctrl, "Ctrl"),
+			(self.alt, "Alt"),
+			(self.shift, "Shift"),
+			(self.meta, meta),
+		]
+		.into_iter()
+		.filter(|(held, _)| *held)
+		.map(|(_, name)| name)
+		.collect();
+		parts.push(&key);
+		parts.join("+")
+	}
+}
+
+/// Canonical form, with modifiers in a fixed order: `ctrl+alt+shift+meta+k`.
+impl fmt::Display for Keystroke {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (held, name) in [
+			(self.ctrl, "ctrl"),
+			(self.alt, "alt"),
+			(self.shift, "shift"),
+			(self.meta, "meta"),
+		] {
+			if held {
+				write!(f, "{}+", name)?;
+			}
+		}
+		write!(f, "{}", self.key)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeybindingErrorKind {
+	Empty,
+	UnknownModifier,
+	DuplicateModifier,
+	MissingKey,
+	UnknownKey,
+	UnknownAction,
+}
+
+/// Why a binding couldn't be used. `index` is its position in the list.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeybindingError {
+	pub index: usize,
+	pub kind: KeybindingErrorKind,
+	/// The part of `key` at fault, if any.
+	pub token: Option<String>,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedKeybinding {
+	pub index: usize,
+	pub action: String,
+	pub when: Option<String>,
+	/// Canonical keystrokes, e.g. `["ctrl+k", "ctrl+s"]`.
+	pub chord: Vec<String>,
+	/// For display, e.g. `⌘K ⌘S`.
+	pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictKind {
+	/// Both bindings have the same keys in overlapping contexts.
+	SameKeys,
+	/// The first binding's keys start the second's chord, so the chord can
+	/// never be typed.
+	ShadowsChord,
+	/// An OS-wide shortcut catches the keys before the app sees them.
+	GlobalShortcut,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeybindingConflict {
+	pub kind: ConflictKind,
+	/// Indexes of the bindings involved, the winning one first.
+	pub indexes: Vec<usize>,
+	/// The global shortcut's action, for `globalShortcut` conflicts.
+	pub global_action: Option<String>,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeybindingReport {
+	pub platform: Platform,
+	pub bindings: Vec<ResolvedKeybinding>,
+	pub errors: Vec<KeybindingError>,
+	pub conflicts: Vec<KeybindingConflict>,
+}
+
+fn error(
+	index: usize,
+	kind: KeybindingErrorKind,
+	token: Option<&str>,
+	message: String,
+) -> KeybindingError {
+	KeybindingError {
+		index,
+		kind,
+		token: token.map(str::to_string),
+		message,
+	}
+}
+
+fn canonical_key(key: &str) -> Option<String> {
+	let lower = key.to_lowercase();
+	if let Some((_, name)) = NAMED_KEYS
+		.iter()
+		.find(|(names, _)| names.contains(&lower.as_str()))
+	{
+		return Some(name.to_string());
+	}
+	let mut chars = lower.chars();
+	match (chars.next(), chars.next()) {
+		(Some(c), None) if c.is_ascii_alphanumeric() || PUNCTUATION.contains(c) => {
+			return Some(lower)
+		}
+		_ => {}
+	}
+	// Function keys
+	lower
+		.strip_prefix('f')
+		.and_then(|n| n.parse::<u8>().ok())
+		.filter(|n| (1..=24).contains(n))
+		.map(|_| lower.clone())
+}
+
+/// Parses one keystroke such as `CmdOrCtrl+Shift+P`, resolving the
+/// cross-platform modifiers for `platform`.
+fn parse_keystroke(
+	stroke: &str,
+	platform: Platform,
+	index: usize,
+) -> Result<Keystroke, KeybindingError> {
+	// `ctrl++` binds the plus key
+	let (modifiers, key) = match stroke.strip_suffix("++") {
+		Some(modifiers) => (modifiers, "+"),
+		None => stroke.rsplit_once('+').unwrap_or(("", stroke)),
+	};
+	let mut keystroke = Keystroke {
+		ctrl: false,
+		alt: false,
+		shift: false,
+		meta: false,
+		key: String::new(),
+	};
+	for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+		let held = match modifier.to_lowercase().as_str() {
+			"ctrl" | "control" => &mut keystroke.ctrl,
+			"alt" | "option" | "opt" => &mut keystroke.alt,
+			"shift" => &mut keystroke.shift,
+			"meta" | "cmd" | "command" | "super" | "win" => &mut keystroke.meta,
+			"cmdorctrl" | "commandorcontrol" | "mod" => {
+				if platform == Platform::Macos {
+					&mut keystroke.meta
+				} else {
+					&mut keystroke.ctrl
+				}
+			}
+			_ => {
+				return Err(error(
+					index,
+					KeybindingErrorKind::UnknownModifier,
+					Some(modifier),
+					format!("Unknown modifier {}", modifier),
+				))
+			}
+		};
+		if *held {
+			return Err(error(
+				index,
+				KeybindingErrorKind::DuplicateModifier,
+				Some(modifier),
+				format!("{} is used twice in {}", modifier, stroke),
+			));
+		}
+		*held = true;
+	}
+
+	if key.is_empty() {
+		return Err(error(
+			index,
+			KeybindingErrorKind::MissingKey,
+			Some(stroke),
+			format!("{} has modifiers but no key", stroke),
+		));
+	}
+	keystroke.key = if key == "+" {
+		"plus".to_string()
+	} else {
+		canonical_key(key).ok_or_else(|| {
+			error(
+				index,
+				KeybindingErrorKind::UnknownKey,
+				Some(key),
+				format!("Unknown key {}", key),
+			)
+		})?
+	};
+	Ok(keystroke)
+}
+
+fn parse_chord(
+	key: &str,
+	platform: Platform,
+	index: usize,
+) -> Result<Vec<Keystroke>, KeybindingError> {
+	let strokes: Vec<&str> = key.split_whitespace().collect();
+	if strokes.is_empty() {
+		return Err(error(
+			index,
+			KeybindingErrorKind::Empty,
+			None,
+			"The binding has no keys".to_string(),
+		));
+	}
+	strokes
+		.into_iter()
+		.map(|stroke| parse_keystroke(stroke, platform, index))
+		.collect()
+}
+
+/// Whether two `when` contexts can hold at the same time. Without a way to
+/// evaluate them, only an absent context or an identical one counts.
+fn contexts_overlap(a: &Option<String>, b: &Option<String>) -> bool {
+	match (a, b) {
+		(None, _) | (_, None) => true,
+		(Some(a), Some(b)) => a.trim() == b.trim(),
+	}
+}
+
+/// Parses `definitions` and checks them against each other, the registered
+/// actions and the global shortcuts. Later bindings win over earlier ones
+/// with the same keys, as in `keybindings.json`.
+pub fn validate(
+	definitions: &[KeybindingDefinition],
+	platform: Platform,
+	known_actions: &[String],
+	global_shortcuts: &[(String, String)],
+) -> KeybindingReport {
+	let mut bindings = Vec::new();
+	let mut errors = Vec::new();
+	let mut parsed: Vec<(usize, Vec<Keystroke>)> = Vec::new();
+	for (index, definition) in definitions.iter().enumerate() {
+		// Nothing registered yet means nothing to check against
+		if !known_actions.is_empty() && !known_actions.contains(&definition.action) {
+			errors.push(error(
+				index,
+				KeybindingErrorKind::UnknownAction,
+				Some(&definition.action),
+				format!("Unknown action {}", definition.action),
+			));
+			continue;
+		}
+		match parse_chord(&definition.key, platform, index) {
+			Ok(chord) => {
+				bindings.push(ResolvedKeybinding {
+					index,
+					action: definition.action.clone(),
+					when: definition.when.clone(),
+					chord: chord.iter().map(|k| k.to_string()).collect(),
+					label: chord
+						.iter()
+						.map(|k| k.label(platform))
+						.collect::<Vec<_>>()
+						.join(" "),
+				});
+				parsed.push((index, chord));
+			}
+			Err(e) => errors.push(e),
+		}
+	}
+
+	let mut conflicts = Vec::new();
+	for (i, (first, first_chord)) in parsed.iter().enumerate() {
+		for (second, second_chord) in &parsed[i + 1..] {
+			let (a, b) = (&definitions[*first], &definitions[*second]);
+			if a.action == b.action || !contexts_overlap(&a.when, &b.when) {
+				continue;
+			}
+			if first_chord == second_chord {
+				conflicts.push(KeybindingConflict {
+					kind: ConflictKind::SameKeys,
+					indexes: vec![*second, *first],
+					global_action: None,
+					message: format!(
+						"{} is bound to both {} and {}; {} wins",
+						a.key, a.action, b.action, b.action
+					),
+				});
+				continue;
+			}
+			let (short, long) = if first_chord.len() < second_chord.len() {
+				((first, first_chord), (second, second_chord))
+			} else {
+				((second, second_chord), (first, first_chord))
+			};
+			if long.1.starts_with(short.1) {
+				let (short_definition, long_definition) =
+					(&definitions[*short.0], &definitions[*long.0]);
+				conflicts.push(KeybindingConflict {
+					kind: ConflictKind::ShadowsChord,
+					indexes: vec![*short.0, *long.0],
+					global_action: None,
+					message: format!(
+						"{} ({}) fires before {} ({}) can be completed",
+						short_definition.key,
+						short_definition.action,
+						long_definition.key,
+						long_definition.action
+					),
+				});
+			}
+		}
+	}
+
+	let global: HashMap<Keystroke, &str> = global_shortcuts
+		.iter()
+		.filter_map(|(action, accelerator)| {
+			let keystroke = parse_keystroke(accelerator, platform, 0).ok()?;
+			Some((keystroke, action.as_str()))
+		})
+		.collect();
+	for (index, chord) in &parsed {
+		if let Some(action) = chord.first().and_then(|k| global.get(k)) {
+			conflicts.push(KeybindingConflict {
+				kind: ConflictKind::GlobalShortcut,
+				indexes: vec![*index],
+				global_action: Some(action.to_string()),
+				message: format!(
+					"{} is taken by the global shortcut for {}",
+					chord[0], action
+				),
+			});
+		}
+	}
+
+	KeybindingReport {
+		platform,
+		bindings,
+		errors,
+		conflicts,
+	}
+}
+
+/// Parses and checks the user's keybindings so the settings UI can show
+/// what's wrong with each. `platform` defaults to the one running.
+#[tauri::command]
+pub async fn validate_keybindings(
+	bindings: Vec<KeybindingDefinition>,
+	platform: Option<Platform>,
+	palette: State<'_, Arc<PaletteManager>>,
+	shortcuts: State<'_, GlobalShortcuts>,
+) -> Result<KeybindingReport, String> {
+	let global: Vec<(String, String)> = shortcuts
+		.list()
+		.into_iter()
+		.map(|status| (status.binding.action, status.binding.accelerator))
+		.collect();
+	Ok(validate(
+		&bindings,
+		platform.unwrap_or_else(Platform::current),
+		&palette.action_ids(),
+		&global,
+	))
+}
@@ -4,10 +4,12 @@
 	windows_subsystem = "windows"
 )]
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::process::Command;
 use std::path::Path;
-use tauri::State;
+use serde::Deserialize;
+use tauri::{Manager, State};
 
 mod terminal;
 use terminal::TerminalManager;
@@ -16,31 +18,358 @@ mod custom_terminal;
 mod custom_terminal_commands;
 
 mod os;
+mod outline;
+
+mod ai_edit;
+mod audit;
+mod bootstrap;
+mod canvas_manager;
+mod clipboard;
+mod code_stats;
+mod collab;
+mod command_policy;
+mod commit_message;
+mod context_builder;
+mod coverage;
+mod document_commands;
+mod document_manager;
+mod editorconfig;
+mod file_reader;
+mod file_watcher;
+mod forge;
+mod formatter;
+mod git;
+mod git_graph;
+mod git_hooks;
+mod gitignore;
+mod hashing;
+mod highlighting;
+mod index_manager;
+mod jobs;
+mod keybindings;
+mod licenses;
+mod local_api;
+mod markdown;
+mod merge;
+mod merge_queue;
+mod palette;
+mod plugins;
+mod semantic_index;
+mod terminal_errors;
+mod terminal_theme;
+mod text_encoding;
+
+mod ai_history;
+mod backend_client;
+mod conversations;
+mod diagnostics;
+mod diff_summary;
+mod project_hooks;
+mod project_sync;
+mod settings_sync;
+
+mod crash_reporter;
+mod deep_link;
+mod dependencies;
+mod env_files;
+mod environment;
+mod external_apps;
+mod logging;
+mod notifications;
+mod ports;
+mod previews;
+mod session;
+mod session_supervisor;
+mod settings;
+mod shortcuts;
+mod snapshots;
+mod ssh;
+mod ssh_remote;
+mod ssh_transfer;
+mod ssh_tunnels;
+mod symlinks;
+mod task_runner;
+mod telemetry;
+mod toolchains;
+mod trust;
+mod updates;
+mod util;
+mod watched_workspaces;
+mod workspace;
+mod wsl;
 
 use custom_terminal_commands::{
 	custom_connect_terminal, custom_kill_terminal, custom_resize_terminal,
 	custom_send_ctrl_c, custom_send_ctrl_d, custom_send_input_lines,
 	custom_send_raw_input, custom_send_scroll_down, custom_send_scroll_up,
 };
+use document_commands::{
+	close_document, detect_file_encoding, list_documents, open_document,
+	reopen_document_with_encoding, revert_document, save_document, set_document_format,
+	update_document,
+};
+use editorconfig::resolve_editorconfig;
+use environment::inspect_os_session;
+use file_reader::{build_line_index, close_line_index, read_file_chunk, read_file_lines};
+use forge::{
+	forge_create_pull_request, forge_list_assigned_issues, forge_list_pull_requests,
+	forge_pull_request_comments, list_forge_accounts, remove_forge_token, set_forge_token,
+};
+use formatter::format_file;
+use git::{
+	git_binary_diff, git_checkout_branch, git_diff_stats, git_lfs_pull, git_lfs_status, git_repo_state, git_set_upstream,
+	git_submodule_status, git_submodule_update,
+};
+use git_graph::git_commit_graph;
+use git_hooks::git_list_hooks;
+use gitignore::{
+	append_to_gitignore, get_gitignore_template, git_check_ignore, list_gitignore_templates,
+};
+use hashing::hash_paths;
+use highlighting::{highlight_file, list_highlight_themes};
+use outline::get_file_outline;
+use index_manager::{close_workspace_index, get_index_status, index_workspace, search_symbols};
+use jobs::{cancel_job, clear_finished_jobs, list_jobs, CancelToken, JobKind, JobManager};
+use markdown::{markdown_highlight_css, render_markdown};
+use merge::merge_file_contents;
+use merge_queue::{
+	abort_merge_queue, continue_merge_queue, get_merge_queue, skip_merge_queue_item,
+	start_merge_queue, MergeQueueManager,
+};
+use ai_edit::apply_ai_edit;
+use audit::{audit_dependencies, get_audit_report, AuditStore};
+use bootstrap::{detect_bootstrap, run_bootstrap};
+use commit_message::{confirm_commit_message, CommitMessageProposals};
+use context_builder::build_chat_context;
+use coverage::{
+	get_canvas_coverage, get_coverage_summary, get_file_coverage, import_coverage, run_coverage,
+	CoverageStore,
+};
+use diagnostics::{
+	clear_problems, collect_terminal_problems, get_problems, parse_build_output, ProblemsStore,
+};
+use ai_history::{
+	clear_ai_history, delete_ai_exchange, export_ai_history, get_ai_exchange,
+	get_ai_history_enabled, record_ai_exchange, search_ai_history, set_ai_history_enabled,
+	AiHistory,
+};
+use conversations::{
+	compact_conversation, create_conversation, delete_conversation, get_conversation_state,
+	list_conversations, send_conversation_message, ConversationManager,
+};
+use diff_summary::{summarize_canvas_diff, SummaryModel};
+use project_hooks::{list_project_hooks, run_project_hooks, HookEvent};
+use project_sync::{
+	acquire_branch_lock, clear_presence, list_branch_locks, list_presence, release_branch_lock,
+	sync_projects, update_presence,
+};
+use semantic_index::{
+	close_semantic_index, get_semantic_index_status, index_semantic_workspace, semantic_search,
+	SemanticIndexManager,
+};
+use keybindings::validate_keybindings;
+use licenses::scan_licenses;
+use palette::{
+	close_palette_workspace, palette_query, record_palette_use, register_palette_actions,
+	unregister_palette_actions, PaletteManager,
+};
+use plugins::{
+	get_plugins_directory, list_plugins, query_plugin_data_source, reload_plugins,
+	run_plugin_command, set_plugin_enabled, PluginHost,
+};
+use settings::{get_settings, reset_settings, update_settings, SettingsManager};
+use settings_sync::sync_settings;
+use telemetry::{
+	clear_telemetry, get_telemetry_opt_in, record_telemetry_event, set_telemetry_opt_in,
+	show_my_telemetry, upload_telemetry, Telemetry,
+};
+use terminal_errors::explain_terminal_output;
+use terminal_theme::{
+	delete_terminal_scheme, export_terminal_scheme, get_terminal_palette, import_terminal_scheme,
+	list_terminal_schemes,
+};
+use canvas_manager::{
+	check_canvases, delete_canvas, get_canvas, list_canvases, repair_canvas, save_canvas,
+	CanvasManager,
+};
+use code_stats::{get_workspace_stats, CodeStatsCache};
+use local_api::{get_local_api_status, regenerate_local_api_token, LocalApi};
+use collab::{
+	create_collab_room, get_collab_status, join_collab_room, leave_collab_room,
+	share_collab_document, share_collab_terminal, unshare_collab_terminal,
+	update_collab_cursor, CollabManager,
+};
+use clipboard::{
+	copy_files_to_clipboard, copy_html_to_clipboard, get_clipboard_files, paste_clipboard_image,
+	ClipboardManager,
+};
+use command_policy::{
+	get_allowed_commands, get_command_audit_log, get_command_limits, set_command_allowed,
+	set_command_limits, CommandOptions, CommandRequest,
+};
+use crash_reporter::{
+	delete_crash_report, get_crash_report_consent, list_crash_reports,
+	set_crash_report_consent, upload_crash_reports,
+};
+use deep_link::take_pending_deep_links;
+use dependencies::{
+	add_dependency, list_dependencies, remove_dependency, upgrade_dependency, RegistryCache,
+};
+use env_files::{
+	diff_env_file, edit_env_file, get_env_injections, list_env_files, read_env_file_variables,
+	set_env_injections,
+};
+use external_apps::{
+	get_external_editor, open_in_external_editor, open_terminal_at, open_with_default_app,
+	set_external_editor,
+};
+use logging::{export_logs, get_recent_logs};
+use notifications::{
+	get_notification_preferences, send_notification, set_notification_preference,
+};
+use ports::{forward_wsl_port, list_ports, stop_port_forward, unwatch_ports, watch_ports};
+use previews::generate_thumbnail;
+use session::{restore_last_session, save_session, update_session, SessionManager};
+use session_supervisor::{
+	list_session_states, unwatch_session, watch_session, RemoteSession, SessionSupervisor,
+};
+use shortcuts::{
+	check_global_shortcut, list_global_shortcuts, register_global_shortcut,
+	unregister_global_shortcut, GlobalShortcuts,
+};
+use snapshots::{
+	create_snapshot, get_snapshot_settings, list_snapshots, restore_snapshot, set_snapshot_settings,
+	unwatch_snapshots, watch_snapshots, SnapshotScheduler,
+};
+use ssh::{
+	generate_ssh_key, install_ssh_public_key, list_ssh_hosts, list_ssh_keys, test_ssh_connection,
+};
+use ssh_remote::{
+	ssh_copy_files, ssh_delete_path, ssh_disconnect, ssh_execute_command, ssh_read_directory,
+	SshConnections,
+};
+use ssh_transfer::{ssh_download, ssh_upload};
+use ssh_tunnels::{close_ssh_tunnel, list_ssh_tunnels, open_ssh_tunnel, SshTunnels};
+use symlinks::{create_symlink, SymlinkMode};
+use task_runner::{list_running_tasks, list_tasks, run_task, stop_task};
+use toolchains::detect_toolchain_versions;
+use trust::{
+	get_workspace_trust, list_trust_decisions, remove_workspace_trust, set_workspace_trust,
+};
+use updates::{
+	check_for_updates, get_update_channel, install_update, set_update_channel, UpdateState,
+};
+use workspace::{
+	delete_workspace, get_workspace, get_workspace_file_trees, get_workspace_folders,
+	get_workspace_repositories, list_workspaces, open_workspace, save_workspace,
+	search_workspace_symbols,
+};
+#[cfg(target_os = "windows")]
+use wsl::WslCommand;
+use wsl::translate_wsl_path;
 
 use crate::{
 	custom_terminal::CustomTerminalManager,
+	document_manager::DocumentManager,
+	file_reader::FileReaderManager,
+	file_watcher::FileWatcher,
+	index_manager::IndexManager,
 	os::{FileNode, GitSearchManager, GitSearchResult, OsSession, OsSessionKind},
+	ports::PortManager,
+	task_runner::TaskRunner,
 };
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+	if crash_reporter::run_monitor_if_requested() || ssh::run_askpass_if_requested() {
+		return;
+	}
+
 	let terminals_manager = Arc::new(TerminalManager::new());
 	let custom_terminals_manager = Arc::new(CustomTerminalManager::new());
 	let git_search_manager = Arc::new(GitSearchManager::new());
+	let file_reader_manager = Arc::new(FileReaderManager::new());
+	let task_runner = Arc::new(TaskRunner::new(terminals_manager.clone()));
+	let port_manager = Arc::new(PortManager::new(terminals_manager.clone()));
+	let clipboard_manager = Arc::new(ClipboardManager::default());
+	let merge_queue_manager = Arc::new(MergeQueueManager::default());
+	let commit_message_proposals = Arc::new(CommitMessageProposals::default());
+	let ssh_connections = Arc::new(SshConnections::default());
 
 	tauri::Builder::default()
+		// Must come first: a second launch hands its deep link or paths to
+		// this instance and exits
+		.plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+			let args = args.get(1..).unwrap_or_default();
+			deep_link::handle_args(app, args, std::path::Path::new(&cwd));
+			deep_link::focus_main_window(app);
+		}))
+		.plugin(tauri_plugin_deep_link::init())
+		.plugin(tauri_plugin_updater::Builder::new().build())
+		.plugin(tauri_plugin_global_shortcut::Builder::new().build())
 		.plugin(tauri_plugin_os::init())
 		.plugin(tauri_plugin_store::Builder::new().build())
 		.plugin(tauri_plugin_fs::init())
+		.plugin(tauri_plugin_notification::init())
+		.plugin(tauri_plugin_dialog::init())
 		.manage(terminals_manager)
 		.manage(custom_terminals_manager)
 		.manage(git_search_manager)
+		.manage(file_reader_manager)
+		.manage(task_runner)
+		.manage(port_manager)
+		.manage(clipboard_manager)
+		.manage(merge_queue_manager)
+		.manage(commit_message_proposals)
+		.manage(ssh_connections)
+		.manage(UpdateState::default())
+		.setup(|app| {
+			if let Err(e) = logging::init(app.handle()) {
+				eprintln!("Failed to start logging: {}", e);
+			}
+			if let Err(e) = crash_reporter::init(app.handle()) {
+				log::error!("Failed to set up crash reporting: {}", e);
+			}
+			let file_watcher = FileWatcher::new()?;
+			let document_manager =
+				DocumentManager::new(app.handle().clone(), file_watcher.clone());
+			app.manage(SessionManager::new(
+				app.handle().clone(),
+				document_manager.clone(),
+			));
+			app.manage(CollabManager::new(
+				app.handle().clone(),
+				document_manager.clone(),
+			));
+			app.manage(document_manager);
+			app.manage(IndexManager::new(app.handle().clone(), file_watcher.clone()));
+			app.manage(SemanticIndexManager::new(
+				app.handle().clone(),
+				file_watcher.clone(),
+			));
+			app.manage(PaletteManager::new(app.handle().clone(), file_watcher.clone()));
+			app.manage(JobManager::new(app.handle().clone()));
+			app.manage(CanvasManager::new(app.handle().clone()));
+			app.manage(CoverageStore::new());
+			app.manage(ProblemsStore::new());
+			app.manage(RegistryCache::new());
+			app.manage(AuditStore::new());
+			app.manage(CodeStatsCache::new(app.handle().clone()));
+			app.manage(SnapshotScheduler::new(app.handle().clone()));
+			app.manage(PluginHost::new(app.handle().clone(), file_watcher.clone())?);
+			app.manage(file_watcher);
+			app.manage(GlobalShortcuts::load(app.handle()));
+			app.manage(SettingsManager::load(app.handle()));
+			app.manage(SshTunnels::new(app.handle().clone()));
+			app.manage(LocalApi::new(app.handle().clone()));
+			app.manage(Telemetry::new(app.handle().clone()));
+			app.manage(AiHistory::new(app.handle().clone()));
+			app.manage(ConversationManager::new(app.handle().clone()));
+			let ssh_connections = app.state::<Arc<SshConnections>>().inner().clone();
+			app.manage(SessionSupervisor::new(app.handle().clone(), ssh_connections));
+			deep_link::setup(app.handle())?;
+			Ok(())
+		})
 		.invoke_handler(tauri::generate_handler![
 			// Original terminal commands
 			create_terminal_connection,
@@ -58,35 +387,338 @@ pub fn run() {
 			custom_send_scroll_up,
 			custom_send_scroll_down,
 			custom_resize_terminal,
+			explain_terminal_output,
+			get_terminal_palette,
+			export_terminal_scheme,
+			import_terminal_scheme,
+			list_terminal_schemes,
+			delete_terminal_scheme,
 			// File tree commands
 			get_current_dir,
 			get_file_tree,
+			get_file_trees,
+			hash_paths,
+			generate_thumbnail,
+			// Markdown commands
+			render_markdown,
+			markdown_highlight_css,
+			// Highlighting commands
+			highlight_file,
+			list_highlight_themes,
+			get_file_outline,
 			// Git search commands
 			start_git_directories_search,
 			get_found_git_directories_so_far,
 			list_available_os_session_kinds,
+			directory_exists,
+			inspect_os_session,
 			// Canvas management commands
 			copy_directory,
 			create_git_branch,
-			execute_command,
 			execute_command_in_dir,
 			execute_command_with_os_session,
 			copy_files_with_os_session,
 			// System integration commands
 			open_path_in_explorer,
+			open_in_external_editor,
+			open_terminal_at,
+			open_with_default_app,
+			get_external_editor,
+			set_external_editor,
+			// Clipboard commands
+			copy_files_to_clipboard,
+			get_clipboard_files,
+			copy_html_to_clipboard,
+			paste_clipboard_image,
+			translate_wsl_path,
 			delete_path,
 			delete_path_with_os_session,
+			create_symlink,
 			// Git repository commands
 			check_git_repository,
 			git_commit,
+			confirm_commit_message,
+			git_list_hooks,
 			git_revert_to_commit,
 			git_check_merge_conflicts,
 			git_get_conflict_files,
 			git_merge_branch,
 			git_get_current_branch,
+			git_checkout_branch,
+			git_set_upstream,
+			git_repo_state,
+			git_diff_stats,
+			git_binary_diff,
+			git_submodule_status,
+			git_submodule_update,
+			git_lfs_status,
+			git_lfs_pull,
+			git_check_ignore,
+			append_to_gitignore,
+			list_gitignore_templates,
+			get_gitignore_template,
+			git_commit_graph,
+			merge_file_contents,
+			// Forge commands
+			list_forge_accounts,
+			set_forge_token,
+			remove_forge_token,
+			forge_list_pull_requests,
+			forge_create_pull_request,
+			forge_pull_request_comments,
+			forge_list_assigned_issues,
+			// Document commands
+			open_document,
+			update_document,
+			save_document,
+			revert_document,
+			close_document,
+			list_documents,
+			set_document_format,
+			reopen_document_with_encoding,
+			detect_file_encoding,
+			resolve_editorconfig,
+			// Formatting commands
+			format_file,
+			// Large file viewer commands
+			read_file_chunk,
+			build_line_index,
+			read_file_lines,
+			close_line_index,
+			// Symbol index commands
+			index_workspace,
+			close_workspace_index,
+			get_index_status,
+			search_symbols,
+			// Code statistics commands
+			get_workspace_stats,
+			// Semantic search commands
+			index_semantic_workspace,
+			close_semantic_index,
+			get_semantic_index_status,
+			semantic_search,
+			// Chat context commands
+			build_chat_context,
+			apply_ai_edit,
+			// Command palette commands
+			palette_query,
+			register_palette_actions,
+			unregister_palette_actions,
+			record_palette_use,
+			close_palette_workspace,
+			// Plugin commands
+			list_plugins,
+			reload_plugins,
+			set_plugin_enabled,
+			get_plugins_directory,
+			run_plugin_command,
+			query_plugin_data_source,
+			// Task runner commands
+			list_tasks,
+			run_task,
+			stop_task,
+			list_running_tasks,
+			// Bootstrap commands
+			detect_bootstrap,
+			run_bootstrap,
+			// Project hook commands
+			list_project_hooks,
+			run_project_hooks,
+			detect_toolchain_versions,
+			// Port commands
+			list_ports,
+			watch_ports,
+			unwatch_ports,
+			forward_wsl_port,
+			stop_port_forward,
+			// Notification commands
+			send_notification,
+			get_notification_preferences,
+			set_notification_preference,
+			// Deep link commands
+			take_pending_deep_links,
+			// Env file commands
+			list_env_files,
+			read_env_file_variables,
+			edit_env_file,
+			diff_env_file,
+			get_env_injections,
+			set_env_injections,
+			// SSH commands
+			list_ssh_hosts,
+			test_ssh_connection,
+			list_ssh_keys,
+			generate_ssh_key,
+			install_ssh_public_key,
+			ssh_read_directory,
+			ssh_execute_command,
+			ssh_copy_files,
+			ssh_delete_path,
+			ssh_disconnect,
+			ssh_upload,
+			ssh_download,
+			open_ssh_tunnel,
+			close_ssh_tunnel,
+			list_ssh_tunnels,
+			// Session commands
+			watch_session,
+			unwatch_session,
+			list_session_states,
+			update_session,
+			save_session,
+			restore_last_session,
+			// Workspace trust commands
+			get_workspace_trust,
+			set_workspace_trust,
+			remove_workspace_trust,
+			list_trust_decisions,
+			// Canvas commands
+			list_canvases,
+			get_canvas,
+			save_canvas,
+			delete_canvas,
+			check_canvases,
+			repair_canvas,
+			// Merge queue commands
+			start_merge_queue,
+			get_merge_queue,
+			continue_merge_queue,
+			skip_merge_queue_item,
+			abort_merge_queue,
+			// Diff summary commands
+			summarize_canvas_diff,
+			// Coverage commands
+			run_coverage,
+			import_coverage,
+			get_file_coverage,
+			get_coverage_summary,
+			get_canvas_coverage,
+			// Problems commands
+			parse_build_output,
+			collect_terminal_problems,
+			get_problems,
+			clear_problems,
+			// Dependency commands
+			list_dependencies,
+			add_dependency,
+			remove_dependency,
+			upgrade_dependency,
+			// Audit commands
+			audit_dependencies,
+			get_audit_report,
+			// License commands
+			scan_licenses,
+			// Collaboration commands
+			create_collab_room,
+			join_collab_room,
+			leave_collab_room,
+			get_collab_status,
+			share_collab_document,
+			update_collab_cursor,
+			share_collab_terminal,
+			unshare_collab_terminal,
+			// Local API commands
+			get_local_api_status,
+			regenerate_local_api_token,
+			// Settings commands
+			get_settings,
+			update_settings,
+			reset_settings,
+			// Keybinding commands
+			validate_keybindings,
+			// Snapshot commands
+			get_snapshot_settings,
+			set_snapshot_settings,
+			watch_snapshots,
+			unwatch_snapshots,
+			create_snapshot,
+			list_snapshots,
+			restore_snapshot,
+			// Job commands
+			list_jobs,
+			cancel_job,
+			clear_finished_jobs,
+			// Command policy commands
+			get_allowed_commands,
+			set_command_allowed,
+			get_command_audit_log,
+			get_command_limits,
+			set_command_limits,
+			// Workspace commands
+			list_workspaces,
+			get_workspace,
+			save_workspace,
+			delete_workspace,
+			open_workspace,
+			get_workspace_file_trees,
+			get_workspace_repositories,
+			get_workspace_folders,
+			search_workspace_symbols,
+			// Update commands
+			get_update_channel,
+			set_update_channel,
+			check_for_updates,
+			install_update,
+			// Log commands
+			get_recent_logs,
+			export_logs,
+			// Crash report commands
+			list_crash_reports,
+			delete_crash_report,
+			get_crash_report_consent,
+			set_crash_report_consent,
+			upload_crash_reports,
+			// Telemetry commands
+			show_my_telemetry,
+			record_telemetry_event,
+			get_telemetry_opt_in,
+			set_telemetry_opt_in,
+			clear_telemetry,
+			upload_telemetry,
+			// AI history commands
+			get_ai_history_enabled,
+			set_ai_history_enabled,
+			record_ai_exchange,
+			search_ai_history,
+			get_ai_exchange,
+			delete_ai_exchange,
+			clear_ai_history,
+			export_ai_history,
+			// Conversation commands
+			create_conversation,
+			send_conversation_message,
+			compact_conversation,
+			get_conversation_state,
+			list_conversations,
+			delete_conversation,
+			// Global shortcut commands
+			list_global_shortcuts,
+			check_global_shortcut,
+			register_global_shortcut,
+			unregister_global_shortcut,
+			// Account sync commands
+			sync_settings,
+			sync_projects,
+			update_presence,
+			list_presence,
+			clear_presence,
+			list_branch_locks,
+			acquire_branch_lock,
+			release_branch_lock,
 		])
-		.run(tauri::generate_context!())
-		.expect("error while running tauri application");
+		.build(tauri::generate_context!())
+		.expect("error while running tauri application")
+		.run(|app_handle, event| {
+			if let tauri::RunEvent::Exit = event {
+				let session = app_handle.state::<Arc<SessionManager>>();
+				if let Err(e) = session.snapshot(true) {
+					log::error!("Failed to save session: {}", e);
+				}
+				app_handle.state::<Arc<SshTunnels>>().close_all();
+				app_handle.state::<Arc<LocalApi>>().stop();
+				app_handle.state::<Arc<SshConnections>>().disconnect_all();
+			}
+		});
 }
 
 #[tauri::command]
@@ -151,9 +783,23 @@ async fn get_current_dir(os_session: OsSession) -> Result<String, String> {
 async fn get_file_tree(
 	os_session: OsSession,
 	path: String,
+	supervisor: State<'_, Arc<SessionSupervisor>>,
 ) -> Result<Vec<FileNode>, String> {
-	os_session
-		.read_directory(&path)
+	supervisor
+		.retry_read(RemoteSession::of(&os_session), || os_session.read_directory(&path))
+		.await
+		.map_err(|e| e.to_string())
+}
+
+/// Lists several directories of the file tree in one call.
+#[tauri::command]
+async fn get_file_trees(
+	os_session: OsSession,
+	paths: Vec<String>,
+	supervisor: State<'_, Arc<SessionSupervisor>>,
+) -> Result<HashMap<String, Vec<FileNode>>, String> {
+	supervisor
+		.retry_read(RemoteSession::of(&os_session), || os_session.read_directories(&paths))
 		.await
 		.map_err(|e| e.to_string())
 }
@@ -162,8 +808,10 @@ async fn get_file_tree(
 async fn start_git_directories_search(
 	os_session_kind: OsSessionKind,
 	git_search_manager: State<'_, Arc<GitSearchManager>>,
+	job_manager: State<'_, Arc<JobManager>>,
 ) -> Result<String, String> {
-	let search_id = git_search_manager.start_search(os_session_kind);
+	let job = job_manager.start(JobKind::Search, "Search for git repositories");
+	let search_id = git_search_manager.start_search(os_session_kind, job);
 	Ok(search_id)
 }
 
@@ -196,32 +844,24 @@ async fn check_git_repository(directory: String) -> Result<bool, String> {
 }
 
 #[tauri::command]
-async fn execute_command(command: String, args: Vec<String>) -> Result<String, String> {
-	let output = Command::new(&command)
-		.args(&args)
-		.output()
-		.map_err(|e| format!("Failed to execute command: {}", e))?;
-	
-	if output.status.success() {
-		Ok(String::from_utf8_lossy(&output.stdout).to_string())
-	} else {
-		Err(String::from_utf8_lossy(&output.stderr).to_string())
-	}
-}
-
-#[tauri::command]
-async fn execute_command_in_dir(command: String, args: Vec<String>, directory: String) -> Result<String, String> {
-	let output = Command::new(&command)
-		.args(&args)
-		.current_dir(&directory)
-		.output()
-		.map_err(|e| format!("Failed to execute command: {}", e))?;
-	
-	if output.status.success() {
-		Ok(String::from_utf8_lossy(&output.stdout).to_string())
-	} else {
-		Err(String::from_utf8_lossy(&output.stderr).to_string())
-	}
+async fn execute_command_in_dir(
+	command: String,
+	args: Vec<String>,
+	directory: String,
+	options: Option<CommandOptions>,
+	app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+	trust::ensure_trusted(&app_handle, &OsSession::Local(directory.clone()), &directory)?;
+	let mut cmd = Command::new(&command);
+	cmd.args(&args).current_dir(&directory);
+	let request = CommandRequest {
+		program: command,
+		args,
+		directory: Some(directory),
+		distribution: None,
+		options: options.unwrap_or_default(),
+	};
+	command_policy::run(&app_handle, request, cmd).await
 }
 
 #[tauri::command]
@@ -229,61 +869,110 @@ async fn execute_command_with_os_session(
 	command: String, 
 	args: Vec<String>, 
 	directory: String, 
-	os_session: OsSession
+	os_session: OsSession,
+	options: Option<CommandOptions>,
+	app_handle: tauri::AppHandle,
 ) -> Result<String, String> {
 	match os_session {
 		OsSession::Local(_) => {
-			execute_command_in_dir(command, args, directory).await
+			execute_command_in_dir(command, args, directory, options, app_handle).await
 		}
-		OsSession::Wsl(wsl_session) => {
-			execute_command_wsl(command, args, directory, &wsl_session.distribution)
+		OsSession::Wsl(ref wsl_session) => {
+			trust::ensure_trusted(&app_handle, &os_session, &directory)?;
+			#[cfg(target_os = "windows")]
+			{
+				let cmd = WslCommand::new(&wsl_session.distribution, &command)
+					.current_dir(&directory)
+					.login()
+					.args(&args)
+					.build();
+				let request = CommandRequest {
+					program: command,
+					args,
+					directory: Some(directory),
+					distribution: Some(wsl_session.distribution.clone()),
+					options: options.unwrap_or_default(),
+				};
+				command_policy::run(&app_handle, request, cmd).await
+			}
+			#[cfg(not(target_os = "windows"))]
+			{
+				let _ = (command, args, options, wsl_session);
+				Err("WSL is only supported on Windows".to_string())
+			}
 		}
 	}
 }
 
-fn execute_command_wsl(command: String, args: Vec<String>, directory: String, distribution: &str) -> Result<String, String> {
-	#[cfg(target_os = "windows")]
-	{
-		let mut wsl_args = vec!["-d".to_string(), distribution.to_string(), "--cd".to_string(), directory];
-		wsl_args.push(command);
-		wsl_args.extend(args);
-		
-		let output = Command::new("wsl")
-			.args(&wsl_args)
-			.output()
-			.map_err(|e| format!("Failed to execute WSL command: {}", e))?;
-		
-		if output.status.success() {
-			Ok(String::from_utf8_lossy(&output.stdout).to_string())
-		} else {
-			Err(String::from_utf8_lossy(&output.stderr).to_string())
-		}
-	}
-	#[cfg(not(target_os = "windows"))]
-	{
-		Err("WSL is only supported on Windows".to_string())
-	}
+/// How `copy_files_with_os_session` treats LFS files and symlinks.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CopyOptions {
+	/// Download LFS files that were never downloaded before copying.
+	#[serde(default)]
+	pull_lfs: bool,
+	/// Copy LFS files that were never downloaded as their pointers.
+	#[serde(default)]
+	allow_missing_lfs: bool,
+	#[serde(default)]
+	symlinks: SymlinkMode,
 }
 
+/// Fails with `LFS_OBJECTS_MISSING` and the source's LFS status as JSON if
+/// the source has LFS files that were never downloaded, unless
+/// `allowMissingLfs` is set. `pullLfs` downloads them first, reporting
+/// progress as `job-progress` events. The copy runs as a job, which can be
+/// cancelled.
 #[tauri::command]
 async fn copy_files_with_os_session(
 	source: String, 
 	destination: String, 
 	os_session: OsSession,
-	exclude_git: bool
+	exclude_git: bool,
+	options: Option<CopyOptions>,
+	job_manager: State<'_, Arc<JobManager>>,
 ) -> Result<(), String> {
-	match os_session {
-		OsSession::Local(_) => {
-			copy_files_local(&source, &destination, exclude_git)
-		}
-		OsSession::Wsl(wsl_session) => {
-			copy_files_wsl(&source, &destination, &wsl_session.distribution, exclude_git)
-		}
-	}
+	let CopyOptions { pull_lfs, allow_missing_lfs, symlinks } = options.unwrap_or_default();
+	let title = format!("Copy {} to {}", source, destination);
+	job_manager.run(JobKind::Copy, title, move |job| {
+		let copy = || -> Result<(), String> {
+			// LFS files that were never downloaded would be copied as pointers
+			if let Ok(mut lfs) = git::lfs_status(&os_session, &source) {
+				if !lfs.missing.is_empty() && lfs.installed && pull_lfs {
+					git::run_job(&os_session, &source, &["lfs", "pull"], job)
+						.map_err(|e| e.to_string())?;
+					lfs = git::lfs_status(&os_session, &source).map_err(|e| e.to_string())?;
+				}
+				if !lfs.missing.is_empty() && !allow_missing_lfs {
+					return Err(format!(
+						"LFS_OBJECTS_MISSING: {}",
+						serde_json::to_string(&lfs).map_err(|e| e.to_string())?
+					));
+				}
+			}
+
+			job.message("Copying files");
+			match &os_session {
+				OsSession::Local(_) => {
+					copy_files_local(&source, &destination, exclude_git, symlinks, job.token())
+				}
+				OsSession::Wsl(wsl_session) => {
+					copy_files_wsl(&source, &destination, &wsl_session.distribution, exclude_git, symlinks, job.token())
+				}
+			}?;
+			// Submodules keep their git directory inside the copied .git, so their
+			// .git files must point into the copy
+			if !exclude_git {
+				git::relink_submodule_gitdirs(&os_session, &source, &destination)
+					.map_err(|e| format!("Failed to relink submodules: {}", e))?;
+			}
+			Ok(())
+		};
+		copy().map_err(anyhow::Error::msg)
+	}).await
 }
 
-fn copy_files_local(source: &str, destination: &str, exclude_git: bool) -> Result<(), String> {
-	use std::fs;
+fn copy_files_local(source: &str, destination: &str, exclude_git: bool, symlinks: SymlinkMode, cancel: &CancelToken) -> Result<(), String> {
 	use std::path::Path;
 	
 	let src_path = Path::new(source);
@@ -306,17 +995,22 @@ fn copy_files_local(source: &str, destination: &str, exclude_git: bool) -> Resul
 		if exclude_git {
 			args.push("/XD".to_string());
 			args.push(".git".to_string());
+			// Submodules have a .git file rather than a directory
+			args.push("/XF".to_string());
+			args.push(".git".to_string());
+		}
+		if symlinks == SymlinkMode::Preserve {
+			// Copy links as links rather than what they point to
+			args.push("/SL".to_string());
 		}
 		
 		// Create destination directory if it doesn't exist
 		if let Some(parent) = Path::new(&destination).parent() {
-			fs::create_dir_all(parent)
+			std::fs::create_dir_all(parent)
 				.map_err(|e| format!("Failed to create destination directory: {}", e))?;
 		}
 		
-		let output = Command::new("robocopy")
-			.args(&args)
-			.output()
+		let output = jobs::output(Command::new("robocopy").args(&args), cancel)
 			.map_err(|e| format!("Failed to execute robocopy: {}", e))?;
 		
 		// Robocopy exit codes: 0-7 are success, >7 are errors
@@ -330,18 +1024,17 @@ fn copy_files_local(source: &str, destination: &str, exclude_git: bool) -> Resul
 	
 	#[cfg(any(target_os = "linux", target_os = "macos"))]
 	{
-		let mut args = vec!["-r".to_string()];
-		
+		// cp can't exclude paths
 		if exclude_git {
-			args.push("--exclude=.git".to_string());
+			return copy_dir_excluding_git(src_path, Path::new(destination), symlinks, cancel);
 		}
 		
-		args.push(format!("{}/*", source));
+		// `/.` copies the contents, hidden files included
+		let mut args = vec!["-R".to_string(), symlinks.flag().to_string()];
+		args.push(format!("{}/.", source));
 		args.push(destination.to_string());
 		
-		let output = Command::new("cp")
-			.args(&args)
-			.output()
+		let output = jobs::output(Command::new("cp").args(&args), cancel)
 			.map_err(|e| format!("Failed to execute cp: {}", e))?;
 		
 		if !output.status.success() {
@@ -352,63 +1045,82 @@ fn copy_files_local(source: &str, destination: &str, exclude_git: bool) -> Resul
 	Ok(())
 }
 
+/// Copies the contents of `source` into `destination`, skipping `.git`
+/// directories and the `.git` files of submodules. Following links stops
+/// with an error at a link back into a folder being copied.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_dir_excluding_git(source: &std::path::Path, destination: &std::path::Path, symlinks: SymlinkMode, cancel: &CancelToken) -> Result<(), String> {
+	let entries = walkdir::WalkDir::new(source)
+		.min_depth(1)
+		.follow_links(symlinks == SymlinkMode::Follow)
+		.into_iter()
+		.filter_entry(|entry| entry.file_name() != ".git");
+	for entry in entries {
+		cancel.check().map_err(|e| e.to_string())?;
+		let entry = entry.map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+		let relative = entry.path().strip_prefix(source).map_err(|e| e.to_string())?;
+		let target = destination.join(relative);
+		let file_type = entry.file_type();
+		let result = if file_type.is_dir() {
+			std::fs::create_dir_all(&target)
+		} else if file_type.is_symlink() {
+			std::fs::read_link(entry.path()).and_then(|link| {
+				let _ = std::fs::remove_file(&target);
+				std::os::unix::fs::symlink(link, &target)
+			})
+		} else {
+			std::fs::copy(entry.path(), &target).map(|_| ())
+		};
+		result.map_err(|e| format!("Failed to copy {}: {}", entry.path().display(), e))?;
+	}
+	Ok(())
+}
+
 #[cfg(target_os = "windows")]
-fn copy_files_wsl(source: &str, destination: &str, distribution: &str, exclude_git: bool) -> Result<(), String> {
+fn copy_files_wsl(source: &str, destination: &str, distribution: &str, exclude_git: bool, symlinks: SymlinkMode, cancel: &CancelToken) -> Result<(), String> {
+	let source = &wsl::to_wsl_path(distribution, source);
+	let destination = &wsl::to_wsl_path(distribution, destination);
 	if exclude_git {
-		// Use rsync to exclude .git directories (more reliable than find/cpio)
-		let rsync_cmd = format!(
-			"rsync -av --exclude='.git' '{}/' '{}'",
-			source.replace("'", "'\"'\"'"),
-			destination.replace("'", "'\"'\"'")
-		);
-		
-		let output = Command::new("wsl")
-			.arg("-d")
-			.arg(distribution)
-			.arg("bash")
-			.arg("-c")
-			.arg(&rsync_cmd)
-			.output()
-			.map_err(|e| format!("Failed to execute WSL rsync: {}", e))?;
+		// Use rsync to exclude .git directories (more reliable than find/cpio).
+		// rsync would follow a link loop forever, so following is left to find
+		let copied = symlinks == SymlinkMode::Preserve
+			&& jobs::output(
+				&mut WslCommand::new(distribution, "rsync")
+					.args(["-a", "--exclude=.git", &format!("{}/", source), destination])
+					.build(),
+				cancel,
+			)
+			.map_err(|e| format!("Failed to execute WSL rsync: {}", e))?
+				.status
+				.success();
 		
-		if !output.status.success() {
-			// Fall back to cp with manual exclusion if rsync is not available
-			let cp_cmd = format!(
-				"mkdir -p '{}' && cd '{}' && find . -name '.git' -prune -o -type f -exec cp --parents {{}} '{}' \\;",
-				destination.replace("'", "'\"'\"'"),
-				source.replace("'", "'\"'\"'"),
-				destination.replace("'", "'\"'\"'")
-			);
-			
-			let output2 = Command::new("wsl")
-				.arg("-d")
-				.arg(distribution)
-				.arg("bash")
-				.arg("-c")
-				.arg(&cp_cmd)
-				.output()
-				.map_err(|e| format!("Failed to execute WSL cp fallback: {}", e))?;
+		if !copied {
+			// Fall back to cp with manual exclusion if rsync is not available;
+			// `find -L` reports link loops instead of entering them
+			let output2 = jobs::output(
+				&mut WslCommand::script(
+					distribution,
+					"mkdir -p \"$2\" && cd \"$1\" && find \"$3\" . -name .git -prune -o \\( -type f -o -type l \\) -exec cp \"$3\" --parents {} \"$2\" \\;",
+				)
+				.args([source, destination, symlinks.flag()])
+				.build(),
+				cancel,
+			)
+			.map_err(|e| format!("Failed to execute WSL cp fallback: {}", e))?;
 			
 			if !output2.status.success() {
 				return Err(format!("WSL copy failed: {}", String::from_utf8_lossy(&output2.stderr)));
 			}
 		}
 	} else {
-		// Simple recursive copy
-		let cp_cmd = format!(
-			"cp -r '{}/'* '{}'",
-			source.replace("'", "'\"'\"'"),
-			destination.replace("'", "'\"'\"'")
-		);
-		
-		let output = Command::new("wsl")
-			.arg("-d")
-			.arg(distribution)
-			.arg("bash")
-			.arg("-c")
-			.arg(&cp_cmd)
-			.output()
-			.map_err(|e| format!("Failed to execute WSL cp: {}", e))?;
+		// Simple recursive copy; `/.` copies the contents, hidden files included
+		let output = jobs::output(
+			&mut WslCommand::new(distribution, "cp")
+				.args(["-R", symlinks.flag(), &format!("{}/.", source), destination])
+				.build(),
+			cancel,
+		)
+		.map_err(|e| format!("Failed to execute WSL cp: {}", e))?;
 		
 		if !output.status.success() {
 			return Err(format!("WSL cp failed: {}", String::from_utf8_lossy(&output.stderr)));
@@ -419,7 +1131,7 @@ fn copy_files_wsl(source: &str, destination: &str, distribution: &str, exclude_g
 }
 
 #[cfg(not(target_os = "windows"))]
-fn copy_files_wsl(_source: &str, _destination: &str, _distribution: &str, _exclude_git: bool) -> Result<(), String> {
+fn copy_files_wsl(_source: &str, _destination: &str, _distribution: &str, _exclude_git: bool, _symlinks: SymlinkMode, _cancel: &CancelToken) -> Result<(), String> {
 	Err("WSL is only supported on Windows".to_string())
 }
 
@@ -432,7 +1144,7 @@ async fn get_found_git_directories_so_far(
 		.get_results(&search_id)
 		.ok_or_else(|| "Search ID not found".to_string())?;
 	
-	println!("Backend - Raw search results before filtering: {} directories", result.directories.len());
+	log::debug!("Backend - Raw search results before filtering: {} directories", result.directories.len());
 	
 	// Filter out deleted directories using appropriate method for each path type
 	let original_count = result.directories.len();
@@ -451,12 +1163,12 @@ async fn get_found_git_directories_so_far(
 		if exists {
 			filtered_dirs.push(path.clone());
 		} else {
-			println!("Backend - Filtering out non-existent directory: {}", path);
+			log::debug!("Backend - Filtering out non-existent directory: {}", path);
 		}
 	}
 	
 	result.directories = filtered_dirs;
-	println!("Backend - After existence filtering: {} directories (removed {})", result.directories.len(), original_count - result.directories.len());
+	log::debug!("Backend - After existence filtering: {} directories (removed {})", result.directories.len(), original_count - result.directories.len());
 	
 	Ok(result)
 }
@@ -468,10 +1180,7 @@ fn check_wsl_path_exists(path: &str) -> bool {
 		for session in available {
 			if let crate::os::OsSessionKind::Wsl(dist_name) = session {
 				// Use WSL test command to check if directory exists
-				let output = Command::new("wsl")
-					.arg("-d")
-					.arg(&dist_name)
-					.arg("test")
+				let output = WslCommand::new(&dist_name, "test")
 					.arg("-d")
 					.arg(path)
 					.output();
@@ -492,24 +1201,45 @@ fn check_wsl_path_exists(_path: &str) -> bool {
 	false
 }
 
+/// Whether `path` is a directory in the session. It runs nothing, so unlike
+/// `test -d` through `execute_command_with_os_session` it needs no trust.
+#[tauri::command]
+async fn directory_exists(path: String, os_session: OsSession) -> Result<bool, String> {
+	tauri::async_runtime::spawn_blocking(move || os_session.host_path_for(&path).is_dir())
+		.await
+		.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn list_available_os_session_kinds() -> Result<Vec<OsSessionKind>, String> {
 	OsSessionKind::list_available().map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn copy_directory(source: String, destination: String, os_session: OsSession) -> Result<(), String> {
-	match os_session {
-		OsSession::Local(_) => {
-			copy_directory_local(&source, &destination)
-		}
-		OsSession::Wsl(wsl_session) => {
-			copy_directory_wsl(&source, &destination, &wsl_session.distribution)
+async fn copy_directory(
+	source: String,
+	destination: String,
+	os_session: OsSession,
+	symlinks: Option<SymlinkMode>,
+	job_manager: State<'_, Arc<JobManager>>,
+) -> Result<(), String> {
+	let symlinks = symlinks.unwrap_or_default();
+	let title = format!("Copy {} to {}", source, destination);
+	job_manager.run(JobKind::Copy, title, move |job| {
+		let cancel = job.token();
+		match os_session {
+			OsSession::Local(_) => {
+				copy_directory_local(&source, &destination, symlinks, cancel)
+			}
+			OsSession::Wsl(wsl_session) => {
+				copy_directory_wsl(&source, &destination, &wsl_session.distribution, symlinks, cancel)
+			}
 		}
-	}
+		.map_err(anyhow::Error::msg)
+	}).await
 }
 
-fn copy_directory_local(source: &str, destination: &str) -> Result<(), String> {
+fn copy_directory_local(source: &str, destination: &str, symlinks: SymlinkMode, cancel: &CancelToken) -> Result<(), String> {
 	use std::fs;
 	use std::path::Path;
 	
@@ -528,7 +1258,29 @@ fn copy_directory_local(source: &str, destination: &str) -> Result<(), String> {
 	
 	// Use system copy command for better performance
 	#[cfg(target_os = "windows")]
-	{
+	if symlinks == SymlinkMode::Preserve {
+		// Copy-Item copies what links point to; robocopy can copy the links
+		// themselves. Like Copy-Item, copy into an existing destination
+		let target = match src_path.file_name() {
+			Some(name) if dst_path.is_dir() => dst_path.join(name),
+			_ => dst_path.to_path_buf(),
+		};
+		let output = jobs::output(
+			Command::new("robocopy")
+				.arg(source)
+				.arg(&target)
+				.args(["*", "/E", "/SL"]),
+			cancel,
+		)
+		.map_err(|e| format!("Failed to execute robocopy: {}", e))?;
+		
+		// Robocopy exit codes: 0-7 are success, >7 are errors
+		let exit_code = output.status.code().unwrap_or(1);
+		if exit_code > 7 {
+			let stdout = String::from_utf8_lossy(&output.stdout);
+			return Err(format!("Robocopy failed with exit code {}: {}", exit_code, stdout));
+		}
+	} else {
 		// Use PowerShell Copy-Item for reliable directory copying on Windows
 		let ps_command = format!(
 			"Copy-Item -Path '{}' -Destination '{}' -Recurse -Force",
@@ -536,10 +1288,7 @@ fn copy_directory_local(source: &str, destination: &str) -> Result<(), String> {
 			destination.replace("'", "''")
 		);
 		
-		let output = Command::new("powershell")
-			.arg("-Command")
-			.arg(&ps_command)
-			.output()
+		let output = jobs::output(Command::new("powershell").arg("-Command").arg(&ps_command), cancel)
 			.map_err(|e| format!("Failed to execute PowerShell copy: {}", e))?;
 		
 		if !output.status.success() {
@@ -551,12 +1300,15 @@ fn copy_directory_local(source: &str, destination: &str) -> Result<(), String> {
 	
 	#[cfg(any(target_os = "linux", target_os = "macos"))]
 	{
-		let output = Command::new("cp")
-			.arg("-r")
-			.arg(source)
-			.arg(destination)
-			.output()
-			.map_err(|e| format!("Failed to execute cp: {}", e))?;
+		let output = jobs::output(
+			Command::new("cp")
+				.arg("-R")
+				.arg(symlinks.flag())
+				.arg(source)
+				.arg(destination),
+			cancel,
+		)
+		.map_err(|e| format!("Failed to execute cp: {}", e))?;
 		
 		if !output.status.success() {
 			return Err(format!("cp failed: {}", String::from_utf8_lossy(&output.stderr)));
@@ -567,16 +1319,17 @@ fn copy_directory_local(source: &str, destination: &str) -> Result<(), String> {
 }
 
 #[cfg(target_os = "windows")]
-fn copy_directory_wsl(source: &str, destination: &str, distribution: &str) -> Result<(), String> {
-	let output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("cp")
-		.arg("-r")
-		.arg(source)
-		.arg(destination)
-		.output()
-		.map_err(|e| format!("Failed to execute WSL cp: {}", e))?;
+fn copy_directory_wsl(source: &str, destination: &str, distribution: &str, symlinks: SymlinkMode, cancel: &CancelToken) -> Result<(), String> {
+	let output = jobs::output(
+		&mut WslCommand::new(distribution, "cp")
+			.arg("-R")
+			.arg(symlinks.flag())
+			.arg(wsl::to_wsl_path(distribution, source))
+			.arg(wsl::to_wsl_path(distribution, destination))
+			.build(),
+		cancel,
+	)
+	.map_err(|e| format!("Failed to execute WSL cp: {}", e))?;
 	
 	if !output.status.success() {
 		return Err(format!("WSL cp failed: {}", String::from_utf8_lossy(&output.stderr)));
@@ -586,7 +1339,7 @@ fn copy_directory_wsl(source: &str, destination: &str, distribution: &str) -> Re
 }
 
 #[cfg(not(target_os = "windows"))]
-fn copy_directory_wsl(_source: &str, _destination: &str, _distribution: &str) -> Result<(), String> {
+fn copy_directory_wsl(_source: &str, _destination: &str, _distribution: &str, _symlinks: SymlinkMode, _cancel: &CancelToken) -> Result<(), String> {
 	Err("WSL is only available on Windows".to_string())
 }
 
@@ -621,12 +1374,8 @@ fn create_git_branch_local(directory: &str, branch_name: &str) -> Result<(), Str
 #[cfg(target_os = "windows")]
 fn create_git_branch_wsl(directory: &str, branch_name: &str, distribution: &str) -> Result<(), String> {
 	// Execute git command inside WSL using --cd to change directory
-	let output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("checkout")
 		.arg("-B")
 		.arg(branch_name)
@@ -648,18 +1397,81 @@ fn create_git_branch_wsl(_directory: &str, _branch_name: &str, _distribution: &s
 }
 
 #[tauri::command]
-async fn git_commit(directory: String, message: String, os_session: OsSession) -> Result<String, String> {
+async fn git_commit(
+	directory: String,
+	message: String,
+	os_session: OsSession,
+	format: Option<bool>,
+	no_verify: Option<bool>,
+	generate_message: Option<SummaryModel>,
+	app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+	// An empty message with a model asks it for one, which the user confirms
+	// through `confirm_commit_message` before anything is committed
+	let message = match generate_message {
+		Some(model) if message.trim().is_empty() => {
+			commit_message::propose(&app_handle, &os_session, &directory, &model)
+				.await
+				.map_err(|e| e.to_string())?
+				.ok_or_else(|| "COMMIT_CANCELLED".to_string())?
+		}
+		_ => message,
+	};
+	// Formats changed files first, refusing to commit ones that don't parse
+	if format.unwrap_or(false) {
+		trust::ensure_trusted(&app_handle, &os_session, &directory)?;
+		formatter::format_changed_files(&os_session, &directory).map_err(|e| e.to_string())?;
+	}
+	let no_verify = no_verify.unwrap_or(false);
+	// The project's own pre-commit hooks, from .ariana/hooks.toml
+	if !no_verify {
+		project_hooks::run(
+			&app_handle,
+			&project_hooks::session_at(&os_session, &directory),
+			HookEvent::PreCommit,
+			HashMap::from([("commit_message".to_string(), message.clone())]),
+		)
+		.await
+		.map_err(|e| format!("PROJECT_HOOK_FAILED: {:#}", e))?;
+	}
+	let hooks = git_hooks::commit_hooks(&os_session, &directory);
+	// Hooks are the repository's code; prepare-commit-msg and post-commit
+	// run even with --no-verify
+	if !hooks.is_empty() {
+		trust::ensure_trusted(&app_handle, &os_session, &directory)?;
+	}
+	let commit = CommitOptions { no_verify, hooks: &hooks, app_handle: &app_handle };
 	match os_session {
 		OsSession::Local(_) => {
-			git_commit_local(&directory, &message)
+			git_commit_local(&directory, &message, &commit)
 		}
 		OsSession::Wsl(wsl_session) => {
-			git_commit_wsl(&directory, &message, &wsl_session.distribution)
+			git_commit_wsl(&directory, &message, &wsl_session.distribution, &commit)
 		}
 	}
 }
 
-fn git_commit_local(directory: &str, message: &str) -> Result<String, String> {
+struct CommitOptions<'a> {
+	no_verify: bool,
+	/// Commit hooks installed in the repository.
+	hooks: &'a [String],
+	app_handle: &'a tauri::AppHandle,
+}
+
+/// Turns a failed commit into an error, `HOOK_FAILED` with the output if a
+/// hook stopped it.
+fn git_commit_error(output: &git_hooks::CommitOutput, commit: &CommitOptions, prefix: &str) -> String {
+	// Check for "nothing to commit" scenarios
+	if output.stderr.contains("nothing to commit") || output.stdout.contains("nothing to commit") {
+		return "NO_CHANGES_TO_COMMIT".to_string();
+	}
+	if git_hooks::failed_in_hook(output, commit.hooks, commit.no_verify) {
+		return format!("HOOK_FAILED: {}", output.combined);
+	}
+	format!("{}: {}", prefix, output.stderr)
+}
+
+fn git_commit_local(directory: &str, message: &str, commit: &CommitOptions) -> Result<String, String> {
 	// First, add all changes
 	let add_output = Command::new("git")
 		.arg("add")
@@ -672,25 +1484,17 @@ fn git_commit_local(directory: &str, message: &str) -> Result<String, String> {
 		return Err(format!("Git add failed: {}", String::from_utf8_lossy(&add_output.stderr)));
 	}
 	
-	// Then commit
-	let commit_output = Command::new("git")
-		.arg("commit")
-		.arg("-m")
-		.arg(message)
-		.current_dir(directory)
-		.output()
-		.map_err(|e| format!("Failed to execute git commit command: {}", e))?;
+	// Then commit, streaming the output of any hooks
+	let mut cmd = Command::new("git");
+	cmd.arg("commit").arg("-m").arg(message).current_dir(directory);
+	if commit.no_verify {
+		cmd.arg("--no-verify");
+	}
+	let commit_output =
+		git_hooks::run_commit(cmd, commit.app_handle, directory, !commit.hooks.is_empty())?;
 	
-	if !commit_output.status.success() {
-		let stderr = String::from_utf8_lossy(&commit_output.stderr);
-		let stdout = String::from_utf8_lossy(&commit_output.stdout);
-		
-		// Check for "nothing to commit" scenarios
-		if stderr.contains("nothing to commit") || stdout.contains("nothing to commit") {
-			return Err("NO_CHANGES_TO_COMMIT".to_string());
-		}
-		
-		return Err(format!("Git commit failed: {}", stderr));
+	if !commit_output.success {
+		return Err(git_commit_error(&commit_output, commit, "Git commit failed"));
 	}
 	
 	// Get the commit hash
@@ -709,14 +1513,10 @@ fn git_commit_local(directory: &str, message: &str) -> Result<String, String> {
 }
 
 #[cfg(target_os = "windows")]
-fn git_commit_wsl(directory: &str, message: &str, distribution: &str) -> Result<String, String> {
+fn git_commit_wsl(directory: &str, message: &str, distribution: &str, commit: &CommitOptions) -> Result<String, String> {
 	// First, add all changes
-	let add_output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let add_output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("add")
 		.arg(".")
 		.output()
@@ -726,38 +1526,25 @@ fn git_commit_wsl(directory: &str, message: &str, distribution: &str) -> Result<
 		return Err(format!("WSL git add failed: {}", String::from_utf8_lossy(&add_output.stderr)));
 	}
 	
-	// Then commit
-	let commit_output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	// Then commit, streaming the output of any hooks
+	let mut cmd = WslCommand::new(distribution, "git");
+	cmd.current_dir(directory)
 		.arg("commit")
 		.arg("-m")
-		.arg(message)
-		.output()
-		.map_err(|e| format!("Failed to execute WSL git commit command: {}", e))?;
+		.arg(message);
+	if commit.no_verify {
+		cmd.arg("--no-verify");
+	}
+	let commit_output =
+		git_hooks::run_commit(cmd.build(), commit.app_handle, directory, !commit.hooks.is_empty())?;
 	
-	if !commit_output.status.success() {
-		let stderr = String::from_utf8_lossy(&commit_output.stderr);
-		let stdout = String::from_utf8_lossy(&commit_output.stdout);
-		
-		// Check for "nothing to commit" scenarios
-		if stderr.contains("nothing to commit") || stdout.contains("nothing to commit") {
-			return Err("NO_CHANGES_TO_COMMIT".to_string());
-		}
-		
-		return Err(format!("WSL git commit failed: {}", stderr));
+	if !commit_output.success {
+		return Err(git_commit_error(&commit_output, commit, "WSL git commit failed"));
 	}
 	
 	// Get the commit hash
-	let hash_output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let hash_output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("rev-parse")
 		.arg("HEAD")
 		.output()
@@ -771,7 +1558,7 @@ fn git_commit_wsl(directory: &str, message: &str, distribution: &str) -> Result<
 }
 
 #[cfg(not(target_os = "windows"))]
-fn git_commit_wsl(_directory: &str, _message: &str, _distribution: &str) -> Result<String, String> {
+fn git_commit_wsl(_directory: &str, _message: &str, _distribution: &str, _commit: &CommitOptions) -> Result<String, String> {
 	Err("WSL is only supported on Windows".to_string())
 }
 
@@ -805,12 +1592,8 @@ fn git_revert_to_commit_local(directory: &str, commit_hash: &str) -> Result<(),
 
 #[cfg(target_os = "windows")]
 fn git_revert_to_commit_wsl(directory: &str, commit_hash: &str, distribution: &str) -> Result<(), String> {
-	let output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("reset")
 		.arg("--hard")
 		.arg(commit_hash)
@@ -830,27 +1613,31 @@ fn git_revert_to_commit_wsl(_directory: &str, _commit_hash: &str, _distribution:
 }
 
 #[tauri::command]
-async fn open_path_in_explorer(path: String) -> Result<(), String> {
+async fn open_path_in_explorer(path: String, os_session: Option<OsSession>) -> Result<(), String> {
 	#[cfg(target_os = "windows")]
 	{
-		// On Windows, normalize the path and use explorer.exe to open it
-		let windows_path = path.replace('/', "\\");
-		println!("Opening path in explorer - Original: '{}', Windows path: '{}'", path, windows_path);
+		// On Windows, normalize the path and use explorer.exe to open it;
+		// paths inside WSL are opened through their Windows form
+		let windows_path = match &os_session {
+			Some(OsSession::Wsl(wsl_session)) => wsl::to_windows_path(&wsl_session.distribution, &path),
+			_ => path.replace('/', "\\"),
+		};
+		log::debug!("Opening path in explorer - Original: '{}', Windows path: '{}'", path, windows_path);
 		
 		// Use /select to open the parent directory and highlight the folder
 		// But if it's a directory, just open it directly
 		let path_obj = std::path::Path::new(&windows_path);
-		println!("Path exists: {}, Is directory: {}", path_obj.exists(), path_obj.is_dir());
+		log::debug!("Path exists: {}, Is directory: {}", path_obj.exists(), path_obj.is_dir());
 		
 		let output = if path_obj.is_dir() {
 			// Open the directory directly
-			println!("Opening directory directly: '{}'", windows_path);
+			log::debug!("Opening directory directly: '{}'", windows_path);
 			Command::new("explorer")
 				.arg(&windows_path)
 				.output()
 		} else {
 			// If it's a file, open the parent and select it
-			println!("Using /select for file: '{}'", windows_path);
+			log::debug!("Using /select for file: '{}'", windows_path);
 			Command::new("explorer")
 				.arg("/select,")
 				.arg(&windows_path)
@@ -863,9 +1650,12 @@ async fn open_path_in_explorer(path: String) -> Result<(), String> {
 			return Err(format!("Explorer failed: {}", String::from_utf8_lossy(&result.stderr)));
 		}
 		
-		println!("Explorer command succeeded");
+		log::debug!("Explorer command succeeded");
 	}
 	
+	#[cfg(not(target_os = "windows"))]
+	let _ = os_session;
+	
 	#[cfg(target_os = "macos")]
 	{
 		// On macOS, use open command
@@ -908,28 +1698,11 @@ async fn open_path_in_explorer(path: String) -> Result<(), String> {
 
 #[tauri::command]
 async fn delete_path(path: String) -> Result<(), String> {
-	use std::fs;
-	
-	let path_obj = Path::new(&path);
-	
-	if !path_obj.exists() {
-		return Err(format!("Path does not exist: {}", path));
-	}
-	
-	println!("Deleting path: {}", path);
-	
-	if path_obj.is_dir() {
-		// Delete directory and all contents recursively
-		fs::remove_dir_all(&path)
-			.map_err(|e| format!("Failed to delete directory '{}': {}", path, e))?;
-		println!("Successfully deleted directory: {}", path);
-	} else {
-		// Delete file
-		fs::remove_file(&path)
-			.map_err(|e| format!("Failed to delete file '{}': {}", path, e))?;
-		println!("Successfully deleted file: {}", path);
-	}
+	log::info!("Deleting path: {}", path);
 	
+	// A symlink is deleted on its own, never the folder it points to
+	symlinks::remove_path(Path::new(&path)).map_err(|e| e.to_string())?;
+	log::debug!("Successfully deleted path: {}", path);
 	Ok(())
 }
 
@@ -946,42 +1719,22 @@ async fn delete_path_with_os_session(path: String, os_session: OsSession) -> Res
 }
 
 fn delete_path_local(path: &str) -> Result<(), String> {
-	use std::fs;
-	
-	let path_obj = Path::new(path);
-	
-	if !path_obj.exists() {
-		return Err(format!("Path does not exist: {}", path));
-	}
-	
-	println!("Deleting local path: {}", path);
-	
-	if path_obj.is_dir() {
-		// Delete directory and all contents recursively
-		fs::remove_dir_all(&path)
-			.map_err(|e| format!("Failed to delete directory '{}': {}", path, e))?;
-		println!("Successfully deleted directory: {}", path);
-	} else {
-		// Delete file
-		fs::remove_file(&path)
-			.map_err(|e| format!("Failed to delete file '{}': {}", path, e))?;
-		println!("Successfully deleted file: {}", path);
-	}
+	log::info!("Deleting local path: {}", path);
 	
+	// A symlink is deleted on its own, never the folder it points to
+	symlinks::remove_path(Path::new(&path)).map_err(|e| e.to_string())?;
+	log::debug!("Successfully deleted local path: {}", path);
 	Ok(())
 }
 
 #[cfg(target_os = "windows")]
 fn delete_path_wsl(path: &str, distribution: &str) -> Result<(), String> {
-	println!("Deleting WSL path: {} using distribution: {}", path, distribution);
+	log::info!("Deleting WSL path: {} using distribution: {}", path, distribution);
 	
 	// Use WSL rm command to delete the path
-	let output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("rm")
+	let output = WslCommand::new(distribution, "rm")
 		.arg("-rf")
-		.arg(path)
+		.arg(wsl::to_wsl_path(distribution, path))
 		.output()
 		.map_err(|e| format!("Failed to execute WSL rm command: {}", e))?;
 	
@@ -991,7 +1744,7 @@ fn delete_path_wsl(path: &str, distribution: &str) -> Result<(), String> {
 		return Err(format!("WSL rm failed: stderr: {} stdout: {}", stderr, stdout));
 	}
 	
-	println!("Successfully deleted WSL path: {}", path);
+	log::debug!("Successfully deleted WSL path: {}", path);
 	Ok(())
 }
 
@@ -1053,12 +1806,8 @@ fn git_check_merge_conflicts_local(directory: &str, source_branch: &str, target_
 #[cfg(target_os = "windows")]
 fn git_check_merge_conflicts_wsl(directory: &str, source_branch: &str, target_branch: &str, distribution: &str) -> Result<bool, String> {
 	// Use git merge-tree to check for conflicts without actually merging
-	let merge_base_output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let merge_base_output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("merge-base")
 		.arg(target_branch)
 		.arg(source_branch)
@@ -1072,12 +1821,8 @@ fn git_check_merge_conflicts_wsl(directory: &str, source_branch: &str, target_br
 	let merge_base = String::from_utf8_lossy(&merge_base_output.stdout).trim().to_string();
 	
 	// Check for conflicts using merge-tree
-	let output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("merge-tree")
 		.arg(&merge_base)
 		.arg(target_branch)
@@ -1133,12 +1878,8 @@ fn git_get_conflict_files_local(directory: &str) -> Result<Vec<String>, String>
 
 #[cfg(target_os = "windows")]
 fn git_get_conflict_files_wsl(directory: &str, distribution: &str) -> Result<Vec<String>, String> {
-	let output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("diff")
 		.arg("--name-only")
 		.arg("--diff-filter=U")
@@ -1168,16 +1909,29 @@ async fn git_merge_branch(
 	directory: String,
 	source_branch: String,
 	target_branch: String,
-	os_session: OsSession
+	os_session: OsSession,
+	app_handle: tauri::AppHandle,
 ) -> Result<String, String> {
-	match os_session {
+	let result = match &os_session {
 		OsSession::Local(_) => {
 			git_merge_branch_local(&directory, &source_branch, &target_branch)
 		}
 		OsSession::Wsl(wsl_session) => {
 			git_merge_branch_wsl(&directory, &source_branch, &target_branch, &wsl_session.distribution)
 		}
+	}?;
+	if result == "MERGE_SUCCESS" {
+		project_hooks::run_in_background(
+			&app_handle,
+			&project_hooks::session_at(&os_session, &directory),
+			HookEvent::PostMerge,
+			HashMap::from([
+				("source_branch".to_string(), source_branch),
+				("target_branch".to_string(), target_branch),
+			]),
+		);
 	}
+	Ok(result)
 }
 
 fn git_merge_branch_local(directory: &str, source_branch: &str, target_branch: &str) -> Result<String, String> {
@@ -1218,12 +1972,8 @@ fn git_merge_branch_local(directory: &str, source_branch: &str, target_branch: &
 #[cfg(target_os = "windows")]
 fn git_merge_branch_wsl(directory: &str, source_branch: &str, target_branch: &str, distribution: &str) -> Result<String, String> {
 	// First checkout target branch
-	let checkout_output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let checkout_output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("checkout")
 		.arg(target_branch)
 		.output()
@@ -1234,12 +1984,8 @@ fn git_merge_branch_wsl(directory: &str, source_branch: &str, target_branch: &st
 	}
 	
 	// Then merge source branch
-	let merge_output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let merge_output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("merge")
 		.arg(source_branch)
 		.output()
@@ -1306,12 +2052,8 @@ fn git_get_current_branch_local(directory: &str) -> Result<String, String> {
 
 #[cfg(target_os = "windows")]
 fn git_get_current_branch_wsl(directory: &str, distribution: &str) -> Result<String, String> {
-	let output = Command::new("wsl")
-		.arg("-d")
-		.arg(distribution)
-		.arg("--cd")
-		.arg(directory)
-		.arg("git")
+	let output = WslCommand::new(distribution, "git")
+		.current_dir(directory)
 		.arg("branch")
 		.arg("--show-current")
 		.output()
@@ -1319,12 +2061,8 @@ fn git_get_current_branch_wsl(directory: &str, distribution: &str) -> Result<Str
 	
 	if !output.status.success() {
 		// Try alternative method for older git versions
-		let output2 = Command::new("wsl")
-			.arg("-d")
-			.arg(distribution)
-			.arg("--cd")
-			.arg(directory)
-			.arg("git")
+		let output2 = WslCommand::new(distribution, "git")
+			.current_dir(directory)
 			.arg("rev-parse")
 			.arg("--abbrev-ref")
 			.arg("HEAD")
@@ -0,0 +1,692 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dependencies::{self, Ecosystem};
+use crate::os::OsSession;
+
+/// The per-project policy, relative to the project root.
+const POLICY_PATH: &str = ".ariana/licenses.toml";
+/// Longer `License:` fields in Python metadata are the license text itself.
+const MAX_LICENSE_FIELD: usize = 100;
+
+const NETWORK_COPYLEFT: &[&str] = &["agpl-1.0", "agpl-3.0", "sspl-1.0"];
+const STRONG_COPYLEFT: &[&str] = &[
+	"gpl-1.0",
+	"gpl-2.0",
+	"gpl-3.0",
+	"eupl-1.1",
+	"eupl-1.2",
+	"osl-3.0",
+	"cc-by-sa-4.0",
+	"rpl-1.5",
+	"sleepycat",
+];
+const WEAK_COPYLEFT: &[&str] = &[
+	"lgpl-2.0", "lgpl-2.1", "lgpl-3.0", "mpl-1.1", "mpl-2.0", "epl-1.0", "epl-2.0",
+	"cddl-1.0", "cddl-1.1", "cpl-1.0", "ms-rl",
+];
+const PERMISSIVE: &[&str] = &[
+	"0bsd",
+	"apache-2.0",
+	"artistic-2.0",
+	"blueoak-1.0.0",
+	"bsl-1.0",
+	"cc-by-4.0",
+	"cc0-1.0",
+	"isc",
+	"mit",
+	"mit-0",
+	"ncsa",
+	"openssl",
+	"psf-2.0",
+	"python-2.0",
+	"unicode-3.0",
+	"unicode-dfs-2016",
+	"unlicense",
+	"wtfpl",
+	"x11",
+	"zlib",
+];
+
+/// Common names that aren't SPDX identifiers, as package metadata and
+/// Python classifiers write them.
+const ALIASES: &[(&str, &str)] = &[
+	("mit license", "MIT"),
+	("the mit license", "MIT"),
+	("apache 2", "Apache-2.0"),
+	("apache 2.0", "Apache-2.0"),
+	("apache-2", "Apache-2.0"),
+	("apache license", "Apache-2.0"),
+	("apache license 2.0", "Apache-2.0"),
+	("apache license, version 2.0", "Apache-2.0"),
+	("apache software license", "Apache-2.0"),
+	("bsd", "BSD-3-Clause"),
+	("bsd license", "BSD-3-Clause"),
+	("new bsd license", "BSD-3-Clause"),
+	("simplified bsd license", "BSD-2-Clause"),
+	("isc license", "ISC"),
+	("isc license (iscl)", "ISC"),
+	("python software foundation license", "PSF-2.0"),
+	("mozilla public license 2.0 (mpl 2.0)", "MPL-2.0"),
+	("the unlicense (unlicense)", "Unlicense"),
+	("gnu general public license v2 (gplv2)", "GPL-2.0-only"),
+	(
+		"gnu general public license v2 or later (gplv2+)",
+		"GPL-2.0-or-later",
+	),
+	("gnu general public license v3 (gplv3)", "GPL-3.0-only"),
+	(
+		"gnu general public license v3 or later (gplv3+)",
+		"GPL-3.0-or-later",
+	),
+	(
+		"gnu lesser general public license v2 (lgplv2)",
+		"LGPL-2.0-only",
+	),
+	(
+		"gnu lesser general public license v2 or later (lgplv2+)",
+		"LGPL-2.0-or-later",
+	),
+	(
+		"gnu lesser general public license v3 (lgplv3)",
+		"LGPL-3.0-only",
+	),
+	(
+		"gnu lesser general public license v3 or later (lgplv3+)",
+		"LGPL-3.0-or-later",
+	),
+	("gnu affero general public license v3", "AGPL-3.0-only"),
+	(
+		"gnu affero general public license v3 or later (agplv3+)",
+		"AGPL-3.0-or-later",
+	),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LicenseCategory {
+	Permissive,
+	/// Copyleft limited to the licensed files or library, e.g. LGPL or MPL.
+	WeakCopyleft,
+	/// Copyleft over the whole program it's part of, e.g. GPL.
+	StrongCopyleft,
+	/// Copyleft reaching users over a network too, e.g. AGPL.
+	NetworkCopyleft,
+	/// Missing, or not a license this knows.
+	Unknown,
+}
+
+impl LicenseCategory {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::Permissive => "permissive",
+			Self::WeakCopyleft => "weak-copyleft",
+			Self::StrongCopyleft => "strong-copyleft",
+			Self::NetworkCopyleft => "network-copyleft",
+			Self::Unknown => "unknown",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+	Allowed,
+	/// Reported, but doesn't fail the check.
+	Warning,
+	Denied,
+}
+
+/// `.ariana/licenses.toml`, whose lists take SPDX identifiers or the
+/// categories `permissive`, `weak-copyleft`, `strong-copyleft`,
+/// `network-copyleft` and `unknown`:
+///
+/// ```toml
+/// deny = ["strong-copyleft", "network-copyleft"]
+/// warn = ["weak-copyleft", "unknown"]
+/// allow = ["MPL-2.0"]     # accepted whatever the lists above say
+/// ignore = ["ring"]       # packages left out of the check
+/// ```
+///
+/// Identifiers match regardless of case and of `-only`, `-or-later` and `+`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LicensePolicy {
+	pub deny: Vec<String>,
+	pub warn: Vec<String>,
+	pub allow: Vec<String>,
+	pub ignore: Vec<String>,
+}
+
+impl Default for LicensePolicy {
+	fn default() -> Self {
+		Self {
+			deny: vec![
+				"strong-copyleft".to_string(),
+				"network-copyleft".to_string(),
+			],
+			warn: vec!["weak-copyleft".to_string(), "unknown".to_string()],
+			allow: Vec::new(),
+			ignore: Vec::new(),
+		}
+	}
+}
+
+impl LicensePolicy {
+	fn verdict(&self, id: &str, category: LicenseCategory) -> Verdict {
+		let id = base_id(id);
+		let listed = |list: &[String]| {
+			list.iter()
+				.any(|entry| base_id(entry) == id || entry == category.as_str())
+		};
+		if listed(&self.allow) {
+			Verdict::Allowed
+		} else if listed(&self.deny) {
+			Verdict::Denied
+		} else if listed(&self.warn) {
+			Verdict::Warning
+		} else {
+			Verdict::Allowed
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageLicense {
+	pub ecosystem: Ecosystem,
+	pub name: String,
+	pub version: String,
+	/// SPDX expression, or the package's own wording when it has none.
+	pub license: Option<String>,
+	/// Of the most permissive choice the license offers.
+	pub category: LicenseCategory,
+	pub verdict: Verdict,
+	/// Listed under `ignore`, so it passes whatever its license.
+	pub ignored: bool,
+}
+
+/// How many packages use a license.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseSummary {
+	pub license: Option<String>,
+	pub category: LicenseCategory,
+	pub verdict: Verdict,
+	pub packages: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseReport {
+	/// Denied packages first, then warnings.
+	pub packages: Vec<PackageLicense>,
+	pub licenses: Vec<LicenseSummary>,
+	pub denied: usize,
+	pub warnings: usize,
+	/// Whether no package is denied.
+	pub passed: bool,
+	/// The policy applied.
+	pub policy: LicensePolicy,
+	/// Set when `.ariana/licenses.toml` couldn't be read, in which case the
+	/// default policy was applied.
+	pub policy_error: Option<String>,
+	/// Ecosystems whose packages couldn't be listed.
+	pub errors: Vec<String>,
+}
+
+/// Lowercase, without the `-only`, `-or-later` or `+` that don't change
+/// what kind of license it is.
+fn base_id(id: &str) -> String {
+	let id = id.trim().to_lowercase();
+	let id = id.strip_suffix('+').unwrap_or(&id);
+	let id = id.strip_suffix("-only").unwrap_or(id);
+	id.strip_suffix("-or-later").unwrap_or(id).to_string()
+}
+
+fn category(id: &str, exception: Option<&str>) -> LicenseCategory {
+	let id = base_id(id);
+	let category = if NETWORK_COPYLEFT.contains(&id.as_str()) {
+		LicenseCategory::NetworkCopyleft
+	} else if STRONG_COPYLEFT.contains(&id.as_str()) {
+		LicenseCategory::StrongCopyleft
+	} else if WEAK_COPYLEFT.contains(&id.as_str()) {
+		LicenseCategory::WeakCopyleft
+	} else if PERMISSIVE.contains(&id.as_str()) || id.starts_with("bsd-") {
+		LicenseCategory::Permissive
+	} else {
+		LicenseCategory::Unknown
+	};
+	// The classpath and linking exceptions let the library be used by
+	// programs under other licenses
+	match exception.map(|exception| exception.to_lowercase()) {
+		Some(exception)
+			if category == LicenseCategory::StrongCopyleft
+				&& (exception.contains("classpath")
+					|| exception.contains("linking")
+					|| exception.contains("gcc")
+					|| exception.contains("llvm")) =>
+		{
+			LicenseCategory::WeakCopyleft
+		}
+		_ => category,
+	}
+}
+
+/// The SPDX identifier for a known name, or the name as is.
+fn normalize_license(license: &str) -> String {
+	let license = license.trim();
+	let lower = license.to_lowercase();
+	ALIASES
+		.iter()
+		.find(|(alias, _)| *alias == lower)
+		.map_or_else(|| license.to_string(), |(_, id)| id.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+	Open,
+	Close,
+	Or,
+	And,
+	With,
+	Id(&'a str),
+}
+
+fn tokenize(expression: &str) -> Vec<Token<'_>> {
+	let mut tokens = Vec::new();
+	let mut rest = expression;
+	while let Some(c) = rest.chars().next() {
+		match c {
+			'(' => tokens.push(Token::Open),
+			')' => tokens.push(Token::Close),
+			// `MIT/Apache-2.0`, as older crates write it
+			'/' => tokens.push(Token::Or),
+			c if c.is_whitespace() => {}
+			_ => {
+				let end = rest
+					.find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '/'))
+					.unwrap_or(rest.len());
+				let word = &rest[..end];
+				tokens.push(match word.to_uppercase().as_str() {
+					"OR" => Token::Or,
+					"AND" => Token::And,
+					"WITH" => Token::With,
+					_ => Token::Id(word),
+				});
+				rest = &rest[end..];
+				continue;
+			}
+		}
+		rest = &rest[c.len_utf8()..];
+	}
+	tokens
+}
+
+/// The verdict and category of a license expression, evaluated as it's
+/// parsed: an `OR` takes its best choice, an `AND` its worst part.
+struct Evaluator<'a, 'p> {
+	tokens: Vec<Token<'a>>,
+	position: usize,
+	policy: &'p LicensePolicy,
+}
+
+type Outcome = (Verdict, LicenseCategory);
+
+impl<'a> Evaluator<'a, '_> {
+	fn next(&mut self) -> Option<Token<'a>> {
+		let token = self.tokens.get(self.position).copied();
+		self.position += 1;
+		token
+	}
+
+	fn peek(&self) -> Option<Token<'a>> {
+		self.tokens.get(self.position).copied()
+	}
+
+	fn expression(&mut self) -> Option<Outcome> {
+		let mut outcome = self.term()?;
+		while self.peek() == Some(Token::Or) {
+			self.position += 1;
+			outcome = outcome.min(self.term()?);
+		}
+		Some(outcome)
+	}
+
+	fn term(&mut self) -> Option<Outcome> {
+		let mut outcome = self.factor()?;
+		while self.peek() == Some(Token::And) {
+			self.position += 1;
+			outcome = outcome.max(self.factor()?);
+		}
+		Some(outcome)
+	}
+
+	fn factor(&mut self) -> Option<Outcome> {
+		match self.next()? {
+			Token::Open => {
+				let outcome = self.expression()?;
+				(self.next()? == Token::Close).then_some(outcome)
+			}
+			Token::Id(id) => {
+				let exception = if self.peek() == Some(Token::With) {
+					self.position += 1;
+					match self.next()? {
+						Token::Id(exception) => Some(exception),
+						_ => return None,
+					}
+				} else {
+					None
+				};
+				let category = category(id, exception);
+				Some((self.policy.verdict(id, category), category))
+			}
+			_ => None,
+		}
+	}
+}
+
+fn evaluate(license: Option<&str>, policy: &LicensePolicy) -> Outcome {
+	let unknown = |id: &str| {
+		let category = LicenseCategory::Unknown;
+		(policy.verdict(id, category), category)
+	};
+	let Some(license) = license else {
+		return unknown("");
+	};
+	let mut evaluator = Evaluator {
+		tokens: tokenize(license),
+		position: 0,
+		policy,
+	};
+	match evaluator.expression() {
+		Some(outcome) if evaluator.position == evaluator.tokens.len() => outcome,
+		// Free text, e.g. "Dual licensed, see LICENSE"
+		_ => unknown(license),
+	}
+}
+
+/// Registry and git packages of the Cargo workspace, from `cargo metadata`,
+/// which knows the license of every crate it has downloaded.
+fn cargo_licenses(session: &OsSession) -> Result<Vec<(String, String, Option<String>)>> {
+	let output = session
+		.build_shell_command("cargo metadata --format-version 1 --locked")?
+		.output()?;
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!(
+			"cargo metadata failed: {}",
+			stderr
+				.lines()
+				.rfind(|line| !line.trim().is_empty())
+				.unwrap_or("")
+		));
+	}
+	let metadata: Value = serde_json::from_slice(&output.stdout)?;
+	Ok(metadata["packages"]
+		.as_array()
+		.into_iter()
+		.flatten()
+		// Workspace members and path dependencies have no source
+		.filter(|package| package["source"].is_string())
+		.filter_map(|package| {
+			Some((
+				package["name"].as_str()?.to_string(),
+				package["version"].as_str()?.to_string(),
+				package["license"].as_str().map(str::to_string),
+			))
+		})
+		.collect())
+}
+
+/// The license of a package.json, from `license` or the older `licenses`.
+fn package_json_license(manifest: &Value) -> Option<String> {
+	let name = |value: &Value| {
+		value
+			.as_str()
+			.or_else(|| value["type"].as_str())
+			.map(str::to_string)
+	};
+	name(&manifest["license"]).or_else(|| {
+		let licenses: Vec<String> = manifest["licenses"]
+			.as_array()?
+			.iter()
+			.filter_map(name)
+			.collect();
+		(!licenses.is_empty()).then(|| licenses.join(" OR "))
+	})
+}
+
+fn read_json(path: &Path) -> Option<Value> {
+	serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// Installed npm packages, from package-lock.json, which records their
+/// licenses since lockfile v2, or else `node_modules`.
+fn npm_licenses(root: &Path) -> Vec<(String, String, Option<String>)> {
+	let mut packages = Vec::new();
+	if let Some(lock) = read_json(&root.join("package-lock.json")) {
+		if let Some(entries) = lock["packages"].as_object() {
+			for (path, entry) in entries {
+				let Some((_, name)) = path.rsplit_once("node_modules/") else {
+					continue;
+				};
+				if entry["link"].as_bool() == Some(true) {
+					continue;
+				}
+				let Some(version) = entry["version"].as_str() else {
+					continue;
+				};
+				let license = package_json_license(entry).or_else(|| {
+					package_json_license(&read_json(
+						&root.join(path).join("package.json"),
+					)?)
+				});
+				packages.push((name.to_string(), version.to_string(), license));
+			}
+			return packages;
+		}
+	}
+
+	let node_modules = root.join("node_modules");
+	let Ok(entries) = fs::read_dir(&node_modules) else {
+		return packages;
+	};
+	let mut dirs = Vec::new();
+	for entry in entries.filter_map(|entry| entry.ok()) {
+		let name = entry.file_name().to_string_lossy().to_string();
+		if name.starts_with('@') {
+			if let Ok(scoped) = fs::read_dir(entry.path()) {
+				dirs.extend(
+					scoped
+						.filter_map(|entry| entry.ok())
+						.map(|entry| entry.path()),
+				);
+			}
+		} else if !name.starts_with('.') {
+			dirs.push(entry.path());
+		}
+	}
+	for dir in dirs {
+		let Some(manifest) = read_json(&dir.join("package.json")) else {
+			continue;
+		};
+		let (Some(name), Some(version)) =
+			(manifest["name"].as_str(), manifest["version"].as_str())
+		else {
+			continue;
+		};
+		packages.push((
+			name.to_string(),
+			version.to_string(),
+			package_json_license(&manifest),
+		));
+	}
+	packages
+}
+
+/// The license in a Python package's METADATA: `License-Expression`, a
+/// short `License` field, or its `License ::` classifiers.
+fn python_metadata_license(metadata: &str) -> Option<String> {
+	let mut license = None;
+	let mut classifiers = Vec::new();
+	// The headers end at the first blank line, before the description
+	for line in metadata.lines().take_while(|line| !line.is_empty()) {
+		let Some((key, value)) = line.split_once(':') else {
+			continue;
+		};
+		let value = value.trim();
+		match key {
+			"License-Expression" => return Some(value.to_string()),
+			"License" if !value.is_empty() && value.len() <= MAX_LICENSE_FIELD => {
+				license = Some(value.to_string());
+			}
+			"Classifier" => {
+				if let Some(name) = value.strip_prefix("License ::") {
+					let name = name.rsplit("::").next().unwrap_or(name).trim();
+					if name != "OSI Approved" {
+						classifiers.push(normalize_license(name));
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	license
+		.filter(|license| license.to_uppercase() != "UNKNOWN")
+		.or_else(|| (!classifiers.is_empty()).then(|| classifiers.join(" OR ")))
+}
+
+fn python_licenses(root: &Path) -> Vec<(String, String, Option<String>)> {
+	dependencies::python_dist_infos(root)
+		.into_iter()
+		.map(|(dir, name, version)| {
+			let license = fs::read_to_string(dir.join("METADATA"))
+				.ok()
+				.and_then(|metadata| python_metadata_license(&metadata));
+			(name, version, license)
+		})
+		.collect()
+}
+
+fn read_policy(root: &Path) -> (LicensePolicy, Option<String>) {
+	match fs::read_to_string(root.join(POLICY_PATH)) {
+		Ok(content) => match toml::from_str(&content) {
+			Ok(policy) => (policy, None),
+			Err(e) => (LicensePolicy::default(), Some(e.to_string())),
+		},
+		Err(_) => (LicensePolicy::default(), None),
+	}
+}
+
+fn scan(session: &OsSession, policy: Option<LicensePolicy>) -> LicenseReport {
+	let root = session.host_path();
+	let (policy, policy_error) = match policy {
+		Some(policy) => (policy, None),
+		None => read_policy(&root),
+	};
+
+	let mut found = Vec::new();
+	let mut errors = Vec::new();
+	if root.join("Cargo.toml").exists() {
+		match cargo_licenses(session) {
+			Ok(packages) => found.extend(
+				packages
+					.into_iter()
+					.map(|package| (Ecosystem::Cargo, package)),
+			),
+			Err(e) => errors.push(e.to_string()),
+		}
+	}
+	found.extend(
+		npm_licenses(&root)
+			.into_iter()
+			.map(|package| (Ecosystem::Npm, package)),
+	);
+	found.extend(
+		python_licenses(&root)
+			.into_iter()
+			.map(|package| (Ecosystem::Python, package)),
+	);
+
+	let ignored: HashSet<&str> = policy.ignore.iter().map(String::as_str).collect();
+	let mut seen = HashSet::new();
+	let mut packages = Vec::new();
+	for (ecosystem, (name, version, license)) in found {
+		if !seen.insert((ecosystem, name.clone(), version.clone())) {
+			continue;
+		}
+		let license = license.map(|license| normalize_license(&license));
+		let (verdict, category) = evaluate(license.as_deref(), &policy);
+		let ignored = ignored.contains(name.as_str());
+		packages.push(PackageLicense {
+			ecosystem,
+			name,
+			version,
+			license,
+			category,
+			verdict: if ignored { Verdict::Allowed } else { verdict },
+			ignored,
+		});
+	}
+	packages.sort_by(|a, b| {
+		b.verdict
+			.cmp(&a.verdict)
+			.then_with(|| a.name.cmp(&b.name))
+			.then_with(|| a.version.cmp(&b.version))
+	});
+
+	let mut licenses: BTreeMap<Option<String>, LicenseSummary> = BTreeMap::new();
+	for package in &packages {
+		licenses
+			.entry(package.license.clone())
+			.or_insert_with(|| {
+				let (verdict, category) = evaluate(package.license.as_deref(), &policy);
+				LicenseSummary {
+					license: package.license.clone(),
+					category,
+					verdict,
+					packages: 0,
+				}
+			})
+			.packages += 1;
+	}
+	let mut licenses: Vec<LicenseSummary> = licenses.into_values().collect();
+	licenses.sort_by_key(|summary| std::cmp::Reverse(summary.packages));
+
+	let count = |verdict| {
+		packages
+			.iter()
+			.filter(|package| package.verdict == verdict)
+			.count()
+	};
+	let denied = count(Verdict::Denied);
+	LicenseReport {
+		warnings: count(Verdict::Warning),
+		passed: denied == 0,
+		denied,
+		packages,
+		licenses,
+		policy,
+		policy_error,
+		errors,
+	}
+}
+
+/// Lists the licenses of the project's Cargo, npm and Python dependencies
+/// and checks them against `policy`, or `.ariana/licenses.toml` when it's
+/// missing, so copyleft dependencies can be caught before a merge.
+#[tauri::command]
+pub async fn scan_licenses(
+	os_session: OsSession,
+	policy: Option<LicensePolicy>,
+) -> Result<LicenseReport, String> {
+	tauri::async_runtime::spawn_blocking(move || scan(&os_session, policy))
+		.await
+		.map_err(|e| e.to_string())
+}
@@ -0,0 +1,794 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{self, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use subtle::ConstantTimeEq;
+use tauri::{AppHandle, Emitter, EventId, Listener, Manager, State, Url};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+use tokio_tungstenite::tungstenite::{
+	handshake::derive_accept_key, protocol::Role, Message,
+};
+use tokio_tungstenite::WebSocketStream;
+use uuid::Uuid;
+
+use crate::deep_link::{self, DeepLink};
+use crate::diagnostics::ProblemsStore;
+use crate::os::OsSession;
+use crate::settings::{LocalApiScope, LocalApiSettings, SettingsManager};
+use crate::task_runner::{self, TaskRunner};
+use crate::trust;
+use crate::util::write_private;
+
+/// Largest request body accepted.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Written to `~/.ariana` while the server runs so tools can find it.
+const DISCOVERY_FILE: &str = "local-api.json";
+
+type ApiResponse = Response<Full<Bytes>>;
+
+/// What the discovery file holds.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Discovery<'a> {
+	url: String,
+	port: u16,
+	token: &'a str,
+	scopes: &'a [LocalApiScope],
+	pid: u32,
+}
+
+/// Returned by `get_local_api_status`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalApiStatus {
+	pub running: bool,
+	pub port: Option<u16>,
+	/// Sent as `Authorization: Bearer <token>`, or `?token=` for WebSockets.
+	pub token: Option<String>,
+	pub scopes: Vec<LocalApiScope>,
+	/// Where the port and token are written for tools to pick up.
+	pub discovery_file: Option<String>,
+	/// Why the server couldn't start.
+	pub error: Option<String>,
+}
+
+/// Payload of the `local-api-start-agent` event; the frontend starts the
+/// agent.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentRequest {
+	pub request_id: String,
+	pub os_session: OsSession,
+	pub prompt: String,
+	pub canvas_id: Option<String>,
+}
+
+/// The session a request is about: `{"root": "/path"}` for a local folder,
+/// or `{"session": ...}` in the shape the frontend uses.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionRef {
+	root: Option<String>,
+	session: Option<OsSession>,
+}
+
+impl SessionRef {
+	fn resolve(self) -> Result<OsSession, ApiError> {
+		match (self.session, self.root) {
+			(Some(session), _) => Ok(session),
+			(None, Some(root)) if Path::new(&root).is_absolute() => {
+				Ok(OsSession::Local(root))
+			}
+			(None, Some(_)) => {
+				Err(ApiError::bad_request("`root` must be an absolute path"))
+			}
+			(None, None) => Err(ApiError::bad_request("Expected `root` or `session`")),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenFileRequest {
+	path: String,
+	line: Option<u32>,
+	column: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RunTaskRequest {
+	#[serde(flatten)]
+	session: SessionRef,
+	task_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StopTaskRequest {
+	connection_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StartAgentRequest {
+	#[serde(flatten)]
+	session: SessionRef,
+	prompt: String,
+	canvas_id: Option<String>,
+}
+
+/// Messages a client can send on the event stream.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum EventStreamMessage {
+	/// Also stream the output of a task run started through the API.
+	#[serde(rename_all = "camelCase")]
+	SubscribeTerminal { connection_id: String },
+}
+
+struct ApiError {
+	status: StatusCode,
+	message: String,
+}
+
+impl ApiError {
+	fn new(status: StatusCode, message: impl Into<String>) -> Self {
+		Self {
+			status,
+			message: message.into(),
+		}
+	}
+
+	fn bad_request(message: impl Into<String>) -> Self {
+		Self::new(StatusCode::BAD_REQUEST, message)
+	}
+
+	fn internal(error: impl ToString) -> Self {
+		Self::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+	}
+
+	fn response(&self) -> ApiResponse {
+		json_response(self.status, &json!({ "error": self.message }))
+	}
+}
+
+fn json_response(status: StatusCode, body: &impl Serialize) -> ApiResponse {
+	let body = serde_json::to_vec(body).unwrap_or_default();
+	let mut response = Response::new(Full::new(Bytes::from(body)));
+	*response.status_mut() = status;
+	response.headers_mut().insert(
+		header::CONTENT_TYPE,
+		HeaderValue::from_static("application/json"),
+	);
+	response
+}
+
+/// Lets browser extensions and pages call the API; the token is what
+/// authorizes a request, not its origin.
+fn with_cors(mut response: ApiResponse) -> ApiResponse {
+	let headers = response.headers_mut();
+	headers.insert(
+		header::ACCESS_CONTROL_ALLOW_ORIGIN,
+		HeaderValue::from_static("*"),
+	);
+	headers.insert(
+		header::ACCESS_CONTROL_ALLOW_HEADERS,
+		HeaderValue::from_static("authorization, content-type"),
+	);
+	headers.insert(
+		header::ACCESS_CONTROL_ALLOW_METHODS,
+		HeaderValue::from_static("GET, POST, OPTIONS"),
+	);
+	response
+}
+
+/// Only requests addressed to localhost are served, which stops web pages
+/// from reaching the server through DNS rebinding.
+fn local_host(request: &Request<Incoming>) -> bool {
+	let Some(host) = request
+		.headers()
+		.get(header::HOST)
+		.and_then(|host| host.to_str().ok())
+	else {
+		return false;
+	};
+	let name = match host.strip_prefix('[') {
+		Some(rest) => rest.split(']').next().unwrap_or_default(),
+		None => host.split(':').next().unwrap_or_default(),
+	};
+	matches!(name, "localhost" | "127.0.0.1" | "::1")
+}
+
+fn query_params(request: &Request<Incoming>) -> HashMap<String, String> {
+	Url::parse(&format!("http://localhost{}", request.uri()))
+		.map(|url| url.query_pairs().into_owned().collect())
+		.unwrap_or_default()
+}
+
+async fn read_json<T: DeserializeOwned>(
+	request: Request<Incoming>,
+) -> Result<T, ApiError> {
+	let body = Limited::new(request.into_body(), MAX_BODY_BYTES)
+		.collect()
+		.await
+		.map_err(|e| ApiError::bad_request(format!("Could not read the body: {}", e)))?
+		.to_bytes();
+	serde_json::from_slice(&body)
+		.map_err(|e| ApiError::bad_request(format!("Invalid body: {}", e)))
+}
+
+/// Everything a request handler needs, fixed for the life of one server.
+struct ApiContext {
+	app_handle: AppHandle,
+	token: String,
+	scopes: Vec<LocalApiScope>,
+	/// Connections of the task runs started through this server, the only
+	/// terminals a client may subscribe to.
+	task_runs: Mutex<HashSet<String>>,
+	/// Turns true when the server stops, closing open connections.
+	stopped: watch::Receiver<bool>,
+}
+
+impl ApiContext {
+	fn authorized(
+		&self,
+		request: &Request<Incoming>,
+		query: &HashMap<String, String>,
+	) -> bool {
+		let bearer = request
+			.headers()
+			.get(header::AUTHORIZATION)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.strip_prefix("Bearer "));
+		match bearer.or(query.get("token").map(String::as_str)) {
+			// Constant time, so response times don't leak how much of a
+			// guessed token was right
+			Some(token) => token.as_bytes().ct_eq(self.token.as_bytes()).into(),
+			None => false,
+		}
+	}
+
+	fn require(&self, scope: LocalApiScope) -> Result<(), ApiError> {
+		if self.scopes.contains(&scope) {
+			Ok(())
+		} else {
+			Err(ApiError::new(
+				StatusCode::FORBIDDEN,
+				format!(
+					"The `{}` scope is not enabled in the local API settings",
+					serde_json::to_value(scope)
+						.ok()
+						.and_then(|v| v.as_str().map(str::to_string))
+						.unwrap_or_default()
+				),
+			))
+		}
+	}
+
+	/// App events forwarded on the event stream, by scope.
+	fn streamed_events(&self) -> Vec<&'static str> {
+		let mut events = Vec::new();
+		if self.scopes.contains(&LocalApiScope::Tasks) {
+			events.push("task-exited");
+		}
+		if self.scopes.contains(&LocalApiScope::Diagnostics) {
+			events.push("problems-updated");
+		}
+		events
+	}
+}
+
+async fn handle(ctx: Arc<ApiContext>, request: Request<Incoming>) -> ApiResponse {
+	if request.method() == Method::OPTIONS {
+		return with_cors(Response::new(Full::default()));
+	}
+	if !local_host(&request) {
+		return ApiError::new(StatusCode::FORBIDDEN, "Only localhost is served")
+			.response();
+	}
+	let query = query_params(&request);
+	if !ctx.authorized(&request, &query) {
+		return with_cors(
+			ApiError::new(StatusCode::UNAUTHORIZED, "Missing or invalid token")
+				.response(),
+		);
+	}
+
+	let path = request.uri().path().trim_end_matches('/').to_string();
+	let method = request.method().clone();
+	let result = match (method, path.as_str()) {
+		(Method::GET, "/v1/status") => Ok(json_response(
+			StatusCode::OK,
+			&json!({ "version": env!("CARGO_PKG_VERSION"), "scopes": ctx.scopes }),
+		)),
+		(Method::GET, "/v1/events") => upgrade_events(ctx, request),
+		(Method::POST, "/v1/files/open") => open_file(&ctx, request).await,
+		(Method::GET, "/v1/tasks") => list_tasks(&ctx, &query).await,
+		(Method::POST, "/v1/tasks/run") => run_task(&ctx, request).await,
+		(Method::POST, "/v1/tasks/stop") => stop_task(&ctx, request).await,
+		(Method::GET, "/v1/diagnostics") => diagnostics(&ctx, &query),
+		(Method::POST, "/v1/agents") => start_agent(&ctx, request).await,
+		_ => Err(ApiError::new(StatusCode::NOT_FOUND, "No such endpoint")),
+	};
+	with_cors(result.unwrap_or_else(|e| e.response()))
+}
+
+/// `session` as a JSON query parameter, or `root` for a local folder.
+fn session_from_query(query: &HashMap<String, String>) -> Result<OsSession, ApiError> {
+	let session =
+		match query.get("session") {
+			Some(session) => Some(serde_json::from_str(session).map_err(|e| {
+				ApiError::bad_request(format!("Invalid `session`: {}", e))
+			})?),
+			None => None,
+		};
+	SessionRef {
+		root: query.get("root").cloned(),
+		session,
+	}
+	.resolve()
+}
+
+async fn open_file(
+	ctx: &ApiContext,
+	request: Request<Incoming>,
+) -> Result<ApiResponse, ApiError> {
+	ctx.require(LocalApiScope::Files)?;
+	let body: OpenFileRequest = read_json(request).await?;
+	if !Path::new(&body.path).is_absolute() {
+		return Err(ApiError::bad_request("`path` must be absolute"));
+	}
+	deep_link::dispatch(
+		&ctx.app_handle,
+		DeepLink::OpenFile {
+			path: body.path,
+			line: body.line,
+			column: body.column,
+		},
+	);
+	deep_link::focus_main_window(&ctx.app_handle);
+	Ok(json_response(StatusCode::ACCEPTED, &json!({})))
+}
+
+async fn list_tasks(
+	ctx: &ApiContext,
+	query: &HashMap<String, String>,
+) -> Result<ApiResponse, ApiError> {
+	ctx.require(LocalApiScope::Tasks)?;
+	let session = session_from_query(query)?;
+	let tasks =
+		tauri::async_runtime::spawn_blocking(move || task_runner::detect_tasks(&session))
+			.await
+			.map_err(ApiError::internal)?;
+	Ok(json_response(StatusCode::OK, &tasks))
+}
+
+/// Runs a task like the Tasks panel does, so only in trusted folders.
+async fn run_task(
+	ctx: &ApiContext,
+	request: Request<Incoming>,
+) -> Result<ApiResponse, ApiError> {
+	ctx.require(LocalApiScope::Tasks)?;
+	let body: RunTaskRequest = read_json(request).await?;
+	let session = body.session.resolve()?;
+	trust::ensure_trusted(&ctx.app_handle, &session, session.get_working_directory())
+		.map_err(|e| ApiError::new(StatusCode::FORBIDDEN, e))?;
+	let app_handle = ctx.app_handle.clone();
+	let run = tauri::async_runtime::spawn_blocking(move || {
+		app_handle.state::<Arc<TaskRunner>>().run_task(
+			&session,
+			&body.task_id,
+			app_handle.clone(),
+		)
+	})
+	.await
+	.map_err(ApiError::internal)?
+	.map_err(|e| ApiError::bad_request(e.to_string()))?;
+	ctx.task_runs
+		.lock()
+		.unwrap()
+		.insert(run.connection_id.clone());
+	Ok(json_response(StatusCode::OK, &run))
+}
+
+async fn stop_task(
+	ctx: &ApiContext,
+	request: Request<Incoming>,
+) -> Result<ApiResponse, ApiError> {
+	ctx.require(LocalApiScope::Tasks)?;
+	let body: StopTaskRequest = read_json(request).await?;
+	ctx.app_handle
+		.state::<Arc<TaskRunner>>()
+		.stop_task(&body.connection_id)
+		.map_err(|e| ApiError::new(StatusCode::NOT_FOUND, e.to_string()))?;
+	Ok(json_response(StatusCode::OK, &json!({})))
+}
+
+fn diagnostics(
+	ctx: &ApiContext,
+	query: &HashMap<String, String>,
+) -> Result<ApiResponse, ApiError> {
+	ctx.require(LocalApiScope::Diagnostics)?;
+	let session = session_from_query(query)?;
+	let problems = ctx.app_handle.state::<Arc<ProblemsStore>>().get(&session);
+	Ok(json_response(StatusCode::OK, &problems))
+}
+
+/// Agents are run by the frontend; this hands it the request and returns
+/// the id it will carry.
+async fn start_agent(
+	ctx: &ApiContext,
+	request: Request<Incoming>,
+) -> Result<ApiResponse, ApiError> {
+	ctx.require(LocalApiScope::Agents)?;
+	let body: StartAgentRequest = read_json(request).await?;
+	if body.prompt.trim().is_empty() {
+		return Err(ApiError::bad_request("`prompt` must not be empty"));
+	}
+	let os_session = body.session.resolve()?;
+	trust::ensure_trusted(
+		&ctx.app_handle,
+		&os_session,
+		os_session.get_working_directory(),
+	)
+	.map_err(|e| ApiError::new(StatusCode::FORBIDDEN, e))?;
+
+	let request_id = Uuid::new_v4().to_string();
+	ctx.app_handle
+		.emit(
+			"local-api-start-agent",
+			AgentRequest {
+				request_id: request_id.clone(),
+				os_session,
+				prompt: body.prompt,
+				canvas_id: body.canvas_id,
+			},
+		)
+		.map_err(ApiError::internal)?;
+	deep_link::focus_main_window(&ctx.app_handle);
+	Ok(json_response(
+		StatusCode::ACCEPTED,
+		&json!({ "requestId": request_id }),
+	))
+}
+
+/// Accepts the WebSocket of `/v1/events`, which streams app events as
+/// `{"event": ..., "payload": ...}` messages.
+fn upgrade_events(
+	ctx: Arc<ApiContext>,
+	mut request: Request<Incoming>,
+) -> Result<ApiResponse, ApiError> {
+	let upgrade = request
+		.headers()
+		.get(header::UPGRADE)
+		.and_then(|value| value.to_str().ok())
+		.is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+	let key = request
+		.headers()
+		.get(header::SEC_WEBSOCKET_KEY)
+		.filter(|_| upgrade)
+		.ok_or_else(|| ApiError::bad_request("Expected a WebSocket upgrade"))?;
+	let accept = derive_accept_key(key.as_bytes());
+
+	let on_upgrade = hyper::upgrade::on(&mut request);
+	tauri::async_runtime::spawn(async move {
+		match on_upgrade.await {
+			Ok(upgraded) => {
+				let socket = WebSocketStream::from_raw_socket(
+					TokioIo::new(upgraded),
+					Role::Server,
+					None,
+				)
+				.await;
+				stream_events(&ctx, socket).await;
+			}
+			Err(e) => log::warn!("Local API WebSocket upgrade failed: {}", e),
+		}
+	});
+
+	let mut response = Response::new(Full::default());
+	*response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+	let headers = response.headers_mut();
+	headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+	headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+	headers.insert(
+		header::SEC_WEBSOCKET_ACCEPT,
+		HeaderValue::from_str(&accept).map_err(ApiError::internal)?,
+	);
+	Ok(response)
+}
+
+/// Forwards `event` to the stream behind `tx`.
+fn forward(
+	app_handle: &AppHandle,
+	event: String,
+	tx: mpsc::UnboundedSender<String>,
+) -> EventId {
+	app_handle.listen(event.clone(), move |e| {
+		let payload = serde_json::from_str::<Value>(e.payload()).unwrap_or(Value::Null);
+		let _ = tx.send(json!({ "event": event, "payload": payload }).to_string());
+	})
+}
+
+async fn stream_events<S>(ctx: &ApiContext, socket: WebSocketStream<S>)
+where
+	S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+	let (mut write, mut read) = socket.split();
+	let (tx, mut rx) = mpsc::unbounded_channel();
+	let mut listeners: Vec<EventId> = ctx
+		.streamed_events()
+		.into_iter()
+		.map(|event| forward(&ctx.app_handle, event.to_string(), tx.clone()))
+		.collect();
+	let mut stopped = ctx.stopped.clone();
+
+	loop {
+		tokio::select! {
+			Some(text) = rx.recv() => {
+				if write.send(Message::Text(text.into())).await.is_err() {
+					break;
+				}
+			}
+			message = read.next() => match message {
+				Some(Ok(Message::Text(text))) => {
+					match serde_json::from_str::<EventStreamMessage>(&text) {
+						Ok(EventStreamMessage::SubscribeTerminal { connection_id }) => {
+							let started_here =
+								ctx.task_runs.lock().unwrap().contains(&connection_id);
+							if ctx.require(LocalApiScope::Tasks).is_ok() && started_here {
+								listeners.push(forward(
+									&ctx.app_handle,
+									format!("terminal-data-{}", connection_id),
+									tx.clone(),
+								));
+							}
+						}
+						Err(e) => log::debug!("Ignoring local API message: {}", e),
+					}
+				}
+				Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+				Some(Ok(_)) => {}
+			},
+			_ = stopped.changed() => break,
+		}
+	}
+
+	for id in listeners {
+		ctx.app_handle.unlisten(id);
+	}
+	let _ = write.close().await;
+}
+
+async fn serve(ctx: Arc<ApiContext>, listener: TcpListener) -> Result<()> {
+	let mut stopped = ctx.stopped.clone();
+	loop {
+		let stream = tokio::select! {
+			accepted = listener.accept() => accepted?.0,
+			_ = stopped.changed() => return Ok(()),
+		};
+		let ctx = ctx.clone();
+		tauri::async_runtime::spawn(async move {
+			let mut stopped = ctx.stopped.clone();
+			let service = service_fn(move |request| {
+				let ctx = ctx.clone();
+				async move { Ok::<_, Infallible>(handle(ctx, request).await) }
+			});
+			let connection = http1::Builder::new()
+				.serve_connection(TokioIo::new(stream), service)
+				.with_upgrades();
+			tokio::pin!(connection);
+			tokio::select! {
+				_ = connection.as_mut() => {}
+				_ = stopped.changed() => {
+					connection.as_mut().graceful_shutdown();
+					let _ = connection.await;
+				}
+			}
+		});
+	}
+}
+
+struct RunningServer {
+	port: u16,
+	token: String,
+	scopes: Vec<LocalApiScope>,
+	stop: watch::Sender<bool>,
+}
+
+/// An opt-in HTTP and WebSocket server on localhost that lets scripts,
+/// browser extensions and test harnesses drive the IDE: open files, run
+/// tasks, read diagnostics and start agents, each behind a scope enabled in
+/// the `localApi` settings. A fresh token is made every time it starts.
+pub struct LocalApi {
+	app_handle: AppHandle,
+	server: Mutex<Option<RunningServer>>,
+	error: Mutex<Option<String>>,
+}
+
+impl LocalApi {
+	/// Starts the server if the settings enable it, and follows later
+	/// changes to them.
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		let api = Arc::new(Self {
+			app_handle: app_handle.clone(),
+			server: Mutex::new(None),
+			error: Mutex::new(None),
+		});
+		let weak = Arc::downgrade(&api);
+		app_handle.listen("settings-changed", move |event| {
+			let changed = serde_json::from_str::<Value>(event.payload())
+				.ok()
+				.and_then(|payload| payload.get("changed").cloned())
+				.and_then(|changed| serde_json::from_value::<Vec<String>>(changed).ok())
+				.unwrap_or_default();
+			if changed.iter().any(|path| path.starts_with("localApi")) {
+				if let Some(api) = weak.upgrade() {
+					api.apply();
+				}
+			}
+		});
+		api.apply();
+		api
+	}
+
+	fn settings(&self) -> LocalApiSettings {
+		self.app_handle
+			.state::<Arc<SettingsManager>>()
+			.get()
+			.settings
+			.local_api
+	}
+
+	fn discovery_path(&self) -> Result<PathBuf> {
+		Ok(self
+			.app_handle
+			.path()
+			.home_dir()?
+			.join(".ariana")
+			.join(DISCOVERY_FILE))
+	}
+
+	/// Restarts or stops the server to match the settings.
+	fn apply(&self) {
+		self.stop();
+		let settings = self.settings();
+		if !settings.enabled {
+			*self.error.lock().unwrap() = None;
+			return;
+		}
+		let result = self.start(&settings);
+		if let Err(e) = &result {
+			log::error!("Failed to start the local API: {}", e);
+		}
+		*self.error.lock().unwrap() = result.err().map(|e| e.to_string());
+	}
+
+	fn start(&self, settings: &LocalApiSettings) -> Result<()> {
+		let listener = std::net::TcpListener::bind(("127.0.0.1", settings.port))
+			.with_context(|| format!("Could not listen on port {}", settings.port))?;
+		listener.set_nonblocking(true)?;
+		let port = listener.local_addr()?.port();
+		let listener = {
+			let runtime = tauri::async_runtime::handle();
+			let _guard = runtime.inner().enter();
+			TcpListener::from_std(listener)?
+		};
+		let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+		let (stop, stopped) = watch::channel(false);
+		let ctx = Arc::new(ApiContext {
+			app_handle: self.app_handle.clone(),
+			token: token.clone(),
+			scopes: settings.scopes.clone(),
+			task_runs: Mutex::new(HashSet::new()),
+			stopped,
+		});
+		tauri::async_runtime::spawn(async move {
+			if let Err(e) = serve(ctx, listener).await {
+				log::error!("Local API server stopped: {}", e);
+			}
+		});
+
+		// Only published once the server accepts connections. The token
+		// gives access to the scopes, so only the user may read it.
+		let path = self.discovery_path()?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let discovery = Discovery {
+			url: format!("http://127.0.0.1:{}", port),
+			port,
+			token: &token,
+			scopes: &settings.scopes,
+			pid: std::process::id(),
+		};
+		let published = serde_json::to_vec_pretty(&discovery)
+			.map_err(anyhow::Error::from)
+			.and_then(|contents| write_private(&path, &contents))
+			.with_context(|| format!("Failed to write {}", path.display()));
+		if let Err(e) = published {
+			let _ = stop.send(true);
+			return Err(e);
+		}
+		log::info!("Local API listening on 127.0.0.1:{}", port);
+
+		*self.server.lock().unwrap() = Some(RunningServer {
+			port,
+			token,
+			scopes: settings.scopes.clone(),
+			stop,
+		});
+		Ok(())
+	}
+
+	/// Stops the server, closing open connections, and removes the discovery
+	/// file.
+	pub fn stop(&self) {
+		let Some(server) = self.server.lock().unwrap().take() else {
+			return;
+		};
+		let _ = server.stop.send(true);
+		if let Ok(path) = self.discovery_path() {
+			let _ = fs::remove_file(path);
+		}
+	}
+
+	pub fn status(&self) -> LocalApiStatus {
+		let server = self.server.lock().unwrap();
+		let error = self.error.lock().unwrap().clone();
+		match server.as_ref() {
+			Some(server) => LocalApiStatus {
+				running: true,
+				port: Some(server.port),
+				token: Some(server.token.clone()),
+				scopes: server.scopes.clone(),
+				discovery_file: self
+					.discovery_path()
+					.ok()
+					.map(|path| path.to_string_lossy().to_string()),
+				error,
+			},
+			None => LocalApiStatus {
+				error,
+				..LocalApiStatus::default()
+			},
+		}
+	}
+
+	/// Starts over with a new token, invalidating the old one.
+	pub fn regenerate_token(&self) -> LocalApiStatus {
+		self.apply();
+		self.status()
+	}
+}
+
+#[tauri::command]
+pub async fn get_local_api_status(
+	api: State<'_, Arc<LocalApi>>,
+) -> Result<LocalApiStatus, String> {
+	Ok(api.status())
+}
+
+#[tauri::command]
+pub async fn regenerate_local_api_token(
+	api: State<'_, Arc<LocalApi>>,
+) -> Result<LocalApiStatus, String> {
+	Ok(api.regenerate_token())
+}
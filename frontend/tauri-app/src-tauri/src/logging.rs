@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+pub(crate) const LOG_FILE: &str = "ariana.log";
+/// The log file is rotated to `ariana.log.1` once it reaches this size.
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Rotated files kept next to the current one.
+const MAX_ROTATED_FILES: usize = 3;
+/// Entries kept in memory for `get_recent_logs`.
+const RING_BUFFER_SIZE: usize = 2000;
+/// Overrides the level for this crate, e.g. `ARIANA_LOG=trace`.
+const LEVEL_ENV: &str = "ARIANA_LOG";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+	/// RFC 3339, UTC.
+	pub timestamp: String,
+	pub level: Level,
+	/// Module the entry came from, e.g. `app_lib::terminal`.
+	pub target: String,
+	pub message: String,
+}
+
+impl LogEntry {
+	pub(crate) fn line(&self) -> String {
+		format!(
+			"{} {:<5} {}: {}\n",
+			self.timestamp, self.level, self.target, self.message
+		)
+	}
+}
+
+struct LogFile {
+	path: PathBuf,
+	file: File,
+	size: u64,
+}
+
+impl LogFile {
+	fn open(path: PathBuf) -> Result<Self> {
+		let file = OpenOptions::new().create(true).append(true).open(&path)?;
+		let size = file.metadata()?.len();
+		Ok(Self { path, file, size })
+	}
+
+	fn write(&mut self, line: &str) -> Result<()> {
+		if self.size + line.len() as u64 > MAX_FILE_SIZE {
+			self.rotate()?;
+		}
+		self.file.write_all(line.as_bytes())?;
+		self.size += line.len() as u64;
+		Ok(())
+	}
+
+	/// Shifts `ariana.log.N` to `ariana.log.N+1`, dropping the oldest, and
+	/// starts a fresh file.
+	fn rotate(&mut self) -> Result<()> {
+		for n in (1..MAX_ROTATED_FILES).rev() {
+			let from = rotated_path(&self.path, n);
+			if from.exists() {
+				fs::rename(&from, rotated_path(&self.path, n + 1))?;
+			}
+		}
+		fs::rename(&self.path, rotated_path(&self.path, 1))?;
+		*self = Self::open(self.path.clone())?;
+		Ok(())
+	}
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+	let mut name = path.as_os_str().to_owned();
+	name.push(format!(".{}", n));
+	PathBuf::from(name)
+}
+
+/// Sends `log` records to a rotating file in the app log directory and a
+/// ring buffer of recent entries. Debug builds echo them to stderr too.
+struct Logger {
+	/// Level for this crate; dependencies only log warnings and errors.
+	level: LevelFilter,
+	file: Mutex<Option<LogFile>>,
+	recent: Mutex<VecDeque<LogEntry>>,
+}
+
+impl Log for Logger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		if metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+			metadata.level() <= self.level
+		} else {
+			metadata.level() <= Level::Warn
+		}
+	}
+
+	fn log(&self, record: &Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+		let entry = LogEntry {
+			timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+			level: record.level(),
+			target: record.target().to_string(),
+			message: record.args().to_string(),
+		};
+		let line = entry.line();
+
+		if cfg!(debug_assertions) {
+			eprint!("{}", line);
+		}
+		if let Some(file) = self.file.lock().unwrap().as_mut() {
+			// Nowhere left to report a failure to
+			let _ = file.write(&line);
+		}
+
+		let mut recent = self.recent.lock().unwrap();
+		if recent.len() == RING_BUFFER_SIZE {
+			recent.pop_front();
+		}
+		recent.push_back(entry);
+	}
+
+	fn flush(&self) {
+		if let Some(file) = self.file.lock().unwrap().as_mut() {
+			let _ = file.file.flush();
+		}
+	}
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Installs the logger, writing to the app log directory. Entries logged
+/// before this are dropped.
+pub fn init(app_handle: &AppHandle) -> Result<()> {
+	let dir = app_handle.path().app_log_dir()?;
+	fs::create_dir_all(&dir)?;
+	let file = LogFile::open(dir.join(LOG_FILE))
+		.with_context(|| format!("Failed to open log file in {}", dir.display()))?;
+
+	let level = std::env::var(LEVEL_ENV)
+		.ok()
+		.and_then(|level| level.parse().ok())
+		.unwrap_or(if cfg!(debug_assertions) {
+			LevelFilter::Debug
+		} else {
+			LevelFilter::Info
+		});
+	let logger = LOGGER.get_or_init(|| Logger {
+		level,
+		file: Mutex::new(None),
+		recent: Mutex::new(VecDeque::with_capacity(RING_BUFFER_SIZE)),
+	});
+	*logger.file.lock().unwrap() = Some(file);
+
+	log::set_logger(logger)?;
+	log::set_max_level(level.max(LevelFilter::Warn));
+	log::info!(
+		"ariana IDE {} starting on {} {}",
+		app_handle.package_info().version,
+		std::env::consts::OS,
+		std::env::consts::ARCH
+	);
+	Ok(())
+}
+
+/// The most recent entries, oldest first, at `min_level` or more severe.
+pub fn recent_logs(limit: usize, min_level: LevelFilter) -> Vec<LogEntry> {
+	let Some(logger) = LOGGER.get() else {
+		return Vec::new();
+	};
+	let recent = logger.recent.lock().unwrap();
+	let mut entries: Vec<_> = recent
+		.iter()
+		.rev()
+		.filter(|entry| entry.level <= min_level)
+		.take(limit)
+		.cloned()
+		.collect();
+	entries.reverse();
+	entries
+}
+
+/// Like `recent_logs` at any level, as log file lines, but returns nothing
+/// rather than wait for a lock. For the panic hook, which may run while the
+/// logger is busy on the same thread.
+pub fn try_recent_lines(limit: usize) -> Vec<String> {
+	let Some(recent) = LOGGER
+		.get()
+		.and_then(|logger| logger.recent.try_lock().ok())
+	else {
+		return Vec::new();
+	};
+	let skip = recent.len().saturating_sub(limit);
+	recent
+		.iter()
+		.skip(skip)
+		.map(|entry| entry.line().trim_end().to_string())
+		.collect()
+}
+
+/// Writes the current and rotated log files, oldest first, to
+/// `destination` for attaching to a problem report.
+pub fn write_log_bundle(app_handle: &AppHandle, destination: &Path) -> Result<()> {
+	let path = app_handle.path().app_log_dir()?.join(LOG_FILE);
+	if let Some(logger) = LOGGER.get() {
+		logger.flush();
+	}
+
+	let mut out = File::create(destination)
+		.with_context(|| format!("Failed to create {}", destination.display()))?;
+	writeln!(
+		out,
+		"ariana IDE {} on {} {}\n",
+		app_handle.package_info().version,
+		std::env::consts::OS,
+		std::env::consts::ARCH
+	)?;
+	for n in (1..=MAX_ROTATED_FILES).rev() {
+		if let Ok(content) = fs::read(rotated_path(&path, n)) {
+			out.write_all(&content)?;
+		}
+	}
+	if let Ok(content) = fs::read(&path) {
+		out.write_all(&content)?;
+	}
+	Ok(())
+}
+
+/// Recent log entries for the problem report dialog. `min_level` is one
+/// of `error`, `warn`, `info`, `debug` or `trace`.
+#[tauri::command]
+pub async fn get_recent_logs(
+	limit: Option<usize>,
+	min_level: Option<String>,
+) -> Result<Vec<LogEntry>, String> {
+	let min_level = match min_level {
+		Some(level) => level
+			.parse()
+			.map_err(|_| format!("Unknown log level: {}", level))?,
+		None => LevelFilter::Trace,
+	};
+	Ok(recent_logs(limit.unwrap_or(RING_BUFFER_SIZE), min_level))
+}
+
+/// Saves the log files to `destination`, a path picked by the user.
+#[tauri::command]
+pub async fn export_logs(
+	destination: String,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	write_log_bundle(&app_handle, Path::new(&destination)).map_err(|e| e.to_string())
+}
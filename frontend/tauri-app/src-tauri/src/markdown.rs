@@ -0,0 +1,302 @@
+use std::fs;
+
+use anyhow::anyhow;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
+use serde::Serialize;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::util::LinesWithEndings;
+
+use crate::highlighting::{syntax_for_token, syntaxes, themes, DEFAULT_THEME};
+use crate::os::OsSession;
+use crate::previews;
+
+/// Classes on highlighted code are prefixed so they can't clash with the
+/// app's own.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+/// The longest side of local images embedded in rendered Markdown.
+const IMAGE_SIZE: u32 = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedMarkdown {
+	pub html: String,
+	/// The text of the first heading.
+	pub title: Option<String>,
+}
+
+/// Where relative links and images point.
+struct Resolver<'a> {
+	session: Option<&'a OsSession>,
+	/// The directory of the document, for `./other.md`.
+	directory: Option<String>,
+	/// The project root, for `/docs/other.md`.
+	root: Option<String>,
+}
+
+/// Whether `url` names a scheme, like `https:` or `javascript:`.
+fn scheme_of(url: &str) -> Option<String> {
+	let (scheme, _) = url.split_once(':')?;
+	let valid = scheme.chars().next()?.is_ascii_alphabetic()
+		&& scheme
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+	// A Windows drive letter isn't a scheme
+	(valid && scheme.len() > 1).then(|| scheme.to_lowercase())
+}
+
+/// Joins `relative` onto `base`, resolving `.` and `..`.
+fn join(base: &str, relative: &str) -> String {
+	let mut segments: Vec<&str> = base.split('/').collect();
+	if segments.last() == Some(&"") && segments.len() > 1 {
+		segments.pop();
+	}
+	for segment in relative.split('/') {
+		match segment {
+			"" | "." => {}
+			".." => {
+				if segments.len() > 1 {
+					segments.pop();
+				}
+			}
+			segment => segments.push(segment),
+		}
+	}
+	segments.join("/")
+}
+
+impl Resolver<'_> {
+	/// The file a relative `url` points to, without its fragment or query.
+	fn local_path(&self, url: &str) -> Option<String> {
+		if url.starts_with('#') || url.starts_with("//") || scheme_of(url).is_some() {
+			return None;
+		}
+		let path = url.split(['#', '?']).next().unwrap_or_default();
+		if path.is_empty() {
+			return None;
+		}
+		let base = if path.starts_with('/') {
+			self.root.as_deref()
+		} else {
+			self.directory.as_deref().or(self.root.as_deref())
+		}?;
+		Some(join(base, path))
+	}
+
+	/// A local image as a resized PNG, so large originals never reach the
+	/// webview.
+	fn embed_image(&self, path: &str) -> Option<String> {
+		let session = self.session?;
+		let host_path = session.host_path_for(path);
+		let size = fs::metadata(&host_path).ok()?.len();
+		if size > previews::MAX_SOURCE_BYTES {
+			return None;
+		}
+		let bytes = fs::read(&host_path).ok()?;
+		previews::render_thumbnail(path, &bytes, IMAGE_SIZE)
+			.ok()
+			.map(|thumbnail| thumbnail.data_url)
+	}
+}
+
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+/// Highlights `code` as `language`, falling back to plain text.
+fn highlight(code: &str, language: &str) -> String {
+	let syntaxes = syntaxes();
+	let syntax =
+		syntax_for_token(language).unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+	let mut generator =
+		ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes, CLASS_STYLE);
+	for line in LinesWithEndings::from(code) {
+		if generator
+			.parse_html_for_line_which_includes_newline(line)
+			.is_err()
+		{
+			return escape(code);
+		}
+	}
+	generator.finalize()
+}
+
+/// Renders `markdown` to HTML. Raw HTML in the source is shown as text and
+/// links may only use web and mail schemes, so the result is safe to set as
+/// `innerHTML`. Relative links get a `data-path` with the file they point
+/// to, and local images are embedded.
+pub fn render(
+	markdown: &str,
+	session: Option<&OsSession>,
+	document_path: Option<&str>,
+	project_root: Option<&str>,
+) -> RenderedMarkdown {
+	let resolver = Resolver {
+		session,
+		directory: document_path
+			.map(|path| path.replace('\\', "/"))
+			.and_then(|path| path.rsplit_once('/').map(|(dir, _)| dir.to_string())),
+		root: project_root.map(|root| root.replace('\\', "/")),
+	};
+	let options = Options::ENABLE_TABLES
+		| Options::ENABLE_STRIKETHROUGH
+		| Options::ENABLE_TASKLISTS
+		| Options::ENABLE_FOOTNOTES;
+
+	let mut events: Vec<Event> = Vec::new();
+	let mut title: Option<String> = None;
+	let mut in_heading = false;
+	// The language and text of the code block being read
+	let mut code_block: Option<(String, String)> = None;
+	for event in Parser::new_ext(markdown, options) {
+		if let Some((language, code)) = code_block.as_mut() {
+			match event {
+				Event::Text(text) => code.push_str(&text),
+				Event::End(TagEnd::CodeBlock) => {
+					let class = if language.is_empty() {
+						String::new()
+					} else {
+						format!(" class=\"language-{}\"", escape(language))
+					};
+					events.push(Event::Html(CowStr::from(format!(
+						"<pre class=\"hl-code\"><code{}>{}</code></pre>\n",
+						class,
+						highlight(code, language)
+					))));
+					code_block = None;
+				}
+				_ => {}
+			}
+			continue;
+		}
+
+		match event {
+			Event::Start(Tag::CodeBlock(kind)) => {
+				let language = match kind {
+					CodeBlockKind::Fenced(info) => info
+						.split_whitespace()
+						.next()
+						.unwrap_or_default()
+						.to_lowercase(),
+					CodeBlockKind::Indented => String::new(),
+				};
+				code_block = Some((language, String::new()));
+			}
+			// Raw HTML is the one way to get a script into the page
+			Event::Html(html) | Event::InlineHtml(html) => events.push(Event::Text(html)),
+			Event::Start(Tag::Heading { .. }) if title.is_none() => {
+				in_heading = true;
+				title = Some(String::new());
+				events.push(event);
+			}
+			Event::End(TagEnd::Heading(_)) if in_heading => {
+				in_heading = false;
+				events.push(event);
+			}
+			Event::Text(ref text) | Event::Code(ref text) if in_heading => {
+				if let Some(title) = title.as_mut() {
+					title.push_str(text);
+				}
+				events.push(event);
+			}
+			Event::Start(Tag::Link {
+				dest_url, title, ..
+			}) => {
+				let (href, path) = match resolver.local_path(&dest_url) {
+					Some(path) => (dest_url.to_string(), Some(path)),
+					None => match scheme_of(&dest_url).as_deref() {
+						None | Some("http" | "https" | "mailto") => {
+							(dest_url.to_string(), None)
+						}
+						Some(_) => ("#".to_string(), None),
+					},
+				};
+				let mut tag = format!("<a href=\"{}\"", escape(&href));
+				if let Some(path) = path {
+					tag.push_str(&format!(" data-path=\"{}\"", escape(&path)));
+				}
+				if !title.is_empty() {
+					tag.push_str(&format!(" title=\"{}\"", escape(&title)));
+				}
+				tag.push('>');
+				events.push(Event::InlineHtml(CowStr::from(tag)));
+			}
+			Event::Start(Tag::Image {
+				link_type,
+				dest_url,
+				title,
+				id,
+			}) => {
+				let dest_url = match resolver.local_path(&dest_url) {
+					Some(path) => resolver.embed_image(&path).unwrap_or_default(),
+					None => match scheme_of(&dest_url).as_deref() {
+						Some("http" | "https") => dest_url.to_string(),
+						Some("data") if dest_url[5..].starts_with("image/") => {
+							dest_url.to_string()
+						}
+						_ => String::new(),
+					},
+				};
+				events.push(Event::Start(Tag::Image {
+					link_type,
+					dest_url: CowStr::from(dest_url),
+					title,
+					id,
+				}));
+			}
+			event => events.push(event),
+		}
+	}
+
+	let mut output = String::new();
+	html::push_html(&mut output, events.into_iter());
+	RenderedMarkdown {
+		html: output,
+		title,
+	}
+}
+
+/// Renders Markdown for a README preview or a chat message. `document_path`
+/// and `project_root` are paths in `os_session` that relative links and
+/// images resolve against.
+#[tauri::command]
+pub async fn render_markdown(
+	markdown: String,
+	os_session: Option<OsSession>,
+	document_path: Option<String>,
+	project_root: Option<String>,
+) -> Result<RenderedMarkdown, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		render(
+			&markdown,
+			os_session.as_ref(),
+			document_path.as_deref(),
+			project_root.as_deref(),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())
+}
+
+/// The stylesheet for code highlighted by `render_markdown`, in one of
+/// syntect's bundled themes.
+#[tauri::command]
+pub async fn markdown_highlight_css(theme: Option<String>) -> Result<String, String> {
+	let name = theme.as_deref().unwrap_or(DEFAULT_THEME);
+	let theme = themes()
+		.themes
+		.get(name)
+		.ok_or_else(|| anyhow!("No highlighting theme {}", name))
+		.map_err(|e| e.to_string())?;
+	css_for_theme_with_class_style(theme, CLASS_STYLE).map_err(|e| e.to_string())
+}
@@ -0,0 +1,353 @@
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeOptions {
+	/// Shown after the conflict markers, `ours`/`base`/`theirs` by default.
+	pub ours_label: Option<String>,
+	pub base_label: Option<String>,
+	pub theirs_label: Option<String>,
+	/// Include the base text in conflicts, like `merge.conflictStyle=diff3`.
+	#[serde(default)]
+	pub diff3: bool,
+}
+
+/// Where the text of a resolved region came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeSource {
+	/// Neither side changed it.
+	Base,
+	Ours,
+	Theirs,
+	/// Both sides made the same change.
+	Both,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum MergeRegion {
+	#[serde(rename_all = "camelCase")]
+	Resolved {
+		source: MergeSource,
+		text: String,
+		/// 1-based line in `merged` where the region starts.
+		line: usize,
+	},
+	#[serde(rename_all = "camelCase")]
+	Conflict {
+		base: String,
+		ours: String,
+		theirs: String,
+		/// 1-based line in `merged` of the opening conflict marker.
+		line: usize,
+	},
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResult {
+	/// The merged text, with conflict markers around each conflict.
+	pub merged: String,
+	pub regions: Vec<MergeRegion>,
+	pub conflicts: usize,
+}
+
+/// For each line of `base`, the matching line of `other`, if it's unchanged.
+fn matching_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+	let mut matches = vec![None; base.len()];
+	for op in capture_diff_slices(Algorithm::Myers, base, other) {
+		if let DiffOp::Equal {
+			old_index,
+			new_index,
+			len,
+		} = op
+		{
+			for i in 0..len {
+				matches[old_index + i] = Some(new_index + i);
+			}
+		}
+	}
+	matches
+}
+
+struct Merger {
+	options: MergeOptions,
+	newline: &'static str,
+	merged: String,
+	line: usize,
+	regions: Vec<MergeRegion>,
+	conflicts: usize,
+}
+
+impl Merger {
+	fn push_text(&mut self, text: &str) {
+		self.merged.push_str(text);
+		self.line += text.matches('\n').count();
+	}
+
+	/// Pushes a conflict section, ending it with a newline so the next
+	/// marker starts its own line.
+	fn push_section(&mut self, marker: &str, label: Option<&str>, text: &str) {
+		let header = match label {
+			Some(label) => format!("{} {}{}", marker, label, self.newline),
+			None => format!("{}{}", marker, self.newline),
+		};
+		self.push_text(&header);
+		self.push_text(text);
+		if !text.is_empty() && !text.ends_with('\n') {
+			self.push_text(self.newline);
+		}
+	}
+
+	fn resolved(&mut self, source: MergeSource, lines: &[&str]) {
+		if lines.is_empty() {
+			return;
+		}
+		let text = lines.concat();
+		if let Some(MergeRegion::Resolved {
+			source: last,
+			text: last_text,
+			..
+		}) = self.regions.last_mut()
+		{
+			if *last == source {
+				last_text.push_str(&text);
+				self.push_text(&text);
+				return;
+			}
+		}
+		self.regions.push(MergeRegion::Resolved {
+			source,
+			text: text.clone(),
+			line: self.line,
+		});
+		self.push_text(&text);
+	}
+
+	fn conflict(&mut self, base: &[&str], ours: &[&str], theirs: &[&str]) {
+		let (base, ours, theirs) = (base.concat(), ours.concat(), theirs.concat());
+		self.regions.push(MergeRegion::Conflict {
+			base: base.clone(),
+			ours: ours.clone(),
+			theirs: theirs.clone(),
+			line: self.line,
+		});
+		self.conflicts += 1;
+
+		let label = |label: &Option<String>, default: &str| {
+			label.clone().unwrap_or_else(|| default.to_string())
+		};
+		let ours_label = label(&self.options.ours_label, "ours");
+		let base_label = label(&self.options.base_label, "base");
+		let theirs_label = label(&self.options.theirs_label, "theirs");
+		self.push_section("<<<<<<<", Some(&ours_label), &ours);
+		if self.options.diff3 {
+			self.push_section("|||||||", Some(&base_label), &base);
+		}
+		self.push_section("=======", None, &theirs);
+		let closing = format!(">>>>>>> {}{}", theirs_label, self.newline);
+		self.push_text(&closing);
+	}
+}
+
+/// Merges the changes `ours` and `theirs` each made to `base`, line by line
+/// as `git merge-file` does. Chunks changed on only one side, or the same
+/// way on both, are taken as they are; the rest become conflicts.
+pub fn merge(base: &str, ours: &str, theirs: &str, options: MergeOptions) -> MergeResult {
+	let base: Vec<&str> = base.split_inclusive('\n').collect();
+	let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+	let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+	let ours_matches = matching_lines(&base, &ours);
+	let theirs_matches = matching_lines(&base, &theirs);
+
+	let crlf = [&base, &ours, &theirs]
+		.iter()
+		.any(|lines| lines.first().is_some_and(|line| line.ends_with("\r\n")));
+	let mut merger = Merger {
+		options,
+		newline: if crlf { "\r\n" } else { "\n" },
+		merged: String::new(),
+		line: 1,
+		regions: Vec::new(),
+		conflicts: 0,
+	};
+
+	let (mut i, mut a, mut b) = (0, 0, 0);
+	while i < base.len() || a < ours.len() || b < theirs.len() {
+		// Lines unchanged on both sides keep the three texts in step
+		if i < base.len() && ours_matches[i] == Some(a) && theirs_matches[i] == Some(b) {
+			merger.resolved(MergeSource::Base, &base[i..i + 1]);
+			i += 1;
+			a += 1;
+			b += 1;
+			continue;
+		}
+
+		// Otherwise the texts differ up to the next line both sides kept
+		let next = (i..base.len())
+			.find(|&k| ours_matches[k].is_some() && theirs_matches[k].is_some())
+			.unwrap_or(base.len());
+		let (next_a, next_b) = match (ours_matches.get(next), theirs_matches.get(next)) {
+			(Some(Some(a)), Some(Some(b))) => (*a, *b),
+			_ => (ours.len(), theirs.len()),
+		};
+		let (base_chunk, ours_chunk, theirs_chunk) =
+			(&base[i..next], &ours[a..next_a], &theirs[b..next_b]);
+		if ours_chunk == base_chunk {
+			merger.resolved(MergeSource::Theirs, theirs_chunk);
+		} else if theirs_chunk == base_chunk {
+			merger.resolved(MergeSource::Ours, ours_chunk);
+		} else if ours_chunk == theirs_chunk {
+			merger.resolved(MergeSource::Both, ours_chunk);
+		} else {
+			merger.conflict(base_chunk, ours_chunk, theirs_chunk);
+		}
+		(i, a, b) = (next, next_a, next_b);
+	}
+
+	MergeResult {
+		merged: merger.merged,
+		regions: merger.regions,
+		conflicts: merger.conflicts,
+	}
+}
+
+/// Three-way merges file contents without touching the repository, e.g.
+/// to re-merge after editing one side in the diff editor.
+#[tauri::command]
+pub async fn merge_file_contents(
+	base: String,
+	ours: String,
+	theirs: String,
+	options: Option<MergeOptions>,
+) -> Result<MergeResult, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		merge(&base, &ours, &theirs, options.unwrap_or_default())
+	})
+	.await
+	.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn merge_default(base: &str, ours: &str, theirs: &str) -> MergeResult {
+		merge(base, ours, theirs, MergeOptions::default())
+	}
+
+	#[test]
+	fn takes_changes_made_on_different_lines() {
+		let result = merge_default("a\nb\nc\n", "A\nb\nc\n", "a\nb\nC\n");
+		assert_eq!(result.merged, "A\nb\nC\n");
+		assert_eq!(result.conflicts, 0);
+	}
+
+	#[test]
+	fn takes_the_same_change_made_on_both_sides_once() {
+		let result = merge_default("a\nb\nc\n", "a\nB\nc\n", "a\nB\nc\n");
+		assert_eq!(result.merged, "a\nB\nc\n");
+		assert_eq!(result.conflicts, 0);
+		assert!(result.regions.iter().any(|region| matches!(
+			region,
+			MergeRegion::Resolved {
+				source: MergeSource::Both,
+				..
+			}
+		)));
+	}
+
+	#[test]
+	fn marks_different_changes_to_the_same_line_as_a_conflict() {
+		let result = merge_default("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+		assert_eq!(
+			result.merged,
+			"a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+		);
+		assert_eq!(result.conflicts, 1);
+		let conflict = result
+			.regions
+			.iter()
+			.find_map(|region| match region {
+				MergeRegion::Conflict {
+					base,
+					ours,
+					theirs,
+					line,
+				} => Some((base.as_str(), ours.as_str(), theirs.as_str(), *line)),
+				_ => None,
+			})
+			.unwrap();
+		assert_eq!(conflict, ("b\n", "ours\n", "theirs\n", 2));
+	}
+
+	#[test]
+	fn includes_the_base_and_labels_in_diff3_conflicts() {
+		let options = MergeOptions {
+			ours_label: Some("HEAD".to_string()),
+			base_label: None,
+			theirs_label: Some("feature".to_string()),
+			diff3: true,
+		};
+		let result = merge("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n", options);
+		assert_eq!(
+			result.merged,
+			"a\n<<<<<<< HEAD\nours\n||||||| base\nb\n=======\ntheirs\n>>>>>>> feature\nc\n"
+		);
+	}
+
+	#[test]
+	fn marks_different_insertions_at_the_same_spot_as_a_conflict() {
+		let result = merge_default("a\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+		assert_eq!(
+			result.merged,
+			"a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+		);
+		assert_eq!(result.conflicts, 1);
+	}
+
+	#[test]
+	fn takes_the_same_insertion_at_the_same_spot_once() {
+		let result = merge_default("a\nc\n", "a\nb\nc\n", "a\nb\nc\n");
+		assert_eq!(result.merged, "a\nb\nc\n");
+		assert_eq!(result.conflicts, 0);
+	}
+
+	#[test]
+	fn merges_against_an_empty_base() {
+		let result = merge_default("", "", "added\n");
+		assert_eq!(result.merged, "added\n");
+		assert_eq!(result.conflicts, 0);
+
+		let result = merge_default("", "same\n", "same\n");
+		assert_eq!(result.merged, "same\n");
+		assert_eq!(result.conflicts, 0);
+
+		let result = merge_default("", "ours\n", "theirs\n");
+		assert_eq!(
+			result.merged,
+			"<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n"
+		);
+		assert_eq!(result.conflicts, 1);
+	}
+
+	#[test]
+	fn ends_conflict_sections_without_a_trailing_newline_on_their_own_line() {
+		let result = merge_default("a\nb", "a\nours", "a\ntheirs");
+		assert_eq!(
+			result.merged,
+			"a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n"
+		);
+	}
+
+	#[test]
+	fn keeps_crlf_line_endings_in_conflict_markers() {
+		let result = merge_default("a\r\nb\r\n", "a\r\nours\r\n", "a\r\ntheirs\r\n");
+		assert_eq!(
+			result.merged,
+			"a\r\n<<<<<<< ours\r\nours\r\n=======\r\ntheirs\r\n>>>>>>> theirs\r\n"
+		);
+	}
+}
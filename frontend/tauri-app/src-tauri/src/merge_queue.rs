@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use uuid::Uuid;
+
+use crate::git::{checkout_branch, repo_state, run_git, RepoOperation};
+use crate::os::OsSession;
+use crate::project_hooks::{self, HookEvent};
+
+/// A canvas branch to merge, from the canvas copy at `directory`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeQueueSource {
+	/// The canvas copy, as a path in the target's session.
+	pub directory: String,
+	pub branch: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeItemState {
+	Pending,
+	Merged,
+	/// Conflicts are waiting to be resolved in the target.
+	Conflicted,
+	Skipped,
+	Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeQueueItem {
+	#[serde(flatten)]
+	pub source: MergeQueueSource,
+	pub state: MergeItemState,
+	/// Files left conflicted when the item is `Conflicted`.
+	pub conflicts: Vec<String>,
+	pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeQueueState {
+	Running,
+	/// Stopped at a conflicted item until it is continued or skipped.
+	Paused,
+	Completed,
+	Aborted,
+	Failed,
+}
+
+/// Canvas branches merged one after another into the branch checked out
+/// in `directory`. Payload of the `merge-queue-updated` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeQueue {
+	pub id: String,
+	pub directory: String,
+	pub os_session: OsSession,
+	/// HEAD before the first merge, restored when the queue is aborted.
+	pub start_commit: String,
+	pub items: Vec<MergeQueueItem>,
+	/// Index of the item being merged, or `items.len()` once done.
+	pub current: usize,
+	pub state: MergeQueueState,
+}
+
+/// Payload of the `merge-queue-conflict` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeQueueConflict {
+	pub queue_id: String,
+	pub branch: String,
+	pub files: Vec<String>,
+}
+
+fn conflicted_files(session: &OsSession, directory: &str) -> Result<Vec<String>> {
+	let output = run_git(
+		session,
+		directory,
+		&["diff", "--name-only", "--diff-filter=U"],
+	)?;
+	Ok(output.lines().map(str::to_string).collect())
+}
+
+fn merge_in_progress(session: &OsSession, directory: &str) -> Result<bool> {
+	Ok(repo_state(session, directory)?
+		.operations
+		.iter()
+		.any(|operation| matches!(operation, RepoOperation::Merge { .. })))
+}
+
+impl MergeQueue {
+	/// Runs the project's post-merge hooks once `branch` is merged.
+	fn merged(&self, app_handle: &AppHandle, branch: &str) {
+		project_hooks::run_in_background(
+			app_handle,
+			&project_hooks::session_at(&self.os_session, &self.directory),
+			HookEvent::PostMerge,
+			HashMap::from([("source_branch".to_string(), branch.to_string())]),
+		);
+	}
+
+	/// Fetches the next item's branch from its copy and merges it, stopping
+	/// at conflicts. Returns `false` when the queue has to wait.
+	fn merge_current(&mut self, app_handle: &AppHandle) -> Result<bool> {
+		let (session, directory) = (&self.os_session, self.directory.as_str());
+		let item = &mut self.items[self.current];
+		let source = item.source.clone();
+		let fetch = run_git(
+			session,
+			directory,
+			&["fetch", "--no-tags", &source.directory, &source.branch],
+		);
+		let result = fetch.and_then(|_| {
+			run_git(
+				session,
+				directory,
+				&[
+					"merge",
+					"--no-ff",
+					"--no-edit",
+					"-m",
+					&format!("Merge canvas branch {}", source.branch),
+					"FETCH_HEAD",
+				],
+			)
+		});
+		let error = match result {
+			Ok(_) => {
+				item.state = MergeItemState::Merged;
+				self.merged(app_handle, &source.branch);
+				return Ok(true);
+			}
+			Err(e) => e,
+		};
+
+		let conflicts = conflicted_files(session, directory)?;
+		if !conflicts.is_empty() {
+			item.state = MergeItemState::Conflicted;
+			item.conflicts = conflicts.clone();
+			self.state = MergeQueueState::Paused;
+			let _ = app_handle.emit(
+				"merge-queue-conflict",
+				MergeQueueConflict {
+					queue_id: self.id.clone(),
+					branch: source.branch,
+					files: conflicts,
+				},
+			);
+			return Ok(false);
+		}
+
+		// Nothing to resolve, so the merge can't go on
+		if merge_in_progress(session, directory)? {
+			run_git(session, directory, &["merge", "--abort"])?;
+		}
+		item.state = MergeItemState::Failed;
+		item.error = Some(error.to_string());
+		self.state = MergeQueueState::Failed;
+		Ok(false)
+	}
+
+	/// Merges items until the queue finishes or has to wait.
+	fn advance(&mut self, app_handle: &AppHandle) -> Result<()> {
+		self.state = MergeQueueState::Running;
+		while self.current < self.items.len() {
+			let _ = app_handle.emit("merge-queue-updated", &*self);
+			if !self.merge_current(app_handle)? {
+				let _ = app_handle.emit("merge-queue-updated", &*self);
+				return Ok(());
+			}
+			self.current += 1;
+		}
+		self.state = MergeQueueState::Completed;
+		let _ = app_handle.emit("merge-queue-updated", &*self);
+		Ok(())
+	}
+
+	fn paused_item(&mut self) -> Result<&mut MergeQueueItem> {
+		if self.state != MergeQueueState::Paused {
+			return Err(anyhow!("The merge queue is not waiting on conflicts"));
+		}
+		Ok(&mut self.items[self.current])
+	}
+}
+
+/// Merge queues started with `start_merge_queue`, by id.
+#[derive(Default)]
+pub struct MergeQueueManager {
+	queues: Mutex<HashMap<String, Arc<Mutex<MergeQueue>>>>,
+}
+
+impl MergeQueueManager {
+	fn queue(&self, id: &str) -> Result<Arc<Mutex<MergeQueue>>> {
+		self.queues
+			.lock()
+			.unwrap()
+			.get(id)
+			.cloned()
+			.ok_or_else(|| anyhow!("Merge queue not found: {}", id))
+	}
+
+	/// Starts merging `sources` in order into `directory`, on `branch` if
+	/// given. The target must be on a branch, with nothing in progress and
+	/// no uncommitted changes to tracked files.
+	pub fn start(
+		&self,
+		session: OsSession,
+		directory: String,
+		branch: Option<String>,
+		sources: Vec<MergeQueueSource>,
+		app_handle: &AppHandle,
+	) -> Result<MergeQueue> {
+		if sources.is_empty() {
+			return Err(anyhow!("No branches to merge"));
+		}
+		if let Some(branch) = &branch {
+			checkout_branch(&session, &directory, branch, false)?;
+		}
+		let state = repo_state(&session, &directory)?;
+		if state.busy {
+			return Err(anyhow!(
+				"The target repository is busy; finish or abort what is in progress first"
+			));
+		}
+		let changes = run_git(
+			&session,
+			&directory,
+			&["status", "--porcelain", "--untracked-files=no"],
+		)?;
+		if !changes.trim().is_empty() {
+			return Err(anyhow!(
+				"The target repository has uncommitted changes; commit or stash them first"
+			));
+		}
+
+		let mut queue = MergeQueue {
+			id: Uuid::new_v4().to_string(),
+			directory,
+			os_session: session,
+			start_commit: state
+				.head
+				.ok_or_else(|| anyhow!("The target branch has no commits"))?,
+			items: sources
+				.into_iter()
+				.map(|source| MergeQueueItem {
+					source,
+					state: MergeItemState::Pending,
+					conflicts: Vec::new(),
+					error: None,
+				})
+				.collect(),
+			current: 0,
+			state: MergeQueueState::Running,
+		};
+		queue.advance(app_handle)?;
+		self.queues
+			.lock()
+			.unwrap()
+			.insert(queue.id.clone(), Arc::new(Mutex::new(queue.clone())));
+		Ok(queue)
+	}
+
+	pub fn get(&self, id: &str) -> Result<MergeQueue> {
+		Ok(self.queue(id)?.lock().unwrap().clone())
+	}
+
+	/// Commits the resolved merge of the paused item and merges the rest.
+	/// A merge the user already committed counts as resolved.
+	pub fn resume(&self, id: &str, app_handle: &AppHandle) -> Result<MergeQueue> {
+		let queue = self.queue(id)?;
+		let mut queue = queue.lock().unwrap();
+		let (session, directory) = (queue.os_session.clone(), queue.directory.clone());
+		queue.paused_item()?;
+		let conflicts = conflicted_files(&session, &directory)?;
+		if !conflicts.is_empty() {
+			queue.paused_item()?.conflicts = conflicts;
+			return Err(anyhow!("Resolve and stage the conflicted files first"));
+		}
+		if merge_in_progress(&session, &directory)? {
+			run_git(&session, &directory, &["commit", "--no-edit"])?;
+		}
+		let item = queue.paused_item()?;
+		item.state = MergeItemState::Merged;
+		item.conflicts.clear();
+		let branch = item.source.branch.clone();
+		queue.merged(app_handle, &branch);
+		queue.current += 1;
+		queue.advance(app_handle)?;
+		Ok(queue.clone())
+	}
+
+	/// Abandons the paused item's merge and merges the rest.
+	pub fn skip(&self, id: &str, app_handle: &AppHandle) -> Result<MergeQueue> {
+		let queue = self.queue(id)?;
+		let mut queue = queue.lock().unwrap();
+		let (session, directory) = (queue.os_session.clone(), queue.directory.clone());
+		queue.paused_item()?;
+		if merge_in_progress(&session, &directory)? {
+			run_git(&session, &directory, &["merge", "--abort"])?;
+		}
+		let item = queue.paused_item()?;
+		item.state = MergeItemState::Skipped;
+		item.conflicts.clear();
+		queue.current += 1;
+		queue.advance(app_handle)?;
+		Ok(queue.clone())
+	}
+
+	/// Stops the queue and resets the target to where it was before the
+	/// first merge, undoing the merges made so far.
+	pub fn abort(&self, id: &str, app_handle: &AppHandle) -> Result<MergeQueue> {
+		let queue = self.queue(id)?;
+		let mut queue = queue.lock().unwrap();
+		if matches!(
+			queue.state,
+			MergeQueueState::Completed | MergeQueueState::Aborted
+		) {
+			return Err(anyhow!("The merge queue has already finished"));
+		}
+		let (session, directory) = (queue.os_session.clone(), queue.directory.clone());
+		if merge_in_progress(&session, &directory)? {
+			run_git(&session, &directory, &["merge", "--abort"])?;
+		}
+		run_git(
+			&session,
+			&directory,
+			&["reset", "--hard", &queue.start_commit],
+		)?;
+		queue.state = MergeQueueState::Aborted;
+		let _ = app_handle.emit("merge-queue-updated", &*queue);
+		Ok(queue.clone())
+	}
+}
+
+/// Merges canvas branches into the branch checked out in `directory`, or
+/// `branch`, one at a time. Pauses with a `merge-queue-conflict` event when
+/// a merge conflicts; progress is reported with `merge-queue-updated`.
+#[tauri::command]
+pub async fn start_merge_queue(
+	directory: String,
+	branch: Option<String>,
+	sources: Vec<MergeQueueSource>,
+	os_session: OsSession,
+	manager: State<'_, Arc<MergeQueueManager>>,
+	app_handle: AppHandle,
+) -> Result<MergeQueue, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		manager.start(os_session, directory, branch, sources, &app_handle)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_merge_queue(
+	queue_id: String,
+	manager: State<'_, Arc<MergeQueueManager>>,
+) -> Result<MergeQueue, String> {
+	manager.get(&queue_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn continue_merge_queue(
+	queue_id: String,
+	manager: State<'_, Arc<MergeQueueManager>>,
+	app_handle: AppHandle,
+) -> Result<MergeQueue, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.resume(&queue_id, &app_handle))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn skip_merge_queue_item(
+	queue_id: String,
+	manager: State<'_, Arc<MergeQueueManager>>,
+	app_handle: AppHandle,
+) -> Result<MergeQueue, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.skip(&queue_id, &app_handle))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn abort_merge_queue(
+	queue_id: String,
+	manager: State<'_, Arc<MergeQueueManager>>,
+	app_handle: AppHandle,
+) -> Result<MergeQueue, String> {
+	let manager = manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.abort(&queue_id, &app_handle))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
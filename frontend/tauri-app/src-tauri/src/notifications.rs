@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, UserAttentionType};
+use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_store::StoreExt;
+
+/// Store holding the user's notification preferences per category.
+const NOTIFICATIONS_STORE: &str = "notifications.json";
+
+/// What kind of job a notification is about, so users can mute some.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationCategory {
+	FileCopy,
+	GitClone,
+	Agent,
+	Build,
+	Task,
+}
+
+impl NotificationCategory {
+	pub const ALL: [Self; 5] = [
+		Self::FileCopy,
+		Self::GitClone,
+		Self::Agent,
+		Self::Build,
+		Self::Task,
+	];
+
+	fn key(&self) -> &'static str {
+		match self {
+			Self::FileCopy => "fileCopy",
+			Self::GitClone => "gitClone",
+			Self::Agent => "agent",
+			Self::Build => "build",
+			Self::Task => "task",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationPreference {
+	/// Show a native OS notification.
+	pub notify: bool,
+	/// Flash the taskbar entry or bounce the dock icon.
+	pub request_attention: bool,
+	/// Stay quiet while the window has focus, since the user can see the
+	/// outcome in the app.
+	pub only_when_unfocused: bool,
+}
+
+impl Default for NotificationPreference {
+	fn default() -> Self {
+		Self {
+			notify: true,
+			request_attention: true,
+			only_when_unfocused: true,
+		}
+	}
+}
+
+/// A finished or failed job worth telling the user about.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notice {
+	pub category: NotificationCategory,
+	pub title: String,
+	pub body: String,
+	/// Failures ask for attention until the window is focused, rather than
+	/// briefly.
+	#[serde(default)]
+	pub urgent: bool,
+}
+
+pub fn preference(
+	app_handle: &AppHandle,
+	category: NotificationCategory,
+) -> Result<NotificationPreference> {
+	let store = app_handle.store(NOTIFICATIONS_STORE)?;
+	Ok(store
+		.get(category.key())
+		.and_then(|v| serde_json::from_value(v).ok())
+		.unwrap_or_default())
+}
+
+pub fn set_preference(
+	app_handle: &AppHandle,
+	category: NotificationCategory,
+	preference: &NotificationPreference,
+) -> Result<()> {
+	let store = app_handle.store(NOTIFICATIONS_STORE)?;
+	store.set(category.key(), serde_json::to_value(preference)?);
+	store.save()?;
+	Ok(())
+}
+
+/// Tells the user about `notice` as their preferences for its category
+/// allow. Returns whether anything was shown.
+pub fn notify(app_handle: &AppHandle, notice: &Notice) -> Result<bool> {
+	let preference = preference(app_handle, notice.category)?;
+	let window = app_handle.get_webview_window("main");
+	let focused = window
+		.as_ref()
+		.and_then(|w| w.is_focused().ok())
+		.unwrap_or(false);
+	if focused && preference.only_when_unfocused {
+		return Ok(false);
+	}
+
+	let mut shown = false;
+	if preference.notify {
+		app_handle
+			.notification()
+			.builder()
+			.title(&notice.title)
+			.body(&notice.body)
+			.show()?;
+		shown = true;
+	}
+	if preference.request_attention && !focused {
+		if let Some(window) = window {
+			let attention = if notice.urgent {
+				UserAttentionType::Critical
+			} else {
+				UserAttentionType::Informational
+			};
+			window.request_user_attention(Some(attention))?;
+			shown = true;
+		}
+	}
+	Ok(shown)
+}
+
+/// Raises a notification for a job the frontend ran, e.g. a file copy.
+#[tauri::command]
+pub async fn send_notification(
+	notice: Notice,
+	app_handle: AppHandle,
+) -> Result<bool, String> {
+	notify(&app_handle, &notice).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_notification_preferences(
+	app_handle: AppHandle,
+) -> Result<HashMap<NotificationCategory, NotificationPreference>, String> {
+	NotificationCategory::ALL
+		.into_iter()
+		.map(|category| Ok((category, preference(&app_handle, category)?)))
+		.collect::<Result<_>>()
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_notification_preference(
+	category: NotificationCategory,
+	preference: NotificationPreference,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	set_preference(&app_handle, category, &preference).map_err(|e| e.to_string())
+}
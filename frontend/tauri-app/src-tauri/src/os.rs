@@ -2,13 +2,18 @@ use anyhow::{anyhow, Result};
 use portable_pty::CommandBuilder;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use uuid::Uuid;
 use walkdir::{DirEntry, WalkDir};
 
+use crate::jobs::{JobHandle, JobProgress, JOB_CANCELLED};
+use crate::wsl;
+#[cfg(target_os = "windows")]
+use crate::wsl::WslCommand;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OsSessionKind {
 	Local,
@@ -64,9 +69,18 @@ pub struct WslSession {
 pub struct FileNode {
 	pub name: String,
 	pub path: String,
+	/// For symlinks, whether the link points to a directory.
 	pub is_directory: bool,
 	pub children: Option<Vec<FileNode>>,
 	pub extension: Option<String>,
+	#[serde(default)]
+	pub is_symlink: bool,
+	/// Where a symlink points, as stored in the link.
+	#[serde(default)]
+	pub link_target: Option<String>,
+	/// A symlink whose target doesn't exist or loops back on itself.
+	#[serde(default)]
+	pub is_broken_link: bool,
 }
 
 fn is_hidden(entry: &DirEntry) -> bool {
@@ -81,6 +95,15 @@ fn is_hidden_name(name: &str) -> bool {
 	name.starts_with('.')
 }
 
+/// Directories first, then by name.
+pub fn sort_nodes(nodes: &mut [FileNode]) {
+	nodes.sort_by(|a, b| match (a.is_directory, b.is_directory) {
+		(true, false) => std::cmp::Ordering::Less,
+		(false, true) => std::cmp::Ordering::Greater,
+		_ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+	});
+}
+
 impl OsSession {
 	pub fn get_working_directory(&self) -> &str {
 		match self {
@@ -89,11 +112,97 @@ impl OsSession {
 		}
 	}
 
+	/// `path`, as printed by a tool run in the session, resolved against the
+	/// working directory unless it is already absolute.
+	pub fn resolve_path(&self, path: &str) -> String {
+		if path.starts_with('/')
+			|| path.starts_with('\\')
+			|| Path::new(path).is_absolute()
+		{
+			return path.to_string();
+		}
+		let directory = self.get_working_directory();
+		let separator = if directory.contains('\\') { '\\' } else { '/' };
+		format!(
+			"{}{}{}",
+			directory.trim_end_matches(['/', '\\']),
+			separator,
+			path.trim_start_matches("./")
+		)
+	}
+
+	/// Where the working directory can be read from this machine.
+	pub fn host_path(&self) -> PathBuf {
+		self.host_path_for(self.get_working_directory())
+	}
+
+	/// Where `path`, a path inside the session, can be read from this
+	/// machine.
+	pub fn host_path_for(&self, path: &str) -> PathBuf {
+		match self {
+			Self::Local(_) => PathBuf::from(path),
+			Self::Wsl(WslSession { distribution, .. }) => wsl::host_path(distribution, path),
+		}
+	}
+
+	/// Builds a command that runs `program` in `directory` inside the
+	/// session, for tools whose output is read rather than shown in a
+	/// terminal. Arguments added to it are passed to `program`.
+	pub fn build_process_command(&self, program: &str, directory: &str) -> Result<Command> {
+		match self {
+			Self::Local(_) => {
+				let mut cmd = Command::new(program);
+				cmd.current_dir(directory);
+				Ok(cmd)
+			}
+			Self::Wsl(session) => {
+				#[cfg(target_os = "windows")]
+				{
+					// A login shell, so tools installed under ~ are on the PATH
+					let mut cmd = WslCommand::new(&session.distribution, program);
+					cmd.current_dir(directory).login();
+					Ok(cmd.build())
+				}
+				#[cfg(not(target_os = "windows"))]
+				{
+					let _ = (session, program, directory);
+					Err(anyhow!("WSL is only available on Windows"))
+				}
+			}
+		}
+	}
+
 	pub async fn read_directory(&self, path: &str) -> Result<Vec<FileNode>> {
 		match self {
 			Self::Local(_) => self.read_directory_local(path).await,
+			Self::Wsl(session) => self
+				.read_directories_wsl(&[path.to_string()], &session.distribution)
+				.await?
+				.remove(path)
+				.ok_or_else(|| anyhow!("Could not read directory {}", path)),
+		}
+	}
+
+	/// Lists several directories at once, e.g. to restore the expanded
+	/// folders of a file tree; WSL sessions read them all in one call.
+	/// Directories that can't be read are left out.
+	pub async fn read_directories(
+		&self,
+		paths: &[String],
+	) -> Result<HashMap<String, Vec<FileNode>>> {
+		match self {
+			Self::Local(_) => {
+				let mut listings = HashMap::new();
+				for path in paths {
+					if let Ok(nodes) = self.read_directory_local(path).await {
+						listings.insert(path.clone(), nodes);
+					}
+				}
+				Ok(listings)
+			}
 			Self::Wsl(session) => {
-				self.read_directory_wsl(path, &session.distribution).await
+				self.read_directories_wsl(paths, &session.distribution)
+					.await
 			}
 		}
 	}
@@ -109,7 +218,18 @@ impl OsSession {
 			.filter_entry(|e| !is_hidden(e))
 			.filter_map(|e| e.ok())
 		{
-			let metadata = entry.metadata()?;
+			let is_symlink = entry.path_is_symlink();
+			// Linked folders expand like the folder they point to
+			let target_metadata = fs::metadata(entry.path()).ok();
+			let is_broken_link = is_symlink && target_metadata.is_none();
+			let metadata = match target_metadata {
+				Some(metadata) => metadata,
+				None => entry.metadata()?,
+			};
+			let link_target = is_symlink
+				.then(|| fs::read_link(entry.path()).ok())
+				.flatten()
+				.map(|target| target.to_string_lossy().to_string());
 			let file_name = entry.file_name().to_string_lossy().to_string();
 			let file_path = entry.path();
 			let path_str = file_path.to_string_lossy().to_string();
@@ -129,142 +249,96 @@ impl OsSession {
 				is_directory: metadata.is_dir(),
 				children: None,
 				extension,
+				is_symlink,
+				link_target,
+				is_broken_link,
 			};
 
 			nodes.push(node);
 		}
 
-		nodes.sort_by(|a, b| match (a.is_directory, b.is_directory) {
-			(true, false) => std::cmp::Ordering::Less,
-			(false, true) => std::cmp::Ordering::Greater,
-			_ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-		});
-
+		sort_nodes(&mut nodes);
 		Ok(nodes)
 	}
 
-	fn convert_path_to_wsl(path: &str) -> String {
-		// If path already starts with / or ~, it's already a Unix-style path
-		if path.starts_with('/') || path.starts_with('~') {
-			return path.to_string();
-		}
-
-		// Check for Windows drive letters (C: through K:, both uppercase and lowercase)
-		if path.len() >= 2 && path.chars().nth(1) == Some(':') {
-			let drive_char = path.chars().nth(0).unwrap().to_ascii_lowercase();
-			if matches!(drive_char, 'c'..='k') {
-				let rest_of_path = &path[2..];
-				let unix_path = rest_of_path.replace('\\', "/");
-				return format!("/mnt/{}{}", drive_char, unix_path);
-			}
-		}
-
-		// If it doesn't match any pattern, return as-is
-		path.to_string()
-	}
-
+	/// Lists `paths` with a single `find`, which prints one NUL-separated
+	/// record per entry, so any file name survives. Each record holds the
+	/// depth, starting point, type, target type, link target and name. Each directory is also
+	/// printed itself, at depth 0, telling existing empty directories apart
+	/// from missing ones.
 	#[cfg(target_os = "windows")]
-	async fn read_directory_wsl(
+	async fn read_directories_wsl(
 		&self,
-		path: &str,
+		paths: &[String],
 		distribution: &str,
-	) -> Result<Vec<FileNode>> {
-		let wsl_path = Self::convert_path_to_wsl(path);
-
-		// Use WSL to execute ls command and parse the output
-		let output = Command::new("wsl")
-			.arg("-d")
-			.arg(distribution)
-			.arg("ls")
-			.arg("-la")
-			.arg("--color=never")
-			.arg(&wsl_path)
-			.output()?;
-
-		if !output.status.success() {
+	) -> Result<HashMap<String, Vec<FileNode>>> {
+		let wsl_paths: Vec<String> = paths
+			.iter()
+			.map(|path| wsl::to_wsl_path(distribution, path))
+			.collect();
+
+		// -H follows starting points that are symlinks to directories
+		let mut find = WslCommand::new(distribution, "find");
+		find.arg("-H")
+			.args(&wsl_paths)
+			.args(["-maxdepth", "1", "-printf", "%d\\0%H\\0%y\\0%Y\\0%l\\0%f\\0"]);
+		let output = find.output()?;
+		// find carries on past unreadable directories, exiting non-zero
+		if !output.status.success() && output.stdout.is_empty() {
 			let error_msg = String::from_utf8_lossy(&output.stderr);
-			return Err(anyhow!("WSL ls command failed: {}", error_msg));
+			return Err(anyhow!("WSL find command failed: {}", error_msg));
 		}
 
-		let output_str = String::from_utf8_lossy(&output.stdout);
-		let mut nodes = Vec::new();
-
-		for line in output_str.lines().skip(1) {
-			// Skip the "total" line
-			if line.trim().is_empty() {
+		let stdout = String::from_utf8_lossy(&output.stdout);
+		let fields: Vec<&str> = stdout.split('\0').collect();
+		let mut listings: HashMap<String, Vec<FileNode>> = HashMap::new();
+		for record in fields.chunks_exact(6) {
+			let [depth, start, kind, target_kind, link_target, name] =
+				[record[0], record[1], record[2], record[3], record[4], record[5]];
+			let Some(index) = wsl_paths.iter().position(|path| path == start) else {
 				continue;
-			}
-
-			let parts: Vec<&str> = line.split_whitespace().collect();
-			if parts.len() < 9 {
-				continue; // Skip malformed lines
-			}
-
-			let permissions = parts[0];
-			let file_name = parts[8..].join(" "); // Handle filenames with spaces
-
-			// Skip . and .. entries, and hidden files
-			if file_name == "." || file_name == ".." || is_hidden_name(&file_name) {
+			};
+			let nodes = listings.entry(paths[index].clone()).or_default();
+			if depth == "0" || is_hidden_name(name) {
 				continue;
 			}
 
-			let is_directory = permissions.starts_with('d');
-			let is_symlink = permissions.starts_with('l');
-
-			// For symlinks, we might want to resolve them or handle them specially
-			let actual_name = if is_symlink && file_name.contains(" -> ") {
-				file_name
-					.split(" -> ")
-					.next()
-					.unwrap_or(&file_name)
-					.to_string()
-			} else {
-				file_name.clone()
-			};
-
-			// Construct full path (WSL style)
-			let full_path = if wsl_path.ends_with('/') {
-				format!("{}{}", wsl_path, actual_name)
+			// %Y is the type symlinks point to, so linked folders expand;
+			// N and L mark missing targets and loops
+			let is_directory = target_kind == "d";
+			let is_symlink = kind == "l";
+			let extension = if is_directory {
+				None
 			} else {
-				format!("{}/{}", wsl_path, actual_name)
-			};
-
-			let extension = if !is_directory {
-				Path::new(&actual_name)
+				Path::new(name)
 					.extension()
 					.and_then(|ext| ext.to_str())
 					.map(|s| s.to_string())
-			} else {
-				None
 			};
-
-			let node = FileNode {
-				name: actual_name,
-				path: full_path,
+			nodes.push(FileNode {
+				name: name.to_string(),
+				path: format!("{}/{}", start.trim_end_matches('/'), name),
 				is_directory,
 				children: None,
 				extension,
-			};
-
-			nodes.push(node);
+				is_symlink,
+				link_target: is_symlink.then(|| link_target.to_string()),
+				is_broken_link: is_symlink && matches!(target_kind, "N" | "L"),
+			});
 		}
 
-		// Sort: directories first, then by name
-		nodes.sort_by(|a, b| match (a.is_directory, b.is_directory) {
-			(true, false) => std::cmp::Ordering::Less,
-			(false, true) => std::cmp::Ordering::Greater,
-			_ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-		});
-
-		Ok(nodes)
+		for nodes in listings.values_mut() {
+			sort_nodes(nodes);
+		}
+		Ok(listings)
 	}
 
 	#[cfg(not(target_os = "windows"))]
-	async fn read_directory_wsl(
+	async fn read_directories_wsl(
 		&self,
-		_path: &str,
+		_paths: &[String],
 		_distribution: &str,
-	) -> Result<Vec<FileNode>> {
+	) -> Result<HashMap<String, Vec<FileNode>>> {
 		Err(anyhow!("WSL is only available on Windows"))
 	}
 
@@ -356,6 +430,93 @@ impl OsSession {
 
 		Ok(cmd)
 	}
+
+	/// Builds a command that runs `command` through the session's login
+	/// shell in its working directory, for output that is read rather than
+	/// shown in a terminal.
+	pub fn build_shell_command(&self, command: &str) -> Result<Command> {
+		let directory = self.get_working_directory();
+		match self {
+			Self::Wsl(_) => {
+				let mut cmd = self.build_process_command("bash", directory)?;
+				cmd.args(["-lc", command]);
+				Ok(cmd)
+			}
+			Self::Local(_) => {
+				#[cfg(any(target_os = "macos", target_os = "linux"))]
+				{
+					let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+					let mut cmd = self.build_process_command(&shell, directory)?;
+					cmd.args(["-l", "-c", command]);
+					Ok(cmd)
+				}
+				#[cfg(target_os = "windows")]
+				{
+					let git_bash = "C:\\Program Files\\Git\\bin\\bash.exe";
+					if std::path::Path::new(git_bash).exists() {
+						let mut cmd = self.build_process_command(git_bash, directory)?;
+						cmd.args(["--login", "-c", command]);
+						Ok(cmd)
+					} else {
+						let mut cmd = self.build_process_command("powershell.exe", directory)?;
+						cmd.args(["-NoProfile", "-Command", command]);
+						Ok(cmd)
+					}
+				}
+			}
+		}
+	}
+
+	/// Builds a command that runs `command` through the session's shell and
+	/// exits with its status, for running tasks in a terminal.
+	pub fn build_task_command(&self, command: &str) -> Result<CommandBuilder> {
+		let mut cmd = match self {
+			Self::Wsl(session) => {
+				#[cfg(target_os = "windows")]
+				{
+					WslCommand::new(&session.distribution, "bash")
+						.current_dir(&session.working_directory)
+						.args(["-lc", command])
+						.build_pty()
+				}
+				#[cfg(not(target_os = "windows"))]
+				{
+					let _ = session;
+					return Err(anyhow::anyhow!("WSL is only available on Windows"));
+				}
+			}
+			Self::Local(working_directory) => {
+				#[cfg(any(target_os = "macos", target_os = "linux"))]
+				{
+					let shell_path = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+					let mut cmd = CommandBuilder::new(shell_path);
+					cmd.args(["-l", "-c", command]);
+					cmd.cwd(working_directory);
+					cmd
+				}
+				#[cfg(target_os = "windows")]
+				{
+					let git_bash = "C:\\Program Files\\Git\\bin\\bash.exe";
+					let mut cmd = if std::path::Path::new(git_bash).exists() {
+						let mut cmd = CommandBuilder::new(git_bash);
+						cmd.args(["--login", "-c", command]);
+						cmd
+					} else {
+						let mut cmd = CommandBuilder::new("powershell.exe");
+						cmd.args(["-NoProfile", "-Command", command]);
+						cmd
+					};
+					cmd.cwd(working_directory);
+					cmd
+				}
+			}
+		};
+
+		cmd.env("TERM", "xterm-256color");
+		cmd.env("COLORTERM", "truecolor");
+
+		Ok(cmd)
+	}
 }
 
 // Git search functionality
@@ -376,8 +537,11 @@ impl GitSearchManager {
 		}
 	}
 
-	pub fn start_search(&self, os_session_kind: OsSessionKind) -> String {
-		let search_id = Uuid::new_v4().to_string();
+	/// Starts searching in the background as `job`, whose id is the
+	/// search id. Cancelling the job stops the search after the current
+	/// root directory.
+	pub fn start_search(&self, os_session_kind: OsSessionKind, job: JobHandle) -> String {
+		let search_id = job.id().to_string();
 
 		// Initialize empty result
 		{
@@ -396,15 +560,23 @@ impl GitSearchManager {
 		let search_id_clone = search_id.clone();
 
 		thread::spawn(move || {
-			println!("Git Search - Starting search with OS session kind: {:?}", os_session_kind);
+			log::debug!("Git Search - Starting search with OS session kind: {:?}", os_session_kind);
 			let root_dirs = Self::get_root_directories(&os_session_kind);
-			println!("Git Search - Root directories to search: {:?}", root_dirs);
+			log::debug!("Git Search - Root directories to search: {:?}", root_dirs);
 			let mut found_dirs = Vec::new();
 
-			for root_dir in root_dirs {
-				println!("Git Search - Searching in root directory: {}", root_dir);
+			for (index, root_dir) in root_dirs.iter().enumerate() {
+				if job.is_cancelled() {
+					break;
+				}
+				job.progress(JobProgress {
+					message: Some(format!("Searching {}", root_dir)),
+					completed: Some(index as u64),
+					total: Some(root_dirs.len() as u64),
+				});
+				log::debug!("Git Search - Searching in root directory: {}", root_dir);
 				Self::search_git_directories(
-					&root_dir,
+					root_dir,
 					&mut found_dirs,
 					&searches_clone,
 					&search_id_clone,
@@ -412,12 +584,17 @@ impl GitSearchManager {
 				);
 			}
 
-			println!("Git Search - Search complete. Total found: {}", found_dirs.len());
+			log::debug!("Git Search - Search complete. Total found: {}", found_dirs.len());
 			// Mark search as complete
 			let mut searches = searches_clone.lock().unwrap();
 			if let Some(result) = searches.get_mut(&search_id_clone) {
 				result.is_complete = true;
 			}
+			job.finish(if job.is_cancelled() {
+				Err(JOB_CANCELLED.to_string())
+			} else {
+				Ok(Some(serde_json::json!({ "found": found_dirs.len() })))
+			});
 		});
 
 		search_id
@@ -483,7 +660,7 @@ impl GitSearchManager {
 			}
 		};
 		
-		println!("Git Search - Root directories determined: {:?}", roots);
+		log::debug!("Git Search - Root directories determined: {:?}", roots);
 		roots
 	}
 
@@ -504,7 +681,7 @@ impl GitSearchManager {
 				os_session_kind,
 			);
 		} else {
-			println!("Git Search - Searching in local directory: {}", root_path);
+			log::debug!("Git Search - Searching in local directory: {}", root_path);
 			Self::search_git_directories_local(
 				root_path, found_dirs, searches, search_id,
 			);
@@ -585,46 +762,38 @@ impl GitSearchManager {
 			}
 		};
 
-		println!("WSL Search - Root path: {}, Distribution: {}", root_path, distribution);
+		log::debug!("WSL Search - Root path: {}, Distribution: {}", root_path, distribution);
 
 		// Skip path existence check - let find handle non-existent paths
 
 		// Use WSL find command to search for .git directories
 		// Limit depth to 3 levels
-		let find_command = format!(
-			"find '{}' -maxdepth 3 -name '.git' -type d 2>/dev/null",
-			root_path.replace("'", "'\"'\"'")
-		);
+		let mut find_command = WslCommand::new(&distribution, "find");
+		find_command.args([root_path, "-maxdepth", "3", "-name", ".git", "-type", "d"]);
 
-		println!("WSL Search - Executing command: wsl -d {} bash -c '{}'", distribution, find_command);
+		log::debug!("WSL Search - Executing command: {:?}", find_command);
 
-		let output = Command::new("wsl")
-			.arg("-d")
-			.arg(&distribution)
-			.arg("bash")
-			.arg("-c")
-			.arg(&find_command)
-			.output();
+		let output = find_command.output();
 
 		match output {
 			Ok(output) => {
-				println!("WSL Search - Command exit status: {}", output.status);
-				println!("WSL Search - Stdout: {}", String::from_utf8_lossy(&output.stdout));
-				println!("WSL Search - Stderr: {}", String::from_utf8_lossy(&output.stderr));
+				log::debug!("WSL Search - Command exit status: {}", output.status);
+				log::debug!("WSL Search - Stdout: {}", String::from_utf8_lossy(&output.stdout));
+				log::debug!("WSL Search - Stderr: {}", String::from_utf8_lossy(&output.stderr));
 
 				if output.status.success() {
 					let output_str = String::from_utf8_lossy(&output.stdout);
 					let lines: Vec<&str> = output_str.lines().collect();
-					println!("WSL Search - Found {} lines of output", lines.len());
+					log::debug!("WSL Search - Found {} lines of output", lines.len());
 
 					for line in lines {
 						let git_path = line.trim();
-						println!("WSL Search - Processing line: '{}'", git_path);
+						log::debug!("WSL Search - Processing line: '{}'", git_path);
 						if !git_path.is_empty() && git_path.ends_with("/.git") {
 							// Get the parent directory (remove /.git)
 							let repo_path = &git_path[..git_path.len() - 5];
 							let normalized_path = repo_path.replace('\\', "/");
-							println!("WSL Search - Found git repo: {}", normalized_path);
+							log::debug!("WSL Search - Found git repo: {}", normalized_path);
 
 							found_dirs.push(normalized_path.clone());
 
@@ -636,11 +805,11 @@ impl GitSearchManager {
 						}
 					}
 				} else {
-					println!("WSL Search - Command failed with status: {}", output.status);
+					log::warn!("WSL Search - Command failed with status: {}", output.status);
 				}
 			}
 			Err(e) => {
-				println!("WSL Search - Failed to execute command: {}", e);
+				log::warn!("WSL Search - Failed to execute command: {}", e);
 			}
 		}
 	}
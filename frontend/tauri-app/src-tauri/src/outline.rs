@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tree_sitter::{Node, Parser};
+
+use crate::os::OsSession;
+
+/// Files larger than this are almost always generated or minified.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// A range of lines that can be collapsed, as in an LSP `FoldingRange`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FoldRange {
+	/// Zero-based line the range starts on, which stays visible.
+	pub start_line: u32,
+	/// Zero-based last line, usually holding the closing bracket.
+	pub end_line: u32,
+	/// `comment` or `imports`, otherwise a region of code.
+	pub kind: Option<String>,
+}
+
+/// A definition in the outline, with what's defined inside it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineItem {
+	pub name: String,
+	/// `function`, `method`, `class`, `struct`, `enum`, `interface`,
+	/// `trait`, `impl`, `module`, `type`, `constant` or `macro`.
+	pub kind: String,
+	/// Zero-based lines of the whole definition.
+	pub start_line: u32,
+	pub end_line: u32,
+	/// Zero-based line and UTF-16 column of the name, as LSP positions use.
+	pub line: u32,
+	pub column: u32,
+	pub children: Vec<OutlineItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOutline {
+	pub language: String,
+	pub folds: Vec<FoldRange>,
+	pub symbols: Vec<OutlineItem>,
+}
+
+fn grammar_for(path: &str) -> Option<(&'static str, tree_sitter::Language)> {
+	let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+	Some(match extension.as_str() {
+		"rs" => ("rust", tree_sitter_rust::LANGUAGE.into()),
+		"js" | "mjs" | "cjs" | "jsx" => {
+			("javascript", tree_sitter_javascript::LANGUAGE.into())
+		}
+		"ts" | "mts" | "cts" => (
+			"typescript",
+			tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+		),
+		"tsx" => ("typescript", tree_sitter_typescript::LANGUAGE_TSX.into()),
+		"py" | "pyi" => ("python", tree_sitter_python::LANGUAGE.into()),
+		"go" => ("go", tree_sitter_go::LANGUAGE.into()),
+		_ => return None,
+	})
+}
+
+fn text<'a>(node: Node, source: &'a str) -> &'a str {
+	&source[node.byte_range()]
+}
+
+/// The outline kind of a definition node, and the node holding its name.
+fn definition<'a>(node: Node<'a>, source: &str) -> Option<(&'static str, Node<'a>)> {
+	let name = node.child_by_field_name("name");
+	let kind = match node.kind() {
+		"function_item"
+		| "function_declaration"
+		| "generator_function_declaration"
+		| "function_definition"
+		| "function_signature_item" => "function",
+		"method_definition" | "method_declaration" | "method_signature" => "method",
+		"class_declaration"
+		| "abstract_class_declaration"
+		| "class_definition"
+		| "class" => "class",
+		"struct_item" | "union_item" => "struct",
+		"enum_item" | "enum_declaration" => "enum",
+		"interface_declaration" => "interface",
+		"trait_item" => "trait",
+		"mod_item" | "internal_module" | "module" => "module",
+		"type_item" | "type_alias_declaration" => "type",
+		"const_item" | "static_item" => "constant",
+		"macro_definition" => "macro",
+		// Named after what's implemented, e.g. `Display for Session`
+		"impl_item" => return Some(("impl", node.child_by_field_name("type")?)),
+		"type_spec" => match node.child_by_field_name("type")?.kind() {
+			"struct_type" => "struct",
+			"interface_type" => "interface",
+			_ => "type",
+		},
+		// `const handler = () => ...`
+		"variable_declarator" => match node.child_by_field_name("value")?.kind() {
+			"arrow_function" | "function_expression" | "function" => "function",
+			"class" => "class",
+			_ => return None,
+		},
+		_ => return None,
+	};
+	let name = name?;
+	// An anonymous `class` expression is named by its declarator
+	if text(name, source).is_empty() {
+		return None;
+	}
+	Some((kind, name))
+}
+
+fn impl_name(node: Node, source: &str) -> String {
+	let implemented = node
+		.child_by_field_name("type")
+		.map(|n| text(n, source))
+		.unwrap_or_default();
+	match node.child_by_field_name("trait") {
+		Some(trait_node) => format!("{} for {}", text(trait_node, source), implemented),
+		None => implemented.to_string(),
+	}
+}
+
+/// The zero-based line `node` ends on. Some grammars' line comments take
+/// in their newline, which would otherwise put them on the next line.
+fn end_line(node: Node) -> u32 {
+	let end = node.end_position();
+	if end.column == 0 && end.row > node.start_position().row {
+		end.row as u32 - 1
+	} else {
+		end.row as u32
+	}
+}
+
+/// The UTF-16 column of `node`'s start.
+fn utf16_column(node: Node, source: &str) -> u32 {
+	let start = node.start_byte();
+	let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+	source[line_start..start].encode_utf16().count() as u32
+}
+
+fn collect_symbols(
+	node: Node,
+	source: &str,
+	in_type: bool,
+	symbols: &mut Vec<OutlineItem>,
+) {
+	let mut cursor = node.walk();
+	for child in node.named_children(&mut cursor) {
+		let Some((kind, name_node)) = definition(child, source) else {
+			collect_symbols(child, source, in_type, symbols);
+			continue;
+		};
+		let kind = if kind == "function" && in_type {
+			"method"
+		} else {
+			kind
+		};
+		let name = if kind == "impl" {
+			impl_name(child, source)
+		} else {
+			text(name_node, source).to_string()
+		};
+		let mut children = Vec::new();
+		let is_type = matches!(
+			kind,
+			"class" | "struct" | "enum" | "interface" | "trait" | "impl" | "module"
+		);
+		// Functions inside functions are rarely worth listing
+		if is_type {
+			collect_symbols(child, source, kind != "module", &mut children);
+		}
+		symbols.push(OutlineItem {
+			name,
+			kind: kind.to_string(),
+			start_line: child.start_position().row as u32,
+			end_line: end_line(child),
+			line: name_node.start_position().row as u32,
+			column: utf16_column(name_node, source),
+			children,
+		});
+	}
+}
+
+fn fold_kind(node: Node) -> Option<String> {
+	let kind = node.kind();
+	if kind.contains("comment") {
+		Some("comment".to_string())
+	} else if kind.contains("import") || kind == "use_declaration" {
+		Some("imports".to_string())
+	} else {
+		None
+	}
+}
+
+/// Every named node spanning several lines can fold; of those starting on
+/// the same line, only the outermost is kept.
+fn collect_folds(node: Node, folds: &mut BTreeMap<u32, FoldRange>) {
+	let mut cursor = node.walk();
+	for child in node.named_children(&mut cursor) {
+		let start_line = child.start_position().row as u32;
+		let end_line = end_line(child);
+		if end_line > start_line {
+			folds.entry(start_line).or_insert_with(|| FoldRange {
+				start_line,
+				end_line,
+				kind: fold_kind(child),
+			});
+		}
+		collect_folds(child, folds);
+	}
+}
+
+/// Folds runs of consecutive line comments and imports, which are each
+/// single-line nodes.
+fn collect_runs(root: Node, folds: &mut BTreeMap<u32, FoldRange>) {
+	let mut cursor = root.walk();
+	let mut run: Option<(String, u32, u32)> = None;
+	let close = |run: Option<(String, u32, u32)>,
+	             folds: &mut BTreeMap<u32, FoldRange>| {
+		if let Some((kind, start_line, end_line)) = run {
+			if end_line > start_line {
+				folds.entry(start_line).or_insert(FoldRange {
+					start_line,
+					end_line,
+					kind: Some(kind),
+				});
+			}
+		}
+	};
+	for child in root.named_children(&mut cursor) {
+		let start = child.start_position().row as u32;
+		let end = end_line(child);
+		match (fold_kind(child), run.as_mut()) {
+			(Some(kind), Some((run_kind, _, run_end)))
+				if *run_kind == kind && start <= *run_end + 1 =>
+			{
+				*run_end = end;
+			}
+			(Some(kind), _) => {
+				close(run.take(), folds);
+				run = Some((kind, start, end));
+			}
+			(None, _) => close(run.take(), folds),
+		}
+	}
+	close(run, folds);
+}
+
+/// Parses `source`, the contents of `path`, into fold ranges and an outline.
+pub fn outline(path: &str, source: &str) -> Result<FileOutline> {
+	let (language, grammar) =
+		grammar_for(path).ok_or_else(|| anyhow!("No outline support for {}", path))?;
+	let mut parser = Parser::new();
+	parser.set_language(&grammar)?;
+	let tree = parser
+		.parse(source, None)
+		.ok_or_else(|| anyhow!("Failed to parse {}", path))?;
+	let root = tree.root_node();
+
+	let mut folds = BTreeMap::new();
+	collect_runs(root, &mut folds);
+	collect_folds(root, &mut folds);
+	let mut symbols = Vec::new();
+	collect_symbols(root, source, false, &mut symbols);
+	Ok(FileOutline {
+		language: language.to_string(),
+		folds: folds.into_values().collect(),
+		symbols,
+	})
+}
+
+/// Fold ranges and a structural outline of a Rust, JavaScript, TypeScript,
+/// Python or Go file, for when no language server provides them. `content`
+/// is the editor's unsaved text; the file is read when it's missing.
+#[tauri::command]
+pub async fn get_file_outline(
+	os_session: OsSession,
+	path: String,
+	content: Option<String>,
+) -> Result<FileOutline, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		let source = match content {
+			Some(content) => content,
+			None => {
+				let host_path = os_session.host_path_for(&path);
+				let size = fs::metadata(&host_path)?.len();
+				if size > MAX_FILE_BYTES {
+					return Err(anyhow!("{} is too large to outline", path));
+				}
+				String::from_utf8_lossy(&fs::read(&host_path)?).to_string()
+			}
+		};
+		outline(&path, &source)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
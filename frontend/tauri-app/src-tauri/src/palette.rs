@@ -0,0 +1,576 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::broadcast::error::RecvError;
+use walkdir::WalkDir;
+
+use crate::file_watcher::{FileChange, FileChangeKind, FileWatcher};
+use crate::index_manager::{is_ignored_dir, IndexManager, IGNORED_DIRS};
+
+/// Store holding how often and how recently palette items were used.
+const PALETTE_STORE: &str = "palette.json";
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+/// Usage records kept; the least recently used are dropped first.
+const MAX_HISTORY: usize = 1000;
+/// Files listed per workspace, to bound memory on huge trees.
+const MAX_FILES: usize = 200_000;
+/// A use counts half as much after this long.
+const FRECENCY_HALF_LIFE_HOURS: f64 = 72.0;
+/// How much frecency weighs against the quality of the match.
+const FRECENCY_WEIGHT: f64 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PaletteItemKind {
+	File,
+	Symbol,
+	/// A command the user ran from the palette before.
+	Command,
+	/// A frontend action registered with `register_palette_actions`.
+	Action,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaletteAction {
+	pub id: String,
+	pub title: String,
+	pub category: Option<String>,
+	/// Shown next to the title, e.g. `CmdOrCtrl+Shift+P`.
+	pub keybinding: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaletteQuery {
+	pub query: String,
+	/// Workspace whose files and symbols are searched.
+	pub root: Option<String>,
+	/// Kinds to include, all by default.
+	pub kinds: Option<Vec<PaletteItemKind>>,
+	pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaletteItem {
+	pub kind: PaletteItemKind,
+	/// Pass back to `record_palette_use` when the item is picked. The
+	/// absolute path for files.
+	pub id: String,
+	pub title: String,
+	/// Directory of a file, file of a symbol or category of an action.
+	pub detail: Option<String>,
+	/// Zero-based, for symbols.
+	pub line: Option<u32>,
+	pub column: Option<u32>,
+	pub keybinding: Option<String>,
+	/// Character positions in `title` that matched the query, to highlight.
+	pub matches: Vec<usize>,
+	pub score: f64,
+}
+
+/// How often and how recently an item was picked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Usage {
+	kind: PaletteItemKind,
+	id: String,
+	title: String,
+	count: u32,
+	/// Milliseconds since the epoch.
+	last_used: i64,
+}
+
+impl Usage {
+	/// Use count decayed by age, so items used a lot long ago fade out.
+	fn frecency(&self, now: i64) -> f64 {
+		let hours = (now - self.last_used).max(0) as f64 / 3_600_000.0;
+		self.count as f64 * 0.5f64.powf(hours / FRECENCY_HALF_LIFE_HOURS)
+	}
+}
+
+fn is_boundary(previous: Option<char>, current: char) -> bool {
+	match previous {
+		None => true,
+		Some(p) => !p.is_alphanumeric() || (p.is_lowercase() && current.is_uppercase()),
+	}
+}
+
+/// Scores `candidate` against the lowercased `query` when every query
+/// character appears in order. Consecutive matches and matches at word
+/// starts score higher; unmatched characters cost a little. Returns the
+/// score and the matched character positions.
+fn fuzzy_match(candidate: &str, query: &str) -> Option<(f64, Vec<usize>)> {
+	if query.is_empty() {
+		return Some((0.0, Vec::new()));
+	}
+	let chars: Vec<char> = candidate.chars().collect();
+	let mut query_chars = query.chars().peekable();
+	let mut positions = Vec::new();
+	let mut score = 0.0;
+	let mut previous_match: Option<usize> = None;
+	for (i, &c) in chars.iter().enumerate() {
+		let Some(&q) = query_chars.peek() else {
+			break;
+		};
+		if !c.to_lowercase().eq(q.to_lowercase()) {
+			continue;
+		}
+		score += 1.0;
+		if previous_match.is_some_and(|p| p + 1 == i) {
+			score += 2.0;
+		}
+		if is_boundary(i.checked_sub(1).map(|p| chars[p]), c) {
+			score += 3.0;
+		}
+		positions.push(i);
+		previous_match = Some(i);
+		query_chars.next();
+	}
+	if query_chars.peek().is_some() {
+		return None;
+	}
+	let unmatched = chars.len() - positions.len();
+	score -= unmatched as f64 * 0.05 + positions[0] as f64 * 0.1;
+	Some((score, positions))
+}
+
+/// Files of an open workspace, relative to its root.
+struct FileList {
+	root: PathBuf,
+	files: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl FileList {
+	fn scan(root: PathBuf) -> Self {
+		let files = WalkDir::new(&root)
+			.into_iter()
+			.filter_entry(|e| !is_ignored_dir(e))
+			.filter_map(|e| e.ok())
+			.filter(|e| e.file_type().is_file())
+			.filter_map(|e| e.path().strip_prefix(&root).ok().map(Path::to_path_buf))
+			.take(MAX_FILES)
+			.collect();
+		Self {
+			root,
+			files: Mutex::new(files),
+		}
+	}
+
+	/// The path relative to the root, unless it's outside or ignored.
+	fn relative(&self, path: &Path) -> Option<PathBuf> {
+		let relative = path.strip_prefix(&self.root).ok()?;
+		let ignored = relative
+			.parent()
+			.into_iter()
+			.flat_map(Path::components)
+			.any(|c| {
+				let Component::Normal(name) = c else {
+					return false;
+				};
+				name.to_str().is_some_and(|name| {
+					name.starts_with('.') || IGNORED_DIRS.contains(&name)
+				})
+			});
+		(!ignored).then(|| relative.to_path_buf())
+	}
+
+	fn apply(&self, change: &FileChange) {
+		let Some(relative) = self.relative(&change.path) else {
+			return;
+		};
+		let mut files = self.files.lock().unwrap();
+		match change.kind {
+			FileChangeKind::Created
+				if change.path.is_file() && files.len() < MAX_FILES =>
+			{
+				files.insert(relative);
+			}
+			FileChangeKind::Removed => {
+				// A removed directory takes its files with it
+				files.retain(|file| !file.starts_with(&relative));
+			}
+			_ => {}
+		}
+	}
+}
+
+/// Answers command palette queries from one merged index of workspace
+/// files, symbols, previously run commands and registered actions, ranked
+/// by match quality and frecency.
+pub struct PaletteManager {
+	app_handle: AppHandle,
+	watcher: Arc<FileWatcher>,
+	actions: Mutex<Vec<PaletteAction>>,
+	history: Mutex<Vec<Usage>>,
+	workspaces: Mutex<HashMap<PathBuf, Arc<FileList>>>,
+}
+
+impl PaletteManager {
+	pub fn new(app_handle: AppHandle, watcher: Arc<FileWatcher>) -> Arc<Self> {
+		let history = app_handle
+			.store(PALETTE_STORE)
+			.ok()
+			.and_then(|store| store.get("history"))
+			.and_then(|v| serde_json::from_value(v).ok())
+			.unwrap_or_default();
+		let manager = Arc::new(Self {
+			app_handle,
+			watcher,
+			actions: Mutex::new(Vec::new()),
+			history: Mutex::new(history),
+			workspaces: Mutex::new(HashMap::new()),
+		});
+		manager.start_watching();
+		manager
+	}
+
+	fn start_watching(self: &Arc<Self>) {
+		let mut changes = self.watcher.subscribe();
+		let manager = Arc::downgrade(self);
+		tauri::async_runtime::spawn(async move {
+			loop {
+				let change = match changes.recv().await {
+					Ok(change) => change,
+					Err(RecvError::Lagged(_)) => {
+						// Missed events; list the files again
+						let Some(manager) = manager.upgrade() else {
+							break;
+						};
+						let _ = tauri::async_runtime::spawn_blocking(move || {
+							manager.rescan_all()
+						})
+						.await;
+						continue;
+					}
+					Err(RecvError::Closed) => break,
+				};
+				let Some(manager) = manager.upgrade() else {
+					break;
+				};
+				let workspaces: Vec<_> = manager
+					.workspaces
+					.lock()
+					.unwrap()
+					.values()
+					.cloned()
+					.collect();
+				for workspace in workspaces {
+					workspace.apply(&change);
+				}
+			}
+		});
+	}
+
+	fn rescan_all(&self) {
+		let roots: Vec<_> = self.workspaces.lock().unwrap().keys().cloned().collect();
+		for root in roots {
+			let list = Arc::new(FileList::scan(root.clone()));
+			let mut workspaces = self.workspaces.lock().unwrap();
+			// Unless it was closed meanwhile
+			if let Some(existing) = workspaces.get_mut(&root) {
+				*existing = list;
+			}
+		}
+	}
+
+	/// The file list of `root`, listing and watching it on first use.
+	fn workspace(&self, root: &str) -> Result<Arc<FileList>> {
+		let root = fs::canonicalize(root)
+			.with_context(|| format!("Failed to open workspace {}", root))?;
+		if let Some(list) = self.workspaces.lock().unwrap().get(&root) {
+			return Ok(list.clone());
+		}
+		let list = Arc::new(FileList::scan(root.clone()));
+		let mut workspaces = self.workspaces.lock().unwrap();
+		if let Some(existing) = workspaces.get(&root) {
+			return Ok(existing.clone());
+		}
+		self.watcher.watch(&root, true)?;
+		workspaces.insert(root, list.clone());
+		Ok(list)
+	}
+
+	/// Stops listing and watching `root`.
+	pub fn close_workspace(&self, root: &str) -> Result<()> {
+		let root = fs::canonicalize(root).unwrap_or_else(|_| PathBuf::from(root));
+		if self.workspaces.lock().unwrap().remove(&root).is_some() {
+			self.watcher.unwatch(&root)?;
+		}
+		Ok(())
+	}
+
+	/// Adds actions, replacing registered ones with the same id.
+	pub fn register_actions(&self, new_actions: Vec<PaletteAction>) {
+		let mut actions = self.actions.lock().unwrap();
+		actions.retain(|action| !new_actions.iter().any(|new| new.id == action.id));
+		actions.extend(new_actions);
+	}
+
+	pub fn action_ids(&self) -> Vec<String> {
+		self.actions
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|action| action.id.clone())
+			.collect()
+	}
+
+	pub fn unregister_actions(&self, ids: &[String]) {
+		self.actions
+			.lock()
+			.unwrap()
+			.retain(|action| !ids.contains(&action.id));
+	}
+
+	/// Records that an item was picked, which ranks it higher from then on.
+	/// Commands are remembered by their title.
+	pub fn record_use(&self, kind: PaletteItemKind, id: &str, title: &str) -> Result<()> {
+		let now = chrono::Utc::now().timestamp_millis();
+		let mut history = self.history.lock().unwrap();
+		match history.iter_mut().find(|u| u.kind == kind && u.id == id) {
+			Some(usage) => {
+				usage.count += 1;
+				usage.last_used = now;
+				usage.title = title.to_string();
+			}
+			None => history.push(Usage {
+				kind,
+				id: id.to_string(),
+				title: title.to_string(),
+				count: 1,
+				last_used: now,
+			}),
+		}
+		if history.len() > MAX_HISTORY {
+			history.sort_by_key(|u| std::cmp::Reverse(u.last_used));
+			history.truncate(MAX_HISTORY);
+		}
+
+		let store = self.app_handle.store(PALETTE_STORE)?;
+		store.set("history", serde_json::to_value(&*history)?);
+		store.save()?;
+		Ok(())
+	}
+
+	/// Searches everything `query.kinds` asks for and returns the best
+	/// items first. An empty query lists recently used items and actions.
+	pub fn query(
+		&self,
+		query: &PaletteQuery,
+		index: &IndexManager,
+	) -> Result<Vec<PaletteItem>> {
+		let text = query.query.trim().to_lowercase();
+		let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+		let wants = |kind| {
+			query
+				.kinds
+				.as_ref()
+				.is_none_or(|kinds| kinds.contains(&kind))
+		};
+		let now = chrono::Utc::now().timestamp_millis();
+		let history = self.history.lock().unwrap().clone();
+		let frecency: HashMap<(PaletteItemKind, &str), f64> = history
+			.iter()
+			.map(|usage| ((usage.kind, usage.id.as_str()), usage.frecency(now)))
+			.collect();
+		let boost = |kind, id: &str| {
+			frecency
+				.get(&(kind, id))
+				.map(|f| FRECENCY_WEIGHT * f.ln_1p())
+				.unwrap_or(0.0)
+		};
+
+		let mut items = Vec::new();
+		if wants(PaletteItemKind::Action) {
+			for action in self.actions.lock().unwrap().iter() {
+				let Some((score, matches)) = fuzzy_match(&action.title, &text) else {
+					continue;
+				};
+				items.push(PaletteItem {
+					kind: PaletteItemKind::Action,
+					id: action.id.clone(),
+					title: action.title.clone(),
+					detail: action.category.clone(),
+					line: None,
+					column: None,
+					keybinding: action.keybinding.clone(),
+					matches,
+					score: score + boost(PaletteItemKind::Action, &action.id),
+				});
+			}
+		}
+
+		if wants(PaletteItemKind::Command) {
+			for usage in history
+				.iter()
+				.filter(|u| u.kind == PaletteItemKind::Command)
+			{
+				let Some((score, matches)) = fuzzy_match(&usage.title, &text) else {
+					continue;
+				};
+				items.push(PaletteItem {
+					kind: PaletteItemKind::Command,
+					id: usage.id.clone(),
+					title: usage.title.clone(),
+					detail: None,
+					line: None,
+					column: None,
+					keybinding: None,
+					matches,
+					score: score + boost(PaletteItemKind::Command, &usage.id),
+				});
+			}
+		}
+
+		if let Some(root) = &query.root {
+			if wants(PaletteItemKind::File) {
+				let list = self.workspace(root)?;
+				let files = list.files.lock().unwrap();
+				for relative in files.iter() {
+					let id = list.root.join(relative).to_string_lossy().to_string();
+					// Nothing to rank an empty query by but past use
+					if text.is_empty()
+						&& !frecency.contains_key(&(PaletteItemKind::File, id.as_str()))
+					{
+						continue;
+					}
+					let name = relative
+						.file_name()
+						.map(|n| n.to_string_lossy().to_string())
+						.unwrap_or_default();
+					let path = relative.to_string_lossy();
+					// Matching the name alone is worth more than the whole path
+					let (score, matches) = match fuzzy_match(&name, &text) {
+						Some((score, matches)) => (score + 2.0, matches),
+						None => match fuzzy_match(&path, &text) {
+							Some((score, _)) => (score, Vec::new()),
+							None => continue,
+						},
+					};
+					let score = score + boost(PaletteItemKind::File, &id);
+					items.push(PaletteItem {
+						kind: PaletteItemKind::File,
+						title: name,
+						detail: relative
+							.parent()
+							.map(|p| p.to_string_lossy().to_string())
+							.filter(|p| !p.is_empty()),
+						id,
+						line: None,
+						column: None,
+						keybinding: None,
+						matches,
+						score,
+					});
+				}
+			}
+
+			if wants(PaletteItemKind::Symbol) && !text.is_empty() {
+				for found in index.search_symbols(&text, Some(root.as_str()), Some(limit))
+				{
+					let Some((score, matches)) = fuzzy_match(&found.symbol.name, &text)
+					else {
+						continue;
+					};
+					let id = format!("{}:{}", found.path, found.symbol.line);
+					let score = score + boost(PaletteItemKind::Symbol, &id);
+					items.push(PaletteItem {
+						kind: PaletteItemKind::Symbol,
+						id,
+						title: found.symbol.name,
+						detail: Some(
+							Path::new(&found.path)
+								.strip_prefix(root)
+								.map(|p| p.to_string_lossy().to_string())
+								.unwrap_or(found.path),
+						),
+						line: Some(found.symbol.line),
+						column: Some(found.symbol.column),
+						keybinding: None,
+						matches,
+						score,
+					});
+				}
+			}
+		}
+
+		if text.is_empty() {
+			// Only what was used, plus every action in a stable order
+			items.retain(|item| {
+				item.kind == PaletteItemKind::Action
+					|| frecency.contains_key(&(item.kind, item.id.as_str()))
+			});
+		}
+		items.sort_by(|a, b| {
+			b.score
+				.total_cmp(&a.score)
+				.then(a.title.len().cmp(&b.title.len()))
+				.then(a.title.cmp(&b.title))
+		});
+		items.truncate(limit);
+		Ok(items)
+	}
+}
+
+/// Answers a palette query with files, symbols, commands and actions in
+/// one list, best first.
+#[tauri::command]
+pub async fn palette_query(
+	query: PaletteQuery,
+	palette: State<'_, Arc<PaletteManager>>,
+	index: State<'_, Arc<IndexManager>>,
+) -> Result<Vec<PaletteItem>, String> {
+	let (palette, index) = (palette.inner().clone(), index.inner().clone());
+	tauri::async_runtime::spawn_blocking(move || palette.query(&query, &index))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn register_palette_actions(
+	actions: Vec<PaletteAction>,
+	palette: State<'_, Arc<PaletteManager>>,
+) -> Result<(), String> {
+	palette.register_actions(actions);
+	Ok(())
+}
+
+#[tauri::command]
+pub async fn unregister_palette_actions(
+	ids: Vec<String>,
+	palette: State<'_, Arc<PaletteManager>>,
+) -> Result<(), String> {
+	palette.unregister_actions(&ids);
+	Ok(())
+}
+
+/// Called when the user picks an item, so it ranks higher next time.
+#[tauri::command]
+pub async fn record_palette_use(
+	kind: PaletteItemKind,
+	id: String,
+	title: String,
+	palette: State<'_, Arc<PaletteManager>>,
+) -> Result<(), String> {
+	palette
+		.record_use(kind, &id, &title)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn close_palette_workspace(
+	root: String,
+	palette: State<'_, Arc<PaletteManager>>,
+) -> Result<(), String> {
+	palette.close_workspace(&root).map_err(|e| e.to_string())
+}
@@ -0,0 +1,945 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Listener, Manager, State};
+use tauri_plugin_dialog::{
+	DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+use tokio::sync::broadcast::error::RecvError;
+use wasmtime::{
+	AsContext, Caller, Config, Engine, Instance, Linker, Memory, Module, Store,
+	StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::diagnostics::ProblemsStore;
+use crate::file_watcher::{FileChange, FileChangeKind, FileWatcher};
+use crate::os::OsSession;
+use crate::palette::{PaletteAction, PaletteManager};
+use crate::util::write_private;
+
+/// The plugins the user enabled, with the capabilities they granted, live in
+/// this directory of the app's local data. Only Rust writes it: the webview
+/// can write stores through the store plugin, and plugins through fs.
+const GRANTS_DIR: &str = "plugin-grants";
+const GRANTS_FILE: &str = "enabled-plugins";
+/// First line of the grants file, followed by the granted capabilities as a
+/// JSON object keyed by plugin id. A file without it is ignored.
+const GRANTS_HEADER: &str = "# Plugins enabled by the user, written by the app";
+const ENABLE: &str = "Enable";
+const CANCEL: &str = "Cancel";
+const MANIFEST_FILE: &str = "plugin.json";
+/// Largest linear memory a plugin may grow to.
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+/// Instructions, roughly, a plugin may run per message before it is stopped.
+const FUEL_PER_CALL: u64 = 2_000_000_000;
+/// Largest file a plugin may read.
+const MAX_READ_BYTES: u64 = 16 * 1024 * 1024;
+/// Largest request or reply the host copies out of plugin memory.
+const MAX_MESSAGE_BYTES: usize = 32 * 1024 * 1024;
+/// Palette action ids of plugin commands start with this, followed by
+/// `<plugin id>.<command id>`.
+pub const ACTION_PREFIX: &str = "plugin.";
+
+/// What a plugin may do beyond answering its own commands and data sources.
+/// Granted when the user enables the plugin, as listed in its manifest then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PluginCapability {
+	/// Read files of the workspace the plugin was invoked for.
+	ReadFiles,
+	/// Create and overwrite files of that workspace.
+	WriteFiles,
+	ListDirectories,
+	/// Read the Problems feed and receive `problemsUpdated` events.
+	Diagnostics,
+	/// Receive `fileChanged` events for watched workspaces.
+	FileEvents,
+}
+
+impl PluginCapability {
+	/// What the capability allows, as the user is asked to grant it.
+	fn description(self) -> &'static str {
+		match self {
+			Self::ReadFiles => "Read files of your workspaces",
+			Self::WriteFiles => "Create and overwrite files of your workspaces",
+			Self::ListDirectories => "List the folders of your workspaces",
+			Self::Diagnostics => "Read the Problems panel",
+			Self::FileEvents => "Follow changes to files of your workspaces",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginCommand {
+	pub id: String,
+	pub title: String,
+}
+
+/// Data a plugin computes for a panel, fetched with
+/// `query_plugin_data_source`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginDataSource {
+	pub id: String,
+	pub title: String,
+}
+
+/// `plugin.json`, next to the module in the plugin's directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginManifest {
+	pub id: String,
+	pub name: String,
+	pub version: String,
+	#[serde(default)]
+	pub description: Option<String>,
+	/// The WebAssembly module, relative to the plugin's directory.
+	#[serde(default = "default_main")]
+	pub main: String,
+	#[serde(default)]
+	pub capabilities: Vec<PluginCapability>,
+	#[serde(default)]
+	pub commands: Vec<PluginCommand>,
+	#[serde(default)]
+	pub data_sources: Vec<PluginDataSource>,
+}
+
+fn default_main() -> String {
+	"plugin.wasm".to_string()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginInfo {
+	#[serde(flatten)]
+	pub manifest: PluginManifest,
+	pub directory: String,
+	pub enabled: bool,
+	/// Why the plugin failed to load or its last call failed.
+	pub error: Option<String>,
+}
+
+/// Payload of the `plugin-notification` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginNotification {
+	pub plugin_id: String,
+	pub message: String,
+}
+
+/// Requests a plugin sends through the `ariana.call` import.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum HostRequest {
+	/// The workspace of the current call, if any.
+	GetWorkspace,
+	ReadFile {
+		path: String,
+	},
+	WriteFile {
+		path: String,
+		content: String,
+	},
+	ListDirectory {
+		path: String,
+	},
+	GetDiagnostics,
+	/// Shows `message` to the user.
+	Notify {
+		message: String,
+	},
+}
+
+/// What a plugin's store carries into host calls.
+struct HostState {
+	app_handle: AppHandle,
+	plugin_id: String,
+	capabilities: Vec<PluginCapability>,
+	/// The workspace of the message being handled. File operations are
+	/// confined to it and fail without one.
+	session: Option<OsSession>,
+	limits: StoreLimits,
+}
+
+impl HostState {
+	fn require(&self, capability: PluginCapability) -> Result<()> {
+		if self.capabilities.contains(&capability) {
+			Ok(())
+		} else {
+			bail!("The plugin did not declare the {:?} capability", capability)
+		}
+	}
+
+	fn session(&self) -> Result<&OsSession> {
+		self.session
+			.as_ref()
+			.ok_or_else(|| anyhow!("No workspace is associated with this call"))
+	}
+
+	/// Resolves `path` against the workspace, refusing anything outside it,
+	/// symlinks included.
+	fn resolve(&self, path: &str) -> Result<PathBuf> {
+		let root = self.session()?.host_path();
+		let root = fs::canonicalize(&root)
+			.with_context(|| format!("Failed to open {}", root.display()))?;
+		let joined = root.join(path);
+		if joined
+			.components()
+			.any(|component| component == Component::ParentDir)
+		{
+			bail!("Paths may not contain `..`");
+		}
+		let resolved = match fs::canonicalize(&joined) {
+			Ok(resolved) => resolved,
+			// A file about to be created; its directory must exist
+			Err(_) => {
+				// A dangling symlink would be followed out of the workspace
+				// by the write
+				if fs::symlink_metadata(&joined).is_ok_and(|meta| meta.is_symlink()) {
+					bail!("{} is a symlink", path);
+				}
+				let parent = joined.parent().context("Invalid path")?;
+				let name = joined.file_name().context("Invalid path")?;
+				fs::canonicalize(parent)
+					.with_context(|| format!("Failed to open {}", parent.display()))?
+					.join(name)
+			}
+		};
+		if !resolved.starts_with(&root) {
+			bail!("{} is outside the workspace", path);
+		}
+		Ok(resolved)
+	}
+
+	fn handle(&self, request: HostRequest) -> Result<Value> {
+		match request {
+			HostRequest::GetWorkspace => Ok(json!(self.session)),
+			HostRequest::ReadFile { path } => {
+				self.require(PluginCapability::ReadFiles)?;
+				let path = self.resolve(&path)?;
+				if fs::metadata(&path)?.len() > MAX_READ_BYTES {
+					bail!("{} is too large", path.display());
+				}
+				Ok(json!(fs::read_to_string(&path)?))
+			}
+			HostRequest::WriteFile { path, content } => {
+				self.require(PluginCapability::WriteFiles)?;
+				fs::write(self.resolve(&path)?, content)?;
+				Ok(Value::Null)
+			}
+			HostRequest::ListDirectory { path } => {
+				self.require(PluginCapability::ListDirectories)?;
+				let mut entries = Vec::new();
+				for entry in fs::read_dir(self.resolve(&path)?)? {
+					let entry = entry?;
+					entries.push(json!({
+						"name": entry.file_name().to_string_lossy(),
+						"isDirectory": entry.file_type()?.is_dir(),
+					}));
+				}
+				Ok(Value::Array(entries))
+			}
+			HostRequest::GetDiagnostics => {
+				self.require(PluginCapability::Diagnostics)?;
+				let session = self.session()?;
+				Ok(json!(self
+					.app_handle
+					.state::<Arc<ProblemsStore>>()
+					.get(session)))
+			}
+			HostRequest::Notify { message } => {
+				self.app_handle.emit(
+					"plugin-notification",
+					PluginNotification {
+						plugin_id: self.plugin_id.clone(),
+						message,
+					},
+				)?;
+				Ok(Value::Null)
+			}
+		}
+	}
+}
+
+/// Packs a buffer in plugin memory into the `i64` the ABI passes around.
+fn pack(ptr: i32, len: usize) -> i64 {
+	((ptr as u32 as i64) << 32) | (len as u32 as i64)
+}
+
+fn unpack(packed: i64) -> (usize, usize) {
+	(
+		(packed as u64 >> 32) as usize,
+		(packed as u64 & 0xffff_ffff) as usize,
+	)
+}
+
+/// Copies `len` bytes at `ptr` out of plugin memory. Both come from the
+/// plugin, so the range is checked before anything is allocated.
+fn read_memory(
+	memory: &Memory,
+	store: impl AsContext,
+	ptr: usize,
+	len: usize,
+) -> Result<Vec<u8>> {
+	if len > MAX_MESSAGE_BYTES {
+		bail!("The plugin passed a {} byte message", len);
+	}
+	match ptr.checked_add(len) {
+		Some(end) if end <= memory.data_size(&store) => {}
+		_ => bail!("The plugin passed a buffer outside its memory"),
+	}
+	let mut buf = vec![0; len];
+	memory.read(&store, ptr, &mut buf)?;
+	Ok(buf)
+}
+
+/// `ariana.call(ptr, len) -> packed`: runs a JSON host request and returns
+/// `{"result": ...}` or `{"error": "..."}` in a buffer obtained from the
+/// plugin's `ariana_alloc`, which the plugin then owns.
+fn host_call(mut caller: Caller<'_, HostState>, ptr: i32, len: i32) -> Result<i64> {
+	let memory = caller
+		.get_export("memory")
+		.and_then(|export| export.into_memory())
+		.context("The plugin exports no memory")?;
+	let request = read_memory(
+		&memory,
+		&caller,
+		usize::try_from(ptr)?,
+		usize::try_from(len)?,
+	)?;
+	let reply = match serde_json::from_slice::<HostRequest>(&request)
+		.map_err(anyhow::Error::from)
+		.and_then(|request| caller.data().handle(request))
+	{
+		Ok(result) => json!({ "result": result }),
+		Err(e) => json!({ "error": e.to_string() }),
+	};
+	let reply = serde_json::to_vec(&reply)?;
+	let alloc = caller
+		.get_export("ariana_alloc")
+		.and_then(|export| export.into_func())
+		.context("The plugin exports no ariana_alloc")?
+		.typed::<i32, i32>(&caller)?;
+	let reply_ptr = alloc.call(&mut caller, i32::try_from(reply.len())?)?;
+	memory.write(&mut caller, usize::try_from(reply_ptr)?, &reply)?;
+	Ok(pack(reply_ptr, reply.len()))
+}
+
+/// `ariana.log(ptr, len)`: writes a line to the app log.
+fn host_log(mut caller: Caller<'_, HostState>, ptr: i32, len: i32) -> Result<()> {
+	let memory = caller
+		.get_export("memory")
+		.and_then(|export| export.into_memory())
+		.context("The plugin exports no memory")?;
+	let line = read_memory(
+		&memory,
+		&caller,
+		usize::try_from(ptr)?,
+		usize::try_from(len)?,
+	)?;
+	log::info!(
+		"[plugin {}] {}",
+		caller.data().plugin_id,
+		String::from_utf8_lossy(&line)
+	);
+	Ok(())
+}
+
+/// A running instance of a plugin's module.
+struct Running {
+	store: Store<HostState>,
+	memory: Memory,
+	alloc: TypedFunc<i32, i32>,
+	handle: TypedFunc<(i32, i32), i64>,
+}
+
+struct Plugin {
+	manifest: PluginManifest,
+	directory: PathBuf,
+	enabled: bool,
+	/// The manifest's capabilities the user granted. A manifest changed
+	/// since declares more, which the plugin doesn't get.
+	granted: Vec<PluginCapability>,
+	module: Option<Module>,
+	running: Mutex<Option<Running>>,
+	error: Mutex<Option<String>>,
+}
+
+impl Plugin {
+	fn info(&self) -> PluginInfo {
+		PluginInfo {
+			manifest: self.manifest.clone(),
+			directory: self.directory.to_string_lossy().to_string(),
+			enabled: self.enabled,
+			error: self.error.lock().unwrap().clone(),
+		}
+	}
+
+	fn has(&self, capability: PluginCapability) -> bool {
+		self.granted.contains(&capability)
+	}
+
+	fn instantiate(
+		&self,
+		app_handle: &AppHandle,
+		linker: &Linker<HostState>,
+	) -> Result<Running> {
+		let module = self.module.as_ref().context("The plugin is not loaded")?;
+		let mut store = Store::new(
+			module.engine(),
+			HostState {
+				app_handle: app_handle.clone(),
+				plugin_id: self.manifest.id.clone(),
+				capabilities: self.granted.clone(),
+				session: None,
+				limits: StoreLimitsBuilder::new()
+					.memory_size(MAX_MEMORY_BYTES)
+					.instances(1)
+					.build(),
+			},
+		);
+		store.limiter(|state| &mut state.limits);
+		store.set_fuel(FUEL_PER_CALL)?;
+		let instance: Instance = linker.instantiate(&mut store, module)?;
+		Ok(Running {
+			memory: instance
+				.get_memory(&mut store, "memory")
+				.context("The plugin exports no memory")?,
+			alloc: instance.get_typed_func(&mut store, "ariana_alloc")?,
+			handle: instance.get_typed_func(&mut store, "ariana_handle")?,
+			store,
+		})
+	}
+
+	/// Sends `message` to the plugin's `ariana_handle` and returns the
+	/// `result` it answers with. A trap, including running out of fuel,
+	/// discards the instance; the next message starts a fresh one.
+	fn call(
+		&self,
+		app_handle: &AppHandle,
+		linker: &Linker<HostState>,
+		session: Option<OsSession>,
+		message: &Value,
+	) -> Result<Value> {
+		let mut running = self.running.lock().unwrap();
+		if running.is_none() {
+			*running = Some(self.instantiate(app_handle, linker)?);
+		}
+		let result = Self::send(running.as_mut().unwrap(), session, message);
+		match &result {
+			Ok(_) => *self.error.lock().unwrap() = None,
+			Err(e) => {
+				log::warn!("Plugin {} failed: {:#}", self.manifest.id, e);
+				*running = None;
+				*self.error.lock().unwrap() = Some(format!("{:#}", e));
+			}
+		}
+		result
+	}
+
+	fn send(
+		running: &mut Running,
+		session: Option<OsSession>,
+		message: &Value,
+	) -> Result<Value> {
+		let store = &mut running.store;
+		store.data_mut().session = session;
+		store.set_fuel(FUEL_PER_CALL)?;
+
+		let message = serde_json::to_vec(message)?;
+		let ptr = running
+			.alloc
+			.call(&mut *store, i32::try_from(message.len())?)?;
+		running
+			.memory
+			.write(&mut *store, usize::try_from(ptr)?, &message)?;
+		let packed = running
+			.handle
+			.call(&mut *store, (ptr, i32::try_from(message.len())?))?;
+		store.data_mut().session = None;
+		if packed == 0 {
+			return Ok(Value::Null);
+		}
+
+		let (ptr, len) = unpack(packed);
+		let reply = read_memory(&running.memory, &*store, ptr, len)?;
+		let mut reply: Value = serde_json::from_slice(&reply)
+			.context("The plugin answered with invalid JSON")?;
+		if let Some(error) = reply.get("error").and_then(Value::as_str) {
+			bail!("{}", error);
+		}
+		Ok(reply
+			.get_mut("result")
+			.map(Value::take)
+			.unwrap_or(Value::Null))
+	}
+}
+
+/// Loads WebAssembly plugins from the `plugins` directory of the app data
+/// and runs them sandboxed: no WASI, bounded memory and fuel, and host
+/// access only through the `ariana` imports, gated by the capabilities the
+/// user granted. Plugins contribute palette commands and panel data
+/// sources, and can follow file and diagnostics events.
+///
+/// The ABI is JSON over linear memory. A plugin exports `memory`,
+/// `ariana_alloc(len) -> ptr` and `ariana_handle(ptr, len) -> packed`, where
+/// `packed` is `ptr << 32 | len` of a `{"result": ...}` or `{"error": ...}`
+/// reply, or 0 for none. Messages are `{"type": "command", "id", "args"}`,
+/// `{"type": "dataSource", "id", "params"}` or
+/// `{"type": "event", "event", "payload"}`.
+pub struct PluginHost {
+	app_handle: AppHandle,
+	engine: Engine,
+	linker: Linker<HostState>,
+	plugins: Mutex<HashMap<String, Arc<Plugin>>>,
+}
+
+impl PluginHost {
+	pub fn new(app_handle: AppHandle, watcher: Arc<FileWatcher>) -> Result<Arc<Self>> {
+		let mut config = Config::new();
+		config.consume_fuel(true);
+		let engine = Engine::new(&config)?;
+		let mut linker = Linker::new(&engine);
+		linker.func_wrap("ariana", "call", host_call)?;
+		linker.func_wrap("ariana", "log", host_log)?;
+
+		let host = Arc::new(Self {
+			app_handle,
+			engine,
+			linker,
+			plugins: Mutex::new(HashMap::new()),
+		});
+
+		// Compiling modules takes a while, keep it off the startup path
+		let loading = host.clone();
+		tauri::async_runtime::spawn_blocking(move || {
+			if let Err(e) = loading.reload() {
+				log::error!("Failed to load plugins: {:#}", e);
+			}
+		});
+		host.forward_file_changes(watcher);
+		host.forward_diagnostics();
+		Ok(host)
+	}
+
+	pub fn directory(&self) -> Result<PathBuf> {
+		Ok(self.app_handle.path().app_data_dir()?.join("plugins"))
+	}
+
+	fn grants_path(&self) -> Result<PathBuf> {
+		Ok(self
+			.app_handle
+			.path()
+			.app_local_data_dir()?
+			.join(GRANTS_DIR)
+			.join(GRANTS_FILE))
+	}
+
+	/// The enabled plugins and the capabilities granted to each.
+	fn grants(&self) -> Result<BTreeMap<String, Vec<PluginCapability>>> {
+		let path = self.grants_path()?;
+		let content = match fs::read_to_string(&path) {
+			Ok(content) => content,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				return Ok(BTreeMap::new())
+			}
+			Err(e) => return Err(e.into()),
+		};
+		match content.split_once('\n') {
+			Some((GRANTS_HEADER, grants)) => Ok(serde_json::from_str(grants)?),
+			_ => {
+				log::warn!("Ignoring malformed plugin grants {}", path.display());
+				Ok(BTreeMap::new())
+			}
+		}
+	}
+
+	fn set_grants(&self, grants: &BTreeMap<String, Vec<PluginCapability>>) -> Result<()> {
+		let path = self.grants_path()?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let content = format!(
+			"{}\n{}\n",
+			GRANTS_HEADER,
+			serde_json::to_string_pretty(grants)?
+		);
+		write_private(&path, content.as_bytes())
+	}
+
+	/// Asks the user in a native dialog, which the webview can't answer for
+	/// them, whether to enable `manifest` with the capabilities it declares.
+	fn confirm_enable(&self, manifest: &PluginManifest) -> bool {
+		let mut message =
+			format!("Enable the plugin {} ({})?", manifest.name, manifest.id);
+		if !manifest.capabilities.is_empty() {
+			message.push_str("\n\nIt will be able to:");
+			for capability in &manifest.capabilities {
+				message.push_str("\n- ");
+				message.push_str(capability.description());
+			}
+		}
+		let answer = self
+			.app_handle
+			.dialog()
+			.message(message)
+			.title("Enable plugin?")
+			.kind(MessageDialogKind::Warning)
+			.buttons(MessageDialogButtons::OkCancelCustom(
+				ENABLE.to_string(),
+				CANCEL.to_string(),
+			))
+			.blocking_show_with_result();
+		answer == MessageDialogResult::Custom(ENABLE.to_string())
+	}
+
+	fn load(
+		&self,
+		directory: &Path,
+		grants: &BTreeMap<String, Vec<PluginCapability>>,
+	) -> Result<Plugin> {
+		let manifest_path = directory.join(MANIFEST_FILE);
+		let manifest: PluginManifest = serde_json::from_str(
+			&fs::read_to_string(&manifest_path)
+				.with_context(|| format!("Failed to read {}", manifest_path.display()))?,
+		)
+		.with_context(|| format!("Invalid {}", manifest_path.display()))?;
+		if manifest.id.is_empty()
+			|| !manifest
+				.id
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+		{
+			bail!(
+				"Invalid plugin id `{}` in {}",
+				manifest.id,
+				manifest_path.display()
+			);
+		}
+
+		let granted: Vec<_> = match grants.get(&manifest.id) {
+			Some(granted) => manifest
+				.capabilities
+				.iter()
+				.copied()
+				.filter(|capability| granted.contains(capability))
+				.collect(),
+			None => Vec::new(),
+		};
+		let enabled = grants.contains_key(&manifest.id);
+		let (module, error) = if enabled {
+			match Module::from_file(&self.engine, directory.join(&manifest.main)) {
+				Ok(module) => (Some(module), None),
+				Err(e) => (None, Some(format!("{:#}", e))),
+			}
+		} else {
+			(None, None)
+		};
+		Ok(Plugin {
+			manifest,
+			directory: directory.to_path_buf(),
+			enabled,
+			granted,
+			module,
+			running: Mutex::new(None),
+			error: Mutex::new(error),
+		})
+	}
+
+	/// Scans the plugins directory again, compiling the enabled plugins and
+	/// registering their commands in the palette.
+	pub fn reload(&self) -> Result<Vec<PluginInfo>> {
+		let directory = self.directory()?;
+		fs::create_dir_all(&directory)?;
+		let grants = self.grants().unwrap_or_else(|e| {
+			log::error!("Failed to read plugin grants: {:#}", e);
+			BTreeMap::new()
+		});
+
+		let mut plugins = HashMap::new();
+		for entry in fs::read_dir(&directory)? {
+			let path = entry?.path();
+			if !path.join(MANIFEST_FILE).is_file() {
+				continue;
+			}
+			match self.load(&path, &grants) {
+				Ok(plugin) if plugins.contains_key(&plugin.manifest.id) => {
+					log::warn!(
+						"Skipping {}: plugin {} is already installed",
+						path.display(),
+						plugin.manifest.id
+					);
+				}
+				Ok(plugin) => {
+					plugins.insert(plugin.manifest.id.clone(), Arc::new(plugin));
+				}
+				Err(e) => log::warn!("Skipping plugin in {}: {:#}", path.display(), e),
+			}
+		}
+
+		let palette = self.app_handle.state::<Arc<PaletteManager>>();
+		let stale: Vec<String> = palette
+			.action_ids()
+			.into_iter()
+			.filter(|id| id.starts_with(ACTION_PREFIX))
+			.collect();
+		palette.unregister_actions(&stale);
+		palette.register_actions(
+			plugins
+				.values()
+				.filter(|plugin| plugin.module.is_some())
+				.flat_map(|plugin| {
+					plugin
+						.manifest
+						.commands
+						.iter()
+						.map(|command| PaletteAction {
+							id: format!(
+								"{}{}.{}",
+								ACTION_PREFIX, plugin.manifest.id, command.id
+							),
+							title: command.title.clone(),
+							category: Some(plugin.manifest.name.clone()),
+							keybinding: None,
+						})
+				})
+				.collect(),
+		);
+
+		*self.plugins.lock().unwrap() = plugins;
+		let list = self.list();
+		let _ = self.app_handle.emit("plugins-changed", &list);
+		Ok(list)
+	}
+
+	pub fn list(&self) -> Vec<PluginInfo> {
+		let mut list: Vec<_> = self
+			.plugins
+			.lock()
+			.unwrap()
+			.values()
+			.map(|plugin| plugin.info())
+			.collect();
+		list.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+		list
+	}
+
+	/// Enabling grants the capabilities the manifest declares, once the user
+	/// confirms them, so the webview can't enable plugins by itself.
+	pub fn set_enabled(&self, id: &str, enabled: bool) -> Result<Vec<PluginInfo>> {
+		let manifest = match self.plugins.lock().unwrap().get(id) {
+			Some(plugin) => plugin.manifest.clone(),
+			None => bail!("No plugin {}", id),
+		};
+		let mut grants = self.grants()?;
+		if enabled {
+			if !self.confirm_enable(&manifest) {
+				bail!("PLUGIN_DENIED: {}", id);
+			}
+			grants.insert(id.to_string(), manifest.capabilities);
+		} else {
+			grants.remove(id);
+		}
+		self.set_grants(&grants)?;
+		self.reload()
+	}
+
+	fn plugin(&self, id: &str) -> Result<Arc<Plugin>> {
+		match self.plugins.lock().unwrap().get(id) {
+			Some(plugin) if plugin.module.is_some() => Ok(plugin.clone()),
+			Some(_) => bail!("Plugin {} is not enabled", id),
+			None => bail!("No plugin {}", id),
+		}
+	}
+
+	pub fn run_command(
+		&self,
+		plugin_id: &str,
+		command_id: &str,
+		session: Option<OsSession>,
+		args: Value,
+	) -> Result<Value> {
+		let plugin = self.plugin(plugin_id)?;
+		if !plugin.manifest.commands.iter().any(|c| c.id == command_id) {
+			bail!("Plugin {} has no command {}", plugin_id, command_id);
+		}
+		plugin.call(
+			&self.app_handle,
+			&self.linker,
+			session,
+			&json!({ "type": "command", "id": command_id, "args": args }),
+		)
+	}
+
+	pub fn query_data_source(
+		&self,
+		plugin_id: &str,
+		source_id: &str,
+		session: Option<OsSession>,
+		params: Value,
+	) -> Result<Value> {
+		let plugin = self.plugin(plugin_id)?;
+		if !plugin
+			.manifest
+			.data_sources
+			.iter()
+			.any(|s| s.id == source_id)
+		{
+			bail!("Plugin {} has no data source {}", plugin_id, source_id);
+		}
+		plugin.call(
+			&self.app_handle,
+			&self.linker,
+			session,
+			&json!({ "type": "dataSource", "id": source_id, "params": params }),
+		)
+	}
+
+	/// Delivers an event to the enabled plugins holding `capability`.
+	/// Errors are recorded on the plugin rather than returned.
+	fn broadcast(&self, capability: PluginCapability, event: &str, payload: Value) {
+		let plugins: Vec<_> = self
+			.plugins
+			.lock()
+			.unwrap()
+			.values()
+			.filter(|plugin| plugin.module.is_some() && plugin.has(capability))
+			.cloned()
+			.collect();
+		let message = json!({ "type": "event", "event": event, "payload": payload });
+		for plugin in plugins {
+			let _ = plugin.call(&self.app_handle, &self.linker, None, &message);
+		}
+	}
+
+	fn forward_file_changes(self: &Arc<Self>, watcher: Arc<FileWatcher>) {
+		let mut changes = watcher.subscribe();
+		let host = Arc::downgrade(self);
+		tauri::async_runtime::spawn(async move {
+			loop {
+				let FileChange { path, kind } = match changes.recv().await {
+					Ok(change) => change,
+					Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => break,
+				};
+				let Some(host) = host.upgrade() else {
+					break;
+				};
+				let kind = match kind {
+					FileChangeKind::Created => "created",
+					FileChangeKind::Modified => "modified",
+					FileChangeKind::Removed => "removed",
+				};
+				let payload = json!({ "path": path, "kind": kind });
+				let _ = tauri::async_runtime::spawn_blocking(move || {
+					host.broadcast(PluginCapability::FileEvents, "fileChanged", payload)
+				})
+				.await;
+			}
+		});
+	}
+
+	fn forward_diagnostics(self: &Arc<Self>) {
+		let host = Arc::downgrade(self);
+		self.app_handle.listen("problems-updated", move |event| {
+			let Some(host) = host.upgrade() else {
+				return;
+			};
+			let payload = serde_json::from_str(event.payload()).unwrap_or(Value::Null);
+			tauri::async_runtime::spawn_blocking(move || {
+				host.broadcast(PluginCapability::Diagnostics, "problemsUpdated", payload)
+			});
+		});
+	}
+}
+
+#[tauri::command]
+pub async fn list_plugins(
+	host: State<'_, Arc<PluginHost>>,
+) -> Result<Vec<PluginInfo>, String> {
+	Ok(host.list())
+}
+
+#[tauri::command]
+pub async fn reload_plugins(
+	host: State<'_, Arc<PluginHost>>,
+) -> Result<Vec<PluginInfo>, String> {
+	let host = host.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || host.reload())
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_plugin_enabled(
+	plugin_id: String,
+	enabled: bool,
+	host: State<'_, Arc<PluginHost>>,
+) -> Result<Vec<PluginInfo>, String> {
+	let host = host.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || host.set_enabled(&plugin_id, enabled))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+/// Where plugins are installed, one directory each with a `plugin.json`.
+#[tauri::command]
+pub async fn get_plugins_directory(
+	host: State<'_, Arc<PluginHost>>,
+) -> Result<String, String> {
+	host.directory()
+		.map(|path| path.to_string_lossy().to_string())
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn run_plugin_command(
+	plugin_id: String,
+	command_id: String,
+	os_session: Option<OsSession>,
+	args: Option<Value>,
+	host: State<'_, Arc<PluginHost>>,
+) -> Result<Value, String> {
+	let host = host.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		host.run_command(
+			&plugin_id,
+			&command_id,
+			os_session,
+			args.unwrap_or(Value::Null),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| format!("{:#}", e))
+}
+
+#[tauri::command]
+pub async fn query_plugin_data_source(
+	plugin_id: String,
+	source_id: String,
+	os_session: Option<OsSession>,
+	params: Option<Value>,
+	host: State<'_, Arc<PluginHost>>,
+) -> Result<Value, String> {
+	let host = host.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		host.query_data_source(
+			&plugin_id,
+			&source_id,
+			os_session,
+			params.unwrap_or(Value::Null),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| format!("{:#}", e))
+}
@@ -0,0 +1,427 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, TcpStream};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, State};
+use tokio::net::{TcpListener, TcpStream as AsyncTcpStream};
+use uuid::Uuid;
+
+use crate::os::OsSession;
+use crate::terminal::TerminalManager;
+use crate::wsl::WslCommand;
+
+/// How often watched sessions are scanned for new listeners.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// How long to wait when probing whether a port is reachable on localhost.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// A TCP port some process is listening on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListeningPort {
+	pub port: u16,
+	/// Bound address, e.g. `0.0.0.0`, `127.0.0.1` or `::`.
+	pub address: String,
+	pub pid: Option<u32>,
+	pub process: Option<String>,
+	/// The process was started from one of our terminal connections.
+	pub managed: bool,
+}
+
+/// Payload of the `port-opened` and `port-closed` events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortEvent {
+	pub watch_id: String,
+	#[serde(flatten)]
+	pub port: ListeningPort,
+	/// Where the port can be opened in a browser on this machine.
+	pub url: String,
+	/// Traffic to the port is relayed into WSL by us.
+	pub forwarded: bool,
+}
+
+/// Splits `host:port` as printed by `ss`, `lsof` and `netstat`, which may
+/// use `*` for any address, bracket IPv6 and append `%interface`.
+fn split_address(local: &str) -> Option<(String, u16)> {
+	let (host, port) = local.rsplit_once(':')?;
+	let port = port.parse().ok()?;
+	let host = host.trim_start_matches('[').trim_end_matches(']');
+	let host = host.split('%').next().unwrap_or(host);
+	let host = match host {
+		"*" => "0.0.0.0",
+		host => host,
+	};
+	Some((host.to_string(), port))
+}
+
+/// Parses `ss -H -ltnp`, e.g.
+/// `LISTEN 0 511 0.0.0.0:3000 0.0.0.0:* users:(("node",pid=1234,fd=20))`.
+fn parse_ss(output: &str) -> Vec<ListeningPort> {
+	output
+		.lines()
+		.filter_map(|line| {
+			let columns: Vec<_> = line.split_whitespace().collect();
+			let (address, port) = split_address(columns.get(3)?)?;
+			let users = columns.get(5).copied().unwrap_or_default();
+			let process = users
+				.split_once("((\"")
+				.and_then(|(_, rest)| rest.split_once('"'))
+				.map(|(name, _)| name.to_string());
+			let pid = users
+				.split_once("pid=")
+				.and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+				.and_then(|pid| pid.parse().ok());
+			Some(ListeningPort {
+				port,
+				address,
+				pid,
+				process,
+				managed: false,
+			})
+		})
+		.collect()
+}
+
+/// Parses `lsof -nP -iTCP -sTCP:LISTEN -F pcn`, which prints a `p<pid>`
+/// and `c<command>` line per process followed by `n<address>` lines.
+fn parse_lsof(output: &str) -> Vec<ListeningPort> {
+	let mut ports = Vec::new();
+	let mut pid = None;
+	let mut process = None;
+	for line in output.lines() {
+		let (field, value) = line.split_at(line.len().min(1));
+		match field {
+			"p" => pid = value.parse().ok(),
+			"c" => process = Some(value.to_string()),
+			"n" => {
+				if let Some((address, port)) = split_address(value) {
+					ports.push(ListeningPort {
+						port,
+						address,
+						pid,
+						process: process.clone(),
+						managed: false,
+					});
+				}
+			}
+			_ => {}
+		}
+	}
+	ports
+}
+
+/// Parses `netstat -ano -p TCP` on Windows, e.g.
+/// `TCP    0.0.0.0:135    0.0.0.0:0    LISTENING    1234`.
+fn parse_netstat(output: &str) -> Vec<ListeningPort> {
+	output
+		.lines()
+		.filter_map(|line| {
+			let columns: Vec<_> = line.split_whitespace().collect();
+			if columns.len() < 5 || columns[0] != "TCP" || columns[3] != "LISTENING" {
+				return None;
+			}
+			let (address, port) = split_address(columns[1])?;
+			Some(ListeningPort {
+				port,
+				address,
+				pid: columns[4].parse().ok(),
+				process: None,
+				managed: false,
+			})
+		})
+		.collect()
+}
+
+fn run(command: &mut Command) -> Result<String> {
+	let output = command.output()?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"{}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn list_local_ports() -> Result<Vec<ListeningPort>> {
+	if cfg!(target_os = "windows") {
+		Ok(parse_netstat(&run(
+			Command::new("netstat").args(["-ano", "-p", "TCP"])
+		)?))
+	} else if cfg!(target_os = "macos") {
+		Ok(parse_lsof(&run(Command::new("lsof").args([
+			"-nP",
+			"-iTCP",
+			"-sTCP:LISTEN",
+			"-F",
+			"pcn",
+		]))?))
+	} else {
+		Ok(parse_ss(&run(Command::new("ss").args(["-H", "-ltnp"]))?))
+	}
+}
+
+fn list_wsl_ports(distribution: &str) -> Result<Vec<ListeningPort>> {
+	Ok(parse_ss(&run(&mut WslCommand::new(distribution, "ss")
+		.args(["-H", "-ltnp"])
+		.build())?))
+}
+
+/// Every process descended from `roots`, including the roots, from the
+/// `ps` process table. Empty where `ps` isn't available.
+fn descendants(roots: &[u32]) -> HashSet<u32> {
+	let Ok(output) = run(Command::new("ps").args(["-A", "-o", "pid=,ppid="])) else {
+		return HashSet::new();
+	};
+	let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+	for line in output.lines() {
+		let mut fields = line.split_whitespace().map(|f| f.parse::<u32>());
+		if let (Some(Ok(pid)), Some(Ok(ppid))) = (fields.next(), fields.next()) {
+			children.entry(ppid).or_default().push(pid);
+		}
+	}
+
+	let mut found: HashSet<u32> = roots.iter().copied().collect();
+	let mut queue = roots.to_vec();
+	while let Some(pid) = queue.pop() {
+		for &child in children.get(&pid).into_iter().flatten() {
+			if found.insert(child) {
+				queue.push(child);
+			}
+		}
+	}
+	found
+}
+
+/// Whether something accepts connections on `127.0.0.1:port` here.
+fn reachable_locally(port: u16) -> bool {
+	TcpStream::connect_timeout(&SocketAddr::from(([127, 0, 0, 1], port)), PROBE_TIMEOUT)
+		.is_ok()
+}
+
+/// The distribution's IP address on the WSL virtual network.
+fn wsl_address(distribution: &str) -> Result<String> {
+	run(&mut WslCommand::new(distribution, "hostname").arg("-I").build())?
+		.split_whitespace()
+		.next()
+		.map(str::to_string)
+		.ok_or_else(|| anyhow!("Could not find the IP address of {}", distribution))
+}
+
+/// Lists listening ports for sessions, watches them for new listeners and
+/// relays localhost traffic into WSL when Windows can't reach a port itself.
+pub struct PortManager {
+	terminal_manager: Arc<TerminalManager>,
+	watches: Mutex<HashMap<String, JoinHandle<()>>>,
+	/// Relays by local port.
+	forwards: Mutex<HashMap<u16, JoinHandle<()>>>,
+}
+
+impl PortManager {
+	pub fn new(terminal_manager: Arc<TerminalManager>) -> Self {
+		Self {
+			terminal_manager,
+			watches: Mutex::new(HashMap::new()),
+			forwards: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Listening TCP ports visible to the session, one entry per port and
+	/// process. Ports of processes started from our terminals are marked
+	/// `managed`; this is only known for local sessions.
+	pub fn list_ports(&self, session: &OsSession) -> Result<Vec<ListeningPort>> {
+		let mut ports = match session {
+			OsSession::Local(_) => {
+				let mut ports = list_local_ports()?;
+				let managed = descendants(&self.terminal_manager.process_ids());
+				for port in &mut ports {
+					port.managed = port.pid.is_some_and(|pid| managed.contains(&pid));
+				}
+				ports
+			}
+			OsSession::Wsl(session) => list_wsl_ports(&session.distribution)?,
+		};
+
+		// IPv4 and IPv6 listeners of the same process are one port to the user
+		let mut seen = HashSet::new();
+		ports.retain(|p| seen.insert((p.port, p.pid)));
+		ports.sort_by_key(|p| p.port);
+		Ok(ports)
+	}
+
+	/// Makes a port listening inside WSL reachable on Windows' localhost.
+	/// WSL usually does this itself, so a relay is only started when the
+	/// port can't be reached. Returns whether a relay is running.
+	pub async fn forward_port(&self, distribution: &str, port: u16) -> Result<bool> {
+		if self.forwards.lock().unwrap().contains_key(&port) {
+			return Ok(true);
+		}
+		if tauri::async_runtime::spawn_blocking(move || reachable_locally(port)).await? {
+			return Ok(false);
+		}
+
+		let distribution = distribution.to_string();
+		let address =
+			tauri::async_runtime::spawn_blocking(move || wsl_address(&distribution))
+				.await??;
+		let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+		let target = format!("{}:{}", address, port);
+
+		let relay = tauri::async_runtime::spawn(async move {
+			while let Ok((mut inbound, _)) = listener.accept().await {
+				let target = target.clone();
+				tokio::spawn(async move {
+					if let Ok(mut outbound) = AsyncTcpStream::connect(&target).await {
+						let _ =
+							tokio::io::copy_bidirectional(&mut inbound, &mut outbound)
+								.await;
+					}
+				});
+			}
+		});
+		self.forwards.lock().unwrap().insert(port, relay);
+		Ok(true)
+	}
+
+	pub fn stop_forward(&self, port: u16) {
+		if let Some(relay) = self.forwards.lock().unwrap().remove(&port) {
+			relay.abort();
+		}
+	}
+
+	/// Scans the session every few seconds and emits `port-opened` and
+	/// `port-closed` events as listeners come and go. Ports already open
+	/// when the watch starts are reported too. Returns the watch id.
+	pub fn watch_ports(
+		self: &Arc<Self>,
+		session: OsSession,
+		app_handle: AppHandle,
+	) -> String {
+		let watch_id = Uuid::new_v4().to_string();
+		let manager = self.clone();
+		let id = watch_id.clone();
+
+		let handle = tauri::async_runtime::spawn(async move {
+			let mut open: HashMap<(u16, Option<u32>), PortEvent> = HashMap::new();
+			let mut interval = tokio::time::interval(WATCH_INTERVAL);
+			loop {
+				interval.tick().await;
+				let scan_manager = manager.clone();
+				let scan_session = session.clone();
+				let ports = match tauri::async_runtime::spawn_blocking(move || {
+					scan_manager.list_ports(&scan_session)
+				})
+				.await
+				{
+					Ok(Ok(ports)) => ports,
+					// Try again on the next tick
+					_ => continue,
+				};
+
+				let current: HashSet<_> = ports.iter().map(|p| (p.port, p.pid)).collect();
+				for key in open.keys().copied().collect::<Vec<_>>() {
+					if !current.contains(&key) {
+						let event = open.remove(&key).unwrap();
+						if event.forwarded {
+							manager.stop_forward(event.port.port);
+						}
+						let _ = app_handle.emit("port-closed", event);
+					}
+				}
+
+				for port in ports {
+					let key = (port.port, port.pid);
+					if open.contains_key(&key) {
+						continue;
+					}
+					let forwarded = match &session {
+						OsSession::Wsl(wsl) => manager
+							.forward_port(&wsl.distribution, port.port)
+							.await
+							.unwrap_or(false),
+						OsSession::Local(_) => false,
+					};
+					let event = PortEvent {
+						watch_id: id.clone(),
+						url: format!("http://localhost:{}", port.port),
+						port,
+						forwarded,
+					};
+					let _ = app_handle.emit("port-opened", event.clone());
+					open.insert(key, event);
+				}
+			}
+		});
+
+		self.watches
+			.lock()
+			.unwrap()
+			.insert(watch_id.clone(), handle);
+		watch_id
+	}
+
+	pub fn unwatch_ports(&self, watch_id: &str) {
+		if let Some(handle) = self.watches.lock().unwrap().remove(watch_id) {
+			handle.abort();
+		}
+	}
+}
+
+#[tauri::command]
+pub async fn list_ports(
+	os_session: OsSession,
+	port_manager: State<'_, Arc<PortManager>>,
+) -> Result<Vec<ListeningPort>, String> {
+	let manager = port_manager.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || manager.list_ports(&os_session))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn watch_ports(
+	os_session: OsSession,
+	port_manager: State<'_, Arc<PortManager>>,
+	app_handle: AppHandle,
+) -> Result<String, String> {
+	Ok(port_manager.watch_ports(os_session, app_handle))
+}
+
+#[tauri::command]
+pub async fn unwatch_ports(
+	watch_id: String,
+	port_manager: State<'_, Arc<PortManager>>,
+) -> Result<(), String> {
+	port_manager.unwatch_ports(&watch_id);
+	Ok(())
+}
+
+/// Makes a WSL port reachable on localhost, returning whether a relay was
+/// needed.
+#[tauri::command]
+pub async fn forward_wsl_port(
+	distribution: String,
+	port: u16,
+	port_manager: State<'_, Arc<PortManager>>,
+) -> Result<bool, String> {
+	port_manager
+		.forward_port(&distribution, port)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn stop_port_forward(
+	port: u16,
+	port_manager: State<'_, Arc<PortManager>>,
+) -> Result<(), String> {
+	port_manager.stop_forward(port);
+	Ok(())
+}
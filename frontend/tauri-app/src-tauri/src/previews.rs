@@ -0,0 +1,147 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::{ImageFormat, ImageReader};
+use resvg::{tiny_skia, usvg};
+use serde::Serialize;
+
+use crate::os::OsSession;
+
+/// Files larger than this aren't decoded.
+pub const MAX_SOURCE_BYTES: u64 = 32 * 1024 * 1024;
+/// The longest side of a thumbnail when none is asked for.
+const DEFAULT_MAX_SIZE: u32 = 256;
+/// Thumbnails are never scaled past this, whatever is asked for.
+const MAX_SIZE_LIMIT: u32 = 2048;
+
+/// A PNG thumbnail, small enough to hand to the webview.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thumbnail {
+	/// `data:image/png;base64,...`
+	pub data_url: String,
+	pub width: u32,
+	pub height: u32,
+	pub original_width: u32,
+	pub original_height: u32,
+}
+
+/// Whether `path` has the extension of a format `render_thumbnail` decodes.
+pub fn is_previewable(path: &str) -> bool {
+	let Some(extension) = Path::new(path).extension().and_then(|e| e.to_str()) else {
+		return false;
+	};
+	matches!(
+		extension.to_lowercase().as_str(),
+		"png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp" | "ico" | "svg"
+	)
+}
+
+fn is_svg(path: &str) -> bool {
+	Path::new(path)
+		.extension()
+		.and_then(|e| e.to_str())
+		.is_some_and(|e| e.eq_ignore_ascii_case("svg"))
+}
+
+/// Fits `width` by `height` inside a `max_size` square, never enlarging.
+fn fit(width: u32, height: u32, max_size: u32) -> (u32, u32) {
+	let scale = (max_size as f64 / width.max(height).max(1) as f64).min(1.0);
+	let scaled = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+	(scaled(width), scaled(height))
+}
+
+fn render_svg(bytes: &[u8], max_size: u32) -> Result<Thumbnail> {
+	let tree = usvg::Tree::from_data(bytes, &usvg::Options::default())
+		.map_err(|e| anyhow!("Failed to parse SVG: {}", e))?;
+	let size = tree.size();
+	let (original_width, original_height) =
+		(size.width().ceil() as u32, size.height().ceil() as u32);
+	// Vectors scale up cleanly, so small icons are drawn at the full size
+	let scale = max_size as f32 / size.width().max(size.height()).max(1.0);
+	let (width, height) = (
+		((size.width() * scale).round() as u32).max(1),
+		((size.height() * scale).round() as u32).max(1),
+	);
+	let mut pixmap = tiny_skia::Pixmap::new(width, height)
+		.ok_or_else(|| anyhow!("SVG is too large to render"))?;
+	resvg::render(
+		&tree,
+		tiny_skia::Transform::from_scale(scale, scale),
+		&mut pixmap.as_mut(),
+	);
+	let png = pixmap
+		.encode_png()
+		.map_err(|e| anyhow!("Failed to encode thumbnail: {}", e))?;
+	Ok(Thumbnail {
+		data_url: format!("data:image/png;base64,{}", BASE64.encode(png)),
+		width,
+		height,
+		original_width,
+		original_height,
+	})
+}
+
+fn render_raster(bytes: &[u8], max_size: u32) -> Result<Thumbnail> {
+	let image = ImageReader::new(Cursor::new(bytes))
+		.with_guessed_format()?
+		.decode()
+		.map_err(|e| anyhow!("Failed to decode image: {}", e))?;
+	let (original_width, original_height) = (image.width(), image.height());
+	let (width, height) = fit(original_width, original_height, max_size);
+	let thumbnail = if (width, height) == (original_width, original_height) {
+		image
+	} else {
+		image.thumbnail(width, height)
+	};
+	let mut png = Vec::new();
+	thumbnail
+		.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+		.map_err(|e| anyhow!("Failed to encode thumbnail: {}", e))?;
+	Ok(Thumbnail {
+		data_url: format!("data:image/png;base64,{}", BASE64.encode(png)),
+		width: thumbnail.width(),
+		height: thumbnail.height(),
+		original_width,
+		original_height,
+	})
+}
+
+/// Renders the image in `bytes`, named `path`, as a PNG whose longest side
+/// is at most `max_size`.
+pub fn render_thumbnail(path: &str, bytes: &[u8], max_size: u32) -> Result<Thumbnail> {
+	let max_size = max_size.clamp(1, MAX_SIZE_LIMIT);
+	if is_svg(path) {
+		render_svg(bytes, max_size)
+	} else {
+		render_raster(bytes, max_size)
+	}
+}
+
+/// Renders a thumbnail of the image at `path` in `os_session`, so the
+/// webview never loads a multi-megabyte original just to show it small.
+#[tauri::command]
+pub async fn generate_thumbnail(
+	os_session: OsSession,
+	path: String,
+	max_size: Option<u32>,
+) -> Result<Thumbnail, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		let host_path = os_session.host_path_for(&path);
+		let size = fs::metadata(&host_path)?.len();
+		if size > MAX_SOURCE_BYTES {
+			return Err(anyhow!("{} is too large to preview ({} bytes)", path, size));
+		}
+		render_thumbnail(
+			&path,
+			&fs::read(&host_path)?,
+			max_size.unwrap_or(DEFAULT_MAX_SIZE),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
@@ -0,0 +1,416 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::jobs::{self, CancelToken, JOB_CANCELLED};
+use crate::os::{OsSession, WslSession};
+use crate::trust;
+
+/// The per-project manifest, relative to the project root.
+const MANIFEST_PATH: &str = ".ariana/hooks.toml";
+const DEFAULT_TIMEOUT_SECONDS: u64 = 5 * 60;
+const MAX_HOOKS: usize = 50;
+const MAX_NAME_LEN: usize = 128;
+const MAX_COMMAND_LEN: usize = 4096;
+const MAX_TIMEOUT_SECONDS: u64 = 60 * 60;
+/// Output kept per hook run; the rest only goes out as events.
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+	/// A project was opened in the IDE.
+	ProjectOpen,
+	/// Before the IDE commits; blocking hooks that fail stop the commit.
+	PreCommit,
+	/// After a branch was merged.
+	PostMerge,
+	/// An agent finished a task on a canvas.
+	AgentTaskComplete,
+}
+
+impl HookEvent {
+	fn name(self) -> &'static str {
+		match self {
+			Self::ProjectOpen => "project-open",
+			Self::PreCommit => "pre-commit",
+			Self::PostMerge => "post-merge",
+			Self::AgentTaskComplete => "agent-task-complete",
+		}
+	}
+
+	/// Whether its hooks run before the action and can stop it.
+	fn can_block(self) -> bool {
+		self == Self::PreCommit
+	}
+}
+
+/// One hook of `.ariana/hooks.toml`:
+///
+/// ```toml
+/// [[hooks]]
+/// event = "pre-commit"
+/// name = "Lint"
+/// command = "pnpm lint"
+/// timeout_seconds = 120   # 300 by default
+/// blocking = true         # a failure stops the commit
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectHook {
+	pub event: HookEvent,
+	pub name: String,
+	/// Shell command line run from the project root.
+	pub command: String,
+	pub timeout_seconds: Option<u64>,
+	pub blocking: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Manifest {
+	hooks: Vec<ProjectHook>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookProblem {
+	/// Position of the hook in the manifest, none for the whole manifest.
+	pub index: Option<usize>,
+	pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectHooks {
+	pub hooks: Vec<ProjectHook>,
+	/// Whether the manifest has no problems. Hooks of a manifest with
+	/// problems don't run.
+	pub valid: bool,
+	pub problems: Vec<HookProblem>,
+	/// Set when the manifest couldn't be read or checked.
+	pub error: Option<String>,
+}
+
+/// Payload of the `project-hook-output` event, one per line a hook prints.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookOutput {
+	pub directory: String,
+	pub event: HookEvent,
+	pub name: String,
+	/// `stdout` or `stderr`.
+	pub stream: &'static str,
+	pub line: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookRun {
+	pub event: HookEvent,
+	pub name: String,
+	pub command: String,
+	pub exit_code: Option<i32>,
+	pub timed_out: bool,
+	pub duration_ms: u64,
+	/// Both streams, interleaved, cut at 64 KiB.
+	pub output: String,
+	/// Set when the hook couldn't be started.
+	pub error: Option<String>,
+}
+
+impl HookRun {
+	pub fn succeeded(&self) -> bool {
+		self.exit_code == Some(0)
+	}
+}
+
+/// `session` with its working directory moved to `directory`, e.g. the
+/// repository a commit is made in.
+pub fn session_at(session: &OsSession, directory: &str) -> OsSession {
+	match session {
+		OsSession::Local(_) => OsSession::Local(directory.to_string()),
+		OsSession::Wsl(wsl) => OsSession::Wsl(WslSession {
+			distribution: wsl.distribution.clone(),
+			working_directory: directory.to_string(),
+		}),
+	}
+}
+
+/// The manifest's hooks, `None` without one.
+fn read_manifest(session: &OsSession) -> Result<Option<Manifest>> {
+	let path = session.host_path().join(MANIFEST_PATH);
+	let content = match fs::read_to_string(&path) {
+		Ok(content) => content,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => {
+			return Err(e).with_context(|| format!("Failed to read {}", path.display()))
+		}
+	};
+	let manifest =
+		toml::from_str(&content).with_context(|| format!("Invalid {}", MANIFEST_PATH))?;
+	Ok(Some(manifest))
+}
+
+/// What is wrong with the hooks of a manifest. Unknown events already fail
+/// to parse.
+fn validate(hooks: &[ProjectHook]) -> Vec<HookProblem> {
+	let mut problems = Vec::new();
+	if hooks.len() > MAX_HOOKS {
+		problems.push(HookProblem {
+			index: None,
+			message: format!("At most {} hooks are allowed", MAX_HOOKS),
+		});
+	}
+
+	let mut names = HashSet::new();
+	for (index, hook) in hooks.iter().enumerate() {
+		let mut problem = |message: String| {
+			problems.push(HookProblem {
+				index: Some(index),
+				message,
+			})
+		};
+		let name = hook.name.trim();
+		if name.is_empty() || name.len() > MAX_NAME_LEN {
+			problem(format!("Hook name must be 1-{} characters", MAX_NAME_LEN));
+		} else if !names.insert((hook.event, name)) {
+			problem(format!(
+				"Another `{}` hook is already named `{}`",
+				hook.event.name(),
+				name
+			));
+		}
+		if hook.command.trim().is_empty() {
+			problem("Hook command is empty".to_string());
+		} else if hook.command.len() > MAX_COMMAND_LEN {
+			problem(format!("Hook command exceeds {} bytes", MAX_COMMAND_LEN));
+		} else if hook.command.contains('\0') {
+			problem("Hook command contains a NUL byte".to_string());
+		}
+		if let Some(timeout) = hook.timeout_seconds {
+			if timeout == 0 || timeout > MAX_TIMEOUT_SECONDS {
+				problem(format!(
+					"timeout_seconds must be between 1 and {}",
+					MAX_TIMEOUT_SECONDS
+				));
+			}
+		}
+		if hook.blocking == Some(true) && !hook.event.can_block() {
+			problem(format!("{} hooks can't be blocking", hook.event.name()));
+		}
+	}
+	problems
+}
+
+/// The hooks of the project in `session`, with the problems of its
+/// manifest.
+pub fn load(session: &OsSession) -> ProjectHooks {
+	match read_manifest(session) {
+		Ok(Some(manifest)) => {
+			let problems = validate(&manifest.hooks);
+			ProjectHooks {
+				valid: problems.is_empty(),
+				hooks: manifest.hooks,
+				problems,
+				error: None,
+			}
+		}
+		Ok(None) => ProjectHooks {
+			hooks: Vec::new(),
+			valid: true,
+			problems: Vec::new(),
+			error: None,
+		},
+		Err(e) => ProjectHooks {
+			hooks: Vec::new(),
+			valid: false,
+			problems: Vec::new(),
+			error: Some(format!("{:#}", e)),
+		},
+	}
+}
+
+/// Runs one hook, emitting its output line by line and killing it once
+/// its timeout passes.
+fn run_hook(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	hook: &ProjectHook,
+	env: &HashMap<String, String>,
+) -> HookRun {
+	let started = Instant::now();
+	let mut run = HookRun {
+		event: hook.event,
+		name: hook.name.clone(),
+		command: hook.command.clone(),
+		exit_code: None,
+		timed_out: false,
+		duration_ms: 0,
+		output: String::new(),
+		error: None,
+	};
+
+	let token = CancelToken::default();
+	let timeout =
+		Duration::from_secs(hook.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS));
+	let timer = {
+		let token = token.clone();
+		tauri::async_runtime::spawn(async move {
+			tokio::time::sleep(timeout).await;
+			token.cancel();
+		})
+	};
+
+	let result = session
+		.build_shell_command(&hook.command)
+		.and_then(|mut cmd| {
+			cmd.envs(env);
+			jobs::stream_lines(&mut cmd, &token, |stream, line| {
+				if run.output.len() < MAX_OUTPUT_BYTES {
+					run.output.push_str(&line);
+					run.output.push('\n');
+				}
+				let _ = app_handle.emit(
+					"project-hook-output",
+					HookOutput {
+						directory: session.get_working_directory().to_string(),
+						event: hook.event,
+						name: hook.name.clone(),
+						stream,
+						line,
+					},
+				);
+			})
+		});
+	timer.abort();
+
+	match result {
+		Ok(status) => run.exit_code = status.code(),
+		Err(e) if e.to_string() == JOB_CANCELLED => {
+			run.timed_out = true;
+			run.error = Some(format!("Timed out after {}s", timeout.as_secs()));
+		}
+		Err(e) => run.error = Some(format!("Failed to run {}: {}", hook.command, e)),
+	}
+	run.duration_ms = started.elapsed().as_millis() as u64;
+	run
+}
+
+/// Runs the project's hooks for `event` in manifest order, with
+/// `ARIANA_HOOK_EVENT`, `ARIANA_PROJECT_DIR` and each `context` entry as
+/// `ARIANA_<KEY>` in their environment. For blocking events, a failing
+/// blocking hook stops the rest and the error carries its output.
+pub async fn run(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	event: HookEvent,
+	context: HashMap<String, String>,
+) -> Result<Vec<HookRun>> {
+	let project = load(session);
+	let hooks: Vec<_> = project
+		.hooks
+		.into_iter()
+		.filter(|hook| hook.event == event)
+		.collect();
+	if hooks.is_empty() {
+		return Ok(Vec::new());
+	}
+	if let Some(error) = project.error {
+		bail!("{}", error);
+	}
+	if !project.valid {
+		let problems: Vec<_> = project
+			.problems
+			.iter()
+			.map(|problem| problem.message.as_str())
+			.collect();
+		bail!("The project hooks were rejected: {}", problems.join("; "));
+	}
+	// Hooks are the repository's code
+	trust::ensure_trusted(app_handle, session, session.get_working_directory())
+		.map_err(|e| anyhow!(e))?;
+
+	let mut env: HashMap<String, String> = context
+		.into_iter()
+		.map(|(key, value)| (format!("ARIANA_{}", key.to_uppercase()), value))
+		.collect();
+	env.insert("ARIANA_HOOK_EVENT".to_string(), event.name().to_string());
+	env.insert(
+		"ARIANA_PROJECT_DIR".to_string(),
+		session.get_working_directory().to_string(),
+	);
+
+	let app_handle = app_handle.clone();
+	let session = session.clone();
+	tauri::async_runtime::spawn_blocking(move || {
+		let mut runs = Vec::new();
+		for hook in &hooks {
+			let run = run_hook(&app_handle, &session, hook, &env);
+			let failed = !run.succeeded();
+			if failed && hook.blocking.unwrap_or(false) {
+				let reason = run.error.clone().unwrap_or_else(|| {
+					format!(
+						"exited with code {}",
+						run.exit_code
+							.map_or("unknown".to_string(), |code| code.to_string())
+					)
+				});
+				return Err(anyhow!("{} {}\n{}", hook.name, reason, run.output));
+			}
+			if failed {
+				log::warn!(
+					"{} hook {} failed in {}",
+					event.name(),
+					hook.name,
+					session.get_working_directory()
+				);
+			}
+			runs.push(run);
+		}
+		Ok(runs)
+	})
+	.await?
+}
+
+/// Runs the hooks of a non-blocking event without waiting for them, for
+/// callers that don't report on them.
+pub fn run_in_background(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	event: HookEvent,
+	context: HashMap<String, String>,
+) {
+	let (app_handle, session) = (app_handle.clone(), session.clone());
+	tauri::async_runtime::spawn(async move {
+		if let Err(e) = run(&app_handle, &session, event, context).await {
+			log::warn!("{} hooks failed: {:#}", event.name(), e);
+		}
+	});
+}
+
+/// The hooks in the project's `.ariana/hooks.toml` and what is wrong with
+/// them, if anything.
+#[tauri::command]
+pub async fn list_project_hooks(os_session: OsSession) -> Result<ProjectHooks, String> {
+	tauri::async_runtime::spawn_blocking(move || load(&os_session))
+		.await
+		.map_err(|e| e.to_string())
+}
+
+/// Runs the hooks of `event`, for the events the frontend drives:
+/// `project-open` and `agent-task-complete`.
+#[tauri::command]
+pub async fn run_project_hooks(
+	os_session: OsSession,
+	event: HookEvent,
+	context: Option<HashMap<String, String>>,
+	app_handle: AppHandle,
+) -> Result<Vec<HookRun>, String> {
+	run(&app_handle, &os_session, event, context.unwrap_or_default())
+		.await
+		.map_err(|e| format!("{:#}", e))
+}
@@ -0,0 +1,402 @@
+use crate::backend_client::BackendClient;
+use anyhow::{anyhow, Context, Result};
+use reqwest::{Method, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+/// A recent-project entry as shared between devices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectMetadata {
+	pub project_id: String,
+	pub name: String,
+	pub repo_url: Option<String>,
+	pub last_opened_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SyncProjectsRequest<'a> {
+	projects: &'a [ProjectMetadata],
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectsResponse {
+	projects: Vec<ProjectMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+	error: String,
+}
+
+/// Turns an unsuccessful response into an error carrying the backend's
+/// message.
+async fn check(response: Response, what: &str) -> Result<Response> {
+	let status = response.status();
+	if status.is_success() {
+		return Ok(response);
+	}
+	Err(match response.json::<ErrorResponse>().await {
+		Ok(body) => anyhow!("{} failed ({}): {}", what, status, body.error),
+		Err(_) => anyhow!("{} failed ({})", what, status),
+	})
+}
+
+async fn sync(
+	client: &BackendClient,
+	projects: &[ProjectMetadata],
+) -> Result<Vec<ProjectMetadata>> {
+	let response = client
+		.request(Method::POST, "/api/projects/sync")
+		.json(&SyncProjectsRequest { projects })
+		.send()
+		.await?;
+	let response = check(response, "Project sync").await?;
+
+	Ok(response.json::<ProjectsResponse>().await?.projects)
+}
+
+/// Uploads the local recent-projects list and returns the merged list for the
+/// account, most recently opened first.
+#[tauri::command]
+pub async fn sync_projects(
+	app_handle: tauri::AppHandle,
+	projects: Vec<ProjectMetadata>,
+) -> Result<Vec<ProjectMetadata>, String> {
+	let client = BackendClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	sync(&client, &projects).await.map_err(|e| e.to_string())
+}
+
+/// This installation as seen by the backend. The id is generated once and
+/// kept in the app data directory; the name is the host name.
+struct Device {
+	id: String,
+	name: String,
+}
+
+fn device(app_handle: &AppHandle) -> Result<Device> {
+	let path = app_handle.path().app_data_dir()?.join("device-id");
+	let id = match fs::read_to_string(&path) {
+		Ok(id) if !id.trim().is_empty() => id.trim().to_string(),
+		_ => {
+			let id = Uuid::new_v4().to_string();
+			if let Some(dir) = path.parent() {
+				fs::create_dir_all(dir)?;
+			}
+			fs::write(&path, &id)
+				.with_context(|| format!("Failed to write {}", path.display()))?;
+			id
+		}
+	};
+	let name = gethostname::gethostname().to_string_lossy().into_owned();
+	Ok(Device {
+		id,
+		name: if name.is_empty() {
+			"Unknown device".to_string()
+		} else {
+			name
+		},
+	})
+}
+
+/// `segments` joined into a request path, each one escaped.
+fn escaped_path(segments: &[&str]) -> String {
+	let mut url = Url::parse("http://localhost").expect("valid base URL");
+	url.path_segments_mut()
+		.expect("base URL has a path")
+		.extend(segments);
+	url.path().to_string()
+}
+
+fn lock_path(project_id: &str, branch: &str) -> String {
+	let mut segments = vec!["api", "projects", project_id, "locks"];
+	segments.extend(branch.split('/'));
+	escaped_path(&segments)
+}
+
+/// A project, or one of its canvases, open on one of the account's devices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceEntry {
+	pub device_id: String,
+	pub device_name: String,
+	pub project_id: String,
+	pub canvas_id: Option<String>,
+	pub updated_at: String,
+	/// Set for the entries of this installation.
+	#[serde(default)]
+	pub current_device: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenEntry {
+	pub project_id: String,
+	pub canvas_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PutPresenceRequest<'a> {
+	device_name: &'a str,
+	open: &'a [OpenEntry],
+}
+
+#[derive(Debug, Deserialize)]
+struct PresenceResponse {
+	presence: Vec<PresenceEntry>,
+}
+
+/// An advisory lock on a canvas branch, so two devices of the account don't
+/// run agents on the same branch at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchLock {
+	pub project_id: String,
+	pub branch: String,
+	pub device_id: String,
+	pub device_name: String,
+	pub canvas_id: Option<String>,
+	pub acquired_at: String,
+	pub expires_at: String,
+	/// Set when this installation holds the lock.
+	#[serde(default)]
+	pub current_device: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct BranchLocksResponse {
+	locks: Vec<BranchLock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BranchLockConflict {
+	holder: BranchLock,
+}
+
+/// Result of trying to take a branch lock: `lock` is ours when `acquired`,
+/// and the other device's otherwise.
+#[derive(Debug, Clone, Serialize)]
+pub struct BranchLockOutcome {
+	pub acquired: bool,
+	pub lock: BranchLock,
+}
+
+#[derive(Debug, Serialize)]
+struct AcquireLockRequest<'a> {
+	device_id: &'a str,
+	device_name: &'a str,
+	canvas_id: Option<&'a str>,
+	ttl_seconds: Option<i64>,
+}
+
+/// Backend client for the presence and lock requests of this device.
+struct DeviceClient {
+	client: BackendClient,
+	device: Device,
+}
+
+impl DeviceClient {
+	fn from_app(app_handle: &AppHandle) -> Result<Self> {
+		Ok(Self {
+			client: BackendClient::from_app(app_handle)?,
+			device: device(app_handle)?,
+		})
+	}
+
+	fn mark_presence(&self, mut presence: Vec<PresenceEntry>) -> Vec<PresenceEntry> {
+		for entry in &mut presence {
+			entry.current_device = entry.device_id == self.device.id;
+		}
+		presence
+	}
+
+	fn mark_lock(&self, mut lock: BranchLock) -> BranchLock {
+		lock.current_device = lock.device_id == self.device.id;
+		lock
+	}
+
+	async fn update_presence(&self, open: &[OpenEntry]) -> Result<Vec<PresenceEntry>> {
+		let response = self
+			.client
+			.request(
+				Method::PUT,
+				&escaped_path(&["api", "presence", &self.device.id]),
+			)
+			.json(&PutPresenceRequest {
+				device_name: &self.device.name,
+				open,
+			})
+			.send()
+			.await?;
+		let response = check(response, "Presence update").await?;
+		let presence = response.json::<PresenceResponse>().await?.presence;
+		Ok(self.mark_presence(presence))
+	}
+
+	async fn list_presence(&self) -> Result<Vec<PresenceEntry>> {
+		let response = self
+			.client
+			.request(Method::GET, "/api/presence")
+			.send()
+			.await?;
+		let response = check(response, "Presence listing").await?;
+		let presence = response.json::<PresenceResponse>().await?.presence;
+		Ok(self.mark_presence(presence))
+	}
+
+	async fn clear_presence(&self) -> Result<()> {
+		let response = self
+			.client
+			.request(
+				Method::DELETE,
+				&escaped_path(&["api", "presence", &self.device.id]),
+			)
+			.send()
+			.await?;
+		check(response, "Presence update").await?;
+		Ok(())
+	}
+
+	async fn list_locks(&self, project_id: &str) -> Result<Vec<BranchLock>> {
+		let response = self
+			.client
+			.request(
+				Method::GET,
+				&escaped_path(&["api", "projects", project_id, "locks"]),
+			)
+			.send()
+			.await?;
+		let response = check(response, "Branch lock listing").await?;
+		let locks = response.json::<BranchLocksResponse>().await?.locks;
+		Ok(locks.into_iter().map(|lock| self.mark_lock(lock)).collect())
+	}
+
+	async fn acquire_lock(
+		&self,
+		project_id: &str,
+		branch: &str,
+		canvas_id: Option<&str>,
+		ttl_seconds: Option<i64>,
+	) -> Result<BranchLockOutcome> {
+		let response = self
+			.client
+			.request(Method::PUT, &lock_path(project_id, branch))
+			.json(&AcquireLockRequest {
+				device_id: &self.device.id,
+				device_name: &self.device.name,
+				canvas_id,
+				ttl_seconds,
+			})
+			.send()
+			.await?;
+		if response.status() == StatusCode::CONFLICT {
+			let conflict = response.json::<BranchLockConflict>().await?;
+			return Ok(BranchLockOutcome {
+				acquired: false,
+				lock: self.mark_lock(conflict.holder),
+			});
+		}
+		let response = check(response, "Branch lock").await?;
+		let lock = response.json::<BranchLock>().await?;
+		Ok(BranchLockOutcome {
+			acquired: true,
+			lock: self.mark_lock(lock),
+		})
+	}
+
+	/// Releasing a lock that is already gone succeeds.
+	async fn release_lock(
+		&self,
+		project_id: &str,
+		branch: &str,
+		force: bool,
+	) -> Result<()> {
+		let response = self
+			.client
+			.request(Method::DELETE, &lock_path(project_id, branch))
+			.query(&[
+				("device_id", self.device.id.as_str()),
+				("force", if force { "true" } else { "false" }),
+			])
+			.send()
+			.await?;
+		if response.status() == StatusCode::NOT_FOUND {
+			return Ok(());
+		}
+		check(response, "Branch lock release").await?;
+		Ok(())
+	}
+}
+
+/// Reports everything open on this device, replacing the previous report,
+/// and returns the presence of all the account's devices. Called
+/// periodically as a heartbeat: presence not refreshed for two minutes
+/// expires.
+#[tauri::command]
+pub async fn update_presence(
+	app_handle: AppHandle,
+	open: Vec<OpenEntry>,
+) -> Result<Vec<PresenceEntry>, String> {
+	let client = DeviceClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	client
+		.update_presence(&open)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_presence(app_handle: AppHandle) -> Result<Vec<PresenceEntry>, String> {
+	let client = DeviceClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	client.list_presence().await.map_err(|e| e.to_string())
+}
+
+/// Clears this device's presence, e.g. when the app quits.
+#[tauri::command]
+pub async fn clear_presence(app_handle: AppHandle) -> Result<(), String> {
+	let client = DeviceClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	client.clear_presence().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_branch_locks(
+	app_handle: AppHandle,
+	project_id: String,
+) -> Result<Vec<BranchLock>, String> {
+	let client = DeviceClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	client
+		.list_locks(&project_id)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+/// Takes the lock on a canvas branch before running an agent on it, or
+/// refreshes it while the agent runs. Locks expire after `ttl_seconds`
+/// (5 minutes by default) unless refreshed.
+#[tauri::command]
+pub async fn acquire_branch_lock(
+	app_handle: AppHandle,
+	project_id: String,
+	branch: String,
+	canvas_id: Option<String>,
+	ttl_seconds: Option<i64>,
+) -> Result<BranchLockOutcome, String> {
+	let client = DeviceClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	client
+		.acquire_lock(&project_id, &branch, canvas_id.as_deref(), ttl_seconds)
+		.await
+		.map_err(|e| e.to_string())
+}
+
+/// Releases a branch lock held by this device; `force` also releases
+/// another device's lock.
+#[tauri::command]
+pub async fn release_branch_lock(
+	app_handle: AppHandle,
+	project_id: String,
+	branch: String,
+	force: Option<bool>,
+) -> Result<(), String> {
+	let client = DeviceClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	client
+		.release_lock(&project_id, &branch, force.unwrap_or(false))
+		.await
+		.map_err(|e| e.to_string())
+}
@@ -0,0 +1,764 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use walkdir::WalkDir;
+
+use crate::backend_client;
+use crate::file_watcher::FileWatcher;
+use crate::index_manager::{is_ignored_dir, modified_millis, IndexState};
+use crate::watched_workspaces::{
+	covers, has_files_under, WatchedWorkspace, WatchedWorkspaces,
+};
+
+/// Bumped whenever the persisted format or the chunking changes, so stale
+/// indexes on disk are rebuilt instead of loaded.
+const INDEX_FORMAT_VERSION: u32 = 1;
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+/// Lines per chunk. Consecutive chunks overlap so code near a boundary is
+/// found through either of them.
+const CHUNK_LINES: usize = 60;
+const CHUNK_OVERLAP: usize = 10;
+/// Chunks made of a few very long lines are cut to stay within the
+/// embedding model's input limit.
+const MAX_CHUNK_CHARS: usize = 6000;
+/// Files read and embedded together, which is also how often progress is
+/// reported.
+const FILES_PER_BATCH: usize = 32;
+/// Chunks sent per `/api/embeddings` request.
+const EMBED_BATCH: usize = 128;
+/// Longer than the symbol index's, since every update costs a request.
+const UPDATE_DEBOUNCE: Duration = Duration::from_secs(2);
+const DEFAULT_RESULTS: usize = 10;
+const MAX_RESULTS: usize = 100;
+/// Extensions of files worth embedding: source code and docs.
+const TEXT_EXTENSIONS: &[&str] = &[
+	"rs", "ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs", "py", "pyi", "go",
+	"java", "kt", "kts", "scala", "c", "h", "cc", "cpp", "hpp", "cs", "fs", "rb", "php",
+	"swift", "m", "mm", "lua", "dart", "ex", "exs", "erl", "hs", "ml", "clj", "sh",
+	"bash", "zsh", "ps1", "sql", "vue", "svelte", "html", "css", "scss", "md", "mdx",
+	"toml", "yaml", "yml",
+];
+
+/// Embedding model to index with, sent to `/api/embeddings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingModel {
+	pub provider: String,
+	pub model: String,
+	pub api_key: Option<String>,
+	/// Vault key id, which requires being logged in.
+	pub key_id: Option<String>,
+	pub org_id: Option<String>,
+}
+
+impl EmbeddingModel {
+	/// Identifies the vector space, so indexes built with another model are
+	/// not mixed in.
+	fn id(&self) -> String {
+		format!("{}/{}", self.provider, self.model)
+	}
+}
+
+/// A chunk of a file that matched a query.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticHit {
+	pub path: String,
+	/// Zero-based, inclusive.
+	pub start_line: u32,
+	/// Zero-based, exclusive.
+	pub end_line: u32,
+	/// Cosine similarity with the query.
+	pub score: f32,
+}
+
+/// Payload of the `semantic-index-status` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticIndexStatus {
+	pub root: String,
+	pub state: IndexState,
+	pub file_count: usize,
+	pub chunk_count: usize,
+	/// Last embeddings failure, cleared by the next successful request.
+	/// Files that failed are retried when they change again.
+	pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChunkEntry {
+	start_line: u32,
+	end_line: u32,
+	#[serde(with = "vector_base64")]
+	vector: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileEntry {
+	size: u64,
+	/// Modification time in milliseconds since the epoch.
+	modified: u64,
+	/// Empty for files that aren't UTF-8 text.
+	chunks: Vec<ChunkEntry>,
+}
+
+/// What is persisted for a workspace, keyed by path relative to the root.
+#[derive(Serialize, Deserialize)]
+struct PersistedIndex {
+	version: u32,
+	root: PathBuf,
+	model: String,
+	files: HashMap<PathBuf, FileEntry>,
+}
+
+/// Vectors are stored as base64 little-endian `f32`s, a fraction of the size
+/// of JSON numbers.
+mod vector_base64 {
+	use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+	use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(
+		vector: &[f32],
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		let bytes: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+		serializer.serialize_str(&BASE64.encode(bytes))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Vec<f32>, D::Error> {
+		let bytes = BASE64
+			.decode(String::deserialize(deserializer)?)
+			.map_err(D::Error::custom)?;
+		Ok(bytes
+			.chunks_exact(4)
+			.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+			.collect())
+	}
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+	provider: &'a str,
+	model: &'a str,
+	input: &'a [String],
+	api_key: Option<&'a str>,
+	key_id: Option<&'a str>,
+	org_id: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+	embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+	error: String,
+}
+
+/// Embeds `input` through the backend, returning unit vectors so scores are
+/// plain dot products.
+async fn embed(
+	app_handle: &AppHandle,
+	model: &EmbeddingModel,
+	input: &[String],
+) -> Result<Vec<Vec<f32>>> {
+	let mut embeddings = Vec::with_capacity(input.len());
+	for batch in input.chunks(EMBED_BATCH) {
+		let response = backend_client::optional_auth_request(
+			app_handle,
+			Method::POST,
+			"/api/embeddings",
+		)
+		.json(&EmbeddingsRequest {
+			provider: &model.provider,
+			model: &model.model,
+			input: batch,
+			api_key: model.api_key.as_deref(),
+			key_id: model.key_id.as_deref(),
+			org_id: model.org_id.as_deref(),
+		})
+		.send()
+		.await?;
+
+		let status = response.status();
+		if !status.is_success() {
+			return Err(match response.json::<ErrorResponse>().await {
+				Ok(body) => {
+					anyhow!("Embeddings request failed ({}): {}", status, body.error)
+				}
+				Err(_) => anyhow!("Embeddings request failed ({})", status),
+			});
+		}
+
+		let vectors = response.json::<EmbeddingsResponse>().await?.embeddings;
+		if vectors.len() != batch.len() {
+			return Err(anyhow!(
+				"Expected {} embeddings, got {}",
+				batch.len(),
+				vectors.len()
+			));
+		}
+		embeddings.extend(vectors.into_iter().map(normalized));
+	}
+	Ok(embeddings)
+}
+
+fn normalized(mut vector: Vec<f32>) -> Vec<f32> {
+	let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+	if norm > 0.0 {
+		vector.iter_mut().for_each(|v| *v /= norm);
+	}
+	vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+	a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn is_text_file(path: &Path) -> bool {
+	path.extension()
+		.and_then(|e| e.to_str())
+		.map(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+		.unwrap_or(false)
+}
+
+struct TextChunk {
+	start_line: u32,
+	end_line: u32,
+	text: String,
+}
+
+/// Splits a file into overlapping line windows. Each chunk starts with the
+/// file's path, which often says as much about the code as the code itself.
+fn chunk_text(relative: &Path, content: &str) -> Vec<TextChunk> {
+	let lines: Vec<&str> = content.lines().collect();
+	let mut chunks = Vec::new();
+	let mut start = 0;
+	while start < lines.len() {
+		let end = (start + CHUNK_LINES).min(lines.len());
+		let body = lines[start..end].join("\n");
+		if !body.trim().is_empty() {
+			let mut text = format!("{}\n{}", relative.display(), body);
+			if text.len() > MAX_CHUNK_CHARS {
+				let mut cut = MAX_CHUNK_CHARS;
+				while !text.is_char_boundary(cut) {
+					cut -= 1;
+				}
+				text.truncate(cut);
+			}
+			chunks.push(TextChunk {
+				start_line: start as u32,
+				end_line: end as u32,
+				text,
+			});
+		}
+		if end == lines.len() {
+			break;
+		}
+		start = end - CHUNK_OVERLAP;
+	}
+	chunks
+}
+
+/// A file read for embedding.
+struct PendingFile {
+	relative: PathBuf,
+	size: u64,
+	modified: u64,
+	chunks: Vec<TextChunk>,
+}
+
+enum FileCheck {
+	Unchanged,
+	/// New or modified since it was embedded.
+	Stale,
+	/// Its entry was dropped.
+	Removed,
+}
+
+struct Workspace {
+	root: PathBuf,
+	model: EmbeddingModel,
+	files: Mutex<HashMap<PathBuf, FileEntry>>,
+	state: Mutex<IndexState>,
+	error: Mutex<Option<String>>,
+	/// Set when the workspace is closed, to stop an index build early.
+	closed: AtomicBool,
+}
+
+impl WatchedWorkspace for Workspace {
+	fn close(&self) {
+		self.closed.store(true, Ordering::Relaxed);
+	}
+}
+
+impl Workspace {
+	fn status(&self) -> SemanticIndexStatus {
+		let files = self.files.lock().unwrap();
+		SemanticIndexStatus {
+			root: self.root.to_string_lossy().to_string(),
+			state: *self.state.lock().unwrap(),
+			file_count: files.len(),
+			chunk_count: files.values().map(|f| f.chunks.len()).sum(),
+			error: self.error.lock().unwrap().clone(),
+		}
+	}
+
+	/// Compares `path` with its entry, dropping the entry if the file is gone
+	/// or no longer worth indexing.
+	fn check_file(&self, path: &Path) -> FileCheck {
+		let Ok(relative) = path.strip_prefix(&self.root) else {
+			return FileCheck::Unchanged;
+		};
+		let metadata = fs::metadata(path)
+			.ok()
+			.filter(|m| m.is_file() && m.len() <= MAX_FILE_BYTES && is_text_file(path));
+
+		let Some(metadata) = metadata else {
+			return match self.files.lock().unwrap().remove(relative) {
+				Some(_) => FileCheck::Removed,
+				None => FileCheck::Unchanged,
+			};
+		};
+		match self.files.lock().unwrap().get(relative) {
+			Some(entry)
+				if entry.size == metadata.len()
+					&& entry.modified == modified_millis(&metadata) =>
+			{
+				FileCheck::Unchanged
+			}
+			_ => FileCheck::Stale,
+		}
+	}
+
+	/// Walks `dir`, returning the files that need embedding and whether
+	/// entries for files that disappeared were dropped.
+	fn scan(&self, dir: &Path) -> (Vec<PathBuf>, bool) {
+		let mut stale = Vec::new();
+		let mut removed = false;
+		let mut seen = HashSet::new();
+		for entry in WalkDir::new(dir)
+			.into_iter()
+			.filter_entry(|e| !is_ignored_dir(e))
+			.filter_map(|e| e.ok())
+		{
+			if self.closed.load(Ordering::Relaxed) {
+				return (stale, removed);
+			}
+			if !entry.file_type().is_file() || !is_text_file(entry.path()) {
+				continue;
+			}
+			match self.check_file(entry.path()) {
+				FileCheck::Stale => stale.push(entry.path().to_path_buf()),
+				FileCheck::Removed => removed = true,
+				FileCheck::Unchanged => {}
+			}
+			seen.insert(entry.into_path());
+		}
+
+		let Ok(relative_dir) = dir.strip_prefix(&self.root) else {
+			return (stale, removed);
+		};
+		let mut files = self.files.lock().unwrap();
+		let before = files.len();
+		files.retain(|relative, _| {
+			!relative.starts_with(relative_dir)
+				|| seen.contains(&self.root.join(relative))
+		});
+		(stale, removed || files.len() != before)
+	}
+
+	/// Like `scan`, for the paths reported by the file watcher.
+	fn check_changes(&self, paths: &HashSet<PathBuf>) -> (Vec<PathBuf>, bool) {
+		let mut stale = Vec::new();
+		let mut removed = false;
+		for path in paths.iter().filter(|p| covers(&self.root, p)) {
+			// A directory that appeared, moved or vanished changes every
+			// file below it
+			if path.is_dir()
+				|| has_files_under(&self.root, &*self.files.lock().unwrap(), path)
+			{
+				let (dir_stale, dir_removed) = self.scan(path);
+				stale.extend(dir_stale);
+				removed |= dir_removed;
+				continue;
+			}
+			match self.check_file(path) {
+				FileCheck::Stale => stale.push(path.clone()),
+				FileCheck::Removed => removed = true,
+				FileCheck::Unchanged => {}
+			}
+		}
+		(stale, removed)
+	}
+
+	/// Reads and chunks `path`, or `None` if it can no longer be read.
+	fn prepare(&self, path: &Path) -> Option<PendingFile> {
+		let relative = path.strip_prefix(&self.root).ok()?.to_path_buf();
+		let metadata = fs::metadata(path).ok()?;
+		let bytes = fs::read(path).ok()?;
+		// Binary files keep an empty entry so they aren't read again
+		let chunks = match String::from_utf8(bytes) {
+			Ok(content) => chunk_text(&relative, &content),
+			Err(_) => Vec::new(),
+		};
+		Some(PendingFile {
+			relative,
+			size: metadata.len(),
+			modified: modified_millis(&metadata),
+			chunks,
+		})
+	}
+
+	/// Stores embedded files; `vectors` holds their chunks' vectors in order.
+	fn insert(&self, pending: Vec<PendingFile>, vectors: Vec<Vec<f32>>) {
+		let mut vectors = vectors.into_iter();
+		let mut files = self.files.lock().unwrap();
+		for file in pending {
+			let chunks = file
+				.chunks
+				.into_iter()
+				.zip(vectors.by_ref())
+				.map(|(chunk, vector)| ChunkEntry {
+					start_line: chunk.start_line,
+					end_line: chunk.end_line,
+					vector,
+				})
+				.collect();
+			files.insert(
+				file.relative,
+				FileEntry {
+					size: file.size,
+					modified: file.modified,
+					chunks,
+				},
+			);
+		}
+	}
+}
+
+/// Embedding index of open workspaces for "find code that does X" queries.
+/// Files are split into line chunks, embedded through the backend and kept
+/// in memory; the index is persisted in the app cache and kept current from
+/// file watcher events.
+pub struct SemanticIndexManager {
+	app_handle: AppHandle,
+	workspaces: WatchedWorkspaces<Workspace>,
+}
+
+impl SemanticIndexManager {
+	pub fn new(app_handle: AppHandle, watcher: Arc<FileWatcher>) -> Arc<Self> {
+		let manager = Arc::new(Self {
+			app_handle,
+			workspaces: WatchedWorkspaces::new(watcher),
+		});
+		manager.workspaces.watch_changes(
+			&manager,
+			UPDATE_DEBOUNCE,
+			|manager, paths, rescan| async move { manager.apply_changes(paths, rescan).await },
+		);
+		manager
+	}
+
+	fn index_path(&self, root: &Path) -> Result<PathBuf> {
+		let mut hasher = DefaultHasher::new();
+		root.hash(&mut hasher);
+		Ok(self
+			.app_handle
+			.path()
+			.app_cache_dir()?
+			.join("semantic-index")
+			.join(format!("{:016x}.json", hasher.finish())))
+	}
+
+	fn load(&self, root: &Path, model: &EmbeddingModel) -> HashMap<PathBuf, FileEntry> {
+		self.index_path(root)
+			.ok()
+			.and_then(|path| fs::read(path).ok())
+			.and_then(|bytes| serde_json::from_slice::<PersistedIndex>(&bytes).ok())
+			.filter(|index| {
+				index.version == INDEX_FORMAT_VERSION
+					&& index.root == root
+					&& index.model == model.id()
+			})
+			.map(|index| index.files)
+			.unwrap_or_default()
+	}
+
+	fn persist(&self, workspace: &Workspace) -> Result<()> {
+		let path = self.index_path(&workspace.root)?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let index = PersistedIndex {
+			version: INDEX_FORMAT_VERSION,
+			root: workspace.root.clone(),
+			model: workspace.model.id(),
+			files: workspace.files.lock().unwrap().clone(),
+		};
+		fs::write(&path, serde_json::to_vec(&index)?)
+			.with_context(|| format!("Failed to write {}", path.display()))
+	}
+
+	fn emit_status(&self, workspace: &Workspace) {
+		let _ = self
+			.app_handle
+			.emit("semantic-index-status", workspace.status());
+	}
+
+	/// Starts indexing `root` in the background, picking up from the index
+	/// persisted by a previous session. Indexing with another model than the
+	/// open index starts over.
+	pub fn index_workspace(
+		self: &Arc<Self>,
+		root: &str,
+		model: EmbeddingModel,
+	) -> Result<SemanticIndexStatus> {
+		let model_id = model.id();
+		let (workspace, created) = self.workspaces.open(
+			root,
+			|workspace| workspace.model.id() == model_id,
+			|root| Workspace {
+				files: Mutex::new(self.load(&root, &model)),
+				root,
+				model,
+				state: Mutex::new(IndexState::Indexing),
+				error: Mutex::new(None),
+				closed: AtomicBool::new(false),
+			},
+		)?;
+		let status = workspace.status();
+		if !created {
+			return Ok(status);
+		}
+
+		let manager = self.clone();
+		tauri::async_runtime::spawn(async move {
+			let scanned = workspace.clone();
+			let Ok((stale, _)) =
+				tauri::async_runtime::spawn_blocking(move || scanned.scan(&scanned.root))
+					.await
+			else {
+				return;
+			};
+			manager.embed_files(&workspace, stale).await;
+			if workspace.closed.load(Ordering::Relaxed) {
+				return;
+			}
+			*workspace.state.lock().unwrap() = IndexState::Ready;
+			if let Err(e) = manager.persist(&workspace) {
+				log::warn!("Failed to persist semantic index: {}", e);
+			}
+			manager.emit_status(&workspace);
+		});
+		Ok(status)
+	}
+
+	/// Stops indexing and watching `root`. The persisted index is kept for
+	/// the next time the workspace is opened.
+	pub fn close_workspace(&self, root: &str) -> Result<()> {
+		self.workspaces.close(root)
+	}
+
+	pub fn status(&self, root: &str) -> Option<SemanticIndexStatus> {
+		self.workspaces
+			.get(root)
+			.map(|workspace| workspace.status())
+	}
+
+	/// Reads, embeds and stores `paths` in batches. Stops at the first
+	/// failed request, leaving the rest for the next change. Returns whether
+	/// the index changed.
+	async fn embed_files(&self, workspace: &Arc<Workspace>, paths: Vec<PathBuf>) -> bool {
+		let mut changed = false;
+		for group in paths.chunks(FILES_PER_BATCH) {
+			if workspace.closed.load(Ordering::Relaxed) {
+				break;
+			}
+			let reader = workspace.clone();
+			let group = group.to_vec();
+			let Ok(mut pending) = tauri::async_runtime::spawn_blocking(move || {
+				group
+					.iter()
+					.filter_map(|path| reader.prepare(path))
+					.collect::<Vec<_>>()
+			})
+			.await
+			else {
+				break;
+			};
+
+			let input: Vec<String> = pending
+				.iter_mut()
+				.flat_map(|file| file.chunks.iter_mut())
+				.map(|chunk| std::mem::take(&mut chunk.text))
+				.collect();
+			let vectors = if input.is_empty() {
+				Vec::new()
+			} else {
+				match embed(&self.app_handle, &workspace.model, &input).await {
+					Ok(vectors) => vectors,
+					Err(e) => {
+						log::warn!("Failed to embed {}: {}", workspace.root.display(), e);
+						*workspace.error.lock().unwrap() = Some(e.to_string());
+						self.emit_status(workspace);
+						return changed;
+					}
+				}
+			};
+
+			*workspace.error.lock().unwrap() = None;
+			workspace.insert(pending, vectors);
+			changed = true;
+			self.emit_status(workspace);
+		}
+		changed
+	}
+
+	/// Finds the `k` chunks closest to `query`. Searches every open
+	/// workspace unless `root` is given.
+	pub async fn search(
+		&self,
+		query: &str,
+		k: Option<usize>,
+		root: Option<&str>,
+	) -> Result<Vec<SemanticHit>> {
+		let query = query.trim();
+		if query.is_empty() {
+			return Ok(Vec::new());
+		}
+		let k = k.unwrap_or(DEFAULT_RESULTS).clamp(1, MAX_RESULTS);
+		let root = root.and_then(|root| fs::canonicalize(root).ok());
+
+		let workspaces: Vec<_> = self
+			.workspaces
+			.all()
+			.into_iter()
+			.filter(|w| root.as_ref().is_none_or(|root| *root == w.root))
+			.collect();
+		if workspaces.is_empty() {
+			return Err(anyhow!("No semantic index is open for this workspace"));
+		}
+
+		// Workspaces indexed with the same model share one query embedding
+		let mut query_vectors: HashMap<String, Vec<f32>> = HashMap::new();
+		let mut hits = Vec::new();
+		for workspace in &workspaces {
+			let model_id = workspace.model.id();
+			if !query_vectors.contains_key(&model_id) {
+				let vector =
+					embed(&self.app_handle, &workspace.model, &[query.to_string()])
+						.await?
+						.remove(0);
+				query_vectors.insert(model_id.clone(), vector);
+			}
+			let query_vector = &query_vectors[&model_id];
+
+			let files = workspace.files.lock().unwrap();
+			for (relative, entry) in files.iter() {
+				for chunk in &entry.chunks {
+					hits.push(SemanticHit {
+						path: workspace.root.join(relative).to_string_lossy().to_string(),
+						start_line: chunk.start_line,
+						end_line: chunk.end_line,
+						score: dot(query_vector, &chunk.vector),
+					});
+				}
+			}
+		}
+
+		hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+		hits.truncate(k);
+		Ok(hits)
+	}
+
+	async fn apply_changes(&self, paths: HashSet<PathBuf>, rescan: bool) {
+		let workspaces = self.workspaces.all();
+		let paths = Arc::new(paths);
+
+		for workspace in workspaces {
+			// Let the initial build pick these up
+			if *workspace.state.lock().unwrap() != IndexState::Ready {
+				continue;
+			}
+
+			let checked = workspace.clone();
+			let paths = paths.clone();
+			let Ok((stale, removed)) = tauri::async_runtime::spawn_blocking(move || {
+				if rescan {
+					checked.scan(&checked.root)
+				} else {
+					checked.check_changes(&paths)
+				}
+			})
+			.await
+			else {
+				continue;
+			};
+
+			let embedded = self.embed_files(&workspace, stale).await;
+			if embedded || removed {
+				if let Err(e) = self.persist(&workspace) {
+					log::warn!("Failed to persist semantic index: {}", e);
+				}
+				self.emit_status(&workspace);
+			}
+		}
+	}
+}
+
+/// Starts building the semantic index of a workspace in the background;
+/// progress is reported with `semantic-index-status` events.
+#[tauri::command]
+pub async fn index_semantic_workspace(
+	root: String,
+	model: EmbeddingModel,
+	manager: State<'_, Arc<SemanticIndexManager>>,
+) -> Result<SemanticIndexStatus, String> {
+	manager
+		.index_workspace(&root, model)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn close_semantic_index(
+	root: String,
+	manager: State<'_, Arc<SemanticIndexManager>>,
+) -> Result<(), String> {
+	manager.close_workspace(&root).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_semantic_index_status(
+	root: String,
+	manager: State<'_, Arc<SemanticIndexManager>>,
+) -> Result<Option<SemanticIndexStatus>, String> {
+	Ok(manager.status(&root))
+}
+
+/// The `k` chunks of indexed code closest in meaning to `query`, best first.
+#[tauri::command]
+pub async fn semantic_search(
+	query: String,
+	k: Option<usize>,
+	root: Option<String>,
+	manager: State<'_, Arc<SemanticIndexManager>>,
+) -> Result<Vec<SemanticHit>, String> {
+	manager
+		.search(&query, k, root.as_deref())
+		.await
+		.map_err(|e| e.to_string())
+}
@@ -0,0 +1,222 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, State};
+
+use crate::document_manager::{self, DocumentManager};
+use crate::os::OsSession;
+use crate::text_encoding::FileFormat;
+use crate::util::now_millis;
+
+/// How often the session is written to disk while the app runs.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// What the frontend shows, reported through `update_session`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UiState {
+	/// Path of the open project.
+	pub project: Option<String>,
+	/// Id of the open multi-root workspace, if any.
+	pub workspace: Option<String>,
+	/// The canvas layout, stored as the frontend sends it.
+	pub canvas_layout: Option<Value>,
+	pub terminals: Vec<TerminalSpec>,
+}
+
+/// Enough to start an equivalent terminal again. The shell's scrollback and
+/// running programs are not kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalSpec {
+	pub os_session: OsSession,
+	#[serde(default)]
+	pub title: Option<String>,
+	/// Started with `custom_connect_terminal` rather than `create_terminal_connection`.
+	#[serde(default)]
+	pub custom: bool,
+}
+
+/// Editor text of a document that had unsaved edits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsavedBuffer {
+	pub path: String,
+	pub text: String,
+	#[serde(flatten)]
+	pub format: FileFormat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+	/// Milliseconds since the Unix epoch.
+	pub saved_at: u64,
+	/// The app quit normally after this snapshot. `false` means it crashed
+	/// or was killed, e.g. to install an update.
+	pub clean_exit: bool,
+	#[serde(flatten)]
+	pub ui: UiState,
+	pub unsaved_buffers: Vec<UnsavedBuffer>,
+}
+
+/// Periodically snapshots the open project, canvas layout, terminals and
+/// unsaved document buffers to `session.json`, so they survive a crash or
+/// forced restart.
+pub struct SessionManager {
+	app_handle: AppHandle,
+	documents: Arc<DocumentManager>,
+	/// `None` until the frontend first reports its state; nothing is written
+	/// before then so the previous session is not overwritten at startup.
+	ui: Mutex<Option<UiState>>,
+	/// The session found on disk at startup, until it is restored.
+	previous: Mutex<Option<Session>>,
+	/// Hash of the last snapshot written, to skip unchanged ones.
+	last_written: Mutex<Option<u64>>,
+}
+
+impl SessionManager {
+	pub fn new(app_handle: AppHandle, documents: Arc<DocumentManager>) -> Arc<Self> {
+		let previous = session_path(&app_handle)
+			.and_then(|path| Ok(fs::read_to_string(path)?))
+			.ok()
+			.and_then(|content| serde_json::from_str(&content).ok());
+
+		let manager = Arc::new(Self {
+			app_handle,
+			documents,
+			ui: Mutex::new(None),
+			previous: Mutex::new(previous),
+			last_written: Mutex::new(None),
+		});
+		manager.start_snapshots();
+		manager
+	}
+
+	fn start_snapshots(self: &Arc<Self>) {
+		let manager = Arc::downgrade(self);
+		tauri::async_runtime::spawn(async move {
+			let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+			loop {
+				interval.tick().await;
+				let Some(manager) = manager.upgrade() else {
+					break;
+				};
+				if let Err(e) = manager.snapshot(false) {
+					log::error!("Failed to save session: {}", e);
+				}
+			}
+		});
+	}
+
+	pub fn update(&self, ui: UiState) {
+		*self.ui.lock().unwrap() = Some(ui);
+	}
+
+	/// Writes the current session if it changed since the last write.
+	/// `clean_exit` marks the final snapshot taken when the app quits.
+	pub fn snapshot(&self, clean_exit: bool) -> Result<()> {
+		let Some(ui) = self.ui.lock().unwrap().clone() else {
+			return Ok(());
+		};
+		let mut unsaved_buffers: Vec<_> = self
+			.documents
+			.unsaved_documents()
+			.into_iter()
+			.map(|document| UnsavedBuffer {
+				path: document.state.path,
+				text: document.text,
+				format: document.state.format,
+			})
+			.collect();
+		unsaved_buffers.sort_by(|a, b| a.path.cmp(&b.path));
+
+		let mut session = Session {
+			saved_at: 0,
+			clean_exit,
+			ui,
+			unsaved_buffers,
+		};
+		let mut hasher = DefaultHasher::new();
+		serde_json::to_string(&session)?.hash(&mut hasher);
+		let hash = hasher.finish();
+
+		let mut last_written = self.last_written.lock().unwrap();
+		if *last_written == Some(hash) {
+			return Ok(());
+		}
+		session.saved_at = now_millis();
+
+		let path = session_path(&self.app_handle)?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		document_manager::write_atomically(&path, &serde_json::to_vec(&session)?)
+			.with_context(|| format!("Failed to write {}", path.display()))?;
+		*last_written = Some(hash);
+		Ok(())
+	}
+
+	/// Returns the session saved by the previous run and reopens its unsaved
+	/// buffers as documents, with the unsaved text applied. Buffers whose
+	/// file can no longer be opened are returned but not reopened. Returns
+	/// `None` after the first call, or if there was no previous session.
+	pub fn restore(&self) -> Option<Session> {
+		let session = self.previous.lock().unwrap().take()?;
+		for buffer in &session.unsaved_buffers {
+			let result = self
+				.documents
+				.open_document(&buffer.path)
+				.and_then(|_| {
+					self.documents.set_document_format(
+						&buffer.path,
+						Some(buffer.format.encoding),
+						Some(buffer.format.bom),
+						Some(buffer.format.line_ending),
+					)
+				})
+				.and_then(|_| {
+					self.documents
+						.update_document(&buffer.path, buffer.text.clone())
+				});
+			if let Err(e) = result {
+				log::error!("Failed to restore unsaved {}: {}", buffer.path, e);
+			}
+		}
+		Some(session)
+	}
+}
+
+fn session_path(app_handle: &AppHandle) -> Result<PathBuf> {
+	Ok(app_handle.path().app_data_dir()?.join("session.json"))
+}
+
+/// Reports what the frontend shows; it is saved with the next snapshot.
+#[tauri::command]
+pub async fn update_session(
+	ui: UiState,
+	manager: State<'_, Arc<SessionManager>>,
+) -> Result<(), String> {
+	manager.update(ui);
+	Ok(())
+}
+
+/// Writes a snapshot now, e.g. before installing an update.
+#[tauri::command]
+pub async fn save_session(manager: State<'_, Arc<SessionManager>>) -> Result<(), String> {
+	manager.snapshot(false).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_last_session(
+	manager: State<'_, Arc<SessionManager>>,
+) -> Result<Option<Session>, String> {
+	Ok(manager.restore())
+}
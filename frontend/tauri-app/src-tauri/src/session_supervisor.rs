@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::os::OsSession;
+use crate::ssh::SshTarget;
+use crate::ssh_remote::SshConnections;
+#[cfg(target_os = "windows")]
+use crate::wsl::WslCommand;
+
+/// How often the supervisor wakes up to probe sessions that are due.
+const TICK: Duration = Duration::from_secs(1);
+/// How often a connected session is probed.
+const PROBE_INTERVAL: Duration = Duration::from_secs(15);
+/// Retry delays for a lost session double from the first to the last.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a read waits for its session to come back before failing.
+const READ_RETRY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A session that runs somewhere else and can drop. Local sessions can't,
+/// so they aren't supervised.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RemoteSession {
+	Wsl { distribution: String },
+	Ssh { target: SshTarget },
+}
+
+impl RemoteSession {
+	pub fn of(session: &OsSession) -> Option<Self> {
+		match session {
+			OsSession::Local(_) => None,
+			OsSession::Wsl(wsl_session) => Some(Self::Wsl {
+				distribution: wsl_session.distribution.clone(),
+			}),
+		}
+	}
+
+	fn key(&self) -> String {
+		match self {
+			Self::Wsl { distribution } => format!("wsl:{}", distribution),
+			Self::Ssh { target } => format!("ssh:{}", target.args().join(" ")),
+		}
+	}
+}
+
+/// Payload of the `session-lost` and `session-restored` events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEvent {
+	pub session: RemoteSession,
+	/// Why the last probe failed.
+	pub error: Option<String>,
+	/// How long the session was gone, for `session-restored`.
+	pub downtime_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionStatus {
+	pub session: RemoteSession,
+	pub connected: bool,
+	/// Failed reconnection attempts since the session was lost.
+	pub attempts: u32,
+	pub error: Option<String>,
+}
+
+struct Supervised {
+	session: RemoteSession,
+	/// Panels that asked for the session to be watched. Sessions only
+	/// seen through a failed read are dropped once they're back.
+	watchers: usize,
+	lost_at: Option<Instant>,
+	attempts: u32,
+	error: Option<String>,
+	next_probe: Instant,
+}
+
+/// Probes WSL and SSH sessions, emitting `session-lost` when one stops
+/// answering and `session-restored` once a retry gets through.
+pub struct SessionSupervisor {
+	app_handle: AppHandle,
+	connections: Arc<SshConnections>,
+	sessions: Mutex<HashMap<String, Supervised>>,
+}
+
+impl SessionSupervisor {
+	pub fn new(app_handle: AppHandle, connections: Arc<SshConnections>) -> Arc<Self> {
+		let supervisor = Arc::new(Self {
+			app_handle,
+			connections,
+			sessions: Mutex::new(HashMap::new()),
+		});
+		let weak = Arc::downgrade(&supervisor);
+		thread::spawn(move || {
+			let mut last_tick = SystemTime::now();
+			while let Some(supervisor) = weak.upgrade() {
+				// The clock jumping far past the tick means the machine
+				// slept, so every session may have dropped
+				let slept = SystemTime::now()
+					.duration_since(last_tick)
+					.map(|elapsed| elapsed > TICK * 5)
+					.unwrap_or(false);
+				last_tick = SystemTime::now();
+				supervisor.probe_due(slept);
+				drop(supervisor);
+				thread::sleep(TICK);
+			}
+		});
+		supervisor
+	}
+
+	fn probe(&self, session: &RemoteSession) -> Result<()> {
+		let output = match session {
+			RemoteSession::Wsl { distribution } => {
+				#[cfg(target_os = "windows")]
+				{
+					WslCommand::new(distribution, "true").output()?
+				}
+				#[cfg(not(target_os = "windows"))]
+				{
+					let _ = distribution;
+					return Err(anyhow!("WSL is only available on Windows"));
+				}
+			}
+			// Also restarts the shared connection if it dropped
+			RemoteSession::Ssh { target } => {
+				self.connections.command(target, "true")?.output()?
+			}
+		};
+		if !output.status.success() {
+			return Err(anyhow!(
+				"{}",
+				String::from_utf8_lossy(&output.stderr).trim()
+			));
+		}
+		Ok(())
+	}
+
+	fn probe_due(&self, all: bool) {
+		let now = Instant::now();
+		let due: Vec<RemoteSession> = self
+			.sessions
+			.lock()
+			.unwrap()
+			.values()
+			.filter(|supervised| all || supervised.next_probe <= now)
+			.map(|supervised| supervised.session.clone())
+			.collect();
+		for session in due {
+			let result = self.probe(&session);
+			self.record(&session, result);
+		}
+	}
+
+	/// Updates `session` with a probe's result, emitting an event when it
+	/// was lost or restored.
+	fn record(&self, session: &RemoteSession, result: Result<()>) {
+		let now = Instant::now();
+		let mut sessions = self.sessions.lock().unwrap();
+		let supervised = sessions.entry(session.key()).or_insert_with(|| Supervised {
+			session: session.clone(),
+			watchers: 0,
+			lost_at: None,
+			attempts: 0,
+			error: None,
+			next_probe: now,
+		});
+		match result {
+			Ok(()) => {
+				supervised.next_probe = now + PROBE_INTERVAL;
+				if let Some(lost_at) = supervised.lost_at.take() {
+					supervised.attempts = 0;
+					supervised.error = None;
+					let _ = self.app_handle.emit(
+						"session-restored",
+						SessionEvent {
+							session: session.clone(),
+							error: None,
+							downtime_ms: Some(lost_at.elapsed().as_millis() as u64),
+						},
+					);
+				}
+				if supervised.watchers == 0 {
+					sessions.remove(&session.key());
+				}
+			}
+			Err(e) => {
+				let error = e.to_string();
+				if supervised.lost_at.is_none() {
+					supervised.lost_at = Some(now);
+					let _ = self.app_handle.emit(
+						"session-lost",
+						SessionEvent {
+							session: session.clone(),
+							error: Some(error.clone()),
+							downtime_ms: None,
+						},
+					);
+				} else {
+					supervised.attempts += 1;
+				}
+				let backoff = MIN_BACKOFF
+					.saturating_mul(1 << supervised.attempts.min(6))
+					.min(MAX_BACKOFF);
+				supervised.next_probe = now + backoff;
+				supervised.error = Some(error);
+			}
+		}
+	}
+
+	pub fn watch(&self, session: RemoteSession) {
+		let mut sessions = self.sessions.lock().unwrap();
+		let supervised = sessions.entry(session.key()).or_insert_with(|| Supervised {
+			session,
+			watchers: 0,
+			lost_at: None,
+			attempts: 0,
+			error: None,
+			next_probe: Instant::now(),
+		});
+		supervised.watchers += 1;
+	}
+
+	pub fn unwatch(&self, session: &RemoteSession) {
+		let mut sessions = self.sessions.lock().unwrap();
+		let key = session.key();
+		if let Some(supervised) = sessions.get_mut(&key) {
+			supervised.watchers = supervised.watchers.saturating_sub(1);
+			if supervised.watchers == 0 && supervised.lost_at.is_none() {
+				sessions.remove(&key);
+			}
+		}
+	}
+
+	pub fn list(&self) -> Vec<SessionStatus> {
+		self.sessions
+			.lock()
+			.unwrap()
+			.values()
+			.map(|supervised| SessionStatus {
+				session: supervised.session.clone(),
+				connected: supervised.lost_at.is_none(),
+				attempts: supervised.attempts,
+				error: supervised.error.clone(),
+			})
+			.collect()
+	}
+
+	/// Runs `read`, which must be safe to repeat. If it fails because the
+	/// session dropped, waits for the session to come back and runs it
+	/// again, so panels don't have to handle a laptop waking up.
+	pub async fn retry_read<T, F, Fut>(
+		self: &Arc<Self>,
+		session: Option<RemoteSession>,
+		read: F,
+	) -> Result<T>
+	where
+		F: Fn() -> Fut,
+		Fut: Future<Output = Result<T>>,
+	{
+		let first = read().await;
+		let Some(session) = session.filter(|_| first.is_err()) else {
+			return first;
+		};
+
+		let started = Instant::now();
+		let mut backoff = MIN_BACKOFF;
+		let mut first_probe = true;
+		loop {
+			let supervisor = self.clone();
+			let probed = session.clone();
+			let alive = tauri::async_runtime::spawn_blocking(move || {
+				let result = supervisor.probe(&probed);
+				let alive = result.is_ok();
+				supervisor.record(&probed, result);
+				alive
+			})
+			.await?;
+			if alive {
+				// A session that never dropped means the read itself failed
+				if first_probe {
+					return first;
+				}
+				return read().await;
+			}
+			first_probe = false;
+			if started.elapsed() + backoff > READ_RETRY_TIMEOUT {
+				return first;
+			}
+			tokio::time::sleep(backoff).await;
+			backoff = (backoff * 2).min(MAX_BACKOFF);
+		}
+	}
+}
+
+#[tauri::command]
+pub async fn watch_session(
+	session: RemoteSession,
+	supervisor: State<'_, Arc<SessionSupervisor>>,
+) -> Result<(), String> {
+	supervisor.watch(session);
+	Ok(())
+}
+
+#[tauri::command]
+pub async fn unwatch_session(
+	session: RemoteSession,
+	supervisor: State<'_, Arc<SessionSupervisor>>,
+) -> Result<(), String> {
+	supervisor.unwatch(&session);
+	Ok(())
+}
+
+#[tauri::command]
+pub async fn list_session_states(
+	supervisor: State<'_, Arc<SessionSupervisor>>,
+) -> Result<Vec<SessionStatus>, String> {
+	Ok(supervisor.list())
+}
@@ -0,0 +1,488 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_store::StoreExt;
+
+/// Store holding the user's settings.
+const SETTINGS_STORE: &str = "settings.json";
+/// Store the frontend kept its state in, theme and onboarding included,
+/// before settings had a schema.
+const LEGACY_STORE: &str = "store.json";
+/// Bumped whenever a setting is renamed, moved or changes meaning; add a
+/// step to `MIGRATIONS` at the same time.
+const SETTINGS_VERSION: u32 = 1;
+
+/// Steps from each version to the next, the first one upgrading version 0.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_v0];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AppearanceSettings {
+	/// `light`, `dark` or a named variant such as `light-sepia`.
+	pub theme: String,
+	pub show_onboarding: bool,
+}
+
+impl Default for AppearanceSettings {
+	fn default() -> Self {
+		Self {
+			theme: "light".to_string(),
+			show_onboarding: false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EditorSettings {
+	pub font_size: u32,
+	pub tab_size: u32,
+	pub insert_spaces: bool,
+	pub word_wrap: bool,
+	pub format_on_save: bool,
+	/// Saves this long after the last change, `None` to save manually.
+	pub auto_save_delay_ms: Option<u64>,
+}
+
+impl Default for EditorSettings {
+	fn default() -> Self {
+		Self {
+			font_size: 14,
+			tab_size: 4,
+			insert_spaces: false,
+			word_wrap: false,
+			format_on_save: false,
+			auto_save_delay_ms: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TerminalSettings {
+	pub font_size: u32,
+	pub copy_on_select: bool,
+}
+
+impl Default for TerminalSettings {
+	fn default() -> Self {
+		Self {
+			font_size: 13,
+			copy_on_select: false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AiSettings {
+	/// As accepted by `/api/inference`, e.g. `anthropic`.
+	pub default_provider: Option<String>,
+	pub default_model: Option<String>,
+}
+
+/// What the local API lets external tools do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalApiScope {
+	/// Open files in the editor.
+	Files,
+	/// List, run and stop project tasks.
+	Tasks,
+	/// Read the Problems feed.
+	Diagnostics,
+	/// Ask the frontend to start an agent.
+	Agents,
+}
+
+/// The opt-in HTTP API on localhost for scripts and extensions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LocalApiSettings {
+	pub enabled: bool,
+	/// 0 picks a free port.
+	pub port: u16,
+	pub scopes: Vec<LocalApiScope>,
+}
+
+impl Default for LocalApiSettings {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			port: 0,
+			// Running code needs to be allowed explicitly
+			scopes: vec![LocalApiScope::Files, LocalApiScope::Diagnostics],
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+	pub version: u32,
+	pub appearance: AppearanceSettings,
+	pub editor: EditorSettings,
+	pub terminal: TerminalSettings,
+	pub ai: AiSettings,
+	pub local_api: LocalApiSettings,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Self {
+			version: SETTINGS_VERSION,
+			appearance: AppearanceSettings::default(),
+			editor: EditorSettings::default(),
+			terminal: TerminalSettings::default(),
+			ai: AiSettings::default(),
+			local_api: LocalApiSettings::default(),
+		}
+	}
+}
+
+/// A setting that was rejected, by its dotted path, e.g. `editor.tabSize`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsProblem {
+	pub path: String,
+	pub message: String,
+}
+
+/// What `get_settings` returns: the settings in effect and what had to be
+/// reset to load them.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsState {
+	pub settings: Settings,
+	pub problems: Vec<SettingsProblem>,
+}
+
+/// Payload of the `settings-changed` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsChanged {
+	pub settings: Settings,
+	/// Dotted paths of the settings that changed.
+	pub changed: Vec<String>,
+}
+
+fn problem(path: &str, message: impl Into<String>) -> SettingsProblem {
+	SettingsProblem {
+		path: path.to_string(),
+		message: message.into(),
+	}
+}
+
+/// Version 0 is the frontend's flat `appState`.
+fn migrate_v0(settings: &mut Map<String, Value>) {
+	let mut appearance = Map::new();
+	for key in ["theme", "showOnboarding"] {
+		if let Some(value) = settings.remove(key) {
+			appearance.insert(key.to_string(), value);
+		}
+	}
+	// Not settings; they stay with the rest of the frontend state
+	settings.remove("gitProjects");
+	settings.remove("currentInterpreterScript");
+	settings.insert("appearance".to_string(), Value::Object(appearance));
+}
+
+/// Brings stored settings up to the current version. Settings from a newer
+/// version of the app are left as they are.
+fn migrate(mut value: Map<String, Value>) -> Map<String, Value> {
+	let version = value.get("version").and_then(Value::as_u64).unwrap_or(0) as usize;
+	for step in MIGRATIONS.iter().skip(version) {
+		step(&mut value);
+	}
+	if version < MIGRATIONS.len() {
+		value.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+	}
+	value
+}
+
+/// Reads each section on its own, so a bad value only resets its section
+/// rather than every setting.
+fn deserialize_lenient(value: Map<String, Value>) -> (Settings, Vec<SettingsProblem>) {
+	let mut problems = Vec::new();
+	let mut settings = Settings::default();
+	let mut defaults = match serde_json::to_value(Settings::default()) {
+		Ok(Value::Object(defaults)) => defaults,
+		_ => return (settings, problems),
+	};
+	for (key, section) in value {
+		if !defaults.contains_key(&key) {
+			// Settings of a newer version or a removed setting
+			continue;
+		}
+		let mut candidate = defaults.clone();
+		candidate.insert(key.clone(), section);
+		match serde_json::from_value::<Settings>(Value::Object(candidate.clone())) {
+			Ok(parsed) => {
+				defaults = candidate;
+				settings = parsed;
+			}
+			Err(e) => problems.push(problem(&key, format!("Reset to defaults: {}", e))),
+		}
+	}
+	(settings, problems)
+}
+
+fn check_range(
+	problems: &mut Vec<SettingsProblem>,
+	path: &str,
+	value: u32,
+	min: u32,
+	max: u32,
+) {
+	if !(min..=max).contains(&value) {
+		problems.push(problem(
+			path,
+			format!("Must be between {} and {}, got {}", min, max, value),
+		));
+	}
+}
+
+impl Settings {
+	/// Everything wrong with the settings; empty when they are valid.
+	pub fn validate(&self) -> Vec<SettingsProblem> {
+		let mut problems = Vec::new();
+		if self.appearance.theme.trim().is_empty() {
+			problems.push(problem("appearance.theme", "Must not be empty"));
+		}
+		check_range(
+			&mut problems,
+			"editor.fontSize",
+			self.editor.font_size,
+			6,
+			72,
+		);
+		check_range(&mut problems, "editor.tabSize", self.editor.tab_size, 1, 16);
+		if self.editor.auto_save_delay_ms.is_some_and(|ms| ms < 100) {
+			problems.push(problem("editor.autoSaveDelayMs", "Must be at least 100 ms"));
+		}
+		check_range(
+			&mut problems,
+			"terminal.fontSize",
+			self.terminal.font_size,
+			6,
+			72,
+		);
+		if self.ai.default_model.is_some() && self.ai.default_provider.is_none() {
+			problems.push(problem(
+				"ai.defaultModel",
+				"A default model needs a default provider",
+			));
+		}
+		if self.local_api.port != 0 && self.local_api.port < 1024 {
+			problems.push(problem("localApi.port", "Must be 0 or at least 1024"));
+		}
+		problems
+	}
+
+	/// Resets the sections with problems to their defaults.
+	fn repair(&mut self, problems: &[SettingsProblem]) {
+		let defaults = Settings::default();
+		for problem in problems {
+			match problem.path.split('.').next() {
+				Some("appearance") => self.appearance = defaults.appearance.clone(),
+				Some("editor") => self.editor = defaults.editor.clone(),
+				Some("terminal") => self.terminal = defaults.terminal.clone(),
+				Some("ai") => self.ai = defaults.ai.clone(),
+				Some("localApi") => self.local_api = defaults.local_api.clone(),
+				_ => {}
+			}
+		}
+	}
+}
+
+/// Dotted paths of the leaves that differ between `old` and `new`.
+fn changed_paths(old: &Value, new: &Value, prefix: &str, changed: &mut Vec<String>) {
+	match (old, new) {
+		(Value::Object(old), Value::Object(new)) => {
+			for (key, value) in new {
+				let path = if prefix.is_empty() {
+					key.clone()
+				} else {
+					format!("{}.{}", prefix, key)
+				};
+				changed_paths(
+					old.get(key).unwrap_or(&Value::Null),
+					value,
+					&path,
+					changed,
+				);
+			}
+		}
+		(old, new) if old != new => changed.push(prefix.to_string()),
+		_ => {}
+	}
+}
+
+/// Merges `patch` into `target`: objects are merged key by key, anything
+/// else replaces what was there.
+fn merge(target: &mut Value, patch: Value) {
+	match (target, patch) {
+		(Value::Object(target), Value::Object(patch)) => {
+			for (key, value) in patch {
+				merge(target.entry(key).or_insert(Value::Null), value);
+			}
+		}
+		(target, patch) => *target = patch,
+	}
+}
+
+/// Typed, validated settings kept in the store and shared with the
+/// frontend, which is told about every change with `settings-changed`.
+pub struct SettingsManager {
+	app_handle: AppHandle,
+	state: Mutex<SettingsState>,
+}
+
+impl SettingsManager {
+	/// Loads the stored settings, migrating them or importing the legacy
+	/// frontend state as needed. Invalid values are reset and reported.
+	pub fn load(app_handle: &AppHandle) -> Arc<Self> {
+		let stored = app_handle
+			.store(SETTINGS_STORE)
+			.ok()
+			.and_then(|store| store.get("settings"));
+		let stored = stored.or_else(|| {
+			app_handle
+				.store(LEGACY_STORE)
+				.ok()
+				.and_then(|store| store.get("appState"))
+		});
+		let raw = match stored {
+			Some(Value::Object(map)) => map,
+			_ => Map::new(),
+		};
+		let needs_save =
+			raw.get("version").and_then(Value::as_u64) != Some(SETTINGS_VERSION as u64);
+
+		let (mut settings, mut problems) = deserialize_lenient(migrate(raw));
+		settings.version = SETTINGS_VERSION;
+		let invalid = settings.validate();
+		settings.repair(&invalid);
+		problems.extend(invalid);
+		for problem in &problems {
+			log::warn!("Invalid setting {}: {}", problem.path, problem.message);
+		}
+
+		let manager = Arc::new(Self {
+			app_handle: app_handle.clone(),
+			state: Mutex::new(SettingsState { settings, problems }),
+		});
+		if needs_save || !manager.state.lock().unwrap().problems.is_empty() {
+			let settings = manager.get().settings;
+			if let Err(e) = manager.persist(&settings) {
+				log::warn!("Failed to save migrated settings: {}", e);
+			}
+		}
+		manager
+	}
+
+	fn persist(&self, settings: &Settings) -> Result<()> {
+		let store = self.app_handle.store(SETTINGS_STORE)?;
+		store.set("settings", serde_json::to_value(settings)?);
+		store.save()?;
+		Ok(())
+	}
+
+	pub fn get(&self) -> SettingsState {
+		self.state.lock().unwrap().clone()
+	}
+
+	fn replace(&self, settings: Settings) -> Result<Settings> {
+		let problems = settings.validate();
+		if !problems.is_empty() {
+			return Err(anyhow!(
+				"Invalid settings: {}",
+				problems
+					.iter()
+					.map(|p| format!("{}: {}", p.path, p.message))
+					.collect::<Vec<_>>()
+					.join("; ")
+			));
+		}
+
+		let mut state = self.state.lock().unwrap();
+		let mut changed = Vec::new();
+		changed_paths(
+			&serde_json::to_value(&state.settings)?,
+			&serde_json::to_value(&settings)?,
+			"",
+			&mut changed,
+		);
+		if changed.is_empty() {
+			return Ok(settings);
+		}
+		self.persist(&settings)?;
+		state.settings = settings.clone();
+		state.problems.clear();
+		drop(state);
+
+		let _ = self.app_handle.emit(
+			"settings-changed",
+			SettingsChanged {
+				settings: settings.clone(),
+				changed,
+			},
+		);
+		Ok(settings)
+	}
+
+	/// Applies a partial update, e.g. `{"editor": {"tabSize": 2}}`. Nothing
+	/// is changed when the result doesn't validate.
+	pub fn update(&self, patch: Value) -> Result<Settings> {
+		let mut value = serde_json::to_value(self.get().settings)?;
+		merge(&mut value, patch);
+		let mut settings: Settings = serde_json::from_value(value)
+			.map_err(|e| anyhow!("Invalid settings: {}", e))?;
+		settings.version = SETTINGS_VERSION;
+		self.replace(settings)
+	}
+
+	/// Resets one section, e.g. `editor`, or every setting.
+	pub fn reset(&self, section: Option<&str>) -> Result<Settings> {
+		let defaults = Settings::default();
+		let mut settings = self.get().settings;
+		match section {
+			None => settings = defaults,
+			Some("appearance") => settings.appearance = defaults.appearance,
+			Some("editor") => settings.editor = defaults.editor,
+			Some("terminal") => settings.terminal = defaults.terminal,
+			Some("ai") => settings.ai = defaults.ai,
+			Some("localApi") => settings.local_api = defaults.local_api,
+			Some(other) => return Err(anyhow!("Unknown settings section {}", other)),
+		}
+		self.replace(settings)
+	}
+}
+
+#[tauri::command]
+pub async fn get_settings(
+	manager: State<'_, Arc<SettingsManager>>,
+) -> Result<SettingsState, String> {
+	Ok(manager.get())
+}
+
+/// Merges `patch` into the settings and returns them. Invalid updates are
+/// rejected with every problem listed.
+#[tauri::command]
+pub async fn update_settings(
+	patch: Value,
+	manager: State<'_, Arc<SettingsManager>>,
+) -> Result<Settings, String> {
+	manager.update(patch).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn reset_settings(
+	section: Option<String>,
+	manager: State<'_, Arc<SettingsManager>>,
+) -> Result<Settings, String> {
+	manager.reset(section.as_deref()).map_err(|e| e.to_string())
+}
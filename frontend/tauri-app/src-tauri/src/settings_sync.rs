@@ -0,0 +1,145 @@
+use crate::backend_client::BackendClient;
+use crate::util::error_from;
+use anyhow::Result;
+use reqwest::{
+	header::{IF_MATCH, IF_NONE_MATCH},
+	Method, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+/// Local store remembering the last ETag synced for each settings namespace.
+const SYNC_STORE: &str = "settings-sync.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteSettings {
+	pub namespace: String,
+	pub version: i64,
+	pub etag: String,
+	pub data: Value,
+	pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConflictResponse {
+	current: Option<RemoteSettings>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SettingsSyncOutcome {
+	/// The server copy hasn't changed since the last sync.
+	Unchanged,
+	/// Nothing is stored on the server for this namespace yet.
+	NotFound,
+	/// The server had a newer copy, which is returned.
+	Pulled { settings: RemoteSettings },
+	/// The local copy was uploaded.
+	Pushed { settings: RemoteSettings },
+	/// Another device wrote in between; merge with `remote` and push again,
+	/// or push with `force` to overwrite it.
+	Conflict { remote: Option<RemoteSettings> },
+}
+
+fn last_etag(app_handle: &AppHandle, namespace: &str) -> Result<Option<String>> {
+	let store = app_handle.store(SYNC_STORE)?;
+	Ok(store
+		.get(namespace)
+		.and_then(|v| v.as_str().map(str::to_string)))
+}
+
+fn remember_etag(app_handle: &AppHandle, namespace: &str, etag: &str) -> Result<()> {
+	let store = app_handle.store(SYNC_STORE)?;
+	store.set(namespace, Value::String(etag.to_string()));
+	store.save()?;
+	Ok(())
+}
+
+async fn pull(
+	app_handle: &AppHandle,
+	client: &BackendClient,
+	namespace: &str,
+) -> Result<SettingsSyncOutcome> {
+	let mut request =
+		client.request(Method::GET, &format!("/api/settings/{}", namespace));
+	if let Some(etag) = last_etag(app_handle, namespace)? {
+		request = request.header(IF_NONE_MATCH, etag);
+	}
+
+	let response = request.send().await?;
+	match response.status() {
+		StatusCode::NOT_MODIFIED => Ok(SettingsSyncOutcome::Unchanged),
+		StatusCode::NOT_FOUND => Ok(SettingsSyncOutcome::NotFound),
+		status if status.is_success() => {
+			let settings: RemoteSettings = response.json().await?;
+			remember_etag(app_handle, namespace, &settings.etag)?;
+			Ok(SettingsSyncOutcome::Pulled { settings })
+		}
+		_ => Err(error_from(response, "Settings sync").await),
+	}
+}
+
+async fn push(
+	app_handle: &AppHandle,
+	client: &BackendClient,
+	namespace: &str,
+	data: Value,
+	force: bool,
+) -> Result<SettingsSyncOutcome> {
+	let mut request = client
+		.request(Method::PUT, &format!("/api/settings/{}", namespace))
+		.json(&serde_json::json!({ "data": data }));
+	if !force {
+		// Without a known version only create, so a fresh machine never
+		// silently overwrites settings pushed from another one.
+		request = match last_etag(app_handle, namespace)? {
+			Some(etag) => request.header(IF_MATCH, etag),
+			None => request.header(IF_NONE_MATCH, "*"),
+		};
+	}
+
+	let response = request.send().await?;
+	match response.status() {
+		StatusCode::PRECONDITION_FAILED => {
+			let conflict: ConflictResponse = response.json().await?;
+			Ok(SettingsSyncOutcome::Conflict {
+				remote: conflict.current,
+			})
+		}
+		status if status.is_success() => {
+			let settings: RemoteSettings = response.json().await?;
+			remember_etag(app_handle, namespace, &settings.etag)?;
+			Ok(SettingsSyncOutcome::Pushed { settings })
+		}
+		_ => Err(error_from(response, "Settings sync").await),
+	}
+}
+
+/// Syncs one settings namespace (`themes`, `keybindings`, `terminal_profiles`, ...)
+/// with the backend. Without `data` the server copy is pulled; with `data` the
+/// local copy is pushed, guarded by the ETag from the previous sync.
+#[tauri::command]
+pub async fn sync_settings(
+	app_handle: AppHandle,
+	namespace: String,
+	data: Option<Value>,
+	force: Option<bool>,
+) -> Result<SettingsSyncOutcome, String> {
+	let client = BackendClient::from_app(&app_handle).map_err(|e| e.to_string())?;
+	let result = match data {
+		None => pull(&app_handle, &client, &namespace).await,
+		Some(data) => {
+			push(
+				&app_handle,
+				&client,
+				&namespace,
+				data,
+				force.unwrap_or(false),
+			)
+			.await
+		}
+	};
+	result.map_err(|e| e.to_string())
+}
@@ -0,0 +1,254 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+use tauri_plugin_store::StoreExt;
+
+/// Store holding the user's global shortcuts.
+const SHORTCUTS_STORE: &str = "shortcuts.json";
+
+/// An OS-wide shortcut that triggers a frontend action, such as
+/// `quickPrompt`, even while the app is in the background.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalShortcutBinding {
+	pub action: String,
+	/// e.g. `CmdOrCtrl+Shift+Space`.
+	pub accelerator: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalShortcutStatus {
+	#[serde(flatten)]
+	pub binding: GlobalShortcutBinding,
+	/// False when the OS refused the shortcut, e.g. because another app
+	/// holds it. The binding is kept so it can be retried.
+	pub registered: bool,
+	pub error: Option<String>,
+}
+
+/// What already uses an accelerator.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ShortcutConflict {
+	/// Bound to another of our actions.
+	#[serde(rename_all = "camelCase")]
+	Action { action: String },
+	/// Held by another application or the OS.
+	System,
+}
+
+/// Payload of the `global-shortcut` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShortcutTriggered {
+	action: String,
+}
+
+fn parse(accelerator: &str) -> Result<Shortcut> {
+	Shortcut::from_str(accelerator)
+		.map_err(|e| anyhow!("Invalid shortcut {}: {}", accelerator, e))
+}
+
+/// Registers the user's global shortcuts with the OS and emits a
+/// `global-shortcut` event naming the action when one is pressed.
+pub struct GlobalShortcuts {
+	app_handle: AppHandle,
+	bindings: Mutex<Vec<GlobalShortcutStatus>>,
+}
+
+impl GlobalShortcuts {
+	/// Registers the stored bindings. Ones the OS refuses are kept
+	/// unregistered, with the reason.
+	pub fn load(app_handle: &AppHandle) -> Self {
+		let stored: Vec<GlobalShortcutBinding> = app_handle
+			.store(SHORTCUTS_STORE)
+			.ok()
+			.and_then(|store| store.get("bindings"))
+			.and_then(|v| serde_json::from_value(v).ok())
+			.unwrap_or_default();
+
+		let shortcuts = Self {
+			app_handle: app_handle.clone(),
+			bindings: Mutex::new(Vec::new()),
+		};
+		let statuses = stored
+			.into_iter()
+			.map(|binding| {
+				let error = shortcuts.register_os(&binding).err().map(|e| {
+					log::warn!(
+						"Failed to register shortcut {}: {}",
+						binding.accelerator,
+						e
+					);
+					e.to_string()
+				});
+				GlobalShortcutStatus {
+					binding,
+					registered: error.is_none(),
+					error,
+				}
+			})
+			.collect();
+		*shortcuts.bindings.lock().unwrap() = statuses;
+		shortcuts
+	}
+
+	fn register_os(&self, binding: &GlobalShortcutBinding) -> Result<()> {
+		let action = binding.action.clone();
+		self.app_handle.global_shortcut().on_shortcut(
+			parse(&binding.accelerator)?,
+			move |app_handle, _shortcut, event| {
+				if event.state == ShortcutState::Pressed {
+					let _ = app_handle.emit(
+						"global-shortcut",
+						ShortcutTriggered {
+							action: action.clone(),
+						},
+					);
+				}
+			},
+		)?;
+		Ok(())
+	}
+
+	fn unregister_os(&self, status: &GlobalShortcutStatus) -> Result<()> {
+		if status.registered {
+			self.app_handle
+				.global_shortcut()
+				.unregister(parse(&status.binding.accelerator)?)?;
+		}
+		Ok(())
+	}
+
+	fn persist(&self, bindings: &[GlobalShortcutStatus]) -> Result<()> {
+		let bindings: Vec<_> = bindings.iter().map(|s| s.binding.clone()).collect();
+		let store = self.app_handle.store(SHORTCUTS_STORE)?;
+		store.set("bindings", serde_json::to_value(bindings)?);
+		store.save()?;
+		Ok(())
+	}
+
+	pub fn list(&self) -> Vec<GlobalShortcutStatus> {
+		self.bindings.lock().unwrap().clone()
+	}
+
+	/// What would stop `accelerator` from working for `action`, if anything.
+	pub fn conflict(
+		&self,
+		accelerator: &str,
+		action: &str,
+	) -> Result<Option<ShortcutConflict>> {
+		let shortcut = parse(accelerator)?;
+		let bindings = self.bindings.lock().unwrap();
+		let existing = bindings.iter().find(|s| {
+			parse(&s.binding.accelerator).is_ok_and(|other| other.id() == shortcut.id())
+		});
+		if let Some(existing) = existing {
+			if existing.binding.action == action && existing.registered {
+				return Ok(None);
+			}
+			if existing.binding.action != action {
+				return Ok(Some(ShortcutConflict::Action {
+					action: existing.binding.action.clone(),
+				}));
+			}
+		}
+
+		// The only way to know whether another app holds it is to try
+		let global_shortcut = self.app_handle.global_shortcut();
+		match global_shortcut.register(shortcut) {
+			Ok(()) => {
+				global_shortcut.unregister(shortcut)?;
+				Ok(None)
+			}
+			Err(_) => Ok(Some(ShortcutConflict::System)),
+		}
+	}
+
+	/// Binds `action` to `accelerator`, replacing its previous shortcut.
+	/// Fails without changing anything if the accelerator is taken.
+	pub fn bind(&self, action: &str, accelerator: &str) -> Result<GlobalShortcutStatus> {
+		match self.conflict(accelerator, action)? {
+			Some(ShortcutConflict::Action { action: other }) => {
+				return Err(anyhow!("{} is already bound to {}", accelerator, other))
+			}
+			Some(ShortcutConflict::System) => {
+				return Err(anyhow!("{} is in use by another application", accelerator))
+			}
+			None => {}
+		}
+
+		let mut bindings = self.bindings.lock().unwrap();
+		if let Some(index) = bindings.iter().position(|s| s.binding.action == action) {
+			let previous = bindings.remove(index);
+			self.unregister_os(&previous)?;
+		}
+		let binding = GlobalShortcutBinding {
+			action: action.to_string(),
+			accelerator: accelerator.to_string(),
+		};
+		self.register_os(&binding)?;
+		let status = GlobalShortcutStatus {
+			binding,
+			registered: true,
+			error: None,
+		};
+		bindings.push(status.clone());
+		self.persist(&bindings)?;
+		Ok(status)
+	}
+
+	pub fn unbind(&self, action: &str) -> Result<()> {
+		let mut bindings = self.bindings.lock().unwrap();
+		let Some(index) = bindings.iter().position(|s| s.binding.action == action) else {
+			return Ok(());
+		};
+		let status = bindings.remove(index);
+		self.unregister_os(&status)?;
+		self.persist(&bindings)
+	}
+}
+
+#[tauri::command]
+pub async fn list_global_shortcuts(
+	shortcuts: State<'_, GlobalShortcuts>,
+) -> Result<Vec<GlobalShortcutStatus>, String> {
+	Ok(shortcuts.list())
+}
+
+/// Checks an accelerator before binding it, so the settings UI can warn
+/// about conflicts as the user types.
+#[tauri::command]
+pub async fn check_global_shortcut(
+	accelerator: String,
+	action: String,
+	shortcuts: State<'_, GlobalShortcuts>,
+) -> Result<Option<ShortcutConflict>, String> {
+	shortcuts
+		.conflict(&accelerator, &action)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn register_global_shortcut(
+	action: String,
+	accelerator: String,
+	shortcuts: State<'_, GlobalShortcuts>,
+) -> Result<GlobalShortcutStatus, String> {
+	shortcuts
+		.bind(&action, &accelerator)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unregister_global_shortcut(
+	action: String,
+	shortcuts: State<'_, GlobalShortcuts>,
+) -> Result<(), String> {
+	shortcuts.unbind(&action).map_err(|e| e.to_string())
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_store::StoreExt;
+
+use crate::git::{current_branch, run_git};
+use crate::os::OsSession;
+use crate::util::now_millis;
+
+/// Store holding the snapshot settings.
+const SNAPSHOTS_STORE: &str = "snapshots.json";
+/// Snapshots live under this ref namespace, by branch, so they are never
+/// pushed or shown as branches.
+const SNAPSHOT_REFS: &str = "refs/ariana/snapshots";
+/// Index used to build snapshot trees, inside the git directory, so the
+/// user's staged changes are left alone.
+const SNAPSHOT_INDEX: &str = "ariana-snapshot-index";
+/// Snapshot commits are authored by the app, whatever git is configured with.
+const SNAPSHOT_IDENTITY: [&str; 4] = [
+	"-c",
+	"user.name=Ariana snapshots",
+	"-c",
+	"user.email=snapshots@ariana.local",
+];
+/// How often watched canvases are checked for a snapshot being due.
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSettings {
+	pub enabled: bool,
+	pub interval_minutes: u64,
+	/// Snapshots kept per branch; older ones are deleted first.
+	pub max_snapshots: usize,
+	/// Snapshots older than this are deleted, `None` to keep them.
+	pub max_age_hours: Option<u64>,
+}
+
+impl Default for SnapshotSettings {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			interval_minutes: 10,
+			max_snapshots: 50,
+			max_age_hours: Some(72),
+		}
+	}
+}
+
+/// A snapshot of a canvas working tree, untracked files included, as a
+/// commit on top of the branch head at the time.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+	/// Milliseconds since the Unix epoch, which is also the creation time.
+	pub id: String,
+	pub commit: String,
+	pub branch: String,
+	pub created_at: u64,
+}
+
+/// Payload of the `snapshot-created` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotCreated {
+	pub directory: String,
+	#[serde(flatten)]
+	pub snapshot: Snapshot,
+}
+
+pub fn settings(app_handle: &AppHandle) -> Result<SnapshotSettings> {
+	let store = app_handle.store(SNAPSHOTS_STORE)?;
+	Ok(store
+		.get("settings")
+		.and_then(|v| serde_json::from_value(v).ok())
+		.unwrap_or_default())
+}
+
+pub fn set_settings(app_handle: &AppHandle, settings: &SnapshotSettings) -> Result<()> {
+	let store = app_handle.store(SNAPSHOTS_STORE)?;
+	store.set("settings", serde_json::to_value(settings)?);
+	store.save()?;
+	Ok(())
+}
+
+/// Runs git with `index` as its index file.
+fn run_git_with_index(
+	session: &OsSession,
+	directory: &str,
+	index: &str,
+	args: &[&str],
+) -> Result<String> {
+	let mut cmd = match session {
+		OsSession::Local(_) => {
+			let mut cmd = session.build_process_command("git", directory)?;
+			cmd.env("GIT_INDEX_FILE", index);
+			cmd
+		}
+		// Variables set here don't reach processes inside WSL
+		OsSession::Wsl(_) => {
+			let mut cmd = session.build_process_command("env", directory)?;
+			cmd.arg(format!("GIT_INDEX_FILE={}", index)).arg("git");
+			cmd
+		}
+	};
+	let output = cmd
+		.args(args)
+		.output()
+		.map_err(|e| anyhow!("Failed to execute git {}: {}", args[0], e))?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"git {} failed: {}",
+			args[0],
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Branch the snapshots of `directory` are kept under.
+fn snapshot_branch(session: &OsSession, directory: &str) -> Result<String> {
+	Ok(current_branch(session, directory)?.unwrap_or_else(|| "detached".to_string()))
+}
+
+/// Snapshots of the branch checked out in `directory`, newest first.
+pub fn list(session: &OsSession, directory: &str) -> Result<Vec<Snapshot>> {
+	let branch = snapshot_branch(session, directory)?;
+	let prefix = format!("{}/{}/", SNAPSHOT_REFS, branch);
+	let output = run_git(
+		session,
+		directory,
+		&["for-each-ref", "--format=%(refname) %(objectname)", &prefix],
+	)?;
+	let mut snapshots: Vec<Snapshot> = output
+		.lines()
+		.filter_map(|line| {
+			let (refname, commit) = line.split_once(' ')?;
+			// Snapshots of branches nested under this one have more components
+			let id = refname.strip_prefix(&prefix)?;
+			let created_at = id.parse().ok()?;
+			Some(Snapshot {
+				id: id.to_string(),
+				commit: commit.to_string(),
+				branch: branch.clone(),
+				created_at,
+			})
+		})
+		.collect();
+	snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+	Ok(snapshots)
+}
+
+/// Snapshots the working tree of `directory`, untracked files included
+/// but ignored ones left out, without touching its index or branch.
+/// Returns `None` when nothing changed since the last snapshot.
+pub fn create(session: &OsSession, directory: &str) -> Result<Option<Snapshot>> {
+	let head = run_git(session, directory, &["rev-parse", "--verify", "HEAD"])
+		.map_err(|_| anyhow!("The branch has no commits to snapshot"))?;
+	let head = head.trim();
+	let index = run_git(
+		session,
+		directory,
+		&["rev-parse", "--git-path", SNAPSHOT_INDEX],
+	)?;
+	let index = index.trim();
+
+	run_git_with_index(session, directory, index, &["read-tree", head])?;
+	run_git_with_index(session, directory, index, &["add", "-A"])?;
+	let tree = run_git_with_index(session, directory, index, &["write-tree"])?;
+	let tree = tree.trim();
+
+	let branch = snapshot_branch(session, directory)?;
+	if let Some(latest) = list(session, directory)?.first() {
+		let latest_tree = run_git(
+			session,
+			directory,
+			&["rev-parse", &format!("{}^{{tree}}", latest.commit)],
+		)?;
+		if latest_tree.trim() == tree {
+			return Ok(None);
+		}
+	}
+
+	let created_at = now_millis();
+	let message = format!("Snapshot of {}", branch);
+	let mut args = SNAPSHOT_IDENTITY.to_vec();
+	args.extend(["commit-tree", tree, "-p", head, "-m", &message]);
+	let commit = run_git(session, directory, &args)?;
+	let commit = commit.trim();
+	let refname = format!("{}/{}/{}", SNAPSHOT_REFS, branch, created_at);
+	run_git(session, directory, &["update-ref", &refname, commit])?;
+
+	Ok(Some(Snapshot {
+		id: created_at.to_string(),
+		commit: commit.to_string(),
+		branch,
+		created_at,
+	}))
+}
+
+/// Deletes the snapshots of the current branch beyond the retention
+/// `settings` allow.
+pub fn prune(
+	session: &OsSession,
+	directory: &str,
+	settings: &SnapshotSettings,
+) -> Result<()> {
+	let snapshots = list(session, directory)?;
+	let cutoff = settings
+		.max_age_hours
+		.map(|hours| now_millis().saturating_sub(hours * 60 * 60 * 1000));
+	for (index, snapshot) in snapshots.iter().enumerate() {
+		let expired = cutoff.is_some_and(|cutoff| snapshot.created_at < cutoff);
+		if index >= settings.max_snapshots || expired {
+			let refname =
+				format!("{}/{}/{}", SNAPSHOT_REFS, snapshot.branch, snapshot.id);
+			run_git(session, directory, &["update-ref", "-d", &refname])?;
+		}
+	}
+	Ok(())
+}
+
+/// Puts the working tree of `directory` back to snapshot `id`. The current
+/// state is snapshotted first, so the restore can be undone; that snapshot
+/// is returned if anything had changed. Files created since the snapshot
+/// that git doesn't track are left in place.
+pub fn restore(
+	session: &OsSession,
+	directory: &str,
+	id: &str,
+) -> Result<Option<Snapshot>> {
+	let snapshot = list(session, directory)?
+		.into_iter()
+		.find(|snapshot| snapshot.id == id)
+		.ok_or_else(|| anyhow!("Snapshot not found: {}", id))?;
+	let before = create(session, directory)?;
+	run_git(
+		session,
+		directory,
+		&[
+			"restore",
+			&format!("--source={}", snapshot.commit),
+			"--worktree",
+			"--",
+			".",
+		],
+	)?;
+	Ok(before)
+}
+
+struct Watched {
+	session: OsSession,
+	last_run: Instant,
+}
+
+/// Snapshots watched canvases in the background, as often as the
+/// settings ask.
+pub struct SnapshotScheduler {
+	app_handle: AppHandle,
+	/// Watched canvases by directory.
+	watched: Mutex<HashMap<String, Watched>>,
+}
+
+impl SnapshotScheduler {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		let scheduler = Arc::new(Self {
+			app_handle,
+			watched: Mutex::new(HashMap::new()),
+		});
+		let weak = Arc::downgrade(&scheduler);
+		thread::spawn(move || {
+			while let Some(scheduler) = weak.upgrade() {
+				scheduler.tick();
+				drop(scheduler);
+				thread::sleep(TICK_INTERVAL);
+			}
+		});
+		scheduler
+	}
+
+	pub fn watch(&self, session: OsSession, directory: String) {
+		self.watched.lock().unwrap().insert(
+			directory,
+			Watched {
+				session,
+				last_run: Instant::now(),
+			},
+		);
+	}
+
+	pub fn unwatch(&self, directory: &str) {
+		self.watched.lock().unwrap().remove(directory);
+	}
+
+	fn tick(&self) {
+		let settings = match settings(&self.app_handle) {
+			Ok(settings) if settings.enabled => settings,
+			_ => return,
+		};
+		let interval = Duration::from_secs(settings.interval_minutes.max(1) * 60);
+		let due: Vec<(String, OsSession)> = {
+			let mut watched = self.watched.lock().unwrap();
+			watched
+				.iter_mut()
+				.filter(|(_, watched)| watched.last_run.elapsed() >= interval)
+				.map(|(directory, watched)| {
+					watched.last_run = Instant::now();
+					(directory.clone(), watched.session.clone())
+				})
+				.collect()
+		};
+		for (directory, session) in due {
+			match create(&session, &directory) {
+				Ok(Some(snapshot)) => {
+					let _ = self.app_handle.emit(
+						"snapshot-created",
+						SnapshotCreated {
+							directory: directory.clone(),
+							snapshot,
+						},
+					);
+				}
+				Ok(None) => {}
+				Err(e) => log::warn!("Failed to snapshot {}: {}", directory, e),
+			}
+			if let Err(e) = prune(&session, &directory, &settings) {
+				log::warn!("Failed to prune snapshots of {}: {}", directory, e);
+			}
+		}
+	}
+}
+
+#[tauri::command]
+pub async fn get_snapshot_settings(
+	app_handle: AppHandle,
+) -> Result<SnapshotSettings, String> {
+	settings(&app_handle).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_snapshot_settings(
+	settings: SnapshotSettings,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	set_settings(&app_handle, &settings).map_err(|e| e.to_string())
+}
+
+/// Snapshots the canvas at `directory` periodically until unwatched.
+#[tauri::command]
+pub async fn watch_snapshots(
+	directory: String,
+	os_session: OsSession,
+	scheduler: State<'_, Arc<SnapshotScheduler>>,
+) -> Result<(), String> {
+	scheduler.watch(os_session, directory);
+	Ok(())
+}
+
+#[tauri::command]
+pub async fn unwatch_snapshots(
+	directory: String,
+	scheduler: State<'_, Arc<SnapshotScheduler>>,
+) -> Result<(), String> {
+	scheduler.unwatch(&directory);
+	Ok(())
+}
+
+/// Snapshots `directory` now. Returns `None` if nothing changed since the
+/// last snapshot.
+#[tauri::command]
+pub async fn create_snapshot(
+	directory: String,
+	os_session: OsSession,
+) -> Result<Option<Snapshot>, String> {
+	tauri::async_runtime::spawn_blocking(move || create(&os_session, &directory))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_snapshots(
+	directory: String,
+	os_session: OsSession,
+) -> Result<Vec<Snapshot>, String> {
+	tauri::async_runtime::spawn_blocking(move || list(&os_session, &directory))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_snapshot(
+	directory: String,
+	snapshot_id: String,
+	os_session: OsSession,
+) -> Result<Option<Snapshot>, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		restore(&os_session, &directory, &snapshot_id)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
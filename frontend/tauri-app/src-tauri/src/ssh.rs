@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+/// Password for ssh to read from this executable, run as its askpass
+/// program.
+const PASSWORD_ENV: &str = "ARIANA_SSH_PASSWORD";
+
+const CONNECT_TIMEOUT_SECS: u32 = 10;
+/// Includes nested deeper than this are ignored, as ssh does.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A host from `~/.ssh/config`, with the options that apply to it
+/// resolved the way ssh would.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SshHost {
+	/// The name given after `Host`.
+	pub alias: String,
+	pub host_name: String,
+	pub user: Option<String>,
+	pub port: u16,
+	pub identity_files: Vec<String>,
+	pub proxy_jump: Option<String>,
+	/// Every other option, keyed by its lowercased name.
+	pub options: BTreeMap<String, String>,
+}
+
+/// Where to connect, with the same fields as the frontend's SSH terminal
+/// kind. `host` may be an alias from the config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SshTarget {
+	pub host: String,
+	pub username: Option<String>,
+	pub port: Option<u16>,
+}
+
+impl SshTarget {
+	/// Arguments selecting this target, ending with the destination.
+	pub fn args(&self) -> Vec<String> {
+		let mut args = Vec::new();
+		if let Some(port) = self.port {
+			args.push("-p".to_string());
+			args.push(port.to_string());
+		}
+		args.push(match &self.username {
+			Some(user) => format!("{}@{}", user, self.host),
+			None => self.host.clone(),
+		});
+		args
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SshConnectionTest {
+	/// The server answered.
+	pub reachable: bool,
+	/// A login succeeded without prompting.
+	pub authenticated: bool,
+	pub latency_ms: u64,
+	pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SshKey {
+	pub private_key_path: String,
+	pub public_key_path: String,
+	pub public_key: String,
+	/// e.g. `ed25519` or `rsa`.
+	pub key_type: String,
+	pub comment: Option<String>,
+}
+
+/// A `Host` block, or `None` patterns for a `Match` block, which isn't
+/// evaluated.
+struct ConfigBlock {
+	patterns: Option<Vec<String>>,
+	options: Vec<(String, String)>,
+}
+
+/// Matches `*` and `?` wildcards.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+	fn matches(pattern: &[char], text: &[char]) -> bool {
+		match pattern.split_first() {
+			None => text.is_empty(),
+			Some(('*', rest)) => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+			Some(('?', rest)) => !text.is_empty() && matches(rest, &text[1..]),
+			Some((c, rest)) => text.first() == Some(c) && matches(rest, &text[1..]),
+		}
+	}
+	let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+	let text: Vec<char> = text.to_lowercase().chars().collect();
+	matches(&pattern, &text)
+}
+
+fn block_matches(patterns: &[String], alias: &str) -> bool {
+	let mut matched = false;
+	for pattern in patterns {
+		match pattern.strip_prefix('!') {
+			Some(negated) if wildcard_match(negated, alias) => return false,
+			Some(_) => {}
+			None => matched |= wildcard_match(pattern, alias),
+		}
+	}
+	matched
+}
+
+/// Splits a config line into its lowercased keyword and argument.
+fn split_config_line(line: &str) -> Option<(String, String)> {
+	let line = line.trim();
+	if line.is_empty() || line.starts_with('#') {
+		return None;
+	}
+	let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+	let (keyword, rest) = line.split_at(end);
+	let value = rest.trim_start().strip_prefix('=').unwrap_or(rest).trim();
+	let value = value
+		.strip_prefix('"')
+		.and_then(|v| v.strip_suffix('"'))
+		.unwrap_or(value);
+	Some((keyword.to_lowercase(), value.to_string()))
+}
+
+/// Paths matched by an `Include` argument, relative to `~/.ssh`.
+fn include_paths(pattern: &str, ssh_dir: &Path, home: &Path) -> Vec<PathBuf> {
+	let path = match pattern.strip_prefix("~/") {
+		Some(rest) => home.join(rest),
+		None => ssh_dir.join(pattern),
+	};
+	let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+		return Vec::new();
+	};
+	if !name.contains(['*', '?']) {
+		return vec![path];
+	}
+	let Some(dir) = path.parent() else {
+		return Vec::new();
+	};
+	let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+		.into_iter()
+		.flatten()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| wildcard_match(&name, &entry.file_name().to_string_lossy()))
+		.map(|entry| entry.path())
+		.collect();
+	paths.sort();
+	paths
+}
+
+fn parse_config_file(
+	path: &Path,
+	ssh_dir: &Path,
+	home: &Path,
+	depth: usize,
+	blocks: &mut Vec<ConfigBlock>,
+) {
+	let Ok(content) = fs::read_to_string(path) else {
+		return;
+	};
+	for (keyword, value) in content.lines().filter_map(split_config_line) {
+		match keyword.as_str() {
+			"host" => blocks.push(ConfigBlock {
+				patterns: Some(value.split_whitespace().map(str::to_string).collect()),
+				options: Vec::new(),
+			}),
+			"match" => blocks.push(ConfigBlock {
+				patterns: None,
+				options: Vec::new(),
+			}),
+			"include" if depth < MAX_INCLUDE_DEPTH => {
+				for pattern in value.split_whitespace() {
+					for include in include_paths(pattern, ssh_dir, home) {
+						parse_config_file(&include, ssh_dir, home, depth + 1, blocks);
+					}
+				}
+			}
+			_ => {
+				if let Some(block) = blocks.last_mut() {
+					block.options.push((keyword, value));
+				}
+			}
+		}
+	}
+}
+
+fn resolve_host(alias: &str, blocks: &[ConfigBlock]) -> SshHost {
+	let mut host = SshHost {
+		alias: alias.to_string(),
+		host_name: alias.to_string(),
+		user: None,
+		port: 22,
+		identity_files: Vec::new(),
+		proxy_jump: None,
+		options: BTreeMap::new(),
+	};
+	let mut seen = HashSet::new();
+	let matching = blocks.iter().filter(|block| {
+		block
+			.patterns
+			.as_ref()
+			.is_some_and(|patterns| block_matches(patterns, alias))
+	});
+	// The first value found for an option is the one that applies
+	for (keyword, value) in matching.flat_map(|block| &block.options) {
+		if keyword == "identityfile" {
+			host.identity_files.push(value.clone());
+			continue;
+		}
+		if !seen.insert(keyword.clone()) {
+			continue;
+		}
+		match keyword.as_str() {
+			"hostname" => host.host_name = value.replace("%h", alias),
+			"user" => host.user = Some(value.clone()),
+			"port" => host.port = value.parse().unwrap_or(22),
+			"proxyjump" => host.proxy_jump = Some(value.clone()),
+			_ => {
+				host.options.insert(keyword.clone(), value.clone());
+			}
+		}
+	}
+	host
+}
+
+fn ssh_dir(app_handle: &AppHandle) -> Result<(PathBuf, PathBuf)> {
+	let home = app_handle.path().home_dir()?;
+	Ok((home.join(".ssh"), home))
+}
+
+/// Every concrete host in `~/.ssh/config` and the files it includes.
+/// Wildcard patterns only contribute options to the hosts they match.
+pub fn list_hosts(app_handle: &AppHandle) -> Result<Vec<SshHost>> {
+	let (ssh_dir, home) = ssh_dir(app_handle)?;
+	let mut blocks = vec![ConfigBlock {
+		patterns: Some(vec!["*".to_string()]),
+		options: Vec::new(),
+	}];
+	parse_config_file(&ssh_dir.join("config"), &ssh_dir, &home, 0, &mut blocks);
+
+	let mut aliases: Vec<&str> = Vec::new();
+	for pattern in blocks
+		.iter()
+		.filter_map(|block| block.patterns.as_ref())
+		.flatten()
+	{
+		if !pattern.contains(['*', '?', '!']) && !aliases.contains(&pattern.as_str()) {
+			aliases.push(pattern);
+		}
+	}
+	Ok(aliases
+		.into_iter()
+		.map(|alias| resolve_host(alias, &blocks))
+		.collect())
+}
+
+pub fn ssh_command(password: Option<&str>) -> Result<Command> {
+	let mut cmd = Command::new("ssh");
+	cmd.args([
+		"-o",
+		&format!("ConnectTimeout={}", CONNECT_TIMEOUT_SECS),
+		"-o",
+		"StrictHostKeyChecking=accept-new",
+	]);
+	match password {
+		// ssh only reads passwords from a terminal or an askpass program
+		Some(password) => {
+			cmd.args(["-o", "NumberOfPasswordPrompts=1"]);
+			cmd.env("SSH_ASKPASS", std::env::current_exe()?);
+			cmd.env("SSH_ASKPASS_REQUIRE", "force");
+			cmd.env(PASSWORD_ENV, password);
+		}
+		None => {
+			cmd.args(["-o", "BatchMode=yes"]);
+		}
+	}
+	Ok(cmd)
+}
+
+fn stderr(output: &Output) -> String {
+	String::from_utf8_lossy(&output.stderr).trim().to_string()
+}
+
+/// Logs in to `target` and runs `exit`, using keys and the agent only.
+pub fn test_connection(target: &SshTarget) -> Result<SshConnectionTest> {
+	let started = Instant::now();
+	let output = ssh_command(None)?
+		.args(target.args())
+		.arg("exit")
+		.stdin(Stdio::null())
+		.output()
+		.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+	let latency_ms = started.elapsed().as_millis() as u64;
+
+	if output.status.success() {
+		return Ok(SshConnectionTest {
+			reachable: true,
+			authenticated: true,
+			latency_ms,
+			error: None,
+		});
+	}
+	let error = stderr(&output);
+	// These come from the server, so it is up but wants other credentials
+	let reachable = [
+		"Permission denied",
+		"Host key verification failed",
+		"Too many authentication failures",
+	]
+	.iter()
+	.any(|message| error.contains(message));
+	Ok(SshConnectionTest {
+		reachable,
+		authenticated: false,
+		latency_ms,
+		error: Some(error),
+	})
+}
+
+fn read_key(private_key_path: PathBuf) -> Option<SshKey> {
+	let public_key_path =
+		private_key_path.with_extension(match private_key_path.extension() {
+			Some(ext) => format!("{}.pub", ext.to_string_lossy()),
+			None => "pub".to_string(),
+		});
+	let public_key = fs::read_to_string(&public_key_path)
+		.ok()?
+		.trim()
+		.to_string();
+	let mut parts = public_key.splitn(3, ' ');
+	let key_type = parts.next()?.trim_start_matches("ssh-").to_string();
+	parts.next()?;
+	Some(SshKey {
+		private_key_path: private_key_path.to_string_lossy().into_owned(),
+		public_key_path: public_key_path.to_string_lossy().into_owned(),
+		comment: parts.next().map(str::to_string),
+		key_type,
+		public_key,
+	})
+}
+
+/// Key pairs in `~/.ssh`, found by their `.pub` files.
+pub fn list_keys(app_handle: &AppHandle) -> Result<Vec<SshKey>> {
+	let (ssh_dir, _) = ssh_dir(app_handle)?;
+	let Ok(entries) = fs::read_dir(&ssh_dir) else {
+		return Ok(Vec::new());
+	};
+	let mut keys: Vec<SshKey> = entries
+		.filter_map(|entry| entry.ok())
+		.filter_map(|entry| {
+			let path = entry.path().to_string_lossy().into_owned();
+			let private = PathBuf::from(path.strip_suffix(".pub")?);
+			if !private.exists() {
+				return None;
+			}
+			read_key(private)
+		})
+		.collect();
+	keys.sort_by(|a, b| a.private_key_path.cmp(&b.private_key_path));
+	Ok(keys)
+}
+
+/// Generates an ed25519 key pair named `name` in `~/.ssh`. Existing keys
+/// are never overwritten.
+pub fn generate_key(
+	app_handle: &AppHandle,
+	name: &str,
+	comment: Option<&str>,
+	passphrase: Option<&str>,
+) -> Result<SshKey> {
+	if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+		return Err(anyhow!("Invalid key name: {}", name));
+	}
+	let (ssh_dir, _) = ssh_dir(app_handle)?;
+	fs::create_dir_all(&ssh_dir)?;
+	let path = ssh_dir.join(name);
+	if path.exists() {
+		return Err(anyhow!("{} already exists", path.display()));
+	}
+
+	let mut cmd = Command::new("ssh-keygen");
+	cmd.args(["-q", "-t", "ed25519", "-f"])
+		.arg(&path)
+		.args(["-N", passphrase.unwrap_or("")]);
+	if let Some(comment) = comment {
+		cmd.args(["-C", comment]);
+	}
+	let output = cmd
+		.stdin(Stdio::null())
+		.output()
+		.map_err(|e| anyhow!("Failed to run ssh-keygen: {}", e))?;
+	if !output.status.success() {
+		return Err(anyhow!("ssh-keygen failed: {}", stderr(&output)));
+	}
+	read_key(path).ok_or_else(|| anyhow!("ssh-keygen did not write a public key"))
+}
+
+/// Appends the public key to `~/.ssh/authorized_keys` on `target` unless
+/// it is already there. `password` is for hosts that don't accept any of
+/// the user's keys yet.
+pub fn install_public_key(
+	target: &SshTarget,
+	public_key_path: &Path,
+	password: Option<&str>,
+) -> Result<()> {
+	let public_key = fs::read_to_string(public_key_path)
+		.map_err(|e| anyhow!("Failed to read {}: {}", public_key_path.display(), e))?;
+	let public_key = public_key.trim();
+	if public_key.is_empty() || public_key.contains('\n') {
+		return Err(anyhow!("{} is not a public key", public_key_path.display()));
+	}
+
+	// The key goes through stdin so it needs no quoting
+	let script = "umask 077; mkdir -p ~/.ssh && touch ~/.ssh/authorized_keys && read -r key && \
+		(grep -qxF \"$key\" ~/.ssh/authorized_keys || printf '%s\\n' \"$key\" >> ~/.ssh/authorized_keys)";
+	let mut child = ssh_command(password)?
+		.args(target.args())
+		.arg(script)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::null())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+	if let Some(mut stdin) = child.stdin.take() {
+		writeln!(stdin, "{}", public_key)?;
+	}
+	let output = child.wait_with_output()?;
+	if !output.status.success() {
+		return Err(anyhow!("Failed to install key: {}", stderr(&output)));
+	}
+	Ok(())
+}
+
+/// Prints the password passed by `ssh_command` if ssh started this process
+/// as its askpass program, returning whether it did. The app must not
+/// start in that case.
+pub fn run_askpass_if_requested() -> bool {
+	if std::env::var_os("SSH_ASKPASS_REQUIRE").is_none() {
+		return false;
+	}
+	match std::env::var(PASSWORD_ENV) {
+		Ok(password) => {
+			println!("{}", password);
+			true
+		}
+		Err(_) => false,
+	}
+}
+
+#[tauri::command]
+pub async fn list_ssh_hosts(app_handle: AppHandle) -> Result<Vec<SshHost>, String> {
+	list_hosts(&app_handle).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn test_ssh_connection(target: SshTarget) -> Result<SshConnectionTest, String> {
+	tauri::async_runtime::spawn_blocking(move || test_connection(&target))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_ssh_keys(app_handle: AppHandle) -> Result<Vec<SshKey>, String> {
+	list_keys(&app_handle).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn generate_ssh_key(
+	name: String,
+	comment: Option<String>,
+	passphrase: Option<String>,
+	app_handle: AppHandle,
+) -> Result<SshKey, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		generate_key(
+			&app_handle,
+			&name,
+			comment.as_deref(),
+			passphrase.as_deref(),
+		)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn install_ssh_public_key(
+	target: SshTarget,
+	public_key_path: String,
+	password: Option<String>,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		install_public_key(&target, Path::new(&public_key_path), password.as_deref())
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
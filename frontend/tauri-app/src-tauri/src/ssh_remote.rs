@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use tauri::State;
+
+use crate::command_policy::{self, CommandOptions, CommandRequest};
+use crate::jobs::{self, CancelToken, JobKind, JobManager};
+use crate::os::{sort_nodes, FileNode};
+use crate::session_supervisor::{RemoteSession, SessionSupervisor};
+use crate::ssh::{self, SshTarget};
+use crate::util::shell_quote;
+
+/// How long an idle shared connection stays open.
+#[cfg(unix)]
+const CONTROL_PERSIST_SECS: u32 = 600;
+/// Keep-alives, so dropped connections are noticed within a minute rather
+/// than when the next command hangs.
+const SERVER_ALIVE_INTERVAL_SECS: u32 = 15;
+const SERVER_ALIVE_COUNT_MAX: u32 = 3;
+
+/// Lists the entries of `$1` as NUL-separated records of kind (`d` or
+/// `f`), `l` for symlinks, `1` for broken ones, link target and name.
+/// Only POSIX tools, since the remote may not have GNU find.
+const LIST_DIRECTORY_SCRIPT: &str = r#"cd -- "$1" || exit 1
+for f in *; do
+	[ -e "$f" ] || [ -L "$f" ] || continue
+	k=f; [ -d "$f" ] && k=d
+	s=-; b=0; t=
+	if [ -L "$f" ]; then s=l; t=$(readlink -- "$f"); [ -e "$f" ] || b=1; fi
+	printf '%s\000%s\000%s\000%s\000%s\000' "$k" "$s" "$b" "$t" "$f"
+done"#;
+
+/// Copies `$1` to `$2` with rsync, or cp where rsync is missing. `$3` is
+/// `1` to leave out `.git`.
+const COPY_SCRIPT: &str = r#"if command -v rsync >/dev/null 2>&1; then
+	if [ "$3" = 1 ]; then exec rsync -a --exclude=.git "$1/" "$2"; fi
+	exec rsync -a "$1/" "$2"
+fi
+mkdir -p -- "$2" && cd -- "$1" || exit 1
+if [ "$3" = 1 ]; then
+	find . -name .git -prune -o \( -type f -o -type l \) -exec sh -c 'mkdir -p -- "$2/$(dirname -- "$1")" && cp -P -- "$1" "$2/$1"' sh {} "$2" \;
+else
+	cp -a . "$2"
+fi"#;
+
+/// Shared ssh connections, one per target. Commands to a target reuse its
+/// connection through an OpenSSH control socket instead of logging in each
+/// time. Windows' OpenSSH can't share connections, so there every command
+/// connects on its own.
+#[derive(Default)]
+pub struct SshConnections {
+	/// Control sockets of the connections we started, keyed by the
+	/// target's ssh arguments.
+	sockets: Mutex<HashMap<Vec<String>, PathBuf>>,
+}
+
+impl SshConnections {
+	/// The control socket for `target`, kept short since socket paths are
+	/// limited to about a hundred bytes.
+	#[cfg(unix)]
+	fn socket_path(target: &SshTarget) -> PathBuf {
+		use std::hash::{Hash, Hasher};
+		let mut hasher = std::collections::hash_map::DefaultHasher::new();
+		target.args().hash(&mut hasher);
+		std::env::temp_dir().join(format!("ariana-ssh-{:016x}", hasher.finish()))
+	}
+
+	/// Starts the shared connection to `target` unless one is up, returning
+	/// the options that route a command through it.
+	#[cfg(unix)]
+	fn connect(&self, target: &SshTarget) -> Result<Vec<String>> {
+		let socket = Self::socket_path(target);
+		let control = vec![
+			"-o".to_string(),
+			"ControlMaster=no".to_string(),
+			"-S".to_string(),
+			socket.to_string_lossy().to_string(),
+		];
+
+		let mut sockets = self.sockets.lock().unwrap();
+		let alive = socket.exists()
+			&& ssh::ssh_command(None)?
+				.args(&control)
+				.args(["-O", "check"])
+				.args(target.args())
+				.stdin(Stdio::null())
+				.stdout(Stdio::null())
+				.stderr(Stdio::null())
+				.status()
+				.map(|status| status.success())
+				.unwrap_or(false);
+		if !alive {
+			// -f backgrounds the master once it has logged in; its output
+			// must not be piped, or reading it would wait for the master
+			let output = ssh::ssh_command(None)?
+				.args(["-M", "-N", "-f", "-S"])
+				.arg(&socket)
+				.args(keep_alive_args())
+				.args([
+					"-o".to_string(),
+					format!("ControlPersist={}", CONTROL_PERSIST_SECS),
+				])
+				.args(target.args())
+				.stdin(Stdio::null())
+				.stdout(Stdio::null())
+				.stderr(Stdio::piped())
+				.output()
+				.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+			if !output.status.success() {
+				return Err(anyhow!(
+					"Failed to connect to {}: {}",
+					target.host,
+					String::from_utf8_lossy(&output.stderr).trim()
+				));
+			}
+		}
+		sockets.insert(target.args(), socket);
+		Ok(control)
+	}
+
+	#[cfg(not(unix))]
+	fn connect(&self, _target: &SshTarget) -> Result<Vec<String>> {
+		Ok(keep_alive_args())
+	}
+
+	/// Builds a command running `remote_command`, a shell command line, on
+	/// `target` over its shared connection.
+	pub fn command(&self, target: &SshTarget, remote_command: &str) -> Result<Command> {
+		let options = self.connect(target)?;
+		let mut cmd = ssh::ssh_command(None)?;
+		cmd.args(options)
+			.args(target.args())
+			.arg("--")
+			.arg(remote_command);
+		Ok(cmd)
+	}
+
+	/// Builds a command running `script` with `sh` on `target`, its
+	/// arguments as `$1`, `$2`, ...
+	pub fn script(
+		&self,
+		target: &SshTarget,
+		script: &str,
+		args: &[&str],
+	) -> Result<Command> {
+		let mut remote_command = format!("sh -c {} sh", shell_quote(script));
+		for arg in args {
+			remote_command.push(' ');
+			remote_command.push_str(&shell_quote(arg));
+		}
+		self.command(target, &remote_command)
+	}
+
+	/// Closes the shared connection to `target`, if we started one.
+	pub fn disconnect(&self, target: &SshTarget) {
+		let socket = self.sockets.lock().unwrap().remove(&target.args());
+		if let Some(socket) = socket {
+			close_master(&socket, &target.args());
+		}
+	}
+
+	/// Closes every shared connection, for when the app exits.
+	pub fn disconnect_all(&self) {
+		let sockets: Vec<_> = self.sockets.lock().unwrap().drain().collect();
+		for (destination, socket) in sockets {
+			close_master(&socket, &destination);
+		}
+	}
+
+	pub fn read_directory(
+		&self,
+		target: &SshTarget,
+		path: &str,
+	) -> Result<Vec<FileNode>> {
+		let output = self
+			.script(target, LIST_DIRECTORY_SCRIPT, &[path])?
+			.stdin(Stdio::null())
+			.output()
+			.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+		if !output.status.success() {
+			return Err(anyhow!(
+				"Failed to read {} on {}: {}",
+				path,
+				target.host,
+				String::from_utf8_lossy(&output.stderr).trim()
+			));
+		}
+
+		let stdout = String::from_utf8_lossy(&output.stdout);
+		let fields: Vec<&str> = stdout.split('\0').collect();
+		let mut nodes: Vec<FileNode> = fields
+			.chunks_exact(5)
+			.filter(|record| !record[4].starts_with('.'))
+			.map(|record| {
+				let [kind, symlink, broken, link_target, name] =
+					[record[0], record[1], record[2], record[3], record[4]];
+				let is_directory = kind == "d";
+				let is_symlink = symlink == "l";
+				FileNode {
+					name: name.to_string(),
+					path: format!("{}/{}", path.trim_end_matches('/'), name),
+					is_directory,
+					children: None,
+					extension: (!is_directory)
+						.then(|| {
+							Path::new(name).extension()?.to_str().map(str::to_string)
+						})
+						.flatten(),
+					is_symlink,
+					link_target: is_symlink.then(|| link_target.to_string()),
+					is_broken_link: broken == "1",
+				}
+			})
+			.collect();
+		sort_nodes(&mut nodes);
+		Ok(nodes)
+	}
+
+	pub fn copy_files(
+		&self,
+		target: &SshTarget,
+		source: &str,
+		destination: &str,
+		exclude_git: bool,
+		cancel: &CancelToken,
+	) -> Result<()> {
+		let exclude_git = if exclude_git { "1" } else { "0" };
+		let output = jobs::output(
+			&mut self.script(target, COPY_SCRIPT, &[source, destination, exclude_git])?,
+			cancel,
+		)?;
+		if !output.status.success() {
+			return Err(anyhow!(
+				"Copy failed on {}: {}",
+				target.host,
+				String::from_utf8_lossy(&output.stderr).trim()
+			));
+		}
+		Ok(())
+	}
+
+	pub fn delete_path(&self, target: &SshTarget, path: &str) -> Result<()> {
+		let trimmed = path.trim_end_matches('/');
+		if trimmed.is_empty() || trimmed == "~" || trimmed == "." {
+			return Err(anyhow!("Refusing to delete {}", path));
+		}
+		let output = self
+			.command(target, &format!("rm -rf -- {}", shell_quote(path)))?
+			.stdin(Stdio::null())
+			.output()
+			.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+		if !output.status.success() {
+			return Err(anyhow!(
+				"Failed to delete {} on {}: {}",
+				path,
+				target.host,
+				String::from_utf8_lossy(&output.stderr).trim()
+			));
+		}
+		Ok(())
+	}
+}
+
+pub fn keep_alive_args() -> Vec<String> {
+	vec![
+		"-o".to_string(),
+		format!("ServerAliveInterval={}", SERVER_ALIVE_INTERVAL_SECS),
+		"-o".to_string(),
+		format!("ServerAliveCountMax={}", SERVER_ALIVE_COUNT_MAX),
+	]
+}
+
+fn close_master(socket: &Path, destination: &[String]) {
+	let result = Command::new("ssh")
+		.arg("-S")
+		.arg(socket)
+		.args(["-O", "exit"])
+		.args(destination)
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.status();
+	if let Err(e) = result {
+		log::warn!("Failed to close ssh connection: {}", e);
+	}
+}
+
+#[tauri::command]
+pub async fn ssh_read_directory(
+	target: SshTarget,
+	path: String,
+	connections: State<'_, Arc<SshConnections>>,
+	supervisor: State<'_, Arc<SessionSupervisor>>,
+) -> Result<Vec<FileNode>, String> {
+	let session = RemoteSession::Ssh {
+		target: target.clone(),
+	};
+	supervisor
+		.retry_read(Some(session), || {
+			let connections = connections.inner().clone();
+			let (target, path) = (target.clone(), path.clone());
+			async move {
+				tauri::async_runtime::spawn_blocking(move || {
+					connections.read_directory(&target, &path)
+				})
+				.await?
+			}
+		})
+		.await
+		.map_err(|e| e.to_string())
+}
+
+/// Runs `command` with `args` in `directory` on the remote, subject to the
+/// same policy as local commands.
+#[tauri::command]
+pub async fn ssh_execute_command(
+	target: SshTarget,
+	command: String,
+	args: Vec<String>,
+	directory: String,
+	options: Option<CommandOptions>,
+	connections: State<'_, Arc<SshConnections>>,
+	app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+	let mut remote_command = format!(
+		"cd {} && exec {}",
+		shell_quote(&directory),
+		shell_quote(&command)
+	);
+	for arg in &args {
+		remote_command.push(' ');
+		remote_command.push_str(&shell_quote(arg));
+	}
+	let connections = connections.inner().clone();
+	let cmd = tauri::async_runtime::spawn_blocking(move || {
+		connections.command(&target, &remote_command)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())?;
+	let request = CommandRequest {
+		program: command,
+		args,
+		directory: Some(directory),
+		distribution: None,
+		options: options.unwrap_or_default(),
+	};
+	command_policy::run(&app_handle, request, cmd).await
+}
+
+/// Copies `source` to `destination`, both on the remote, as a job.
+#[tauri::command]
+pub async fn ssh_copy_files(
+	target: SshTarget,
+	source: String,
+	destination: String,
+	exclude_git: bool,
+	connections: State<'_, Arc<SshConnections>>,
+	job_manager: State<'_, Arc<JobManager>>,
+) -> Result<(), String> {
+	let connections = connections.inner().clone();
+	let title = format!("Copy {} to {} on {}", source, destination, target.host);
+	job_manager
+		.run(JobKind::Copy, title, move |job| {
+			connections.copy_files(
+				&target,
+				&source,
+				&destination,
+				exclude_git,
+				job.token(),
+			)
+		})
+		.await
+}
+
+#[tauri::command]
+pub async fn ssh_delete_path(
+	target: SshTarget,
+	path: String,
+	connections: State<'_, Arc<SshConnections>>,
+) -> Result<(), String> {
+	let connections = connections.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || connections.delete_path(&target, &path))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn ssh_disconnect(
+	target: SshTarget,
+	connections: State<'_, Arc<SshConnections>>,
+) -> Result<(), String> {
+	let connections = connections.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || connections.disconnect(&target))
+		.await
+		.map_err(|e| e.to_string())
+}
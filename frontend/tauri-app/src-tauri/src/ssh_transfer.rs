@@ -0,0 +1,367 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tauri::State;
+use walkdir::WalkDir;
+
+use crate::hashing::sha256_file;
+use crate::jobs::{JobHandle, JobKind, JobManager, JobProgress};
+use crate::ssh::SshTarget;
+use crate::ssh_remote::SshConnections;
+
+/// Bytes sent or received between progress reports and cancellation
+/// checks.
+const CHUNK_SIZE: usize = 1024 * 1024;
+/// Appended to the destination while a transfer is incomplete. A transfer
+/// finding one picks up where the last one stopped.
+const PARTIAL_SUFFIX: &str = ".ariana-part";
+
+/// Prints the size of `$1`, or 0 if there is no such file.
+const REMOTE_SIZE_SCRIPT: &str = r#"if [ -f "$1" ]; then wc -c < "$1"; else echo 0; fi"#;
+/// Prints the SHA-256 of `$1`; macOS and the BSDs only have shasum.
+const REMOTE_HASH_SCRIPT: &str = r#"[ -f "$1" ] || { echo "No such file: $1" >&2; exit 1; }
+if command -v sha256sum >/dev/null 2>&1; then sha256sum < "$1"; else shasum -a 256 < "$1"; fi | cut -d ' ' -f 1"#;
+/// Appends stdin to `$1`, creating its directory.
+const REMOTE_APPEND_SCRIPT: &str = r#"mkdir -p -- "$(dirname -- "$1")" && cat >> "$1""#;
+/// Prints `$1` from byte `$2`, counting from 1.
+const REMOTE_READ_SCRIPT: &str = r#"tail -c +"$2" -- "$1""#;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferredFile {
+	pub source: String,
+	pub destination: String,
+	pub bytes: u64,
+	/// Bytes an earlier, interrupted transfer had already moved.
+	pub resumed_from: u64,
+	pub sha256: String,
+}
+
+/// Totals across the files of a transfer, reported as job progress.
+struct Progress<'a> {
+	job: &'a JobHandle,
+	completed: u64,
+	total: u64,
+}
+
+impl Progress<'_> {
+	fn advance(&mut self, bytes: u64, name: &str) {
+		self.completed += bytes;
+		self.job.progress(JobProgress {
+			message: Some(name.to_string()),
+			completed: Some(self.completed),
+			total: Some(self.total),
+		});
+	}
+}
+
+/// Runs `script` on the remote and returns what it printed.
+fn remote_output(
+	connections: &SshConnections,
+	target: &SshTarget,
+	script: &str,
+	args: &[&str],
+) -> Result<String> {
+	let output = connections
+		.script(target, script, args)?
+		.stdin(Stdio::null())
+		.output()
+		.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"{}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn remote_size(
+	connections: &SshConnections,
+	target: &SshTarget,
+	path: &str,
+) -> Result<u64> {
+	remote_output(connections, target, REMOTE_SIZE_SCRIPT, &[path])?
+		.parse()
+		.map_err(|e| anyhow!("Failed to read the size of {}: {}", path, e))
+}
+
+fn remote_remove(
+	connections: &SshConnections,
+	target: &SshTarget,
+	path: &str,
+) -> Result<()> {
+	remote_output(connections, target, r#"rm -f -- "$1""#, &[path]).map(|_| ())
+}
+
+/// Kills `child` and fails if the job was cancelled.
+fn check_cancelled(job: &JobHandle, child: &mut Child) -> Result<()> {
+	if let Err(e) = job.token().check() {
+		let _ = child.kill();
+		let _ = child.wait();
+		return Err(e);
+	}
+	Ok(())
+}
+
+fn upload_file(
+	connections: &SshConnections,
+	target: &SshTarget,
+	local: &Path,
+	remote: &str,
+	progress: &mut Progress,
+) -> Result<TransferredFile> {
+	let size = fs::metadata(local)?.len();
+	let partial = format!("{}{}", remote, PARTIAL_SUFFIX);
+	let mut resumed_from = remote_size(connections, target, &partial)?;
+	// The source shrank since, so what was sent can't be a prefix of it
+	if resumed_from > size {
+		remote_remove(connections, target, &partial)?;
+		resumed_from = 0;
+	}
+	let name = local.to_string_lossy().to_string();
+	progress.advance(resumed_from, &name);
+
+	let mut file = File::open(local)?;
+	file.seek(SeekFrom::Start(resumed_from))?;
+	let mut child = connections
+		.script(target, REMOTE_APPEND_SCRIPT, &[&partial])?
+		.stdin(Stdio::piped())
+		.stdout(Stdio::null())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+	let mut stdin = child
+		.stdin
+		.take()
+		.ok_or_else(|| anyhow!("ssh has no stdin"))?;
+	let mut buffer = vec![0; CHUNK_SIZE];
+	loop {
+		check_cancelled(progress.job, &mut child)?;
+		let read = file.read(&mut buffer)?;
+		if read == 0 {
+			break;
+		}
+		stdin.write_all(&buffer[..read])?;
+		progress.advance(read as u64, &name);
+	}
+	drop(stdin);
+	let output = child.wait_with_output()?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"Upload of {} failed: {}",
+			name,
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+
+	let sha256 = sha256_file(local)?;
+	let received = remote_output(connections, target, REMOTE_HASH_SCRIPT, &[&partial])?;
+	if received != sha256 {
+		// Resuming from it again would fail the same way
+		remote_remove(connections, target, &partial)?;
+		return Err(anyhow!(
+			"{} changed or was corrupted in transit; the partial upload was discarded",
+			name
+		));
+	}
+	remote_output(
+		connections,
+		target,
+		r#"mv -f -- "$1" "$2""#,
+		&[&partial, remote],
+	)?;
+	Ok(TransferredFile {
+		source: name,
+		destination: remote.to_string(),
+		bytes: size,
+		resumed_from,
+		sha256,
+	})
+}
+
+/// Uploads `local`, a file or a directory, to `remote`. Directories are
+/// copied file by file, skipping `.git` when `exclude_git` is set.
+pub fn upload(
+	connections: &SshConnections,
+	target: &SshTarget,
+	local: &Path,
+	remote: &str,
+	exclude_git: bool,
+	job: &JobHandle,
+) -> Result<Vec<TransferredFile>> {
+	let files: Vec<(PathBuf, String)> = if local.is_dir() {
+		WalkDir::new(local)
+			.into_iter()
+			.filter_entry(|entry| !(exclude_git && entry.file_name() == ".git"))
+			.filter_map(|entry| entry.ok())
+			.filter(|entry| entry.file_type().is_file())
+			.filter_map(|entry| {
+				let relative = entry.path().strip_prefix(local).ok()?;
+				let relative = relative.to_string_lossy().replace('\\', "/");
+				let destination =
+					format!("{}/{}", remote.trim_end_matches('/'), relative);
+				Some((entry.into_path(), destination))
+			})
+			.collect()
+	} else {
+		vec![(local.to_path_buf(), remote.to_string())]
+	};
+
+	let total = files
+		.iter()
+		.filter_map(|(path, _)| fs::metadata(path).ok())
+		.map(|metadata| metadata.len())
+		.sum();
+	let mut progress = Progress {
+		job,
+		completed: 0,
+		total,
+	};
+	files
+		.iter()
+		.map(|(path, destination)| {
+			upload_file(connections, target, path, destination, &mut progress)
+		})
+		.collect()
+}
+
+/// Downloads the file `remote` to `local`.
+pub fn download(
+	connections: &SshConnections,
+	target: &SshTarget,
+	remote: &str,
+	local: &Path,
+	job: &JobHandle,
+) -> Result<TransferredFile> {
+	// Hashed first, so a file changing during the download fails the check
+	let expected = remote_output(connections, target, REMOTE_HASH_SCRIPT, &[remote])?;
+	let size = remote_size(connections, target, remote)?;
+
+	let mut partial = local.as_os_str().to_owned();
+	partial.push(PARTIAL_SUFFIX);
+	let partial = PathBuf::from(partial);
+	if let Some(parent) = partial.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	let mut resumed_from = fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+	if resumed_from > size {
+		fs::remove_file(&partial)?;
+		resumed_from = 0;
+	}
+	let mut progress = Progress {
+		job,
+		completed: 0,
+		total: size,
+	};
+	progress.advance(resumed_from, remote);
+
+	let mut file = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(&partial)?;
+	let start = (resumed_from + 1).to_string();
+	let mut child = connections
+		.script(target, REMOTE_READ_SCRIPT, &[remote, &start])?
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| anyhow!("Failed to run ssh: {}", e))?;
+	let mut stdout = child
+		.stdout
+		.take()
+		.ok_or_else(|| anyhow!("ssh has no stdout"))?;
+	let mut buffer = vec![0; CHUNK_SIZE];
+	loop {
+		check_cancelled(job, &mut child)?;
+		let read = stdout.read(&mut buffer)?;
+		if read == 0 {
+			break;
+		}
+		file.write_all(&buffer[..read])?;
+		progress.advance(read as u64, remote);
+	}
+	file.sync_all()?;
+	let output = child.wait_with_output()?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"Download of {} failed: {}",
+			remote,
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+
+	let sha256 = sha256_file(&partial)?;
+	if sha256 != expected {
+		fs::remove_file(&partial)?;
+		return Err(anyhow!(
+			"{} changed or was corrupted in transit; the partial download was discarded",
+			remote
+		));
+	}
+	fs::rename(&partial, local)?;
+	Ok(TransferredFile {
+		source: remote.to_string(),
+		destination: local.to_string_lossy().to_string(),
+		bytes: size,
+		resumed_from,
+		sha256,
+	})
+}
+
+/// Uploads a file or directory as a job, resuming any interrupted earlier
+/// upload of the same files and checking each against its SHA-256.
+#[tauri::command]
+pub async fn ssh_upload(
+	target: SshTarget,
+	local_path: String,
+	remote_path: String,
+	exclude_git: Option<bool>,
+	connections: State<'_, Arc<SshConnections>>,
+	job_manager: State<'_, Arc<JobManager>>,
+) -> Result<Vec<TransferredFile>, String> {
+	let connections = connections.inner().clone();
+	let title = format!("Upload {} to {}:{}", local_path, target.host, remote_path);
+	job_manager
+		.run(JobKind::Copy, title, move |job| {
+			upload(
+				&connections,
+				&target,
+				Path::new(&local_path),
+				&remote_path,
+				exclude_git.unwrap_or(false),
+				job,
+			)
+		})
+		.await
+}
+
+/// Downloads a file as a job, resuming and verifying like `ssh_upload`.
+#[tauri::command]
+pub async fn ssh_download(
+	target: SshTarget,
+	remote_path: String,
+	local_path: String,
+	connections: State<'_, Arc<SshConnections>>,
+	job_manager: State<'_, Arc<JobManager>>,
+) -> Result<TransferredFile, String> {
+	let connections = connections.inner().clone();
+	let title = format!("Download {}:{} to {}", target.host, remote_path, local_path);
+	job_manager
+		.run(JobKind::Copy, title, move |job| {
+			download(
+				&connections,
+				&target,
+				&remote_path,
+				Path::new(&local_path),
+				job,
+			)
+		})
+		.await
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::TcpListener;
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+use uuid::Uuid;
+
+use crate::ssh::{self, SshTarget};
+use crate::ssh_remote::keep_alive_args;
+
+/// How long ssh must keep running before a tunnel counts as open; it exits
+/// within this time when the forward can't be set up.
+const ESTABLISH_GRACE: Duration = Duration::from_secs(2);
+/// How often the ssh process is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Reconnection delays double from the first to the last.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelDirection {
+	/// A local port reaching a port on or beside the remote (`ssh -L`).
+	Local,
+	/// A remote port reaching a port on or beside this machine (`ssh -R`).
+	Remote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelState {
+	Connecting,
+	Open,
+	/// The connection dropped and is being retried.
+	Reconnecting,
+	/// The forward was refused, e.g. because the port is taken.
+	Failed,
+}
+
+/// A port forward. Emitted as the `ssh-tunnel-changed` event whenever its
+/// state changes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tunnel {
+	pub id: String,
+	pub target: SshTarget,
+	pub direction: TunnelDirection,
+	/// The port listened on: here for local forwards, on the remote for
+	/// remote ones.
+	pub bind_port: u16,
+	/// Where connections go, as seen from the other end.
+	pub host: String,
+	pub port: u16,
+	pub state: TunnelState,
+	pub reconnects: u32,
+	/// What ssh printed when it last exited.
+	pub error: Option<String>,
+	/// Where a local forward can be opened in a browser.
+	pub url: Option<String>,
+}
+
+impl Tunnel {
+	fn forward_args(&self) -> [String; 2] {
+		let flag = match self.direction {
+			TunnelDirection::Local => "-L",
+			TunnelDirection::Remote => "-R",
+		};
+		let spec = format!("{}:{}:{}", self.bind_port, self.host, self.port);
+		[flag.to_string(), spec]
+	}
+}
+
+struct RunningTunnel {
+	tunnel: Arc<Mutex<Tunnel>>,
+	stopped: Arc<AtomicBool>,
+	child: Arc<Mutex<Option<Child>>>,
+}
+
+impl RunningTunnel {
+	fn stop(&self) {
+		self.stopped.store(true, Ordering::SeqCst);
+		if let Some(mut child) = self.child.lock().unwrap().take() {
+			let _ = child.kill();
+			let _ = child.wait();
+		}
+	}
+}
+
+/// SSH port forwards, each kept up by a thread that restarts its ssh
+/// process when the connection drops.
+pub struct SshTunnels {
+	app_handle: AppHandle,
+	tunnels: Mutex<HashMap<String, RunningTunnel>>,
+}
+
+impl SshTunnels {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		Arc::new(Self {
+			app_handle,
+			tunnels: Mutex::new(HashMap::new()),
+		})
+	}
+
+	/// Starts a forward and waits until it is open or refused. A local
+	/// `bind_port` of 0 picks a free port.
+	pub fn open(
+		&self,
+		target: SshTarget,
+		direction: TunnelDirection,
+		bind_port: u16,
+		host: String,
+		port: u16,
+	) -> Result<Tunnel> {
+		let bind_port = match (direction, bind_port) {
+			(TunnelDirection::Local, 0) => {
+				TcpListener::bind(("127.0.0.1", 0))?.local_addr()?.port()
+			}
+			(TunnelDirection::Remote, 0) => {
+				return Err(anyhow!("A remote forward needs a port to listen on"))
+			}
+			(_, bind_port) => bind_port,
+		};
+		let tunnel = Tunnel {
+			id: Uuid::new_v4().to_string(),
+			target,
+			direction,
+			bind_port,
+			host,
+			port,
+			state: TunnelState::Connecting,
+			reconnects: 0,
+			error: None,
+			url: (direction == TunnelDirection::Local)
+				.then(|| format!("http://localhost:{}", bind_port)),
+		};
+		let running = RunningTunnel {
+			tunnel: Arc::new(Mutex::new(tunnel.clone())),
+			stopped: Arc::new(AtomicBool::new(false)),
+			child: Arc::new(Mutex::new(None)),
+		};
+		self.supervise(&running);
+
+		// The first attempt decides whether the forward works at all
+		loop {
+			let current = running.tunnel.lock().unwrap().clone();
+			match current.state {
+				TunnelState::Connecting => thread::sleep(POLL_INTERVAL),
+				TunnelState::Open => {
+					self.tunnels
+						.lock()
+						.unwrap()
+						.insert(current.id.clone(), running);
+					return Ok(current);
+				}
+				TunnelState::Failed | TunnelState::Reconnecting => {
+					running.stop();
+					return Err(anyhow!(
+						"Failed to forward port {}: {}",
+						current.bind_port,
+						current.error.unwrap_or_default()
+					));
+				}
+			}
+		}
+	}
+
+	fn supervise(&self, running: &RunningTunnel) {
+		let app_handle = self.app_handle.clone();
+		let tunnel = running.tunnel.clone();
+		let snapshot = running.tunnel.clone();
+		let stopped = running.stopped.clone();
+		let child_slot = running.child.clone();
+		let update = move |change: &dyn Fn(&mut Tunnel)| {
+			let mut tunnel = tunnel.lock().unwrap();
+			change(&mut tunnel);
+			let _ = app_handle.emit("ssh-tunnel-changed", tunnel.clone());
+		};
+
+		thread::spawn(move || {
+			let mut backoff = MIN_BACKOFF;
+			let mut ever_opened = false;
+			while !stopped.load(Ordering::SeqCst) {
+				let current = snapshot.lock().unwrap().clone();
+				let spawned = ssh::ssh_command(None).and_then(|mut cmd| {
+					cmd.args(keep_alive_args())
+						.args(["-N", "-o", "ExitOnForwardFailure=yes"])
+						.args(current.forward_args())
+						.args(current.target.args())
+						.stdin(Stdio::null())
+						.stdout(Stdio::null())
+						.stderr(Stdio::piped())
+						.spawn()
+						.map_err(|e| anyhow!("Failed to run ssh: {}", e))
+				});
+				let child = match spawned {
+					Ok(child) => child,
+					Err(e) => {
+						update(&|tunnel| {
+							tunnel.state = TunnelState::Failed;
+							tunnel.error = Some(e.to_string());
+						});
+						return;
+					}
+				};
+				*child_slot.lock().unwrap() = Some(child);
+				// Stopped while ssh was starting, before it could be killed
+				if stopped.load(Ordering::SeqCst) {
+					if let Some(mut child) = child_slot.lock().unwrap().take() {
+						let _ = child.kill();
+						let _ = child.wait();
+					}
+					return;
+				}
+
+				let started = Instant::now();
+				let mut open = false;
+				let error = loop {
+					let mut slot = child_slot.lock().unwrap();
+					let Some(child) = slot.as_mut() else {
+						// Stopped
+						return;
+					};
+					if let Ok(Some(_)) = child.try_wait() {
+						let mut error = String::new();
+						if let Some(mut stderr) = child.stderr.take() {
+							let _ = stderr.read_to_string(&mut error);
+						}
+						slot.take();
+						break error.trim().to_string();
+					}
+					drop(slot);
+					if !open && started.elapsed() >= ESTABLISH_GRACE {
+						open = true;
+						ever_opened = true;
+						backoff = MIN_BACKOFF;
+						update(&|tunnel| {
+							tunnel.state = TunnelState::Open;
+							tunnel.error = None;
+						});
+					}
+					thread::sleep(POLL_INTERVAL);
+				};
+				if stopped.load(Ordering::SeqCst) {
+					return;
+				}
+
+				// A tunnel that never opened was refused, e.g. because the port
+				// is taken, and retrying won't help; `open` reports it
+				if !ever_opened {
+					update(&|tunnel| {
+						tunnel.state = TunnelState::Failed;
+						tunnel.error = Some(error.clone());
+					});
+					return;
+				}
+				update(&|tunnel| {
+					tunnel.state = TunnelState::Reconnecting;
+					tunnel.error = Some(error.clone());
+					tunnel.reconnects += 1;
+				});
+				thread::sleep(backoff);
+				backoff = (backoff * 2).min(MAX_BACKOFF);
+			}
+		});
+	}
+
+	pub fn close(&self, id: &str) -> Result<()> {
+		let running = self
+			.tunnels
+			.lock()
+			.unwrap()
+			.remove(id)
+			.ok_or_else(|| anyhow!("No tunnel {}", id))?;
+		running.stop();
+		Ok(())
+	}
+
+	/// Closes every tunnel, for when the app exits.
+	pub fn close_all(&self) {
+		let tunnels: Vec<_> = self.tunnels.lock().unwrap().drain().collect();
+		for (_, running) in tunnels {
+			running.stop();
+		}
+	}
+
+	pub fn list(&self) -> Vec<Tunnel> {
+		let mut tunnels: Vec<Tunnel> = self
+			.tunnels
+			.lock()
+			.unwrap()
+			.values()
+			.map(|running| running.tunnel.lock().unwrap().clone())
+			.collect();
+		tunnels.sort_by_key(|tunnel| (tunnel.target.host.clone(), tunnel.bind_port));
+		tunnels
+	}
+}
+
+/// Forwards `bind_port` to `host:port` through `target`, reconnecting when
+/// the connection drops. For a local forward, `host` is resolved on the
+/// remote, so "localhost" is the remote machine.
+#[tauri::command]
+pub async fn open_ssh_tunnel(
+	target: SshTarget,
+	direction: TunnelDirection,
+	bind_port: u16,
+	host: Option<String>,
+	port: u16,
+	tunnels: State<'_, Arc<SshTunnels>>,
+) -> Result<Tunnel, String> {
+	let tunnels = tunnels.inner().clone();
+	let host = host.unwrap_or_else(|| "localhost".to_string());
+	tauri::async_runtime::spawn_blocking(move || {
+		tunnels.open(target, direction, bind_port, host, port)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn close_ssh_tunnel(
+	id: String,
+	tunnels: State<'_, Arc<SshTunnels>>,
+) -> Result<(), String> {
+	let tunnels = tunnels.inner().clone();
+	tauri::async_runtime::spawn_blocking(move || tunnels.close(&id))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_ssh_tunnels(
+	tunnels: State<'_, Arc<SshTunnels>>,
+) -> Result<Vec<Tunnel>, String> {
+	Ok(tunnels.list())
+}
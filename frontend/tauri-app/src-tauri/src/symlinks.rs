@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::os::OsSession;
+#[cfg(target_os = "windows")]
+use crate::wsl::{self, WslCommand};
+
+/// What copies do with symbolic links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SymlinkMode {
+	/// Copy the link itself, still pointing where the original does.
+	#[default]
+	Preserve,
+	/// Copy what the link points to. A link back into a folder being copied
+	/// is reported instead of copied over and over.
+	Follow,
+}
+
+impl SymlinkMode {
+	/// The `cp` and `find` option for this mode.
+	pub fn flag(self) -> &'static str {
+		match self {
+			Self::Preserve => "-P",
+			Self::Follow => "-L",
+		}
+	}
+}
+
+/// Deletes `path`. A symlink is removed on its own, never what it points
+/// to, and broken links can be deleted too.
+pub fn remove_path(path: &Path) -> Result<()> {
+	let metadata = fs::symlink_metadata(path)
+		.with_context(|| format!("Path does not exist: {}", path.display()))?;
+	let result = if metadata.is_symlink() {
+		// Links to directories are directories on Windows
+		fs::remove_file(path).or_else(|_| fs::remove_dir(path))
+	} else if metadata.is_dir() {
+		fs::remove_dir_all(path)
+	} else {
+		fs::remove_file(path)
+	};
+	result.with_context(|| format!("Failed to delete '{}'", path.display()))
+}
+
+fn create_symlink_local(target: &str, link: &str) -> Result<()> {
+	#[cfg(target_os = "windows")]
+	{
+		// Windows needs to know whether the link is to a directory
+		let resolved = Path::new(link)
+			.parent()
+			.map(|parent| parent.join(target))
+			.unwrap_or_else(|| Path::new(target).to_path_buf());
+		if resolved.is_dir() {
+			std::os::windows::fs::symlink_dir(target, link)?;
+		} else {
+			std::os::windows::fs::symlink_file(target, link)?;
+		}
+	}
+	#[cfg(not(target_os = "windows"))]
+	std::os::unix::fs::symlink(target, link)?;
+	Ok(())
+}
+
+/// Creates `link` pointing to `target`. A relative `target` is relative to
+/// the link's folder, as with `ln -s`.
+pub fn symlink(session: &OsSession, target: &str, link: &str) -> Result<()> {
+	match session {
+		OsSession::Local(_) => create_symlink_local(target, link)
+			.with_context(|| format!("Failed to create symlink {}", link)),
+		OsSession::Wsl(wsl_session) => {
+			#[cfg(target_os = "windows")]
+			{
+				let distribution = &wsl_session.distribution;
+				// Relative targets are kept as written so the link moves with its folder
+				let target = if target.contains(':') || target.starts_with("\\\\") {
+					wsl::to_wsl_path(distribution, target)
+				} else {
+					target.replace('\\', "/")
+				};
+				let output = WslCommand::new(distribution, "ln")
+					.args(["-s", "--", &target, &wsl::to_wsl_path(distribution, link)])
+					.output()?;
+				if !output.status.success() {
+					return Err(anyhow!(
+						"ln failed: {}",
+						String::from_utf8_lossy(&output.stderr).trim()
+					));
+				}
+				Ok(())
+			}
+			#[cfg(not(target_os = "windows"))]
+			{
+				let _ = wsl_session;
+				Err(anyhow!("WSL is only available on Windows"))
+			}
+		}
+	}
+}
+
+#[tauri::command]
+pub async fn create_symlink(
+	target: String,
+	link: String,
+	os_session: OsSession,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || symlink(&os_session, &target, &link))
+		.await
+		.map_err(|e| e.to_string())?
+		.map_err(|e| e.to_string())
+}
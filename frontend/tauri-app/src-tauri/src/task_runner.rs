@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::diagnostics;
+use crate::env_files::{self, EnvTarget};
+use crate::jobs::{JobKind, JobManager, JOB_CANCELLED};
+use crate::notifications::{self, Notice, NotificationCategory};
+use crate::os::OsSession;
+use crate::terminal::{OutputCapture, TerminalManager};
+use crate::toolchains;
+use crate::trust;
+
+/// How often running tasks are checked for exit.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskSource {
+	Npm,
+	Cargo,
+	Make,
+	Just,
+}
+
+/// Something runnable in a project: a package script, Cargo command,
+/// Makefile goal or justfile recipe.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+	/// Stable across detections, e.g. `npm:dev` or `cargo:run:server`.
+	pub id: String,
+	pub label: String,
+	pub source: TaskSource,
+	/// Shell command line run from the project root.
+	pub command: String,
+}
+
+/// A task started in a terminal connection, whose output arrives as that
+/// connection's `terminal-data-{id}` events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRun {
+	pub task_id: String,
+	pub connection_id: String,
+	pub command: String,
+}
+
+/// Payload of the `task-exited` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskExit {
+	pub task_id: String,
+	pub connection_id: String,
+	/// `None` if the task was stopped before it exited on its own.
+	pub exit_code: Option<u32>,
+	pub success: bool,
+}
+
+#[derive(Deserialize)]
+struct PackageJson {
+	#[serde(default)]
+	scripts: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct CargoManifest {
+	package: Option<CargoPackage>,
+	#[serde(default)]
+	bin: Vec<CargoTarget>,
+	#[serde(default)]
+	example: Vec<CargoTarget>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+	name: String,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+	name: String,
+}
+
+/// The package manager whose lockfile is present, npm by default.
+fn package_manager(root: &Path) -> &'static str {
+	if root.join("pnpm-lock.yaml").exists() {
+		"pnpm"
+	} else if root.join("yarn.lock").exists() {
+		"yarn"
+	} else if root.join("bun.lockb").exists() || root.join("bun.lock").exists() {
+		"bun"
+	} else {
+		"npm"
+	}
+}
+
+fn npm_tasks(root: &Path) -> Vec<Task> {
+	let Ok(content) = fs::read_to_string(root.join("package.json")) else {
+		return Vec::new();
+	};
+	let Ok(package) = serde_json::from_str::<PackageJson>(&content) else {
+		return Vec::new();
+	};
+	let manager = package_manager(root);
+
+	let mut names: Vec<_> = package.scripts.into_keys().collect();
+	names.sort();
+	names
+		.into_iter()
+		.map(|name| Task {
+			id: format!("npm:{}", name),
+			label: name.clone(),
+			source: TaskSource::Npm,
+			command: format!("{} run {}", manager, name),
+		})
+		.collect()
+}
+
+fn cargo_tasks(root: &Path) -> Vec<Task> {
+	let Ok(content) = fs::read_to_string(root.join("Cargo.toml")) else {
+		return Vec::new();
+	};
+	let Ok(manifest) = toml::from_str::<CargoManifest>(&content) else {
+		return Vec::new();
+	};
+
+	let task = |id: &str, label: String, command: String| Task {
+		id: format!("cargo:{}", id),
+		label,
+		source: TaskSource::Cargo,
+		command,
+	};
+	let mut tasks: Vec<_> = ["build", "check", "test", "clippy"]
+		.into_iter()
+		.map(|c| task(c, format!("cargo {}", c), format!("cargo {}", c)))
+		.collect();
+
+	// The package's implicit binary, unless targets are listed explicitly
+	let mut binaries: Vec<_> = manifest.bin.into_iter().map(|b| b.name).collect();
+	if binaries.is_empty() && root.join("src").join("main.rs").exists() {
+		binaries.extend(manifest.package.map(|p| p.name));
+	}
+	for name in binaries {
+		tasks.push(task(
+			&format!("run:{}", name),
+			format!("cargo run --bin {}", name),
+			format!("cargo run --bin {}", name),
+		));
+	}
+
+	// Examples listed in the manifest plus those discovered in examples/
+	let mut examples: Vec<_> = manifest.example.into_iter().map(|e| e.name).collect();
+	if let Ok(entries) = fs::read_dir(root.join("examples")) {
+		for entry in entries.filter_map(|e| e.ok()) {
+			let path = entry.path();
+			if path.extension().and_then(|e| e.to_str()) == Some("rs") {
+				if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+					examples.push(stem.to_string());
+				}
+			}
+		}
+	}
+	examples.sort();
+	examples.dedup();
+	for name in examples {
+		tasks.push(task(
+			&format!("example:{}", name),
+			format!("cargo run --example {}", name),
+			format!("cargo run --example {}", name),
+		));
+	}
+
+	tasks
+}
+
+/// Explicit goals of a Makefile: `name:` rules at the start of a line,
+/// skipping pattern rules, special targets and variable assignments.
+fn make_tasks(root: &Path) -> Vec<Task> {
+	let Some(content) = ["GNUmakefile", "makefile", "Makefile"]
+		.iter()
+		.find_map(|name| fs::read_to_string(root.join(name)).ok())
+	else {
+		return Vec::new();
+	};
+
+	let mut goals = Vec::new();
+	for line in content.lines() {
+		let Some((targets, rest)) = line.split_once(':') else {
+			continue;
+		};
+		if line.starts_with(['\t', ' ', '#', '.'])
+			|| targets.contains('=')
+			|| rest.starts_with('=')
+		{
+			continue;
+		}
+		for target in targets.split_whitespace() {
+			let valid = target
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'));
+			if valid && !goals.contains(&target) {
+				goals.push(target);
+			}
+		}
+	}
+
+	goals
+		.into_iter()
+		.map(|goal| Task {
+			id: format!("make:{}", goal),
+			label: goal.to_string(),
+			source: TaskSource::Make,
+			command: format!("make {}", goal),
+		})
+		.collect()
+}
+
+/// Recipes of a justfile: unindented `name args...:` lines, skipping
+/// settings, aliases, assignments and private recipes.
+fn just_tasks(root: &Path) -> Vec<Task> {
+	let Some(content) = ["justfile", "Justfile", ".justfile"]
+		.iter()
+		.find_map(|name| fs::read_to_string(root.join(name)).ok())
+	else {
+		return Vec::new();
+	};
+
+	let mut recipes = Vec::new();
+	for line in content.lines() {
+		if line.starts_with([' ', '\t', '#', '[']) || line.contains(":=") {
+			continue;
+		}
+		let Some((header, _)) = line.split_once(':') else {
+			continue;
+		};
+		let header = header.trim_start_matches('@');
+		let Some(name) = header.split_whitespace().next() else {
+			continue;
+		};
+		let keyword = matches!(
+			name,
+			"set" | "alias" | "export" | "import" | "mod" | "unexport"
+		);
+		let valid = name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+		if valid && !keyword && !name.starts_with('_') && !recipes.contains(&name) {
+			recipes.push(name);
+		}
+	}
+
+	recipes
+		.into_iter()
+		.map(|recipe| Task {
+			id: format!("just:{}", recipe),
+			label: recipe.to_string(),
+			source: TaskSource::Just,
+			command: format!("just {}", recipe),
+		})
+		.collect()
+}
+
+/// Every task defined at the root of the session's working directory.
+pub fn detect_tasks(session: &OsSession) -> Vec<Task> {
+	let root = session.host_path();
+	let mut tasks = npm_tasks(&root);
+	tasks.extend(cargo_tasks(&root));
+	tasks.extend(make_tasks(&root));
+	tasks.extend(just_tasks(&root));
+	tasks
+}
+
+/// Runs project tasks in terminal connections and reports when they exit.
+pub struct TaskRunner {
+	terminal_manager: Arc<TerminalManager>,
+	/// Task id of every running connection.
+	running: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl TaskRunner {
+	pub fn new(terminal_manager: Arc<TerminalManager>) -> Self {
+		Self {
+			terminal_manager,
+			running: Arc::new(Mutex::new(HashMap::new())),
+		}
+	}
+
+	/// Starts the task with `task_id` in a new terminal connection. A
+	/// `task-exited` event follows once it finishes or is stopped.
+	pub fn run_task(
+		&self,
+		session: &OsSession,
+		task_id: &str,
+		app_handle: AppHandle,
+	) -> Result<TaskRun> {
+		let task = detect_tasks(session)
+			.into_iter()
+			.find(|task| task.id == task_id)
+			.ok_or_else(|| anyhow!("Task not found: {}", task_id))?;
+
+		let mut cmd = session.build_task_command(&task.command)?;
+		env_files::apply(&app_handle, session, EnvTarget::Task, &mut cmd);
+		toolchains::apply(&app_handle, session, &mut cmd);
+		let output = OutputCapture::default();
+		let connection_id = self.terminal_manager.create_command_connection(
+			cmd,
+			app_handle.clone(),
+			Some(output.clone()),
+		)?;
+		self.running
+			.lock()
+			.unwrap()
+			.insert(connection_id.clone(), task.id.clone());
+
+		// Shown in the jobs list too, where cancelling it stops the task
+		let job = app_handle
+			.state::<Arc<JobManager>>()
+			.start(JobKind::Task, task.label.clone());
+		let (closer, closed_id) = (self.terminal_manager.clone(), connection_id.clone());
+		job.token().on_cancel(move || {
+			let _ = closer.close_connection(&closed_id);
+		});
+
+		let terminal_manager = self.terminal_manager.clone();
+		let running = self.running.clone();
+		let (label, command) = (task.label.clone(), task.command.clone());
+		let session = session.clone();
+		let exit = TaskExit {
+			task_id: task.id.clone(),
+			connection_id: connection_id.clone(),
+			exit_code: None,
+			success: false,
+		};
+		thread::spawn(move || {
+			let status = loop {
+				match terminal_manager.try_wait(&exit.connection_id) {
+					Ok(None) => thread::sleep(EXIT_POLL_INTERVAL),
+					Ok(Some(status)) => break Some(status),
+					// The connection was closed, killing the task
+					Err(_) => break None,
+				}
+			};
+			running.lock().unwrap().remove(&exit.connection_id);
+			// Builds show their errors in the Problems feed too
+			if status.is_some() {
+				let output = String::from_utf8_lossy(&output.lock().unwrap()).to_string();
+				diagnostics::record_task_output(&app_handle, &session, &command, &output);
+			}
+			if status.is_none() {
+				// Stopped with `stop_task` rather than through the job
+				job.token().cancel();
+			}
+			job.finish(match &status {
+				Some(status) if status.success() => Ok(None),
+				Some(status) => Err(format!("Exited with code {}", status.exit_code())),
+				None => Err(JOB_CANCELLED.to_string()),
+			});
+			// Stopped tasks were ended by the user, who needs no reminder
+			if let Some(status) = &status {
+				let notice = Notice {
+					category: NotificationCategory::Task,
+					title: if status.success() {
+						format!("{} finished", label)
+					} else {
+						format!("{} failed", label)
+					},
+					body: format!(
+						"`{}` exited with code {}",
+						command,
+						status.exit_code()
+					),
+					urgent: !status.success(),
+				};
+				if let Err(e) = notifications::notify(&app_handle, &notice) {
+					log::warn!("Failed to notify about task exit: {}", e);
+				}
+			}
+			let _ = app_handle.emit(
+				"task-exited",
+				TaskExit {
+					exit_code: status.as_ref().map(|s| s.exit_code()),
+					success: status.is_some_and(|s| s.success()),
+					..exit
+				},
+			);
+		});
+
+		Ok(TaskRun {
+			task_id: task.id,
+			connection_id,
+			command: task.command,
+		})
+	}
+
+	/// Kills a running task by closing its terminal connection.
+	pub fn stop_task(&self, connection_id: &str) -> Result<()> {
+		if !self.running.lock().unwrap().contains_key(connection_id) {
+			return Err(anyhow!("No running task for connection {}", connection_id));
+		}
+		self.terminal_manager.close_connection(connection_id)
+	}
+
+	/// Running tasks, as connection id to task id.
+	pub fn running_tasks(&self) -> HashMap<String, String> {
+		self.running.lock().unwrap().clone()
+	}
+}
+
+#[tauri::command]
+pub async fn list_tasks(os_session: OsSession) -> Result<Vec<Task>, String> {
+	tauri::async_runtime::spawn_blocking(move || detect_tasks(&os_session))
+		.await
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn run_task(
+	os_session: OsSession,
+	task_id: String,
+	task_runner: State<'_, Arc<TaskRunner>>,
+	app_handle: AppHandle,
+) -> Result<TaskRun, String> {
+	trust::ensure_trusted(&app_handle, &os_session, os_session.get_working_directory())?;
+	task_runner
+		.run_task(&os_session, &task_id, app_handle)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn stop_task(
+	connection_id: String,
+	task_runner: State<'_, Arc<TaskRunner>>,
+) -> Result<(), String> {
+	task_runner
+		.stop_task(&connection_id)
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_running_tasks(
+	task_runner: State<'_, Arc<TaskRunner>>,
+) -> Result<HashMap<String, String>, String> {
+	Ok(task_runner.running_tasks())
+}
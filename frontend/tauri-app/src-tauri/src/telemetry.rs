@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+use crate::backend_client;
+
+/// Store remembering whether the user opted in.
+const TELEMETRY_STORE: &str = "telemetry.json";
+/// One event per line, in the app data directory.
+const QUEUE_FILE: &str = "telemetry-queue.jsonl";
+/// Oldest events are dropped past this.
+const MAX_QUEUED: usize = 5000;
+/// Events per upload; the backend accepts up to 500.
+const BATCH_SIZE: usize = 500;
+const UPLOAD_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const MAX_NAME_LEN: usize = 64;
+const MAX_PROPERTIES: usize = 32;
+/// Longer strings are dropped rather than cut, as are strings that look
+/// like paths, URLs or email addresses.
+const MAX_VALUE_LEN: usize = 64;
+
+/// One usage event, exactly as it is uploaded. There is no account or
+/// installation id; `session_id` is new every time the app starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryEvent {
+	pub event_id: String,
+	pub session_id: String,
+	/// Like `palette.opened`.
+	pub name: String,
+	/// RFC 3339, UTC.
+	pub occurred_at: String,
+	/// Flat and scalar: booleans, numbers and short strings.
+	pub properties: BTreeMap<String, Value>,
+}
+
+/// The body of `POST /api/telemetry`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetryBatch {
+	pub app_version: String,
+	pub os: String,
+	pub arch: String,
+	pub events: Vec<TelemetryEvent>,
+}
+
+/// What `show_my_telemetry` returns.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryPreview {
+	/// `None` until the user has been asked. Nothing is recorded after
+	/// opting out, nothing is sent before opting in.
+	pub opt_in: Option<bool>,
+	/// Where the batches go.
+	pub endpoint: String,
+	/// The request bodies the next upload would send, unchanged.
+	pub batches: Vec<TelemetryBatch>,
+	pub last_upload_at: Option<String>,
+}
+
+/// Lowercase letters, digits, `_` and `.`, which is also what the backend
+/// accepts.
+fn valid_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name.len() <= MAX_NAME_LEN
+		&& name.chars().all(|c| {
+			c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '.')
+		})
+}
+
+/// Keeps the properties that can't identify the user or their code:
+/// scalars, and strings that are short and don't look like paths, URLs or
+/// email addresses.
+fn sanitize(properties: BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+	properties
+		.into_iter()
+		.filter(|(key, value)| {
+			valid_name(key)
+				&& match value {
+					Value::Null | Value::Bool(_) | Value::Number(_) => true,
+					Value::String(s) => {
+						s.len() <= MAX_VALUE_LEN && !s.contains(['/', '\\', '@', ':'])
+					}
+					_ => false,
+				}
+		})
+		.take(MAX_PROPERTIES)
+		.collect()
+}
+
+/// Anonymous usage events, queued on disk and uploaded in batches only
+/// once the user opts in. `show_my_telemetry` shows the exact payload.
+pub struct Telemetry {
+	app_handle: AppHandle,
+	session_id: String,
+	/// Serializes access to the queue file and holds how many events it
+	/// has, once known.
+	queue: Mutex<Option<usize>>,
+}
+
+impl Telemetry {
+	pub fn new(app_handle: AppHandle) -> Arc<Self> {
+		let telemetry = Arc::new(Self {
+			app_handle,
+			session_id: Uuid::new_v4().to_string(),
+			queue: Mutex::new(None),
+		});
+		telemetry.record("app.started", BTreeMap::new());
+
+		let weak = Arc::downgrade(&telemetry);
+		tauri::async_runtime::spawn(async move {
+			loop {
+				let Some(telemetry) = weak.upgrade() else {
+					break;
+				};
+				if telemetry.opt_in() == Some(true) {
+					if let Err(e) = telemetry.upload().await {
+						log::warn!("Failed to upload telemetry: {}", e);
+					}
+				}
+				drop(telemetry);
+				tokio::time::sleep(UPLOAD_INTERVAL).await;
+			}
+		});
+		telemetry
+	}
+
+	fn queue_path(&self) -> Result<PathBuf> {
+		Ok(self.app_handle.path().app_data_dir()?.join(QUEUE_FILE))
+	}
+
+	/// `None` until the user has been asked.
+	pub fn opt_in(&self) -> Option<bool> {
+		self.app_handle
+			.store(TELEMETRY_STORE)
+			.ok()
+			.and_then(|store| store.get("optIn"))
+			.and_then(|v| v.as_bool())
+	}
+
+	/// Opting out also deletes what was queued.
+	pub fn set_opt_in(&self, opt_in: bool) -> Result<()> {
+		let store = self.app_handle.store(TELEMETRY_STORE)?;
+		store.set("optIn", opt_in);
+		store.save()?;
+		if !opt_in {
+			self.clear()?;
+		}
+		Ok(())
+	}
+
+	/// Queues an event, unless the user opted out. Invalid names are
+	/// ignored and properties that could identify someone are dropped.
+	pub fn record(&self, name: &str, properties: BTreeMap<String, Value>) {
+		if self.opt_in() == Some(false) {
+			return;
+		}
+		if !valid_name(name) {
+			log::warn!("Ignoring telemetry event with invalid name {}", name);
+			return;
+		}
+		let event = TelemetryEvent {
+			event_id: Uuid::new_v4().to_string(),
+			session_id: self.session_id.clone(),
+			name: name.to_string(),
+			occurred_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+			properties: sanitize(properties),
+		};
+		if let Err(e) = self.append(&event) {
+			log::warn!("Failed to queue telemetry: {}", e);
+		}
+	}
+
+	fn append(&self, event: &TelemetryEvent) -> Result<()> {
+		let mut queued = self.queue.lock().unwrap();
+		let path = self.queue_path()?;
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let count = match *queued {
+			Some(count) => count,
+			None => self.read_locked()?.len(),
+		};
+		let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+		writeln!(file, "{}", serde_json::to_string(event)?)?;
+		drop(file);
+
+		*queued = Some(count + 1);
+		if count + 1 > MAX_QUEUED {
+			let events = self.read_locked()?;
+			let keep = &events[events.len().saturating_sub(MAX_QUEUED)..];
+			self.write_locked(keep)?;
+			*queued = Some(keep.len());
+		}
+		Ok(())
+	}
+
+	/// Queued events, oldest first. Lines that don't parse are skipped.
+	fn read_locked(&self) -> Result<Vec<TelemetryEvent>> {
+		let content = match fs::read_to_string(self.queue_path()?) {
+			Ok(content) => content,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(e.into()),
+		};
+		Ok(content
+			.lines()
+			.filter_map(|line| serde_json::from_str(line).ok())
+			.collect())
+	}
+
+	fn write_locked(&self, events: &[TelemetryEvent]) -> Result<()> {
+		let mut content = String::new();
+		for event in events {
+			content.push_str(&serde_json::to_string(event)?);
+			content.push('\n');
+		}
+		fs::write(self.queue_path()?, content)?;
+		Ok(())
+	}
+
+	/// Drops `sent` from the queue, keeping events queued meanwhile.
+	fn remove_sent(&self, sent: &[TelemetryEvent]) -> Result<()> {
+		let sent: HashSet<_> = sent.iter().map(|event| event.event_id.as_str()).collect();
+		let mut queued = self.queue.lock().unwrap();
+		let remaining: Vec<_> = self
+			.read_locked()?
+			.into_iter()
+			.filter(|event| !sent.contains(event.event_id.as_str()))
+			.collect();
+		self.write_locked(&remaining)?;
+		*queued = Some(remaining.len());
+		Ok(())
+	}
+
+	pub fn clear(&self) -> Result<()> {
+		let mut queued = self.queue.lock().unwrap();
+		*queued = None;
+		match fs::remove_file(self.queue_path()?) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		}
+	}
+
+	fn batches(&self, events: Vec<TelemetryEvent>) -> Vec<TelemetryBatch> {
+		events
+			.chunks(BATCH_SIZE)
+			.map(|events| TelemetryBatch {
+				app_version: self.app_handle.package_info().version.to_string(),
+				os: std::env::consts::OS.to_string(),
+				arch: std::env::consts::ARCH.to_string(),
+				events: events.to_vec(),
+			})
+			.collect()
+	}
+
+	fn endpoint(&self) -> String {
+		format!(
+			"{}/api/telemetry",
+			backend_client::backend_url(&self.app_handle)
+		)
+	}
+
+	pub fn preview(&self) -> Result<TelemetryPreview> {
+		let events = {
+			let _guard = self.queue.lock().unwrap();
+			self.read_locked()?
+		};
+		Ok(TelemetryPreview {
+			opt_in: self.opt_in(),
+			endpoint: self.endpoint(),
+			batches: self.batches(events),
+			last_upload_at: self
+				.app_handle
+				.store(TELEMETRY_STORE)?
+				.get("lastUploadAt")
+				.and_then(|v| v.as_str().map(str::to_string)),
+		})
+	}
+
+	/// Sends the queue in batches, without credentials, removing each batch
+	/// once accepted. Returns how many events were sent.
+	pub async fn upload(&self) -> Result<usize> {
+		if self.opt_in() != Some(true) {
+			return Err(anyhow!("The user has not opted in to telemetry"));
+		}
+		let events = {
+			let _guard = self.queue.lock().unwrap();
+			self.read_locked()?
+		};
+		let client = reqwest::Client::new();
+		let mut sent = 0;
+		for batch in self.batches(events) {
+			client
+				.post(self.endpoint())
+				.json(&batch)
+				.send()
+				.await?
+				.error_for_status()?;
+
+			self.remove_sent(&batch.events)?;
+			sent += batch.events.len();
+		}
+		if sent > 0 {
+			let store = self.app_handle.store(TELEMETRY_STORE)?;
+			store.set(
+				"lastUploadAt",
+				Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+			);
+			store.save()?;
+		}
+		Ok(sent)
+	}
+}
+
+/// Exactly what would be sent: the queued events as the upload batches,
+/// and where they go.
+#[tauri::command]
+pub async fn show_my_telemetry(
+	telemetry: State<'_, Arc<Telemetry>>,
+) -> Result<TelemetryPreview, String> {
+	telemetry.preview().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn record_telemetry_event(
+	name: String,
+	properties: Option<BTreeMap<String, Value>>,
+	telemetry: State<'_, Arc<Telemetry>>,
+) -> Result<(), String> {
+	telemetry.record(&name, properties.unwrap_or_default());
+	Ok(())
+}
+
+#[tauri::command]
+pub async fn get_telemetry_opt_in(
+	telemetry: State<'_, Arc<Telemetry>>,
+) -> Result<Option<bool>, String> {
+	Ok(telemetry.opt_in())
+}
+
+#[tauri::command]
+pub async fn set_telemetry_opt_in(
+	opt_in: bool,
+	telemetry: State<'_, Arc<Telemetry>>,
+) -> Result<(), String> {
+	telemetry.set_opt_in(opt_in).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_telemetry(telemetry: State<'_, Arc<Telemetry>>) -> Result<(), String> {
+	telemetry.clear().map_err(|e| e.to_string())
+}
+
+/// Sends queued events now; requires opting in.
+#[tauri::command]
+pub async fn upload_telemetry(
+	telemetry: State<'_, Arc<Telemetry>>,
+) -> Result<usize, String> {
+	telemetry.upload().await.map_err(|e| e.to_string())
+}
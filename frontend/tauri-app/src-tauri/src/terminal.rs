@@ -4,22 +4,32 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 
 use anyhow::{anyhow, Result};
-use portable_pty::{Child, PtyPair, PtySize};
+use portable_pty::{Child, CommandBuilder, ExitStatus, PtyPair, PtySize};
 use tauri::AppHandle;
 use tauri::Emitter;
 use uuid::Uuid;
 
+use crate::env_files::{self, EnvTarget};
 use crate::os::OsSession;
+use crate::toolchains;
+
+/// Output of a connection kept for the backend, e.g. to read build
+/// problems from a task once it exits. Only the last `MAX_CAPTURE_BYTES`
+/// are kept.
+pub type OutputCapture = Arc<Mutex<Vec<u8>>>;
+
+const MAX_CAPTURE_BYTES: usize = 4 * 1024 * 1024;
 
 pub struct TerminalConnection {
 	pub id: String,
 	pub pty_pair: PtyPair,
 	pub child: Box<dyn Child + Send + Sync>,
 	pub app_handle: AppHandle,
+	pub capture: Option<OutputCapture>,
 }
 
 impl TerminalConnection {
-	pub fn new(id: String, os_session: OsSession, app_handle: AppHandle) -> Result<Self> {
+	pub fn spawn(id: String, cmd: CommandBuilder, app_handle: AppHandle) -> Result<Self> {
 		let pty_system = portable_pty::native_pty_system();
 
 		let pty_pair = pty_system.openpty(PtySize {
@@ -29,7 +39,6 @@ impl TerminalConnection {
 			pixel_height: 0,
 		})?;
 
-		let cmd = os_session.build_command(true)?;
 		let child = pty_pair.slave.spawn_command(cmd)?;
 
 		Ok(Self {
@@ -37,6 +46,7 @@ impl TerminalConnection {
 			pty_pair,
 			child,
 			app_handle,
+			capture: None,
 		})
 	}
 
@@ -52,6 +62,7 @@ impl TerminalConnection {
 		let mut reader = self.pty_pair.master.try_clone_reader()?;
 		let app_handle = self.app_handle.clone();
 		let connection_id = self.id.clone();
+		let capture = self.capture.clone();
 
 		// Spawn thread to read from PTY and send to frontend
 		thread::spawn(move || {
@@ -60,17 +71,23 @@ impl TerminalConnection {
 				match reader.read(&mut buffer) {
 					Ok(0) => break, // EOF
 					Ok(n) => {
+						if let Some(capture) = &capture {
+							let mut captured = capture.lock().unwrap();
+							captured.extend_from_slice(&buffer[..n]);
+							let excess = captured.len().saturating_sub(MAX_CAPTURE_BYTES);
+							captured.drain(..excess);
+						}
 						let data = String::from_utf8_lossy(&buffer[..n]).to_string();
-						println!("Backend received from PTY: {:?}", data);
+						log::trace!("Backend received from PTY: {:?}", data);
 						if let Err(e) = app_handle
 							.emit(&format!("terminal-data-{}", connection_id), &data)
 						{
-							eprintln!("Failed to emit terminal data: {}", e);
+							log::error!("Failed to emit terminal data: {}", e);
 							break;
 						}
 					}
 					Err(e) => {
-						eprintln!("Error reading from PTY: {}", e);
+						log::error!("Error reading from PTY: {}", e);
 						break;
 					}
 				}
@@ -114,6 +131,20 @@ impl TerminalManager {
 		&self,
 		session: OsSession,
 		app_handle: AppHandle,
+	) -> Result<String> {
+		let mut cmd = session.build_command(true)?;
+		env_files::apply(&app_handle, &session, EnvTarget::Terminal, &mut cmd);
+		toolchains::apply(&app_handle, &session, &mut cmd);
+		self.create_command_connection(cmd, app_handle, None)
+	}
+
+	/// Like `create_connection`, but runs `cmd` rather than a shell. Its
+	/// output is also appended to `capture` when given.
+	pub fn create_command_connection(
+		&self,
+		cmd: CommandBuilder,
+		app_handle: AppHandle,
+		capture: Option<OutputCapture>,
 	) -> Result<String> {
 		// Check connection limit first
 		{
@@ -124,8 +155,9 @@ impl TerminalManager {
 		}
 
 		let connection_id = Uuid::new_v4().to_string();
-		let connection =
-			TerminalConnection::new(connection_id.clone(), session, app_handle)?;
+		let mut connection =
+			TerminalConnection::spawn(connection_id.clone(), cmd, app_handle)?;
+		connection.capture = capture;
 
 		// Get the writer before starting the IO loop
 		let writer = connection.pty_pair.master.take_writer()?;
@@ -142,7 +174,7 @@ impl TerminalManager {
 	}
 
 	pub fn send_data(&self, connection_id: &str, data: &str) -> Result<()> {
-		println!("Backend sending data: {:?}", data);
+		log::trace!("Backend sending data: {:?}", data);
 		let mut writers = self.writers.lock().unwrap();
 		if let Some(writer) = writers.get_mut(connection_id) {
 			writer.write_all(data.as_bytes())?;
@@ -181,7 +213,7 @@ impl TerminalManager {
 		if let Some(mut connection) = connections.remove(connection_id) {
 			// Forcefully kill the child process
 			if let Err(e) = connection.child.kill() {
-				eprintln!("Failed to kill child process: {}", e);
+				log::warn!("Failed to kill child process: {}", e);
 			}
 
 			// Wait for the child to actually terminate
@@ -194,6 +226,26 @@ impl TerminalManager {
 		Ok(())
 	}
 
+	/// Exit status of the connection's process, `None` while it is running.
+	pub fn try_wait(&self, connection_id: &str) -> Result<Option<ExitStatus>> {
+		let mut connections = self.connections.lock().unwrap();
+		if let Some(connection) = connections.get_mut(connection_id) {
+			Ok(connection.child.try_wait()?)
+		} else {
+			Err(anyhow!("Connection not found: {}", connection_id))
+		}
+	}
+
+	/// Process ids of every connection's shell or command.
+	pub fn process_ids(&self) -> Vec<u32> {
+		self.connections
+			.lock()
+			.unwrap()
+			.values()
+			.filter_map(|connection| connection.child.process_id())
+			.collect()
+	}
+
 	pub fn cleanup_dead_connections(&self) -> Result<()> {
 		let mut connections = self.connections.lock().unwrap();
 		let mut writers = self.writers.lock().unwrap();
@@ -208,7 +260,7 @@ impl TerminalManager {
 
 		// Remove dead connections
 		for id in dead_connections {
-			println!("Cleaning up dead terminal connection: {}", id);
+			log::debug!("Cleaning up dead terminal connection: {}", id);
 
 			// Cleanup writer
 			if let Some(mut writer) = writers.remove(&id) {
@@ -0,0 +1,402 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::ai_history::{self, HistoryMessage, NewAiExchange};
+use crate::backend_client;
+use crate::custom_terminal::CustomTerminalManager;
+use crate::diff_summary::SummaryModel;
+
+const DEFAULT_LINE_COUNT: usize = 200;
+/// Lines kept after an error's first line, e.g. a rustc snippet or a trace.
+const MAX_CONTEXT_LINES: usize = 30;
+/// Errors sent to the model, most recent last.
+const MAX_EXPLAINED_ERRORS: usize = 5;
+
+const EXPLAIN_PROMPT: &str = "You are helping a developer understand an error printed in their terminal. \
+Explain in a few sentences what went wrong and what is the most likely fix. \
+Refer to files and lines when the output mentions them. Answer in Markdown without a heading.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+/// A `file:line` reference found in the output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceLocation {
+	/// As printed, usually relative to the terminal's working directory.
+	pub path: String,
+	/// One-based.
+	pub line: u32,
+	pub column: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalError {
+	pub severity: Severity,
+	pub message: String,
+	/// The first reference, which is where compilers point at the problem.
+	pub location: Option<SourceLocation>,
+	/// Further references, e.g. stack frames.
+	pub frames: Vec<SourceLocation>,
+	/// The lines the error was read from, first line included.
+	pub context: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalErrorReport {
+	pub lines: Vec<String>,
+	pub errors: Vec<TerminalError>,
+	/// Set when a model was given to explain the errors with.
+	pub explanation: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiMessage<'a> {
+	role: &'a str,
+	content: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct InferenceRequest<'a> {
+	provider: &'a str,
+	model: &'a str,
+	messages: Vec<ApiMessage<'a>>,
+	api_key: Option<&'a str>,
+	key_id: Option<&'a str>,
+	org_id: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InferenceResponse {
+	content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+	error: String,
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+	if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	text.parse().ok().filter(|&n| n > 0)
+}
+
+/// Whether `path` looks like a file rather than a URL, a time or a label.
+fn looks_like_path(path: &str) -> bool {
+	!path.is_empty()
+		&& !path.contains("://")
+		&& !path.starts_with("http")
+		&& !path.bytes().all(|b| b.is_ascii_digit())
+		&& (path.contains('/')
+			|| path.contains('\\')
+			|| path.rsplit_once('.').is_some_and(|(stem, ext)| {
+				!stem.is_empty() && ext.chars().all(char::is_alphanumeric)
+			}))
+}
+
+const OPENERS: [char; 6] = ['(', '[', '<', '\'', '"', '`'];
+const CLOSERS: [char; 10] = [')', ']', '>', '\'', '"', '`', ',', ';', '.', ':'];
+
+/// Parses `path:line`, `path:line:column` and `path(line,column)` out of a
+/// word of output, ignoring surrounding brackets and quotes. Returns the
+/// location and the byte range of the reference within `word`.
+fn parse_location(word: &str) -> Option<(Range<usize>, SourceLocation)> {
+	let opened = word.trim_start_matches(OPENERS);
+	let trimmed = opened.trim_end_matches(CLOSERS);
+	let start = word.len() - opened.len();
+	let mut range = start..start + trimmed.len();
+	let token = trimmed.strip_prefix("file://").unwrap_or(trimmed);
+	// `file:///C:/src/main.rs`
+	let token = match token.strip_prefix('/') {
+		Some(rest) if rest.get(1..2) == Some(":") => rest,
+		_ => token,
+	};
+
+	// TypeScript and MSBuild style
+	if let Some((path, rest)) = token.split_once('(') {
+		let mut numbers = rest.split(',');
+		if let Some(line) = numbers.next().and_then(parse_number) {
+			if looks_like_path(path) {
+				if word[range.end..].starts_with(')') {
+					range.end += 1;
+				}
+				let location = SourceLocation {
+					path: path.to_string(),
+					line,
+					column: numbers.next().and_then(parse_number),
+				};
+				return Some((range, location));
+			}
+		}
+	}
+
+	// Windows drive letters contain a colon, so split from the right
+	let mut parts = token.rsplitn(3, ':');
+	let last = parts.next()?;
+	let middle = parts.next()?;
+	let (path, line, column) = match (parse_number(middle), parts.next()) {
+		(Some(line), Some(path)) if parse_number(last).is_some() => {
+			(path, line, parse_number(last))
+		}
+		_ => {
+			let path = token.rsplit_once(':')?.0;
+			(path, parse_number(last)?, None)
+		}
+	};
+	let location = SourceLocation {
+		path: path.to_string(),
+		line,
+		column,
+	};
+	looks_like_path(path).then_some((range, location))
+}
+
+/// Python's `File "path", line 12, in name`. The range covers the path and
+/// the line number.
+fn parse_python_location(line: &str) -> Option<(Range<usize>, SourceLocation)> {
+	let start = line.find("File \"")? + "File \"".len();
+	let (path, rest) = line[start..].split_once('"')?;
+	let after_path = rest.trim_start_matches(',').trim_start();
+	let number = after_path.strip_prefix("line ")?;
+	let number = number.split(|c: char| !c.is_ascii_digit()).next()?;
+	let end = line.len() - after_path.len() + "line ".len() + number.len();
+	let location = SourceLocation {
+		path: path.to_string(),
+		line: parse_number(number)?,
+		column: None,
+	};
+	Some((start..end, location))
+}
+
+/// Every `file:line` reference on a line of output, in order, with the byte
+/// range it covers. Handles relative, POSIX, Windows (`C:\src\main.rs:3`,
+/// `\\wsl$\Ubuntu\...`) and WSL (`/mnt/c/...`) paths.
+pub fn find_locations(line: &str) -> Vec<(Range<usize>, SourceLocation)> {
+	if let Some(location) = parse_python_location(line) {
+		return vec![location];
+	}
+	let mut locations = Vec::new();
+	let mut offset = 0;
+	for word in line.split_inclusive(char::is_whitespace) {
+		if let Some((range, location)) = parse_location(word.trim_end()) {
+			locations.push((offset + range.start..offset + range.end, location));
+		}
+		offset += word.len();
+	}
+	locations
+}
+
+/// Every location mentioned on a line, in order.
+fn locations_in(line: &str) -> Vec<SourceLocation> {
+	find_locations(line)
+		.into_iter()
+		.map(|(_, location)| location)
+		.collect()
+}
+
+/// Whether a line starts a new error, and how severe it is.
+fn severity_of(line: &str) -> Option<Severity> {
+	let trimmed = line.trim_start();
+	let lower = trimmed.to_lowercase();
+	let error_starts = [
+		"error:",
+		"error[",
+		"error ts",
+		"fatal error",
+		"fatal:",
+		"traceback (most recent call last)",
+		"panic:",
+		"exception in thread",
+		"uncaught ",
+		"failed:",
+	];
+	if error_starts.iter().any(|start| lower.starts_with(start))
+		|| lower.contains("panicked at")
+		|| lower.contains(": error:")
+		|| lower.contains("): error ")
+		|| lower.contains(" error ts")
+		|| lower.starts_with("thread '") && lower.contains("panicked")
+	{
+		return Some(Severity::Error);
+	}
+	// `TypeError: x is undefined`, `java.lang.NullPointerException: ...`
+	if let Some((kind, _)) = trimmed.split_once(": ") {
+		if !kind.contains(' ') && (kind.ends_with("Error") || kind.ends_with("Exception"))
+		{
+			return Some(Severity::Error);
+		}
+	}
+	if lower.starts_with("warning:")
+		|| lower.starts_with("warning[")
+		|| lower.contains(": warning:")
+	{
+		return Some(Severity::Warning);
+	}
+	None
+}
+
+/// Groups output into errors: each starts at a line that reads like an error
+/// and takes the indented or location-bearing lines that follow it.
+pub fn extract_errors(lines: &[String]) -> Vec<TerminalError> {
+	let mut errors: Vec<TerminalError> = Vec::new();
+	let mut current: Option<TerminalError> = None;
+	for line in lines {
+		if let Some(severity) = severity_of(line) {
+			// A traceback's final `ValueError: ...` belongs to the traceback
+			if let Some(error) = current.as_mut().filter(|error| {
+				error.message.to_lowercase().starts_with("traceback")
+					&& !line.starts_with(char::is_whitespace)
+			}) {
+				error.message = line.trim().to_string();
+				error.context.push(line.clone());
+				errors.extend(current.take());
+				continue;
+			}
+			errors.extend(current.take());
+			let mut locations = locations_in(line);
+			let location = (!locations.is_empty()).then(|| locations.remove(0));
+			current = Some(TerminalError {
+				severity,
+				message: line.trim().to_string(),
+				location,
+				frames: locations,
+				context: vec![line.clone()],
+			});
+			continue;
+		}
+
+		let Some(error) = current.as_mut() else {
+			continue;
+		};
+		let locations = locations_in(line);
+		let continues = line.starts_with(char::is_whitespace) || !locations.is_empty();
+		if !continues || error.context.len() > MAX_CONTEXT_LINES {
+			errors.extend(current.take());
+			continue;
+		}
+		error.context.push(line.clone());
+		for location in locations {
+			if error.location.is_none() {
+				error.location = Some(location);
+			} else if error.location.as_ref() != Some(&location)
+				&& !error.frames.contains(&location)
+			{
+				error.frames.push(location);
+			}
+		}
+	}
+	errors.extend(current);
+	errors
+}
+
+/// Asks the model what the errors mean, through the backend's inference
+/// endpoint.
+async fn explain(
+	app_handle: &AppHandle,
+	errors: &[TerminalError],
+	lines: &[String],
+	model: &SummaryModel,
+) -> Result<String> {
+	let output = if errors.is_empty() {
+		lines.join("\n")
+	} else {
+		errors[errors.len().saturating_sub(MAX_EXPLAINED_ERRORS)..]
+			.iter()
+			.map(|error| error.context.join("\n"))
+			.collect::<Vec<_>>()
+			.join("\n\n")
+	};
+	let question = format!("Terminal output:\n```\n{}\n```", output);
+	let body = InferenceRequest {
+		provider: &model.provider,
+		model: &model.model,
+		messages: vec![
+			ApiMessage {
+				role: "system",
+				content: EXPLAIN_PROMPT,
+			},
+			ApiMessage {
+				role: "user",
+				content: &question,
+			},
+		],
+		api_key: model.api_key.as_deref(),
+		key_id: model.key_id.as_deref(),
+		org_id: model.org_id.as_deref(),
+	};
+	let response =
+		backend_client::optional_auth_request(app_handle, Method::POST, "/api/inference")
+			.json(&body)
+			.send()
+			.await?;
+
+	let status = response.status();
+	if !status.is_success() {
+		return Err(match response.json::<ErrorResponse>().await {
+			Ok(body) => anyhow!("Explanation failed ({}): {}", status, body.error),
+			Err(_) => anyhow!("Explanation failed ({})", status),
+		});
+	}
+	let answer = response.json::<InferenceResponse>().await?.content;
+	ai_history::record(
+		app_handle,
+		NewAiExchange {
+			source: "terminal_explanation".to_string(),
+			provider: model.provider.clone(),
+			model: model.model.clone(),
+			messages: body
+				.messages
+				.iter()
+				.map(|m| HistoryMessage {
+					role: m.role.to_string(),
+					content: m.content.to_string(),
+				})
+				.collect(),
+			response: answer.clone(),
+		},
+	);
+	Ok(answer)
+}
+
+/// Reads the last `line_count` lines of a terminal and extracts the errors
+/// in them. With `explain_with` set, the model also explains them.
+#[tauri::command]
+pub async fn explain_terminal_output(
+	app_handle: AppHandle,
+	id: String,
+	line_count: Option<usize>,
+	explain_with: Option<SummaryModel>,
+	manager: State<'_, Arc<CustomTerminalManager>>,
+) -> Result<TerminalErrorReport, String> {
+	let lines = manager
+		.last_lines(&id, line_count.unwrap_or(DEFAULT_LINE_COUNT))
+		.map_err(|e| e.to_string())?;
+	let errors = extract_errors(&lines);
+
+	let explanation = match explain_with {
+		Some(model) if !lines.is_empty() => Some(
+			explain(&app_handle, &errors, &lines, &model)
+				.await
+				.map_err(|e| e.to_string())?,
+		),
+		_ => None,
+	};
+	Ok(TerminalErrorReport {
+		lines,
+		errors,
+		explanation,
+	})
+}
@@ -0,0 +1,535 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+/// Store holding imported color schemes, by name.
+const SCHEMES_STORE: &str = "terminal_schemes.json";
+/// Minimum contrast ratio of ANSI colors against the background, below the
+/// 4.5 asked of body text since terminal colors are mostly accents.
+const DEFAULT_MIN_CONTRAST: f64 = 3.0;
+const ANSI_NAMES: [&str; 16] = [
+	"black",
+	"red",
+	"green",
+	"yellow",
+	"blue",
+	"purple",
+	"cyan",
+	"white",
+	"brightBlack",
+	"brightRed",
+	"brightGreen",
+	"brightYellow",
+	"brightBlue",
+	"brightPurple",
+	"brightCyan",
+	"brightWhite",
+];
+/// Hues the derived palette starts from, before tinting and contrast.
+const RED: Rgb = Rgb(0xcd, 0x3b, 0x3b);
+const YELLOW: Rgb = Rgb(0xd7, 0xa5, 0x3a);
+const BLUE: Rgb = Rgb(0x3b, 0x78, 0xd8);
+const MAGENTA: Rgb = Rgb(0xb0, 0x5e, 0xc9);
+const CYAN: Rgb = Rgb(0x2f, 0xa8, 0xb8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rgb(u8, u8, u8);
+
+impl Rgb {
+	fn parse(hex: &str) -> Option<Self> {
+		let hex = hex.trim().trim_start_matches('#');
+		if hex.len() != 6 || !hex.is_ascii() {
+			return None;
+		}
+		let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+		Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+	}
+
+	fn hex(self) -> String {
+		format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+	}
+
+	/// `self` moved `amount` of the way towards `other`.
+	fn mix(self, other: Rgb, amount: f64) -> Rgb {
+		let channel =
+			|a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * amount).round() as u8;
+		Rgb(
+			channel(self.0, other.0),
+			channel(self.1, other.1),
+			channel(self.2, other.2),
+		)
+	}
+
+	/// WCAG relative luminance.
+	fn luminance(self) -> f64 {
+		let linear = |c: u8| {
+			let c = c as f64 / 255.0;
+			if c <= 0.03928 {
+				c / 12.92
+			} else {
+				((c + 0.055) / 1.055).powf(2.4)
+			}
+		};
+		0.2126 * linear(self.0) + 0.7152 * linear(self.1) + 0.0722 * linear(self.2)
+	}
+
+	fn contrast(self, other: Rgb) -> f64 {
+		let (a, b) = (self.luminance(), other.luminance());
+		(a.max(b) + 0.05) / (a.min(b) + 0.05)
+	}
+
+	/// Moves towards `towards` until the contrast with `background` reaches
+	/// `min_contrast`, or as far as it goes.
+	fn with_contrast(self, background: Rgb, towards: Rgb, min_contrast: f64) -> Rgb {
+		let mut color = self;
+		let mut amount = 0.0;
+		while color.contrast(background) < min_contrast && amount < 1.0 {
+			amount += 0.05;
+			color = self.mix(towards, amount);
+		}
+		color
+	}
+}
+
+/// The base colors of an IDE theme, as in `scripts/generateThemes.cjs`.
+struct ThemeColors {
+	accent: Rgb,
+	base: Rgb,
+	/// `--whitest`, the background of the theme.
+	whitest: Rgb,
+	/// `--blackest`, its text color.
+	blackest: Rgb,
+	positive: Rgb,
+	negative: Rgb,
+}
+
+fn builtin_theme(name: &str) -> Option<ThemeColors> {
+	let (accent, base, whitest, blackest, positive, negative) = match name {
+		"dark" => (
+			"#feae34", "#3a4466", "#181425", "#e8b796", "#1ebc73", "#a24b6f",
+		),
+		"dark-red" => (
+			"#b33831", "#6e2727", "#45293f", "#ffffff", "#1ebc73", "#a24b6f",
+		),
+		"semi-sky" => (
+			"#0099db", "#0099db", "#ffffff", "#181425", "#1ebc73", "#a24b6f",
+		),
+		"ghi" => (
+			"#000000", "#ffffff", "#00aeed", "#000000", "#bfffa8", "#a24b6f",
+		),
+		"ghost" => (
+			"#eae1e8", "#d4c4d1", "#151b1b", "#ffffff", "#1ebc73", "#a24b6f",
+		),
+		"light" => (
+			"#ff4f69", "#8b8396", "#fff7f8", "#2b0f54", "#1ebc73", "#a24b6f",
+		),
+		"light-sand" => (
+			"#b8926f", "#968a81", "#ffffff", "#000000", "#1ebc73", "#a24b6f",
+		),
+		_ => return None,
+	};
+	Some(ThemeColors {
+		accent: Rgb::parse(accent)?,
+		base: Rgb::parse(base)?,
+		whitest: Rgb::parse(whitest)?,
+		blackest: Rgb::parse(blackest)?,
+		positive: Rgb::parse(positive)?,
+		negative: Rgb::parse(negative)?,
+	})
+}
+
+/// Colors a terminal needs, the way color scheme files describe them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorScheme {
+	pub name: String,
+	pub background: String,
+	pub foreground: String,
+	pub cursor: String,
+	pub selection: String,
+	/// ANSI colors 0 to 15 as `#rrggbb`.
+	pub ansi: Vec<String>,
+}
+
+/// A scheme with the full 256-color table, so every color a terminal line
+/// item can carry resolves to the same hex value everywhere: named colors
+/// index `ansi`, `Extended` colors index `extended`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalPalette {
+	#[serde(flatten)]
+	pub scheme: ColorScheme,
+	pub dark: bool,
+	/// Colors 0 to 255: the ANSI colors, the 6×6×6 cube and the gray ramp.
+	pub extended: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SchemeFormat {
+	/// `.itermcolors` property list.
+	Iterm2,
+	/// An entry of the `schemes` list in Windows Terminal's settings.
+	WindowsTerminal,
+}
+
+/// Derives a scheme from an IDE theme: black and white from its text and
+/// background, red and green from its status colors, the other hues tinted
+/// with its accent, then pushed to `min_contrast` against the background.
+fn derive_scheme(name: &str, theme: &ThemeColors, min_contrast: f64) -> ColorScheme {
+	let background = theme.whitest;
+	let foreground = theme.blackest;
+	let tint = |hue: Rgb| hue.mix(theme.accent, 0.2);
+	let hues = [
+		theme.negative.mix(RED, 0.3),
+		theme.positive,
+		tint(YELLOW),
+		tint(BLUE),
+		tint(MAGENTA),
+		tint(CYAN),
+	];
+
+	let mut ansi = vec![Rgb(0, 0, 0); 16];
+	let dark = background.luminance() < 0.5;
+	// ANSI black is the dark end and white the light end, whichever of the
+	// two the background is
+	let (darkest, lightest) = if dark {
+		(background, foreground)
+	} else {
+		(foreground, background)
+	};
+	ansi[0] = darkest.mix(lightest, 0.1);
+	ansi[8] = darkest.mix(lightest, 0.4);
+	ansi[7] = lightest.mix(darkest, 0.2);
+	ansi[15] = lightest;
+	for (i, hue) in hues.into_iter().enumerate() {
+		let normal = hue.with_contrast(background, foreground, min_contrast);
+		// Bright colors are lighter on dark backgrounds and deeper on light
+		// ones, so they still stand out from the normal ones
+		let bright = normal
+			.mix(
+				if dark {
+					Rgb(255, 255, 255)
+				} else {
+					Rgb(0, 0, 0)
+				},
+				0.25,
+			)
+			.with_contrast(background, foreground, min_contrast);
+		ansi[i + 1] = normal;
+		ansi[i + 9] = bright;
+	}
+
+	ColorScheme {
+		name: name.to_string(),
+		background: background.hex(),
+		foreground: foreground.hex(),
+		cursor: theme
+			.accent
+			.with_contrast(background, foreground, min_contrast)
+			.hex(),
+		selection: background.mix(theme.base, 0.35).hex(),
+		ansi: ansi.into_iter().map(Rgb::hex).collect(),
+	}
+}
+
+fn palette_of(scheme: ColorScheme) -> Result<TerminalPalette> {
+	let parse =
+		|hex: &str| Rgb::parse(hex).ok_or_else(|| anyhow!("Invalid color {}", hex));
+	if scheme.ansi.len() != 16 {
+		return Err(anyhow!(
+			"A scheme needs 16 ANSI colors, {} has {}",
+			scheme.name,
+			scheme.ansi.len()
+		));
+	}
+	let background = parse(&scheme.background)?;
+	let foreground = parse(&scheme.foreground)?;
+	let mut extended = Vec::with_capacity(256);
+	for color in &scheme.ansi {
+		extended.push(parse(color)?.hex());
+	}
+	const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+	for i in 0..216 {
+		extended.push(Rgb(LEVELS[i / 36], LEVELS[(i / 6) % 6], LEVELS[i % 6]).hex());
+	}
+	// The gray ramp runs from the background to the text color, so it
+	// follows the theme rather than assuming a black background
+	for i in 0..24 {
+		extended.push(background.mix(foreground, (i as f64 + 1.0) / 25.0).hex());
+	}
+	Ok(TerminalPalette {
+		dark: background.luminance() < 0.5,
+		scheme,
+		extended,
+	})
+}
+
+fn imported_schemes(app_handle: &AppHandle) -> Result<BTreeMap<String, ColorScheme>> {
+	let store = app_handle.store(SCHEMES_STORE)?;
+	Ok(store
+		.get("schemes")
+		.and_then(|v| serde_json::from_value(v).ok())
+		.unwrap_or_default())
+}
+
+fn save_schemes(
+	app_handle: &AppHandle,
+	schemes: &BTreeMap<String, ColorScheme>,
+) -> Result<()> {
+	let store = app_handle.store(SCHEMES_STORE)?;
+	store.set("schemes", serde_json::to_value(schemes)?);
+	store.save()?;
+	Ok(())
+}
+
+/// The palette of an imported scheme or, failing that, of a built-in theme.
+pub fn palette(
+	app_handle: &AppHandle,
+	theme: &str,
+	min_contrast: Option<f64>,
+) -> Result<TerminalPalette> {
+	if let Some(scheme) = imported_schemes(app_handle)?.remove(theme) {
+		return palette_of(scheme);
+	}
+	let colors =
+		builtin_theme(theme).ok_or_else(|| anyhow!("Unknown theme {}", theme))?;
+	let min_contrast = min_contrast
+		.unwrap_or(DEFAULT_MIN_CONTRAST)
+		.clamp(1.0, 21.0);
+	palette_of(derive_scheme(theme, &colors, min_contrast))
+}
+
+fn iterm_color(name: &str, hex: &str) -> Result<String> {
+	let color = Rgb::parse(hex).ok_or_else(|| anyhow!("Invalid color {}", hex))?;
+	let component = |c: u8| c as f64 / 255.0;
+	Ok(format!(
+		"\t<key>{}</key>\n\t<dict>\n\t\t<key>Alpha Component</key>\n\t\t<real>1</real>\n\t\t<key>Blue Component</key>\n\t\t<real>{}</real>\n\t\t<key>Color Space</key>\n\t\t<string>sRGB</string>\n\t\t<key>Green Component</key>\n\t\t<real>{}</real>\n\t\t<key>Red Component</key>\n\t\t<real>{}</real>\n\t</dict>\n",
+		name,
+		component(color.2),
+		component(color.1),
+		component(color.0)
+	))
+}
+
+fn export_iterm2(scheme: &ColorScheme) -> Result<String> {
+	let mut body = String::new();
+	for (i, color) in scheme.ansi.iter().enumerate() {
+		body.push_str(&iterm_color(&format!("Ansi {} Color", i), color)?);
+	}
+	body.push_str(&iterm_color("Background Color", &scheme.background)?);
+	body.push_str(&iterm_color("Cursor Color", &scheme.cursor)?);
+	body.push_str(&iterm_color("Foreground Color", &scheme.foreground)?);
+	body.push_str(&iterm_color("Selection Color", &scheme.selection)?);
+	Ok(format!(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n<plist version=\"1.0\">\n<dict>\n{}</dict>\n</plist>\n",
+		body
+	))
+}
+
+/// The text between `<tag>` and `</tag>` after `from`, and where it ends.
+fn element<'a>(text: &'a str, tag: &str, from: usize) -> Option<(&'a str, usize)> {
+	let open = format!("<{}>", tag);
+	let close = format!("</{}>", tag);
+	let start = text[from..].find(&open)? + from + open.len();
+	let end = text[start..].find(&close)? + start;
+	Some((&text[start..end], end + close.len()))
+}
+
+/// Reads the colors of an `.itermcolors` file. Its top-level dict maps
+/// names such as `Ansi 1 Color` to dicts of `Red Component`, ... between 0
+/// and 1.
+fn import_iterm2(name: &str, content: &str) -> Result<ColorScheme> {
+	let mut colors = BTreeMap::new();
+	let mut position = 0;
+	while let Some((key, after_key)) = element(content, "key", position) {
+		position = after_key;
+		let next_tag = content[after_key..].trim_start();
+		if !next_tag.starts_with("<dict>") {
+			continue;
+		}
+		let Some((dict, after_dict)) = element(content, "dict", after_key) else {
+			break;
+		};
+		position = after_dict;
+		let mut components = [None; 3];
+		let mut inner = 0;
+		while let Some((component, after)) = element(dict, "key", inner) {
+			inner = after;
+			let slot = match component {
+				"Red Component" => 0,
+				"Green Component" => 1,
+				"Blue Component" => 2,
+				_ => continue,
+			};
+			if let Some((value, after)) = element(dict, "real", after) {
+				components[slot] = value.trim().parse::<f64>().ok();
+				inner = after;
+			}
+		}
+		if let [Some(r), Some(g), Some(b)] = components {
+			let channel = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+			colors.insert(
+				key.to_string(),
+				Rgb(channel(r), channel(g), channel(b)).hex(),
+			);
+		}
+	}
+
+	let get = |key: &str| {
+		colors
+			.get(key)
+			.cloned()
+			.ok_or_else(|| anyhow!("The scheme has no {}", key))
+	};
+	let ansi = (0..16)
+		.map(|i| get(&format!("Ansi {} Color", i)))
+		.collect::<Result<Vec<_>>>()?;
+	let background = get("Background Color")?;
+	let foreground = get("Foreground Color")?;
+	Ok(ColorScheme {
+		name: name.to_string(),
+		cursor: get("Cursor Color").unwrap_or_else(|_| foreground.clone()),
+		selection: get("Selection Color").unwrap_or_else(|_| ansi[8].clone()),
+		background,
+		foreground,
+		ansi,
+	})
+}
+
+fn export_windows_terminal(scheme: &ColorScheme) -> Result<String> {
+	let mut json = serde_json::Map::new();
+	json.insert("name".to_string(), Value::from(scheme.name.clone()));
+	json.insert(
+		"background".to_string(),
+		Value::from(scheme.background.clone()),
+	);
+	json.insert(
+		"foreground".to_string(),
+		Value::from(scheme.foreground.clone()),
+	);
+	json.insert(
+		"cursorColor".to_string(),
+		Value::from(scheme.cursor.clone()),
+	);
+	json.insert(
+		"selectionBackground".to_string(),
+		Value::from(scheme.selection.clone()),
+	);
+	for (name, color) in ANSI_NAMES.iter().zip(&scheme.ansi) {
+		json.insert(name.to_string(), Value::from(color.clone()));
+	}
+	Ok(serde_json::to_string_pretty(&Value::Object(json))?)
+}
+
+fn import_windows_terminal(name: Option<&str>, content: &str) -> Result<ColorScheme> {
+	let json: Value =
+		serde_json::from_str(content).map_err(|e| anyhow!("Invalid scheme: {}", e))?;
+	let get = |key: &str| -> Result<String> {
+		let value = json
+			.get(key)
+			.and_then(Value::as_str)
+			.ok_or_else(|| anyhow!("The scheme has no {}", key))?;
+		Rgb::parse(value)
+			.map(Rgb::hex)
+			.ok_or_else(|| anyhow!("Invalid color {} for {}", value, key))
+	};
+	let ansi = ANSI_NAMES
+		.iter()
+		.map(|key| get(key))
+		.collect::<Result<Vec<_>>>()?;
+	let foreground = get("foreground")?;
+	Ok(ColorScheme {
+		name: name
+			.map(str::to_string)
+			.or_else(|| json.get("name").and_then(Value::as_str).map(str::to_string))
+			.ok_or_else(|| anyhow!("The scheme has no name"))?,
+		background: get("background")?,
+		cursor: get("cursorColor").unwrap_or_else(|_| foreground.clone()),
+		selection: get("selectionBackground").unwrap_or_else(|_| ansi[8].clone()),
+		foreground,
+		ansi,
+	})
+}
+
+/// The terminal palette for an IDE theme or imported scheme. Built-in
+/// themes have their ANSI colors pushed to `min_contrast` against the
+/// background, 3 by default.
+#[tauri::command]
+pub async fn get_terminal_palette(
+	theme: String,
+	min_contrast: Option<f64>,
+	app_handle: AppHandle,
+) -> Result<TerminalPalette, String> {
+	palette(&app_handle, &theme, min_contrast).map_err(|e| e.to_string())
+}
+
+/// Writes a theme's palette as a color scheme file for another terminal.
+#[tauri::command]
+pub async fn export_terminal_scheme(
+	theme: String,
+	format: SchemeFormat,
+	app_handle: AppHandle,
+) -> Result<String, String> {
+	let palette = palette(&app_handle, &theme, None).map_err(|e| e.to_string())?;
+	match format {
+		SchemeFormat::Iterm2 => export_iterm2(&palette.scheme),
+		SchemeFormat::WindowsTerminal => export_windows_terminal(&palette.scheme),
+	}
+	.map_err(|e| e.to_string())
+}
+
+/// Imports a color scheme file, replacing any scheme with the same name.
+/// iTerm2 files don't name their scheme, so `name` is required for them.
+#[tauri::command]
+pub async fn import_terminal_scheme(
+	content: String,
+	format: SchemeFormat,
+	name: Option<String>,
+	app_handle: AppHandle,
+) -> Result<TerminalPalette, String> {
+	let scheme = match format {
+		SchemeFormat::Iterm2 => {
+			let name = name.ok_or("An iTerm2 scheme needs a name")?;
+			import_iterm2(&name, &content)
+		}
+		SchemeFormat::WindowsTerminal => {
+			import_windows_terminal(name.as_deref(), &content)
+		}
+	}
+	.map_err(|e| e.to_string())?;
+	if builtin_theme(&scheme.name).is_some() {
+		return Err(format!("{} is the name of a built-in theme", scheme.name));
+	}
+
+	let palette = palette_of(scheme.clone()).map_err(|e| e.to_string())?;
+	let mut schemes = imported_schemes(&app_handle).map_err(|e| e.to_string())?;
+	schemes.insert(scheme.name.clone(), scheme);
+	save_schemes(&app_handle, &schemes).map_err(|e| e.to_string())?;
+	Ok(palette)
+}
+
+#[tauri::command]
+pub async fn list_terminal_schemes(
+	app_handle: AppHandle,
+) -> Result<Vec<ColorScheme>, String> {
+	imported_schemes(&app_handle)
+		.map(|schemes| schemes.into_values().collect())
+		.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_terminal_scheme(
+	name: String,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	let mut schemes = imported_schemes(&app_handle).map_err(|e| e.to_string())?;
+	if schemes.remove(&name).is_some() {
+		save_schemes(&app_handle, &schemes).map_err(|e| e.to_string())?;
+	}
+	Ok(())
+}
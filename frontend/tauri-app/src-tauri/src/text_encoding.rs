@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Result};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use serde::{Deserialize, Serialize};
+
+/// How much of a file is sampled when guessing its encoding.
+const DETECTION_SAMPLE_BYTES: usize = 64 * 1024;
+
+/// Text encodings the editor can open and save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextEncoding {
+	#[serde(rename = "utf-8")]
+	Utf8,
+	#[serde(rename = "utf-16le")]
+	Utf16Le,
+	#[serde(rename = "utf-16be")]
+	Utf16Be,
+	/// Windows-1252, the superset of ISO-8859-1 that browsers and most
+	/// editors use when a file is labelled Latin-1.
+	#[serde(rename = "latin1")]
+	Latin1,
+}
+
+impl TextEncoding {
+	fn bom(&self) -> &'static [u8] {
+		match self {
+			TextEncoding::Utf8 => b"\xef\xbb\xbf",
+			TextEncoding::Utf16Le => b"\xff\xfe",
+			TextEncoding::Utf16Be => b"\xfe\xff",
+			TextEncoding::Latin1 => b"",
+		}
+	}
+
+	fn encoding(&self) -> &'static Encoding {
+		match self {
+			TextEncoding::Utf8 => UTF_8,
+			TextEncoding::Utf16Le => encoding_rs::UTF_16LE,
+			TextEncoding::Utf16Be => encoding_rs::UTF_16BE,
+			TextEncoding::Latin1 => WINDOWS_1252,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+	Lf,
+	Crlf,
+}
+
+impl LineEnding {
+	/// The dominant line ending of `text`; files without any newline use LF.
+	pub fn detect(text: &str) -> Self {
+		let (lf, crlf) = line_ending_counts(text);
+		if crlf > lf {
+			LineEnding::Crlf
+		} else {
+			LineEnding::Lf
+		}
+	}
+
+	pub fn apply(&self, text: &str) -> String {
+		match self {
+			LineEnding::Lf => text.to_string(),
+			LineEnding::Crlf => text.replace('\n', "\r\n"),
+		}
+	}
+}
+
+/// Number of bare `\n` and of `\r\n` line endings in `text`.
+fn line_ending_counts(text: &str) -> (usize, usize) {
+	let crlf = text.matches("\r\n").count();
+	(text.matches('\n').count() - crlf, crlf)
+}
+
+/// How a file is stored on disk. Documents are edited as `\n`-terminated
+/// Rust strings and converted back to this format when saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileFormat {
+	pub encoding: TextEncoding,
+	/// The file starts with a byte order mark.
+	pub bom: bool,
+	pub line_ending: LineEnding,
+}
+
+/// The result of inspecting a file's bytes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodingInfo {
+	#[serde(flatten)]
+	pub format: FileFormat,
+	/// The file uses both LF and CRLF line endings.
+	pub mixed_line_endings: bool,
+	/// The encoding came from a BOM or the bytes are valid UTF-8, rather than
+	/// being a best guess.
+	pub confident: bool,
+}
+
+/// Guesses the encoding of `bytes`, ignoring any BOM. UTF-16 without a BOM
+/// is recognised by its zero high bytes in mostly-ASCII text; anything else
+/// that isn't valid UTF-8 is treated as Latin-1, which decodes every byte.
+fn guess_encoding(bytes: &[u8]) -> (TextEncoding, bool) {
+	let sample = &bytes[..bytes.len().min(DETECTION_SAMPLE_BYTES)];
+
+	let pairs = sample.len() / 2;
+	if pairs > 0 {
+		let zeros_at = |parity: usize| {
+			sample
+				.chunks_exact(2)
+				.filter(|pair| pair[parity] == 0)
+				.count()
+		};
+		let (even, odd) = (zeros_at(0), zeros_at(1));
+		if odd * 10 > pairs * 3 && even * 10 < pairs {
+			return (TextEncoding::Utf16Le, false);
+		}
+		if even * 10 > pairs * 3 && odd * 10 < pairs {
+			return (TextEncoding::Utf16Be, false);
+		}
+	}
+
+	// Only the sample is checked, allowing for a character cut off at its end
+	let valid_utf8 = match std::str::from_utf8(sample) {
+		Ok(_) => true,
+		Err(e) => e.error_len().is_none() && sample.len() < bytes.len(),
+	};
+	if valid_utf8 {
+		(TextEncoding::Utf8, true)
+	} else {
+		(TextEncoding::Latin1, false)
+	}
+}
+
+/// Finds the encoding of `bytes` from its BOM, falling back to heuristics.
+/// Returns the encoding, whether a BOM was present and whether the result
+/// is certain.
+fn detect(bytes: &[u8]) -> (TextEncoding, bool, bool) {
+	match Encoding::for_bom(bytes) {
+		Some((encoding, _)) if encoding == UTF_8 => (TextEncoding::Utf8, true, true),
+		Some((encoding, _)) if encoding == encoding_rs::UTF_16LE => {
+			(TextEncoding::Utf16Le, true, true)
+		}
+		Some(_) => (TextEncoding::Utf16Be, true, true),
+		None => {
+			let (encoding, confident) = guess_encoding(bytes);
+			(encoding, false, confident)
+		}
+	}
+}
+
+/// Inspects a file's bytes without keeping the decoded text.
+pub fn inspect(bytes: &[u8]) -> EncodingInfo {
+	let (encoding, bom, confident) = detect(bytes);
+	let body = if bom {
+		&bytes[encoding.bom().len()..]
+	} else {
+		bytes
+	};
+	let (text, _) = encoding.encoding().decode_without_bom_handling(body);
+	let (lf, crlf) = line_ending_counts(&text);
+
+	EncodingInfo {
+		format: FileFormat {
+			encoding,
+			bom,
+			line_ending: LineEnding::detect(&text),
+		},
+		mixed_line_endings: lf > 0 && crlf > 0,
+		confident,
+	}
+}
+
+/// Decodes a file's bytes, detecting the encoding unless one is given.
+/// Returns the text with `\n` line endings and the format to save it back in.
+/// Fails rather than substituting replacement characters, so saving never
+/// silently corrupts a file opened with the wrong encoding.
+pub fn decode(
+	bytes: &[u8],
+	encoding: Option<TextEncoding>,
+) -> Result<(String, FileFormat)> {
+	let (encoding, bom) = match encoding {
+		Some(encoding) => (
+			encoding,
+			bytes.starts_with(encoding.bom()) && !encoding.bom().is_empty(),
+		),
+		None => {
+			let (encoding, bom, _) = detect(bytes);
+			(encoding, bom)
+		}
+	};
+	let body = if bom {
+		&bytes[encoding.bom().len()..]
+	} else {
+		bytes
+	};
+
+	let raw = encoding
+		.encoding()
+		.decode_without_bom_handling_and_without_replacement(body)
+		.ok_or_else(|| anyhow!("File is not valid {}", encoding.encoding().name()))?;
+	let line_ending = LineEnding::detect(&raw);
+
+	Ok((
+		raw.replace("\r\n", "\n"),
+		FileFormat {
+			encoding,
+			bom,
+			line_ending,
+		},
+	))
+}
+
+/// Encodes `\n`-terminated `text` in `format`, failing if it contains
+/// characters the encoding cannot represent.
+pub fn encode(text: &str, format: &FileFormat) -> Result<Vec<u8>> {
+	let text = format.line_ending.apply(text);
+	let mut bytes = Vec::with_capacity(text.len() + 3);
+	if format.bom {
+		bytes.extend_from_slice(format.encoding.bom());
+	}
+
+	match format.encoding {
+		TextEncoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+		// encoding_rs only decodes UTF-16, so encode it by hand
+		TextEncoding::Utf16Le => {
+			bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes))
+		}
+		TextEncoding::Utf16Be => {
+			bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes))
+		}
+		TextEncoding::Latin1 => {
+			let (encoded, _, unmappable) = WINDOWS_1252.encode(&text);
+			if unmappable {
+				return Err(anyhow!(
+					"Text contains characters that cannot be saved as Latin-1"
+				));
+			}
+			bytes.extend_from_slice(&encoded);
+		}
+	}
+	Ok(bytes)
+}
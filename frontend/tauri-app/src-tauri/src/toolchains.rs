@@ -0,0 +1,343 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::os::OsSession;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tool {
+	Node,
+	Rust,
+	Python,
+}
+
+impl Tool {
+	const ALL: [Tool; 3] = [Tool::Node, Tool::Rust, Tool::Python];
+
+	/// Files pinning the tool's version, nearest first within a directory.
+	fn version_files(self) -> &'static [&'static str] {
+		match self {
+			Tool::Node => &[".nvmrc", ".node-version"],
+			Tool::Rust => &["rust-toolchain.toml", "rust-toolchain"],
+			Tool::Python => &[".python-version"],
+		}
+	}
+
+	fn install_hint(self, version: &str) -> String {
+		match self {
+			Tool::Node => format!("nvm install {}", version),
+			Tool::Rust => format!("rustup toolchain install {}", version),
+			Tool::Python => format!("pyenv install {}", version),
+		}
+	}
+
+	fn label(self) -> &'static str {
+		match self {
+			Tool::Node => "Node",
+			Tool::Rust => "Rust",
+			Tool::Python => "Python",
+		}
+	}
+}
+
+/// A toolchain version a project asks for, and where it was found
+/// installed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolchainRequest {
+	pub tool: Tool,
+	/// As written in the file, e.g. "18", "lts/*" or "1.75.0".
+	pub version: String,
+	/// The file asking for it.
+	pub file: String,
+	/// The installed version that satisfies it.
+	pub installed: Option<String>,
+	/// Prepended to the PATH of processes started in the project.
+	pub bin_dir: Option<String>,
+	/// Why the version can't be used, with how to install it.
+	pub error: Option<String>,
+}
+
+/// Payload of the `toolchain-missing` event, emitted when a process starts
+/// without a version its project asks for.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolchainMissing {
+	pub directory: String,
+	pub request: ToolchainRequest,
+}
+
+#[derive(Deserialize)]
+struct RustToolchainFile {
+	toolchain: RustToolchain,
+}
+
+#[derive(Deserialize)]
+struct RustToolchain {
+	channel: Option<String>,
+}
+
+/// The version pinned by `path`, a `tool` version file.
+fn read_version(tool: Tool, path: &Path) -> Option<String> {
+	let content = fs::read_to_string(path).ok()?;
+	let version = if path.extension().is_some_and(|e| e == "toml") {
+		toml::from_str::<RustToolchainFile>(&content)
+			.ok()?
+			.toolchain
+			.channel?
+	} else {
+		// .python-version may list several; the first is used
+		content
+			.lines()
+			.map(|line| line.split('#').next().unwrap_or_default().trim())
+			.find(|line| !line.is_empty())?
+			.to_string()
+	};
+	let version = match tool {
+		Tool::Node => version.trim_start_matches('v').to_string(),
+		_ => version,
+	};
+	(!version.is_empty()).then_some(version)
+}
+
+/// The nearest file in `directory` or above it pinning `tool`.
+fn find_request(tool: Tool, directory: &Path) -> Option<(String, PathBuf)> {
+	directory.ancestors().find_map(|dir| {
+		tool.version_files().iter().find_map(|name| {
+			let path = dir.join(name);
+			read_version(tool, &path).map(|version| (version, path))
+		})
+	})
+}
+
+/// Numeric components of a version such as "v18.17.0" or "3.12.1".
+fn numbers(version: &str) -> Option<Vec<u64>> {
+	version
+		.trim_start_matches('v')
+		.split('.')
+		.map(|part| part.parse().ok())
+		.collect()
+}
+
+/// The highest of `installed` matching `requested`, where "18" matches any
+/// 18.x.y. Aliases like "lts/*" or "node" take the highest installed, or
+/// for LTS the highest even major.
+fn best_match<'a>(requested: &str, installed: &'a [String]) -> Option<&'a String> {
+	let lts = requested.starts_with("lts");
+	let latest = lts || matches!(requested, "node" | "stable" | "latest");
+	let wanted = if latest {
+		Some(Vec::new())
+	} else {
+		numbers(requested)
+	};
+	let Some(wanted) = wanted else {
+		return installed.iter().find(|version| *version == requested);
+	};
+	installed
+		.iter()
+		.filter_map(|version| Some((numbers(version)?, version)))
+		.filter(|(numbers, _)| numbers.starts_with(&wanted))
+		.filter(|(numbers, _)| {
+			!lts || numbers.first().is_some_and(|major| major % 2 == 0)
+		})
+		.max_by(|a, b| a.0.cmp(&b.0))
+		.map(|(_, version)| version)
+}
+
+/// Names of the entries of `dir`.
+fn entries(dir: &Path) -> Vec<String> {
+	fs::read_dir(dir)
+		.map(|entries| {
+			entries
+				.filter_map(|entry| entry.ok())
+				.filter_map(|entry| entry.file_name().into_string().ok())
+				.collect()
+		})
+		.unwrap_or_default()
+}
+
+fn env_dir(name: &str) -> Option<PathBuf> {
+	std::env::var_os(name)
+		.filter(|value| !value.is_empty())
+		.map(PathBuf::from)
+}
+
+/// A directory a version manager keeps its installs in, with where the
+/// binaries of an install are inside it.
+type Installs = (PathBuf, fn(PathBuf) -> PathBuf);
+
+fn node_installs(home: &Path) -> Vec<Installs> {
+	let nvm = env_dir("NVM_DIR").unwrap_or_else(|| home.join(".nvm"));
+	let mut installs: Vec<Installs> = vec![
+		(nvm.join("versions/node"), |dir| dir.join("bin")),
+		(home.join(".local/share/fnm/node-versions"), |dir| {
+			dir.join("installation/bin")
+		}),
+		(
+			home.join("Library/Application Support/fnm/node-versions"),
+			|dir| dir.join("installation/bin"),
+		),
+	];
+	// nvm-windows keeps node.exe at the top of each version
+	if let Some(nvm_home) = env_dir("NVM_HOME") {
+		installs.push((nvm_home, |dir| dir));
+	}
+	installs
+}
+
+fn python_installs(home: &Path) -> Vec<Installs> {
+	let pyenv = env_dir("PYENV_ROOT").unwrap_or_else(|| home.join(".pyenv"));
+	vec![
+		(pyenv.join("versions"), |dir| dir.join("bin")),
+		(pyenv.join("pyenv-win/versions"), |dir| dir),
+	]
+}
+
+/// Finds an installed version satisfying `request`.
+fn resolve(home: &Path, request: &mut ToolchainRequest) {
+	let version = request.version.clone();
+	match request.tool {
+		// The system interpreter is whatever is already on the PATH
+		Tool::Python if version == "system" => return,
+		Tool::Node | Tool::Python => {
+			let installs = match request.tool {
+				Tool::Node => node_installs(home),
+				_ => python_installs(home),
+			};
+			for (dir, bin_dir) in installs {
+				let installed = entries(&dir);
+				if let Some(found) = best_match(&version, &installed) {
+					request.bin_dir =
+						Some(bin_dir(dir.join(found)).to_string_lossy().to_string());
+					request.installed = Some(found.trim_start_matches('v').to_string());
+					return;
+				}
+			}
+		}
+		Tool::Rust => {
+			let rustup = env_dir("RUSTUP_HOME").unwrap_or_else(|| home.join(".rustup"));
+			// Toolchains are named like "1.75.0-x86_64-unknown-linux-gnu"
+			let found = entries(&rustup.join("toolchains"))
+				.into_iter()
+				.find(|name| {
+					name == &version || name.starts_with(&format!("{}-", version))
+				});
+			if let Some(found) = found {
+				request.installed = Some(found);
+				return;
+			}
+		}
+	}
+	request.error = Some(format!(
+		"{} {} is requested by {} but isn't installed. Install it with `{}`.",
+		request.tool.label(),
+		version,
+		request.file,
+		request.tool.install_hint(&version)
+	));
+}
+
+/// The versions pinned for the session's working directory and where they
+/// are installed. Only local sessions are resolved; WSL keeps its own
+/// version managers, which its login shell sets up.
+pub fn detect(session: &OsSession, home: &Path) -> Vec<ToolchainRequest> {
+	let directory = session.host_path();
+	Tool::ALL
+		.into_iter()
+		.filter_map(|tool| {
+			let (version, file) = find_request(tool, &directory)?;
+			let mut request = ToolchainRequest {
+				tool,
+				version,
+				file: file.to_string_lossy().to_string(),
+				installed: None,
+				bin_dir: None,
+				error: None,
+			};
+			if let OsSession::Local(_) = session {
+				resolve(home, &mut request);
+			}
+			Some(request)
+		})
+		.collect()
+}
+
+/// Puts the project's pinned toolchains first on `cmd`'s PATH and sets the
+/// variables rustup and pyenv read, emitting `toolchain-missing` for
+/// versions that aren't installed. Like injected env files, failures never
+/// stop the process from starting.
+pub fn apply(app_handle: &AppHandle, session: &OsSession, cmd: &mut CommandBuilder) {
+	if !matches!(session, OsSession::Local(_)) {
+		return;
+	}
+	let Ok(home) = app_handle.path().home_dir() else {
+		return;
+	};
+	let requests = detect(session, &home);
+	let mut bin_dirs: Vec<PathBuf> = Vec::new();
+	for request in requests {
+		if request.error.is_some() {
+			log::warn!("{}", request.error.as_deref().unwrap_or_default());
+			let _ = app_handle.emit(
+				"toolchain-missing",
+				ToolchainMissing {
+					directory: session.get_working_directory().to_string(),
+					request,
+				},
+			);
+			continue;
+		}
+		match request.tool {
+			Tool::Rust => {
+				cmd.env("RUSTUP_TOOLCHAIN", &request.version);
+			}
+			Tool::Python if request.installed.is_some() => {
+				cmd.env(
+					"PYENV_VERSION",
+					request.installed.as_deref().unwrap_or_default(),
+				);
+			}
+			_ => {}
+		}
+		bin_dirs.extend(request.bin_dir.map(PathBuf::from));
+	}
+	if bin_dirs.is_empty() {
+		return;
+	}
+
+	let path: OsString = cmd
+		.get_env("PATH")
+		.map(|path| path.to_os_string())
+		.or_else(|| std::env::var_os("PATH"))
+		.unwrap_or_default();
+	bin_dirs.extend(std::env::split_paths(&path));
+	match std::env::join_paths(bin_dirs) {
+		Ok(path) => {
+			cmd.env("PATH", path);
+		}
+		Err(e) => log::warn!("Failed to set toolchain PATH: {}", e),
+	}
+}
+
+/// The toolchain versions the project in `os_session` pins with `.nvmrc`,
+/// `.node-version`, `rust-toolchain.toml`, `rust-toolchain` or
+/// `.python-version`, and whether they're installed.
+#[tauri::command]
+pub async fn detect_toolchain_versions(
+	os_session: OsSession,
+	app_handle: AppHandle,
+) -> Result<Vec<ToolchainRequest>, String> {
+	let home = app_handle.path().home_dir().map_err(|e| e.to_string())?;
+	tauri::async_runtime::spawn_blocking(move || -> Result<_> {
+		Ok(detect(&os_session, &home))
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
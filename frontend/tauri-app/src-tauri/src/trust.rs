@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::{
+	DialogExt, MessageDialogButtons, MessageDialogKind, MessageDialogResult,
+};
+
+use crate::os::OsSession;
+use crate::util::{now_millis, write_private};
+#[cfg(target_os = "windows")]
+use crate::wsl;
+
+/// Decisions live in this directory of the app's local data, out of reach of
+/// the webview's fs scope and of the store plugin, so only Rust writes them.
+const TRUST_DIR: &str = "workspace-trust";
+const DECISIONS_FILE: &str = "decisions";
+/// First line of the decisions file, followed by the decisions as a JSON
+/// object keyed by `trust_key`. A file without it is ignored.
+const DECISIONS_HEADER: &str = "# Folder trust decisions, written by the app";
+
+const TRUST: &str = "Trust";
+const DONT_TRUST: &str = "Don't trust";
+const CANCEL: &str = "Cancel";
+
+/// Serializes reads and writes of the decisions file.
+static DECISIONS_LOCK: Mutex<()> = Mutex::new(());
+/// Held while asking, so commands started together ask about a folder once.
+static PROMPT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Whether code from a folder may run without asking: tasks, git hooks,
+/// agents, project formatters and commands in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustState {
+	Trusted,
+	Untrusted,
+	/// No decision yet, e.g. the folder is opened for the first time.
+	/// The user is asked before code from it runs.
+	Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustDecision {
+	pub path: String,
+	/// Set for folders inside WSL.
+	#[serde(default)]
+	pub distribution: Option<String>,
+	pub trusted: bool,
+	/// Milliseconds since the Unix epoch.
+	pub decided_at: u64,
+}
+
+/// `path` as decisions are stored: without trailing separators, with
+/// Windows paths case-folded, and WSL paths prefixed by their
+/// distribution so equal paths in different sessions don't collide.
+fn trust_key(session: &OsSession, path: &str) -> String {
+	match session {
+		OsSession::Local(_) => {
+			let path = std::fs::canonicalize(path)
+				.map(|path| path.to_string_lossy().to_string())
+				.unwrap_or_else(|_| path.to_string());
+			let path = path.trim_start_matches("\\\\?\\");
+			let path = trim_separators(path);
+			if cfg!(target_os = "windows") {
+				path.replace('/', "\\").to_lowercase()
+			} else {
+				path.to_string()
+			}
+		}
+		OsSession::Wsl(wsl_session) => {
+			#[cfg(target_os = "windows")]
+			let path = &wsl::to_wsl_path(&wsl_session.distribution, path);
+			format!("wsl:{}:{}", wsl_session.distribution, trim_separators(path))
+		}
+	}
+}
+
+fn trim_separators(path: &str) -> &str {
+	let trimmed = path.trim_end_matches(['/', '\\']);
+	if trimmed.is_empty() || trimmed.ends_with(':') {
+		// Keep the root itself, `/` or `C:\`
+		&path[..(trimmed.len() + 1).min(path.len())]
+	} else {
+		trimmed
+	}
+}
+
+/// `key` and the keys of its parent folders, nearest first.
+fn ancestor_keys(key: &str) -> impl Iterator<Item = &str> {
+	std::iter::successors(Some(key), |key| {
+		let (parent, _) = key.rsplit_once(['/', '\\'])?;
+		if parent.is_empty() || parent.ends_with(':') {
+			// The root keeps its separator
+			let root = &key[..parent.len() + 1];
+			(root != *key).then_some(root)
+		} else {
+			Some(parent)
+		}
+	})
+}
+
+fn decisions_path(app_handle: &AppHandle) -> Result<PathBuf> {
+	Ok(app_handle
+		.path()
+		.app_local_data_dir()?
+		.join(TRUST_DIR)
+		.join(DECISIONS_FILE))
+}
+
+fn read_decisions(app_handle: &AppHandle) -> Result<BTreeMap<String, TrustDecision>> {
+	let path = decisions_path(app_handle)?;
+	let content = match fs::read_to_string(&path) {
+		Ok(content) => content,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+		Err(e) => return Err(e.into()),
+	};
+	match content.split_once('\n') {
+		Some((DECISIONS_HEADER, decisions)) => Ok(serde_json::from_str(decisions)?),
+		_ => {
+			log::warn!("Ignoring malformed trust decisions {}", path.display());
+			Ok(BTreeMap::new())
+		}
+	}
+}
+
+/// Applies `change` to the decisions and writes them back.
+fn update_decisions(
+	app_handle: &AppHandle,
+	change: impl FnOnce(&mut BTreeMap<String, TrustDecision>),
+) -> Result<()> {
+	let _guard = DECISIONS_LOCK.lock().unwrap();
+	let mut decisions = read_decisions(app_handle)?;
+	change(&mut decisions);
+	let path = decisions_path(app_handle)?;
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir)?;
+	}
+	let content = format!(
+		"{}\n{}\n",
+		DECISIONS_HEADER,
+		serde_json::to_string_pretty(&decisions)?
+	);
+	write_private(&path, content.as_bytes())
+}
+
+fn record_decision(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	path: &str,
+	trusted: bool,
+) -> Result<()> {
+	let key = trust_key(session, path);
+	let decision = TrustDecision {
+		path: path.to_string(),
+		distribution: match session {
+			OsSession::Wsl(wsl_session) => Some(wsl_session.distribution.clone()),
+			OsSession::Local(_) => None,
+		},
+		trusted,
+		decided_at: now_millis(),
+	};
+	update_decisions(app_handle, |decisions| {
+		decisions.insert(key, decision);
+	})
+}
+
+/// The decision for `path`, inherited from the nearest folder above it
+/// that has one.
+pub fn trust_state(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	path: &str,
+) -> Result<TrustState> {
+	let decisions = {
+		let _guard = DECISIONS_LOCK.lock().unwrap();
+		read_decisions(app_handle)?
+	};
+	let key = trust_key(session, path);
+	for key in ancestor_keys(&key) {
+		if let Some(decision) = decisions.get(key) {
+			return Ok(if decision.trusted {
+				TrustState::Trusted
+			} else {
+				TrustState::Untrusted
+			});
+		}
+	}
+	Ok(TrustState::Unknown)
+}
+
+/// Asks the user in a native dialog, which the webview can't answer for
+/// them, whether to trust `path`, and records the answer. Cancelling
+/// records nothing, so the next command asks again.
+fn ask(app_handle: &AppHandle, session: &OsSession, path: &str) -> Result<bool> {
+	let location = match session {
+		OsSession::Wsl(wsl_session) => format!("{} ({})", path, wsl_session.distribution),
+		OsSession::Local(_) => path.to_string(),
+	};
+	let answer = app_handle
+		.dialog()
+		.message(format!(
+			"Do you trust the code in this folder?\n\n{}\n\nTrusting it lets the app \
+			 run its tasks, git hooks, formatters and agents, and commands in it.",
+			location
+		))
+		.title("Trust folder?")
+		.kind(MessageDialogKind::Warning)
+		.buttons(MessageDialogButtons::YesNoCancelCustom(
+			TRUST.to_string(),
+			DONT_TRUST.to_string(),
+			CANCEL.to_string(),
+		))
+		.blocking_show_with_result();
+	match answer {
+		MessageDialogResult::Custom(label) if label == TRUST => {
+			record_decision(app_handle, session, path, true)?;
+			Ok(true)
+		}
+		MessageDialogResult::Custom(label) if label == DONT_TRUST => {
+			record_decision(app_handle, session, path, false)?;
+			Ok(false)
+		}
+		_ => Ok(false),
+	}
+}
+
+/// Fails with an `UNTRUSTED_WORKSPACE` error unless `path` is trusted, for
+/// commands that would run code from it. The user is asked about a folder
+/// the first time code from it would run.
+pub fn ensure_trusted(
+	app_handle: &AppHandle,
+	session: &OsSession,
+	path: &str,
+) -> Result<(), String> {
+	let state = || trust_state(app_handle, session, path).map_err(|e| e.to_string());
+	let trusted = match state()? {
+		TrustState::Trusted => true,
+		TrustState::Untrusted => false,
+		TrustState::Unknown => {
+			let _guard = PROMPT_LOCK.lock().unwrap();
+			// Another command may have asked while this one waited
+			match state()? {
+				TrustState::Trusted => true,
+				TrustState::Untrusted => false,
+				TrustState::Unknown => {
+					ask(app_handle, session, path).map_err(|e| e.to_string())?
+				}
+			}
+		}
+	};
+	if trusted {
+		Ok(())
+	} else {
+		Err(format!("UNTRUSTED_WORKSPACE: {}", path))
+	}
+}
+
+#[tauri::command]
+pub async fn get_workspace_trust(
+	path: String,
+	os_session: OsSession,
+	app_handle: AppHandle,
+) -> Result<TrustState, String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		trust_state(&app_handle, &os_session, &path)
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+/// Records whether `path` and the folders inside it are trusted. Trusting
+/// asks the user first, so the webview can't trust folders by itself.
+#[tauri::command]
+pub async fn set_workspace_trust(
+	path: String,
+	os_session: OsSession,
+	trusted: bool,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		if !trusted {
+			return record_decision(&app_handle, &os_session, &path, false)
+				.map_err(|e| e.to_string());
+		}
+		let _guard = PROMPT_LOCK.lock().unwrap();
+		if ask(&app_handle, &os_session, &path).map_err(|e| e.to_string())? {
+			Ok(())
+		} else {
+			Err(format!("UNTRUSTED_WORKSPACE: {}", path))
+		}
+	})
+	.await
+	.map_err(|e| e.to_string())?
+}
+
+/// Forgets the decision for `path`, so it is asked for again.
+#[tauri::command]
+pub async fn remove_workspace_trust(
+	path: String,
+	os_session: OsSession,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	tauri::async_runtime::spawn_blocking(move || {
+		let key = trust_key(&os_session, &path);
+		update_decisions(&app_handle, |decisions| {
+			decisions.remove(&key);
+		})
+	})
+	.await
+	.map_err(|e| e.to_string())?
+	.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_trust_decisions(
+	app_handle: AppHandle,
+) -> Result<Vec<TrustDecision>, String> {
+	let decisions = {
+		let _guard = DECISIONS_LOCK.lock().unwrap();
+		read_decisions(&app_handle).map_err(|e| e.to_string())?
+	};
+	let mut decisions: Vec<TrustDecision> = decisions.into_values().collect();
+	decisions.sort_by(|a, b| a.path.cmp(&b.path));
+	Ok(decisions)
+}
@@ -0,0 +1,198 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, State, Url};
+use tauri_plugin_store::StoreExt;
+use tauri_plugin_updater::{Update, UpdaterExt};
+use uuid::Uuid;
+
+use crate::backend_client;
+use crate::session::SessionManager;
+
+/// Store holding the update channel and this install's id.
+const UPDATES_STORE: &str = "updates.json";
+
+/// Minisign public key matching the key release bundles are signed with,
+/// provided when building releases.
+const UPDATER_PUBKEY: Option<&str> = option_env!("ARIANA_UPDATER_PUBKEY");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+	#[default]
+	Stable,
+	Beta,
+	Nightly,
+}
+
+impl UpdateChannel {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Stable => "stable",
+			Self::Beta => "beta",
+			Self::Nightly => "nightly",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateInfo {
+	pub version: String,
+	pub current_version: String,
+	pub notes: Option<String>,
+	/// RFC 3339.
+	pub date: Option<String>,
+}
+
+/// Payload of the `update-download-progress` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgress {
+	pub downloaded: u64,
+	/// `None` when the server didn't send a length.
+	pub total: Option<u64>,
+}
+
+/// The update found by the last check, until it is installed.
+#[derive(Default)]
+pub struct UpdateState {
+	pending: Mutex<Option<Update>>,
+}
+
+pub fn channel(app_handle: &AppHandle) -> Result<UpdateChannel> {
+	let store = app_handle.store(UPDATES_STORE)?;
+	Ok(store
+		.get("channel")
+		.and_then(|v| serde_json::from_value(v).ok())
+		.unwrap_or_default())
+}
+
+pub fn set_channel(app_handle: &AppHandle, channel: UpdateChannel) -> Result<()> {
+	let store = app_handle.store(UPDATES_STORE)?;
+	store.set("channel", serde_json::to_value(channel)?);
+	store.save()?;
+	Ok(())
+}
+
+/// Random id sent with update checks so the backend can stage rollouts;
+/// created on first use.
+fn install_id(app_handle: &AppHandle) -> Result<String> {
+	let store = app_handle.store(UPDATES_STORE)?;
+	if let Some(Value::String(id)) = store.get("installId") {
+		return Ok(id);
+	}
+	let id = Uuid::new_v4().to_string();
+	store.set("installId", Value::String(id.clone()));
+	store.save()?;
+	Ok(id)
+}
+
+/// Asks the backend for a release on `channel` newer than this build.
+pub async fn check(
+	app_handle: &AppHandle,
+	channel: UpdateChannel,
+) -> Result<Option<Update>> {
+	let pubkey = UPDATER_PUBKEY
+		.filter(|key| !key.is_empty())
+		.ok_or_else(|| anyhow!("This build was made without an update signing key"))?;
+	// The updater fills in the {{...}} placeholders
+	let endpoint = Url::parse(&format!(
+		"{}/api/updates/{}/{{{{target}}}}/{{{{arch}}}}/{{{{current_version}}}}",
+		backend_client::backend_url(app_handle),
+		channel.as_str()
+	))?;
+
+	let updater = app_handle
+		.updater_builder()
+		.pubkey(pubkey)
+		.endpoints(vec![endpoint])?
+		.header("X-Install-Id", install_id(app_handle)?)?
+		.build()?;
+	Ok(updater.check().await?)
+}
+
+#[tauri::command]
+pub async fn get_update_channel(app_handle: AppHandle) -> Result<UpdateChannel, String> {
+	channel(&app_handle).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_update_channel(
+	channel: UpdateChannel,
+	app_handle: AppHandle,
+) -> Result<(), String> {
+	set_channel(&app_handle, channel).map_err(|e| e.to_string())
+}
+
+/// Checks the stored channel, or `channel` if given, for an update. A found
+/// update is kept for `install_update`.
+#[tauri::command]
+pub async fn check_for_updates(
+	channel: Option<UpdateChannel>,
+	app_handle: AppHandle,
+	state: State<'_, UpdateState>,
+) -> Result<Option<UpdateInfo>, String> {
+	let channel = match channel {
+		Some(channel) => channel,
+		None => self::channel(&app_handle).map_err(|e| e.to_string())?,
+	};
+	let update = check(&app_handle, channel)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	let info = update.as_ref().map(|update| UpdateInfo {
+		version: update.version.clone(),
+		current_version: update.current_version.clone(),
+		notes: update.body.clone(),
+		date: update
+			.raw_json
+			.get("pub_date")
+			.and_then(Value::as_str)
+			.map(str::to_string),
+	});
+	*state.pending.lock().unwrap() = update;
+	Ok(info)
+}
+
+/// Downloads the update found by `check_for_updates`, emitting
+/// `update-download-progress` events, then saves the session and restarts
+/// into the new version.
+#[tauri::command]
+pub async fn install_update(
+	app_handle: AppHandle,
+	state: State<'_, UpdateState>,
+	session: State<'_, Arc<SessionManager>>,
+) -> Result<(), String> {
+	let update = state
+		.pending
+		.lock()
+		.unwrap()
+		.take()
+		.ok_or_else(|| "No update to install; check for updates first".to_string())?;
+
+	let mut downloaded = 0u64;
+	let bytes = update
+		.download(
+			|chunk, total| {
+				downloaded += chunk as u64;
+				let _ = app_handle.emit(
+					"update-download-progress",
+					DownloadProgress { downloaded, total },
+				);
+			},
+			|| {},
+		)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	// Installing may quit the app straight away on Windows
+	if let Err(e) = session.snapshot(false) {
+		log::error!("Failed to save session before updating: {}", e);
+	}
+	update.install(bytes).map_err(|e| e.to_string())?;
+	log::info!("Installed update {}, restarting", update.version);
+	app_handle.restart();
+}
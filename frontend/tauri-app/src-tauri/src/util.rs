@@ -0,0 +1,95 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use reqwest::Response;
+use serde_json::Value;
+
+/// Milliseconds since the Unix epoch, as timestamps are stored and sent to
+/// the frontend.
+pub fn now_millis() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_millis() as u64
+}
+
+/// Quotes `arg` as one word for a POSIX shell, including the remote shell
+/// ssh hands its command line to.
+pub fn shell_quote(arg: &str) -> String {
+	format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Turns an unsuccessful response into an error saying `what` failed, with
+/// the server's `message` or `error` field when its body has one.
+pub async fn error_from(response: Response, what: &str) -> anyhow::Error {
+	let status = response.status();
+	let message = response.json::<Value>().await.ok().and_then(|body| {
+		body.get("message")
+			.or_else(|| body.get("error"))
+			.map(|message| match message {
+				Value::String(message) => message.clone(),
+				other => other.to_string(),
+			})
+	});
+	match message {
+		Some(message) => anyhow!("{} failed ({}): {}", what, status, message),
+		None => anyhow!("{} failed ({})", what, status),
+	}
+}
+
+/// Writes `contents` to `path` readable by the current user only. It goes
+/// through a temporary file, made owner-only before anything is written to
+/// it, then renamed over `path`.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+	let temp = path.with_extension("tmp");
+	let _ = fs::remove_file(&temp);
+	let mut options = OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::OpenOptionsExt;
+		options.mode(0o600);
+	}
+	let mut file = options.open(&temp)?;
+	let written = restrict_to_owner(&temp)
+		.and_then(|()| Ok(file.write_all(contents)?))
+		.and_then(|()| Ok(fs::rename(&temp, path)?));
+	if written.is_err() {
+		let _ = fs::remove_file(&temp);
+	}
+	written
+}
+
+/// Created with mode 0600 on unix, so there is nothing left to do.
+#[cfg(unix)]
+fn restrict_to_owner(_path: &Path) -> Result<()> {
+	Ok(())
+}
+
+/// Replaces the inherited ACL with one granting only the current user.
+#[cfg(windows)]
+fn restrict_to_owner(path: &Path) -> Result<()> {
+	use anyhow::Context;
+	use std::os::windows::process::CommandExt;
+	const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+	let user = std::env::var("USERNAME").context("USERNAME is not set")?;
+	let status = std::process::Command::new("icacls")
+		.arg(path)
+		.args(["/inheritance:r", "/grant:r"])
+		.arg(format!("{}:F", user))
+		.stdout(std::process::Stdio::null())
+		.stderr(std::process::Stdio::null())
+		.creation_flags(CREATE_NO_WINDOW)
+		.status()
+		.context("Failed to run icacls")?;
+	anyhow::ensure!(
+		status.success(),
+		"icacls failed to restrict {}",
+		path.display()
+	);
+	Ok(())
+}
@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::file_watcher::{FileChange, FileWatcher};
+use crate::index_manager::IGNORED_DIRS;
+
+/// The per-workspace state of an indexer.
+pub trait WatchedWorkspace: Send + Sync + 'static {
+	/// Stops background work on the workspace, such as an index build.
+	fn close(&self);
+}
+
+/// The workspaces an indexer keeps current, keyed by canonical root, each
+/// watched recursively while it is open.
+pub struct WatchedWorkspaces<W> {
+	watcher: Arc<FileWatcher>,
+	open: Mutex<HashMap<PathBuf, Arc<W>>>,
+}
+
+impl<W: WatchedWorkspace> WatchedWorkspaces<W> {
+	pub fn new(watcher: Arc<FileWatcher>) -> Self {
+		Self {
+			watcher,
+			open: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Opens the workspace at `root`, or returns the open one if `keep`
+	/// accepts it. Otherwise the open one is closed and `create` makes the
+	/// replacement from the canonical root. The flag tells whether the
+	/// workspace was created.
+	pub fn open(
+		&self,
+		root: &str,
+		keep: impl FnOnce(&W) -> bool,
+		create: impl FnOnce(PathBuf) -> W,
+	) -> Result<(Arc<W>, bool)> {
+		let root = fs::canonicalize(root)
+			.with_context(|| format!("Failed to open workspace {}", root))?;
+		if !root.is_dir() {
+			return Err(anyhow!("{} is not a directory", root.display()));
+		}
+
+		let mut open = self.open.lock().unwrap();
+		let replaced = match open.get(&root) {
+			Some(workspace) if keep(workspace) => return Ok((workspace.clone(), false)),
+			Some(workspace) => {
+				workspace.close();
+				true
+			}
+			None => false,
+		};
+		let workspace = Arc::new(create(root.clone()));
+		// A replaced workspace hands its watch over
+		if !replaced {
+			self.watcher.watch(&root, true)?;
+		}
+		open.insert(root, workspace.clone());
+		Ok((workspace, true))
+	}
+
+	/// Closes and stops watching `root`.
+	pub fn close(&self, root: &str) -> Result<()> {
+		let root = fs::canonicalize(root).unwrap_or_else(|_| PathBuf::from(root));
+		let Some(workspace) = self.open.lock().unwrap().remove(&root) else {
+			return Ok(());
+		};
+		workspace.close();
+		self.watcher.unwatch(&root)
+	}
+
+	pub fn get(&self, root: &str) -> Option<Arc<W>> {
+		let root = fs::canonicalize(root).ok()?;
+		self.open.lock().unwrap().get(&root).cloned()
+	}
+
+	pub fn all(&self) -> Vec<Arc<W>> {
+		self.open.lock().unwrap().values().cloned().collect()
+	}
+
+	/// Feeds file watcher events to `apply` for as long as `owner` lives.
+	/// Changes are collected until the watcher has been quiet for
+	/// `debounce`, so a branch switch or build is handled in one pass.
+	/// `apply` gets the changed paths, and whether events were missed and
+	/// the whole of every workspace must be compared with its index.
+	pub fn watch_changes<M, F, Fut>(&self, owner: &Arc<M>, debounce: Duration, apply: F)
+	where
+		M: Send + Sync + 'static,
+		F: Fn(Arc<M>, HashSet<PathBuf>, bool) -> Fut + Send + 'static,
+		Fut: Future<Output = ()> + Send + 'static,
+	{
+		let mut changes = self.watcher.subscribe();
+		let owner = Arc::downgrade(owner);
+		tauri::async_runtime::spawn(async move {
+			let mut pending = HashSet::new();
+			let mut rescan = false;
+			loop {
+				let received = if pending.is_empty() && !rescan {
+					Some(changes.recv().await)
+				} else {
+					tokio::time::timeout(debounce, changes.recv()).await.ok()
+				};
+				match received {
+					Some(Ok(FileChange { path, .. })) => {
+						pending.insert(path);
+						continue;
+					}
+					// Missed events; compare the whole tree with the index
+					Some(Err(RecvError::Lagged(_))) => {
+						rescan = true;
+						continue;
+					}
+					Some(Err(RecvError::Closed)) => break,
+					None => {}
+				}
+
+				let Some(owner) = owner.upgrade() else {
+					break;
+				};
+				let paths = std::mem::take(&mut pending);
+				let full = std::mem::take(&mut rescan);
+				apply(owner, paths, full).await;
+			}
+		});
+	}
+}
+
+/// Whether `files`, keyed by path relative to `root`, has entries below
+/// `dir`, which may no longer exist.
+pub fn has_files_under<V>(root: &Path, files: &HashMap<PathBuf, V>, dir: &Path) -> bool {
+	let Ok(relative) = dir.strip_prefix(root) else {
+		return false;
+	};
+	files
+		.keys()
+		.any(|file| file != relative && file.starts_with(relative))
+}
+
+/// Whether `path` is inside `root` and not in an ignored directory.
+pub fn covers(root: &Path, path: &Path) -> bool {
+	let Ok(relative) = path.strip_prefix(root) else {
+		return false;
+	};
+	relative
+		.parent()
+		.into_iter()
+		.flat_map(Path::components)
+		.all(|c| {
+			let name = c.as_os_str().to_string_lossy();
+			!name.starts_with('.') && !IGNORED_DIRS.contains(&name.as_ref())
+		})
+}
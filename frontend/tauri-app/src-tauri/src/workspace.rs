@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State, Url};
+use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+use crate::git::{current_branch, run_git};
+use crate::index_manager::{IndexManager, IndexStatus, SymbolMatch};
+use crate::os::{FileNode, OsSession};
+
+/// Store holding the saved workspaces by id.
+const WORKSPACE_STORE: &str = "workspaces.json";
+
+/// A folder of a workspace, opened with its own session so one workspace
+/// can mix local and WSL folders.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceRoot {
+	/// Shown instead of the folder name, if set.
+	#[serde(default)]
+	pub name: Option<String>,
+	/// The session's working directory is the root folder.
+	pub os_session: OsSession,
+}
+
+impl WorkspaceRoot {
+	pub fn path(&self) -> &str {
+		self.os_session.get_working_directory()
+	}
+
+	pub fn display_name(&self) -> String {
+		if let Some(name) = &self.name {
+			return name.clone();
+		}
+		let path = self.path().trim_end_matches(['/', '\\']);
+		path.rsplit(['/', '\\'])
+			.next()
+			.filter(|name| !name.is_empty())
+			.unwrap_or(path)
+			.to_string()
+	}
+}
+
+/// Several root folders shown together in one window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Workspace {
+	/// Empty when saving a new workspace; one is assigned.
+	#[serde(default)]
+	pub id: String,
+	pub name: String,
+	pub roots: Vec<WorkspaceRoot>,
+}
+
+/// The top level of one root's file tree.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootTree {
+	pub name: String,
+	pub os_session: OsSession,
+	pub nodes: Vec<FileNode>,
+	/// Why the root couldn't be listed, e.g. its WSL distribution is gone.
+	pub error: Option<String>,
+}
+
+/// The repository a root belongs to, if any.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootRepository {
+	pub name: String,
+	pub os_session: OsSession,
+	/// Top level of the repository, in the root's session.
+	pub repository: Option<String>,
+	pub branch: Option<String>,
+}
+
+/// A root as an LSP `WorkspaceFolder`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceFolder {
+	pub uri: String,
+	pub name: String,
+}
+
+fn load_workspace(app_handle: &AppHandle, id: &str) -> Result<Workspace> {
+	let store = app_handle.store(WORKSPACE_STORE)?;
+	let value = store
+		.get(id)
+		.ok_or_else(|| anyhow!("Workspace {} not found", id))?;
+	Ok(serde_json::from_value(value)?)
+}
+
+/// The repository containing `root`, or `None` if it isn't in one.
+fn root_repository(root: &WorkspaceRoot) -> RootRepository {
+	let session = &root.os_session;
+	let repository = run_git(session, root.path(), &["rev-parse", "--show-toplevel"])
+		.ok()
+		.map(|top| top.trim().to_string())
+		.filter(|top| !top.is_empty());
+	let branch = repository
+		.as_ref()
+		.and_then(|_| current_branch(session, root.path()).ok().flatten());
+	RootRepository {
+		name: root.display_name(),
+		os_session: session.clone(),
+		repository,
+		branch,
+	}
+}
+
+#[tauri::command]
+pub async fn list_workspaces(app_handle: AppHandle) -> Result<Vec<Workspace>, String> {
+	let store = app_handle
+		.store(WORKSPACE_STORE)
+		.map_err(|e| e.to_string())?;
+	let mut workspaces: Vec<Workspace> = store
+		.entries()
+		.into_iter()
+		.filter_map(|(_, value)| serde_json::from_value(value).ok())
+		.collect();
+	workspaces.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+	Ok(workspaces)
+}
+
+#[tauri::command]
+pub async fn get_workspace(
+	app_handle: AppHandle,
+	id: String,
+) -> Result<Workspace, String> {
+	load_workspace(&app_handle, &id).map_err(|e| e.to_string())
+}
+
+/// Creates the workspace, or replaces the one with the same id.
+#[tauri::command]
+pub async fn save_workspace(
+	app_handle: AppHandle,
+	mut workspace: Workspace,
+) -> Result<Workspace, String> {
+	if workspace.id.is_empty() {
+		workspace.id = Uuid::new_v4().to_string();
+	}
+	let store = app_handle
+		.store(WORKSPACE_STORE)
+		.map_err(|e| e.to_string())?;
+	store.set(
+		workspace.id.clone(),
+		serde_json::to_value(&workspace).map_err(|e| e.to_string())?,
+	);
+	store.save().map_err(|e| e.to_string())?;
+	Ok(workspace)
+}
+
+#[tauri::command]
+pub async fn delete_workspace(app_handle: AppHandle, id: String) -> Result<(), String> {
+	let store = app_handle
+		.store(WORKSPACE_STORE)
+		.map_err(|e| e.to_string())?;
+	store.delete(id);
+	store.save().map_err(|e| e.to_string())
+}
+
+/// Starts indexing every root for symbol search. Roots that can't be
+/// indexed are skipped.
+#[tauri::command]
+pub async fn open_workspace(
+	app_handle: AppHandle,
+	id: String,
+	manager: State<'_, Arc<IndexManager>>,
+) -> Result<Vec<IndexStatus>, String> {
+	let workspace = load_workspace(&app_handle, &id).map_err(|e| e.to_string())?;
+	let mut statuses = Vec::new();
+	for root in &workspace.roots {
+		let host_path = root.os_session.host_path();
+		match manager.index_workspace(&host_path.to_string_lossy()) {
+			Ok(status) => statuses.push(status),
+			Err(e) => log::warn!("Failed to index {}: {}", host_path.display(), e),
+		}
+	}
+	Ok(statuses)
+}
+
+/// Lists the top level of every root, in the workspace's order.
+#[tauri::command]
+pub async fn get_workspace_file_trees(
+	app_handle: AppHandle,
+	id: String,
+) -> Result<Vec<RootTree>, String> {
+	let workspace = load_workspace(&app_handle, &id).map_err(|e| e.to_string())?;
+	let mut trees = Vec::new();
+	for root in workspace.roots {
+		let (nodes, error) = match root.os_session.read_directory(root.path()).await {
+			Ok(nodes) => (nodes, None),
+			Err(e) => (Vec::new(), Some(e.to_string())),
+		};
+		trees.push(RootTree {
+			name: root.display_name(),
+			os_session: root.os_session,
+			nodes,
+			error,
+		});
+	}
+	Ok(trees)
+}
+
+/// Finds the git repository of every root, in the workspace's order.
+#[tauri::command]
+pub async fn get_workspace_repositories(
+	app_handle: AppHandle,
+	id: String,
+) -> Result<Vec<RootRepository>, String> {
+	let workspace = load_workspace(&app_handle, &id).map_err(|e| e.to_string())?;
+	tauri::async_runtime::spawn_blocking(move || {
+		workspace.roots.iter().map(root_repository).collect()
+	})
+	.await
+	.map_err(|e| e.to_string())
+}
+
+/// The roots as LSP workspace folders, for `initialize` and
+/// `workspace/workspaceFolders`.
+#[tauri::command]
+pub async fn get_workspace_folders(
+	app_handle: AppHandle,
+	id: String,
+) -> Result<Vec<WorkspaceFolder>, String> {
+	let workspace = load_workspace(&app_handle, &id).map_err(|e| e.to_string())?;
+	workspace
+		.roots
+		.iter()
+		.map(|root| {
+			let host_path = root.os_session.host_path();
+			let uri = Url::from_file_path(&host_path).map_err(|_| {
+				format!("Invalid workspace root: {}", host_path.display())
+			})?;
+			Ok(WorkspaceFolder {
+				uri: uri.to_string(),
+				name: root.display_name(),
+			})
+		})
+		.collect()
+}
+
+/// Searches the symbols of every indexed root of the workspace.
+#[tauri::command]
+pub async fn search_workspace_symbols(
+	app_handle: AppHandle,
+	id: String,
+	query: String,
+	limit: Option<usize>,
+	manager: State<'_, Arc<IndexManager>>,
+) -> Result<Vec<SymbolMatch>, String> {
+	let workspace = load_workspace(&app_handle, &id).map_err(|e| e.to_string())?;
+	let roots: Vec<String> = workspace
+		.roots
+		.iter()
+		.map(|root| root.os_session.host_path().to_string_lossy().to_string())
+		.collect();
+	let roots: Vec<&str> = roots.iter().map(String::as_str).collect();
+	Ok(manager.search_symbols_in(&query, Some(&roots), limit))
+}
@@ -0,0 +1,230 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use anyhow::{anyhow, Result};
+use portable_pty::CommandBuilder;
+use serde::Deserialize;
+
+/// The two ways a path can be written across the WSL boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PathForm {
+	/// `C:\Users\me` or `\\wsl$\Ubuntu\home\me`.
+	Windows,
+	/// `/mnt/c/Users/me` or `/home/me`.
+	Wsl,
+}
+
+/// Splits `\\wsl$\Distro\rest` or `\\wsl.localhost\Distro\rest`, with
+/// either kind of slash, into the distribution and the path inside it.
+fn parse_unc(path: &str) -> Option<(&str, String)> {
+	let path = path
+		.strip_prefix("\\\\")
+		.or_else(|| path.strip_prefix("//"))?;
+	let (server, rest) = path.split_once(['\\', '/'])?;
+	if !server.eq_ignore_ascii_case("wsl$")
+		&& !server.eq_ignore_ascii_case("wsl.localhost")
+	{
+		return None;
+	}
+	let (distribution, rest) = rest.split_once(['\\', '/']).unwrap_or((rest, ""));
+	Some((distribution, format!("/{}", rest.replace('\\', "/"))))
+}
+
+/// Maps a path to WSL form without asking WSL, assuming drives are mounted
+/// under `/mnt`. `None` when that isn't enough, e.g. for network shares.
+fn wsl_path_of(distribution: &str, path: &str) -> Option<String> {
+	if path.starts_with('/') || path.starts_with('~') {
+		return Some(path.to_string());
+	}
+	if let Some((unc_distribution, rest)) = parse_unc(path) {
+		return unc_distribution
+			.eq_ignore_ascii_case(distribution)
+			.then_some(rest);
+	}
+	let mut chars = path.chars();
+	match (chars.next(), chars.next(), chars.next()) {
+		(Some(drive), Some(':'), None | Some('\\' | '/'))
+			if drive.is_ascii_alphabetic() =>
+		{
+			Some(format!(
+				"/mnt/{}{}",
+				drive.to_ascii_lowercase(),
+				path[2..].replace('\\', "/")
+			))
+		}
+		_ => None,
+	}
+}
+
+/// Maps an absolute WSL path to Windows form: drive letters for the
+/// mounted Windows drives, `\\wsl$` for the distribution's own files.
+fn windows_path_of(distribution: &str, path: &str) -> Option<String> {
+	if !path.starts_with('/') {
+		return None;
+	}
+	if let Some(rest) = path.strip_prefix("/mnt/") {
+		let (drive, rest) = rest.split_once('/').unwrap_or((rest, ""));
+		let mut letters = drive.chars();
+		if let (Some(letter), None) = (letters.next(), letters.next()) {
+			if letter.is_ascii_alphabetic() {
+				return Some(format!(
+					"{}:\\{}",
+					letter.to_ascii_uppercase(),
+					rest.replace('/', "\\")
+				));
+			}
+		}
+	}
+	Some(format!(
+		"\\\\wsl$\\{}{}",
+		distribution,
+		path.replace('/', "\\")
+	))
+}
+
+/// Asks the distribution's `wslpath` to convert `path`, `-u` giving the WSL
+/// form and `-w` the Windows one.
+fn wslpath(distribution: &str, flag: &str, path: &str) -> Result<String> {
+	let output = WslCommand::new(distribution, "wslpath")
+		.args([flag, path])
+		.output()?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"wslpath failed: {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `path` as seen inside `distribution`. Anything neither mapping nor
+/// `wslpath` understands is returned as it is.
+pub fn to_wsl_path(distribution: &str, path: &str) -> String {
+	wsl_path_of(distribution, path)
+		.or_else(|| wslpath(distribution, "-u", path).ok())
+		.unwrap_or_else(|| path.to_string())
+}
+
+/// `path`, a path inside `distribution`, as seen from Windows.
+pub fn to_windows_path(distribution: &str, path: &str) -> String {
+	windows_path_of(distribution, path)
+		.or_else(|| wslpath(distribution, "-w", path).ok())
+		.unwrap_or_else(|| path.to_string())
+}
+
+/// Where `path`, a path inside `distribution`, can be read from Windows.
+pub fn host_path(distribution: &str, path: &str) -> PathBuf {
+	PathBuf::from(to_windows_path(distribution, path))
+}
+
+/// A program run inside a WSL distribution. Arguments reach it as argv
+/// through `wsl --exec`, which skips the distribution's shell: otherwise
+/// `$`, backticks and globs in paths or commit messages would be expanded.
+#[derive(Debug, Clone)]
+pub struct WslCommand {
+	distribution: String,
+	directory: Option<String>,
+	login: bool,
+	program: String,
+	args: Vec<String>,
+}
+
+// Only Windows has WSL, so elsewhere most of this goes unused
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+impl WslCommand {
+	pub fn new(distribution: &str, program: &str) -> Self {
+		Self {
+			distribution: distribution.to_string(),
+			directory: None,
+			login: false,
+			program: program.to_string(),
+			args: Vec::new(),
+		}
+	}
+
+	/// Runs `script` with bash, for work that needs a pipeline or fallback.
+	/// Arguments added afterwards are the script's `$1`, `$2`, ...; paths
+	/// must be passed that way rather than formatted into the script.
+	pub fn script(distribution: &str, script: &str) -> Self {
+		let mut cmd = Self::new(distribution, "bash");
+		cmd.args(["-c", script, "bash"]);
+		cmd
+	}
+
+	/// Sets the directory to run in, a path in either form.
+	pub fn current_dir(&mut self, directory: &str) -> &mut Self {
+		self.directory = Some(to_wsl_path(&self.distribution, directory));
+		self
+	}
+
+	/// Starts the program from a login shell, so tools installed under `~`
+	/// are on the PATH.
+	pub fn login(&mut self) -> &mut Self {
+		self.login = true;
+		self
+	}
+
+	pub fn arg(&mut self, arg: impl AsRef<str>) -> &mut Self {
+		self.args.push(arg.as_ref().to_string());
+		self
+	}
+
+	pub fn args<I, S>(&mut self, args: I) -> &mut Self
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<str>,
+	{
+		self.args
+			.extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+		self
+	}
+
+	/// The arguments to `wsl.exe`.
+	fn wsl_args(&self) -> Vec<String> {
+		let mut wsl_args = vec!["-d".to_string(), self.distribution.clone()];
+		if let Some(directory) = &self.directory {
+			wsl_args.extend(["--cd".to_string(), directory.clone()]);
+		}
+		wsl_args.push("--exec".to_string());
+		if self.login {
+			// The login shell execs the program with the arguments untouched
+			wsl_args.extend(["bash", "-lc", "exec \"$0\" \"$@\""].map(str::to_string));
+		}
+		wsl_args.push(self.program.clone());
+		wsl_args.extend(self.args.iter().cloned());
+		wsl_args
+	}
+
+	pub fn build(&self) -> Command {
+		let mut cmd = Command::new("wsl");
+		cmd.args(self.wsl_args());
+		cmd
+	}
+
+	/// Builds the command for running in a terminal.
+	pub fn build_pty(&self) -> CommandBuilder {
+		let mut cmd = CommandBuilder::new("wsl");
+		cmd.args(self.wsl_args());
+		cmd
+	}
+
+	pub fn output(&self) -> std::io::Result<Output> {
+		self.build().output()
+	}
+}
+
+/// Converts `path` between its Windows and WSL forms for `distribution`.
+#[tauri::command]
+pub async fn translate_wsl_path(
+	path: String,
+	to: PathForm,
+	distribution: String,
+) -> Result<String, String> {
+	tauri::async_runtime::spawn_blocking(move || match to {
+		PathForm::Wsl => to_wsl_path(&distribution, &path),
+		PathForm::Windows => to_windows_path(&distribution, &path),
+	})
+	.await
+	.map_err(|e| e.to_string())
+}
@@ -7,6 +7,7 @@ import { GitProject } from "./types/GitProject";
 import { useStore } from "./state";
 import { BackgroundAgentsList } from "./components/BackgroundAgentsList";
 import { BackgroundAgentTerminalView } from "./components/BackgroundAgentTerminalView";
+import { detectBootstrap, runBootstrap } from "./bindings/bootstrap";
 import { div } from "framer-motion/client";
 
 const GitProjectView: React.FC<{}> = ({ }) => {
@@ -101,6 +102,21 @@ const GitProjectView: React.FC<{}> = ({ }) => {
 		});
 	};
 
+	// Set the project up when it opens, if its bootstrap manifest asks for it
+	useEffect(() => {
+		if (!selectedGitProject) return;
+		const root = selectedGitProject.root;
+		detectBootstrap(root)
+			.then(plan => {
+				if (plan.runOnOpen && plan.steps.length > 0) {
+					return runBootstrap(root).then(jobId => {
+						console.log('Started project setup, job ID:', jobId);
+					});
+				}
+			})
+			.catch(error => console.error('Failed to set up project:', error));
+	}, [selectedGitProject?.id]);
+
 	// Close context menu when clicking outside
 	useEffect(() => {
 		const handleClickOutside = (event: MouseEvent) => {
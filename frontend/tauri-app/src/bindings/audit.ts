@@ -0,0 +1,50 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { Ecosystem } from "./dependencies";
+import type { OsSession } from "./os";
+
+export type AdvisorySeverity = "low" | "moderate" | "medium" | "high" | "critical";
+
+export interface Vulnerability {
+	id: string; // e.g. "RUSTSEC-2023-0071" or "GHSA-..."
+	aliases: string[];
+	summary: string;
+	severity: AdvisorySeverity | null;
+	cvss: string | null; // CVSS vector
+	fixedVersions: string[];
+	informational: string | null; // e.g. "unmaintained" for RustSec notices
+	url: string | null;
+}
+
+export interface VulnerablePackage {
+	ecosystem: Ecosystem;
+	name: string;
+	version: string;
+	lockfile: string;
+	vulnerabilities: Vulnerability[];
+}
+
+export interface AuditReport {
+	lockfiles: string[];
+	packagesScanned: number;
+	vulnerable: VulnerablePackage[];
+	errors: string[]; // databases that couldn't be queried
+	generatedAt: number; // ms since epoch
+}
+
+// Payload of the audit-updated event
+export interface AuditUpdated {
+	directory: string;
+	report: AuditReport;
+}
+
+// Starts an audit job over the project's lockfiles and returns its id; the
+// report arrives as audit-updated
+export function auditDependencies(osSession: OsSession): Promise<string> {
+	return invoke<string>("audit_dependencies", { osSession });
+}
+
+export function getAuditReport(
+	osSession: OsSession,
+): Promise<AuditReport | null> {
+	return invoke<AuditReport | null>("get_audit_report", { osSession });
+}
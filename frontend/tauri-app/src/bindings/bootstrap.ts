@@ -0,0 +1,39 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface BootstrapStep {
+	id: string; // e.g. "node", "cargo", "venv", "pip" or "manifest:0"
+	name: string;
+	command: string; // run from the project root
+	source: "detected" | "manifest";
+	reason: string; // e.g. "node_modules is missing"
+}
+
+export interface BootstrapPlan {
+	steps: BootstrapStep[];
+	runOnOpen: boolean; // run_on_open in .ariana/bootstrap.toml
+	manifestError: string | null;
+}
+
+// Payload of the bootstrap-output event
+export interface BootstrapOutput {
+	jobId: string;
+	stepId: string;
+	stream: "stdout" | "stderr";
+	line: string;
+}
+
+export function detectBootstrap(osSession: OsSession): Promise<BootstrapPlan> {
+	return invoke<BootstrapPlan>("detect_bootstrap", { osSession });
+}
+
+// Starts the needed steps, or only stepIds, as one job; returns the job id
+export function runBootstrap(
+	osSession: OsSession,
+	stepIds?: string[],
+): Promise<string> {
+	return invoke<string>("run_bootstrap", {
+		osSession,
+		stepIds: stepIds ?? null,
+	});
+}
@@ -0,0 +1,27 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface LineCounts {
+	code: number;
+	comments: number;
+	blanks: number;
+	bytes: number;
+}
+
+export interface LanguageStats extends LineCounts {
+	language: string; // e.g. "Rust" or "TypeScript"
+	files: number;
+}
+
+export interface WorkspaceStats extends LineCounts {
+	root: string;
+	languages: LanguageStats[]; // most code first
+	files: number;
+	otherFiles: number; // unknown languages and files too large to count
+	recounted: number; // files read again because they changed
+}
+
+// Cached per workspace; only files changed since the last call are read
+export function getWorkspaceStats(osSession: OsSession): Promise<WorkspaceStats> {
+	return invoke<WorkspaceStats>("get_workspace_stats", { osSession });
+}
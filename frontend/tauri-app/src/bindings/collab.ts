@@ -0,0 +1,87 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface CollabMember {
+	memberId: number;
+	accountId: string;
+	email: string;
+}
+
+// A terminal another member shares; it can be watched but not typed into
+export interface RemoteTerminal {
+	owner: number; // member id
+	terminal: string;
+	title: string;
+	scrollback: string; // recent output, for late joiners
+}
+
+// Payload of the collab-status event
+export interface CollabStatus {
+	roomId: string | null;
+	connected: boolean;
+	memberId: number | null;
+	members: CollabMember[];
+	documents: string[]; // absolute paths synced with the room
+	sharedTerminals: string[]; // local terminal ids streamed to the room
+	remoteTerminals: RemoteTerminal[];
+	error: string | null;
+}
+
+// Payload of the collab-cursor event; offsets are UTF-16 code units
+export interface RemoteCursor {
+	memberId: number;
+	path: string;
+	anchor: number;
+	head: number;
+}
+
+// Payload of the collab-terminal-output event
+export interface RemoteTerminalOutput {
+	owner: number;
+	terminal: string;
+	data: string;
+}
+
+// Creates a room on the backend and joins it; share the returned id to invite
+export function createCollabRoom(osSession: OsSession): Promise<string> {
+	return invoke<string>("create_collab_room", { osSession });
+}
+
+// Documents already shared in the room are opened when they exist locally
+export function joinCollabRoom(
+	roomId: string,
+	osSession: OsSession,
+): Promise<void> {
+	return invoke<void>("join_collab_room", { roomId, osSession });
+}
+
+export function leaveCollabRoom(): Promise<void> {
+	return invoke<void>("leave_collab_room");
+}
+
+export function getCollabStatus(): Promise<CollabStatus> {
+	return invoke<CollabStatus>("get_collab_status");
+}
+
+export function shareCollabDocument(path: string): Promise<void> {
+	return invoke<void>("share_collab_document", { path });
+}
+
+export function updateCollabCursor(
+	path: string,
+	anchor: number,
+	head: number,
+): Promise<void> {
+	return invoke<void>("update_collab_cursor", { path, anchor, head });
+}
+
+export function shareCollabTerminal(
+	terminalId: string,
+	title: string,
+): Promise<void> {
+	return invoke<void>("share_collab_terminal", { terminalId, title });
+}
+
+export function unshareCollabTerminal(terminalId: string): Promise<void> {
+	return invoke<void>("unshare_collab_terminal", { terminalId });
+}
@@ -0,0 +1,74 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export type CoverageTool = "cargo-llvm-cov" | "c8" | "coverage-py";
+
+export interface LineHits {
+	line: number; // one-based
+	hits: number;
+}
+
+export interface FileCoverage {
+	path: string; // relative to the project root, or absolute outside it
+	lines: LineHits[];
+	linesFound: number;
+	linesHit: number;
+}
+
+export interface CoverageSummary {
+	source: string; // the tool or imported report
+	files: number;
+	linesFound: number;
+	linesHit: number;
+	percent: number; // 0 to 100
+	generatedAt: number; // ms since the epoch
+}
+
+export interface CanvasCoverage {
+	canvasId: string;
+	name: string;
+	branch: string;
+	summary: CoverageSummary | null; // null until measured
+}
+
+// Payload of the coverage-updated event
+export interface CoverageUpdated {
+	directory: string;
+	summary: CoverageSummary;
+}
+
+// Starts the project's tests under a coverage tool as a job; returns the job
+// id. The tool is picked from the project's files when omitted
+export function runCoverage(
+	osSession: OsSession,
+	tool?: CoverageTool,
+): Promise<string> {
+	return invoke<string>("run_coverage", { osSession, tool: tool ?? null });
+}
+
+// Reads an LCOV or Cobertura report, relative to the project or absolute
+export function importCoverage(
+	osSession: OsSession,
+	path: string,
+): Promise<CoverageSummary> {
+	return invoke<CoverageSummary>("import_coverage", { osSession, path });
+}
+
+export function getFileCoverage(
+	osSession: OsSession,
+	path: string,
+): Promise<FileCoverage | null> {
+	return invoke<FileCoverage | null>("get_file_coverage", { osSession, path });
+}
+
+export function getCoverageSummary(
+	osSession: OsSession,
+): Promise<CoverageSummary | null> {
+	return invoke<CoverageSummary | null>("get_coverage_summary", { osSession });
+}
+
+export function getCanvasCoverage(
+	projectRoot: OsSession,
+): Promise<CanvasCoverage[]> {
+	return invoke<CanvasCoverage[]>("get_canvas_coverage", { projectRoot });
+}
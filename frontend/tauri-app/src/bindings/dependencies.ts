@@ -0,0 +1,89 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export type Ecosystem = "cargo" | "npm" | "python";
+export type DependencyKind = "normal" | "dev" | "build" | "peer" | "optional";
+
+export interface Dependency {
+	name: string;
+	kind: DependencyKind;
+	section: string; // e.g. "dev-dependencies" or "project.optional-dependencies.test"
+	requirement: string | null; // as written, e.g. "^1.2"
+	source: string | null; // set for path, git and workspace dependencies
+	installed: string | null;
+	latest: string | null; // only when checkLatest was asked
+	outdated: boolean;
+}
+
+export interface ManifestDependencies {
+	ecosystem: Ecosystem;
+	path: string;
+	dependencies: Dependency[];
+	error: string | null;
+}
+
+export interface ManifestEdit {
+	path: string;
+	original: string;
+	updated: string;
+	written: boolean; // false for dry runs and edits that change nothing
+}
+
+// Lists the dependencies of Cargo.toml, package.json and pyproject.toml;
+// checkLatest asks crates.io, npm and PyPI too, cached for an hour
+export function listDependencies(
+	osSession: OsSession,
+	checkLatest?: boolean,
+): Promise<ManifestDependencies[]> {
+	return invoke<ManifestDependencies[]>("list_dependencies", {
+		osSession,
+		checkLatest: checkLatest ?? null,
+	});
+}
+
+// Without a version, the latest release is used
+export function addDependency(
+	osSession: OsSession,
+	ecosystem: Ecosystem,
+	name: string,
+	options: { version?: string; kind?: DependencyKind; dryRun?: boolean } = {},
+): Promise<ManifestEdit> {
+	return invoke<ManifestEdit>("add_dependency", {
+		osSession,
+		ecosystem,
+		name,
+		version: options.version ?? null,
+		kind: options.kind ?? null,
+		dryRun: options.dryRun ?? null,
+	});
+}
+
+export function removeDependency(
+	osSession: OsSession,
+	ecosystem: Ecosystem,
+	name: string,
+	options: { kind?: DependencyKind; dryRun?: boolean } = {},
+): Promise<ManifestEdit> {
+	return invoke<ManifestEdit>("remove_dependency", {
+		osSession,
+		ecosystem,
+		name,
+		kind: options.kind ?? null,
+		dryRun: options.dryRun ?? null,
+	});
+}
+
+export function upgradeDependency(
+	osSession: OsSession,
+	ecosystem: Ecosystem,
+	name: string,
+	options: { version?: string; dryRun?: boolean } = {},
+): Promise<ManifestEdit> {
+	return invoke<ManifestEdit>("upgrade_dependency", {
+		osSession,
+		ecosystem,
+		name,
+		version: options.version ?? null,
+		dryRun: options.dryRun ?? null,
+	});
+}
@@ -0,0 +1,81 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export type BuildTool = "cargo" | "tsc" | "eslint";
+
+// Zero-based, as in LSP
+export interface Position {
+	line: number;
+	character: number;
+}
+
+export interface Range {
+	start: Position;
+	end: Position;
+}
+
+export interface Location {
+	path: string;
+	range: Range;
+}
+
+// Shaped like an LSP Diagnostic; severity is 1 error, 2 warning, 3 info,
+// 4 hint
+export interface Diagnostic {
+	range: Range;
+	severity: 1 | 2 | 3 | 4;
+	code: string | null;
+	source: string; // "rustc", "tsc" or "eslint"
+	message: string;
+	relatedInformation: { location: Location; message: string }[];
+}
+
+export interface FileDiagnostics {
+	path: string;
+	diagnostics: Diagnostic[];
+}
+
+// Payload of the problems-updated event, with every problem now known for
+// the directory
+export interface ProblemsUpdated {
+	directory: string;
+	files: FileDiagnostics[];
+}
+
+// Parses build output into the Problems feed; with tool set, that tool's old
+// problems are cleared even when the output has none
+export function parseBuildOutput(
+	osSession: OsSession,
+	output: string,
+	tool?: BuildTool,
+): Promise<FileDiagnostics[]> {
+	return invoke<FileDiagnostics[]>("parse_build_output", {
+		osSession,
+		output,
+		tool: tool ?? null,
+	});
+}
+
+// Reads build problems from the last lines of a terminal
+export function collectTerminalProblems(
+	osSession: OsSession,
+	id: string,
+	lineCount?: number,
+): Promise<FileDiagnostics[]> {
+	return invoke<FileDiagnostics[]>("collect_terminal_problems", {
+		osSession,
+		id,
+		lineCount: lineCount ?? null,
+	});
+}
+
+export function getProblems(osSession: OsSession): Promise<FileDiagnostics[]> {
+	return invoke<FileDiagnostics[]>("get_problems", { osSession });
+}
+
+export function clearProblems(
+	osSession: OsSession,
+	tool?: BuildTool,
+): Promise<void> {
+	return invoke<void>("clear_problems", { osSession, tool: tool ?? null });
+}
@@ -0,0 +1,25 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export type IndentStyle = "tab" | "space";
+export type EndOfLine = "lf" | "crlf" | "cr";
+export type Charset = "latin1" | "utf-8" | "utf-8-bom" | "utf-16be" | "utf-16le";
+
+// Properties the .editorconfig files above a file give it; null where none
+// says. Saving a document applies the line ending, charset, trailing
+// whitespace and final newline ones
+export interface EditorConfig {
+	indentStyle: IndentStyle | null;
+	indentSize: number | null;
+	tabWidth: number | null;
+	endOfLine: EndOfLine | null;
+	charset: Charset | null;
+	trimTrailingWhitespace: boolean | null;
+	insertFinalNewline: boolean | null;
+	maxLineLength: number | null;
+	sources: string[]; // .editorconfig files read, nearest last
+}
+
+// The file needn't exist yet
+export function resolveEditorConfig(path: string): Promise<EditorConfig> {
+	return invoke<EditorConfig>("resolve_editorconfig", { path });
+}
@@ -0,0 +1,23 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface Toolchain {
+	name: string; // "node", "npm", "python", "rustc", "cargo", "go" or "git"
+	program: string | null; // e.g. "python3"; null when not installed
+	path: string | null;
+	version: string | null; // e.g. "1.80.0"
+	details: string | null; // first line of `--version`
+}
+
+export interface EnvironmentReport {
+	toolchains: Toolchain[];
+	shell: string | null;
+	system: string | null;
+	env: Record<string, string>; // a fixed allowlist, never secrets
+}
+
+export function inspectOsSession(
+	osSession: OsSession,
+): Promise<EnvironmentReport> {
+	return invoke<EnvironmentReport>("inspect_os_session", { osSession });
+}
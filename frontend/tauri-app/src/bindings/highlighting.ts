@@ -0,0 +1,40 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface HighlightSpan {
+	text: string;
+	color: string; // #rrggbb or #rrggbbaa
+	bold?: boolean;
+	italic?: boolean;
+	underline?: boolean;
+}
+
+export interface HighlightedFile {
+	language: string; // e.g. "Rust" or "Plain Text"
+	theme: string;
+	background: string | null;
+	foreground: string | null;
+	lines: HighlightSpan[][]; // without line terminators
+	truncated: boolean; // the file goes on past the last line
+	totalSize: number;
+}
+
+// Highlights the first maxBytes (default 64 KiB, at most 1 MiB) of a text
+// file for read-only previews; binary files are rejected
+export function highlightFile(
+	osSession: OsSession,
+	path: string,
+	theme?: string,
+	maxBytes?: number,
+): Promise<HighlightedFile> {
+	return invoke<HighlightedFile>("highlight_file", {
+		osSession,
+		path,
+		theme: theme ?? null,
+		maxBytes: maxBytes ?? null,
+	});
+}
+
+export function listHighlightThemes(): Promise<string[]> {
+	return invoke<string[]>("list_highlight_themes");
+}
@@ -0,0 +1,59 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { Ecosystem } from "./dependencies";
+import type { OsSession } from "./os";
+
+export type LicenseCategory =
+	| "permissive"
+	| "weak-copyleft"
+	| "strong-copyleft"
+	| "network-copyleft"
+	| "unknown";
+export type LicenseVerdict = "allowed" | "warning" | "denied";
+
+// Lists take SPDX ids or categories; allow wins over deny and warn
+export interface LicensePolicy {
+	deny: string[];
+	warn: string[];
+	allow: string[];
+	ignore: string[]; // package names left out of the check
+}
+
+export interface PackageLicense {
+	ecosystem: Ecosystem;
+	name: string;
+	version: string;
+	license: string | null; // SPDX expression when the package gives one
+	category: LicenseCategory;
+	verdict: LicenseVerdict;
+	ignored: boolean;
+}
+
+export interface LicenseSummary {
+	license: string | null;
+	category: LicenseCategory;
+	verdict: LicenseVerdict;
+	packages: number;
+}
+
+export interface LicenseReport {
+	packages: PackageLicense[]; // denied first, then warnings
+	licenses: LicenseSummary[];
+	denied: number;
+	warnings: number;
+	passed: boolean;
+	policy: LicensePolicy;
+	policyError: string | null; // .ariana/licenses.toml couldn't be read
+	errors: string[];
+}
+
+// Without a policy, the project's .ariana/licenses.toml is used, or the
+// default that denies strong and network copyleft
+export function scanLicenses(
+	osSession: OsSession,
+	policy?: Partial<LicensePolicy>,
+): Promise<LicenseReport> {
+	return invoke<LicenseReport>("scan_licenses", {
+		osSession,
+		policy: policy ?? null,
+	});
+}
@@ -0,0 +1,30 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+import type { LocalApiScope } from "./settings";
+
+// The localhost server external tools use; enabled under localApi in settings
+export interface LocalApiStatus {
+	running: boolean;
+	port: number | null;
+	token: string | null; // sent as "Authorization: Bearer <token>"
+	scopes: LocalApiScope[];
+	discoveryFile: string | null; // port and token, for tools to read
+	error: string | null; // why the server couldn't start
+}
+
+// Payload of the local-api-start-agent event; the frontend starts the agent
+export interface LocalApiAgentRequest {
+	requestId: string;
+	osSession: OsSession;
+	prompt: string;
+	canvasId: string | null;
+}
+
+export function getLocalApiStatus(): Promise<LocalApiStatus> {
+	return invoke<LocalApiStatus>("get_local_api_status");
+}
+
+// Restarts the server with a new token; the old one stops working
+export function regenerateLocalApiToken(): Promise<LocalApiStatus> {
+	return invoke<LocalApiStatus>("regenerate_local_api_token");
+}
@@ -0,0 +1,32 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface RenderedMarkdown {
+	// Safe to set as innerHTML: raw HTML in the source is escaped and only
+	// http, https and mailto links are kept. Relative links carry the file
+	// they point to in data-path; local images are embedded.
+	html: string;
+	title: string | null; // text of the first heading
+}
+
+export function renderMarkdown(
+	markdown: string,
+	options: {
+		osSession?: OsSession;
+		documentPath?: string; // relative links resolve against its directory
+		projectRoot?: string; // links starting with / resolve against it
+	} = {},
+): Promise<RenderedMarkdown> {
+	return invoke<RenderedMarkdown>("render_markdown", {
+		markdown,
+		osSession: options.osSession ?? null,
+		documentPath: options.documentPath ?? null,
+		projectRoot: options.projectRoot ?? null,
+	});
+}
+
+// Stylesheet for the hl- classes on highlighted code blocks; see
+// listHighlightThemes for the theme names
+export function markdownHighlightCss(theme?: string): Promise<string> {
+	return invoke<string>("markdown_highlight_css", { theme: theme ?? null });
+}
@@ -0,0 +1,42 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+// Lines are zero-based, as in LSP
+export interface FoldRange {
+	startLine: number;
+	endLine: number;
+	kind: "comment" | "imports" | null;
+}
+
+export interface OutlineItem {
+	name: string;
+	// "function", "method", "class", "struct", "enum", "interface",
+	// "trait", "impl", "module", "type", "constant" or "macro"
+	kind: string;
+	startLine: number;
+	endLine: number;
+	line: number; // position of the name
+	column: number; // UTF-16
+	children: OutlineItem[];
+}
+
+export interface FileOutline {
+	language: string;
+	folds: FoldRange[];
+	symbols: OutlineItem[];
+}
+
+// Tree-sitter folds and outline for Rust, JavaScript, TypeScript, Python
+// and Go, for when no language server provides them. Pass content to
+// outline unsaved editor text instead of the file on disk.
+export function getFileOutline(
+	osSession: OsSession,
+	path: string,
+	content?: string,
+): Promise<FileOutline> {
+	return invoke<FileOutline>("get_file_outline", {
+		osSession,
+		path,
+		content: content ?? null,
+	});
+}
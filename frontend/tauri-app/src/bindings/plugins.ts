@@ -0,0 +1,95 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+// What a plugin may do besides answering its own commands and data
+// sources; enabling a plugin grants what its manifest declares
+export type PluginCapability =
+	| "readFiles"
+	| "writeFiles"
+	| "listDirectories"
+	| "diagnostics"
+	| "fileEvents";
+
+export interface PluginCommand {
+	id: string;
+	title: string;
+}
+
+// Data a plugin computes for a panel
+export interface PluginDataSource {
+	id: string;
+	title: string;
+}
+
+// Also the payload of the plugins-changed event, as a list
+export interface PluginInfo {
+	id: string;
+	name: string;
+	version: string;
+	description: string | null;
+	main: string; // module, relative to directory
+	capabilities: PluginCapability[];
+	commands: PluginCommand[];
+	dataSources: PluginDataSource[];
+	directory: string;
+	enabled: boolean;
+	error: string | null; // failed to load, or last call failed
+}
+
+// Payload of the plugin-notification event
+export interface PluginNotification {
+	pluginId: string;
+	message: string;
+}
+
+// Palette actions of plugin commands have ids "plugin.<pluginId>.<commandId>"
+export const PLUGIN_ACTION_PREFIX = "plugin.";
+
+export function listPlugins(): Promise<PluginInfo[]> {
+	return invoke<PluginInfo[]>("list_plugins");
+}
+
+// Scans the plugins directory again, e.g. after installing one
+export function reloadPlugins(): Promise<PluginInfo[]> {
+	return invoke<PluginInfo[]>("reload_plugins");
+}
+
+export function setPluginEnabled(
+	pluginId: string,
+	enabled: boolean,
+): Promise<PluginInfo[]> {
+	return invoke<PluginInfo[]>("set_plugin_enabled", { pluginId, enabled });
+}
+
+export function getPluginsDirectory(): Promise<string> {
+	return invoke<string>("get_plugins_directory");
+}
+
+// osSession is the workspace the plugin's file operations are confined to
+export function runPluginCommand(
+	pluginId: string,
+	commandId: string,
+	osSession?: OsSession,
+	args?: unknown,
+): Promise<unknown> {
+	return invoke<unknown>("run_plugin_command", {
+		pluginId,
+		commandId,
+		osSession: osSession ?? null,
+		args: args ?? null,
+	});
+}
+
+export function queryPluginDataSource(
+	pluginId: string,
+	sourceId: string,
+	osSession?: OsSession,
+	params?: unknown,
+): Promise<unknown> {
+	return invoke<unknown>("query_plugin_data_source", {
+		pluginId,
+		sourceId,
+		osSession: osSession ?? null,
+		params: params ?? null,
+	});
+}
@@ -0,0 +1,79 @@
+import { invoke } from "@tauri-apps/api/core";
+
+// A project, or one of its canvases, open on one of the account's devices
+export interface PresenceEntry {
+	device_id: string;
+	device_name: string;
+	project_id: string;
+	canvas_id: string | null;
+	updated_at: string;
+	current_device: boolean; // reported by this installation
+}
+
+export interface OpenEntry {
+	project_id: string;
+	canvas_id: string | null;
+}
+
+// Advisory lock on a canvas branch, held by one device of the account
+export interface BranchLock {
+	project_id: string;
+	branch: string;
+	device_id: string;
+	device_name: string;
+	canvas_id: string | null;
+	acquired_at: string;
+	expires_at: string;
+	current_device: boolean; // held by this installation
+}
+
+// lock is ours when acquired, the holder's otherwise
+export interface BranchLockOutcome {
+	acquired: boolean;
+	lock: BranchLock;
+}
+
+// Replaces what this device reports as open; send it as a heartbeat at
+// least every minute, presence expires after two
+export function updatePresence(open: OpenEntry[]): Promise<PresenceEntry[]> {
+	return invoke<PresenceEntry[]>("update_presence", { open });
+}
+
+export function listPresence(): Promise<PresenceEntry[]> {
+	return invoke<PresenceEntry[]>("list_presence");
+}
+
+export function clearPresence(): Promise<void> {
+	return invoke<void>("clear_presence");
+}
+
+export function listBranchLocks(projectId: string): Promise<BranchLock[]> {
+	return invoke<BranchLock[]>("list_branch_locks", { projectId });
+}
+
+// Take the lock before running an agent on a canvas branch and refresh it
+// while the agent runs; it expires after ttlSeconds (5 minutes by default)
+export function acquireBranchLock(
+	projectId: string,
+	branch: string,
+	options: { canvasId?: string; ttlSeconds?: number } = {},
+): Promise<BranchLockOutcome> {
+	return invoke<BranchLockOutcome>("acquire_branch_lock", {
+		projectId,
+		branch,
+		canvasId: options.canvasId ?? null,
+		ttlSeconds: options.ttlSeconds ?? null,
+	});
+}
+
+export function releaseBranchLock(
+	projectId: string,
+	branch: string,
+	force?: boolean,
+): Promise<void> {
+	return invoke<void>("release_branch_lock", {
+		projectId,
+		branch,
+		force: force ?? null,
+	});
+}
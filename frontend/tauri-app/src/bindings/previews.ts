@@ -0,0 +1,41 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface Thumbnail {
+	dataUrl: string; // data:image/png;base64,...
+	width: number;
+	height: number;
+	originalWidth: number;
+	originalHeight: number;
+}
+
+// Extensions the backend can render; anything else fails
+export const PREVIEWABLE_EXTENSIONS = [
+	"png",
+	"jpg",
+	"jpeg",
+	"gif",
+	"webp",
+	"bmp",
+	"ico",
+	"svg",
+];
+
+export function isPreviewable(path: string): boolean {
+	const extension = path.split(".").pop()?.toLowerCase() ?? "";
+	return path.includes(".") && PREVIEWABLE_EXTENSIONS.includes(extension);
+}
+
+// Renders a PNG no larger than maxSize (default 256) on either side,
+// so the webview never loads the full-size original
+export function generateThumbnail(
+	osSession: OsSession,
+	path: string,
+	maxSize?: number,
+): Promise<Thumbnail> {
+	return invoke<Thumbnail>("generate_thumbnail", {
+		osSession,
+		path,
+		maxSize: maxSize ?? null,
+	});
+}
@@ -0,0 +1,68 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export type HookEvent =
+	| "project-open"
+	| "pre-commit"
+	| "post-merge"
+	| "agent-task-complete";
+
+// One entry of .ariana/hooks.toml, snake_case like the manifest
+export interface ProjectHook {
+	event: HookEvent;
+	name: string;
+	command: string; // run from the project root
+	timeout_seconds: number | null; // 300 by default
+	blocking: boolean | null; // a failing pre-commit hook stops the commit
+}
+
+export interface HookProblem {
+	index: number | null; // hook position, null for the whole manifest
+	message: string;
+}
+
+export interface ProjectHooks {
+	hooks: ProjectHook[];
+	valid: boolean; // hooks of a manifest with problems don't run
+	problems: HookProblem[];
+	error: string | null;
+}
+
+// Payload of the project-hook-output event
+export interface HookOutput {
+	directory: string;
+	event: HookEvent;
+	name: string;
+	stream: "stdout" | "stderr";
+	line: string;
+}
+
+export interface HookRun {
+	event: HookEvent;
+	name: string;
+	command: string;
+	exitCode: number | null;
+	timedOut: boolean;
+	durationMs: number;
+	output: string; // both streams, cut at 64 KiB
+	error: string | null;
+}
+
+export function listProjectHooks(osSession: OsSession): Promise<ProjectHooks> {
+	return invoke<ProjectHooks>("list_project_hooks", { osSession });
+}
+
+// pre-commit and post-merge hooks run with git_commit and merges; call
+// this for project-open and agent-task-complete. context entries reach the
+// hooks as ARIANA_<KEY> environment variables
+export function runProjectHooks(
+	osSession: OsSession,
+	event: HookEvent,
+	context?: Record<string, string>,
+): Promise<HookRun[]> {
+	return invoke<HookRun[]>("run_project_hooks", {
+		osSession,
+		event,
+		context: context ?? null,
+	});
+}
@@ -0,0 +1,77 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export interface AppearanceSettings {
+	theme: string;
+	showOnboarding: boolean;
+}
+
+export interface EditorSettings {
+	fontSize: number;
+	tabSize: number;
+	insertSpaces: boolean;
+	wordWrap: boolean;
+	formatOnSave: boolean;
+	autoSaveDelayMs: number | null;
+}
+
+export interface TerminalSettings {
+	fontSize: number;
+	copyOnSelect: boolean;
+}
+
+export interface AiSettings {
+	defaultProvider: string | null;
+	defaultModel: string | null;
+}
+
+// files: open files; tasks: run project tasks; diagnostics: read Problems;
+// agents: start agents
+export type LocalApiScope = "files" | "tasks" | "diagnostics" | "agents";
+
+export interface LocalApiSettings {
+	enabled: boolean;
+	port: number; // 0 picks a free port
+	scopes: LocalApiScope[];
+}
+
+export interface Settings {
+	version: number;
+	appearance: AppearanceSettings;
+	editor: EditorSettings;
+	terminal: TerminalSettings;
+	ai: AiSettings;
+	localApi: LocalApiSettings;
+}
+
+export interface SettingsProblem {
+	path: string; // dotted, e.g. "editor.tabSize"
+	message: string;
+}
+
+export interface SettingsState {
+	settings: Settings;
+	problems: SettingsProblem[];
+}
+
+// Payload of the "settings-changed" event
+export interface SettingsChanged {
+	settings: Settings;
+	changed: string[];
+}
+
+type DeepPartial<T> = { [K in keyof T]?: DeepPartial<T[K]> };
+
+export function getSettings(): Promise<SettingsState> {
+	return invoke<SettingsState>("get_settings");
+}
+
+// Rejects with every problem listed when the result doesn't validate
+export function updateSettings(
+	patch: DeepPartial<Omit<Settings, "version">>,
+): Promise<Settings> {
+	return invoke<Settings>("update_settings", { patch });
+}
+
+export function resetSettings(section?: keyof Settings): Promise<Settings> {
+	return invoke<Settings>("reset_settings", { section: section ?? null });
+}
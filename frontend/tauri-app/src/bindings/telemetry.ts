@@ -0,0 +1,60 @@
+import { invoke } from "@tauri-apps/api/core";
+
+// One usage event, exactly as uploaded; snake_case like the request body.
+// There is no account or installation id, session_id changes every launch
+export interface TelemetryEvent {
+	event_id: string;
+	session_id: string;
+	name: string; // like "palette.opened"
+	occurred_at: string;
+	properties: Record<string, boolean | number | string | null>;
+}
+
+// The body of one upload
+export interface TelemetryBatch {
+	app_version: string;
+	os: string;
+	arch: string;
+	events: TelemetryEvent[];
+}
+
+export interface TelemetryPreview {
+	optIn: boolean | null; // null until the user has been asked
+	endpoint: string;
+	batches: TelemetryBatch[]; // what the next upload would send
+	lastUploadAt: string | null;
+}
+
+export function showMyTelemetry(): Promise<TelemetryPreview> {
+	return invoke<TelemetryPreview>("show_my_telemetry");
+}
+
+// Names are lowercase letters, digits, "_" and "."; properties that could
+// identify someone (long strings, paths, URLs, emails) are dropped
+export function recordTelemetryEvent(
+	name: string,
+	properties?: Record<string, boolean | number | string | null>,
+): Promise<void> {
+	return invoke<void>("record_telemetry_event", {
+		name,
+		properties: properties ?? null,
+	});
+}
+
+export function getTelemetryOptIn(): Promise<boolean | null> {
+	return invoke<boolean | null>("get_telemetry_opt_in");
+}
+
+// Opting out deletes the queued events and stops recording
+export function setTelemetryOptIn(optIn: boolean): Promise<void> {
+	return invoke<void>("set_telemetry_opt_in", { optIn });
+}
+
+export function clearTelemetry(): Promise<void> {
+	return invoke<void>("clear_telemetry");
+}
+
+// Sends the queue now; requires opting in
+export function uploadTelemetry(): Promise<number> {
+	return invoke<number>("upload_telemetry");
+}
@@ -0,0 +1,85 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export interface ColorScheme {
+	name: string;
+	background: string;
+	foreground: string;
+	cursor: string;
+	selection: string;
+	ansi: string[]; // 16 colors, "#rrggbb"
+}
+
+export interface TerminalPalette extends ColorScheme {
+	dark: boolean;
+	extended: string[]; // 256 colors
+}
+
+export type SchemeFormat = "iterm2" | "windowsTerminal";
+
+const ANSI_INDEXES: Record<string, number> = {
+	Black: 0,
+	Red: 1,
+	Green: 2,
+	Yellow: 3,
+	Blue: 4,
+	Magenta: 5,
+	Cyan: 6,
+	White: 7,
+	BrightBlack: 8,
+	BrightRed: 9,
+	BrightGreen: 10,
+	BrightYellow: 11,
+	BrightBlue: 12,
+	BrightMagenta: 13,
+	BrightCyan: 14,
+	BrightWhite: 15,
+};
+
+// Resolves a LineItem color as serialized by the backend: "Red",
+// { Extended: 208 } or { Rgb: [r, g, b] }. Default colors give null.
+export function resolveTerminalColor(
+	color: string | { Extended?: number; Rgb?: [number, number, number] } | null | undefined,
+	palette: TerminalPalette,
+): string | null {
+	if (!color || color === "Default") return null;
+	if (typeof color === "string") {
+		const index = ANSI_INDEXES[color];
+		return index === undefined ? null : palette.extended[index];
+	}
+	if (color.Extended !== undefined) {
+		return palette.extended[color.Extended] ?? null;
+	}
+	if (color.Rgb !== undefined) {
+		return `#${color.Rgb.map((c) => c.toString(16).padStart(2, "0")).join("")}`;
+	}
+	return null;
+}
+
+export function getTerminalPalette(
+	theme: string,
+	minContrast?: number,
+): Promise<TerminalPalette> {
+	return invoke<TerminalPalette>("get_terminal_palette", {
+		theme,
+		minContrast: minContrast ?? null,
+	});
+}
+
+export function exportTerminalScheme(
+	theme: string,
+	format: SchemeFormat,
+): Promise<string> {
+	return invoke<string>("export_terminal_scheme", { theme, format });
+}
+
+export function importTerminalScheme(
+	content: string,
+	format: SchemeFormat,
+	name?: string,
+): Promise<TerminalPalette> {
+	return invoke<TerminalPalette>("import_terminal_scheme", {
+		content,
+		format,
+		name: name ?? null,
+	});
+}
@@ -0,0 +1,26 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { OsSession } from "./os";
+
+export interface ToolchainRequest {
+	tool: "node" | "rust" | "python";
+	version: string; // as written, e.g. "18", "lts/*" or "1.75.0"
+	file: string; // e.g. the project's .nvmrc
+	installed: string | null;
+	binDir: string | null; // put first on PATH for terminals, tasks and agents
+	error: string | null; // set when the version isn't installed
+}
+
+// Payload of the toolchain-missing event, emitted when a process starts
+// without a version its project asks for
+export interface ToolchainMissing {
+	directory: string;
+	request: ToolchainRequest;
+}
+
+// Versions pinned by .nvmrc, .node-version, rust-toolchain(.toml) and
+// .python-version; only resolved for local sessions
+export function detectToolchainVersions(
+	osSession: OsSession,
+): Promise<ToolchainRequest[]> {
+	return invoke<ToolchainRequest[]>("detect_toolchain_versions", { osSession });
+}
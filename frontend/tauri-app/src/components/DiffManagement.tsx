@@ -260,6 +260,24 @@ export default function DiffManagement({ onClose, initialState, onStateChange, m
       }
       
       const parsedFiles = diffService.parseDiff(diffText);
+
+      // Binary files have no hunks; fetch sizes and thumbnails instead when
+      // both sides are commits or the working tree
+      const binaryRefs: [string, string | undefined] | null =
+        targetCommit === 'UNSTAGED' || baseCommit === 'UNSTAGED' ? ['HEAD', undefined]
+        : targetCommit === 'STAGED' || baseCommit === 'STAGED' ? null
+        : baseBranch && targetBranch ? [baseCommit || baseBranch, targetCommit || targetBranch]
+        : ['HEAD', undefined];
+      if (binaryRefs) {
+        await Promise.all(parsedFiles.filter(file => file.binary).map(async file => {
+          try {
+            file.binaryChange = await diffService.getBinaryChange(file, binaryRefs[0], binaryRefs[1]);
+          } catch (err) {
+            console.error(`Failed to load binary change for ${file.filePath}:`, err);
+          }
+        }));
+      }
+
       const summary = diffService.categorizeChanges(parsedFiles);
       setDiffSummary(summary);
       setShowBranchSelection(false);
@@ -1658,6 +1676,71 @@ function highlightSearchTerm(text: string, searchTerm: string): JSX.Element {
 }
 
 // File Diff Viewer Component
+function formatBytes(bytes: number): string {
+  const units = ['B', 'KB', 'MB', 'GB'];
+  let value = Math.abs(bytes);
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit++;
+  }
+  return `${bytes < 0 ? '-' : ''}${unit === 0 ? value : value.toFixed(1)} ${units[unit]}`;
+}
+
+// Shown in place of hunks for binary files: sizes, blob hashes and, for
+// images, a before/after preview
+function BinaryChangeSummary({ file }: { file: GitDiffFile }) {
+  const change = file.binaryChange;
+  if (!change) {
+    return (
+      <div className="px-4 py-3 text-sm text-[var(--base-600)]">
+        Binary file changed
+      </div>
+    );
+  }
+
+  const sides = [
+    { label: 'Before', version: change.old },
+    { label: 'After', version: change.new },
+  ];
+  return (
+    <div className="px-4 py-3 text-sm">
+      <div className="flex items-center space-x-3 mb-3 text-[var(--base-700)]">
+        <span>Binary file{change.mimeType ? ` (${change.mimeType})` : ''}</span>
+        <span className={change.sizeDelta > 0 ? "text-green-600" : change.sizeDelta < 0 ? "text-red-600" : "text-[var(--base-600)]"}>
+          {change.sizeDelta > 0 ? '+' : ''}{formatBytes(change.sizeDelta)}
+        </span>
+      </div>
+      <div className="grid grid-cols-2 gap-4">
+        {sides.map(({ label, version }) => (
+          <div key={label} className="bg-[var(--base-250)] rounded p-3">
+            <div className="text-xs font-medium text-[var(--base-600)] mb-2">{label}</div>
+            {version ? (
+              <>
+                {version.thumbnail && (
+                  <img
+                    src={version.thumbnail}
+                    alt={`${file.filePath} (${label.toLowerCase()})`}
+                    className="max-h-64 max-w-full mb-2 rounded"
+                  />
+                )}
+                <div className="text-xs text-[var(--base-700)]">{formatBytes(version.size)}</div>
+                <div className="text-xs font-mono text-[var(--base-500)] truncate" title={version.hash}>
+                  {version.hash.slice(0, 12)}
+                </div>
+              </>
+            ) : (
+              <div className="text-xs text-[var(--base-500)]">
+                {label === 'Before' ? 'Added' : 'Deleted'}
+              </div>
+            )}
+          </div>
+        ))}
+      </div>
+    </div>
+  );
+}
+
 interface FileDiffViewerProps {
   file: GitDiffFile;
   currentHunkIndex?: number;
@@ -1791,6 +1874,8 @@ function FileDiffViewer({ file, currentHunkIndex = 0, searchQuery = "", onDiscar
         <div className="p-4">
           <div className="bg-[var(--base-200)] rounded-lg overflow-hidden">
 
+            {file.binary && <BinaryChangeSummary file={file} />}
+
             {file.hunks.map((hunk, hunkIndex) => (
               <div 
                 key={hunkIndex} 
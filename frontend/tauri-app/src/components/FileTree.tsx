@@ -2,6 +2,11 @@ import { invoke } from "@tauri-apps/api/core";
 import { getIcon } from "material-file-icons";
 import type React from "react";
 import { useEffect, useState } from "react";
+import {
+	generateThumbnail,
+	isPreviewable,
+	type Thumbnail,
+} from "../bindings/previews";
 
 interface FileNode {
 	name: string;
@@ -23,6 +28,19 @@ const FileTreeItem: React.FC<{
 	onToggle?: (path: string) => void;
 	isExpanded?: boolean;
 }> = ({ node, depth, onFileSelect, onToggle, isExpanded }) => {
+	const [thumbnail, setThumbnail] = useState<Thumbnail | null>(null);
+	const [hovered, setHovered] = useState(false);
+	const previewable = !node.isDirectory && isPreviewable(node.name);
+
+	const handleMouseEnter = () => {
+		setHovered(true);
+		if (previewable && !thumbnail) {
+			generateThumbnail({ Local: "." }, node.path)
+				.then(setThumbnail)
+				.catch(() => {});
+		}
+	};
+
 	const handleClick = () => {
 		if (node.isDirectory) {
 			onToggle?.(node.path);
@@ -35,6 +53,8 @@ const FileTreeItem: React.FC<{
 		<div>
 			<div
 				onClick={handleClick}
+				onMouseEnter={handleMouseEnter}
+				onMouseLeave={() => setHovered(false)}
 				style={{
 					paddingLeft: `${depth * 16}px`,
 					padding: "4px 8px",
@@ -42,6 +62,7 @@ const FileTreeItem: React.FC<{
 					display: "flex",
 					alignItems: "center",
 					gap: "4px",
+					position: "relative",
 				}}
 				className="file-tree-item"
 			>
@@ -67,6 +88,31 @@ const FileTreeItem: React.FC<{
 					/>
 				)}
 				<span>{node.name}</span>
+				{hovered && thumbnail && (
+					<div
+						style={{
+							position: "absolute",
+							left: "100%",
+							top: 0,
+							zIndex: 10,
+							padding: "4px",
+							background: "var(--base-200)",
+							borderRadius: "4px",
+							pointerEvents: "none",
+						}}
+					>
+						<img
+							src={thumbnail.dataUrl}
+							alt={node.name}
+							width={thumbnail.width}
+							height={thumbnail.height}
+							style={{ display: "block", maxWidth: "256px" }}
+						/>
+						<div style={{ fontSize: "11px" }}>
+							{thumbnail.originalWidth} × {thumbnail.originalHeight}
+						</div>
+					</div>
+				)}
 			</div>
 			{isExpanded && node.children && (
 				<div>
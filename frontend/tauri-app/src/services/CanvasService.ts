@@ -59,23 +59,14 @@ export class CanvasService {
 	static async executeCommand(
 		command: string,
 		args: string[],
-		directory?: string
+		directory: string
 	): Promise<{ success: boolean; output?: string; error?: string }> {
 		try {
-			let output: string;
-			
-			if (directory) {
-				output = await invoke("execute_command_in_dir", {
-					command,
-					args,
-					directory
-				});
-			} else {
-				output = await invoke("execute_command", {
-					command,
-					args
-				});
-			}
+			const output = await invoke<string>("execute_command_in_dir", {
+				command,
+				args,
+				directory
+			});
 
 			return { success: true, output };
 		} catch (error) {
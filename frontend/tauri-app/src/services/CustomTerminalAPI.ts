@@ -48,6 +48,14 @@ export interface LineItem {
 	is_italic: boolean;
 	background_color?: Color;
 	foreground_color?: Color;
+	link?: FileLink | null;
+}
+
+/** A `path:line:column` reference printed in the terminal. */
+export interface FileLink {
+	path: string;
+	line: number;
+	column?: number | null;
 }
 
 export function defaultLineItem(): LineItem {
@@ -1,5 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
-import { GitDiffFile, GitDiffHunk, GitDiffLine, DiffSummary, MainLogicChange, DiffChange, SubLogicPath, GitBranch, GitCommit, BranchComparison } from "../types/diff";
+import { BinaryChange, GitDiffFile, GitDiffHunk, GitDiffLine, DiffSummary, MainLogicChange, DiffChange, SubLogicPath, GitBranch, GitCommit, BranchComparison } from "../types/diff";
 
 export class DiffService {
   private workingDirectory: string | null = null;
@@ -17,25 +17,17 @@ export class DiffService {
     console.log("[FRONTEND] workingDirectory:", this.workingDirectory);
     
     try {
-      let result: string;
-      if (this.workingDirectory) {
-        console.log("[FRONTEND] Calling execute_command_in_dir via invoke...");
-        result = await invoke<string>("execute_command_in_dir", {
-          command: "git",
-          args,
-          directory: this.workingDirectory
-        });
-        console.log("[FRONTEND] execute_command_in_dir completed, result type:", typeof result);
-        console.log("[FRONTEND] execute_command_in_dir result length:", result?.length || 0);
-      } else {
-        console.log("[FRONTEND] Calling execute_command via invoke...");
-        result = await invoke<string>("execute_command", {
-          command: "git",
-          args
-        });
-        console.log("[FRONTEND] execute_command completed, result type:", typeof result);
-        console.log("[FRONTEND] execute_command result length:", result?.length || 0);
+      if (!this.workingDirectory) {
+        throw new Error("No working directory set");
       }
+      console.log("[FRONTEND] Calling execute_command_in_dir via invoke...");
+      const result = await invoke<string>("execute_command_in_dir", {
+        command: "git",
+        args,
+        directory: this.workingDirectory
+      });
+      console.log("[FRONTEND] execute_command_in_dir completed, result type:", typeof result);
+      console.log("[FRONTEND] execute_command_in_dir result length:", result?.length || 0);
       
       console.log("[FRONTEND] executeGitCommand returning result");
       return result;
@@ -758,6 +750,22 @@ export class DiffService {
     }
   }
 
+  // Sizes, hashes and image thumbnails for a binary file, comparing the
+  // same refs as the text diff; targetRef undefined means the working tree
+  async getBinaryChange(file: GitDiffFile, baseRef: string, targetRef?: string): Promise<BinaryChange> {
+    if (!this.workingDirectory) {
+      throw new Error("No working directory set");
+    }
+    return await invoke<BinaryChange>("git_binary_diff", {
+      directory: this.workingDirectory,
+      path: file.filePath,
+      oldPath: file.oldFilePath ?? null,
+      from: baseRef,
+      to: targetRef ?? null,
+      osSession: { Local: this.workingDirectory }
+    });
+  }
+
   parseDiff(diffText: string): GitDiffFile[] {
     const files: GitDiffFile[] = [];
     const lines = diffText.split('\n');
@@ -796,6 +804,9 @@ export class DiffService {
       else if (line.startsWith('rename from')) {
         if (currentFile) currentFile.status = 'renamed';
       }
+      else if (line.startsWith('Binary files ') || line === 'GIT binary patch') {
+        if (currentFile) currentFile.binary = true;
+      }
       
       // Hunk header
       else if (line.startsWith('@@')) {
@@ -10,6 +10,13 @@ import {
 import { Command } from "../scripting/baseScript";
 import { OsSession, osSessionEquals } from "../bindings/os";
 import { GitProject } from "../types/GitProject";
+import {
+	getSettings,
+	resetSettings,
+	updateSettings,
+	type SettingsChanged,
+} from "../bindings/settings";
+import { listen } from "@tauri-apps/api/event";
 
 // Define the shape of the state
 interface AppState {
@@ -19,6 +26,9 @@ interface AppState {
 	gitProjects: GitProject[];
 }
 
+// Theme and onboarding are settings, kept by the Rust side
+type PersistedState = Omit<AppState, "theme" | "showOnboarding">;
+
 // Define the shape of the store, including state and actions
 export interface IStore extends AppState {
 	setTheme: (theme: string) => void;
@@ -50,17 +60,24 @@ export function StoreProvider({ children }: { children: ReactNode }) {
 	>([]);
 	const [gitProjects, setGitProjects] = useState<GitProject[]>([]);
 	const [tauriStore, setTauriStore] = useState<Store | null>(null);
+	const [settingsLoaded, setSettingsLoaded] = useState(false);
 
 	// Load state from disk on initial render
 	useEffect(() => {
 		const loadState = async () => {
 			try {
+				const { settings, problems } = await getSettings();
+				for (const problem of problems) {
+					console.warn(`Setting ${problem.path} was reset: ${problem.message}`);
+				}
+				setThemeState(settings.appearance.theme);
+				setShowOnboardingState(settings.appearance.showOnboarding);
+				setSettingsLoaded(true);
+
 				const tauriStore = await load("store.json", { autoSave: false });
 				setTauriStore(tauriStore);
-				const savedState = await tauriStore.get<AppState>("appState");
+				const savedState = await tauriStore.get<PersistedState>("appState");
 				if (savedState) {
-					setThemeState(savedState.theme);
-					setShowOnboardingState(savedState.showOnboarding);
 					setCurrentInterpreterScriptState(savedState.currentInterpreterScript);
 					// Handle migration from old osSessions to new gitProjects structure
 					if (savedState.gitProjects) {
@@ -85,16 +102,30 @@ export function StoreProvider({ children }: { children: ReactNode }) {
 			}
 		};
 		loadState();
+
+		const unlisten = listen<SettingsChanged>("settings-changed", (event) => {
+			setThemeState(event.payload.settings.appearance.theme);
+			setShowOnboardingState(event.payload.settings.appearance.showOnboarding);
+		});
+		return () => {
+			unlisten.then((unlisten) => unlisten());
+		};
 	}, []);
 
+	// Save settings through the backend, which validates them
+	useEffect(() => {
+		if (!settingsLoaded) return;
+		updateSettings({ appearance: { theme, showOnboarding } }).catch((error) =>
+			console.error("Failed to save settings:", error),
+		);
+	}, [settingsLoaded, theme, showOnboarding]);
+
 	// Save state to disk whenever it changes
 	useEffect(() => {
 		const saveState = async () => {
 			try {
 				if (!tauriStore) return;
-				const stateToSave: AppState = {
-					theme,
-					showOnboarding,
+				const stateToSave: PersistedState = {
 					currentInterpreterScript,
 					gitProjects: gitProjects.map(project => project.toJSON()),
 				};
@@ -105,12 +136,7 @@ export function StoreProvider({ children }: { children: ReactNode }) {
 			}
 		};
 		saveState();
-	}, [
-		theme,
-		showOnboarding,
-		currentInterpreterScript,
-		gitProjects,
-	]);
+	}, [currentInterpreterScript, gitProjects]);
 
 	const setTheme = (newTheme: string) => setThemeState(newTheme);
 	const setShowOnboarding = (show: boolean) => setShowOnboardingState(show);
@@ -205,6 +231,7 @@ export function StoreProvider({ children }: { children: ReactNode }) {
 					await tauriStore.clear();
 					await tauriStore.save();
 				}
+				await resetSettings("appearance");
 				// Reset all state to defaults
 				setThemeState("light");
 				setShowOnboardingState(false);
@@ -126,14 +126,11 @@ export class MergeBackgroundAgent extends BackgroundAgent<MergeAgentContext> {
 
 		console.log(`Agent setup: Validating canvas directory ${canvasDir}`);
 		// Validate that the canvas directory exists before proceeding
-		try {
-			await invoke('execute_command_with_os_session', {
-				command: 'test',
-				args: ['-d', canvasDir],
-				directory: '/',
-				osSession: this.context.canvasToMergeOsSession
-			});
-		} catch (error) {
+		const canvasDirExists = await invoke<boolean>('directory_exists', {
+			path: canvasDir,
+			osSession: this.context.canvasToMergeOsSession
+		});
+		if (!canvasDirExists) {
 			throw new Error(`Canvas directory no longer exists: ${canvasDir}. The canvas may have been deleted.`);
 		}
 
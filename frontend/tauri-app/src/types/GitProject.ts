@@ -238,14 +238,16 @@ export class GitProject {
 
 		// Validate that the canvas directory still exists
 		const canvasDir = osSessionGetWorkingDirectory(canvas.osSession);
+		let canvasDirExists: boolean;
 		try {
-			await invoke('execute_command_with_os_session', {
-				command: 'test',
-				args: ['-d', canvasDir],
-				directory: '/',
+			canvasDirExists = await invoke<boolean>('directory_exists', {
+				path: canvasDir,
 				osSession: canvas.osSession
 			});
 		} catch (error) {
+			return { success: false, error: `Failed to check canvas directory ${canvasDir}: ${error}` };
+		}
+		if (!canvasDirExists) {
 			return { 
 				success: false, 
 				error: `Canvas directory no longer exists: ${canvasDir}. The canvas may have been deleted.` 
@@ -14,6 +14,21 @@ export interface GitDiffHunk {
   lines: GitDiffLine[];
 }
 
+export interface BinaryVersion {
+  size: number;
+  hash: string; // git blob id
+  thumbnail: string | null; // data: URL, for small images
+}
+
+// Returned by the git_binary_diff command; a side is null when the file
+// was added or deleted
+export interface BinaryChange {
+  old: BinaryVersion | null;
+  new: BinaryVersion | null;
+  sizeDelta: number;
+  mimeType: string | null;
+}
+
 export interface GitDiffFile {
   filePath: string;
   oldFilePath?: string;
@@ -21,6 +36,9 @@ export interface GitDiffFile {
   hunks: GitDiffHunk[];
   additions: number;
   deletions: number;
+  // Set for files git reports as "Binary files ... differ", which have no hunks
+  binary?: boolean;
+  binaryChange?: BinaryChange;
 }
 
 export interface DiffChange {